@@ -0,0 +1,56 @@
+//! How long one generation cycle takes, end to end, from the engine's request
+//! to the master track's frames, for projects of different sizes on each
+//! executor. Most of the time in these projects goes to passing messages.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use spike_actor_system::{engine::Engine, executor::Executor};
+use std::time::Duration;
+
+const FRAMES: usize = 64;
+
+/// How many tracks, and how many entities on each.
+const SIZES: [(usize, usize); 4] = [(1, 1), (4, 4), (16, 4), (32, 8)];
+
+/// A project whose tracks each have one generator, followed by effects.
+fn project(executor: Executor, tracks: usize, entities: usize) -> Engine {
+    let mut engine = Engine::new_with(executor);
+    engine.set_block_size(FRAMES);
+    for _ in 0..tracks {
+        let track_uid = engine.create_track().unwrap();
+        let track = engine.track(track_uid).unwrap();
+        track.add_entity_by_key("always-0.5").unwrap();
+        for _ in 1..entities {
+            track.add_entity_by_key("quietener").unwrap();
+        }
+    }
+    engine
+}
+
+fn generation_cycle(c: &mut Criterion) {
+    let workers = std::thread::available_parallelism().map_or(4, |n| n.get());
+    // Pool workers live as long as the process, so every project shares one
+    // pool.
+    let pool = Executor::new_pool(workers);
+    let mut group = c.benchmark_group("generation_cycle");
+    for name in ["synchronous", "threaded", "pool"] {
+        for (tracks, entities) in SIZES {
+            // A synchronous executor keeps visiting the actors of earlier
+            // projects, so each project gets its own.
+            let executor = match name {
+                "synchronous" => Executor::new_synchronous(),
+                "threaded" => Executor::Threaded,
+                _ => pool.clone(),
+            };
+            let id = BenchmarkId::new(name, format!("{tracks}x{entities}"));
+            let mut engine = project(executor, tracks, entities);
+            // The first cycle also has to handle the project's setup.
+            engine.render(FRAMES).unwrap();
+            group.bench_function(id, |b| b.iter(|| engine.render(FRAMES).unwrap()));
+            engine.shutdown(Duration::from_secs(5)).unwrap();
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, generation_cycle);
+criterion_main!(benches);
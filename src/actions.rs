@@ -1,3 +1,4 @@
+use crate::{buffer_pool::BufferPool, meter::MeterSnapshot, track::TrackInfo};
 use ensnare::prelude::*;
 
 /// The actor has produced a buffer of audio.
@@ -5,6 +6,18 @@ use ensnare::prelude::*;
 pub struct AudioAction {
     pub(crate) source_uid: Uid,
     pub(crate) frames: Vec<StereoSample>,
+    /// The output pairs after the main one, when the
+    /// [ChannelLayout](crate::channels::ChannelLayout) has more than two
+    /// channels. Only the master track fills these in.
+    pub(crate) other_pairs: Vec<Vec<StereoSample>>,
+}
+impl AudioAction {
+    /// Hands all of the action's buffers back to the [BufferPool].
+    pub(crate) fn recycle(self) {
+        let pool = BufferPool::global();
+        pool.recycle(self.frames);
+        self.other_pairs.into_iter().for_each(|p| pool.recycle(p));
+    }
 }
 
 /// This actor has produced a MIDI message.
@@ -13,6 +26,10 @@ pub struct MidiAction {
     pub(crate) source_uid: Uid,
     pub(crate) channel: MidiChannel,
     pub(crate) message: MidiMessage,
+    /// How many entities in a row produced this message in response to the
+    /// one before, not counting the source. Zero for MIDI that an entity
+    /// produced on its own.
+    pub(crate) hops: usize,
 }
 
 /// The entity's signal has changed.
@@ -21,3 +38,33 @@ pub struct ControlAction {
     pub(crate) source_uid: Uid,
     pub(crate) value: ControlValue,
 }
+
+/// A track is reporting on its state.
+#[derive(Debug, Clone)]
+pub enum TrackAction {
+    /// Levels of the buffer the track most recently produced.
+    Meter(TrackUid, MeterSnapshot),
+    /// The buffer the track most recently produced. Only sent to
+    /// [TrackRequest::SubscribeFrames](crate::track::TrackRequest::SubscribeFrames)
+    /// subscribers.
+    Frames(TrackUid, Vec<StereoSample>),
+    /// The track's name or color changed. Also sent to each new subscriber.
+    Info(TrackUid, TrackInfo),
+    /// How many frames late the track's output is changed.
+    Latency(TrackUid, usize),
+    /// The track got a message that its state machine didn't expect, so it
+    /// dropped the message and started over from idle.
+    Fault(TrackUid, String),
+}
+impl TrackAction {
+    /// The variant's name, for [MessageTrace](crate::trace::MessageTrace).
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            TrackAction::Meter(..) => "Meter",
+            TrackAction::Frames(..) => "Frames",
+            TrackAction::Info(..) => "Info",
+            TrackAction::Latency(..) => "Latency",
+            TrackAction::Fault(..) => "Fault",
+        }
+    }
+}
@@ -17,11 +17,14 @@ impl Generates<StereoSample> for AlwaysSame {
     }
 }
 impl Configurable for AlwaysSame {}
+#[cfg(feature = "gui")]
 impl Displays for AlwaysSame {
     fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
         ui.label(format!("My value: {:0.2}", self.value))
     }
 }
+#[cfg(not(feature = "gui"))]
+impl Displays for AlwaysSame {}
 impl AlwaysSame {
     pub fn new_with(value: f64) -> Self {
         Self {
@@ -1,33 +1,208 @@
 use derivative::Derivative;
+#[cfg(feature = "gui")]
+use eframe::egui::{ComboBox, Slider};
 use ensnare::{prelude::*, util::MidiUtils};
 use ensnare_proc_macros::{Control, IsEntity, Metadata};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use std::fmt::Display;
 
+/// The order in which the arpeggiator walks through its notes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArpPattern {
+    #[default]
+    Up,
+    Down,
+    /// Up, then back down, without repeating the top and bottom notes.
+    UpDown,
+    Random,
+}
+impl Display for ArpPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ArpPattern::Up => "Up",
+            ArpPattern::Down => "Down",
+            ArpPattern::UpDown => "Up/Down",
+            ArpPattern::Random => "Random",
+        })
+    }
+}
+impl From<ControlValue> for ArpPattern {
+    fn from(value: ControlValue) -> Self {
+        Self::ALL[step_index(value, Self::ALL.len())]
+    }
+}
+impl ArpPattern {
+    pub const ALL: [ArpPattern; 4] = [Self::Up, Self::Down, Self::UpDown, Self::Random];
+}
+
+/// How long each step lasts, as a fraction of a whole note.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArpRate {
+    Quarter,
+    Eighth,
+    #[default]
+    Sixteenth,
+    ThirtySecond,
+}
+impl Display for ArpRate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ArpRate::Quarter => "1/4",
+            ArpRate::Eighth => "1/8",
+            ArpRate::Sixteenth => "1/16",
+            ArpRate::ThirtySecond => "1/32",
+        })
+    }
+}
+impl From<ControlValue> for ArpRate {
+    fn from(value: ControlValue) -> Self {
+        Self::ALL[step_index(value, Self::ALL.len())]
+    }
+}
+impl ArpRate {
+    pub const ALL: [ArpRate; 4] = [
+        Self::Quarter,
+        Self::Eighth,
+        Self::Sixteenth,
+        Self::ThirtySecond,
+    ];
+
+    fn steps_per_beat(&self) -> usize {
+        match self {
+            ArpRate::Quarter => 1,
+            ArpRate::Eighth => 2,
+            ArpRate::Sixteenth => 4,
+            ArpRate::ThirtySecond => 8,
+        }
+    }
+}
+
+/// Stretches or squeezes the [ArpRate].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArpFeel {
+    #[default]
+    Straight,
+    /// Half again as long.
+    Dotted,
+    /// Three steps in the time of two.
+    Triplet,
+}
+impl Display for ArpFeel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ArpFeel::Straight => "Straight",
+            ArpFeel::Dotted => "Dotted",
+            ArpFeel::Triplet => "Triplet",
+        })
+    }
+}
+impl From<ControlValue> for ArpFeel {
+    fn from(value: ControlValue) -> Self {
+        Self::ALL[step_index(value, Self::ALL.len())]
+    }
+}
+impl ArpFeel {
+    pub const ALL: [ArpFeel; 3] = [Self::Straight, Self::Dotted, Self::Triplet];
+}
+
+/// How many octaves the pattern spans, from one to [ArpOctaves::MAX].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArpOctaves(pub u8);
+impl Default for ArpOctaves {
+    fn default() -> Self {
+        Self(1)
+    }
+}
+impl From<ControlValue> for ArpOctaves {
+    fn from(value: ControlValue) -> Self {
+        Self(1 + step_index(value, Self::MAX as usize) as u8)
+    }
+}
+impl ArpOctaves {
+    pub const MAX: u8 = 4;
+}
+
+/// Maps a control value evenly onto one of `count` choices.
+fn step_index(value: ControlValue, count: usize) -> usize {
+    ((value.0.clamp(0.0, 1.0) * count as f64) as usize).min(count - 1)
+}
+
+/// Plays the notes being held one at a time, in a pattern, in time with the
+/// transport.
 #[derive(Debug, Derivative, IsEntity, Control, Metadata, Serialize, Deserialize)]
 #[derivative(Default)]
 #[entity(TransformsAudio)]
+#[serde(default)]
 pub struct Arpeggiator {
     uid: Uid,
-    last_beat: usize,
-    is_playing: bool,
-    should_play_low_note: bool,
-    #[derivative(Default(value = "60"))]
-    base_note: u8,
-    note_we_are_playing: u8,
+
+    #[control]
+    pattern: ArpPattern,
+    #[control]
+    octaves: ArpOctaves,
+    #[control]
+    rate: ArpRate,
+    #[control]
+    feel: ArpFeel,
+    /// How much of each step the note sounds for.
+    #[control]
+    #[derivative(Default(value = "Normal::from(0.5)"))]
+    gate: Normal,
+    /// Keep playing the last chord after its keys are released, until a key
+    /// is pressed again.
+    #[control]
+    is_latched: bool,
+
+    /// The keys down right now.
+    #[serde(skip)]
+    pressed: Vec<u8>,
+    /// What the pattern is built from, lowest first. Unless latched, the
+    /// same as `pressed`.
+    #[serde(skip)]
+    notes: Vec<u8>,
+    #[serde(skip)]
+    channel: MidiChannel,
+    #[serde(skip)]
+    #[derivative(Default(value = "127"))]
+    velocity: u8,
+    /// Where the next step is in the pattern.
+    #[serde(skip)]
+    position: usize,
+    /// The note sounding now, and when it should stop, in
+    /// [MusicalTime] units.
+    #[serde(skip)]
+    sounding: Option<(u8, usize)>,
+    #[serde(skip)]
     time_range: TimeRange,
 }
 impl Serializable for Arpeggiator {}
 impl HandlesMidi for Arpeggiator {
     fn handle_midi_message(
         &mut self,
-        _channel: MidiChannel,
+        channel: MidiChannel,
         message: MidiMessage,
         _midi_messages_fn: &mut MidiMessagesFn,
     ) {
         match message {
-            #[allow(unused_variables)]
-            MidiMessage::NoteOn { key, vel } => {
-                self.base_note = key.into();
+            MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => {
+                let key = key.as_int();
+                if self.is_latched && self.pressed.is_empty() {
+                    self.notes.clear();
+                }
+                self.pressed.push(key);
+                if let Err(index) = self.notes.binary_search(&key) {
+                    self.notes.insert(index, key);
+                }
+                self.channel = channel;
+                self.velocity = vel.as_int();
+            }
+            MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                let key = key.as_int();
+                self.pressed.retain(|k| *k != key);
+                if !self.is_latched {
+                    self.notes.retain(|k| *k != key);
+                }
             }
             _ => {}
         }
@@ -35,11 +210,60 @@ impl HandlesMidi for Arpeggiator {
 }
 impl Generates<StereoSample> for Arpeggiator {}
 impl Configurable for Arpeggiator {}
+#[cfg(feature = "gui")]
 impl Displays for Arpeggiator {
     fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
-        ui.label(format!("Last beat: {}", self.last_beat))
+        let response = ui.label(if self.notes.is_empty() {
+            "Hold some notes".to_string()
+        } else {
+            format!("Notes: {:?}", self.notes)
+        });
+        ComboBox::new(ui.next_auto_id(), "Pattern")
+            .selected_text(self.pattern.to_string())
+            .show_ui(ui, |ui| {
+                for pattern in ArpPattern::ALL {
+                    ui.selectable_value(&mut self.pattern, pattern, pattern.to_string());
+                }
+            });
+        let mut octaves = self.octaves.0;
+        if ui
+            .add(Slider::new(&mut octaves, 1..=ArpOctaves::MAX).text("Octaves"))
+            .changed()
+        {
+            self.set_octaves(ArpOctaves(octaves));
+        }
+        ui.horizontal(|ui| {
+            ComboBox::new(ui.next_auto_id(), "")
+                .selected_text(self.rate.to_string())
+                .show_ui(ui, |ui| {
+                    for rate in ArpRate::ALL {
+                        ui.selectable_value(&mut self.rate, rate, rate.to_string());
+                    }
+                });
+            ComboBox::new(ui.next_auto_id(), "Rate")
+                .selected_text(self.feel.to_string())
+                .show_ui(ui, |ui| {
+                    for feel in ArpFeel::ALL {
+                        ui.selectable_value(&mut self.feel, feel, feel.to_string());
+                    }
+                });
+        });
+        let mut gate = self.gate.0;
+        if ui
+            .add(Slider::new(&mut gate, 0.05..=1.0).text("Gate"))
+            .changed()
+        {
+            self.set_gate(Normal::from(gate));
+        }
+        let mut is_latched = self.is_latched;
+        if ui.checkbox(&mut is_latched, "Latch").changed() {
+            self.set_is_latched(is_latched);
+        }
+        response
     }
 }
+#[cfg(not(feature = "gui"))]
+impl Displays for Arpeggiator {}
 impl Controls for Arpeggiator {
     fn time_range(&self) -> Option<TimeRange> {
         Some(self.time_range.clone())
@@ -49,31 +273,24 @@ impl Controls for Arpeggiator {
         self.time_range = time_range.clone();
     }
 
-    // Play one whole note on every other beat, and alternate between two
-    // pitches.
     fn work(&mut self, control_events_fn: &mut ControlEventsFn) {
-        let latest_beat = self.time_range.0.end.total_beats();
-        let beat_changed = latest_beat > self.last_beat;
-        if beat_changed {
-            self.last_beat = latest_beat;
-        }
-        if self.is_playing {
-            if beat_changed {
+        let start = self.time_range.0.start.total_units();
+        let end = self.time_range.0.end.total_units();
+        let step_units = self.step_units();
+        let mut step_start = start.div_ceil(step_units) * step_units;
+        while step_start < end {
+            self.stop_sounding_note(step_start, control_events_fn);
+            if let Some(note) = self.next_note() {
                 control_events_fn(WorkEvent::Midi(
-                    MidiChannel::default(),
-                    MidiUtils::new_note_off(self.note_we_are_playing, 127),
+                    self.channel,
+                    MidiUtils::new_note_on(note, self.velocity),
                 ));
-                self.is_playing = false;
-                self.should_play_low_note = !self.should_play_low_note;
+                let gate_units = ((step_units as f64 * self.gate.0) as usize).max(1);
+                self.sounding = Some((note, step_start + gate_units));
             }
-        } else if beat_changed {
-            self.note_we_are_playing = self.get_note_to_play();
-            control_events_fn(WorkEvent::Midi(
-                MidiChannel::default(),
-                MidiUtils::new_note_on(self.note_we_are_playing, 127),
-            ));
-            self.is_playing = true;
+            step_start += step_units;
         }
+        self.stop_sounding_note(end, control_events_fn);
     }
 
     fn is_finished(&self) -> bool {
@@ -84,7 +301,9 @@ impl Controls for Arpeggiator {
 
     fn stop(&mut self) {}
 
-    fn skip_to_start(&mut self) {}
+    fn skip_to_start(&mut self) {
+        self.position = 0;
+    }
 
     fn is_performing(&self) -> bool {
         false
@@ -92,7 +311,87 @@ impl Controls for Arpeggiator {
 }
 
 impl Arpeggiator {
-    fn get_note_to_play(&mut self) -> u8 {
-        self.base_note + if self.should_play_low_note { 0 } else { 7 }
+    /// The length of one step, in [MusicalTime] units.
+    fn step_units(&self) -> usize {
+        let units = MusicalTime::UNITS_IN_BEAT / self.rate.steps_per_beat();
+        match self.feel {
+            ArpFeel::Straight => units,
+            ArpFeel::Dotted => units * 3 / 2,
+            ArpFeel::Triplet => units * 2 / 3,
+        }
+        .max(1)
+    }
+
+    /// The held notes, repeated up each octave in range, in pattern order.
+    fn sequence(&self) -> Vec<u8> {
+        let mut sequence: Vec<u8> = (0..self.octaves.0)
+            .flat_map(|octave| self.notes.iter().map(move |note| note + octave * 12))
+            .filter(|note| *note <= 127)
+            .collect();
+        match self.pattern {
+            ArpPattern::Up | ArpPattern::Random => {}
+            ArpPattern::Down => sequence.reverse(),
+            ArpPattern::UpDown => {
+                let down: Vec<u8> = sequence.iter().rev().skip(1).copied().collect();
+                if let Some((_, inner)) = down.split_last() {
+                    sequence.extend_from_slice(inner);
+                }
+            }
+        }
+        sequence
+    }
+
+    fn next_note(&mut self) -> Option<u8> {
+        let sequence = self.sequence();
+        if sequence.is_empty() {
+            return None;
+        }
+        if self.pattern == ArpPattern::Random {
+            return Some(sequence[rand::thread_rng().gen_range(0..sequence.len())]);
+        }
+        let note = sequence[self.position % sequence.len()];
+        self.position = (self.position + 1) % sequence.len();
+        Some(note)
+    }
+
+    /// Ends the sounding note if it's due to stop by `units`.
+    fn stop_sounding_note(&mut self, units: usize, control_events_fn: &mut ControlEventsFn) {
+        if let Some((note, stop_units)) = self.sounding {
+            if stop_units <= units {
+                control_events_fn(WorkEvent::Midi(
+                    self.channel,
+                    MidiUtils::new_note_off(note, 0),
+                ));
+                self.sounding = None;
+            }
+        }
+    }
+
+    fn set_pattern(&mut self, pattern: ArpPattern) {
+        self.pattern = pattern;
+    }
+
+    fn set_octaves(&mut self, octaves: ArpOctaves) {
+        self.octaves = ArpOctaves(octaves.0.clamp(1, ArpOctaves::MAX));
+    }
+
+    fn set_rate(&mut self, rate: ArpRate) {
+        self.rate = rate;
+    }
+
+    fn set_feel(&mut self, feel: ArpFeel) {
+        self.feel = feel;
+    }
+
+    fn set_gate(&mut self, gate: Normal) {
+        self.gate = gate;
+    }
+
+    /// Unlatching drops the notes that are no longer held.
+    fn set_is_latched(&mut self, is_latched: bool) {
+        self.is_latched = is_latched;
+        if !is_latched {
+            self.notes.retain(|note| self.pressed.contains(note));
+        }
     }
 }
@@ -0,0 +1,132 @@
+use crate::snapshot::EngineSnapshot;
+use eframe::{
+    egui::{Align2, ScrollArea, Sense, Slider, Ui},
+    epaint::{pos2, vec2, Color32, Rect, Stroke},
+};
+use ensnare::prelude::*;
+
+/// Shows the tracks as horizontal lanes with their clips, under a beat ruler
+/// with the playhead.
+#[derive(Debug)]
+pub struct ArrangementView {
+    pixels_per_beat: f32,
+}
+impl Default for ArrangementView {
+    fn default() -> Self {
+        Self {
+            pixels_per_beat: 16.0,
+        }
+    }
+}
+impl ArrangementView {
+    const RULER_HEIGHT: f32 = 16.0;
+    const LANE_HEIGHT: f32 = 28.0;
+    const LABEL_WIDTH: f32 = 100.0;
+    /// How much of the song the view covers at least, in beats.
+    const MIN_BEATS: usize = 64;
+
+    /// Draws the view. If the user clicked somewhere, returns the position to
+    /// seek to.
+    pub fn show(&mut self, ui: &mut Ui, snapshot: &EngineSnapshot) -> Option<MusicalTime> {
+        ui.add(
+            Slider::new(&mut self.pixels_per_beat, 4.0..=64.0)
+                .logarithmic(true)
+                .text("Zoom"),
+        );
+        let lanes: Vec<_> = snapshot
+            .tracks
+            .iter()
+            .map(|track| {
+                let color = track
+                    .color
+                    .map_or(Color32::GRAY, |[r, g, b]| Color32::from_rgb(r, g, b));
+                (track.name.as_str(), color, &track.clip_spans)
+            })
+            .collect();
+        let playhead = snapshot.position;
+        let beats = lanes
+            .iter()
+            .flat_map(|(_, _, spans)| spans.iter().map(|span| span.end.total_beats() + 1))
+            .chain([playhead.total_beats() + 1, Self::MIN_BEATS])
+            .max()
+            .unwrap_or(Self::MIN_BEATS);
+
+        let size = vec2(
+            Self::LABEL_WIDTH + beats as f32 * self.pixels_per_beat,
+            Self::RULER_HEIGHT + lanes.len() as f32 * Self::LANE_HEIGHT,
+        );
+        ScrollArea::horizontal()
+            .id_source("arrangement")
+            .show(ui, |ui| {
+                let (response, painter) = ui.allocate_painter(size, Sense::click_and_drag());
+                let rect = response.rect;
+                let left = rect.left() + Self::LABEL_WIDTH;
+                let x_of = |time: MusicalTime| {
+                    let beats = time.total_units() as f32 / MusicalTime::UNITS_IN_BEAT as f32;
+                    left + beats * self.pixels_per_beat
+                };
+
+                for beat in 0..=beats {
+                    let x = left + beat as f32 * self.pixels_per_beat;
+                    // Number the bars, assuming 4/4.
+                    let is_bar = beat % 4 == 0;
+                    if is_bar {
+                        painter.text(
+                            pos2(x + 2.0, rect.top()),
+                            Align2::LEFT_TOP,
+                            (beat / 4 + 1).to_string(),
+                            Default::default(),
+                            ui.visuals().text_color(),
+                        );
+                    }
+                    let color = if is_bar {
+                        Color32::GRAY
+                    } else {
+                        Color32::from_gray(60)
+                    };
+                    painter.line_segment(
+                        [pos2(x, rect.top() + Self::RULER_HEIGHT / 2.0), pos2(x, rect.bottom())],
+                        Stroke::new(1.0, color),
+                    );
+                }
+                for (i, (name, color, spans)) in lanes.iter().enumerate() {
+                    let top = rect.top() + Self::RULER_HEIGHT + i as f32 * Self::LANE_HEIGHT;
+                    let y_range = top + 2.0..=top + Self::LANE_HEIGHT - 2.0;
+                    painter.text(
+                        pos2(rect.left(), top + Self::LANE_HEIGHT / 2.0),
+                        Align2::LEFT_CENTER,
+                        name,
+                        Default::default(),
+                        ui.visuals().text_color(),
+                    );
+                    for span in spans.iter() {
+                        // A MIDI clip can be a single event, so give it some width.
+                        let end = x_of(span.end).max(x_of(span.start) + 4.0);
+                        let clip_rect =
+                            Rect::from_x_y_ranges(x_of(span.start)..=end, y_range.clone());
+                        painter.rect_filled(clip_rect, 3.0, color.gamma_multiply(0.6));
+                        painter.text(
+                            clip_rect.left_center() + vec2(3.0, 0.0),
+                            Align2::LEFT_CENTER,
+                            if span.is_audio { "Audio" } else { "MIDI" },
+                            Default::default(),
+                            Color32::WHITE,
+                        );
+                    }
+                }
+                let x = x_of(playhead);
+                painter.line_segment(
+                    [pos2(x, rect.top()), pos2(x, rect.bottom())],
+                    Stroke::new(2.0, Color32::YELLOW),
+                );
+
+                let response = response.on_hover_text("Click to move the playhead");
+                response.interact_pointer_pos().map(|pointer| {
+                    let beats = ((pointer.x - left) / self.pixels_per_beat).max(0.0);
+                    let units = (beats * MusicalTime::UNITS_IN_BEAT as f32) as usize;
+                    MusicalTime::new_with_units(units)
+                })
+            })
+            .inner
+    }
+}
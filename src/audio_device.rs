@@ -0,0 +1,89 @@
+use cpal::traits::{DeviceTrait, HostTrait};
+use eframe::egui::{ComboBox, Ui};
+use log::warn;
+
+/// The audio device the user picked.
+#[derive(Debug, Clone)]
+pub enum AudioDeviceSelection {
+    Output(String),
+    Input(String),
+}
+
+/// Lists the system's audio devices and lets the user pick one of each kind.
+#[derive(Debug, Default)]
+pub struct AudioDevicePicker {
+    outputs: Vec<String>,
+    output_selected: usize,
+    inputs: Vec<String>,
+    input_selected: usize,
+}
+impl AudioDevicePicker {
+    pub fn new() -> Self {
+        let mut r = Self::default();
+        r.refresh();
+        r
+    }
+
+    /// Asks the audio host for its current devices. The default device of
+    /// each kind starts out selected.
+    pub fn refresh(&mut self) {
+        let host = cpal::default_host();
+        let default_output = host.default_output_device().and_then(|d| d.name().ok());
+        let default_input = host.default_input_device().and_then(|d| d.name().ok());
+        match host.output_devices() {
+            Ok(devices) => self.outputs = devices.filter_map(|d| d.name().ok()).collect(),
+            Err(e) => warn!("While listing audio outputs: {e:?}"),
+        }
+        match host.input_devices() {
+            Ok(devices) => self.inputs = devices.filter_map(|d| d.name().ok()).collect(),
+            Err(e) => warn!("While listing audio inputs: {e:?}"),
+        }
+        self.output_selected = Self::index_of(&self.outputs, default_output);
+        self.input_selected = Self::index_of(&self.inputs, default_input);
+    }
+
+    /// Selects the devices with the given names, if they're still there.
+    pub fn select(&mut self, output: Option<&str>, input: Option<&str>) {
+        if let Some(i) = output.and_then(|name| self.outputs.iter().position(|n| n == name)) {
+            self.output_selected = i;
+        }
+        if let Some(i) = input.and_then(|name| self.inputs.iter().position(|n| n == name)) {
+            self.input_selected = i;
+        }
+    }
+
+    fn index_of(names: &[String], name: Option<String>) -> usize {
+        name.and_then(|name| names.iter().position(|n| *n == name)).unwrap_or_default()
+    }
+
+    /// Draws the pickers, and returns the device the user just chose, if any.
+    pub fn show(&mut self, ui: &mut Ui) -> Option<AudioDeviceSelection> {
+        let mut selection = None;
+        if !self.outputs.is_empty()
+            && ComboBox::new(ui.next_auto_id(), "Audio Output")
+                .show_index(ui, &mut self.output_selected, self.outputs.len(), |i| {
+                    self.outputs[i].clone()
+                })
+                .changed()
+        {
+            selection = Some(AudioDeviceSelection::Output(
+                self.outputs[self.output_selected].clone(),
+            ));
+        }
+        if !self.inputs.is_empty()
+            && ComboBox::new(ui.next_auto_id(), "Audio Input")
+                .show_index(ui, &mut self.input_selected, self.inputs.len(), |i| {
+                    self.inputs[i].clone()
+                })
+                .changed()
+        {
+            selection = Some(AudioDeviceSelection::Input(
+                self.inputs[self.input_selected].clone(),
+            ));
+        }
+        if ui.button("Refresh devices").clicked() {
+            self.refresh();
+        }
+        selection
+    }
+}
@@ -0,0 +1,124 @@
+//! Captures an audio input device for recording. The device's callback turns
+//! each buffer into stereo frames and hands them to the engine with
+//! [EngineServiceInput::AudioInput].
+
+use crate::{engine::EngineServiceInput, executor::join_on_drop};
+use anyhow::anyhow;
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    FromSample, Sample as _, SampleFormat, SizedSample, StreamConfig,
+};
+use crossbeam_channel::Sender;
+use ensnare::prelude::*;
+use log::error;
+use std::{thread::JoinHandle, time::Duration};
+
+/// Sends an input device's audio to the engine until it's dropped.
+#[derive(Debug)]
+pub struct AudioInputService {
+    quit_sender: Sender<()>,
+    /// The stream runs on its own thread, because cpal streams can't move
+    /// between threads on every platform.
+    thread: Option<JoinHandle<()>>,
+}
+impl Drop for AudioInputService {
+    fn drop(&mut self) {
+        let _ = self.quit_sender.try_send(());
+        join_on_drop(self.thread.take(), Self::DROP_TIMEOUT, "The audio input");
+    }
+}
+impl AudioInputService {
+    /// How long dropping the service waits for the stream to close.
+    const DROP_TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Opens the input device with the given name, or the default one, at the
+    /// engine's sample rate. The engine doesn't resample its input, so a
+    /// device that can't run at that rate is an error.
+    pub fn new_with(
+        device_name: Option<&str>,
+        sample_rate: SampleRate,
+        engine_sender: Sender<EngineServiceInput>,
+    ) -> anyhow::Result<Self> {
+        let device_name = device_name.map(str::to_string);
+        let (opened_sender, opened_receiver) = crossbeam_channel::bounded(1);
+        let (quit_sender, quit_receiver) = crossbeam_channel::bounded(1);
+        let thread = std::thread::spawn(move || {
+            match Self::open(device_name.as_deref(), sample_rate, engine_sender) {
+                Ok(stream) => {
+                    let _ = opened_sender.send(Ok(()));
+                    let _ = quit_receiver.recv();
+                    drop(stream);
+                }
+                Err(e) => {
+                    let _ = opened_sender.send(Err(e));
+                }
+            }
+        });
+        opened_receiver.recv()??;
+        Ok(Self {
+            quit_sender,
+            thread: Some(thread),
+        })
+    }
+
+    fn open(
+        device_name: Option<&str>,
+        sample_rate: SampleRate,
+        engine_sender: Sender<EngineServiceInput>,
+    ) -> anyhow::Result<cpal::Stream> {
+        let host = cpal::default_host();
+        let device = match device_name {
+            Some(name) => host
+                .input_devices()?
+                .find(|d| d.name().is_ok_and(|n| n == name))
+                .ok_or_else(|| anyhow!("No audio input named {name}"))?,
+            None => host
+                .default_input_device()
+                .ok_or_else(|| anyhow!("{:?} has no input device", host.id()))?,
+        };
+        let rate = cpal::SampleRate(sample_rate.0 as u32);
+        let supported = device
+            .supported_input_configs()?
+            .find(|c| c.min_sample_rate() <= rate && rate <= c.max_sample_rate())
+            .ok_or_else(|| anyhow!("The audio input can't record at {} Hz", sample_rate.0))?
+            .with_sample_rate(rate);
+        let config: StreamConfig = supported.config();
+        let stream = match supported.sample_format() {
+            SampleFormat::F32 => Self::build::<f32>(&device, &config, engine_sender)?,
+            SampleFormat::I32 => Self::build::<i32>(&device, &config, engine_sender)?,
+            SampleFormat::I16 => Self::build::<i16>(&device, &config, engine_sender)?,
+            format => return Err(anyhow!("Unsupported sample format {format:?}")),
+        };
+        stream.play()?;
+        Ok(stream)
+    }
+
+    fn build<T: SizedSample>(
+        device: &cpal::Device,
+        config: &StreamConfig,
+        engine_sender: Sender<EngineServiceInput>,
+    ) -> anyhow::Result<cpal::Stream>
+    where
+        f64: FromSample<T>,
+    {
+        let channels = config.channels as usize;
+        let stream = device.build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                // A mono input goes to both sides.
+                let frames = data
+                    .chunks(channels)
+                    .map(|frame| {
+                        let left = frame[0].to_sample::<f64>();
+                        let right = frame.get(1).map_or(left, |s| s.to_sample::<f64>());
+                        StereoSample(Sample(left), Sample(right))
+                    })
+                    .collect();
+                let _ = engine_sender.try_send(EngineServiceInput::AudioInput(frames));
+            },
+            |e| error!("Audio input: {e:?}"),
+            None,
+        )?;
+        Ok(stream)
+    }
+}
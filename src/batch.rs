@@ -0,0 +1,71 @@
+use crate::{actions::AudioAction, buffer_pool::BufferPool};
+use crossbeam_channel::Sender;
+use ensnare::prelude::*;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex, MutexGuard,
+};
+
+/// One cycle's worth of output from all of a track's generators. The track
+/// hands the same batch to each generator with
+/// [EntityRequest::NeedsAudioBatch](crate::entity::EntityRequest::NeedsAudioBatch),
+/// each one fills its own slot, and whichever finishes last mixes the slots
+/// and sends the track a single [AudioAction]. That's one message back per
+/// cycle instead of one per generator.
+#[derive(Debug)]
+pub struct AudioBatch {
+    slots: Vec<Mutex<Vec<StereoSample>>>,
+    frame_count: AtomicUsize,
+    /// Slots that haven't been filled yet in this cycle.
+    remaining: AtomicUsize,
+    /// Where the mixed result goes.
+    sender: Sender<AudioAction>,
+}
+impl AudioBatch {
+    pub(crate) fn new_with(slot_count: usize, sender: Sender<AudioAction>) -> Self {
+        Self {
+            slots: (0..slot_count).map(|_| Default::default()).collect(),
+            frame_count: Default::default(),
+            remaining: Default::default(),
+            sender,
+        }
+    }
+
+    pub(crate) fn slot_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Gets ready for a new cycle. Call only while no generator is working
+    /// on the batch.
+    pub(crate) fn start(&self, frame_count: usize) {
+        self.frame_count.store(frame_count, Ordering::Release);
+        self.remaining.store(self.slots.len(), Ordering::Release);
+    }
+
+    /// The given slot, cleared and sized for this cycle.
+    pub(crate) fn slot(&self, index: usize) -> MutexGuard<Vec<StereoSample>> {
+        let mut slot = self.slots[index].lock().unwrap();
+        slot.clear();
+        slot.resize(self.frame_count.load(Ordering::Acquire), StereoSample::SILENCE);
+        slot
+    }
+
+    /// Marks one slot as filled. If it was the last one, mixes all of them
+    /// and sends the result on behalf of the given entity.
+    pub(crate) fn finish(&self, source_uid: Uid) {
+        if self.remaining.fetch_sub(1, Ordering::AcqRel) != 1 {
+            return;
+        }
+        let mut frames = BufferPool::global().take(self.frame_count.load(Ordering::Acquire));
+        for slot in self.slots.iter() {
+            for (dst, src) in frames.iter_mut().zip(slot.lock().unwrap().iter()) {
+                *dst += *src;
+            }
+        }
+        let _ = self.sender.try_send(AudioAction {
+            source_uid,
+            frames,
+            other_pairs: Default::default(),
+        });
+    }
+}
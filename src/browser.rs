@@ -0,0 +1,194 @@
+//! A searchable list of everything that can be added to a track: the
+//! [EntityRegistry]'s entities and the [PluginHost](crate::plugin::PluginHost)'s
+//! plugins, grouped by category, with favorites at the top.
+
+use crate::{plugin::PluginDescriptor, registry::EntityRegistry};
+#[cfg(feature = "gui")]
+use eframe::egui::{ComboBox, Label, Sense, Ui};
+use ensnare::prelude::*;
+use std::{collections::BTreeSet, fmt::Display};
+
+/// Something that the browser can add to a track.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BrowserItem {
+    /// The entity registered under the given key.
+    Entity(String),
+    Plugin(PluginDescriptor),
+}
+impl BrowserItem {
+    /// What identifies the item among favorites, which outlive the session.
+    pub fn favorite_key(&self) -> String {
+        match self {
+            BrowserItem::Entity(key) => key.clone(),
+            BrowserItem::Plugin(descriptor) => {
+                format!("plugin:{}:{}", descriptor.format, descriptor.id)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BrowserCategory {
+    Instrument,
+    Effect,
+    Controller,
+}
+impl Display for BrowserCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            BrowserCategory::Instrument => "Instruments",
+            BrowserCategory::Effect => "Effects",
+            BrowserCategory::Controller => "Controllers",
+        })
+    }
+}
+
+/// One line of the browser.
+#[derive(Debug, Clone)]
+pub struct BrowserEntry {
+    pub name: String,
+    pub category: BrowserCategory,
+    pub item: BrowserItem,
+}
+
+/// The browser's search text, favorites, and the track that double-clicked
+/// items go to.
+#[derive(Debug, Default)]
+pub struct EntityBrowser {
+    query: String,
+    favorites: BTreeSet<String>,
+    /// [TrackUid::default()] is the master track.
+    target: TrackUid,
+}
+impl EntityBrowser {
+    /// A browser that starts with the given favorites, as returned by
+    /// [EntityBrowser::favorites].
+    pub fn new_with(favorites: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            favorites: favorites.into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn set_query(&mut self, query: &str) {
+        self.query = query.to_string();
+    }
+
+    pub fn favorites(&self) -> impl Iterator<Item = &String> {
+        self.favorites.iter()
+    }
+
+    pub fn is_favorite(&self, item: &BrowserItem) -> bool {
+        self.favorites.contains(&item.favorite_key())
+    }
+
+    pub fn set_favorite(&mut self, item: &BrowserItem, is_favorite: bool) {
+        if is_favorite {
+            self.favorites.insert(item.favorite_key());
+        } else {
+            self.favorites.remove(&item.favorite_key());
+        }
+    }
+
+    /// Everything in the registry whose name contains the search text,
+    /// ignoring case, sorted by category and then by name.
+    pub fn entries(&self, registry: &EntityRegistry) -> Vec<BrowserEntry> {
+        let entities = registry.entries().iter().map(|entry| BrowserEntry {
+            name: entry.name.clone(),
+            category: if entry.roles.generates_audio {
+                BrowserCategory::Instrument
+            } else if entry.roles.transforms_audio {
+                BrowserCategory::Effect
+            } else {
+                BrowserCategory::Controller
+            },
+            item: BrowserItem::Entity(entry.key.clone()),
+        });
+        let plugins = registry
+            .plugin_host()
+            .descriptors()
+            .iter()
+            .map(|descriptor| BrowserEntry {
+                name: format!("{} ({})", descriptor.name, descriptor.format),
+                category: if descriptor.is_instrument {
+                    BrowserCategory::Instrument
+                } else {
+                    BrowserCategory::Effect
+                },
+                item: BrowserItem::Plugin(descriptor.clone()),
+            });
+        let query = self.query.to_lowercase();
+        let mut entries: Vec<_> = entities
+            .chain(plugins)
+            .filter(|entry| entry.name.to_lowercase().contains(&query))
+            .collect();
+        entries.sort_by(|a, b| (a.category, &a.name).cmp(&(b.category, &b.name)));
+        entries
+    }
+}
+#[cfg(feature = "gui")]
+impl EntityBrowser {
+    /// Shows the search box, the target track picker, and the matching
+    /// entries. Each entry can be dragged onto a track, or double-clicked to
+    /// add it to the target track, in which case this returns the track and
+    /// the item.
+    pub fn show(
+        &mut self,
+        ui: &mut Ui,
+        registry: &EntityRegistry,
+        tracks: &[(TrackUid, String)],
+    ) -> Option<(TrackUid, BrowserItem)> {
+        ui.text_edit_singleline(&mut self.query)
+            .on_hover_text("Search by name");
+        let target_name = tracks
+            .iter()
+            .find(|(uid, _)| *uid == self.target)
+            .map_or("Master", |(_, name)| name.as_str());
+        ComboBox::new(ui.next_auto_id(), "Add to")
+            .selected_text(target_name)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.target, TrackUid::default(), "Master");
+                for (uid, name) in tracks {
+                    ui.selectable_value(&mut self.target, *uid, name);
+                }
+            });
+
+        let entries = self.entries(registry);
+        let (favorites, others): (Vec<_>, Vec<_>) = entries
+            .into_iter()
+            .partition(|entry| self.is_favorite(&entry.item));
+        let mut picked = None;
+        let mut favorite_to_set = None;
+        let mut heading = None;
+        if !favorites.is_empty() {
+            ui.strong("Favorites");
+        }
+        for (i, entry) in favorites.iter().chain(others.iter()).enumerate() {
+            if i >= favorites.len() && heading != Some(entry.category) {
+                heading = Some(entry.category);
+                ui.strong(entry.category.to_string());
+            }
+            ui.horizontal(|ui| {
+                let is_favorite = i < favorites.len();
+                let star = if is_favorite { "★" } else { "☆" };
+                if ui.small_button(star).on_hover_text("Favorite").clicked() {
+                    favorite_to_set = Some((entry.item.clone(), !is_favorite));
+                }
+                let label = Label::new(&entry.name).sense(Sense::click_and_drag());
+                let response = ui.add(label).on_hover_text("Drag onto a track");
+                response.dnd_set_drag_payload(entry.item.clone());
+                if response.double_clicked() {
+                    picked = Some(entry.item.clone());
+                }
+            });
+        }
+        if let Some((item, is_favorite)) = favorite_to_set {
+            self.set_favorite(&item, is_favorite);
+        }
+        picked.map(|item| (self.target, item))
+    }
+}
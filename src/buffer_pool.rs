@@ -0,0 +1,51 @@
+use crossbeam_queue::ArrayQueue;
+use ensnare::prelude::*;
+use std::sync::OnceLock;
+
+/// Recycles the frame buffers that [AudioAction](crate::actions::AudioAction)s
+/// carry, so that the audio path stops allocating once it has warmed up.
+/// Whoever consumes a buffer hands it back with [BufferPool::recycle]; one
+/// that isn't handed back is simply dropped.
+#[derive(Debug)]
+pub struct BufferPool {
+    buffers: ArrayQueue<Vec<StereoSample>>,
+}
+impl BufferPool {
+    /// The most buffers the pool keeps. Buffers recycled beyond this are
+    /// dropped.
+    pub const CAPACITY: usize = 1024;
+
+    /// The pool that entities, tracks, and the engine service share.
+    pub fn global() -> &'static Self {
+        static POOL: OnceLock<BufferPool> = OnceLock::new();
+        POOL.get_or_init(|| Self {
+            buffers: ArrayQueue::new(Self::CAPACITY),
+        })
+    }
+
+    /// A buffer of the given number of silent frames.
+    pub fn take(&self, len: usize) -> Vec<StereoSample> {
+        let mut buffer = self.buffers.pop().unwrap_or_default();
+        buffer.clear();
+        buffer.resize(len, StereoSample::SILENCE);
+        buffer
+    }
+
+    /// A buffer holding a copy of the given frames.
+    pub fn take_copy(&self, frames: &[StereoSample]) -> Vec<StereoSample> {
+        let mut buffer = self.buffers.pop().unwrap_or_default();
+        buffer.clear();
+        buffer.extend_from_slice(frames);
+        buffer
+    }
+
+    /// Hands a buffer back for reuse.
+    pub fn recycle(&self, buffer: Vec<StereoSample>) {
+        let _ = self.buffers.push(buffer);
+    }
+
+    /// How many buffers are waiting to be reused.
+    pub fn available(&self) -> usize {
+        self.buffers.len()
+    }
+}
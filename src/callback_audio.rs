@@ -0,0 +1,270 @@
+//! Audio outputs that the device's callback drives. Rather than keeping a
+//! queue topped up the way [CpalAudioService] does, each callback asks the
+//! engine for the next period, so the output's latency is about one device
+//! buffer.
+//!
+//! The app opens one of these when the settings or the ACTOR_LOW_LATENCY
+//! environment variable give it a buffer size, with the
+//! [LowLatencyBackend] from the settings.
+//!
+//! On Windows, only [LowLatencyBackend::Asio] gets the latency down to the
+//! buffer size. cpal can open WASAPI only in shared mode, so
+//! [LowLatencyBackend::System] still goes through the Windows mixer, which
+//! adds its own buffer of about 10 ms. Exclusive mode would avoid that, but
+//! cpal doesn't offer it.
+
+use crate::engine::{Engine, EngineServiceInput};
+use anyhow::anyhow;
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    BufferSize, FromSample, SampleFormat, SizedSample, StreamConfig, SupportedBufferSize,
+};
+use crossbeam_channel::{Receiver, Sender};
+use ensnare::{prelude::*, types::CrossbeamChannel};
+use ensnare_services::prelude::*;
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, fmt::Display};
+
+/// Feeds a device callback from the engine, one period at a time. Each
+/// period plays the frames that the engine generated during the one before.
+#[derive(Debug)]
+pub(crate) struct CallbackFeed {
+    engine_sender: Sender<EngineServiceInput>,
+    receiver: Receiver<CpalAudioServiceInput>,
+    /// Frames that the engine generated but that haven't been played yet.
+    queue: VecDeque<(f32, f32)>,
+}
+impl CallbackFeed {
+    /// `period` is the device's buffer size, if it's known, and sizes the
+    /// queue so that the callback doesn't allocate.
+    pub(crate) fn new_with(
+        engine_sender: Sender<EngineServiceInput>,
+        receiver: Receiver<CpalAudioServiceInput>,
+        period: usize,
+    ) -> Self {
+        Self {
+            engine_sender,
+            receiver,
+            queue: VecDeque::with_capacity(period.max(Engine::MAX_BLOCK_SIZE) * 8),
+        }
+    }
+
+    pub(crate) fn engine_sender(&self) -> &Sender<EngineServiceInput> {
+        &self.engine_sender
+    }
+
+    /// The next `frame_count` frames, padded with silence if the engine is
+    /// behind. None once the device has been asked to quit.
+    pub(crate) fn next_period(
+        &mut self,
+        frame_count: usize,
+    ) -> Option<impl Iterator<Item = (f32, f32)> + '_> {
+        while let Ok(input) = self.receiver.try_recv() {
+            if let CpalAudioServiceInput::Frames(frames) = &input {
+                self.queue.extend(frames.iter());
+            } else if matches!(input, CpalAudioServiceInput::Quit) {
+                return None;
+            }
+        }
+        if self.queue.len() < frame_count {
+            let _ = self
+                .engine_sender
+                .try_send(EngineServiceInput::AudioUnderrun);
+        }
+        // The engine generates whole blocks, so it can get ahead of us. Only
+        // ask for more once less than a period would be left.
+        if self.queue.len().saturating_sub(frame_count) < frame_count {
+            let _ = self
+                .engine_sender
+                .try_send(EngineServiceInput::AudioQueueNeedsAudio(frame_count));
+        }
+        let available = self.queue.len().min(frame_count);
+        Some(
+            self.queue
+                .drain(..available)
+                .chain(std::iter::repeat((0.0, 0.0)))
+                .take(frame_count),
+        )
+    }
+}
+
+/// Which of the system's audio APIs to open the output with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LowLatencyBackend {
+    /// ASIO, in Windows builds with the `asio` feature.
+    Asio,
+    /// The platform's usual API: WASAPI on Windows, CoreAudio on macOS, and
+    /// ALSA on Linux. cpal opens WASAPI in shared mode, so Windows' mixer
+    /// still adds its own buffer; only ASIO avoids it.
+    System,
+}
+impl Display for LowLatencyBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            LowLatencyBackend::Asio => "ASIO",
+            LowLatencyBackend::System => "System",
+        })
+    }
+}
+impl LowLatencyBackend {
+    pub const ALL: [LowLatencyBackend; 2] = [Self::Asio, Self::System];
+
+    /// Whether this build can open the backend.
+    pub fn is_available(&self) -> bool {
+        match self {
+            Self::Asio => cfg!(all(windows, feature = "asio")),
+            Self::System => true,
+        }
+    }
+
+    /// ASIO if this build has it, and otherwise the system's API.
+    pub fn best_available() -> Self {
+        if Self::Asio.is_available() {
+            Self::Asio
+        } else {
+            Self::System
+        }
+    }
+
+    fn host(&self) -> anyhow::Result<cpal::Host> {
+        match self {
+            #[cfg(all(windows, feature = "asio"))]
+            Self::Asio => Ok(cpal::host_from_id(cpal::HostId::Asio)?),
+            #[cfg(not(all(windows, feature = "asio")))]
+            Self::Asio => Err(anyhow!(
+                "This build doesn't include ASIO. Rebuild on Windows with the `asio` feature."
+            )),
+            Self::System => Ok(cpal::default_host()),
+        }
+    }
+}
+
+/// Plays the engine's output on the backend's default device with a small,
+/// fixed buffer. It tells the engine the buffer size with
+/// [EngineServiceInput::Configure], so that the engine's blocks fit in it.
+///
+/// Give the engine [LowLatencyAudioService::audio_sender] with
+/// [EngineServiceInput::SetAudioSender].
+#[derive(Debug)]
+pub struct LowLatencyAudioService {
+    audio: CrossbeamChannel<CpalAudioServiceInput>,
+    backend: LowLatencyBackend,
+    /// The buffer size that the device agreed to.
+    period: usize,
+}
+impl LowLatencyAudioService {
+    /// Opens the device with a buffer as close to `period` frames as it
+    /// allows. The stream runs on its own thread, because cpal streams can't
+    /// move between threads on every platform.
+    pub fn new_with(
+        backend: LowLatencyBackend,
+        period: usize,
+        engine_sender: Sender<EngineServiceInput>,
+    ) -> anyhow::Result<Self> {
+        let audio: CrossbeamChannel<CpalAudioServiceInput> = Default::default();
+        let receiver = audio.receiver.clone();
+        let (opened_sender, opened_receiver) = crossbeam_channel::bounded(1);
+        std::thread::spawn(move || {
+            let (quit_sender, quit_receiver) = crossbeam_channel::bounded(1);
+            match Self::open(backend, period, engine_sender, receiver, quit_sender) {
+                Ok((stream, period)) => {
+                    let _ = opened_sender.send(Ok(period));
+                    let _ = quit_receiver.recv();
+                    drop(stream);
+                }
+                Err(e) => {
+                    let _ = opened_sender.send(Err(e));
+                }
+            }
+        });
+        let period = opened_receiver.recv()??;
+        Ok(Self {
+            audio,
+            backend,
+            period,
+        })
+    }
+
+    /// Where the engine should send its frames.
+    pub fn audio_sender(&self) -> &Sender<CpalAudioServiceInput> {
+        &self.audio.sender
+    }
+
+    pub fn backend(&self) -> LowLatencyBackend {
+        self.backend
+    }
+
+    pub fn period(&self) -> usize {
+        self.period
+    }
+
+    /// Starts the stream, and returns it with the buffer size it got.
+    fn open(
+        backend: LowLatencyBackend,
+        period: usize,
+        engine_sender: Sender<EngineServiceInput>,
+        receiver: Receiver<CpalAudioServiceInput>,
+        quit_sender: Sender<()>,
+    ) -> anyhow::Result<(cpal::Stream, usize)> {
+        let host = backend.host()?;
+        let device = host
+            .default_output_device()
+            .ok_or_else(|| anyhow!("{:?} has no output device", host.id()))?;
+        let supported = device.default_output_config()?;
+        let period = match supported.buffer_size() {
+            SupportedBufferSize::Range { min, max } => (period as u32).clamp(*min, *max),
+            SupportedBufferSize::Unknown => period as u32,
+        };
+        let config = StreamConfig {
+            channels: supported.channels(),
+            sample_rate: supported.sample_rate(),
+            buffer_size: BufferSize::Fixed(period),
+        };
+        let _ = engine_sender.try_send(EngineServiceInput::Configure(
+            SampleRate(config.sample_rate.0 as usize),
+            config.channels as u8,
+            Some(period as usize),
+        ));
+        let feed = CallbackFeed::new_with(engine_sender, receiver, period as usize);
+        let stream = match supported.sample_format() {
+            SampleFormat::F32 => Self::build::<f32>(&device, &config, feed, quit_sender)?,
+            SampleFormat::I32 => Self::build::<i32>(&device, &config, feed, quit_sender)?,
+            SampleFormat::I16 => Self::build::<i16>(&device, &config, feed, quit_sender)?,
+            format => return Err(anyhow!("Unsupported sample format {format:?}")),
+        };
+        stream.play()?;
+        Ok((stream, period as usize))
+    }
+
+    fn build<T: SizedSample + FromSample<f32>>(
+        device: &cpal::Device,
+        config: &StreamConfig,
+        mut feed: CallbackFeed,
+        quit_sender: Sender<()>,
+    ) -> anyhow::Result<cpal::Stream> {
+        let channels = config.channels as usize;
+        let stream = device.build_output_stream(
+            config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                let Some(frames) = feed.next_period(data.len() / channels) else {
+                    data.fill(T::EQUILIBRIUM);
+                    let _ = quit_sender.try_send(());
+                    return;
+                };
+                for (frame, (left, right)) in data.chunks_mut(channels).zip(frames) {
+                    for (i, sample) in frame.iter_mut().enumerate() {
+                        *sample = T::from_sample(match i {
+                            0 => left,
+                            1 => right,
+                            _ => 0.0,
+                        });
+                    }
+                }
+            },
+            |e| error!("Low-latency audio: {e:?}"),
+            None,
+        )?;
+        Ok(stream)
+    }
+}
@@ -0,0 +1,73 @@
+use ensnare::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+/// How many output channels the engine produces. Channels come in stereo
+/// pairs, and the master track's mixer sends each track to one pair. The
+/// first pair is the main output, which is what the audio device plays and
+/// master-track effects process.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChannelLayout {
+    #[default]
+    Stereo,
+    Quad,
+    /// In WAV channel order: front left/right, center/LFE, rear left/right.
+    Surround51,
+}
+impl Display for ChannelLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ChannelLayout::Stereo => "Stereo",
+            ChannelLayout::Quad => "Quad",
+            ChannelLayout::Surround51 => "5.1",
+        })
+    }
+}
+impl ChannelLayout {
+    pub const ALL: [ChannelLayout; 3] = [Self::Stereo, Self::Quad, Self::Surround51];
+
+    pub fn channel_count(&self) -> u8 {
+        match self {
+            ChannelLayout::Stereo => 2,
+            ChannelLayout::Quad => 4,
+            ChannelLayout::Surround51 => 6,
+        }
+    }
+
+    pub fn pair_count(&self) -> usize {
+        self.channel_count() as usize / 2
+    }
+
+    /// What the given pair is for, e.g., "Rear".
+    pub fn pair_name(&self, pair: usize) -> &'static str {
+        let names: &[&'static str] = match self {
+            ChannelLayout::Stereo => &["Main"],
+            ChannelLayout::Quad => &["Front", "Rear"],
+            ChannelLayout::Surround51 => &["Front", "Center/LFE", "Rear"],
+        };
+        names.get(pair).copied().unwrap_or("Unknown")
+    }
+}
+
+/// Lays out one frame of each pair as consecutive channels, filling in
+/// silence for missing pairs, and stopping after `channel_count` samples.
+/// `pairs` holds the pairs after the main one.
+pub fn interleave<'a>(
+    main: &'a [StereoSample],
+    pairs: &'a [&'a [StereoSample]],
+    channel_count: usize,
+) -> impl Iterator<Item = Sample> + 'a {
+    main.iter().enumerate().flat_map(move |(i, frame)| {
+        let others = (0..).map(move |pair| {
+            pairs
+                .get(pair)
+                .and_then(|p| p.get(i))
+                .copied()
+                .unwrap_or(StereoSample::SILENCE)
+        });
+        std::iter::once(*frame)
+            .chain(others)
+            .flat_map(|f| [f.0, f.1])
+            .take(channel_count)
+    })
+}
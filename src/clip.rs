@@ -0,0 +1,333 @@
+use crate::resampler::{resample, ResampleQuality};
+use anyhow::anyhow;
+use ensnare::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{collections::HashMap, path::Path, sync::Arc};
+
+/// A single MIDI event placed at a musical position.
+#[derive(Debug, Clone)]
+pub struct MidiClipEvent {
+    pub(crate) time: MusicalTime,
+    pub(crate) channel: MidiChannel,
+    pub(crate) message: MidiMessage,
+}
+
+/// A sequence of timestamped MIDI events, kept sorted by time.
+#[derive(Debug, Clone, Default)]
+pub struct MidiClip {
+    events: Vec<MidiClipEvent>,
+}
+impl MidiClip {
+    /// Adds an event, keeping the clip sorted. Events at the same time keep
+    /// the order in which they were recorded.
+    pub fn record(&mut self, time: MusicalTime, channel: MidiChannel, message: MidiMessage) {
+        let index = self.events.partition_point(|e| e.time <= time);
+        self.events.insert(
+            index,
+            MidiClipEvent {
+                time,
+                channel,
+                message,
+            },
+        );
+    }
+
+    /// Removes every event.
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &MidiClipEvent> {
+        self.events.iter()
+    }
+
+    /// Returns the events that fall within the given time range.
+    pub fn events_in(&self, time_range: &TimeRange) -> impl Iterator<Item = &MidiClipEvent> {
+        let start = self.events.partition_point(|e| e.time < time_range.0.start);
+        let end = self.events.partition_point(|e| e.time < time_range.0.end);
+        self.events[start..end].iter()
+    }
+
+    /// A copy with each note moved toward the nearest grid line.
+    pub fn quantized(&self, quantize: &Quantize) -> MidiClip {
+        self.moved(|event| (quantize.shift(event.time), 0))
+    }
+
+    /// A copy with each note moved and its velocity changed a little, at
+    /// random.
+    pub fn humanized(&self, humanize: &Humanize) -> MidiClip {
+        self.moved(|event| humanize.jitter(event))
+    }
+
+    /// `change` gives how far to move an event, in units, and how much to
+    /// change its velocity. Only note-ons get changed. A note's end moves
+    /// as far as its start did, so that the note keeps its length.
+    fn moved(&self, change: impl Fn(&MidiClipEvent) -> (i64, i16)) -> MidiClip {
+        let mut shifts: HashMap<(u8, u8), i64> = HashMap::default();
+        let mut r = MidiClip::default();
+        for event in self.events.iter() {
+            let mut message = event.message;
+            let shift = match event.message {
+                MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => {
+                    let (shift, velocity_change) = change(event);
+                    let vel = (vel.as_int() as i16 + velocity_change).clamp(1, 127) as u8;
+                    message = MidiMessage::NoteOn {
+                        key,
+                        vel: vel.into(),
+                    };
+                    shifts.insert((event.channel.0, key.as_int()), shift);
+                    shift
+                }
+                MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => shifts
+                    .remove(&(event.channel.0, key.as_int()))
+                    .unwrap_or_else(|| change(event).0),
+                _ => change(event).0,
+            };
+            let units = (event.time.total_units() as i64 + shift).max(0) as usize;
+            r.record(MusicalTime::new_with_units(units), event.channel, message);
+        }
+        r
+    }
+}
+
+/// Moves events toward a grid. See [MidiClip::quantized].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quantize {
+    /// The grid's spacing, in [MusicalTime] units.
+    pub grid_units: usize,
+    /// How much of the way to the grid line each event moves.
+    pub strength: Normal,
+}
+impl Default for Quantize {
+    fn default() -> Self {
+        Self::new_with(4, Normal::maximum())
+    }
+}
+impl Quantize {
+    /// The grids that the UI offers, as divisions of a beat, with their
+    /// names.
+    pub const DIVISIONS: [(usize, &'static str); 6] = [
+        (1, "1/4"),
+        (2, "1/8"),
+        (3, "1/8T"),
+        (4, "1/16"),
+        (6, "1/16T"),
+        (8, "1/32"),
+    ];
+
+    /// A grid of `division` steps per beat.
+    pub fn new_with(division: usize, strength: Normal) -> Self {
+        Self {
+            grid_units: MusicalTime::UNITS_IN_BEAT / division.max(1),
+            strength,
+        }
+    }
+
+    /// How far to move an event at the given time, in units.
+    fn shift(&self, time: MusicalTime) -> i64 {
+        let grid = self.grid_units.max(1) as i64;
+        let units = time.total_units() as i64;
+        let nearest = (units + grid / 2) / grid * grid;
+        ((nearest - units) as f64 * self.strength.0).round() as i64
+    }
+}
+
+/// Random changes to timing and velocity. See [MidiClip::humanized].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Humanize {
+    /// The most that an event moves either way, in [MusicalTime] units.
+    pub timing_units: usize,
+    /// The most that a note's velocity changes either way.
+    pub velocity: u8,
+    /// The same seed always changes the same clip the same way, so that a
+    /// track can humanize its clip again each time it changes.
+    pub seed: u64,
+}
+impl Default for Humanize {
+    fn default() -> Self {
+        Self {
+            timing_units: MusicalTime::UNITS_IN_BEAT / 64,
+            velocity: 8,
+            seed: 0,
+        }
+    }
+}
+impl Humanize {
+    /// Each event's changes depend only on the seed, its time, and its key,
+    /// so recording more events doesn't change the earlier ones.
+    fn jitter(&self, event: &MidiClipEvent) -> (i64, i16) {
+        let key = match event.message {
+            MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => key.as_int(),
+            _ => 0,
+        };
+        let time = event.time.total_units() as u64;
+        let mut rng = StdRng::seed_from_u64(self.seed ^ (time << 8) ^ key as u64);
+        let timing = self.timing_units as i64;
+        let velocity = self.velocity as i16;
+        (
+            rng.gen_range(-timing..=timing),
+            rng.gen_range(-velocity..=velocity),
+        )
+    }
+}
+
+/// Quantizing and humanizing that a track applies to its clip as it plays
+/// it back, leaving the recorded clip alone.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ClipEdits {
+    pub quantize: Option<Quantize>,
+    pub humanize: Option<Humanize>,
+}
+impl ClipEdits {
+    pub fn is_empty(&self) -> bool {
+        self.quantize.is_none() && self.humanize.is_none()
+    }
+
+    /// The clip as it should play back: quantized, then humanized.
+    pub fn apply(&self, clip: &MidiClip) -> MidiClip {
+        let quantized = match self.quantize.as_ref() {
+            Some(quantize) => clip.quantized(quantize),
+            None => clip.clone(),
+        };
+        match self.humanize.as_ref() {
+            Some(humanize) => quantized.humanized(humanize),
+            None => quantized,
+        }
+    }
+}
+
+/// Where a clip sits on a track, for drawing the arrangement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClipSpan {
+    pub start: MusicalTime,
+    pub end: MusicalTime,
+    pub is_audio: bool,
+}
+
+/// A region of audio placed at a musical position on a track.
+#[derive(Debug, Clone)]
+pub struct AudioClip {
+    start: MusicalTime,
+    sample_rate: SampleRate,
+    frames: Arc<Vec<StereoSample>>,
+}
+impl AudioClip {
+    pub fn new_with(
+        start: MusicalTime,
+        sample_rate: SampleRate,
+        frames: Vec<StereoSample>,
+    ) -> Self {
+        Self {
+            start,
+            sample_rate,
+            frames: Arc::new(frames),
+        }
+    }
+
+    /// Loads a WAV file into a clip that starts at the given position.
+    pub fn new_from_wav(path: &Path, start: MusicalTime) -> anyhow::Result<Self> {
+        let (sample_rate, frames) = read_wav(path)?;
+        Ok(Self::new_with(start, sample_rate, frames))
+    }
+
+    /// A copy of the clip converted to the given sample rate, so that
+    /// [AudioClip::mix_into] can copy frames rather than interpolate them.
+    pub fn resampled(&self, sample_rate: SampleRate, quality: ResampleQuality) -> Self {
+        Self::new_with(
+            self.start,
+            sample_rate,
+            resample(&self.frames, self.sample_rate, sample_rate, quality),
+        )
+    }
+
+    /// Adds the part of the clip that overlaps the buffer beginning at `time`
+    /// into `dest`, converting from the clip's sample rate to `sample_rate`.
+    /// That conversion is linear, so it's best if the rates already match.
+    pub fn mix_into(
+        &self,
+        time: MusicalTime,
+        tempo: Tempo,
+        sample_rate: SampleRate,
+        dest: &mut [StereoSample],
+    ) {
+        if self.frames.is_empty() {
+            return;
+        }
+        let beats = Self::beats(time) - Self::beats(self.start);
+        let offset_seconds = beats * 60.0 / tempo.0;
+        let step = self.sample_rate.0 as f64 / sample_rate.0 as f64;
+        let mut position = offset_seconds * self.sample_rate.0 as f64;
+        for frame in dest.iter_mut() {
+            if position >= 0.0 {
+                // Linear interpolation between the two nearest source frames.
+                let index = position as usize;
+                if index + 1 >= self.frames.len() {
+                    break;
+                }
+                let fraction = position - index as f64;
+                let a = self.frames[index];
+                let b = self.frames[index + 1];
+                *frame += StereoSample(
+                    Sample(a.0 .0 + (b.0 .0 - a.0 .0) * fraction),
+                    Sample(a.1 .0 + (b.1 .0 - a.1 .0) * fraction),
+                );
+            }
+            position += step;
+        }
+    }
+
+    pub fn start(&self) -> MusicalTime {
+        self.start
+    }
+
+    /// The position just after the clip's last frame at the given tempo.
+    pub fn end(&self, tempo: Tempo) -> MusicalTime {
+        let seconds = self.frames.len() as f64 / self.sample_rate.0 as f64;
+        let units = seconds * tempo.0 / 60.0 * MusicalTime::UNITS_IN_BEAT as f64;
+        MusicalTime::new_with_units(self.start.total_units() + units as usize)
+    }
+
+    fn beats(time: MusicalTime) -> f64 {
+        time.total_units() as f64 / MusicalTime::UNITS_IN_BEAT as f64
+    }
+}
+
+/// Reads a WAV file's frames and sample rate. Mono files are copied to both
+/// channels, and extra channels are ignored.
+pub fn read_wav(path: &Path) -> anyhow::Result<(SampleRate, Vec<StereoSample>)> {
+    let mut reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+    let samples: Vec<f64> = match spec.sample_format {
+        hound::SampleFormat::Float => reader
+            .samples::<f32>()
+            .map(|s| s.map(|s| s as f64))
+            .collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f64;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f64 / scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+    let frames = match spec.channels {
+        0 => return Err(anyhow!("{path:?} has no channels")),
+        1 => samples
+            .iter()
+            .map(|&s| StereoSample(Sample(s), Sample(s)))
+            .collect(),
+        channels => samples
+            .chunks_exact(channels as usize)
+            .map(|c| StereoSample(Sample(c[0]), Sample(c[1])))
+            .collect(),
+    };
+    Ok((SampleRate(spec.sample_rate as usize), frames))
+}
@@ -0,0 +1,174 @@
+use crate::{
+    clip::{ClipEdits, Humanize, MidiClip, Quantize},
+    engine::{ControlRoute, DetachedTrack},
+    mixer::{CrossfadeCurve, CrossfadeGroup},
+    plugin::PluginDescriptor,
+    track::{DetachedEntity, EntityClipboard},
+    transfer::TransferFunction,
+};
+use ensnare::prelude::*;
+use std::sync::Arc;
+
+/// A change to the project that can be undone. The UI sends these to the
+/// [Engine](crate::engine::Engine) rather than making the change itself, and
+/// applying one produces its inverse.
+#[derive(Debug)]
+pub enum Command {
+    /// Add an empty track at the end.
+    AddTrack,
+    /// Delete the track.
+    DeleteTrack(TrackUid),
+    /// Put a deleted track back where it was.
+    RestoreTrack(DetachedTrack),
+    /// Add the entity registered under the given key to the track.
+    AddEntity(TrackUid, String),
+    /// Add an instance of the plugin to the track.
+    AddPlugin(TrackUid, PluginDescriptor),
+    /// Remove the entity from the track.
+    RemoveEntity(TrackUid, Uid),
+    /// Put a removed entity back where it was.
+    RestoreEntity(TrackUid, DetachedEntity),
+    /// Add copies of the clipboard's entities to the track.
+    PasteEntities(TrackUid, Arc<EntityClipboard>),
+    /// Move the entities from the first track to the end of the second.
+    MoveEntities(TrackUid, TrackUid, Vec<Uid>),
+    /// Move the entity to this position in its track's serial effects chain.
+    MoveEntity(TrackUid, Uid, usize),
+    /// Put the effect in a parallel group, or None for serial.
+    SetEffectGroup(TrackUid, Uid, Option<usize>),
+    /// Set how much of the effect's output replaces its input.
+    SetEffectMix(TrackUid, Uid, Normal),
+    /// Add the entity to its track's MIDI-effect chain (true), or take it
+    /// out (false).
+    SetMidiEffect(TrackUid, Uid, bool),
+    /// Reshape the signal of the source entity's link.
+    SetControlTransfer(TrackUid, Uid, ControlLink, TransferFunction),
+    /// Arm (true) or disarm (false) the track for MIDI recording.
+    ArmTrack(TrackUid, bool),
+    /// Ask all of the track's instruments for audio with one shared buffer
+    /// (true), or one at a time (false).
+    SetBatchGenerators(TrackUid, bool),
+    /// Drive the target's parameter with the source entity's control signal.
+    Link(TrackUid, Uid, ControlLink),
+    /// Undo a [Command::Link].
+    Unlink(TrackUid, Uid, ControlLink),
+    /// Drive a parameter with an entity's control signal, across tracks.
+    RouteControl(ControlRoute),
+    /// Undo a [Command::RouteControl].
+    UnrouteControl(ControlRoute),
+    /// Set the track's level in the master mixer.
+    SetMixerLevel(TrackUid, Normal),
+    /// Mute (true) or unmute (false) the track in the master mixer.
+    SetMixerMute(TrackUid, bool),
+    /// Send the track to this output pair of the
+    /// [ChannelLayout](crate::channels::ChannelLayout).
+    SetOutputPair(TrackUid, usize),
+    /// Put the track on a side of the master mixer's crossfader.
+    SetCrossfadeGroup(TrackUid, CrossfadeGroup),
+    /// Move the master mixer's crossfader.
+    SetCrossfader(Normal),
+    SetCrossfadeCurve(CrossfadeCurve),
+    /// Replace the track's MIDI clip.
+    SetMidiClip(TrackUid, MidiClip),
+    /// Move the notes in the track's MIDI clip toward a grid.
+    QuantizeMidiClip(TrackUid, Quantize),
+    /// Move the notes in the track's MIDI clip, and change their velocities,
+    /// a little at random.
+    HumanizeMidiClip(TrackUid, Humanize),
+    /// Change the quantizing and humanizing that the track applies to its
+    /// MIDI clip during playback.
+    SetClipEdits(TrackUid, ClipEdits),
+    /// Several commands, applied in order, that undo as one step.
+    Batch(Vec<Command>),
+}
+
+/// The undo and redo stacks. Each holds the commands that reverse what was
+/// last done or undone.
+#[derive(Debug, Default)]
+pub struct CommandHistory {
+    undo: Vec<Command>,
+    redo: Vec<Command>,
+}
+impl CommandHistory {
+    /// How many steps back undo can go.
+    const MAX_DEPTH: usize = 100;
+
+    /// Records the inverse of a newly applied command. This forgets anything
+    /// that was undone. A slider drag sends a command for each frame, so
+    /// consecutive level changes to the same track, consecutive crossfader
+    /// moves, consecutive playback edits to the same track's clip, or
+    /// consecutive changes to the same effect's mix or link's curve, undo as
+    /// one step.
+    pub fn push(&mut self, inverse: Command) {
+        self.redo.clear();
+        if let (Some(Command::SetMixerLevel(last_uid, _)), Command::SetMixerLevel(uid, _)) =
+            (self.undo.last(), &inverse)
+        {
+            if last_uid == uid {
+                return;
+            }
+        }
+        if let (Some(Command::SetCrossfader(_)), Command::SetCrossfader(_)) =
+            (self.undo.last(), &inverse)
+        {
+            return;
+        }
+        if let (Some(Command::SetClipEdits(last_uid, _)), Command::SetClipEdits(uid, _)) =
+            (self.undo.last(), &inverse)
+        {
+            if last_uid == uid {
+                return;
+            }
+        }
+        if let (
+            Some(Command::SetEffectMix(last_track_uid, last_uid, _)),
+            Command::SetEffectMix(track_uid, uid, _),
+        ) = (self.undo.last(), &inverse)
+        {
+            if last_track_uid == track_uid && last_uid == uid {
+                return;
+            }
+        }
+        if let (
+            Some(Command::SetControlTransfer(last_track_uid, last_uid, last_link, _)),
+            Command::SetControlTransfer(track_uid, uid, link, _),
+        ) = (self.undo.last(), &inverse)
+        {
+            if last_track_uid == track_uid
+                && last_uid == uid
+                && last_link.uid == link.uid
+                && last_link.param == link.param
+            {
+                return;
+            }
+        }
+        if self.undo.len() == Self::MAX_DEPTH {
+            self.undo.remove(0);
+        }
+        self.undo.push(inverse);
+    }
+
+    pub fn pop_undo(&mut self) -> Option<Command> {
+        self.undo.pop()
+    }
+
+    pub fn push_undo(&mut self, inverse: Command) {
+        self.undo.push(inverse);
+    }
+
+    pub fn pop_redo(&mut self) -> Option<Command> {
+        self.redo.pop()
+    }
+
+    pub fn push_redo(&mut self, inverse: Command) {
+        self.redo.push(inverse);
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
@@ -0,0 +1,90 @@
+use ensnare::prelude::*;
+use rand::Rng;
+
+/// Two sets of values for an entity's parameters, A and B, for comparing
+/// settings by ear, plus a way to roll random ones. It goes only through
+/// [ControlIndex], so it works with any [Controllable], including plugins.
+///
+/// [Controllable] can set a parameter but can't report one, so each
+/// snapshot holds only the values set from here. Switching to a snapshot
+/// leaves its missing parameters alone.
+#[derive(Debug, Default)]
+pub struct ParameterCompare {
+    /// A, then B, each holding a value per [ControlIndex].
+    snapshots: [Vec<Option<ControlValue>>; 2],
+    is_b: bool,
+    /// The lowest and highest random value for each parameter.
+    ranges: Vec<(f64, f64)>,
+}
+impl ParameterCompare {
+    pub fn is_b(&self) -> bool {
+        self.is_b
+    }
+
+    pub fn range(&self, index: ControlIndex) -> (f64, f64) {
+        self.ranges.get(index.0).copied().unwrap_or((0.0, 1.0))
+    }
+
+    /// Both ends are clamped to 0.0..=1.0, and swapped if they're reversed.
+    pub fn set_range(&mut self, index: ControlIndex, range: (f64, f64)) {
+        let (low, high) = (range.0.clamp(0.0, 1.0), range.1.clamp(0.0, 1.0));
+        if self.ranges.len() <= index.0 {
+            self.ranges.resize(index.0 + 1, (0.0, 1.0));
+        }
+        self.ranges[index.0] = (low.min(high), low.max(high));
+    }
+
+    /// The parameter's value in the current snapshot, if it was set from
+    /// here.
+    pub fn current(&self, index: ControlIndex) -> Option<ControlValue> {
+        self.snapshots[self.is_b as usize]
+            .get(index.0)
+            .copied()
+            .flatten()
+    }
+
+    /// Notes a value that was set some other way, so that the current
+    /// snapshot keeps it.
+    pub fn record(&mut self, index: ControlIndex, value: ControlValue) {
+        let snapshot = &mut self.snapshots[self.is_b as usize];
+        if snapshot.len() <= index.0 {
+            snapshot.resize(index.0 + 1, None);
+        }
+        snapshot[index.0] = Some(value);
+    }
+
+    /// Picks a value within its range for each of the entity's `count`
+    /// parameters, and stores them in the current snapshot. Returns them for
+    /// sending to the entity.
+    pub fn randomize(&mut self, count: usize) -> Vec<(ControlIndex, ControlValue)> {
+        let mut rng = rand::thread_rng();
+        let values: Vec<_> = (0..count)
+            .map(ControlIndex)
+            .map(|index| {
+                let (low, high) = self.range(index);
+                (index, ControlValue(rng.gen_range(low..=high)))
+            })
+            .collect();
+        for &(index, value) in values.iter() {
+            self.record(index, value);
+        }
+        values
+    }
+
+    /// Switches to the other snapshot, and returns its values for sending to
+    /// the entity.
+    pub fn toggle(&mut self) -> Vec<(ControlIndex, ControlValue)> {
+        self.is_b = !self.is_b;
+        self.snapshots[self.is_b as usize]
+            .iter()
+            .enumerate()
+            .filter_map(|(i, value)| value.map(|value| (ControlIndex(i), value)))
+            .collect()
+    }
+
+    /// Replaces the other snapshot with a copy of the current one.
+    pub fn copy_to_other(&mut self) {
+        let current = self.snapshots[self.is_b as usize].clone();
+        self.snapshots[!self.is_b as usize] = current;
+    }
+}
@@ -0,0 +1,145 @@
+use eframe::egui::{Button, Color32, Key, ScrollArea, TextEdit, Ui};
+use ensnare::prelude::*;
+use spike_actor_system::{
+    engine::Engine,
+    inject::Injection,
+    script::ScriptHost,
+    trace::{MessageTrace, TraceEvent},
+};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// A panel for typing in scripts and running them against the engine.
+#[derive(Debug, Default)]
+pub struct ScriptConsole {
+    /// None until the engine has started.
+    host: Option<ScriptHost>,
+    source: String,
+    /// What past runs printed, and any errors.
+    output: Vec<String>,
+}
+impl ScriptConsole {
+    /// The engine restarted, so scripts should now talk to this one.
+    pub fn set_engine(&mut self, engine: Arc<Mutex<Engine>>) {
+        self.host = Some(ScriptHost::new_with(engine));
+    }
+
+    fn run(&mut self) {
+        let Some(host) = self.host.as_ref() else {
+            return;
+        };
+        match host.run(&self.source) {
+            Ok(lines) => self.output.extend(lines),
+            Err(e) => self.output.push(format!("Error: {e}")),
+        }
+    }
+}
+impl Displays for ScriptConsole {
+    fn ui(&mut self, ui: &mut Ui) -> eframe::egui::Response {
+        let response = ui.heading("Script");
+        ui.add(
+            TextEdit::multiline(&mut self.source)
+                .code_editor()
+                .desired_rows(6)
+                .hint_text("let t = create_track();\nadd_entity(t, \"toy-synth\");"),
+        );
+        ui.horizontal(|ui| {
+            if ui.add_enabled(self.host.is_some(), Button::new("Run")).clicked() {
+                self.run();
+            }
+            if ui.button("Clear").clicked() {
+                self.output.clear();
+            }
+        });
+        ScrollArea::vertical()
+            .max_height(160.0)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for line in self.output.iter() {
+                    ui.monospace(line);
+                }
+            });
+        response
+    }
+}
+
+/// A panel for sending hand-made requests to track and entity actors, and
+/// watching the messages that follow.
+#[derive(Debug, Default)]
+pub struct MessageConsole {
+    /// None until the engine has started.
+    engine: Option<Arc<Mutex<Engine>>>,
+    line: String,
+    error: Option<String>,
+    /// When the last request went out, in [MessageTrace] time.
+    injected_at: Option<Duration>,
+}
+impl MessageConsole {
+    /// At most this many of the messages that follow a request are shown.
+    const MAX_EVENTS: usize = 200;
+
+    /// The engine restarted, so requests should now go to this one.
+    pub fn set_engine(&mut self, engine: Arc<Mutex<Engine>>) {
+        self.engine = Some(engine);
+    }
+
+    fn send(&mut self) {
+        let Some(engine) = self.engine.as_ref() else {
+            return;
+        };
+        let result = Injection::parse(&self.line).and_then(|injection| {
+            // Turn the trace on first, so that it catches the first reply.
+            let trace = MessageTrace::global();
+            trace.set_enabled(true);
+            self.injected_at = Some(trace.elapsed());
+            engine.lock().unwrap().inject(injection)
+        });
+        self.error = result.err().map(|e| format!("Error: {e}"));
+    }
+
+    fn events(&self) -> Vec<TraceEvent> {
+        let Some(injected_at) = self.injected_at else {
+            return Vec::default();
+        };
+        let events = MessageTrace::global().events();
+        events
+            .into_iter()
+            .filter(|event| event.at >= injected_at)
+            .take(Self::MAX_EVENTS)
+            .collect()
+    }
+}
+impl Displays for MessageConsole {
+    fn ui(&mut self, ui: &mut Ui) -> eframe::egui::Response {
+        let response = ui.heading("Inject").on_hover_text(Injection::SYNTAX);
+        ui.horizontal(|ui| {
+            let edit = TextEdit::singleline(&mut self.line)
+                .code_editor()
+                .hint_text("track 1 needs-audio 64");
+            let edit_response = ui.add(edit).on_hover_text(Injection::SYNTAX);
+            let is_submitted =
+                edit_response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter));
+            let button = Button::new("Send");
+            if ui.add_enabled(self.engine.is_some(), button).clicked() || is_submitted {
+                self.send();
+            }
+        });
+        if let Some(error) = self.error.as_ref() {
+            ui.colored_label(Color32::RED, error);
+        }
+        ScrollArea::vertical()
+            .id_source("injection-trace")
+            .max_height(160.0)
+            .show(ui, |ui| {
+                for event in self.events() {
+                    ui.monospace(format!(
+                        "{} → {}: {}",
+                        event.source, event.destination, event.variant
+                    ));
+                }
+            });
+        response
+    }
+}
@@ -0,0 +1,85 @@
+use ensnare::prelude::*;
+
+/// Ramps the master output's gain around play and stop, so that starting or
+/// stopping in the middle of a waveform doesn't pop. The
+/// [Engine](crate::engine::Engine) fades in when the transport starts, and
+/// holds off stopping the transport until the fade-out has finished.
+#[derive(Debug)]
+pub struct Declicker {
+    gain: f64,
+    /// Where the gain is heading: 0.0 or 1.0.
+    target: f64,
+    /// How far the gain moves each frame.
+    step: f64,
+}
+impl Default for Declicker {
+    fn default() -> Self {
+        Self {
+            gain: 1.0,
+            target: 1.0,
+            step: 1.0,
+        }
+    }
+}
+impl Declicker {
+    const RAMP_SECONDS: f64 = 0.002;
+
+    /// Starts over from silence.
+    pub fn fade_in(&mut self, sample_rate: SampleRate) {
+        self.gain = 0.0;
+        self.ramp_to(1.0, sample_rate);
+    }
+
+    /// Heads for silence. Returns false if it was already doing so.
+    pub fn fade_out(&mut self, sample_rate: SampleRate) -> bool {
+        if self.is_fading_out() {
+            return false;
+        }
+        self.ramp_to(0.0, sample_rate);
+        true
+    }
+
+    /// Heads back up from wherever the gain is, e.g., when play is pressed
+    /// during a fade-out.
+    pub fn cancel_fade_out(&mut self) {
+        self.target = 1.0;
+    }
+
+    pub fn is_fading_out(&self) -> bool {
+        self.target == 0.0
+    }
+
+    /// Returns true, once, when a fade-out has reached silence. The gain
+    /// then ramps back up, so that whatever sounds after the transport stops,
+    /// such as release tails, fades in too.
+    pub fn take_finished_fade_out(&mut self) -> bool {
+        if self.is_fading_out() && self.gain == 0.0 {
+            self.target = 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn process(&mut self, frames: &mut [StereoSample]) {
+        if self.gain == 1.0 && self.target == 1.0 {
+            return;
+        }
+        for frame in frames.iter_mut() {
+            self.gain = if self.gain < self.target {
+                (self.gain + self.step).min(self.target)
+            } else {
+                (self.gain - self.step).max(self.target)
+            };
+            *frame = StereoSample(
+                Sample(frame.0 .0 * self.gain),
+                Sample(frame.1 .0 * self.gain),
+            );
+        }
+    }
+
+    fn ramp_to(&mut self, target: f64, sample_rate: SampleRate) {
+        self.target = target;
+        self.step = 1.0 / (Self::RAMP_SECONDS * sample_rate.0 as f64).max(1.0);
+    }
+}
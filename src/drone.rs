@@ -23,11 +23,14 @@ pub struct DroneController {
     #[serde(skip)]
     oscillator_buffer: GenerationBuffer<BipolarNormal>,
 }
+#[cfg(feature = "gui")]
 impl Displays for DroneController {
     fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
         ui.label(format!("Drone value: {:.4}", self.value.0))
     }
 }
+#[cfg(not(feature = "gui"))]
+impl Displays for DroneController {}
 impl Controls for DroneController {
     fn time_range(&self) -> Option<TimeRange> {
         Some(self.time_range.clone())
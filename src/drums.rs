@@ -0,0 +1,291 @@
+use crate::{
+    clip::read_wav,
+    resampler::{resample, ResampleQuality},
+};
+#[cfg(feature = "gui")]
+use eframe::egui::{CollapsingHeader, DragValue, Slider};
+use ensnare::prelude::*;
+use ensnare_proc_macros::{IsEntity, Metadata};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, sync::Arc};
+
+const PAD_COUNT: usize = 16;
+/// The General MIDI percussion note for each pad: kick, snare, hi-hats,
+/// toms, cymbals, and a few hand percussion sounds.
+const DEFAULT_NOTES: [u8; PAD_COUNT] = [
+    36, 38, 42, 46, 41, 43, 45, 47, 49, 51, 39, 37, 56, 54, 69, 70,
+];
+/// The most hits that can ring at once, across all pads.
+const MAX_VOICES: usize = 32;
+
+/// One sample, and how it plays.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DrumPad {
+    /// The MIDI note that plays it.
+    pub note: u8,
+    /// The WAV file it plays. It's loaded again after deserialization.
+    pub path: Option<PathBuf>,
+    pub level: Normal,
+    pub pan: BipolarNormal,
+    /// Playing a pad silences the others in its group, as an open hi-hat is
+    /// cut off by a closed one.
+    pub choke_group: Option<u8>,
+
+    /// The file's frames, at the file's sample rate.
+    #[serde(skip)]
+    source: Option<(SampleRate, Arc<Vec<StereoSample>>)>,
+    /// The file's frames, at the entity's sample rate.
+    #[serde(skip)]
+    frames: Arc<Vec<StereoSample>>,
+    /// What the user has typed for the pad's file.
+    #[cfg(feature = "gui")]
+    #[serde(skip)]
+    path_text: String,
+}
+impl DrumPad {
+    fn new_with(note: u8) -> Self {
+        Self {
+            note,
+            path: None,
+            level: Normal::maximum(),
+            pan: BipolarNormal::from(0.0),
+            choke_group: None,
+            source: None,
+            frames: Default::default(),
+            #[cfg(feature = "gui")]
+            path_text: Default::default(),
+        }
+    }
+
+    fn load(&mut self, path: PathBuf, sample_rate: SampleRate) -> anyhow::Result<()> {
+        let (file_sample_rate, frames) = read_wav(&path)?;
+        self.source = Some((file_sample_rate, Arc::new(frames)));
+        #[cfg(feature = "gui")]
+        self.path_text = path.display().to_string();
+        self.path = Some(path);
+        self.fit_sample_rate(sample_rate);
+        Ok(())
+    }
+
+    fn fit_sample_rate(&mut self, sample_rate: SampleRate) {
+        if let Some((file_sample_rate, frames)) = self.source.as_ref() {
+            self.frames = if file_sample_rate.0 == sample_rate.0 {
+                Arc::clone(frames)
+            } else {
+                let quality = ResampleQuality::default();
+                Arc::new(resample(frames, *file_sample_rate, sample_rate, quality))
+            };
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct DrumVoice {
+    pad: usize,
+    position: usize,
+    /// Both channels' gains, from the pad's level and pan and the hit's
+    /// velocity.
+    gains: (f64, f64),
+}
+
+/// Plays a WAV file for each of sixteen pads, each triggered by its own
+/// MIDI note. Each pad's level and pan are controls, named like
+/// `pad-1-level`.
+#[derive(Debug, IsEntity, Metadata, Serialize, Deserialize)]
+#[entity(Controls, TransformsAudio)]
+pub struct DrumMachine {
+    uid: Uid,
+    pads: Vec<DrumPad>,
+
+    #[serde(skip)]
+    sample_rate: SampleRate,
+    #[serde(skip)]
+    voices: Vec<DrumVoice>,
+}
+impl Default for DrumMachine {
+    fn default() -> Self {
+        Self {
+            uid: Default::default(),
+            pads: DEFAULT_NOTES.into_iter().map(DrumPad::new_with).collect(),
+            sample_rate: Default::default(),
+            voices: Default::default(),
+        }
+    }
+}
+impl Serializable for DrumMachine {
+    fn after_deser(&mut self) {
+        let sample_rate = self.sample_rate;
+        for (index, pad) in self.pads.iter_mut().enumerate() {
+            if let Some(path) = pad.path.clone() {
+                if let Err(e) = pad.load(path, sample_rate) {
+                    warn!("While loading pad {}'s sample: {e:?}", index + 1);
+                }
+            }
+        }
+    }
+}
+impl HandlesMidi for DrumMachine {
+    fn handle_midi_message(
+        &mut self,
+        _channel: MidiChannel,
+        message: MidiMessage,
+        _midi_messages_fn: &mut MidiMessagesFn,
+    ) {
+        // Samples play to the end, so NoteOffs don't matter.
+        if let MidiMessage::NoteOn { key, vel } = message {
+            if vel.as_int() > 0 {
+                self.hit(key.as_int(), vel.as_int());
+            }
+        }
+    }
+}
+impl Generates<StereoSample> for DrumMachine {
+    fn generate(&mut self, values: &mut [StereoSample]) -> bool {
+        values.fill(StereoSample::SILENCE);
+        for voice in self.voices.iter_mut() {
+            let frames = &self.pads[voice.pad].frames;
+            let remaining = frames.get(voice.position..).unwrap_or_default();
+            for (value, frame) in values.iter_mut().zip(remaining) {
+                *value = StereoSample(
+                    Sample(value.0 .0 + frame.0 .0 * voice.gains.0),
+                    Sample(value.1 .0 + frame.1 .0 * voice.gains.1),
+                );
+            }
+            voice.position += values.len();
+        }
+        let is_sounding = !self.voices.is_empty();
+        let pads = &self.pads;
+        self.voices
+            .retain(|voice| voice.position < pads[voice.pad].frames.len());
+        is_sounding
+    }
+}
+impl Configurable for DrumMachine {
+    fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate;
+        for pad in self.pads.iter_mut() {
+            pad.fit_sample_rate(sample_rate);
+        }
+    }
+}
+impl Controllable for DrumMachine {
+    fn control_index_count(&self) -> usize {
+        self.pads.len() * 2
+    }
+
+    fn control_name_for_index(&self, index: ControlIndex) -> Option<String> {
+        (index.0 < self.control_index_count()).then(|| {
+            let param = if index.0 % 2 == 0 { "level" } else { "pan" };
+            format!("pad-{}-{param}", index.0 / 2 + 1)
+        })
+    }
+
+    fn control_index_for_name(&self, name: &str) -> Option<ControlIndex> {
+        (0..self.control_index_count())
+            .map(ControlIndex)
+            .find(|&index| self.control_name_for_index(index).as_deref() == Some(name))
+    }
+
+    fn control_set_param_by_index(&mut self, index: ControlIndex, value: ControlValue) {
+        if let Some(pad) = self.pads.get_mut(index.0 / 2) {
+            if index.0 % 2 == 0 {
+                pad.level = Normal::from(value.0);
+            } else {
+                pad.pan = BipolarNormal::from(value.0 * 2.0 - 1.0);
+            }
+        }
+    }
+
+    fn control_set_param_by_name(&mut self, name: &str, value: ControlValue) {
+        if let Some(index) = self.control_index_for_name(name) {
+            self.control_set_param_by_index(index, value);
+        }
+    }
+}
+#[cfg(feature = "gui")]
+impl Displays for DrumMachine {
+    fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
+        let response = ui.label(format!("Hits ringing: {}", self.voices.len()));
+        let sample_rate = self.sample_rate;
+        for (index, pad) in self.pads.iter_mut().enumerate() {
+            let name = pad
+                .path
+                .as_ref()
+                .and_then(|path| path.file_stem())
+                .map_or("empty".into(), |stem| stem.to_string_lossy().to_string());
+            CollapsingHeader::new(format!("Pad {} ({name})", index + 1))
+                .id_source(("drum-pad", index))
+                .show(ui, |ui| {
+                    let note = DragValue::new(&mut pad.note).clamp_range(0..=127);
+                    ui.add(note.prefix("Note: "));
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut pad.path_text);
+                        if ui.button("Load").clicked() {
+                            let path = PathBuf::from(pad.path_text.trim());
+                            if let Err(e) = pad.load(path, sample_rate) {
+                                warn!("While loading pad {}'s sample: {e:?}", index + 1);
+                            }
+                        }
+                    });
+                    let mut level = pad.level.0;
+                    let slider = Slider::new(&mut level, Normal::range()).text("Level");
+                    if ui.add(slider).changed() {
+                        pad.level = Normal::from(level);
+                    }
+                    let mut pan = pad.pan.0;
+                    let slider = Slider::new(&mut pan, -1.0..=1.0).text("Pan");
+                    if ui.add(slider).changed() {
+                        pad.pan = BipolarNormal::from(pan);
+                    }
+                    let mut group = pad.choke_group.unwrap_or_default();
+                    let choke = DragValue::new(&mut group).clamp_range(0..=8);
+                    if ui
+                        .add(choke.prefix("Choke group: "))
+                        .on_hover_text("0 = none")
+                        .changed()
+                    {
+                        pad.choke_group = (group != 0).then_some(group);
+                    }
+                });
+        }
+        response
+    }
+}
+#[cfg(not(feature = "gui"))]
+impl Displays for DrumMachine {}
+impl DrumMachine {
+    fn hit(&mut self, key: u8, velocity: u8) {
+        let velocity = velocity as f64 / 127.0;
+        for index in 0..self.pads.len() {
+            let pad = &self.pads[index];
+            if pad.note != key || pad.frames.is_empty() {
+                continue;
+            }
+            if let Some(group) = pad.choke_group {
+                let pads = &self.pads;
+                self.voices.retain(|voice| {
+                    voice.pad == index || pads[voice.pad].choke_group != Some(group)
+                });
+            }
+            if self.voices.len() >= MAX_VOICES {
+                self.voices.remove(0);
+            }
+            // The same balance law as the entity's insert pan.
+            let pad = &self.pads[index];
+            let level = pad.level.0 * velocity;
+            self.voices.push(DrumVoice {
+                pad: index,
+                position: 0,
+                gains: (
+                    level * (1.0 - pad.pan.0).min(1.0),
+                    level * (1.0 + pad.pan.0).min(1.0),
+                ),
+            });
+        }
+    }
+}
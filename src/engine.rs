@@ -1,19 +1,65 @@
 use crate::{
-    actions::{AudioAction, MidiAction},
+    actions::{AudioAction, MidiAction, TrackAction},
+    browser::BrowserItem,
+    channels::ChannelLayout,
+    clip::AudioClip,
+    command::{Command, CommandHistory},
+    declick::Declicker,
+    entity::EntityRequest,
+    executor::{join_on_drop, join_until, Executor},
+    inject::Injection,
+    latency::{compensation_delays, CompensationDelays},
+    limiter::Limiter,
+    limits::ActorLimits,
+    link::LinkSession,
+    meter::Meter,
+    metronome::Metronome,
+    metrics::CpuMetrics,
+    performance::{EnginePerformance, MessageCounter},
+    midi_clock::MidiClock,
+    registry::EntityRegistry,
+    resampler::ResampleQuality,
+    scene::{Scene, SceneParam, SceneStore},
+    snapshot::{EngineSnapshot, TrackSnapshot},
+    spectrum::SpectrumAnalyzer,
+    midi_file::{
+        import_midi_file, MidiFileWriterEvent, MidiFileWriterInput, MidiFileWriterService,
+    },
+    notification::{report_error, Notifications, Severity},
+    project::Project,
+    punch::PunchRegion,
+    recording::RecordingManager,
     subscription::Subscription,
-    track::{TrackActor, TrackRequest},
+    tempo::{TapTempo, TempoMap},
+    trace::{trace_message, ActorId, MessageTrace},
+    track::{EntityClipboard, TrackActor, TrackInfo, TrackRequest},
     traits::ProvidesActorService,
-    wav_writer::{WavWriterInput, WavWriterService},
+    wav_writer::{ExportFormat, WavWriterEvent, WavWriterInput, WavWriterService},
+    ATOMIC_ORDERING,
 };
-use crossbeam_channel::{Select, Sender};
+use anyhow::anyhow;
+use crossbeam_channel::{Receiver, Select, Sender};
 use delegate::delegate;
+#[cfg(feature = "gui")]
+use {
+    crate::{
+        metronome::{ClickOutput, ClickSample, ClickSound},
+        tempo::TempoPoint,
+    },
+    eframe::egui::{CollapsingHeader, ComboBox, DragValue, Slider},
+};
 use ensnare::{orchestration::TrackUidFactory, prelude::*, traits::{MidiNoteLabelMetadata, ProvidesService}, types::CrossbeamChannel};
 use ensnare_v1::prelude::*;
 use ensnare_services::prelude::*;
+use log::{debug, error, warn};
+use midly::live::SystemRealtime;
 use std::{
-    collections::HashMap,
-    path::PathBuf,
-    sync::{Arc, Mutex},
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::{atomic::AtomicBool, Arc, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant},
 };
 
 /// Communication from the client to [EngineService].
@@ -21,15 +67,140 @@ use std::{
 pub enum EngineServiceInput {
     /// The engine should send frames to this audio service.
     SetAudioSender(Sender<CpalAudioServiceInput>),
-    /// The configuration changed.
-    Configure(SampleRate, u8),
+    /// The configuration changed: the sample rate, the channel count, and,
+    /// for a device with a fixed buffer, its size in frames. The engine's
+    /// blocks are then made to fit in that buffer.
+    Configure(SampleRate, u8, Option<usize>),
+    /// Capture the master output to this WAV file, starting with the next
+    /// [EngineServiceInput::Configure].
+    SetCapturePath(PathBuf),
+    /// See [RecordingManager::set_capture_directory].
+    SetCaptureDirectory(PathBuf),
+    /// See [Engine::set_channel_layout]. Starts a new capture file.
+    SetChannelLayout(ChannelLayout),
+    /// See [Engine::set_export_format]. Starts a new capture file.
+    SetExportFormat(ExportFormat),
+    /// See [RecordingManager::set_master_armed].
+    SetMasterCapture(bool),
+    /// See [RecordingManager::set_track_armed].
+    SetTrackCapture(TrackUid, bool),
     /// An external MIDI message arrived.
     Midi(MidiChannel, MidiMessage),
     /// The AudioQueue needs more audio.
     AudioQueueNeedsAudio(usize),
+    /// The audio interface captured some input frames.
+    AudioInput(Vec<StereoSample>),
+    /// The audio device ran out of frames.
+    AudioUnderrun,
+    /// Move the transport to the given position. A block that's already
+    /// being generated is discarded and generated again from there.
+    Seek(MusicalTime),
+    /// Like [EngineServiceInput::Seek], but to the given frame, counting from
+    /// the start at the current tempo. For following an external transport.
+    SeekToFrame(usize),
+    /// See [Engine::set_tempo].
+    SetTempo(Tempo),
+    /// The user tapped the tempo at the given moment. See
+    /// [Engine::tap_tempo].
+    TapTempo(Instant),
+    /// Start the transport.
+    Play,
+    /// Stop the transport.
+    Stop,
+    /// Start (true) or stop (false) recording on armed tracks.
+    SetRecording(bool),
+    /// See [Engine::set_block_size].
+    SetBlockSize(usize),
+    /// Turn off the clip indicator.
+    ResetClipping,
+    /// Make an undoable change to the project, such as a mixer change.
+    Execute(Command),
+    Undo,
+    Redo,
+    /// Add an empty track at the end. Answered with
+    /// [EngineServiceEvent::TrackCreated].
+    CreateTrack,
+    /// Delete the track. It can be undone.
+    DeleteTrack(TrackUid),
+    SetTrackName(TrackUid, String),
+    /// See [Engine::add_entity_to_track]. Answered with
+    /// [EngineServiceEvent::EntityAdded].
+    AddEntity(TrackUid, String),
+    /// See [Engine::remove_entity].
+    RemoveEntity(TrackUid, Uid),
+    /// Set one of an entity's parameters.
+    SetParam(TrackUid, Uid, ControlIndex, ControlValue),
+    /// See [Engine::create_bus_track].
+    CreateBusTrack,
+    /// See [Engine::duplicate_track].
+    DuplicateTrack(TrackUid),
+    /// See [Engine::route_track].
+    RouteTrack(TrackUid, Option<TrackUid>),
+    /// See [Engine::set_send].
+    SetSend(TrackUid, TrackUid, Normal),
+    /// Change the track's selected entities all at once.
+    EditSelection(TrackUid, SelectionAction),
+    /// See [Engine::add_browser_item].
+    AddBrowserItem(TrackUid, BrowserItem),
+    /// Close the project and start an empty one. See
+    /// [Engine::new_project]. The new engine arrives with
+    /// [EngineServiceEvent::Reset].
+    NewProject,
+    /// Close the project and open the saved one. See [Project::load] and
+    /// [Engine::add_project]. The new engine arrives with
+    /// [EngineServiceEvent::Reset].
+    OpenProject(PathBuf),
+    /// See [Engine::save_project].
+    SaveProject(PathBuf),
     /// The client would like the service to exit.
     Quit,
 }
+impl EngineServiceInput {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EngineServiceInput::SetAudioSender(..) => "SetAudioSender",
+            EngineServiceInput::Configure(..) => "Configure",
+            EngineServiceInput::SetCapturePath(..) => "SetCapturePath",
+            EngineServiceInput::SetCaptureDirectory(..) => "SetCaptureDirectory",
+            EngineServiceInput::SetChannelLayout(..) => "SetChannelLayout",
+            EngineServiceInput::SetExportFormat(..) => "SetExportFormat",
+            EngineServiceInput::SetMasterCapture(..) => "SetMasterCapture",
+            EngineServiceInput::SetTrackCapture(..) => "SetTrackCapture",
+            EngineServiceInput::Midi(..) => "Midi",
+            EngineServiceInput::AudioQueueNeedsAudio(..) => "AudioQueueNeedsAudio",
+            EngineServiceInput::AudioInput(..) => "AudioInput",
+            EngineServiceInput::AudioUnderrun => "AudioUnderrun",
+            EngineServiceInput::Seek(..) => "Seek",
+            EngineServiceInput::SeekToFrame(..) => "SeekToFrame",
+            EngineServiceInput::SetTempo(..) => "SetTempo",
+            EngineServiceInput::TapTempo(..) => "TapTempo",
+            EngineServiceInput::Play => "Play",
+            EngineServiceInput::Stop => "Stop",
+            EngineServiceInput::SetRecording(..) => "SetRecording",
+            EngineServiceInput::SetBlockSize(..) => "SetBlockSize",
+            EngineServiceInput::ResetClipping => "ResetClipping",
+            EngineServiceInput::Execute(..) => "Execute",
+            EngineServiceInput::Undo => "Undo",
+            EngineServiceInput::Redo => "Redo",
+            EngineServiceInput::CreateTrack => "CreateTrack",
+            EngineServiceInput::DeleteTrack(..) => "DeleteTrack",
+            EngineServiceInput::SetTrackName(..) => "SetTrackName",
+            EngineServiceInput::AddEntity(..) => "AddEntity",
+            EngineServiceInput::RemoveEntity(..) => "RemoveEntity",
+            EngineServiceInput::SetParam(..) => "SetParam",
+            EngineServiceInput::CreateBusTrack => "CreateBusTrack",
+            EngineServiceInput::DuplicateTrack(..) => "DuplicateTrack",
+            EngineServiceInput::RouteTrack(..) => "RouteTrack",
+            EngineServiceInput::SetSend(..) => "SetSend",
+            EngineServiceInput::EditSelection(..) => "EditSelection",
+            EngineServiceInput::AddBrowserItem(..) => "AddBrowserItem",
+            EngineServiceInput::NewProject => "NewProject",
+            EngineServiceInput::OpenProject(..) => "OpenProject",
+            EngineServiceInput::SaveProject(..) => "SaveProject",
+            EngineServiceInput::Quit => "Quit",
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum EngineServiceEvent {
@@ -38,6 +209,24 @@ pub enum EngineServiceEvent {
     Reset(Arc<Mutex<Engine>>),
     /// The engine produced a MIDI message.
     Midi(MidiChannel, MidiMessage),
+    /// The engine produced a MIDI clock or transport message for the MIDI
+    /// output.
+    MidiRealtime(SystemRealtime),
+    /// The master track hasn't answered a request for audio in a while. Sent
+    /// once per stall.
+    Stalled(StallDiagnostics),
+    /// The latest state for the UI to draw, sent every
+    /// [EngineService::SNAPSHOT_INTERVAL].
+    Snapshot(EngineSnapshot),
+    /// An input that drives the transport or changes the project succeeded.
+    /// Holds the input's [EngineServiceInput::name].
+    Done(&'static str),
+    /// An input that drives the transport or changes the project failed.
+    Failed(&'static str, String),
+    /// The answer to [EngineServiceInput::CreateTrack].
+    TrackCreated(TrackUid),
+    /// The answer to [EngineServiceInput::AddEntity].
+    EntityAdded(TrackUid, Uid),
 }
 
 #[derive(Debug)]
@@ -46,7 +235,7 @@ pub struct EngineService {
     events: CrossbeamChannel<EngineServiceEvent>,
     audio_actions: CrossbeamChannel<AudioAction>,
     midi_actions: CrossbeamChannel<MidiAction>,
-    engine: Arc<Mutex<Engine>>,
+    thread: Option<JoinHandle<()>>,
 }
 impl Default for EngineService {
     fn default() -> Self {
@@ -62,78 +251,188 @@ impl ProvidesService<EngineServiceInput, EngineServiceEvent> for EngineService {
         &self.inputs.sender
     }
 }
+impl Drop for EngineService {
+    fn drop(&mut self) {
+        let _ = self.inputs.sender.try_send(EngineServiceInput::Quit);
+        // The service waits for the engine's actors before it exits.
+        let timeout = Self::SHUTDOWN_TIMEOUT * 2;
+        join_on_drop(self.thread.take(), timeout, "The engine service");
+    }
+}
 impl EngineService {
+    /// How long the master track can take to answer a request for audio
+    /// before the watchdog reports a stall.
+    const STALL_TIMEOUT: Duration = Duration::from_millis(500);
+    /// How often the service sends [EngineServiceEvent::Snapshot], and runs
+    /// the commands that the UI has sent.
+    pub const SNAPSHOT_INTERVAL: Duration = Duration::from_millis(33);
+    /// How long [EngineServiceInput::Quit] waits for the WAV writer and the
+    /// engine's actors to finish.
+    const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
     pub fn new() -> Self {
+        Self::new_with(Executor::Threaded)
+    }
+
+    /// Tells the client how an input that drives the transport or changes the
+    /// project turned out.
+    fn acknowledge(
+        events: &Sender<EngineServiceEvent>,
+        name: &'static str,
+        result: anyhow::Result<()>,
+    ) {
+        let event = match result {
+            Ok(()) => EngineServiceEvent::Done(name),
+            Err(e) => EngineServiceEvent::Failed(name, format!("{e:?}")),
+        };
+        let _ = events.try_send(event);
+    }
+
+    /// Creates a service whose engine runs its actors with the given
+    /// executor.
+    pub fn new_with(executor: Executor) -> Self {
         let audio_action_channel_pair: CrossbeamChannel<AudioAction> = Default::default();
         let midi_action_channel_pair: CrossbeamChannel<MidiAction> = Default::default();
-        let mut engine = Engine::new();
+        let mut engine = Engine::new_with(executor);
         engine.subscribe_audio(&audio_action_channel_pair.sender);
         engine.subscribe_midi(&midi_action_channel_pair.sender);
 
-        let r = Self {
-            engine: Arc::new(Mutex::new(engine)),
+        let mut r = Self {
             inputs: Default::default(),
             events: Default::default(),
             audio_actions: audio_action_channel_pair,
             midi_actions: midi_action_channel_pair,
+            thread: Default::default(),
         };
 
-        r.start_thread();
+        r.thread = Some(r.start_thread(engine));
 
         r
     }
 
-    fn start_thread(&self) {
+    /// Waits up to the timeout for the service to finish shutting down after
+    /// [EngineServiceInput::Quit]. The service's thread exits only once the
+    /// WAV file is finalized and the engine's actors have quit.
+    pub fn join(&mut self, timeout: Duration) -> anyhow::Result<()> {
+        if join_until(self.thread.take(), Instant::now() + timeout) > 0 {
+            return Err(anyhow!("The engine service didn't quit within {timeout:?}"));
+        }
+        Ok(())
+    }
+
+    fn start_thread(&self, engine: Engine) -> JoinHandle<()> {
         let service_event_sender = self.events.sender.clone();
 
-        let engine = Arc::clone(&self.engine);
+        let mut engine = Arc::new(Mutex::new(engine));
         let _ = self
             .events
             .sender
-            .try_send(EngineServiceEvent::Reset(Arc::clone(&self.engine)));
+            .try_send(EngineServiceEvent::Reset(Arc::clone(&engine)));
         let service_input_receiver = self.inputs.receiver.clone();
 
-        let writer_service = WavWriterService::new();
-
         let mut frames_requested = 0;
+        let mut generation_started_at: Option<Instant> = None;
+        // A seek happened while a block was being generated.
+        let mut is_flushing = false;
+        let mut last_round_trip = None;
+        let mut message_counter = MessageCounter::default();
 
+        let audio_action_sender = self.audio_actions.sender.clone();
         let audio_action_receiver = self.audio_actions.receiver.clone();
+        let midi_action_sender = self.midi_actions.sender.clone();
         let midi_action_receiver = self.midi_actions.receiver.clone();
 
+        // These carry over from one project to the next.
+        let (mut limiter, spectrum_feed, writer_sender, writer_event_receiver) = {
+            let engine = engine.lock().unwrap();
+            (
+                Limiter::new_with(Arc::clone(&engine.is_clipping)),
+                engine.spectrum_analyzer.feed(),
+                engine.recording.master_sender(),
+                engine.recording.master_events(),
+            )
+        };
+
         std::thread::spawn(move || {
             let mut sel = Select::default();
             let service_index = sel.recv(&service_input_receiver);
             let audio_index = sel.recv(&audio_action_receiver);
             let midi_index = sel.recv(&midi_action_receiver);
+            let writer_index = sel.recv(&writer_event_receiver);
 
             let mut audio_sender = None;
+            let mut is_stalled = false;
+            let mut snapshot_sent_at = Instant::now();
 
             loop {
-                let operation = sel.select();
+                if snapshot_sent_at.elapsed() >= Self::SNAPSHOT_INTERVAL {
+                    snapshot_sent_at = Instant::now();
+                    let mut engine = engine.lock().unwrap();
+                    engine.handle_commands();
+                    // Keeps the meters current even without a UI.
+                    engine.handle_track_actions();
+                    let _ = service_event_sender
+                        .try_send(EngineServiceEvent::Snapshot(engine.snapshot()));
+                }
+                if let Some(started_at) = generation_started_at {
+                    let waited = started_at.elapsed();
+                    if !is_stalled && waited >= Self::STALL_TIMEOUT {
+                        is_stalled = true;
+                        let diagnostics =
+                            engine.lock().unwrap().diagnose_stall(waited, frames_requested);
+                        warn!("{diagnostics}");
+                        let _ = service_event_sender
+                            .try_send(EngineServiceEvent::Stalled(diagnostics));
+                    }
+                }
+                // Wake up now and then even if nothing arrives, so that we
+                // keep sending snapshots and notice stalls.
+                let Ok(operation) = sel.select_timeout(Self::SNAPSHOT_INTERVAL) else {
+                    continue;
+                };
                 let mut start_generation = false;
                 match operation.index() {
                     index if index == service_index => {
                         if let Ok(input) = Self::recv_operation(operation, &service_input_receiver)
                         {
+                            message_counter.inputs += 1;
+                            let name = input.name();
+                            let events = &service_event_sender;
                             match input {
-                                EngineServiceInput::Configure(
-                                    sample_rate,
-                                    channel_count,
-                                ) => {
-                                    engine.lock().unwrap().update_sample_rate(sample_rate);
-                                    writer_service.send_input(WavWriterInput::Reset(
-                                        PathBuf::from(format!(
-                                            "/home/miket/out-{}-{}.wav",
-                                            sample_rate.0, channel_count
-                                        )),
-                                        sample_rate,
-                                        channel_count,
-                                    ));
+                                // The capture file follows the channel layout,
+                                // not the device.
+                                EngineServiceInput::Configure(sample_rate, _, period) => {
+                                    let mut engine = engine.lock().unwrap();
+                                    engine.update_sample_rate(sample_rate);
+                                    if let Some(period) = period {
+                                        engine.fit_block_size(period);
+                                    }
+                                    let channel_count = engine.channel_layout().channel_count();
+                                    engine.recording.configure(sample_rate, channel_count);
+                                }
+                                EngineServiceInput::SetCapturePath(path) => {
+                                    engine.lock().unwrap().recording.set_capture_path(path);
+                                }
+                                EngineServiceInput::SetCaptureDirectory(directory) => {
+                                    let mut engine = engine.lock().unwrap();
+                                    engine.recording.set_capture_directory(directory);
+                                }
+                                EngineServiceInput::SetChannelLayout(channel_layout) => {
+                                    engine.lock().unwrap().set_channel_layout(channel_layout);
+                                }
+                                EngineServiceInput::SetExportFormat(export_format) => {
+                                    engine.lock().unwrap().set_export_format(export_format);
+                                }
+                                EngineServiceInput::SetMasterCapture(is_armed) => {
+                                    engine.lock().unwrap().recording.set_master_armed(is_armed);
+                                }
+                                EngineServiceInput::SetTrackCapture(track_uid, is_armed) => {
+                                    engine.lock().unwrap().set_track_capture(track_uid, is_armed);
                                 }
                                 EngineServiceInput::Midi(channel, message) => engine
                                     .lock()
                                     .unwrap()
-                                    .handle_midi_message(channel, message, &mut |_, _| panic!("This MIDI message should have been sent via channel, not callback.")),
+                                    .handle_midi_message(channel, message, &mut |_, _| warn!("This MIDI message should have been sent via channel, not callback.")),
                                 EngineServiceInput::AudioQueueNeedsAudio(count) => {
                                     if frames_requested == 0 {
                                         start_generation = true;
@@ -141,34 +440,227 @@ impl EngineService {
                                     frames_requested += count;
                                 }
                                 EngineServiceInput::Quit => {
-                                    engine.lock().unwrap().request_quit();
-                                    writer_service.send_input(WavWriterInput::Quit);
+                                    // Audio that has already arrived still
+                                    // belongs in the file.
+                                    while let Ok(mut action) = audio_action_receiver.try_recv() {
+                                        limiter.process(&mut action.frames);
+                                        let _ = writer_sender.try_send(WavWriterInput::Frames(
+                                            action.frames,
+                                            action.other_pairs,
+                                        ));
+                                    }
+                                    let timeout = Self::SHUTDOWN_TIMEOUT;
+                                    if let Err(e) = engine.lock().unwrap().shutdown(timeout) {
+                                        error!("While shutting down the engine: {e:?}");
+                                    }
+                                    debug!("Engine service quit");
                                     break;
                                 }
                                 EngineServiceInput::SetAudioSender(sender) => audio_sender = Some(sender),
+                                EngineServiceInput::AudioInput(frames) => {
+                                    engine.lock().unwrap().handle_audio_input(frames);
+                                }
+                                EngineServiceInput::AudioUnderrun => {
+                                    engine.lock().unwrap().performance.underruns += 1;
+                                }
+                                EngineServiceInput::Seek(time) => {
+                                    engine.lock().unwrap().seek(time);
+                                    is_flushing = generation_started_at.is_some();
+                                    Self::acknowledge(events, name, Ok(()));
+                                }
+                                EngineServiceInput::SeekToFrame(frame) => {
+                                    engine.lock().unwrap().seek_to_frame(frame);
+                                    is_flushing = generation_started_at.is_some();
+                                    Self::acknowledge(events, name, Ok(()));
+                                }
+                                EngineServiceInput::Play => {
+                                    engine.lock().unwrap().play();
+                                    Self::acknowledge(events, name, Ok(()));
+                                }
+                                EngineServiceInput::Stop => {
+                                    engine.lock().unwrap().stop();
+                                    Self::acknowledge(events, name, Ok(()));
+                                }
+                                EngineServiceInput::SetRecording(is_recording) => {
+                                    let mut engine = engine.lock().unwrap();
+                                    if is_recording {
+                                        engine.start_recording();
+                                    } else {
+                                        engine.stop_recording();
+                                    }
+                                }
+                                EngineServiceInput::SetTempo(tempo) => {
+                                    engine.lock().unwrap().set_tempo(tempo);
+                                }
+                                EngineServiceInput::TapTempo(at) => {
+                                    engine.lock().unwrap().tap_tempo(at);
+                                }
+                                EngineServiceInput::SetBlockSize(block_size) => {
+                                    engine.lock().unwrap().set_block_size(block_size);
+                                }
+                                EngineServiceInput::ResetClipping => {
+                                    engine.lock().unwrap().reset_clipping();
+                                }
+                                EngineServiceInput::Execute(command) => {
+                                    let result = engine.lock().unwrap().execute(command);
+                                    if let Err(e) = result.as_ref() {
+                                        report_error("While executing a command", e);
+                                    }
+                                    Self::acknowledge(events, name, result);
+                                }
+                                EngineServiceInput::Undo => {
+                                    let result = engine.lock().unwrap().undo();
+                                    if let Err(e) = result.as_ref() {
+                                        report_error("While undoing", e);
+                                    }
+                                    Self::acknowledge(events, name, result);
+                                }
+                                EngineServiceInput::Redo => {
+                                    let result = engine.lock().unwrap().redo();
+                                    if let Err(e) = result.as_ref() {
+                                        report_error("While redoing", e);
+                                    }
+                                    Self::acknowledge(events, name, result);
+                                }
+                                EngineServiceInput::CreateTrack
+                                | EngineServiceInput::DeleteTrack(..)
+                                | EngineServiceInput::SetTrackName(..)
+                                | EngineServiceInput::AddEntity(..)
+                                | EngineServiceInput::RemoveEntity(..)
+                                | EngineServiceInput::SetParam(..) => {
+                                    let event = engine.lock().unwrap().handle_project_input(input);
+                                    let _ = events.try_send(event);
+                                }
+                                EngineServiceInput::CreateBusTrack
+                                | EngineServiceInput::DuplicateTrack(..)
+                                | EngineServiceInput::RouteTrack(..)
+                                | EngineServiceInput::SetSend(..)
+                                | EngineServiceInput::EditSelection(..)
+                                | EngineServiceInput::AddBrowserItem(..) => {
+                                    let result = engine.lock().unwrap().handle_edit_input(input);
+                                    Self::acknowledge(events, name, result);
+                                }
+                                EngineServiceInput::SaveProject(path) => {
+                                    let result = engine.lock().unwrap().save_project(&path);
+                                    if let Err(e) = result.as_ref() {
+                                        report_error("While saving the project", e);
+                                    }
+                                    Self::acknowledge(events, name, result);
+                                }
+                                EngineServiceInput::NewProject
+                                | EngineServiceInput::OpenProject(..) => {
+                                    // A file that won't load leaves the
+                                    // current project open.
+                                    let opened = match &input {
+                                        EngineServiceInput::OpenProject(path) => {
+                                            match Project::load(path) {
+                                                Ok(project) => Some((path.clone(), project)),
+                                                Err(e) => {
+                                                    report_error("While opening the project", &e);
+                                                    Self::acknowledge(events, name, Err(e));
+                                                    continue;
+                                                }
+                                            }
+                                        }
+                                        _ => None,
+                                    };
+                                    // Build the new project completely before
+                                    // replacing the old one, so that a partial
+                                    // load can't be saved over the file.
+                                    let timeout = Self::SHUTDOWN_TIMEOUT;
+                                    let mut new_engine = engine.lock().unwrap().new_project();
+                                    if let Some((path, project)) = opened {
+                                        if let Err(e) = new_engine.add_project(&project) {
+                                            report_error("While opening the project", &e);
+                                            if let Err(e) = new_engine.shutdown(timeout) {
+                                                report_error("While closing the project", &e);
+                                            }
+                                            Self::acknowledge(events, name, Err(e));
+                                            continue;
+                                        }
+                                        new_engine.set_project_path(path);
+                                    }
+                                    new_engine.subscribe_audio(&audio_action_sender);
+                                    new_engine.subscribe_midi(&midi_action_sender);
+                                    let mut old_engine = engine.lock().unwrap();
+                                    if let Err(e) = old_engine.shutdown(timeout) {
+                                        report_error("While closing the project", &e);
+                                    }
+                                    drop(old_engine);
+
+                                    // Whatever the old project was generating
+                                    // is no longer wanted.
+                                    for action in audio_action_receiver.try_iter() {
+                                        action.recycle();
+                                    }
+                                    midi_action_receiver.try_iter().for_each(drop);
+                                    generation_started_at = None;
+                                    is_flushing = false;
+                                    start_generation = frames_requested > 0;
+
+                                    engine = Arc::new(Mutex::new(new_engine));
+                                    let reset = EngineServiceEvent::Reset(Arc::clone(&engine));
+                                    let _ = events.try_send(reset);
+                                    Self::acknowledge(events, name, Ok(()));
+                                }
                             }
                         }
                     }
-                    index if index == audio_index => {
+                    index if index == audio_index && is_flushing => {
+                        // This block is from before the seek, so generate it
+                        // again from the new position.
                         if let Ok(action) = Self::recv_operation(operation, &audio_action_receiver)
                         {
+                            is_flushing = false;
+                            generation_started_at = None;
+                            action.recycle();
+                            start_generation = frames_requested > 0;
+                        }
+                    }
+                    index if index == audio_index => {
+                        if let Ok(mut action) =
+                            Self::recv_operation(operation, &audio_action_receiver)
+                        {
+                            message_counter.audio += 1;
+                            trace_message(
+                                ActorId::Track(TrackUid::default()),
+                                ActorId::Engine,
+                                "Audio",
+                            );
+                            engine.lock().unwrap().process_master_output(&mut action.frames);
+                            limiter.process(&mut action.frames);
+                            spectrum_feed.push(&action.frames);
+
                             let frames_len = action.frames.len();
-                            assert!(frames_len <= 64);
+                            is_stalled = false;
+                            last_round_trip = generation_started_at.take().map(|t| t.elapsed());
 
                             if let Some(audio_sender) = audio_sender.as_ref() {
+                                // Clicks on the cue output are heard, but
+                                // stay out of captures of the master output.
+                                let engine = engine.lock().unwrap();
+                                let silence = std::iter::repeat(&StereoSample::SILENCE);
+                                let clicks = engine.metronome().cue_frames().iter().chain(silence);
                                 let wrapped_buffer = Arc::new(
                                     action
                                         .frames
                                         .iter()
-                                        .map(|s| (s.0 .0 as f32, s.1 .0 as f32))
+                                        .zip(clicks)
+                                        .map(|(s, click)| {
+                                            let mut s = *s;
+                                            s += *click;
+                                            (s.0 .0 as f32, s.1 .0 as f32)
+                                        })
                                         .collect(),
                                 );
                                 let _ = audio_sender
                                     .try_send(CpalAudioServiceInput::Frames(wrapped_buffer));
                             }
-                            writer_service.send_input(WavWriterInput::Frames(action.frames));
+                            let _ = writer_sender.try_send(WavWriterInput::Frames(
+                                action.frames,
+                                action.other_pairs,
+                            ));
 
-                            assert!(frames_len <= 64);
                             if frames_requested > frames_len {
                                 // We still have work to do, so kick off
                                 // generation once again.
@@ -177,18 +669,25 @@ impl EngineService {
                             } else {
                                 // The case of (frames_requested <
                                 // frames_len) can happen because we
-                                // always generate 64 frames at once,
+                                // always generate a full block at once,
                                 // even if the request is for fewer than
                                 // that. This ends up adding as many as
-                                // 63 extra frames to the audio queue,
-                                // but we know we'll be needing it soon,
-                                // so it's OK.
+                                // block_size - 1 extra frames to the audio
+                                // queue, but we know we'll be needing it
+                                // soon, so it's OK.
                                 frames_requested = 0;
                             }
                         }
                     }
                     index if index == midi_index => {
                         if let Ok(action) = Self::recv_operation(operation, &midi_action_receiver) {
+                            message_counter.midi += 1;
+                            trace_message(
+                                ActorId::Track(TrackUid::default()),
+                                ActorId::Engine,
+                                "Midi",
+                            );
+                            engine.lock().unwrap().capture_midi(&action);
                             // TODO: is this the right point to
                             // concentrate these messages? It seems
                             // burdensome for the external MIDI service
@@ -200,19 +699,71 @@ impl EngineService {
                                 .try_send(EngineServiceEvent::Midi(action.channel, action.message));
                         }
                     }
-                    _ => panic!(),
+                    index if index == writer_index => {
+                        if let Ok(WavWriterEvent::Err(e)) =
+                            Self::recv_operation(operation, &writer_event_receiver)
+                        {
+                            report_error("While writing the WAV file", &e);
+                        }
+                    }
+                    index => error!("EngineService: unexpected select index {index}"),
                 }
                 if start_generation {
-                    engine
-                        .lock()
-                        .unwrap()
-                        .start_generation(frames_requested.min(64));
+                    let mut engine = engine.lock().unwrap();
+                    if let Some(round_trip) = last_round_trip.take() {
+                        engine.performance.record(round_trip, frames_requested);
+                    }
+                    if let Some(rates) = message_counter.take_rates() {
+                        engine.performance.message_rates = rates;
+                    }
+                    let block_size = engine.block_size();
+                    engine.start_generation(frames_requested.min(block_size));
+                    generation_started_at = Some(Instant::now());
+                    for message in engine.midi_clock.take_messages() {
+                        let _ = service_event_sender
+                            .try_send(EngineServiceEvent::MidiRealtime(message));
+                    }
                 }
             }
-        });
+        })
+    }
+}
+
+/// What the engine knew when it noticed that the master track had stopped
+/// answering.
+#[derive(Debug, Clone)]
+pub struct StallDiagnostics {
+    /// How long ago the engine asked for the block that never came.
+    pub waited: Duration,
+    /// Frames that the audio queue has asked for but not yet received.
+    pub frames_requested: usize,
+    /// The master track first, then the others in order.
+    pub tracks: Vec<TrackDiagnostics>,
+}
+impl Display for StallDiagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "No audio for {} ms ({} frames pending)",
+            self.waited.as_millis(),
+            self.frames_requested
+        )?;
+        for track in self.tracks.iter() {
+            writeln!(f, "  {}: {}", track.name, track.state)?;
+        }
+        Ok(())
     }
 }
 
+/// One track's part of [StallDiagnostics].
+#[derive(Debug, Clone)]
+pub struct TrackDiagnostics {
+    pub uid: TrackUid,
+    pub name: String,
+    /// Where the track's state machine is, e.g., waiting for two sources.
+    pub state: String,
+}
+
 #[derive(Debug)]
 pub struct Engine {
     master_track: TrackActor,
@@ -220,11 +771,165 @@ pub struct Engine {
     tracks: HashMap<TrackUid, TrackActor>,
     track_uid_factory: Arc<TrackUidFactory>,
     entity_uid_factory: Arc<EntityUidFactory>,
+    registry: Arc<EntityRegistry>,
+
+    /// Tracks that other tracks can route their output to.
+    bus_track_uids: HashSet<TrackUid>,
+    /// Where each track's output goes. Tracks without an entry go to the
+    /// master track.
+    track_outputs: HashMap<TrackUid, TrackUid>,
+    /// Each track's sends to buses, in addition to its main output, and their
+    /// levels.
+    track_sends: HashMap<TrackUid, HashMap<TrackUid, Normal>>,
+    /// Control signals that cross from one track to another, or to the
+    /// master mixer. They survive removing an entity, so that undoing the
+    /// removal brings them back.
+    control_routes: Vec<ControlRoute>,
+    /// Names and colors, as reported by the tracks.
+    track_infos: HashMap<TrackUid, TrackInfo>,
+    /// How many frames late each track's output is, as reported by the
+    /// tracks.
+    track_latencies: HashMap<TrackUid, usize>,
+    /// How many frames we've asked each track to delay its output and its
+    /// sends, so that all tracks reach the master track in time with each
+    /// other.
+    latency_compensation: CompensationDelays,
 
     track_subscription: Subscription<TrackRequest>,
 
     transport: Transport,
     c: Configurables,
+
+    is_recording: bool,
+    punch: PunchRegion,
+    /// Recording started because the playhead entered the punch region, so
+    /// leaving it should stop recording.
+    is_punched_in: bool,
+
+    /// The file that the project was last opened from or saved to.
+    project_path: Option<PathBuf>,
+
+    /// Captures outgoing MIDI for export.
+    midi_writer: MidiFileWriterService,
+
+    /// Set by the master-output limiter when a sample exceeds +/-1.0.
+    is_clipping: Arc<AtomicBool>,
+
+    /// Receives meter readings from every track, including the master.
+    track_actions: CrossbeamChannel<TrackAction>,
+    meters: HashMap<TrackUid, Meter>,
+
+    /// Analyzes the master output, after the limiter.
+    spectrum_analyzer: SpectrumAnalyzer,
+
+    /// Writes each track to its own file while stem export is on.
+    stem_writer: Option<WavWriterService>,
+
+    /// Sends clock and transport messages to the MIDI output.
+    midi_clock: MidiClock,
+
+    /// Tempo and beat sync with other apps on the network.
+    link: LinkSession,
+    /// Play was pressed while Link is on, and we're waiting for the next bar
+    /// of the Link session to actually start.
+    is_waiting_for_link: bool,
+    /// Tempo automation, which takes over the tempo during playback.
+    tempo_map: TempoMap,
+    /// Whether the tracks last heard that there's a tempo map.
+    is_tempo_mapped: bool,
+    tap_tempo: TapTempo,
+    /// Clicks along with playback, and counts in before recording.
+    metronome: Metronome,
+    /// The WAV file that the metronome's "Load" button loads.
+    #[cfg(feature = "gui")]
+    click_sample_path: String,
+    /// Fades the master output in and out around play and stop.
+    declicker: Declicker,
+
+    /// How many frames the engine generates at a time.
+    block_size: usize,
+    channel_layout: ChannelLayout,
+    /// How imported audio files are converted to the engine's sample rate.
+    resample_quality: ResampleQuality,
+    /// Writes the master output and armed tracks to WAV files.
+    recording: RecordingManager,
+    /// Snapshots of the mixer and selected parameters, and the recall that's
+    /// morphing between them.
+    scenes: SceneStore,
+    performance: EnginePerformance,
+
+    /// The entities that "Copy" last took from a track.
+    entity_clipboard: Option<Arc<EntityClipboard>>,
+
+    /// Changes that the UI wants to make, which go into the undo history.
+    commands: CrossbeamChannel<Command>,
+    history: CommandHistory,
+
+    /// Where the tracks and their entities run.
+    executor: Executor,
+    /// Receives the master track's output for [Engine::render].
+    render_output: Option<Receiver<AudioAction>>,
+}
+
+/// A parameter anywhere in the project that an entity's control signal can
+/// drive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ControlTarget {
+    /// A parameter of the given entity, which is on the given track.
+    Entity(TrackUid, Uid, ControlIndex),
+    /// The given track's level in the master mixer.
+    MixerLevel(TrackUid),
+    /// The master mixer's crossfader.
+    Crossfader,
+}
+impl ControlTarget {
+    /// The track that the target belongs to.
+    pub fn track_uid(&self) -> TrackUid {
+        match self {
+            ControlTarget::Entity(track_uid, ..) | ControlTarget::MixerLevel(track_uid) => {
+                *track_uid
+            }
+            ControlTarget::Crossfader => TrackUid::default(),
+        }
+    }
+
+    /// The entity whose control receiver gets the signal, or None for the
+    /// master track's.
+    fn receiver(&self) -> Option<Uid> {
+        match self {
+            ControlTarget::Entity(_, uid, _) => Some(*uid),
+            ControlTarget::MixerLevel(_) | ControlTarget::Crossfader => None,
+        }
+    }
+}
+
+/// Drives a [ControlTarget] with the control signal of an entity on any
+/// track. Links within one track are [Command::Link]s instead.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ControlRoute {
+    pub source_track: TrackUid,
+    pub source: Uid,
+    pub target: ControlTarget,
+}
+
+/// A track that was deleted but kept alive, along with its routing, so that
+/// it can be put back. Its master mixer settings start over. Dropping it ends
+/// the track's thread.
+#[derive(Debug)]
+pub struct DetachedTrack {
+    uid: TrackUid,
+    actor: Option<TrackActor>,
+    /// Where it was in the track list.
+    index: usize,
+    is_bus: bool,
+    output: Option<TrackUid>,
+    sends: HashMap<TrackUid, Normal>,
+    /// If it's a bus, the tracks that were routed to it.
+    routed_sources: Vec<TrackUid>,
+    /// If it's a bus, the tracks that were sending to it, and their levels.
+    send_sources: Vec<(TrackUid, Normal)>,
+    /// The control routes that started or ended on it.
+    control_routes: Vec<ControlRoute>,
 }
 impl Configurable for Engine {
     delegate! {
@@ -236,9 +941,13 @@ impl Configurable for Engine {
     }
     fn update_sample_rate(&mut self, sample_rate: SampleRate) {
         self.c.update_sample_rate(sample_rate);
+        self.spectrum_analyzer.update_sample_rate(sample_rate);
+        self.broadcast_configuration();
     }
     fn update_tempo(&mut self, tempo: Tempo) {
         self.c.update_tempo(tempo);
+        self.link.set_tempo(tempo);
+        self.broadcast_configuration();
     }
     fn update_time_signature(&mut self, time_signature: TimeSignature) {
         self.c.update_time_signature(time_signature);
@@ -251,6 +960,15 @@ impl HandlesMidi for Engine {
         message: MidiMessage,
         _midi_messages_fn: &mut MidiMessagesFn,
     ) {
+        // Tracks still hear program changes, because they might be meant for
+        // an instrument rather than a scene.
+        if let MidiMessage::ProgramChange { program } = message {
+            let number = program.as_int();
+            if self.scenes.scene(number).is_some() {
+                let morph_seconds = self.scenes.morph_seconds();
+                let _ = self.recall_scene(number, morph_seconds);
+            }
+        }
         self.track_subscription
             .broadcast_mut(TrackRequest::Midi(channel, message));
     }
@@ -266,27 +984,238 @@ impl Controls for Engine {
             fn update_time_range(&mut self, time_range: &TimeRange);
             fn work(&mut self, control_events_fn: &mut ControlEventsFn);
             fn is_finished(&self) -> bool;
-            fn play(&mut self);
             fn skip_to_start(&mut self);
             fn is_performing(&self) -> bool;
         }
     }
 
+    fn play(&mut self) {
+        // The transport hasn't stopped yet, so it can just keep going.
+        if self.declicker.is_fading_out() {
+            self.declicker.cancel_fade_out();
+            return;
+        }
+        if self.link.is_enabled() && !self.transport.is_performing() {
+            // start_generation() starts the transport on the Link bar line.
+            self.is_waiting_for_link = true;
+            return;
+        }
+        // start_generation() starts the transport when the count-in ends.
+        if self.is_recording
+            && !self.transport.is_performing()
+            && self.metronome.start_count_in(
+                self.sample_rate(),
+                self.tempo(),
+                self.time_signature(),
+            )
+        {
+            return;
+        }
+        self.start_transport();
+    }
+
     fn stop(&mut self) {
-        self.transport.stop();
-        self.track_subscription.broadcast_mut(TrackRequest::Midi(
-            MidiChannel::default(),
-            MidiMessage::Controller {
-                controller: 123.into(),
-                value: 0.into(),
-            },
-        ));
+        // start_generation() finishes stopping once the output has faded
+        // out. Stopping again during the fade-out stops right away.
+        if self.transport.is_performing() && self.declicker.fade_out(self.sample_rate()) {
+            return;
+        }
+        self.finish_stop();
     }
 }
 impl Engine {
-    fn new() -> Self {
+    pub const DEFAULT_BLOCK_SIZE: usize = 64;
+    pub const MIN_BLOCK_SIZE: usize = 32;
+    pub const MAX_BLOCK_SIZE: usize = 512;
+    /// The block sizes the UI offers.
+    pub const BLOCK_SIZES: [usize; 5] = [32, 64, 128, 256, 512];
+    /// How long [Engine::render] waits for each block from actors that run
+    /// on their own threads.
+    pub const RENDER_TIMEOUT: Duration = Duration::from_secs(5);
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Changes how many frames the engine generates at a time. Smaller blocks
+    /// lower latency, and larger ones lower overhead. Takes effect with the
+    /// next block.
+    pub fn set_block_size(&mut self, block_size: usize) {
+        self.block_size = block_size.clamp(Self::MIN_BLOCK_SIZE, Self::MAX_BLOCK_SIZE);
+        self.performance = Default::default();
+        self.broadcast_configuration();
+    }
+
+    /// Picks the largest of [Engine::BLOCK_SIZES] that divides an audio
+    /// device's buffer, so that each buffer takes a whole number of blocks.
+    /// If none does, e.g., for a 100-frame buffer, it picks the largest that
+    /// fits, and what's left of the last block waits for the next buffer.
+    pub fn fit_block_size(&mut self, period: usize) {
+        let fitting = Self::BLOCK_SIZES
+            .into_iter()
+            .filter(|block_size| *block_size <= period);
+        let block_size = fitting
+            .clone()
+            .filter(|block_size| period % block_size == 0)
+            .max()
+            .or_else(|| fitting.max())
+            .unwrap_or(Self::MIN_BLOCK_SIZE);
+        self.set_block_size(block_size);
+    }
+
+    pub fn channel_layout(&self) -> ChannelLayout {
+        self.channel_layout
+    }
+
+    /// Changes how many output channels the master track produces. The
+    /// master mixer decides which pair each track goes to. The audio device
+    /// still plays only the main pair; the others go to the capture file.
+    pub fn set_channel_layout(&mut self, channel_layout: ChannelLayout) {
+        self.channel_layout = channel_layout;
+        self.master_track.set_channel_layout(channel_layout);
+        self.recording
+            .set_channel_count(channel_layout.channel_count());
+    }
+
+    pub fn resample_quality(&self) -> ResampleQuality {
+        self.resample_quality
+    }
+
+    /// Sets how [Engine::import_audio_file] converts files whose sample rate
+    /// differs from the engine's. Clips that are already imported keep the
+    /// rate they were converted to.
+    pub fn set_resample_quality(&mut self, resample_quality: ResampleQuality) {
+        self.resample_quality = resample_quality;
+    }
+
+    pub fn export_format(&self) -> ExportFormat {
+        self.recording.export_format()
+    }
+
+    /// Sets the format that the master output is captured in, and starts a
+    /// new capture file. The conversion happens in [WavWriterService], so the
+    /// engine keeps running at the device's rate.
+    pub fn set_export_format(&mut self, export_format: ExportFormat) {
+        self.recording.set_export_format(export_format);
+    }
+
+    pub fn recording(&self) -> &RecordingManager {
+        &self.recording
+    }
+
+    /// Starts or finishes writing the track's output to its own file.
+    pub fn set_track_capture(&mut self, track_uid: TrackUid, is_armed: bool) {
+        match self.tracks.get(&track_uid) {
+            Some(track) => self.recording.set_track_armed(track_uid, track, is_armed),
+            None => self.recording.disarm_track(track_uid, None),
+        }
+    }
+
+    pub fn performance(&self) -> &EnginePerformance {
+        &self.performance
+    }
+
+    /// Copies what the transport bar and remote clients show.
+    pub fn snapshot(&self) -> EngineSnapshot {
+        EngineSnapshot {
+            can_undo: self.history.can_undo(),
+            can_redo: self.history.can_redo(),
+            is_performing: self.is_performing(),
+            is_recording: self.is_recording,
+            is_clipping: self.is_clipping(),
+            block_size: self.block_size,
+            channel_layout: self.channel_layout,
+            export_format: self.export_format(),
+            is_master_capture_armed: self.recording.is_master_armed(),
+            sample_rate: self.sample_rate(),
+            performance: self.performance.clone(),
+            position: self.position(),
+            tempo: self.tempo(),
+            levels: self
+                .meters
+                .iter()
+                .map(|(uid, meter)| (*uid, meter.level()))
+                .collect(),
+            tracks: self
+                .ordered_track_uids
+                .iter()
+                .filter_map(|&uid| Some(self.track_snapshot(uid, self.tracks.get(&uid)?)))
+                .collect(),
+            master_track: self.master_track.editor(),
+            can_paste: self.entity_clipboard.is_some(),
+            registry: Arc::clone(&self.registry),
+            #[cfg(feature = "gui")]
+            control_targets: Arc::new(self.control_targets()),
+        }
+    }
+
+    fn track_snapshot(&self, uid: TrackUid, track: &TrackActor) -> TrackSnapshot {
+        TrackSnapshot {
+            uid,
+            name: self.track_name(uid),
+            color: self.track_info(uid).map(|info| info.color),
+            clip_spans: track.clip_spans(),
+            is_bus: self.bus_track_uids.contains(&uid),
+            output: self.track_outputs.get(&uid).copied(),
+            sends: self.track_sends.get(&uid).cloned().unwrap_or_default(),
+            is_capturing: self.recording.is_track_armed(uid),
+            editor: track.editor(),
+        }
+    }
+
+    fn start_transport(&mut self) {
+        let from_beginning = match self.transport.time_range() {
+            Some(time_range) => time_range.0.start == MusicalTime::START,
+            None => true,
+        };
+        if !self.transport.is_performing() {
+            self.declicker.fade_in(self.sample_rate());
+        }
+        self.transport.play();
+        self.midi_clock.start(from_beginning);
+    }
+
+    fn finish_stop(&mut self) {
+        self.declicker.cancel_fade_out();
+        self.is_waiting_for_link = false;
+        self.metronome.cancel_count_in();
+        if self.is_punched_in {
+            self.is_punched_in = false;
+            self.stop_recording();
+        }
+        self.transport.stop();
+        self.midi_clock.stop();
+        self.all_notes_off();
+    }
+
+    /// The engine's own stages on the master track's output, ahead of the
+    /// limiter.
+    fn process_master_output(&mut self, frames: &mut [StereoSample]) {
+        self.declicker.process(frames);
+        self.metronome.mix_into(frames);
+    }
+
+    /// Creates an empty project whose tracks and entities run on the given
+    /// [Executor]. [EngineService] uses [Executor::Threaded] by default; use
+    /// [Executor::Pool] for large projects, or [Executor::Synchronous] with
+    /// [Engine::render] for repeatable output.
+    pub fn new_with(executor: Executor) -> Self {
+        Self::new_with_registry(executor, Arc::new(EntityRegistry::new_with_builtins()))
+    }
+
+    /// Like [Engine::new_with], with entities and plugin formats from the
+    /// given registry instead of only the built-in ones.
+    pub fn new_with_registry(executor: Executor, registry: Arc<EntityRegistry>) -> Self {
         let entity_uid_factory: Arc<EntityUidFactory> = Default::default();
-        let master_track = TrackActor::new_with(TrackUid::default(), true, &entity_uid_factory);
+        let commands: CrossbeamChannel<Command> = Default::default();
+        let master_track = TrackActor::new_with(
+            TrackUid::default(),
+            true,
+            &entity_uid_factory,
+            &registry,
+            &commands.sender,
+            &executor,
+        );
         let master_track_request = master_track.sender().clone();
 
         let mut r = Self {
@@ -295,14 +1224,126 @@ impl Engine {
             tracks: Default::default(),
             track_uid_factory: Default::default(),
             entity_uid_factory,
+            registry,
+            bus_track_uids: Default::default(),
+            track_outputs: Default::default(),
+            track_sends: Default::default(),
+            control_routes: Default::default(),
+            track_infos: Default::default(),
+            track_latencies: Default::default(),
+            latency_compensation: Default::default(),
             track_subscription: Default::default(),
             transport: Default::default(),
             c: Default::default(),
+            is_recording: Default::default(),
+            punch: Default::default(),
+            is_punched_in: Default::default(),
+            project_path: Default::default(),
+            midi_writer: Default::default(),
+            is_clipping: Default::default(),
+            track_actions: Default::default(),
+            meters: Default::default(),
+            spectrum_analyzer: Default::default(),
+            stem_writer: Default::default(),
+            midi_clock: Default::default(),
+            link: Default::default(),
+            is_waiting_for_link: Default::default(),
+            tempo_map: Default::default(),
+            is_tempo_mapped: Default::default(),
+            tap_tempo: Default::default(),
+            metronome: Default::default(),
+            #[cfg(feature = "gui")]
+            click_sample_path: Default::default(),
+            declicker: Default::default(),
+            block_size: Self::DEFAULT_BLOCK_SIZE,
+            channel_layout: Default::default(),
+            resample_quality: Default::default(),
+            recording: Default::default(),
+            scenes: Default::default(),
+            performance: Default::default(),
+            entity_clipboard: Default::default(),
+            commands,
+            history: Default::default(),
+            executor,
+            render_output: Default::default(),
         };
         r.track_subscription.subscribe(&master_track_request);
+        r.master_track.send_request(TrackRequest::SubscribeTrackActions(
+            r.track_actions.sender.clone(),
+        ));
+        r
+    }
+
+    /// An empty project that runs on the same executor, with the same sample
+    /// rate, block size, and channel layout. The master capture, the clip
+    /// indicator, and the spectrum analyzer move over to it, because
+    /// [EngineService] keeps feeding them. Armed tracks finish their capture
+    /// files. This engine is left to be shut down.
+    pub fn new_project(&mut self) -> Self {
+        let mut r = Self::new_with_registry(self.executor.clone(), Arc::clone(&self.registry));
+        r.update_sample_rate(self.sample_rate());
+        r.set_block_size(self.block_size);
+        r.set_channel_layout(self.channel_layout);
+        r.resample_quality = self.resample_quality;
+        self.recording.disarm_tracks(&self.tracks);
+        std::mem::swap(&mut r.recording, &mut self.recording);
+        std::mem::swap(&mut r.is_clipping, &mut self.is_clipping);
+        std::mem::swap(&mut r.spectrum_analyzer, &mut self.spectrum_analyzer);
         r
     }
 
+    /// The project's tracks, in order, with their names, colors, and
+    /// entities.
+    pub fn to_project(&self) -> anyhow::Result<Project> {
+        let tracks = self
+            .ordered_track_uids
+            .iter()
+            .filter_map(|uid| self.tracks.get(uid))
+            .map(|track| track.to_project())
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Project { tracks })
+    }
+
+    /// Adds the saved project's tracks after any that the engine already
+    /// has. To open a project, add it to the engine that [Engine::new_project]
+    /// returns.
+    pub fn add_project(&mut self, project: &Project) -> anyhow::Result<()> {
+        for track_project in project.tracks.iter() {
+            let track_uid = self.create_track()?;
+            self.track_or_master(track_uid)?.add_project(track_project)?;
+        }
+        Ok(())
+    }
+
+    /// Saves the project to the given file, which becomes the project's
+    /// path.
+    pub fn save_project(&mut self, path: &Path) -> anyhow::Result<()> {
+        self.to_project()?.save(path)?;
+        self.project_path = Some(path.to_path_buf());
+        Ok(())
+    }
+
+    /// The file that the project was last opened from or saved to, if any.
+    pub fn project_path(&self) -> Option<&Path> {
+        self.project_path.as_deref()
+    }
+
+    pub fn set_project_path(&mut self, path: PathBuf) {
+        self.project_path = Some(path);
+    }
+
+    /// Where recordings and exports go: the project file's directory, or the
+    /// [RecordingManager::capture_directory] if the project hasn't been
+    /// saved.
+    pub fn output_directory(&self) -> PathBuf {
+        self.project_path
+            .as_deref()
+            .and_then(Path::parent)
+            .filter(|directory| !directory.as_os_str().is_empty())
+            .unwrap_or(self.recording.capture_directory())
+            .to_path_buf()
+    }
+
     fn subscribe_audio(&mut self, sender: &Sender<AudioAction>) {
         // We delegate the subscription request to the master track.
         self.master_track
@@ -316,8 +1357,48 @@ impl Engine {
     }
 
     fn start_generation(&mut self, count: usize) {
+        MessageTrace::global().next_cycle();
+        CpuMetrics::global().collect();
+
+        // Follow tempo changes that other Link peers made.
+        if let Some(tempo) = self.link.tempo() {
+            if tempo.0 != self.tempo().0 {
+                self.update_tempo(tempo);
+            }
+        }
+        if self.is_waiting_for_link {
+            let duration_micros = (count * 1_000_000 / self.sample_rate().0) as i64;
+            let quantum = self.time_signature().top as f64;
+            if self.link.crosses_boundary(duration_micros, quantum) {
+                self.is_waiting_for_link = false;
+                self.start_transport();
+            }
+        }
+        if self.metronome.take_finished_count_in() {
+            self.start_transport();
+        }
+        if self.declicker.take_finished_fade_out() {
+            self.finish_stop();
+        }
+        if let Some(scene) = self.scenes.advance_morph(count) {
+            self.apply_scene(&scene);
+        }
+
         // Figure out the time slice for this batch of frames.
-        let time_range = self.transport.advance(count);
+        let time_range = match self.advance_tempo_map(count) {
+            Some(time_range) => time_range,
+            None => self.transport.advance(count),
+        };
+        self.midi_clock.advance(&time_range);
+        self.update_punch(&time_range);
+        self.broadcast_is_tempo_mapped();
+        let beats_per_bar = self.time_signature().top as usize;
+        self.metronome.advance(
+            count,
+            self.transport.is_performing().then_some(&time_range),
+            self.sample_rate(),
+            beats_per_bar,
+        );
 
         // Ask tracks to do their time-based work.
         self.track_subscription
@@ -328,85 +1409,1736 @@ impl Engine {
             .send_request(TrackRequest::NeedsAudio(count));
     }
 
-    fn create_track(&mut self) -> anyhow::Result<TrackUid> {
+    /// Generates the next `count` frames of the master track's output, and
+    /// waits for them. With [Executor::Synchronous], the actors run on the
+    /// caller's thread, and the same project and requests always produce the
+    /// same frames. With the other executors, each block can take up to
+    /// [Engine::RENDER_TIMEOUT].
+    pub fn render(&mut self, count: usize) -> anyhow::Result<Vec<StereoSample>> {
+        if self.render_output.is_none() {
+            let channel: CrossbeamChannel<AudioAction> = Default::default();
+            self.subscribe_audio(&channel.sender);
+            self.render_output = Some(channel.receiver);
+        }
+        let mut frames = Vec::with_capacity(count);
+        while frames.len() < count {
+            self.start_generation((count - frames.len()).min(self.block_size));
+            let receiver = self.render_output.clone();
+            let action = match &self.executor {
+                Executor::Synchronous(executor) => {
+                    executor.run_until_idle();
+                    receiver.and_then(|receiver| receiver.try_recv().ok())
+                }
+                _ => receiver.and_then(|receiver| receiver.recv_timeout(Self::RENDER_TIMEOUT).ok()),
+            };
+            self.handle_track_actions();
+            let mut action =
+                action.ok_or_else(|| anyhow!("The master track didn't produce any frames"))?;
+            self.process_master_output(&mut action.frames);
+            frames.extend_from_slice(&action.frames);
+            action.recycle();
+        }
+        Ok(frames)
+    }
+
+    /// Adds an empty track at the end, unless the project already has as
+    /// many as [ActorLimits] allows.
+    pub fn create_track(&mut self) -> anyhow::Result<TrackUid> {
+        ActorLimits::global().check_new_track(self.tracks.len(), &self.executor)?;
         let track_uid = self.track_uid_factory.mint_next();
         let is_master_track = false;
 
-        let track_actor =
-            TrackActor::new_with(track_uid, is_master_track, &self.entity_uid_factory);
+        let track_actor = TrackActor::new_with(
+            track_uid,
+            is_master_track,
+            &self.entity_uid_factory,
+            &self.registry,
+            &self.commands.sender,
+            &self.executor,
+        );
+        self.ordered_track_uids.push(track_uid);
+        self.connect_track(track_uid, track_actor);
+
+        Ok(track_uid)
+    }
+
+    /// Hooks up a new or restored track to the master track and to us, and
+    /// starts including it in broadcasts.
+    fn connect_track(&mut self, track_uid: TrackUid, track_actor: TrackActor) {
         track_actor.send_request(TrackRequest::SubscribeAudio(
             self.master_track.audio_sender().clone(),
         ));
         track_actor.send_request(TrackRequest::SubscribeMidi(
             self.master_track.midi_sender().clone(),
         ));
+        track_actor.send_request(TrackRequest::SubscribeTrackActions(
+            self.master_track.track_action_sender().clone(),
+        ));
+        track_actor.send_request(TrackRequest::SubscribeTrackActions(
+            self.track_actions.sender.clone(),
+        ));
 
         self.master_track.send_request(TrackRequest::AddSend(
             track_uid,
             track_actor.sender().clone(),
         ));
 
+        track_actor.send_request(TrackRequest::Configure(
+            self.sample_rate(),
+            self.tempo(),
+            self.block_size,
+        ));
+        track_actor.send_request(TrackRequest::SetIsTempoMapped(self.is_tempo_mapped));
+
         self.track_subscription.subscribe(track_actor.sender());
-        self.ordered_track_uids.push(track_uid);
         self.tracks.insert(track_uid, track_actor);
-
-        Ok(track_uid)
+        self.update_latency_compensation();
     }
 
-    fn delete_track(&mut self, uid: TrackUid) {
-        self.master_track
-            .send_request(TrackRequest::RemoveSend(uid));
-        if let Some(track_actor) = self.tracks.get(&uid) {
-            track_actor.send_request(TrackRequest::UnsubscribeAudio(
-                self.master_track.audio_sender().clone(),
-            ));
-            track_actor.send_request(TrackRequest::UnsubscribeMidi(
-                self.master_track.midi_sender().clone(),
-            ));
-            track_actor.send_request(TrackRequest::Quit);
+    /// Asks each track whose delays have changed to delay its output and its
+    /// sends by enough to line them up with the slowest path to the master
+    /// track.
+    fn update_latency_compensation(&mut self) {
+        let latencies: HashMap<TrackUid, usize> = self
+            .tracks
+            .keys()
+            .map(|uid| (*uid, self.track_latency(*uid)))
+            .collect();
+        let sends: Vec<(TrackUid, TrackUid)> = self
+            .track_sends
+            .iter()
+            .flat_map(|(uid, sends)| sends.keys().map(|bus_uid| (*uid, *bus_uid)))
+            .collect();
+        let delays = compensation_delays(&latencies, &self.track_outputs, &sends);
+        for (uid, &delay) in delays.outputs.iter() {
+            if self.latency_compensation.outputs.get(uid) != Some(&delay) {
+                if let Some(track) = self.tracks.get(uid) {
+                    track.send_request(TrackRequest::SetLatencyCompensation(delay));
+                }
+            }
+        }
+        for (&(uid, bus_uid), &delay) in delays.sends.iter() {
+            if self.latency_compensation.sends.get(&(uid, bus_uid)) != Some(&delay) {
+                if let Some(track) = self.tracks.get(&uid) {
+                    let request = TrackRequest::SetSendLatencyCompensation(bus_uid, delay);
+                    track.send_request(request);
+                }
+            }
         }
-        self.ordered_track_uids.retain(|t| *t != uid);
-        self.tracks.remove(&uid);
+        self.latency_compensation = delays;
     }
 
-    fn request_quit(&mut self) {
-        self.track_subscription.broadcast_mut(TrackRequest::Quit);
+    /// How many frames late the given track's output is, as last reported by
+    /// the track.
+    pub fn track_latency(&self, uid: TrackUid) -> usize {
+        self.track_latencies.get(&uid).copied().unwrap_or_default()
     }
-}
-impl Displays for Engine {
-    fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
-        ui.horizontal_wrapped(|ui| {
-            if ui.button("Play").clicked() {
-                self.play();
-            }
-            if ui.button("Stop").clicked() {
-                self.stop();
-            }
-            ui.end_row();
-            if ui.button("Add track").clicked() {
-                let _ = self.create_track();
-            }
-        });
-        let response = ui.separator();
 
-        let mut track_index_to_delete = None;
-
-        for &track_uid in self.ordered_track_uids.iter() {
-            if let Some(track) = self.tracks.get_mut(&track_uid) {
-                track.ui(ui);
+    /// How many frames we've asked each track to delay its output and its
+    /// sends.
+    pub fn latency_compensation(&self) -> &CompensationDelays {
+        &self.latency_compensation
+    }
 
-                if ui.button(format!("Delete Track {}", track_uid)).clicked() {
-                    track_index_to_delete = Some(track_uid);
-                }
+    /// Creates a new track just like the given one, and puts it right after
+    /// it.
+    pub fn duplicate_track(&mut self, uid: TrackUid) -> anyhow::Result<TrackUid> {
+        if !self.tracks.contains_key(&uid) {
+            return Err(anyhow!("No track {uid}"));
+        }
+        let new_uid = self.create_track()?;
+        if let (Some(track), Some(new_track)) = (self.tracks.get(&uid), self.tracks.get(&new_uid)) {
+            new_track.duplicate_from(track)?;
+        }
+        if self.bus_track_uids.contains(&uid) {
+            self.bus_track_uids.insert(new_uid);
+        }
+        if let Some(&output) = self.track_outputs.get(&uid) {
+            self.route_track(new_uid, Some(output))?;
+        }
+        if let Some(sends) = self.track_sends.get(&uid).cloned() {
+            for (bus_uid, level) in sends {
+                self.set_send(new_uid, bus_uid, level)?;
             }
         }
-        ui.separator();
-        self.master_track.ui(ui);
+        self.master_track
+            .send_request(TrackRequest::CopyMixerSettings(uid, new_uid));
+
+        // create_track() put it at the end.
+        self.ordered_track_uids.retain(|t| *t != new_uid);
+        if let Some(index) = self.ordered_track_uids.iter().position(|t| *t == uid) {
+            self.ordered_track_uids.insert(index + 1, new_uid);
+        }
+        Ok(new_uid)
+    }
 
-        if let Some(uid) = track_index_to_delete {
-            self.delete_track(uid);
+    /// Creates a track that other tracks can send their output to, e.g., to
+    /// share an effects chain.
+    pub fn create_bus_track(&mut self) -> anyhow::Result<TrackUid> {
+        let track_uid = self.create_track()?;
+        self.bus_track_uids.insert(track_uid);
+        if let Some(track) = self.tracks.get(&track_uid) {
+            track.send_request(TrackRequest::SetName(format!("Bus {track_uid}")));
         }
+        Ok(track_uid)
+    }
 
+    /// Sends the track's output to the given bus, or to the master track if
+    /// None. Buses always go to the master track, which keeps the routing
+    /// free of cycles.
+    pub fn route_track(
+        &mut self,
+        uid: TrackUid,
+        output: Option<TrackUid>,
+    ) -> anyhow::Result<()> {
+        if let Some(output) = output {
+            if !self.bus_track_uids.contains(&output) {
+                return Err(anyhow!("Track {output} isn't a bus"));
+            }
+            if self.bus_track_uids.contains(&uid) {
+                return Err(anyhow!("Bus {uid} can route only to the master track"));
+            }
+        }
+        // A track can't both route and send to the same bus.
+        if let Some(output) = output {
+            if self
+                .track_sends
+                .get(&uid)
+                .is_some_and(|sends| sends.contains_key(&output))
+            {
+                self.set_send(uid, output, Normal::minimum())?;
+            }
+        }
+        let track = self
+            .tracks
+            .get(&uid)
+            .ok_or_else(|| anyhow!("No track {uid}"))?;
+        if let Some(old_output) = self.output_actor(self.track_outputs.get(&uid).copied()) {
+            old_output.send_request(TrackRequest::RemoveSend(uid));
+            track.send_request(TrackRequest::UnsubscribeAudio(
+                old_output.audio_sender().clone(),
+            ));
+        }
+        if let Some(new_output) = self.output_actor(output) {
+            new_output.send_request(TrackRequest::AddSend(uid, track.sender().clone()));
+            track.send_request(TrackRequest::SubscribeAudio(
+                new_output.audio_sender().clone(),
+            ));
+        }
+        if let Some(output) = output {
+            self.track_outputs.insert(uid, output);
+        } else {
+            self.track_outputs.remove(&uid);
+        }
+        self.update_latency_compensation();
+        Ok(())
+    }
+
+    /// Sends a copy of the track's output to the given bus at the given level,
+    /// in addition to its main output. A level of zero removes the send.
+    pub fn set_send(
+        &mut self,
+        uid: TrackUid,
+        bus_uid: TrackUid,
+        level: Normal,
+    ) -> anyhow::Result<()> {
+        if !self.bus_track_uids.contains(&bus_uid) {
+            return Err(anyhow!("Track {bus_uid} isn't a bus"));
+        }
+        if self.bus_track_uids.contains(&uid) {
+            return Err(anyhow!("Bus {uid} can't send to other buses"));
+        }
+        if self.track_outputs.get(&uid) == Some(&bus_uid) {
+            return Err(anyhow!("Track {uid} already routes to bus {bus_uid}"));
+        }
+        let (Some(track), Some(bus)) = (self.tracks.get(&uid), self.tracks.get(&bus_uid)) else {
+            return Err(anyhow!("No track {uid} or {bus_uid}"));
+        };
+        let sends = self.track_sends.entry(uid).or_default();
+        let is_sending = sends.contains_key(&bus_uid);
+        if level == Normal::minimum() {
+            if is_sending {
+                bus.send_request(TrackRequest::RemoveSend(uid));
+                track.send_request(TrackRequest::UnsubscribeSend(bus_uid));
+                sends.remove(&bus_uid);
+            }
+        } else if is_sending {
+            track.send_request(TrackRequest::SetSendLevel(bus_uid, level));
+            sends.insert(bus_uid, level);
+        } else {
+            bus.send_request(TrackRequest::AddSend(uid, track.sender().clone()));
+            track.send_request(TrackRequest::SubscribeSend(
+                bus_uid,
+                bus.audio_sender().clone(),
+                level,
+            ));
+            sends.insert(bus_uid, level);
+            // The new send starts out undelayed.
+            self.latency_compensation.sends.remove(&(uid, bus_uid));
+        }
+        self.update_latency_compensation();
+        Ok(())
+    }
+
+    /// Drives a parameter anywhere in the project with an entity's control
+    /// signal. Unlike [TrackActor::link], the source and the target can be on
+    /// different tracks.
+    pub fn route_control(&mut self, route: ControlRoute) -> anyhow::Result<()> {
+        let source_track = self.track_or_master(route.source_track)?;
+        match route.target {
+            ControlTarget::Entity(track_uid, uid, index) => {
+                let target_track = self.track_or_master(track_uid)?;
+                let sender = target_track.entity_control_sender(uid)?;
+                source_track
+                    .send_entity_request(route.source, EntityRequest::ControlSubscribe(sender))?;
+                target_track
+                    .send_entity_request(uid, EntityRequest::ControlLinkAdd(route.source, index))?;
+            }
+            ControlTarget::MixerLevel(track_uid) => {
+                if !self.tracks.contains_key(&track_uid) {
+                    return Err(anyhow!("No track {track_uid}"));
+                }
+                let sender = self.master_track.control_sender();
+                source_track
+                    .send_entity_request(route.source, EntityRequest::ControlSubscribe(sender))?;
+                self.master_track.link_mixer_level(route.source, track_uid);
+            }
+            ControlTarget::Crossfader => {
+                let sender = self.master_track.control_sender();
+                source_track
+                    .send_entity_request(route.source, EntityRequest::ControlSubscribe(sender))?;
+                self.master_track.link_crossfader(route.source);
+            }
+        }
+        self.control_routes.push(route);
+        Ok(())
+    }
+
+    /// Moves the entities between tracks without stopping them. Links
+    /// among them go along, and control routes follow them. Returns the
+    /// links with entities that stay behind, which the move breaks.
+    fn move_entities(
+        &mut self,
+        from: TrackUid,
+        to: TrackUid,
+        uids: &[Uid],
+    ) -> anyhow::Result<Vec<(Uid, ControlLink)>> {
+        let (from_track, to_track) = (self.track_or_master(from)?, self.track_or_master(to)?);
+        let broken = from_track.move_entities_to(to_track, uids)?;
+        // The actors keep their subscriptions, so the routes still work, but
+        // they should name the right tracks.
+        for route in self.control_routes.iter_mut() {
+            if route.source_track == from && uids.contains(&route.source) {
+                route.source_track = to;
+            }
+            if let ControlTarget::Entity(track_uid, uid, _) = &mut route.target {
+                if *track_uid == from && uids.contains(uid) {
+                    *track_uid = to;
+                }
+            }
+        }
+        Ok(broken)
+    }
+
+    /// Undoes [Engine::route_control].
+    pub fn unroute_control(&mut self, route: ControlRoute) -> anyhow::Result<()> {
+        let position = self
+            .control_routes
+            .iter()
+            .position(|r| *r == route)
+            .ok_or_else(|| anyhow!("No such control route"))?;
+        self.control_routes.remove(position);
+        let source_track = self.track_or_master(route.source_track)?;
+        let sender = match route.target {
+            ControlTarget::Entity(track_uid, uid, index) => {
+                let target_track = self.track_or_master(track_uid)?;
+                let request = EntityRequest::ControlLinkRemove(route.source, index);
+                target_track.send_entity_request(uid, request)?;
+                target_track.entity_control_sender(uid)?
+            }
+            ControlTarget::MixerLevel(track_uid) => {
+                self.master_track.unlink_mixer_level(route.source, track_uid);
+                self.master_track.control_sender()
+            }
+            ControlTarget::Crossfader => {
+                self.master_track.unlink_crossfader(route.source);
+                self.master_track.control_sender()
+            }
+        };
+        // Unsubscribing removes every subscription to the same receiver, so
+        // it waits for the last route that needs it.
+        let is_receiver_in_use = self.control_routes.iter().any(|r| {
+            r.source == route.source && r.target.receiver() == route.target.receiver()
+        });
+        if !is_receiver_in_use {
+            source_track
+                .send_entity_request(route.source, EntityRequest::ControlUnsubscribe(sender))?;
+        }
+        Ok(())
+    }
+
+    pub fn control_routes(&self) -> &[ControlRoute] {
+        &self.control_routes
+    }
+
+    /// Every parameter that an entity's control signal can drive, with its
+    /// name for the UI.
+    pub fn control_targets(&self) -> Vec<(ControlTarget, String)> {
+        let tracks = self
+            .ordered_track_uids
+            .iter()
+            .filter_map(|uid| Some((*uid, self.tracks.get(uid)?)))
+            .chain(std::iter::once((TrackUid::default(), &self.master_track)));
+        let mut targets = Vec::default();
+        for (track_uid, track) in tracks {
+            let track_name = if track_uid == TrackUid::default() {
+                "Master".to_string()
+            } else {
+                self.track_name(track_uid)
+            };
+            for (uid, index, name) in track.controllables() {
+                let target = ControlTarget::Entity(track_uid, uid, index);
+                targets.push((target, format!("{track_name}: {name}")));
+            }
+        }
+        for &track_uid in self.ordered_track_uids.iter() {
+            let name = format!("Mixer: {} level", self.track_name(track_uid));
+            targets.push((ControlTarget::MixerLevel(track_uid), name));
+        }
+        if !self.ordered_track_uids.is_empty() {
+            targets.push((ControlTarget::Crossfader, "Mixer: crossfader".to_string()));
+        }
+        targets
+    }
+
+    pub fn scenes(&self) -> &SceneStore {
+        &self.scenes
+    }
+
+    pub fn scenes_mut(&mut self) -> &mut SceneStore {
+        &mut self.scenes
+    }
+
+    /// Stores the master mixer's levels and mutes, and the selected
+    /// parameters, as the numbered scene. Parameters whose values aren't
+    /// known yet are left out; see [TrackActor::param].
+    pub fn capture_scene(&mut self, number: u8) {
+        let scene = self.current_scene(self.scenes.params());
+        self.scenes.insert(number, scene);
+    }
+
+    /// Moves to the numbered scene over `morph_seconds`, or right away if
+    /// that's zero. Like automation, recalling a scene doesn't go into the
+    /// undo history.
+    pub fn recall_scene(&mut self, number: u8, morph_seconds: f64) -> anyhow::Result<()> {
+        let scene = self
+            .scenes
+            .scene(number)
+            .cloned()
+            .ok_or_else(|| anyhow!("There's no scene {number}"))?;
+        let frames = (morph_seconds.max(0.0) * self.sample_rate().0 as f64).round() as usize;
+        if frames == 0 {
+            self.scenes.cancel_morph();
+            self.apply_scene(&scene);
+        } else {
+            let params: Vec<_> = scene.params.iter().map(|(param, _)| *param).collect();
+            let from = self.current_scene(&params);
+            self.scenes.start_morph(from, scene, frames);
+        }
+        Ok(())
+    }
+
+    fn current_scene(&self, params: &[SceneParam]) -> Scene {
+        let params = params
+            .iter()
+            .filter_map(|param| {
+                let track = self.track_or_master(param.track_uid).ok()?;
+                Some((*param, track.param(param.uid, param.index)?))
+            })
+            .collect();
+        Scene {
+            mixer: self.master_track.mixer_levels(),
+            params,
+        }
+    }
+
+    fn apply_scene(&self, scene: &Scene) {
+        // The scene might name tracks and entities that have since been
+        // deleted, so errors just mean there's nothing to set.
+        for &(track_uid, level, muted) in scene.mixer.iter() {
+            let _ = self.master_track.set_mixer_level(track_uid, level);
+            let _ = self.master_track.set_mixer_mute(track_uid, muted);
+        }
+        for &(param, value) in scene.params.iter() {
+            if let Ok(track) = self.track_or_master(param.track_uid) {
+                let _ = track.set_param(param.uid, param.index, value);
+            }
+        }
+    }
+
+    /// The given bus, or the master track if None.
+    fn output_actor(&self, output: Option<TrackUid>) -> Option<&TrackActor> {
+        match output {
+            Some(uid) => self.tracks.get(&uid),
+            None => Some(&self.master_track),
+        }
+    }
+
+    /// Where new entities come from.
+    pub fn registry(&self) -> &EntityRegistry {
+        &self.registry
+    }
+
+    /// Adds an entity or plugin from the
+    /// [EntityBrowser](crate::browser::EntityBrowser) to the given track.
+    /// Adding an entity can be undone.
+    pub fn add_browser_item(
+        &mut self,
+        track_uid: TrackUid,
+        item: &BrowserItem,
+    ) -> anyhow::Result<()> {
+        match item {
+            BrowserItem::Entity(key) => self.execute(Command::AddEntity(track_uid, key.clone())),
+            BrowserItem::Plugin(descriptor) => {
+                self.execute(Command::AddPlugin(track_uid, descriptor.clone()))
+            }
+        }
+    }
+
+    /// All tracks except the master track, in display order.
+    pub fn track_uids(&self) -> &[TrackUid] {
+        &self.ordered_track_uids
+    }
+
+    pub fn track(&self, uid: TrackUid) -> Option<&TrackActor> {
+        self.tracks.get(&uid)
+    }
+
+    pub fn master_track(&self) -> &TrackActor {
+        &self.master_track
+    }
+
+    /// The track's name and color, as of the last [Engine::handle_track_actions()].
+    pub fn track_info(&self, uid: TrackUid) -> Option<&TrackInfo> {
+        self.track_infos.get(&uid)
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.is_recording
+    }
+
+    /// Moves the transport to the given position. If it's playing, it keeps
+    /// playing from there. Notes that were sounding are released, because
+    /// their note-offs are now somewhere else in the song.
+    pub fn seek(&mut self, time: MusicalTime) {
+        self.all_notes_off();
+        self.transport.update_time_range(&TimeRange(time..time));
+        self.track_subscription
+            .broadcast_mut(TrackRequest::Seek(time));
+        self.follow_tempo_map(time);
+    }
+
+    /// Seeks to the given frame, counting from the start along the tempo
+    /// map, or at the current tempo if there isn't one.
+    pub fn seek_to_frame(&mut self, frame: usize) {
+        let end = self
+            .tempo_map
+            .advance(MusicalTime::START, frame, self.sample_rate());
+        if let Some((time, _)) = end {
+            self.seek(time);
+            return;
+        }
+        let beats = frame as f64 / self.sample_rate().0 as f64 * self.tempo().0 / 60.0;
+        self.seek(MusicalTime::new_with_units(
+            (beats * MusicalTime::UNITS_IN_BEAT as f64) as usize,
+        ));
+    }
+
+    /// Sends All Notes Off (CC 123) to every track.
+    fn all_notes_off(&mut self) {
+        self.track_subscription.broadcast_mut(TrackRequest::Midi(
+            MidiChannel::default(),
+            MidiMessage::Controller {
+                controller: 123.into(),
+                value: 0.into(),
+            },
+        ));
+    }
+
+    /// Where the next block of frames starts.
+    pub fn position(&self) -> MusicalTime {
+        self.transport
+            .time_range()
+            .map(|time_range| time_range.0.end)
+            .unwrap_or(MusicalTime::START)
+    }
+
+    /// Changes the tempo. During playback, a tempo map overrides it.
+    pub fn set_tempo(&mut self, tempo: Tempo) {
+        if tempo.0 != self.tempo().0 {
+            self.update_tempo(tempo);
+        }
+    }
+
+    /// Sets the tempo from the time between this tap and the ones before it.
+    /// The first tap of a series does nothing.
+    pub fn tap_tempo(&mut self, at: Instant) {
+        if let Some(tempo) = self.tap_tempo.tap(at) {
+            self.set_tempo(tempo);
+        }
+    }
+
+    pub fn tempo_map(&self) -> &TempoMap {
+        &self.tempo_map
+    }
+
+    /// The tempo automation. Changes to it take effect with the next block
+    /// of playback, or the next seek.
+    pub fn tempo_map_mut(&mut self) -> &mut TempoMap {
+        &mut self.tempo_map
+    }
+
+    /// Moves the transport along the tempo map during playback, and returns
+    /// the slice of time that the next `count` frames cover. None if there's
+    /// no tempo map, or if the transport is stopped.
+    fn advance_tempo_map(&mut self, count: usize) -> Option<TimeRange> {
+        if !self.transport.is_performing() {
+            return None;
+        }
+        let start = self.position();
+        let (end, tempo) = self.tempo_map.advance(start, count, self.sample_rate())?;
+        let time_range = TimeRange(start..end);
+        self.transport.update_time_range(&time_range);
+        self.set_tempo(tempo);
+        Some(time_range)
+    }
+
+    /// Tells the tracks when a tempo map comes or goes, because they can't
+    /// freeze while there is one.
+    fn broadcast_is_tempo_mapped(&mut self) {
+        let is_tempo_mapped = !self.tempo_map.is_empty();
+        if is_tempo_mapped != self.is_tempo_mapped {
+            self.is_tempo_mapped = is_tempo_mapped;
+            self.track_subscription
+                .broadcast_mut(TrackRequest::SetIsTempoMapped(is_tempo_mapped));
+        }
+    }
+
+    /// Switches to the tempo map's tempo at the given time, if there's a
+    /// tempo map.
+    fn follow_tempo_map(&mut self, time: MusicalTime) {
+        if let Some(tempo) = self.tempo_map.tempo_at(time) {
+            self.set_tempo(tempo);
+        }
+    }
+
+    pub fn metronome(&self) -> &Metronome {
+        &self.metronome
+    }
+
+    /// The metronome's settings. While recording is on, its count-in runs
+    /// before [Controls::play] starts the transport.
+    pub fn metronome_mut(&mut self) -> &mut Metronome {
+        &mut self.metronome
+    }
+
+    pub fn punch(&self) -> PunchRegion {
+        self.punch
+    }
+
+    /// Sets where recording starts and stops on its own during playback.
+    /// Recording that the user started by hand isn't affected.
+    pub fn set_punch(&mut self, punch: PunchRegion) {
+        self.punch = punch;
+    }
+
+    /// Punches in or out if this slice of time crosses an edge of the punch
+    /// region. Checking only the start of the slice means the edges land on
+    /// block boundaries.
+    fn update_punch(&mut self, time_range: &TimeRange) {
+        let should_record = self.punch.is_enabled
+            && self.transport.is_performing()
+            && self.punch.contains(time_range.0.start);
+        if should_record && !self.is_recording {
+            self.is_punched_in = true;
+            self.start_recording();
+        } else if !should_record && self.is_punched_in {
+            self.is_punched_in = false;
+            self.stop_recording();
+        }
+    }
+
+    /// Whether the master output has clipped since the last
+    /// [Engine::reset_clipping()].
+    pub fn is_clipping(&self) -> bool {
+        self.is_clipping.load(ATOMIC_ORDERING)
+    }
+
+    pub fn reset_clipping(&self) {
+        self.is_clipping.store(false, ATOMIC_ORDERING);
+    }
+
+    #[cfg(feature = "gui")]
+    /// Describes each track's state, for when the master track stops
+    /// answering.
+    fn diagnose_stall(&self, waited: Duration, frames_requested: usize) -> StallDiagnostics {
+        let master = TrackDiagnostics {
+            uid: TrackUid::default(),
+            name: "Master".to_string(),
+            state: self.master_track.describe_state(),
+        };
+        let tracks = self
+            .ordered_track_uids
+            .iter()
+            .filter_map(|&uid| {
+                self.tracks.get(&uid).map(|track| TrackDiagnostics {
+                    uid,
+                    name: self.track_name(uid),
+                    state: track.describe_state(),
+                })
+            });
+        StallDiagnostics {
+            waited,
+            frames_requested,
+            tracks: std::iter::once(master).chain(tracks).collect(),
+        }
+    }
+
+    /// The name the track reported, or a placeholder until it does.
+    pub fn track_name(&self, uid: TrackUid) -> String {
+        self.track_infos
+            .get(&uid)
+            .map(|info| info.name.clone())
+            .unwrap_or_else(|| format!("Track {uid}"))
+    }
+
+    /// Creates a new track for each SMF track in the given file, with its
+    /// notes loaded as the track's clip.
+    pub fn import_midi_file(&mut self, path: &Path) -> anyhow::Result<()> {
+        for clip in import_midi_file(path)? {
+            let track_uid = self.create_track()?;
+            if let Some(track) = self.tracks.get(&track_uid) {
+                track.send_request(TrackRequest::SetMidiClip(clip));
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates a new track holding the given audio file as a clip at the
+    /// start of the song.
+    pub fn import_audio_file(&mut self, path: &Path) -> anyhow::Result<()> {
+        let clip = AudioClip::new_from_wav(path, MusicalTime::START)?
+            .resampled(self.sample_rate(), self.resample_quality);
+        let track_uid = self.create_track()?;
+        if let Some(track) = self.tracks.get(&track_uid) {
+            track.send_request(TrackRequest::AddAudioClip(clip));
+        }
+        Ok(())
+    }
+
+    fn broadcast_configuration(&mut self) {
+        let request = TrackRequest::Configure(self.sample_rate(), self.tempo(), self.block_size);
+        self.track_subscription.broadcast_mut(request);
+    }
+
+    pub fn delete_track(&mut self, uid: TrackUid) {
+        // Dropping the detached track ends it.
+        if let Err(e) = self.detach_track(uid) {
+            warn!("While deleting track {uid}: {e:?}");
+        }
+        self.track_latencies.remove(&uid);
+    }
+
+    /// Disconnects the track from everything, but keeps it running so that
+    /// [Engine::attach_track()] can put it back.
+    fn detach_track(&mut self, uid: TrackUid) -> anyhow::Result<DetachedTrack> {
+        let index = self
+            .ordered_track_uids
+            .iter()
+            .position(|t| *t == uid)
+            .ok_or_else(|| anyhow!("No track {uid}"))?;
+
+        // Tracks routed to a deleted bus go back to the master track, and
+        // sends to it go away.
+        let is_bus = self.bus_track_uids.contains(&uid);
+        let mut routed_sources = Vec::default();
+        let mut send_sources = Vec::default();
+        if is_bus {
+            routed_sources = self
+                .track_outputs
+                .iter()
+                .filter(|(_, output)| **output == uid)
+                .map(|(source, _)| *source)
+                .collect();
+            for source in routed_sources.iter() {
+                let _ = self.route_track(*source, None);
+            }
+            send_sources = self
+                .track_sends
+                .iter()
+                .filter_map(|(source, sends)| sends.get(&uid).map(|level| (*source, *level)))
+                .collect();
+            for (source, _) in send_sources.iter() {
+                let _ = self.set_send(*source, uid, Normal::minimum());
+            }
+            self.bus_track_uids.remove(&uid);
+        }
+        let sends = self.track_sends.remove(&uid).unwrap_or_default();
+        if let Some(track_actor) = self.tracks.get(&uid) {
+            for bus_uid in sends.keys() {
+                if let Some(bus) = self.tracks.get(bus_uid) {
+                    bus.send_request(TrackRequest::RemoveSend(uid));
+                    track_actor.send_request(TrackRequest::UnsubscribeSend(*bus_uid));
+                }
+            }
+        }
+        let output = self.track_outputs.remove(&uid);
+        if let Some(output_actor) = self.output_actor(output) {
+            output_actor.send_request(TrackRequest::RemoveSend(uid));
+            if let Some(track_actor) = self.tracks.get(&uid) {
+                track_actor.send_request(TrackRequest::UnsubscribeAudio(
+                    output_actor.audio_sender().clone(),
+                ));
+            }
+        }
+        let control_routes: Vec<ControlRoute> = self
+            .control_routes
+            .iter()
+            .filter(|r| r.source_track == uid || r.target.track_uid() == uid)
+            .copied()
+            .collect();
+        for &route in control_routes.iter() {
+            if let Err(e) = self.unroute_control(route) {
+                warn!("While removing track {uid}'s control routes: {e:?}");
+            }
+        }
+        let track_actor = self
+            .tracks
+            .remove(&uid)
+            .ok_or_else(|| anyhow!("No track {uid}"))?;
+        self.recording.disarm_track(uid, Some(&track_actor));
+        track_actor.send_request(TrackRequest::ReleaseNotes);
+        track_actor.send_request(TrackRequest::UnsubscribeMidi(
+            self.master_track.midi_sender().clone(),
+        ));
+        track_actor.send_request(TrackRequest::UnsubscribeTrackActions(
+            self.master_track.track_action_sender().clone(),
+        ));
+        track_actor.send_request(TrackRequest::UnsubscribeTrackActions(
+            self.track_actions.sender.clone(),
+        ));
+        self.track_subscription.unsubscribe(track_actor.sender());
+        if let Some(stem_writer) = self.stem_writer.as_ref() {
+            stem_writer.send_input(WavWriterInput::RemoveStem(uid));
+        }
+        self.ordered_track_uids.remove(index);
+        self.meters.remove(&uid);
+        self.track_infos.remove(&uid);
+        self.latency_compensation.outputs.remove(&uid);
+        self.latency_compensation
+            .sends
+            .retain(|(source, _), _| *source != uid);
+        self.update_latency_compensation();
+
+        Ok(DetachedTrack {
+            uid,
+            actor: Some(track_actor),
+            index,
+            is_bus,
+            output,
+            sends,
+            routed_sources,
+            send_sources,
+            control_routes,
+        })
+    }
+
+    /// Puts a detached track back where it was, with its routing.
+    fn attach_track(&mut self, mut detached: DetachedTrack) -> anyhow::Result<TrackUid> {
+        let track_actor = detached
+            .actor
+            .take()
+            .ok_or_else(|| anyhow!("Track was already attached"))?;
+        let uid = detached.uid;
+        let index = detached.index.min(self.ordered_track_uids.len());
+        self.ordered_track_uids.insert(index, uid);
+        self.connect_track(uid, track_actor);
+        if detached.is_bus {
+            self.bus_track_uids.insert(uid);
+        }
+
+        // A track or bus on the other end might itself have been deleted
+        // since.
+        if detached.output.is_some() {
+            if let Err(e) = self.route_track(uid, detached.output) {
+                warn!("While restoring track {uid}'s output: {e:?}");
+            }
+        }
+        for (&bus_uid, &level) in detached.sends.iter() {
+            if let Err(e) = self.set_send(uid, bus_uid, level) {
+                warn!("While restoring track {uid}'s send to {bus_uid}: {e:?}");
+            }
+        }
+        for &source in detached.routed_sources.iter() {
+            if let Err(e) = self.route_track(source, Some(uid)) {
+                warn!("While restoring track {source}'s output: {e:?}");
+            }
+        }
+        for &(source, level) in detached.send_sources.iter() {
+            if let Err(e) = self.set_send(source, uid, level) {
+                warn!("While restoring track {source}'s send to {uid}: {e:?}");
+            }
+        }
+        for &route in detached.control_routes.iter() {
+            if let Err(e) = self.route_control(route) {
+                warn!("While restoring track {uid}'s control routes: {e:?}");
+            }
+        }
+        Ok(uid)
+    }
+
+    /// The given track, which may be the master track.
+    fn track_or_master(&self, uid: TrackUid) -> anyhow::Result<&TrackActor> {
+        if uid == TrackUid::default() {
+            Ok(&self.master_track)
+        } else {
+            self.tracks.get(&uid).ok_or_else(|| anyhow!("No track {uid}"))
+        }
+    }
+
+    /// Makes the change, and returns the command that reverses it.
+    fn apply(&mut self, command: Command) -> anyhow::Result<Command> {
+        Ok(match command {
+            Command::AddTrack => Command::DeleteTrack(self.create_track()?),
+            Command::DeleteTrack(uid) => Command::RestoreTrack(self.detach_track(uid)?),
+            Command::RestoreTrack(detached) => Command::DeleteTrack(self.attach_track(detached)?),
+            Command::AddEntity(track_uid, key) => {
+                let uid = self.track_or_master(track_uid)?.add_entity_by_key(&key)?;
+                Command::RemoveEntity(track_uid, uid)
+            }
+            Command::AddPlugin(track_uid, descriptor) => {
+                let uid = self.track_or_master(track_uid)?.add_plugin(&descriptor)?;
+                Command::RemoveEntity(track_uid, uid)
+            }
+            Command::RemoveEntity(track_uid, uid) => {
+                let detached = self.track_or_master(track_uid)?.detach_entity(uid)?;
+                Command::RestoreEntity(track_uid, detached)
+            }
+            Command::RestoreEntity(track_uid, detached) => {
+                let uid = self.track_or_master(track_uid)?.attach_entity(detached)?;
+                Command::RemoveEntity(track_uid, uid)
+            }
+            Command::PasteEntities(track_uid, clipboard) => {
+                let track = self.track_or_master(track_uid)?;
+                let uids = track.paste_entities(&clipboard)?;
+                let inverses = uids
+                    .into_iter()
+                    .rev()
+                    .map(|uid| Command::RemoveEntity(track_uid, uid))
+                    .collect();
+                Command::Batch(inverses)
+            }
+            Command::MoveEntities(from, to, uids) => {
+                let broken = self.move_entities(from, to, &uids)?;
+                // Moving back doesn't restore the broken links by itself.
+                let links = broken
+                    .into_iter()
+                    .map(|(source_uid, link)| Command::Link(from, source_uid, link));
+                let inverse = Command::MoveEntities(to, from, uids);
+                Command::Batch(std::iter::once(inverse).chain(links).collect())
+            }
+            Command::MoveEntity(track_uid, uid, index) => {
+                let old_index = self.track_or_master(track_uid)?.move_entity(uid, index)?;
+                Command::MoveEntity(track_uid, uid, old_index)
+            }
+            Command::SetEffectGroup(track_uid, uid, group) => {
+                let track = self.track_or_master(track_uid)?;
+                Command::SetEffectGroup(track_uid, uid, track.set_effect_group(uid, group))
+            }
+            Command::SetEffectMix(track_uid, uid, mix) => {
+                let old_mix = self.track_or_master(track_uid)?.set_effect_mix(uid, mix);
+                Command::SetEffectMix(track_uid, uid, old_mix)
+            }
+            Command::SetMidiEffect(track_uid, uid, is_midi_effect) => {
+                let track = self.track_or_master(track_uid)?;
+                Command::SetMidiEffect(track_uid, uid, track.set_midi_effect(uid, is_midi_effect))
+            }
+            Command::SetControlTransfer(track_uid, source_uid, link, transfer) => {
+                let track = self.track_or_master(track_uid)?;
+                let old = track.set_control_transfer(source_uid, link.uid, link.param, transfer);
+                Command::SetControlTransfer(track_uid, source_uid, link, old)
+            }
+            Command::ArmTrack(uid, is_armed) => {
+                Command::ArmTrack(uid, self.track_or_master(uid)?.set_armed(is_armed))
+            }
+            Command::SetBatchGenerators(uid, is_batching) => {
+                let track = self.track_or_master(uid)?;
+                Command::SetBatchGenerators(uid, track.set_batching_generators(is_batching))
+            }
+            Command::Link(track_uid, source_uid, link) => {
+                self.track_or_master(track_uid)?.link(source_uid, link.uid, link.param)?;
+                Command::Unlink(track_uid, source_uid, link)
+            }
+            Command::Unlink(track_uid, source_uid, link) => {
+                self.track_or_master(track_uid)?.unlink(source_uid, link.uid, link.param);
+                Command::Link(track_uid, source_uid, link)
+            }
+            Command::RouteControl(route) => {
+                self.route_control(route)?;
+                Command::UnrouteControl(route)
+            }
+            Command::UnrouteControl(route) => {
+                self.unroute_control(route)?;
+                Command::RouteControl(route)
+            }
+            Command::SetMixerLevel(uid, level) => {
+                Command::SetMixerLevel(uid, self.master_track.set_mixer_level(uid, level)?)
+            }
+            Command::SetMixerMute(uid, muted) => {
+                Command::SetMixerMute(uid, self.master_track.set_mixer_mute(uid, muted)?)
+            }
+            Command::SetOutputPair(uid, pair) => {
+                Command::SetOutputPair(uid, self.master_track.set_output_pair(uid, pair)?)
+            }
+            Command::SetCrossfadeGroup(uid, group) => {
+                Command::SetCrossfadeGroup(uid, self.master_track.set_crossfade_group(uid, group)?)
+            }
+            Command::SetCrossfader(position) => {
+                Command::SetCrossfader(self.master_track.set_crossfader(position)?)
+            }
+            Command::SetCrossfadeCurve(curve) => {
+                Command::SetCrossfadeCurve(self.master_track.set_crossfade_curve(curve)?)
+            }
+            Command::SetMidiClip(uid, clip) => {
+                Command::SetMidiClip(uid, self.track_or_master(uid)?.set_midi_clip(clip))
+            }
+            Command::QuantizeMidiClip(uid, quantize) => {
+                let track = self.track_or_master(uid)?;
+                let clip = track.midi_clip().quantized(&quantize);
+                Command::SetMidiClip(uid, track.set_midi_clip(clip))
+            }
+            Command::HumanizeMidiClip(uid, humanize) => {
+                let track = self.track_or_master(uid)?;
+                let clip = track.midi_clip().humanized(&humanize);
+                Command::SetMidiClip(uid, track.set_midi_clip(clip))
+            }
+            Command::SetClipEdits(uid, clip_edits) => {
+                Command::SetClipEdits(uid, self.track_or_master(uid)?.set_clip_edits(clip_edits))
+            }
+            Command::Batch(commands) => {
+                let mut inverses = Vec::with_capacity(commands.len());
+                for command in commands {
+                    match self.apply(command) {
+                        Ok(inverse) => inverses.push(inverse),
+                        Err(e) => {
+                            // Leave things as they were before the batch.
+                            for inverse in inverses.into_iter().rev() {
+                                if let Err(e) = self.apply(inverse) {
+                                    error!("While rolling back a batch: {e:?}");
+                                }
+                            }
+                            return Err(e);
+                        }
+                    }
+                }
+                inverses.reverse();
+                Command::Batch(inverses)
+            }
+        })
+    }
+
+    /// Makes the change, and remembers how to undo it.
+    pub fn execute(&mut self, command: Command) -> anyhow::Result<()> {
+        let inverse = self.apply(command)?;
+        self.history.push(inverse);
+        Ok(())
+    }
+
+    /// Adds the entity registered under the given key to the track, which
+    /// may be the master track, and returns the new entity's uid. Like
+    /// [Command::AddEntity], it can be undone.
+    pub fn add_entity_to_track(&mut self, track_uid: TrackUid, key: &str) -> anyhow::Result<Uid> {
+        let uid = self.track_or_master(track_uid)?.add_entity_by_key(key)?;
+        self.history.push(Command::RemoveEntity(track_uid, uid));
+        Ok(uid)
+    }
+
+    /// Executes [Command::RemoveEntity].
+    pub fn remove_entity(&mut self, track_uid: TrackUid, uid: Uid) -> anyhow::Result<()> {
+        self.execute(Command::RemoveEntity(track_uid, uid))
+    }
+
+    /// Removes the track's selected entities as one undoable step.
+    pub fn remove_selected_entities(&mut self, track_uid: TrackUid) -> anyhow::Result<()> {
+        let uids = self.track_or_master(track_uid)?.selection();
+        let commands = uids
+            .into_iter()
+            .map(|uid| Command::RemoveEntity(track_uid, uid))
+            .collect();
+        self.execute(Command::Batch(commands))
+    }
+
+    /// Bypasses (true) or re-enables (false) the track's selected effects.
+    pub fn bypass_selected_entities(
+        &mut self,
+        track_uid: TrackUid,
+        is_bypassed: bool,
+    ) -> anyhow::Result<()> {
+        let track = self.track_or_master(track_uid)?;
+        track.set_bypass(&track.selection(), is_bypassed);
+        Ok(())
+    }
+
+    /// Copies the track's selected entities, as they are now, for
+    /// [Engine::paste_entities].
+    pub fn copy_selected_entities(&mut self, track_uid: TrackUid) -> anyhow::Result<()> {
+        let track = self.track_or_master(track_uid)?;
+        let clipboard = track.copy_entities(&track.selection())?;
+        self.entity_clipboard = (!clipboard.is_empty()).then(|| Arc::new(clipboard));
+        Ok(())
+    }
+
+    /// Moves the track's selected entities to the end of another track.
+    /// Executes [Command::MoveEntities].
+    pub fn move_selected_entities(&mut self, from: TrackUid, to: TrackUid) -> anyhow::Result<()> {
+        let uids = self.track_or_master(from)?.selection();
+        self.execute(Command::MoveEntities(from, to, uids))
+    }
+
+    /// Adds copies of the most recently copied entities to the track.
+    /// Executes [Command::PasteEntities].
+    pub fn paste_entities(&mut self, track_uid: TrackUid) -> anyhow::Result<()> {
+        let clipboard = self
+            .entity_clipboard
+            .clone()
+            .ok_or_else(|| anyhow!("Nothing has been copied"))?;
+        self.execute(Command::PasteEntities(track_uid, clipboard))
+    }
+
+    /// Drives the target entity's parameter with the source entity's control
+    /// signal. Both are on the given track. Executes [Command::Link].
+    pub fn link_control(
+        &mut self,
+        track_uid: TrackUid,
+        source_uid: Uid,
+        target_uid: Uid,
+        param: ControlIndex,
+    ) -> anyhow::Result<()> {
+        let link = ControlLink {
+            uid: target_uid,
+            param,
+        };
+        self.execute(Command::Link(track_uid, source_uid, link))
+    }
+
+    /// Sends a hand-made request straight to a track or entity actor, for
+    /// debugging. Nothing is recorded for undo, and the engine doesn't know
+    /// about the change, so e.g. the replies to an injected
+    /// [TrackRequest::NeedsAudio] arrive as if the engine had asked.
+    pub fn inject(&self, injection: Injection) -> anyhow::Result<()> {
+        match injection {
+            Injection::Track(track_uid, request) => {
+                self.track_or_master(track_uid)?.send_request(request);
+            }
+            Injection::Entity(track_uid, uid, request) => {
+                let track = self.track_or_master(track_uid)?;
+                track.send_entity_request(uid, request)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn undo(&mut self) -> anyhow::Result<()> {
+        if let Some(command) = self.history.pop_undo() {
+            let inverse = self.apply(command)?;
+            self.history.push_redo(inverse);
+        }
+        Ok(())
+    }
+
+    pub fn redo(&mut self) -> anyhow::Result<()> {
+        if let Some(command) = self.history.pop_redo() {
+            let inverse = self.apply(command)?;
+            self.history.push_undo(inverse);
+        }
+        Ok(())
+    }
+
+    /// Where to send commands for [Engine::handle_commands()] to execute.
+    pub fn command_sender(&self) -> &Sender<Command> {
+        &self.commands.sender
+    }
+
+    /// Carries out one of the [EngineServiceInput]s that build the project,
+    /// and returns the event that answers it.
+    fn handle_project_input(&mut self, input: EngineServiceInput) -> EngineServiceEvent {
+        let name = input.name();
+        let done = |_| EngineServiceEvent::Done(name);
+        let result = match input {
+            EngineServiceInput::CreateTrack => {
+                self.create_track().map(EngineServiceEvent::TrackCreated)
+            }
+            EngineServiceInput::DeleteTrack(uid) => {
+                self.execute(Command::DeleteTrack(uid)).map(done)
+            }
+            EngineServiceInput::SetTrackName(uid, track_name) => self
+                .track_or_master(uid)
+                .map(|track| track.send_request(TrackRequest::SetName(track_name)))
+                .map(done),
+            EngineServiceInput::AddEntity(track_uid, key) => self
+                .add_entity_to_track(track_uid, &key)
+                .map(|uid| EngineServiceEvent::EntityAdded(track_uid, uid)),
+            EngineServiceInput::RemoveEntity(track_uid, uid) => {
+                self.remove_entity(track_uid, uid).map(done)
+            }
+            EngineServiceInput::SetParam(track_uid, uid, index, value) => self
+                .track_or_master(track_uid)
+                .and_then(|track| track.set_param(uid, index, value))
+                .map(done),
+            _ => Err(anyhow!("{name} doesn't build the project")),
+        };
+        result.unwrap_or_else(|e| EngineServiceEvent::Failed(name, format!("{e:?}")))
+    }
+
+    /// Carries out an edit from the track list or the browser, reporting
+    /// what went wrong.
+    fn handle_edit_input(&mut self, input: EngineServiceInput) -> anyhow::Result<()> {
+        let name = input.name();
+        let (result, context) = match input {
+            EngineServiceInput::CreateBusTrack => (
+                self.create_bus_track().map(|_| ()),
+                "While adding a bus".to_string(),
+            ),
+            EngineServiceInput::DuplicateTrack(uid) => (
+                self.duplicate_track(uid).map(|_| ()),
+                format!("While duplicating track {uid}"),
+            ),
+            EngineServiceInput::RouteTrack(uid, output) => (
+                self.route_track(uid, output),
+                format!("While routing track {uid}"),
+            ),
+            EngineServiceInput::SetSend(uid, bus_uid, level) => (
+                self.set_send(uid, bus_uid, level),
+                format!("While setting track {uid}'s send to {bus_uid}"),
+            ),
+            EngineServiceInput::EditSelection(uid, action) => (
+                self.handle_selection_action(uid, action),
+                format!("While changing track {uid}'s selection"),
+            ),
+            EngineServiceInput::AddBrowserItem(uid, item) => (
+                self.add_browser_item(uid, &item),
+                format!("While adding to track {uid}"),
+            ),
+            _ => return Err(anyhow!("{name} isn't an edit")),
+        };
+        if let Err(e) = result.as_ref() {
+            report_error(&context, e);
+        }
+        result
+    }
+
+    fn handle_selection_action(
+        &mut self,
+        track_uid: TrackUid,
+        action: SelectionAction,
+    ) -> anyhow::Result<()> {
+        match action {
+            SelectionAction::Remove => self.remove_selected_entities(track_uid),
+            SelectionAction::Bypass(is_bypassed) => {
+                self.bypass_selected_entities(track_uid, is_bypassed)
+            }
+            SelectionAction::Copy => self.copy_selected_entities(track_uid),
+            SelectionAction::Paste => self.paste_entities(track_uid),
+            SelectionAction::MoveTo(to) => self.move_selected_entities(track_uid, to),
+        }
+    }
+
+    /// Executes the commands that the UI has sent since the last call.
+    pub fn handle_commands(&mut self) {
+        while let Ok(command) = self.commands.receiver.try_recv() {
+            if let Err(e) = self.execute(command) {
+                report_error("While executing a command", &e);
+            }
+        }
+    }
+
+    fn handle_audio_input(&mut self, frames: Vec<StereoSample>) {
+        // What the player does during the count-in isn't part of the take.
+        if self.is_recording && !self.metronome.is_counting_in() {
+            self.track_subscription
+                .broadcast_mut(TrackRequest::AudioInput(frames));
+        }
+    }
+
+    pub fn start_recording(&mut self) {
+        self.is_recording = true;
+        self.track_subscription
+            .broadcast_mut(TrackRequest::StartRecording);
+    }
+
+    pub fn stop_recording(&mut self) {
+        self.is_recording = false;
+        self.is_punched_in = false;
+        self.track_subscription
+            .broadcast_mut(TrackRequest::StopRecording);
+
+        // Tracks that didn't record anything ignore this.
+        let sample_rate = self.sample_rate();
+        let directory = self.output_directory();
+        for (uid, track) in self.tracks.iter() {
+            let file_name = format!("track-{}-{}.wav", uid, sample_rate.0);
+            track.send_request(TrackRequest::WriteRecording(
+                directory.join(file_name),
+                sample_rate,
+            ));
+        }
+    }
+
+    /// Writes the MIDI captured so far to the [Engine::output_directory],
+    /// named after the project.
+    pub fn export_midi(&self) {
+        let name = self
+            .project_path
+            .as_deref()
+            .and_then(Path::file_stem)
+            .map_or_else(|| "midi-capture".into(), |stem| stem.to_string_lossy());
+        let path = self.output_directory().join(format!("{name}.mid"));
+        self.midi_writer
+            .send_input(MidiFileWriterInput::Export(path));
+    }
+
+    /// Starts writing every track's output to its own file in the given
+    /// directory. Tracks added after this point aren't included.
+    pub fn start_stem_export(&mut self, directory: &Path) {
+        let stem_writer = WavWriterService::new();
+        let sample_rate = self.sample_rate();
+        for (uid, track) in self.tracks.iter() {
+            let file_name = format!("stem-{}-{}.wav", uid, sample_rate.0);
+            stem_writer.send_input(WavWriterInput::AddStem(
+                *uid,
+                directory.join(file_name),
+                sample_rate,
+            ));
+            track.send_request(TrackRequest::SubscribeFrames(
+                stem_writer.track_action_sender().clone(),
+            ));
+        }
+        self.stem_writer = Some(stem_writer);
+    }
+
+    pub fn stop_stem_export(&mut self) {
+        if let Some(stem_writer) = self.detach_stem_writer() {
+            stem_writer.send_input(WavWriterInput::Quit);
+        }
+    }
+
+    /// Stops sending track output to the stem writer, and hands it over.
+    fn detach_stem_writer(&mut self) -> Option<WavWriterService> {
+        let stem_writer = self.stem_writer.take()?;
+        for track in self.tracks.values() {
+            track.send_request(TrackRequest::UnsubscribeFrames(
+                stem_writer.track_action_sender().clone(),
+            ));
+        }
+        Some(stem_writer)
+    }
+
+    fn capture_midi(&self, action: &MidiAction) {
+        let time = self
+            .transport
+            .time_range()
+            .map(|time_range| time_range.0.start)
+            .unwrap_or_default();
+        self.midi_writer.send_input(MidiFileWriterInput::Midi(
+            time,
+            action.channel,
+            action.message,
+        ));
+    }
+
+    /// Catches up on meter readings, track info, and latencies that the
+    /// tracks have reported. The UI calls this every frame; a headless client
+    /// should call it periodically so that the reports don't pile up.
+    pub fn handle_track_actions(&mut self) {
+        self.recording.report_errors();
+        if let Some(stem_writer) = self.stem_writer.as_ref() {
+            while let Ok(WavWriterEvent::Err(e)) = stem_writer.receiver().try_recv() {
+                report_error("While exporting stems", &e);
+            }
+        }
+        while let Ok(MidiFileWriterEvent::Err(e)) = self.midi_writer.receiver().try_recv() {
+            report_error("While exporting MIDI", &e);
+        }
+        while let Ok(action) = self.track_actions.receiver.try_recv() {
+            match action {
+                TrackAction::Meter(track_uid, snapshot) => {
+                    self.meters.entry(track_uid).or_default().update(snapshot);
+                }
+                TrackAction::Info(track_uid, info) => {
+                    self.track_infos.insert(track_uid, info);
+                }
+                TrackAction::Latency(track_uid, latency) => {
+                    if self.tracks.contains_key(&track_uid) {
+                        self.track_latencies.insert(track_uid, latency);
+                        self.update_latency_compensation();
+                    }
+                }
+                TrackAction::Fault(track_uid, fault) => {
+                    let message = format!("{} recovered: {fault}", self.track_name(track_uid));
+                    Notifications::global().post(Severity::Warning, message);
+                }
+                TrackAction::Frames(..) => {}
+            }
+        }
+    }
+
+    /// The most recent levels of the given track.
+    pub fn meter(&self, uid: TrackUid) -> Option<&Meter> {
+        self.meters.get(&uid)
+    }
+
+    fn request_quit(&mut self) {
+        self.stop_stem_export();
+        self.midi_writer.send_input(MidiFileWriterInput::Quit);
+        self.track_subscription.broadcast_mut(TrackRequest::Quit);
+    }
+
+    /// Tells every track and entity to quit, finalizes any stem export, and
+    /// waits up to the timeout for their threads to exit. Calling it again
+    /// does no harm.
+    pub fn shutdown(&mut self, timeout: Duration) -> anyhow::Result<()> {
+        let deadline = Instant::now() + timeout;
+        let stem_writer = self.detach_stem_writer();
+        self.request_quit();
+        let stem_result = match stem_writer {
+            Some(mut stem_writer) => stem_writer.quit_and_join(timeout),
+            None => Ok(()),
+        };
+        let recording_result = self.recording.quit_and_join(&self.tracks, timeout);
+
+        let mut threads = self.master_track.take_threads();
+        for track in self.tracks.values_mut() {
+            threads.extend(track.take_threads());
+        }
+        let running = join_until(threads, deadline);
+        if running > 0 {
+            return Err(anyhow!("{running} actor threads didn't quit within {timeout:?}"));
+        }
+        stem_result.and(recording_result)
+    }
+}
+/// A bulk operation on a track's selected entities, as picked in the UI.
+#[derive(Debug, Clone, Copy)]
+pub enum SelectionAction {
+    Remove,
+    Bypass(bool),
+    Copy,
+    /// Adds copies of the copied entities, whether or not any are selected.
+    Paste,
+    MoveTo(TrackUid),
+}
+
+#[cfg(feature = "gui")]
+impl Engine {
+    /// Lists the control routes that cross tracks. Clicking one removes it.
+    fn ui_control_routes(&self, ui: &mut eframe::egui::Ui, targets: &[(ControlTarget, String)]) {
+        if self.control_routes.is_empty() {
+            return;
+        }
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Control routes");
+            for route in self.control_routes.iter() {
+                let target_name = targets
+                    .iter()
+                    .find(|(target, _)| *target == route.target)
+                    .map_or("(removed)", |(_, name)| name.as_str());
+                let source_name =
+                    format!("{} #{}", self.track_name(route.source_track), route.source);
+                if ui
+                    .button(format!("{source_name} → {target_name}"))
+                    .on_hover_text("Click to remove")
+                    .clicked()
+                {
+                    let _ = self.commands.sender.send(Command::UnrouteControl(*route));
+                }
+            }
+        });
+    }
+
+    /// Recalls and captures scenes, and picks the parameters that they
+    /// capture. Right-clicking a scene captures it again.
+    fn ui_scenes(&mut self, ui: &mut eframe::egui::Ui, targets: &[(ControlTarget, String)]) {
+        CollapsingHeader::new("Scenes").show(ui, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                let numbers: Vec<u8> = self.scenes.numbers().collect();
+                for &number in numbers.iter() {
+                    let response = ui
+                        .button(format!("Scene {number}"))
+                        .on_hover_text("Click to recall, or right-click to capture again");
+                    if response.clicked() {
+                        let morph_seconds = self.scenes.morph_seconds();
+                        if let Err(e) = self.recall_scene(number, morph_seconds) {
+                            report_error("While recalling a scene", &e);
+                        }
+                    } else if response.secondary_clicked() {
+                        self.capture_scene(number);
+                    }
+                }
+                // Program changes reach only scenes 0 through 127.
+                let next = numbers.last().map_or(0, |number| *number as usize + 1);
+                if next < 128 && ui.button("Capture new").clicked() {
+                    self.capture_scene(next as u8);
+                }
+            });
+            let mut morph_seconds = self.scenes.morph_seconds();
+            if ui
+                .add(
+                    Slider::new(&mut morph_seconds, 0.0..=SceneStore::MAX_MORPH_SECONDS)
+                        .text("Morph seconds"),
+                )
+                .changed()
+            {
+                self.scenes.set_morph_seconds(morph_seconds);
+            }
+            CollapsingHeader::new("Captured parameters").show(ui, |ui| {
+                for (target, name) in targets.iter() {
+                    let ControlTarget::Entity(track_uid, uid, index) = *target else {
+                        continue;
+                    };
+                    let param = SceneParam {
+                        track_uid,
+                        uid,
+                        index,
+                    };
+                    let mut is_selected = self.scenes.is_param_selected(param);
+                    if ui.checkbox(&mut is_selected, name).changed() {
+                        self.scenes.select_param(param, is_selected);
+                    }
+                }
+            });
+        });
+    }
+
+    /// Where the metronome's clicks go, and what they sound like.
+    fn ui_click(&mut self, ui: &mut eframe::egui::Ui) {
+        let mut output = self.metronome.output();
+        ComboBox::new(ui.next_auto_id(), "Output")
+            .selected_text(output.to_string())
+            .show_ui(ui, |ui| {
+                for o in ClickOutput::ALL {
+                    ui.selectable_value(&mut output, o, o.to_string());
+                }
+            })
+            .response
+            .on_hover_text("Cue clicks go only to the audio device, not to captures");
+        if output != self.metronome.output() {
+            self.metronome.set_output(output);
+        }
+        let mut sound = None;
+        ComboBox::new(ui.next_auto_id(), "Sound")
+            .selected_text(self.metronome.sound().to_string())
+            .show_ui(ui, |ui| {
+                for s in [ClickSound::Beep, ClickSound::Sidestick] {
+                    if ui.selectable_label(false, s.to_string()).clicked() {
+                        sound = Some(s);
+                    }
+                }
+            });
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.click_sample_path)
+                .on_hover_text("A WAV file to click with");
+            if ui.button("Load").clicked() {
+                let path = Path::new(&self.click_sample_path);
+                match ClickSample::new_from_wav(path) {
+                    Ok(sample) => sound = Some(ClickSound::Sample(sample)),
+                    Err(e) => report_error(&format!("While loading {path:?}"), &e),
+                }
+            }
+        });
+        if let Some(sound) = sound {
+            self.metronome.set_sound(sound);
+        }
+    }
+
+    /// Lists the tempo map's points for editing, and adds new ones at the
+    /// playhead.
+    fn ui_tempo_map(&mut self, ui: &mut eframe::egui::Ui) {
+        CollapsingHeader::new("Tempo map").show(ui, |ui| {
+            let mut point_to_remove = None;
+            let mut point_to_change = None;
+            for point in self.tempo_map.points().iter() {
+                ui.horizontal(|ui| {
+                    let beats = point.time.total_units() as f64 / MusicalTime::UNITS_IN_BEAT as f64;
+                    ui.label(format!("Beat {beats:.2}"));
+                    let mut bpm = point.tempo.0;
+                    let tempo = DragValue::new(&mut bpm)
+                        .clamp_range(20.0..=300.0)
+                        .speed(0.1)
+                        .suffix(" BPM");
+                    if ui.add(tempo).changed() {
+                        point_to_change = Some(TempoPoint {
+                            tempo: Tempo(bpm),
+                            ..*point
+                        });
+                    }
+                    if ui.button("Remove").clicked() {
+                        point_to_remove = Some(point.time);
+                    }
+                });
+            }
+            if let Some(point) = point_to_change {
+                self.tempo_map.insert(point);
+            }
+            if let Some(time) = point_to_remove {
+                self.tempo_map.remove(time);
+            }
+            if ui
+                .button("Add point at playhead")
+                .on_hover_text("The tempo ramps from each point to the next")
+                .clicked()
+            {
+                let point = TempoPoint {
+                    time: self.position(),
+                    tempo: self.tempo(),
+                };
+                self.tempo_map.insert(point);
+            }
+        });
+    }
+
+    /// Shows each track's latency and the delays that line it up with the
+    /// others. Hidden until some track has latency.
+    fn ui_latency_compensation(&self, ui: &mut eframe::egui::Ui) {
+        if self.track_latencies.values().all(|latency| *latency == 0) {
+            return;
+        }
+        let sample_rate = self.sample_rate().0.max(1) as f64;
+        let frames_text = |frames: usize| {
+            let ms = frames as f64 * 1000.0 / sample_rate;
+            format!("{frames} frames ({ms:.2} ms)")
+        };
+        CollapsingHeader::new("Delay compensation").show(ui, |ui| {
+            for &uid in self.ordered_track_uids.iter() {
+                let delays = &self.latency_compensation;
+                ui.label(format!(
+                    "{}: {} late, output delayed {}",
+                    self.track_name(uid),
+                    frames_text(self.track_latency(uid)),
+                    frames_text(delays.outputs.get(&uid).copied().unwrap_or_default())
+                ));
+                let mut sends: Vec<_> = delays
+                    .sends
+                    .iter()
+                    .filter(|((source, _), _)| *source == uid)
+                    .map(|((_, bus_uid), delay)| (self.track_name(*bus_uid), *delay))
+                    .collect();
+                sends.sort();
+                for (bus_name, delay) in sends {
+                    let delay = frames_text(delay);
+                    ui.label(format!("    Send to {bus_name} delayed {delay}"));
+                }
+            }
+        });
+    }
+}
+#[cfg(feature = "gui")]
+impl Displays for Engine {
+    /// Draws the engine-wide settings that aren't in the [EngineSnapshot]'s
+    /// transport bar. The tracks draw from the snapshot instead.
+    fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
+        ui.horizontal_wrapped(|ui| {
+            let mut is_clock_enabled = self.midi_clock.is_enabled();
+            if ui.checkbox(&mut is_clock_enabled, "Send MIDI clock").changed() {
+                self.midi_clock.set_enabled(is_clock_enabled);
+            }
+            if LinkSession::is_available() {
+                let mut is_link_enabled = self.link.is_enabled();
+                if ui.checkbox(&mut is_link_enabled, "Link").changed() {
+                    self.link.set_enabled(is_link_enabled, self.tempo());
+                }
+                if is_link_enabled {
+                    ui.label(format!("{} peers", self.link.peer_count()));
+                }
+                if self.is_waiting_for_link {
+                    ui.label("Waiting for bar...");
+                }
+            }
+            let mut is_metronome_enabled = self.metronome.is_enabled();
+            if ui.checkbox(&mut is_metronome_enabled, "Metronome").changed() {
+                self.metronome.set_enabled(is_metronome_enabled);
+            }
+            let count_in_text = |bars: usize| match bars {
+                0 => "Off".to_string(),
+                1 => "1 bar".to_string(),
+                _ => format!("{bars} bars"),
+            };
+            let mut count_in_bars = self.metronome.count_in_bars();
+            ComboBox::new(ui.next_auto_id(), "Count-in")
+                .selected_text(count_in_text(count_in_bars))
+                .show_ui(ui, |ui| {
+                    for bars in Metronome::COUNT_IN_BARS {
+                        ui.selectable_value(&mut count_in_bars, bars, count_in_text(bars));
+                    }
+                })
+                .response
+                .on_hover_text("Bars of clicks before recording starts");
+            if count_in_bars != self.metronome.count_in_bars() {
+                self.metronome.set_count_in_bars(count_in_bars);
+            }
+            if self.metronome.is_counting_in() {
+                ui.label("Counting in...");
+            }
+            ui.menu_button("Click…", |ui| self.ui_click(ui));
+            ComboBox::new(ui.next_auto_id(), "Import resampling")
+                .selected_text(self.resample_quality.to_string())
+                .show_ui(ui, |ui| {
+                    for quality in ResampleQuality::ALL {
+                        ui.selectable_value(
+                            &mut self.resample_quality,
+                            quality,
+                            quality.to_string(),
+                        );
+                    }
+                });
+            ui.end_row();
+            if ui
+                .button("Export MIDI")
+                .on_hover_text("Write the captured MIDI next to the project")
+                .clicked()
+            {
+                self.export_midi();
+            }
+            if ui.button("Clear MIDI capture").clicked() {
+                self.midi_writer.send_input(MidiFileWriterInput::Clear);
+            }
+            if ui
+                .selectable_label(self.stem_writer.is_some(), "Export stems")
+                .on_hover_text("Write each track to its own file during playback")
+                .clicked()
+            {
+                if self.stem_writer.is_some() {
+                    self.stop_stem_export();
+                } else {
+                    self.start_stem_export(&self.output_directory());
+                }
+            }
+        });
+        let playhead = self.transport.time_range().map(|time_range| time_range.0.start);
+        self.punch.show(ui, playhead);
+        let control_targets = self.control_targets();
+        self.ui_control_routes(ui, &control_targets);
+        self.ui_latency_compensation(ui);
+        self.ui_scenes(ui, &control_targets);
+        self.ui_tempo_map(ui);
+        let response = ui.separator();
+
+        let mut is_spectrum_enabled = self.spectrum_analyzer.is_enabled();
+        if ui.checkbox(&mut is_spectrum_enabled, "Spectrum").changed() {
+            self.spectrum_analyzer.set_enabled(is_spectrum_enabled);
+        }
+        if is_spectrum_enabled {
+            self.spectrum_analyzer.ui(ui);
+        }
         response
     }
 }
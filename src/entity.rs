@@ -1,15 +1,35 @@
 use ensnare_v1::prelude::*;
 use crate::{
     actions::{AudioAction, ControlAction, MidiAction},
+    batch::AudioBatch,
+    buffer_pool::BufferPool,
+    compare::ParameterCompare,
+    executor::{join_on_drop, ActorLoop, ActorStep, Executor},
+    metrics::{time_work, CpuMetrics},
+    plugin::PluginEntity,
+    preset::EntityPresets,
+    registry::EntityLatencyFn,
+    trace::{trace_message, ActorId},
     subscription::Subscription,
     traits::ProvidesActorService,
+    transfer::TransferFunction,
     ATOMIC_ORDERING,
 };
-use crossbeam_channel::{Select, Sender};
+use crossbeam_channel::{Receiver, Select, Sender};
+use derivative::Derivative;
 use ensnare::{prelude::*, types::CrossbeamChannel};
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
     sync::{atomic::AtomicBool, Arc, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+#[cfg(feature = "gui")]
+use {
+    crate::{notification::report_error, piano::Piano},
+    eframe::egui::{CollapsingHeader, ComboBox, DragValue, Slider},
 };
 
 #[derive(Debug, Clone)]
@@ -30,12 +50,33 @@ pub enum EntityRequest {
     ControlLinkAdd(Uid, ControlIndex),
     /// Unlink this entity's controllable parameter from the specified source entity.
     ControlLinkRemove(Uid, ControlIndex),
+    /// Reshape the values that reach the given parameter from the given
+    /// linked source.
+    SetControlTransfer(Uid, ControlIndex, TransferFunction),
     /// The entity should handle this message (if it listens on this channel).
     /// As with [EntityRequest::Work], it can produce [MidiAction] and/or
     /// [ControlAction].
     Midi(MidiChannel, MidiMessage),
+    /// Like [EntityRequest::Midi], but the message came from another entity
+    /// by way of the track. Whatever the entity produces in response is one
+    /// hop further along.
+    RelayedMidi(MidiAction),
     /// The entity should adjust the given control as specified.
     Control(ControlIndex, ControlValue),
+    /// Accept MIDI only on the given channel (Some), or on any channel (None).
+    SetMidiChannel(Option<MidiChannel>),
+    /// Drive the given parameter (Some) with the given kind of MIDI message,
+    /// or stop doing so (None).
+    MapMidiControl(MidiControlSource, Option<ControlIndex>),
+    /// Set the level applied to the entity's audio output.
+    SetGain(Normal),
+    /// Set the stereo position applied to the entity's audio output.
+    SetPan(BipolarNormal),
+    /// The tempo changed.
+    UpdateTempo(Tempo),
+    /// While bypassed, an effect returns [EntityRequest::NeedsTransformation]
+    /// buffers unchanged.
+    SetBypass(bool),
     /// The entity should perform work for the given slice of time. During this
     /// time slice, it can produce any number of [MidiAction] and/or
     /// [ControlAction].
@@ -44,6 +85,9 @@ pub enum EntityRequest {
     /// [EntityAction::Frames]. If it doesn't produce audio, it should produce a
     /// silent buffer.
     NeedsAudio(usize),
+    /// Like [EntityRequest::NeedsAudio], but the entity should write into the
+    /// given slot of the batch instead of sending its frames.
+    NeedsAudioBatch(Arc<AudioBatch>, usize),
     /// The entity should transform the given buffer of audio via
     /// [EntityAction::Transformed]. If it doesn't transform audio, it should
     /// return the buffer unchanged.
@@ -51,12 +95,223 @@ pub enum EntityRequest {
     /// The entity should exit.
     Quit,
 }
+impl EntityRequest {
+    /// The variant's name, for [MessageTrace](crate::trace::MessageTrace).
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            EntityRequest::ActionSubscribe(..) => "ActionSubscribe",
+            EntityRequest::ActionUnsubscribe(..) => "ActionUnsubscribe",
+            EntityRequest::MidiSubscribe(..) => "MidiSubscribe",
+            EntityRequest::MidiUnsubscribe(..) => "MidiUnsubscribe",
+            EntityRequest::ControlSubscribe(..) => "ControlSubscribe",
+            EntityRequest::ControlUnsubscribe(..) => "ControlUnsubscribe",
+            EntityRequest::ControlLinkAdd(..) => "ControlLinkAdd",
+            EntityRequest::ControlLinkRemove(..) => "ControlLinkRemove",
+            EntityRequest::SetControlTransfer(..) => "SetControlTransfer",
+            EntityRequest::Midi(..) => "Midi",
+            EntityRequest::RelayedMidi(..) => "RelayedMidi",
+            EntityRequest::Control(..) => "Control",
+            EntityRequest::SetMidiChannel(..) => "SetMidiChannel",
+            EntityRequest::MapMidiControl(..) => "MapMidiControl",
+            EntityRequest::SetGain(..) => "SetGain",
+            EntityRequest::SetPan(..) => "SetPan",
+            EntityRequest::UpdateTempo(..) => "UpdateTempo",
+            EntityRequest::SetBypass(..) => "SetBypass",
+            EntityRequest::Work(..) => "Work",
+            EntityRequest::NeedsAudio(..) => "NeedsAudio",
+            EntityRequest::NeedsAudioBatch(..) => "NeedsAudioBatch",
+            EntityRequest::NeedsTransformation(..) => "NeedsTransformation",
+            EntityRequest::Quit => "Quit",
+        }
+    }
+
+    /// Whether the request is on the audio path. These go ahead of the
+    /// others, which are mostly from the UI, but keep their order among
+    /// themselves so that MIDI from a block's Work still reaches an
+    /// instrument before that block's NeedsAudio. Subscriptions are here too,
+    /// because an entity added during playback has to know where its audio
+    /// goes before it answers its first NeedsAudio.
+    pub(crate) fn is_audio_path(&self) -> bool {
+        matches!(
+            self,
+            EntityRequest::ActionSubscribe(..)
+                | EntityRequest::ActionUnsubscribe(..)
+                | EntityRequest::MidiSubscribe(..)
+                | EntityRequest::MidiUnsubscribe(..)
+                | EntityRequest::ControlSubscribe(..)
+                | EntityRequest::ControlUnsubscribe(..)
+                | EntityRequest::Midi(..)
+                | EntityRequest::RelayedMidi(..)
+                | EntityRequest::UpdateTempo(..)
+                | EntityRequest::Work(..)
+                | EntityRequest::NeedsAudio(..)
+                | EntityRequest::NeedsAudioBatch(..)
+                | EntityRequest::NeedsTransformation(..)
+                | EntityRequest::Quit
+        )
+    }
+}
+
+/// What an entity does in a track's audio pipeline. A track asks only
+/// generators for audio, and routes its buffer only through transformers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EntityRoles {
+    /// The entity should receive [EntityRequest::NeedsAudio], and its output
+    /// is mixed into the track's buffer.
+    pub generates_audio: bool,
+    /// The entity is part of the track's effects chain and should receive
+    /// [EntityRequest::NeedsTransformation].
+    pub transforms_audio: bool,
+    /// How often the entity should receive [EntityRequest::Work].
+    pub work: WorkSchedule,
+    /// The entity is silent unless it's playing notes, so the track can stop
+    /// asking it for audio once it has been quiet for a while.
+    pub can_suspend: bool,
+}
+impl EntityRoles {
+    pub const INSTRUMENT: Self = Self {
+        generates_audio: true,
+        transforms_audio: false,
+        work: WorkSchedule::EveryBlock,
+        can_suspend: false,
+    };
+    pub const EFFECT: Self = Self {
+        generates_audio: false,
+        transforms_audio: true,
+        work: WorkSchedule::EveryBlock,
+        can_suspend: false,
+    };
+    /// An instrument that sounds only while it's playing notes.
+    pub const MIDI_INSTRUMENT: Self = Self {
+        can_suspend: true,
+        ..Self::INSTRUMENT
+    };
+    /// Controllers only do time-based work and handle MIDI.
+    pub const CONTROLLER: Self = Self {
+        generates_audio: false,
+        transforms_audio: false,
+        work: WorkSchedule::EveryBlock,
+        can_suspend: false,
+    };
+
+    pub const fn with_work(self, work: WorkSchedule) -> Self {
+        Self { work, ..self }
+    }
+}
+
+/// How often a track sends an entity [EntityRequest::Work]. In a large
+/// project, most of the messages in a block are Work that nobody needed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WorkSchedule {
+    /// With every block, for entities that have to stay on the song
+    /// position, such as sequencers.
+    #[default]
+    EveryBlock,
+    /// With every nth block, covering the blocks since the last one. Enough
+    /// for control signals that change slowly.
+    ControlRate(usize),
+    /// Never, for effects that only transform the audio they're given.
+    Never,
+}
+impl WorkSchedule {
+    /// How many blocks a typical control-rate entity waits between Work.
+    pub const CONTROL_RATE_BLOCKS: usize = 4;
+}
+
+/// The gain and pan that [EntityActor] applies to its entity's output before
+/// passing it along.
+#[derive(Debug, Clone, Copy)]
+pub struct InsertParams {
+    gain: Normal,
+    pan: BipolarNormal,
+}
+impl Default for InsertParams {
+    fn default() -> Self {
+        Self {
+            gain: Normal::maximum(),
+            pan: BipolarNormal::from(0.0),
+        }
+    }
+}
+impl InsertParams {
+    fn is_unity(&self) -> bool {
+        self.gain == Normal::maximum() && self.pan.0 == 0.0
+    }
+
+    fn apply(&self, frames: &mut [StereoSample]) {
+        if self.is_unity() {
+            return;
+        }
+        // Simple balance law: center leaves both channels alone, and moving
+        // toward one side attenuates the other.
+        let left = self.gain.0 * (1.0 - self.pan.0).min(1.0);
+        let right = self.gain.0 * (1.0 + self.pan.0).min(1.0);
+        for frame in frames.iter_mut() {
+            *frame = StereoSample(Sample(frame.0 .0 * left), Sample(frame.1 .0 * right));
+        }
+    }
+}
+
+/// A continuous MIDI message that can drive an entity parameter, for entities
+/// that only look at notes themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MidiControlSource {
+    /// A control change message for the given controller number.
+    Controller(u8),
+    PitchBend,
+    /// Channel pressure, also known as aftertouch.
+    ChannelPressure,
+}
+impl MidiControlSource {
+    /// The sources offered in the UI.
+    pub const COMMON: [(Self, &'static str); 3] = [
+        (Self::Controller(1), "Mod wheel"),
+        (Self::PitchBend, "Pitch bend"),
+        (Self::ChannelPressure, "Aftertouch"),
+    ];
+
+    /// If the message is a continuous one, returns its source and its value
+    /// scaled to 0.0..=1.0.
+    pub fn from_message(message: &MidiMessage) -> Option<(Self, ControlValue)> {
+        match message {
+            MidiMessage::Controller { controller, value } => Some((
+                Self::Controller(controller.as_int()),
+                ControlValue(value.as_int() as f64 / 127.0),
+            )),
+            MidiMessage::PitchBend { bend } => Some((
+                Self::PitchBend,
+                ControlValue(bend.0.as_int() as f64 / 16383.0),
+            )),
+            MidiMessage::ChannelAftertouch { vel } => Some((
+                Self::ChannelPressure,
+                ControlValue(vel.as_int() as f64 / 127.0),
+            )),
+            _ => None,
+        }
+    }
+}
 
-#[derive(Debug)]
+/// The settings that [EntityActor] keeps outside the entity itself, as
+/// copied along with the entity.
+#[derive(Debug, Clone)]
+pub(crate) struct EntitySettings {
+    insert_params: InsertParams,
+    is_bypassed: bool,
+    midi_channel: Option<MidiChannel>,
+    midi_control_map: HashMap<MidiControlSource, ControlIndex>,
+}
+
+#[derive(Derivative)]
+#[derivative(Debug)]
 pub struct EntityActor {
     /// Incoming requests to this entity.
     requests: CrossbeamChannel<EntityRequest>,
 
+    /// Requests that aren't on the audio path, such as parameter changes.
+    /// The loop gets to them only when `requests` is empty, so that a burst
+    /// of them can't hold up a block. See [EntityRequest::is_audio_path].
+    ui_requests: CrossbeamChannel<EntityRequest>,
+
     /// This entity's audio subscriptions (actions from other entities).
     audio_actions: CrossbeamChannel<AudioAction>,
 
@@ -71,204 +326,315 @@ pub struct EntityActor {
 
     /// Have we just emitted sound? Used for GUI activity indicators.
     is_sound_active: Arc<AtomicBool>,
+
+    /// Has the entity been silent long enough to stop asking it for audio?
+    /// See [EntityRoles::can_suspend].
+    is_suspended: Arc<AtomicBool>,
+
+    /// Which parts of the audio pipeline this entity takes part in.
+    roles: EntityRoles,
+
+    /// The blocks that a [WorkSchedule::ControlRate] entity hasn't done the
+    /// Work for yet, and how many there are.
+    deferred_work: Option<(TimeRange, usize)>,
+
+    /// The UI's copy of the bypass state.
+    is_bypassed: bool,
+
+    /// The UI's copy of the MIDI channel filter.
+    midi_channel: Option<MidiChannel>,
+
+    /// The UI's copy of the MIDI control map.
+    midi_control_map: HashMap<MidiControlSource, ControlIndex>,
+
+    /// For auditioning instruments.
+    #[cfg(feature = "gui")]
+    piano: Piano,
+
+    /// The UI's copy of the insert parameters. The actor thread has its own,
+    /// updated with [EntityRequest::SetGain] and [EntityRequest::SetPan].
+    insert_params: InsertParams,
+
+    /// Present if the entity came from the
+    /// [EntityRegistry](crate::registry::EntityRegistry).
+    presets: Option<EntityPresets>,
+
+    /// Present if the entity is a plugin, so that the project can save its
+    /// descriptor and state.
+    plugin: Option<Arc<Mutex<PluginEntity>>>,
+
+    /// Present if the entity's output lags its input.
+    #[derivative(Debug = "ignore")]
+    latency_fn: Option<EntityLatencyFn>,
+
+    /// What the user has typed for "Save preset…".
+    #[cfg(feature = "gui")]
+    preset_name: String,
+
+    /// The A/B snapshots of the entity's parameters.
+    compare: ParameterCompare,
+
+    /// The actor's thread, if the executor gave it one.
+    thread: Option<JoinHandle<()>>,
 }
-impl EntityActor {
-    pub(crate) fn new_with(entity: impl Entity + 'static) -> Self {
-        let uid = entity.uid();
-        Self::new_with_wrapped(uid, Arc::new(Mutex::new(entity)))
+impl Drop for EntityActor {
+    fn drop(&mut self) {
+        self.send(EntityRequest::Quit);
+        let name = format!("Entity {}", self.uid);
+        join_on_drop(self.thread.take(), Self::DROP_TIMEOUT, &name);
     }
+}
+impl EntityActor {
+    /// How long dropping the actor waits for its thread to exit.
+    const DROP_TIMEOUT: Duration = Duration::from_secs(1);
 
-    pub(crate) fn new_with_wrapped(uid: Uid, entity: Arc<Mutex<dyn Entity>>) -> Self {
-        let r = Self {
+    /// How many silent buffers in a row suspend an entity that
+    /// [EntityRoles::can_suspend].
+    pub const SUSPEND_AFTER_BLOCKS: usize = 16;
+
+    pub(crate) fn new_with_wrapped(
+        uid: Uid,
+        entity: Arc<Mutex<dyn Entity>>,
+        roles: EntityRoles,
+        executor: &Executor,
+    ) -> Self {
+        let mut r = Self {
             requests: Default::default(),
+            ui_requests: Default::default(),
             audio_actions: Default::default(),
             control_actions: Default::default(),
             uid,
             entity,
             is_sound_active: Default::default(),
+            is_suspended: Default::default(),
+            roles,
+            deferred_work: Default::default(),
+            is_bypassed: Default::default(),
+            midi_channel: Default::default(),
+            midi_control_map: Default::default(),
+            #[cfg(feature = "gui")]
+            piano: Default::default(),
+            insert_params: Default::default(),
+            presets: Default::default(),
+            plugin: Default::default(),
+            latency_fn: Default::default(),
+            #[cfg(feature = "gui")]
+            preset_name: Default::default(),
+            compare: Default::default(),
+            thread: Default::default(),
         };
-        r.start_input_thread();
+        r.thread = r.start_loop(executor);
         r
     }
 
-    fn start_input_thread(&self) {
-        let request_receiver = self.requests.receiver.clone();
-        let mut audio_subscription: Subscription<AudioAction> = Default::default();
-        let mut midi_subscription: Subscription<MidiAction> = Default::default();
-        let mut control_subscription: Subscription<ControlAction> = Default::default();
-        let mut source_uid_to_control_indexes: HashMap<Uid, Vec<ControlIndex>> = Default::default();
-        let entity = Arc::clone(&self.entity);
-        let mut buffer = GenerationBuffer::<StereoSample>::default();
-        let is_sound_active = Arc::clone(&self.is_sound_active);
-        let action_receiver = self.audio_actions.receiver.clone();
-        let control_receiver = self.control_actions.receiver.clone();
-        let uid = self.uid;
-
-        std::thread::spawn(move || {
-            let midi_channel_pair: CrossbeamChannel<MidiAction> = Default::default();
-            let midi_receiver = midi_channel_pair.receiver.clone();
-
-            let mut sel = Select::default();
-            let request_index = sel.recv(&request_receiver);
-            let action_index = sel.recv(&action_receiver);
-            let midi_index = sel.recv(&midi_receiver);
-            let control_index = sel.recv(&control_receiver);
-
-            loop {
-                let operation = sel.select();
-                match operation.index() {
-                    index if index == request_index => {
-                        if let Ok(request) = Self::recv_operation(operation, &request_receiver) {
-                            match request {
-                                EntityRequest::Midi(channel, message) => {
-                                    Self::handle_midi(
-                                        &entity,
-                                        channel,
-                                        message,
-                                        &mut midi_subscription,
-                                    );
-                                }
-                                EntityRequest::Control(index, value) => {
-                                    entity
-                                        .lock()
-                                        .unwrap()
-                                        .control_set_param_by_index(index, value);
-                                }
-                                EntityRequest::NeedsAudio(count) => {
-                                    buffer.resize(count);
-                                    buffer.clear();
-                                    let is_active =
-                                        entity.lock().unwrap().generate(buffer.buffer_mut());
-                                    is_sound_active.store(is_active, ATOMIC_ORDERING);
-                                    audio_subscription.broadcast_mut(AudioAction {
-                                        source_uid: uid,
-                                        frames: buffer.buffer().into(),
-                                    });
-                                }
-                                EntityRequest::Quit => {
-                                    break;
-                                }
-                                EntityRequest::NeedsTransformation(frames) => {
-                                    let count = frames.len();
-                                    buffer.resize(count);
-                                    buffer.buffer_mut().copy_from_slice(&frames);
-                                    entity.lock().unwrap().transform(buffer.buffer_mut());
-                                    audio_subscription.broadcast_mut(AudioAction {
-                                        source_uid: uid,
-                                        frames: buffer.buffer().into(),
-                                    });
-                                }
-                                EntityRequest::Work(time_range) => {
-                                    if let Ok(mut entity) = entity.lock() {
-                                        entity.update_time_range(&time_range);
-                                        entity.work(&mut |event| match event {
-                                            WorkEvent::Midi(channel, message) => {
-                                                midi_subscription.broadcast_mut(MidiAction {
-                                                    source_uid: uid,
-                                                    channel,
-                                                    message,
-                                                });
-                                            }
-                                            WorkEvent::MidiForTrack(_, _, _) => {
-                                                todo!(
-                                                    "This might be obsolete or not applicable here"
-                                                )
-                                            }
-                                            WorkEvent::Control(value) => {
-                                                control_subscription.broadcast_mut(ControlAction {
-                                                    source_uid: uid,
-                                                    value,
-                                                });
-                                            }
-                                        });
-                                    }
-                                }
-                                EntityRequest::ActionSubscribe(sender) => {
-                                    audio_subscription.subscribe(&sender);
-                                }
-                                EntityRequest::ActionUnsubscribe(sender) => {
-                                    audio_subscription.unsubscribe(&sender);
-                                }
-                                EntityRequest::MidiSubscribe(sender) => {
-                                    midi_subscription.subscribe(&sender)
-                                }
-                                EntityRequest::MidiUnsubscribe(sender) => {
-                                    midi_subscription.unsubscribe(&sender)
-                                }
-                                EntityRequest::ControlSubscribe(sender) => {
-                                    control_subscription.subscribe(&sender)
-                                }
-                                EntityRequest::ControlUnsubscribe(sender) => {
-                                    control_subscription.unsubscribe(&sender)
-                                }
-                                EntityRequest::ControlLinkAdd(uid, index) => {
-                                    source_uid_to_control_indexes
-                                        .entry(uid)
-                                        .or_default()
-                                        .push(index)
-                                }
-                                EntityRequest::ControlLinkRemove(uid, index) => {
-                                    if let Some(indexes) =
-                                        source_uid_to_control_indexes.get_mut(&uid)
-                                    {
-                                        indexes.retain(|&i| i != index)
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    index if index == action_index => {
-                        if let Ok(_action) = Self::recv_operation(operation, &action_receiver) {
-                            panic!("this shouldn't happen")
-                        }
-                    }
-                    index if index == midi_index => {
-                        if let Ok(action) = Self::recv_operation(operation, &midi_receiver) {
-                            Self::handle_midi(
-                                &entity,
-                                action.channel,
-                                action.message,
-                                &mut midi_subscription,
-                            )
-                        }
-                    }
-                    index if index == control_index => {
-                        if let Ok(action) = Self::recv_operation(operation, &control_receiver) {
-                            if let Some(indexes) =
-                                source_uid_to_control_indexes.get(&action.source_uid)
-                            {
-                                if let Ok(mut entity) = entity.lock() {
-                                    for &index in indexes {
-                                        entity.control_set_param_by_index(index, action.value)
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    _ => {
-                        panic!("Unexpected select index")
-                    }
-                }
-            }
-        });
+    fn start_loop(&self, executor: &Executor) -> Option<JoinHandle<()>> {
+        executor.start(EntityLoop {
+            uid: self.uid,
+            entity: Arc::clone(&self.entity),
+            requests: self.requests.receiver.clone(),
+            ui_requests: self.ui_requests.receiver.clone(),
+            audio_actions: self.audio_actions.receiver.clone(),
+            midi_actions: Default::default(),
+            control_actions: self.control_actions.receiver.clone(),
+            audio_subscription: Default::default(),
+            midi_subscription: Default::default(),
+            control_subscription: Default::default(),
+            source_uid_to_control_indexes: Default::default(),
+            buffer: Default::default(),
+            is_sound_active: Arc::clone(&self.is_sound_active),
+            is_suspended: Arc::clone(&self.is_suspended),
+            can_suspend: self.roles.can_suspend,
+            silent_blocks: Default::default(),
+            insert_params: self.insert_params,
+            is_bypassed: self.is_bypassed,
+            midi_channel: self.midi_channel,
+            midi_control_map: self.midi_control_map.clone(),
+        })
+    }
+
+    /// Hands over the actor's thread, for joining once it has been told to
+    /// [EntityRequest::Quit].
+    pub(crate) fn take_thread(&mut self) -> Option<JoinHandle<()>> {
+        self.thread.take()
     }
 
     pub(crate) fn send(&self, msg: EntityRequest) {
-        let _ = self.requests.sender.try_send(msg);
+        let channel = if msg.is_audio_path() {
+            &self.requests
+        } else {
+            &self.ui_requests
+        };
+        let _ = channel.sender.try_send(msg);
     }
 
     pub(crate) fn uid(&self) -> Uid {
         self.uid
     }
 
+    pub(crate) fn roles(&self) -> EntityRoles {
+        self.roles
+    }
+
+    /// The [EntityRequest::Work] that this block calls for, if any, given
+    /// the entity's [WorkSchedule]. A control-rate entity's Work covers the
+    /// blocks it skipped, unless the time jumped in between.
+    pub(crate) fn schedule_work(&mut self, time_range: &TimeRange) -> Option<EntityRequest> {
+        let blocks = match self.roles.work {
+            WorkSchedule::EveryBlock => return Some(EntityRequest::Work(time_range.clone())),
+            WorkSchedule::ControlRate(blocks) => blocks,
+            WorkSchedule::Never => return None,
+        };
+        let (start, count) = match self.deferred_work.take() {
+            Some((deferred, count)) if deferred.0.end == time_range.0.start => {
+                (deferred.0.start, count + 1)
+            }
+            _ => (time_range.0.start, 1),
+        };
+        let time_range = TimeRange(start..time_range.0.end);
+        if count >= blocks {
+            Some(EntityRequest::Work(time_range))
+        } else {
+            self.deferred_work = Some((time_range, count));
+            None
+        }
+    }
+
+    pub(crate) fn presets(&self) -> Option<&EntityPresets> {
+        self.presets.as_ref()
+    }
+
+    pub(crate) fn set_presets(&mut self, presets: EntityPresets) {
+        self.presets = Some(presets);
+    }
+
+    pub(crate) fn plugin(&self) -> Option<&Arc<Mutex<PluginEntity>>> {
+        self.plugin.as_ref()
+    }
+
+    pub(crate) fn set_plugin(&mut self, plugin: Arc<Mutex<PluginEntity>>) {
+        self.plugin = Some(plugin);
+    }
+
+    pub(crate) fn set_latency_fn(&mut self, latency_fn: EntityLatencyFn) {
+        self.latency_fn = Some(latency_fn);
+    }
+
+    /// Only effects can be bypassed, so this does nothing to other entities.
+    pub(crate) fn set_bypass(&mut self, is_bypassed: bool) {
+        if self.roles.transforms_audio {
+            self.is_bypassed = is_bypassed;
+            self.send(EntityRequest::SetBypass(is_bypassed));
+        }
+    }
+
+    /// How many frames late the entity's output is. A bypassed effect has
+    /// none, because the audio goes around it.
+    pub(crate) fn latency(&self) -> usize {
+        if self.is_bypassed && self.roles.transforms_audio {
+            return 0;
+        }
+        self.latency_fn.as_ref().map_or(0, |f| f())
+    }
+
+    /// How many requests and actions are waiting for the entity.
+    pub(crate) fn queue_depth(&self) -> usize {
+        self.requests.receiver.len()
+            + self.ui_requests.receiver.len()
+            + self.audio_actions.receiver.len()
+            + self.control_actions.receiver.len()
+    }
+
+    /// Sets one of the entity's parameters, and remembers the value in the
+    /// current A/B snapshot.
+    pub(crate) fn set_param(&mut self, index: ControlIndex, value: ControlValue) {
+        self.compare.record(index, value);
+        self.send(EntityRequest::Control(index, value));
+    }
+
+    /// The parameter's last value, if it was set through
+    /// [EntityActor::set_param] or the A/B snapshots.
+    pub(crate) fn param(&self, index: ControlIndex) -> Option<ControlValue> {
+        self.compare.current(index)
+    }
+
+    /// Gives each of the entity's parameters a random value within its
+    /// range.
+    pub(crate) fn randomize_params(&mut self) {
+        let count = self.entity.lock().unwrap().control_index_count();
+        for (index, value) in self.compare.randomize(count) {
+            self.send(EntityRequest::Control(index, value));
+        }
+    }
+
+    /// Switches between the A and B snapshots of the entity's parameters.
+    pub(crate) fn toggle_compare(&mut self) {
+        for (index, value) in self.compare.toggle() {
+            self.send(EntityRequest::Control(index, value));
+        }
+    }
+
+    pub(crate) fn compare_mut(&mut self) -> &mut ParameterCompare {
+        &mut self.compare
+    }
+
+    /// The names of the entity's parameters, by [ControlIndex].
+    #[cfg(feature = "gui")]
+    fn control_names(&self) -> Vec<String> {
+        let entity = self.entity.lock().unwrap();
+        (0..entity.control_index_count())
+            .map(|i| {
+                entity
+                    .control_name_for_index(i.into())
+                    .map(|name| name.to_string())
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+
+    /// Gives this actor the other one's gain, pan, and bypass settings.
+    pub(crate) fn copy_settings_from(&mut self, other: &EntityActor) {
+        self.apply_settings(&other.settings());
+    }
+
+    pub(crate) fn settings(&self) -> EntitySettings {
+        EntitySettings {
+            insert_params: self.insert_params,
+            is_bypassed: self.is_bypassed,
+            midi_channel: self.midi_channel,
+            midi_control_map: self.midi_control_map.clone(),
+        }
+    }
+
+    pub(crate) fn apply_settings(&mut self, settings: &EntitySettings) {
+        self.insert_params = settings.insert_params;
+        self.is_bypassed = settings.is_bypassed;
+        self.midi_channel = settings.midi_channel;
+        self.midi_control_map = settings.midi_control_map.clone();
+        self.send(EntityRequest::SetGain(self.insert_params.gain));
+        self.send(EntityRequest::SetPan(self.insert_params.pan));
+        self.send(EntityRequest::SetBypass(self.is_bypassed));
+        self.send(EntityRequest::SetMidiChannel(self.midi_channel));
+        for (&source, &index) in self.midi_control_map.iter() {
+            self.send(EntityRequest::MapMidiControl(source, Some(index)));
+        }
+    }
+
     pub(crate) fn is_sound_active(&self) -> bool {
         self.is_sound_active.load(ATOMIC_ORDERING)
     }
 
+    /// Whether the track should skip asking the entity for audio. MIDI and
+    /// parameter changes wake it.
+    pub(crate) fn is_suspended(&self) -> bool {
+        self.is_suspended.load(ATOMIC_ORDERING)
+    }
+
+    /// Anything the entity produces in response is `hops` hops along.
     fn handle_midi(
         entity: &Arc<Mutex<dyn Entity>>,
         channel: MidiChannel,
         message: MidiMessage,
+        hops: usize,
         subscription: &mut Subscription<MidiAction>,
     ) {
         if let Ok(mut entity) = entity.lock() {
@@ -278,6 +644,7 @@ impl EntityActor {
                     source_uid: uid,
                     channel: c,
                     message: m,
+                    hops,
                 });
             });
         }
@@ -286,15 +653,549 @@ impl EntityActor {
     pub(crate) fn control_sender(&self) -> &Sender<ControlAction> {
         &self.control_actions.sender
     }
+
+    /// A preset picker, and a way to save the current settings as a preset.
+    #[cfg(feature = "gui")]
+    fn ui_presets(&mut self, ui: &mut eframe::egui::Ui) {
+        let Some(presets) = self.presets.as_ref() else {
+            return;
+        };
+        ui.horizontal(|ui| {
+            let mut name_to_load = None;
+            ComboBox::new(ui.next_auto_id(), "Preset")
+                .selected_text("Load…")
+                .show_ui(ui, |ui| {
+                    let names = presets.names();
+                    if names.is_empty() {
+                        ui.label("No presets yet");
+                    }
+                    for name in names {
+                        if ui.selectable_label(false, &name).clicked() {
+                            name_to_load = Some(name);
+                        }
+                    }
+                });
+            if let Some(name) = name_to_load {
+                if let Err(e) = presets.load(&name) {
+                    report_error(&format!("While loading preset {name}"), &e);
+                }
+            }
+            ui.menu_button("Save preset…", |ui| {
+                ui.text_edit_singleline(&mut self.preset_name);
+                if ui.button("Save").clicked() {
+                    if let Err(e) = presets.save(&self.preset_name) {
+                        report_error("While saving preset", &e);
+                    }
+                    ui.close_menu();
+                }
+            });
+        });
+    }
+
+    /// A/B switching and randomizing, with the range of random values for
+    /// each parameter.
+    #[cfg(feature = "gui")]
+    fn ui_compare(&mut self, ui: &mut eframe::egui::Ui) {
+        let param_names = self.control_names();
+        if param_names.is_empty() {
+            return;
+        }
+        CollapsingHeader::new("Randomize and compare")
+            .id_source((self.uid, "compare"))
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    let is_b = self.compare.is_b();
+                    let wants_a = ui.selectable_label(!is_b, "A").clicked();
+                    let wants_b = ui.selectable_label(is_b, "B").clicked();
+                    if (wants_a && is_b) || (wants_b && !is_b) {
+                        self.toggle_compare();
+                    }
+                    let other = if is_b { "A" } else { "B" };
+                    if ui.button(format!("Copy to {other}")).clicked() {
+                        self.compare.copy_to_other();
+                    }
+                    if ui.button("Randomize").clicked() {
+                        self.randomize_params();
+                    }
+                });
+                for (i, name) in param_names.iter().enumerate() {
+                    let index = ControlIndex(i);
+                    let (mut low, mut high) = self.compare.range(index);
+                    ui.horizontal(|ui| {
+                        let low_changed = ui
+                            .add(DragValue::new(&mut low).clamp_range(0.0..=1.0).speed(0.01))
+                            .changed();
+                        let high_changed = ui
+                            .add(DragValue::new(&mut high).clamp_range(0.0..=1.0).speed(0.01))
+                            .changed();
+                        ui.label(name);
+                        if low_changed || high_changed {
+                            self.compare.set_range(index, (low, high));
+                        }
+                    });
+                }
+            });
+    }
+
+    /// Lets the user pick which parameter each common MIDI control drives.
+    #[cfg(feature = "gui")]
+    fn ui_midi_control_map(&mut self, ui: &mut eframe::egui::Ui) {
+        let param_names = self.control_names();
+        if param_names.is_empty() {
+            return;
+        }
+        CollapsingHeader::new("MIDI controls")
+            .id_source(self.uid)
+            .show(ui, |ui| {
+                for (source, label) in MidiControlSource::COMMON {
+                    let mut selected_index = self
+                        .midi_control_map
+                        .get(&source)
+                        .map(|index| index.0 + 1)
+                        .unwrap_or_default();
+                    if ComboBox::new(ui.next_auto_id(), label)
+                        .show_index(ui, &mut selected_index, param_names.len() + 1, |i| {
+                            if i == 0 {
+                                "None".to_string()
+                            } else {
+                                param_names[i - 1].clone()
+                            }
+                        })
+                        .changed()
+                    {
+                        let index = if selected_index == 0 {
+                            self.midi_control_map.remove(&source);
+                            None
+                        } else {
+                            let index = ControlIndex(selected_index - 1);
+                            self.midi_control_map.insert(source, index);
+                            Some(index)
+                        };
+                        self.send(EntityRequest::MapMidiControl(source, index));
+                    }
+                }
+            });
+    }
+}
+
+/// The state that an [EntityActor]'s message loop owns.
+struct EntityLoop {
+    uid: Uid,
+    entity: Arc<Mutex<dyn Entity>>,
+    requests: Receiver<EntityRequest>,
+    ui_requests: Receiver<EntityRequest>,
+    audio_actions: Receiver<AudioAction>,
+    /// Nothing sends to this yet.
+    midi_actions: CrossbeamChannel<MidiAction>,
+    control_actions: Receiver<ControlAction>,
+    audio_subscription: Subscription<AudioAction>,
+    midi_subscription: Subscription<MidiAction>,
+    control_subscription: Subscription<ControlAction>,
+    source_uid_to_control_indexes: HashMap<Uid, Vec<(ControlIndex, TransferFunction)>>,
+    buffer: GenerationBuffer<StereoSample>,
+    is_sound_active: Arc<AtomicBool>,
+    is_suspended: Arc<AtomicBool>,
+    can_suspend: bool,
+    /// How many buffers in a row the entity has generated silence.
+    silent_blocks: usize,
+    insert_params: InsertParams,
+    is_bypassed: bool,
+    midi_channel: Option<MidiChannel>,
+    midi_control_map: HashMap<MidiControlSource, ControlIndex>,
+}
+impl ActorLoop for EntityLoop {
+    fn run(mut self) {
+        let request_receiver = self.requests.clone();
+        let ui_request_receiver = self.ui_requests.clone();
+        let action_receiver = self.audio_actions.clone();
+        let midi_receiver = self.midi_actions.receiver.clone();
+        let control_receiver = self.control_actions.clone();
+
+        let mut sel = Select::default();
+        let request_index = sel.recv(&request_receiver);
+        let ui_request_index = sel.recv(&ui_request_receiver);
+        let action_index = sel.recv(&action_receiver);
+        let midi_index = sel.recv(&midi_receiver);
+        let control_index = sel.recv(&control_receiver);
+
+        loop {
+            let operation = sel.select();
+            match operation.index() {
+                index if index == request_index => {
+                    if let Ok(request) = EntityActor::recv_operation(operation, &request_receiver) {
+                        if self.handle_request(request) == ActorStep::Quit {
+                            break;
+                        }
+                    }
+                }
+                index if index == ui_request_index => {
+                    let result = EntityActor::recv_operation(operation, &ui_request_receiver);
+                    if let Ok(request) = result {
+                        // Select picks at random among the ready channels,
+                        // so catch up on the audio path first.
+                        if self.handle_audio_path_requests() == ActorStep::Quit
+                            || self.handle_request(request) == ActorStep::Quit
+                        {
+                            break;
+                        }
+                    }
+                }
+                index if index == action_index => {
+                    if let Ok(action) = EntityActor::recv_operation(operation, &action_receiver) {
+                        self.handle_audio_action(action);
+                    }
+                }
+                index if index == midi_index => {
+                    if let Ok(action) = EntityActor::recv_operation(operation, &midi_receiver) {
+                        self.handle_midi_action(action);
+                    }
+                }
+                index if index == control_index => {
+                    if let Ok(action) = EntityActor::recv_operation(operation, &control_receiver) {
+                        self.handle_control_action(action);
+                    }
+                }
+                index => error!("Entity {}: unexpected select index {index}", self.uid),
+            }
+        }
+    }
+
+    fn step(&mut self) -> ActorStep {
+        if let Ok(request) = self.requests.try_recv() {
+            return self.handle_request(request);
+        }
+        if let Ok(action) = self.audio_actions.try_recv() {
+            self.handle_audio_action(action);
+        } else if let Ok(action) = self.midi_actions.receiver.try_recv() {
+            self.handle_midi_action(action);
+        } else if let Ok(action) = self.control_actions.try_recv() {
+            self.handle_control_action(action);
+        } else if let Ok(request) = self.ui_requests.try_recv() {
+            return self.handle_request(request);
+        } else {
+            return ActorStep::Idle;
+        }
+        ActorStep::Busy
+    }
+}
+impl EntityLoop {
+    /// Handles the audio-path requests that are waiting.
+    fn handle_audio_path_requests(&mut self) -> ActorStep {
+        while let Ok(request) = self.requests.try_recv() {
+            if self.handle_request(request) == ActorStep::Quit {
+                return ActorStep::Quit;
+            }
+        }
+        ActorStep::Busy
+    }
+
+    fn handle_request(&mut self, request: EntityRequest) -> ActorStep {
+        trace_message(ActorId::Unknown, ActorId::Entity(self.uid), request.name());
+        let entity = &self.entity;
+        match request {
+            EntityRequest::Midi(channel, message) => self.handle_midi(channel, message, 0),
+            EntityRequest::RelayedMidi(action) => {
+                self.handle_midi(action.channel, action.message, action.hops + 1)
+            }
+            EntityRequest::SetMidiChannel(channel) => {
+                self.midi_channel = channel;
+            }
+            EntityRequest::MapMidiControl(source, index) => {
+                if let Some(index) = index {
+                    self.midi_control_map.insert(source, index);
+                } else {
+                    self.midi_control_map.remove(&source);
+                }
+            }
+            EntityRequest::Control(index, value) => {
+                entity
+                    .lock()
+                    .unwrap()
+                    .control_set_param_by_index(index, value);
+                self.wake();
+            }
+            EntityRequest::SetGain(gain) => {
+                self.insert_params.gain = gain;
+            }
+            EntityRequest::SetPan(pan) => {
+                self.insert_params.pan = pan;
+            }
+            EntityRequest::UpdateTempo(tempo) => {
+                entity.lock().unwrap().update_tempo(tempo);
+            }
+            EntityRequest::SetBypass(bypass) => {
+                self.is_bypassed = bypass;
+            }
+            EntityRequest::NeedsAudio(count) => {
+                self.buffer.resize(count);
+                self.buffer.clear();
+                let is_active = {
+                    let mut entity = entity.lock().unwrap();
+                    let buffer = self.buffer.buffer_mut();
+                    time_work(ActorId::Entity(self.uid), || entity.generate(buffer))
+                };
+                self.insert_params.apply(self.buffer.buffer_mut());
+                self.is_sound_active.store(is_active, ATOMIC_ORDERING);
+                self.note_activity(is_active);
+                self.audio_subscription.broadcast_mut(AudioAction {
+                    source_uid: self.uid,
+                    frames: BufferPool::global().take_copy(self.buffer.buffer()),
+                    other_pairs: Default::default(),
+                });
+            }
+            EntityRequest::NeedsAudioBatch(batch, index) => {
+                let mut frames = batch.slot(index);
+                let is_active = {
+                    let mut entity = entity.lock().unwrap();
+                    time_work(ActorId::Entity(self.uid), || entity.generate(&mut frames))
+                };
+                self.insert_params.apply(&mut frames);
+                self.is_sound_active.store(is_active, ATOMIC_ORDERING);
+                self.note_activity(is_active);
+                drop(frames);
+                batch.finish(self.uid);
+            }
+            EntityRequest::Quit => {
+                debug!("Entity {} quit", self.uid);
+                return ActorStep::Quit;
+            }
+            EntityRequest::NeedsTransformation(mut frames) => {
+                // Transform in place and send the same buffer back.
+                if !self.is_bypassed {
+                    let mut entity = entity.lock().unwrap();
+                    time_work(ActorId::Entity(self.uid), || entity.transform(&mut frames));
+                    self.insert_params.apply(&mut frames);
+                }
+                self.audio_subscription.broadcast_mut(AudioAction {
+                    source_uid: self.uid,
+                    frames,
+                    other_pairs: Default::default(),
+                });
+            }
+            EntityRequest::Work(time_range) => {
+                let uid = self.uid;
+                let midi_subscription = &mut self.midi_subscription;
+                let control_subscription = &mut self.control_subscription;
+                if let Ok(mut entity) = entity.lock() {
+                    entity.update_time_range(&time_range);
+                    let started_at = Instant::now();
+                    entity.work(&mut |event| match event {
+                        WorkEvent::Midi(channel, message) => {
+                            midi_subscription.broadcast_mut(MidiAction {
+                                source_uid: uid,
+                                channel,
+                                message,
+                                hops: 0,
+                            });
+                        }
+                        WorkEvent::MidiForTrack(track_uid, ..) => {
+                            // Entities reach other tracks only through
+                            // their own track's routing.
+                            error!("Entity {uid}: dropping MIDI addressed to track {track_uid}");
+                        }
+                        WorkEvent::Control(value) => {
+                            control_subscription.broadcast_mut(ControlAction {
+                                source_uid: uid,
+                                value,
+                            });
+                        }
+                    });
+                    CpuMetrics::global().record(ActorId::Entity(uid), started_at.elapsed());
+                }
+            }
+            EntityRequest::ActionSubscribe(sender) => {
+                self.audio_subscription.subscribe(&sender);
+            }
+            EntityRequest::ActionUnsubscribe(sender) => {
+                self.audio_subscription.unsubscribe(&sender);
+            }
+            EntityRequest::MidiSubscribe(sender) => self.midi_subscription.subscribe(&sender),
+            EntityRequest::MidiUnsubscribe(sender) => self.midi_subscription.unsubscribe(&sender),
+            EntityRequest::ControlSubscribe(sender) => self.control_subscription.subscribe(&sender),
+            EntityRequest::ControlUnsubscribe(sender) => {
+                self.control_subscription.unsubscribe(&sender)
+            }
+            EntityRequest::ControlLinkAdd(uid, index) => self
+                .source_uid_to_control_indexes
+                .entry(uid)
+                .or_default()
+                .push((index, TransferFunction::default())),
+            EntityRequest::ControlLinkRemove(uid, index) => {
+                if let Some(indexes) = self.source_uid_to_control_indexes.get_mut(&uid) {
+                    indexes.retain(|&(i, _)| i != index)
+                }
+            }
+            EntityRequest::SetControlTransfer(uid, index, transfer) => {
+                if let Some(indexes) = self.source_uid_to_control_indexes.get_mut(&uid) {
+                    for (_, t) in indexes.iter_mut().filter(|(i, _)| *i == index) {
+                        *t = transfer;
+                    }
+                }
+            }
+        }
+        ActorStep::Busy
+    }
+
+    /// Counts silent buffers, and suspends the entity once there have been
+    /// enough of them in a row.
+    fn note_activity(&mut self, is_active: bool) {
+        if !self.can_suspend {
+            return;
+        }
+        if is_active {
+            self.silent_blocks = 0;
+        } else {
+            self.silent_blocks += 1;
+        }
+        let is_suspended = self.silent_blocks >= EntityActor::SUSPEND_AFTER_BLOCKS;
+        self.is_suspended.store(is_suspended, ATOMIC_ORDERING);
+    }
+
+    fn wake(&mut self) {
+        self.silent_blocks = 0;
+        self.is_suspended.store(false, ATOMIC_ORDERING);
+    }
+
+    /// Entities don't subscribe to audio, so nothing should arrive here.
+    fn handle_audio_action(&mut self, action: AudioAction) {
+        let source_uid = action.source_uid;
+        error!("Entity {}: ignoring audio from {source_uid}", self.uid);
+        action.recycle();
+    }
+
+    fn handle_midi_action(&mut self, action: MidiAction) {
+        let source = ActorId::Entity(action.source_uid);
+        trace_message(source, ActorId::Entity(self.uid), "Midi");
+        self.wake();
+        EntityActor::handle_midi(
+            &self.entity,
+            action.channel,
+            action.message,
+            action.hops + 1,
+            &mut self.midi_subscription,
+        )
+    }
+
+    fn handle_midi(&mut self, channel: MidiChannel, message: MidiMessage, hops: usize) {
+        if self.midi_channel.is_some_and(|c| c != channel) {
+            return;
+        }
+        self.wake();
+        if let Some((source, value)) = MidiControlSource::from_message(&message) {
+            if let Some(&index) = self.midi_control_map.get(&source) {
+                self.entity
+                    .lock()
+                    .unwrap()
+                    .control_set_param_by_index(index, value);
+            }
+        }
+        EntityActor::handle_midi(
+            &self.entity,
+            channel,
+            message,
+            hops,
+            &mut self.midi_subscription,
+        );
+    }
+
+    fn handle_control_action(&mut self, action: ControlAction) {
+        let source = ActorId::Entity(action.source_uid);
+        trace_message(source, ActorId::Entity(self.uid), "Control");
+        self.wake();
+        if let Some(indexes) = self.source_uid_to_control_indexes.get(&action.source_uid) {
+            if let Ok(mut entity) = self.entity.lock() {
+                for (index, transfer) in indexes {
+                    entity.control_set_param_by_index(*index, transfer.apply(action.value))
+                }
+            }
+        }
+    }
 }
 
 impl ProvidesActorService<EntityRequest, AudioAction> for EntityActor {
+    /// The audio-path channel. Use it only for requests that
+    /// [EntityRequest::is_audio_path] accepts.
     fn sender(&self) -> &Sender<EntityRequest> {
         &self.requests.sender
     }
+
+    fn send_request(&self, request: EntityRequest) {
+        self.send(request);
+    }
 }
+#[cfg(feature = "gui")]
 impl Displays for EntityActor {
     fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
-        self.entity.lock().unwrap().ui(ui)
+        let response = self.entity.lock().unwrap().ui(ui);
+        if response.changed() {
+            // The change might make it sound. If not, it goes back to sleep
+            // after its next silent buffer.
+            self.is_suspended.store(false, ATOMIC_ORDERING);
+        }
+        if let Some(usage) = CpuMetrics::global().usage(ActorId::Entity(self.uid)) {
+            ui.label(format!("CPU: {usage:.1}%"));
+        }
+        if self.is_suspended() {
+            ui.label("Suspended");
+        }
+        self.ui_presets(ui);
+
+        let mut gain = self.insert_params.gain.0;
+        if ui.add(Slider::new(&mut gain, Normal::range()).text("Gain")).changed() {
+            self.insert_params.gain = Normal::from(gain);
+            self.send(EntityRequest::SetGain(self.insert_params.gain));
+        }
+        let mut pan = self.insert_params.pan.0;
+        if ui.add(Slider::new(&mut pan, -1.0..=1.0).text("Pan")).changed() {
+            self.insert_params.pan = BipolarNormal::from(pan);
+            self.send(EntityRequest::SetPan(self.insert_params.pan));
+        }
+        if self.roles.transforms_audio && ui.checkbox(&mut self.is_bypassed, "Bypass").changed() {
+            self.send(EntityRequest::SetBypass(self.is_bypassed));
+        }
+        if ui_midi_channel(ui, "MIDI channel", &mut self.midi_channel) {
+            self.send(EntityRequest::SetMidiChannel(self.midi_channel));
+        }
+        self.ui_midi_control_map(ui);
+        self.ui_compare(ui);
+        if self.roles.generates_audio {
+            // This goes straight to the entity, skipping the track and the
+            // rest of its entities.
+            let (_, messages) = self.piano.show(ui);
+            let channel = self.midi_channel.unwrap_or_default();
+            for message in messages {
+                self.send(EntityRequest::Midi(channel, message));
+            }
+        }
+
+        response
+    }
+}
+
+/// A picker for an optional MIDI channel, where None means any channel.
+/// Returns true if the selection changed.
+#[cfg(feature = "gui")]
+pub(crate) fn ui_midi_channel(
+    ui: &mut eframe::egui::Ui,
+    label: &str,
+    channel: &mut Option<MidiChannel>,
+) -> bool {
+    let mut index = channel.map(|c| c.0 as usize + 1).unwrap_or_default();
+    let changed = ComboBox::new(ui.next_auto_id(), label)
+        .show_index(ui, &mut index, 17, |i| {
+            if i == 0 {
+                "Omni".to_string()
+            } else {
+                i.to_string()
+            }
+        })
+        .changed();
+    if changed {
+        *channel = if index == 0 {
+            None
+        } else {
+            Some(MidiChannel((index - 1) as u8))
+        };
     }
+    changed
 }
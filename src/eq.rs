@@ -0,0 +1,361 @@
+#[cfg(feature = "gui")]
+use eframe::{
+    egui::{Sense, Slider, Stroke},
+    epaint::{pos2, vec2, Color32},
+};
+use ensnare::prelude::*;
+use ensnare_proc_macros::{Control, IsEntity, Metadata};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+const MIN_FREQUENCY: f64 = 20.0;
+const MAX_FREQUENCY: f64 = 20000.0;
+const MAX_GAIN_DB: f64 = 18.0;
+const MIN_Q: f64 = 0.1;
+const MAX_Q: f64 = 10.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BiquadKind {
+    LowShelf,
+    Peaking,
+    HighShelf,
+}
+
+/// A second-order IIR filter with coefficients from the RBJ Audio EQ
+/// Cookbook.
+#[derive(Debug, Clone)]
+struct Biquad {
+    kind: BiquadKind,
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+
+    /// Per-channel history: x[n-1], x[n-2], y[n-1], y[n-2].
+    state: [[f64; 4]; 2],
+}
+impl Biquad {
+    fn new_with(kind: BiquadKind) -> Self {
+        Self {
+            kind,
+            b0: 1.0,
+            b1: 0.0,
+            b2: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+            state: Default::default(),
+        }
+    }
+
+    fn update(&mut self, sample_rate: SampleRate, frequency: f64, gain_db: f64, q: f64) {
+        let a = 10.0f64.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * frequency / sample_rate.0 as f64;
+        let (sin_w0, cos_w0) = w0.sin_cos();
+        let alpha = sin_w0 / (2.0 * q);
+        let (b0, b1, b2, a0, a1, a2) = match self.kind {
+            BiquadKind::Peaking => (
+                1.0 + alpha * a,
+                -2.0 * cos_w0,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos_w0,
+                1.0 - alpha / a,
+            ),
+            BiquadKind::LowShelf => {
+                let k = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) - (a - 1.0) * cos_w0 + k),
+                    2.0 * a * ((a - 1.0) - (a + 1.0) * cos_w0),
+                    a * ((a + 1.0) - (a - 1.0) * cos_w0 - k),
+                    (a + 1.0) + (a - 1.0) * cos_w0 + k,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cos_w0),
+                    (a + 1.0) + (a - 1.0) * cos_w0 - k,
+                )
+            }
+            BiquadKind::HighShelf => {
+                let k = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) + (a - 1.0) * cos_w0 + k),
+                    -2.0 * a * ((a - 1.0) + (a + 1.0) * cos_w0),
+                    a * ((a + 1.0) + (a - 1.0) * cos_w0 - k),
+                    (a + 1.0) - (a - 1.0) * cos_w0 + k,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cos_w0),
+                    (a + 1.0) - (a - 1.0) * cos_w0 - k,
+                )
+            }
+        };
+        self.b0 = b0 / a0;
+        self.b1 = b1 / a0;
+        self.b2 = b2 / a0;
+        self.a1 = a1 / a0;
+        self.a2 = a2 / a0;
+    }
+
+    fn process(&mut self, channel: usize, x: f64) -> f64 {
+        let [x1, x2, y1, y2] = self.state[channel];
+        let y = self.b0 * x + self.b1 * x1 + self.b2 * x2 - self.a1 * y1 - self.a2 * y2;
+        self.state[channel] = [x, x1, y, y1];
+        y
+    }
+
+    /// The filter's gain in dB at the given frequency.
+    #[cfg(feature = "gui")]
+    fn magnitude_db(&self, frequency: f64, sample_rate: SampleRate) -> f64 {
+        // Evaluate H(z) at z = e^(jw).
+        let w = 2.0 * PI * frequency / sample_rate.0 as f64;
+        let (sin_w, cos_w) = w.sin_cos();
+        let (sin_2w, cos_2w) = (2.0 * w).sin_cos();
+        let num_re = self.b0 + self.b1 * cos_w + self.b2 * cos_2w;
+        let num_im = -(self.b1 * sin_w + self.b2 * sin_2w);
+        let den_re = 1.0 + self.a1 * cos_w + self.a2 * cos_2w;
+        let den_im = -(self.a1 * sin_w + self.a2 * sin_2w);
+        let magnitude_squared =
+            (num_re * num_re + num_im * num_im) / (den_re * den_re + den_im * den_im);
+        10.0 * magnitude_squared.log10()
+    }
+}
+
+/// A three-band parametric equalizer: a low shelf, a peaking band, and a high
+/// shelf.
+///
+/// Each band's controls are [Normal]s so that they work with control links.
+/// Frequency maps logarithmically onto 20Hz-20kHz, gain linearly onto
+/// +/-18dB, and Q logarithmically onto 0.1-10.
+#[derive(Debug, Control, IsEntity, Metadata, Serialize, Deserialize)]
+#[entity(Controls, GeneratesStereoSample)]
+pub struct ParametricEq {
+    uid: Uid,
+
+    #[control]
+    low_frequency: Normal,
+    #[control]
+    low_gain: Normal,
+    #[control]
+    low_q: Normal,
+    #[control]
+    mid_frequency: Normal,
+    #[control]
+    mid_gain: Normal,
+    #[control]
+    mid_q: Normal,
+    #[control]
+    high_frequency: Normal,
+    #[control]
+    high_gain: Normal,
+    #[control]
+    high_q: Normal,
+
+    #[serde(skip)]
+    sample_rate: SampleRate,
+    #[serde(skip, default = "ParametricEq::new_bands")]
+    bands: [Biquad; 3],
+}
+impl Default for ParametricEq {
+    fn default() -> Self {
+        let mut r = Self {
+            uid: Default::default(),
+            low_frequency: Self::frequency_to_normal(100.0),
+            low_gain: Self::gain_to_normal(0.0),
+            low_q: Self::q_to_normal(0.707),
+            mid_frequency: Self::frequency_to_normal(1000.0),
+            mid_gain: Self::gain_to_normal(0.0),
+            mid_q: Self::q_to_normal(0.707),
+            high_frequency: Self::frequency_to_normal(8000.0),
+            high_gain: Self::gain_to_normal(0.0),
+            high_q: Self::q_to_normal(0.707),
+            sample_rate: Default::default(),
+            bands: Self::new_bands(),
+        };
+        r.update_bands();
+        r
+    }
+}
+impl TransformsAudio for ParametricEq {
+    fn transform(&mut self, samples: &mut [StereoSample]) {
+        for sample in samples {
+            *sample = StereoSample(
+                self.transform_channel(0, sample.0),
+                self.transform_channel(1, sample.1),
+            )
+        }
+    }
+
+    fn transform_channel(&mut self, channel: usize, input_sample: Sample) -> Sample {
+        Sample(
+            self.bands
+                .iter_mut()
+                .fold(input_sample.0, |x, band| band.process(channel, x)),
+        )
+    }
+}
+impl Serializable for ParametricEq {
+    fn after_deser(&mut self) {
+        self.update_bands();
+    }
+}
+impl HandlesMidi for ParametricEq {}
+impl Configurable for ParametricEq {
+    fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate;
+        self.update_bands();
+    }
+}
+#[cfg(feature = "gui")]
+impl Displays for ParametricEq {
+    fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
+        let response = self.ui_response_curve(ui);
+        let mut changed = false;
+        for (name, frequency, gain, q) in [
+            ("Low", &mut self.low_frequency, &mut self.low_gain, &mut self.low_q),
+            ("Mid", &mut self.mid_frequency, &mut self.mid_gain, &mut self.mid_q),
+            ("High", &mut self.high_frequency, &mut self.high_gain, &mut self.high_q),
+        ] {
+            ui.label(name);
+            changed |= Self::ui_normal(ui, frequency, "Freq");
+            changed |= Self::ui_normal(ui, gain, "Gain");
+            changed |= Self::ui_normal(ui, q, "Q");
+        }
+        if changed {
+            self.update_bands();
+        }
+        response
+    }
+}
+#[cfg(not(feature = "gui"))]
+impl Displays for ParametricEq {}
+impl ParametricEq {
+    fn new_bands() -> [Biquad; 3] {
+        [
+            Biquad::new_with(BiquadKind::LowShelf),
+            Biquad::new_with(BiquadKind::Peaking),
+            Biquad::new_with(BiquadKind::HighShelf),
+        ]
+    }
+
+    fn update_bands(&mut self) {
+        let params = [
+            (self.low_frequency, self.low_gain, self.low_q),
+            (self.mid_frequency, self.mid_gain, self.mid_q),
+            (self.high_frequency, self.high_gain, self.high_q),
+        ];
+        for (band, (frequency, gain, q)) in self.bands.iter_mut().zip(params) {
+            band.update(
+                self.sample_rate,
+                Self::normal_to_frequency(frequency),
+                Self::normal_to_gain(gain),
+                Self::normal_to_q(q),
+            );
+        }
+    }
+
+    fn frequency_to_normal(frequency: f64) -> Normal {
+        Normal::from((frequency / MIN_FREQUENCY).ln() / (MAX_FREQUENCY / MIN_FREQUENCY).ln())
+    }
+
+    fn normal_to_frequency(value: Normal) -> f64 {
+        MIN_FREQUENCY * (MAX_FREQUENCY / MIN_FREQUENCY).powf(value.0)
+    }
+
+    fn gain_to_normal(gain_db: f64) -> Normal {
+        Normal::from((gain_db + MAX_GAIN_DB) / (2.0 * MAX_GAIN_DB))
+    }
+
+    fn normal_to_gain(value: Normal) -> f64 {
+        value.0 * 2.0 * MAX_GAIN_DB - MAX_GAIN_DB
+    }
+
+    fn q_to_normal(q: f64) -> Normal {
+        Normal::from((q / MIN_Q).ln() / (MAX_Q / MIN_Q).ln())
+    }
+
+    fn normal_to_q(value: Normal) -> f64 {
+        MIN_Q * (MAX_Q / MIN_Q).powf(value.0)
+    }
+
+    #[cfg(feature = "gui")]
+    fn ui_normal(ui: &mut eframe::egui::Ui, value: &mut Normal, label: &str) -> bool {
+        let mut v = value.0;
+        let changed = ui.add(Slider::new(&mut v, Normal::range()).text(label)).changed();
+        if changed {
+            value.set(v);
+        }
+        changed
+    }
+
+    /// Draws the combined frequency response of all bands on a log-frequency
+    /// axis.
+    #[cfg(feature = "gui")]
+    fn ui_response_curve(&self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
+        let (response, painter) = ui.allocate_painter(vec2(256.0, 96.0), Sense::hover());
+        let rect = response.rect;
+        painter.rect_stroke(rect, 0.0, Stroke::new(0.5, Color32::GRAY));
+        painter.hline(rect.x_range(), rect.center().y, Stroke::new(0.5, Color32::DARK_GRAY));
+
+        let points = (0..=rect.width() as usize)
+            .map(|x| {
+                let fraction = x as f64 / rect.width() as f64;
+                let frequency = Self::normal_to_frequency(Normal::from(fraction));
+                let gain_db: f64 = self
+                    .bands
+                    .iter()
+                    .map(|band| band.magnitude_db(frequency, self.sample_rate))
+                    .sum();
+                let y = rect.center().y
+                    - (gain_db / MAX_GAIN_DB).clamp(-1.0, 1.0) as f32 * rect.height() / 2.0;
+                pos2(rect.left() + x as f32, y)
+            })
+            .collect();
+        painter.line(points, Stroke::new(1.0, Color32::LIGHT_GREEN));
+        response
+    }
+
+    fn set_low_frequency(&mut self, value: Normal) {
+        self.low_frequency = value;
+        self.update_bands();
+    }
+
+    fn set_low_gain(&mut self, value: Normal) {
+        self.low_gain = value;
+        self.update_bands();
+    }
+
+    fn set_low_q(&mut self, value: Normal) {
+        self.low_q = value;
+        self.update_bands();
+    }
+
+    fn set_mid_frequency(&mut self, value: Normal) {
+        self.mid_frequency = value;
+        self.update_bands();
+    }
+
+    fn set_mid_gain(&mut self, value: Normal) {
+        self.mid_gain = value;
+        self.update_bands();
+    }
+
+    fn set_mid_q(&mut self, value: Normal) {
+        self.mid_q = value;
+        self.update_bands();
+    }
+
+    fn set_high_frequency(&mut self, value: Normal) {
+        self.high_frequency = value;
+        self.update_bands();
+    }
+
+    fn set_high_gain(&mut self, value: Normal) {
+        self.high_gain = value;
+        self.update_bands();
+    }
+
+    fn set_high_q(&mut self, value: Normal) {
+        self.high_q = value;
+        self.update_bands();
+    }
+}
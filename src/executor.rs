@@ -0,0 +1,287 @@
+use crate::limits::ActorLimits;
+use crossbeam_deque::{Injector, Steal, Stealer, Worker};
+use derivative::Derivative;
+use log::warn;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use std::{
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// What an actor did when asked to handle one waiting message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActorStep {
+    /// It handled a message.
+    Busy,
+    /// Nothing was waiting.
+    Idle,
+    /// It handled [Quit](crate::entity::EntityRequest::Quit), and won't run
+    /// again.
+    Quit,
+}
+
+/// An actor's message loop, which an [Executor] runs.
+pub(crate) trait ActorLoop: Send + 'static {
+    /// Handles messages until the actor quits, blocking while none are
+    /// waiting.
+    fn run(self)
+    where
+        Self: Sized;
+
+    /// Handles at most one waiting message without blocking. When several
+    /// channels have something waiting, it always picks them in the same
+    /// order.
+    fn step(&mut self) -> ActorStep;
+}
+
+/// Decides where actors run.
+#[derive(Debug, Clone, Default)]
+pub enum Executor {
+    /// Each actor gets its own thread.
+    #[default]
+    Threaded,
+    /// Actors run only inside [SyncExecutor::run_until_idle].
+    Synchronous(SyncExecutor),
+    /// Actors share a fixed set of worker threads.
+    Pool(PoolExecutor),
+}
+impl Executor {
+    pub fn new_synchronous() -> Self {
+        Self::Synchronous(Default::default())
+    }
+
+    pub fn new_pool(worker_count: usize) -> Self {
+        Self::Pool(PoolExecutor::new_with(worker_count))
+    }
+
+    /// Starts running the actor. Returns its thread, if it got one of its
+    /// own.
+    pub(crate) fn start(&self, actor: impl ActorLoop) -> Option<JoinHandle<()>> {
+        match self {
+            Executor::Threaded => {
+                let limits = ActorLimits::global();
+                limits.thread_started();
+                return Some(std::thread::spawn(move || {
+                    actor.run();
+                    limits.thread_ended();
+                }));
+            }
+            Executor::Synchronous(executor) => executor.add(actor),
+            Executor::Pool(executor) => executor.add(actor),
+        }
+        None
+    }
+}
+
+/// Waits for the threads to exit, but not past the deadline. Returns how many
+/// were still running.
+pub(crate) fn join_until(
+    threads: impl IntoIterator<Item = JoinHandle<()>>,
+    deadline: Instant,
+) -> usize {
+    let mut running = 0;
+    for thread in threads {
+        while !thread.is_finished() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        if thread.is_finished() {
+            let _ = thread.join();
+        } else {
+            running += 1;
+        }
+    }
+    running
+}
+
+/// For [Drop] impls: waits up to the timeout for the thread, if there is one,
+/// and logs if it's still running. Does nothing when called on the thread
+/// itself, which can't wait for its own exit.
+pub(crate) fn join_on_drop(thread: Option<JoinHandle<()>>, timeout: Duration, name: &str) {
+    let Some(thread) = thread else {
+        return;
+    };
+    if thread.thread().id() == std::thread::current().id() {
+        return;
+    }
+    if join_until(Some(thread), Instant::now() + timeout) > 0 {
+        warn!("{name} didn't quit within {timeout:?}");
+    }
+}
+
+type ActorTask = Box<dyn FnMut() -> ActorStep + Send>;
+
+/// Runs actors on the caller's thread, one message at a time, visiting them
+/// in the order they were started. Given the same requests, it handles
+/// everything in the same order every time, so the output is identical from
+/// run to run. Good for tests and offline rendering.
+#[derive(Derivative, Clone, Default)]
+#[derivative(Debug)]
+pub struct SyncExecutor {
+    #[derivative(Debug = "ignore")]
+    actors: Arc<Mutex<Vec<ActorTask>>>,
+    /// Present if the actors are visited in a random order instead.
+    #[derivative(Debug = "ignore")]
+    shuffle: Option<Arc<Mutex<StdRng>>>,
+}
+impl SyncExecutor {
+    /// An executor that visits the actors in a different random order on
+    /// each pass, so that their messages interleave the way they might on
+    /// threads. The same seed gives the same order every time.
+    pub fn new_shuffled(seed: u64) -> Self {
+        Self {
+            shuffle: Some(Arc::new(Mutex::new(StdRng::seed_from_u64(seed)))),
+            ..Default::default()
+        }
+    }
+
+    fn add(&self, mut actor: impl ActorLoop) {
+        self.actors
+            .lock()
+            .unwrap()
+            .push(Box::new(move || actor.step()));
+    }
+
+    /// Lets every actor handle its waiting messages, and whatever those
+    /// messages cause, until nothing is left.
+    pub fn run_until_idle(&self) {
+        loop {
+            // Take the actors out so that any started along the way can add
+            // themselves.
+            let mut actors = std::mem::take(&mut *self.actors.lock().unwrap());
+            if let Some(rng) = self.shuffle.as_ref() {
+                actors.shuffle(&mut *rng.lock().unwrap());
+            }
+            let mut is_busy = false;
+            actors.retain_mut(|actor| match actor() {
+                ActorStep::Busy => {
+                    is_busy = true;
+                    true
+                }
+                ActorStep::Idle => true,
+                ActorStep::Quit => {
+                    is_busy = true;
+                    false
+                }
+            });
+            let mut guard = self.actors.lock().unwrap();
+            let started = std::mem::take(&mut *guard);
+            is_busy |= !started.is_empty();
+            actors.extend(started);
+            *guard = actors;
+            if !is_busy {
+                break;
+            }
+        }
+    }
+}
+
+/// Runs actors as tasks on a fixed number of worker threads, so that a
+/// project with hundreds of entities doesn't need hundreds of threads. Each
+/// worker keeps its own queue of actors, and steals from the others when its
+/// queue is empty.
+///
+/// Actors' channels don't tell the pool when a message arrives, so workers
+/// poll their actors, and sleep briefly when none of them has anything to
+/// do. The worker threads live as long as the process.
+#[derive(Derivative, Clone)]
+#[derivative(Debug)]
+pub struct PoolExecutor {
+    #[derivative(Debug = "ignore")]
+    injector: Arc<Injector<ActorTask>>,
+    worker_count: usize,
+}
+impl PoolExecutor {
+    /// How many messages an actor can handle before its worker moves on to
+    /// the next one.
+    const BATCH_SIZE: usize = 16;
+    /// How many idle actors in a row a worker visits before sleeping.
+    const IDLE_VISITS_BEFORE_SLEEP: usize = 64;
+    const IDLE_SLEEP: Duration = Duration::from_micros(100);
+
+    pub fn new_with(worker_count: usize) -> Self {
+        let worker_count = worker_count.max(1);
+        let injector: Arc<Injector<ActorTask>> = Default::default();
+        let workers: Vec<Worker<ActorTask>> =
+            (0..worker_count).map(|_| Worker::new_fifo()).collect();
+        let stealers: Arc<Vec<Stealer<ActorTask>>> =
+            Arc::new(workers.iter().map(|w| w.stealer()).collect());
+        for (index, worker) in workers.into_iter().enumerate() {
+            let injector = Arc::clone(&injector);
+            let stealers = Arc::clone(&stealers);
+            std::thread::Builder::new()
+                .name(format!("actor-pool-{index}"))
+                .spawn(move || Self::work(worker, &injector, &stealers))
+                .expect("failed to spawn an actor pool worker");
+        }
+        Self {
+            injector,
+            worker_count,
+        }
+    }
+
+    pub fn worker_count(&self) -> usize {
+        self.worker_count
+    }
+
+    fn add(&self, mut actor: impl ActorLoop) {
+        self.injector.push(Box::new(move || actor.step()));
+    }
+
+    fn work(
+        local: Worker<ActorTask>,
+        injector: &Injector<ActorTask>,
+        stealers: &[Stealer<ActorTask>],
+    ) {
+        let mut idle_visits = 0;
+        loop {
+            let Some(mut task) = Self::find_task(&local, injector, stealers) else {
+                std::thread::sleep(Self::IDLE_SLEEP);
+                continue;
+            };
+            let mut is_busy = false;
+            let mut has_quit = false;
+            for _ in 0..Self::BATCH_SIZE {
+                match task() {
+                    ActorStep::Busy => is_busy = true,
+                    ActorStep::Idle => break,
+                    ActorStep::Quit => {
+                        has_quit = true;
+                        break;
+                    }
+                }
+            }
+            if has_quit {
+                continue;
+            }
+            local.push(task);
+            if is_busy {
+                idle_visits = 0;
+            } else {
+                idle_visits += 1;
+                if idle_visits >= Self::IDLE_VISITS_BEFORE_SLEEP.max(local.len()) {
+                    idle_visits = 0;
+                    std::thread::sleep(Self::IDLE_SLEEP);
+                }
+            }
+        }
+    }
+
+    /// Takes the next actor from this worker's queue, or else from the new
+    /// actors, or else from another worker.
+    fn find_task(
+        local: &Worker<ActorTask>,
+        injector: &Injector<ActorTask>,
+        stealers: &[Stealer<ActorTask>],
+    ) -> Option<ActorTask> {
+        local.pop().or_else(|| {
+            std::iter::repeat_with(|| {
+                injector
+                    .steal_batch_and_pop(local)
+                    .or_else(|| stealers.iter().map(|s| s.steal()).collect())
+            })
+            .find(|s| !s.is_retry())
+            .and_then(Steal::success)
+        })
+    }
+}
@@ -0,0 +1,137 @@
+#[cfg(feature = "gui")]
+use eframe::egui::Slider;
+use ensnare::prelude::*;
+use ensnare_proc_macros::{Control, IsEntity, Metadata};
+use serde::{Deserialize, Serialize};
+
+const MIN_TIME_SECONDS: f64 = 0.001;
+const MAX_TIME_SECONDS: f64 = 1.0;
+
+/// Tracks the envelope of the audio passing through it and publishes the
+/// envelope as a control signal. Link its output to another entity's
+/// parameter for sidechain-style effects; turn on inversion to duck.
+///
+/// The audio itself passes through unchanged.
+#[derive(Debug, Control, IsEntity, Metadata, Serialize, Deserialize)]
+#[entity(GeneratesStereoSample, HandlesMidi, Serializable)]
+pub struct EnvelopeFollower {
+    uid: Uid,
+
+    /// How quickly the envelope rises, mapped logarithmically onto 1ms-1s.
+    #[control]
+    attack: Normal,
+
+    /// How quickly the envelope falls, mapped logarithmically onto 1ms-1s.
+    #[control]
+    release: Normal,
+
+    /// Publish 1.0 - envelope instead of envelope.
+    is_inverted: bool,
+
+    #[serde(skip)]
+    sample_rate: SampleRate,
+
+    #[serde(skip)]
+    envelope: f64,
+
+    #[serde(skip)]
+    last_value: Normal,
+
+    #[serde(skip)]
+    time_range: TimeRange,
+}
+impl Default for EnvelopeFollower {
+    fn default() -> Self {
+        Self {
+            uid: Default::default(),
+            attack: Normal::from(0.2),
+            release: Normal::from(0.6),
+            is_inverted: false,
+            sample_rate: Default::default(),
+            envelope: 0.0,
+            last_value: Default::default(),
+            time_range: Default::default(),
+        }
+    }
+}
+impl TransformsAudio for EnvelopeFollower {
+    fn transform(&mut self, samples: &mut [StereoSample]) {
+        let attack = self.coefficient(self.attack);
+        let release = self.coefficient(self.release);
+        for sample in samples.iter() {
+            let level = sample.0 .0.abs().max(sample.1 .0.abs());
+            let coefficient = if level > self.envelope {
+                attack
+            } else {
+                release
+            };
+            self.envelope = level + coefficient * (self.envelope - level);
+        }
+    }
+}
+impl Controls for EnvelopeFollower {
+    fn time_range(&self) -> Option<TimeRange> {
+        Some(self.time_range.clone())
+    }
+
+    fn update_time_range(&mut self, time_range: &TimeRange) {
+        self.time_range = time_range.clone()
+    }
+
+    fn work(&mut self, control_events_fn: &mut ControlEventsFn) {
+        // As with DroneController, this reports the envelope of the previous
+        // cycle, because work() happens before transform().
+        let envelope = self.envelope.min(1.0);
+        let value = Normal::from(if self.is_inverted {
+            1.0 - envelope
+        } else {
+            envelope
+        });
+        if value != self.last_value {
+            control_events_fn(WorkEvent::Control(value.into()));
+            self.last_value = value;
+        }
+    }
+}
+impl Configurable for EnvelopeFollower {
+    fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate;
+    }
+}
+#[cfg(feature = "gui")]
+impl Displays for EnvelopeFollower {
+    fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
+        let response = ui.label(format!("Envelope: {:.4}", self.last_value.0));
+        let mut attack = self.attack.0;
+        if ui.add(Slider::new(&mut attack, Normal::range()).text("Attack")).changed() {
+            self.set_attack(Normal::from(attack));
+        }
+        let mut release = self.release.0;
+        if ui.add(Slider::new(&mut release, Normal::range()).text("Release")).changed() {
+            self.set_release(Normal::from(release));
+        }
+        ui.checkbox(&mut self.is_inverted, "Invert");
+        response
+    }
+}
+#[cfg(not(feature = "gui"))]
+impl Displays for EnvelopeFollower {}
+impl EnvelopeFollower {
+    /// The one-pole smoothing coefficient for the given time control.
+    fn coefficient(&self, time: Normal) -> f64 {
+        let seconds = MIN_TIME_SECONDS * (MAX_TIME_SECONDS / MIN_TIME_SECONDS).powf(time.0);
+        (-1.0 / (seconds * self.sample_rate.0 as f64)).exp()
+    }
+
+    fn set_attack(&mut self, attack: Normal) {
+        self.attack = attack;
+    }
+
+    fn set_release(&mut self, release: Normal) {
+        self.release = release;
+    }
+}
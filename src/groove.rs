@@ -0,0 +1,133 @@
+//! Swing and groove for a track's sequencers. Rather than moving events
+//! after the fact, a [Groove] bends the time that the sequencers see, so
+//! that an event on a straight grid position comes out a little early or
+//! late.
+
+use ensnare::prelude::*;
+use std::fmt::Display;
+
+/// The steps that swing moves every other one of.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GrooveGrid {
+    #[default]
+    Eighth,
+    Sixteenth,
+}
+impl GrooveGrid {
+    pub const ALL: [GrooveGrid; 2] = [Self::Eighth, Self::Sixteenth];
+
+    fn step_units(&self) -> usize {
+        match self {
+            GrooveGrid::Eighth => MusicalTime::UNITS_IN_BEAT / 2,
+            GrooveGrid::Sixteenth => MusicalTime::UNITS_IN_BEAT / 4,
+        }
+    }
+}
+impl Display for GrooveGrid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            GrooveGrid::Eighth => "1/8",
+            GrooveGrid::Sixteenth => "1/16",
+        })
+    }
+}
+
+/// A timing feel that repeats every four steps, on top of the swing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GrooveTemplate {
+    #[default]
+    Straight,
+    /// Each step after the first of four drifts a little further behind.
+    LaidBack,
+    /// Each step after the first of four gets a little further ahead.
+    Pushed,
+}
+impl GrooveTemplate {
+    pub const ALL: [GrooveTemplate; 3] = [Self::Straight, Self::LaidBack, Self::Pushed];
+
+    /// How far each of the four steps moves, in steps. The first never
+    /// moves, so that the groove lines up with the grid at the start of each
+    /// cycle.
+    fn offsets(&self) -> [f64; 4] {
+        match self {
+            GrooveTemplate::Straight => [0.0; 4],
+            GrooveTemplate::LaidBack => [0.0, 0.06, 0.03, 0.09],
+            GrooveTemplate::Pushed => [0.0, -0.06, -0.03, -0.09],
+        }
+    }
+}
+impl Display for GrooveTemplate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            GrooveTemplate::Straight => "Straight",
+            GrooveTemplate::LaidBack => "Laid back",
+            GrooveTemplate::Pushed => "Pushed",
+        })
+    }
+}
+
+/// A track's swing and groove template.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Groove {
+    /// How much of each pair of steps the first one takes, as a percentage.
+    /// 50 is straight, and about 67 is a triplet shuffle.
+    pub swing: f64,
+    pub grid: GrooveGrid,
+    pub template: GrooveTemplate,
+}
+impl Default for Groove {
+    fn default() -> Self {
+        Self {
+            swing: Self::MIN_SWING,
+            grid: Default::default(),
+            template: Default::default(),
+        }
+    }
+}
+impl Groove {
+    pub const MIN_SWING: f64 = 50.0;
+    pub const MAX_SWING: f64 = 75.0;
+    /// Steps never move half a step or more, so that they stay in order.
+    const MAX_OFFSET: f64 = 0.49;
+
+    pub fn is_straight(&self) -> bool {
+        self.swing <= Self::MIN_SWING && self.template == GrooveTemplate::Straight
+    }
+
+    /// The straight time range that's heard during the given one, which is
+    /// what the track's sequencers should work on.
+    pub fn straight_range(&self, time_range: &TimeRange) -> TimeRange {
+        let start = self.straight_time(time_range.0.start);
+        let end = self.straight_time(time_range.0.end);
+        TimeRange(start..end)
+    }
+
+    /// The straight time that's heard at the given time.
+    pub fn straight_time(&self, time: MusicalTime) -> MusicalTime {
+        let step_units = self.grid.step_units() as f64;
+        let heard = time.total_units() as f64;
+        // Steps move less than half a step, so the step that's playing is
+        // this one or one of its neighbors.
+        let mut step = (heard / step_units) as usize;
+        if step > 0 && heard < self.step_start(step) {
+            step -= 1;
+        } else if heard >= self.step_start(step + 1) {
+            step += 1;
+        }
+        let (start, end) = (self.step_start(step), self.step_start(step + 1));
+        let straight = (step as f64 + (heard - start) / (end - start)) * step_units;
+        MusicalTime::new_with_units(straight.max(0.0).round() as usize)
+    }
+
+    /// Where the given step is heard, in units.
+    fn step_start(&self, step: usize) -> f64 {
+        let swing = if step % 2 == 1 {
+            self.swing.clamp(Self::MIN_SWING, Self::MAX_SWING) / 50.0 - 1.0
+        } else {
+            0.0
+        };
+        let offset =
+            (swing + self.template.offsets()[step % 4]).clamp(-Self::MAX_OFFSET, Self::MAX_OFFSET);
+        (step as f64 + offset) * self.grid.step_units() as f64
+    }
+}
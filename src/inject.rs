@@ -0,0 +1,121 @@
+//! Hand-made requests for debugging the actor protocol. An [Injection] is
+//! parsed from a line such as `track 1 needs-audio 64` or
+//! `entity 1 3 note-on 60 100`, and [Engine::inject](crate::engine::Engine::inject)
+//! sends it straight to the actor, bypassing the engine's own bookkeeping.
+
+use crate::{entity::EntityRequest, track::TrackRequest};
+use anyhow::anyhow;
+use ensnare::prelude::*;
+use std::str::SplitWhitespace;
+
+/// A request for one track or entity. Track 0 is the master track.
+#[derive(Debug)]
+pub enum Injection {
+    Track(TrackUid, TrackRequest),
+    Entity(TrackUid, Uid, EntityRequest),
+}
+impl Injection {
+    /// What [Injection::parse] understands, for the console's help text.
+    pub const SYNTAX: &'static str = "\
+track <track> needs-audio <frames>
+track <track> seek <beat>
+track <track> release-notes
+track <track> <midi>
+entity <track> <entity> needs-audio <frames>
+entity <track> <entity> control <param> <value>
+entity <track> <entity> bypass <true|false>
+entity <track> <entity> <midi>
+where <midi> is note-on <key> <velocity> [channel],
+note-off <key> [channel], or cc <controller> <value> [channel]";
+
+    pub fn parse(line: &str) -> anyhow::Result<Self> {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("track") => {
+                let track_uid = TrackUid(Self::value(&mut words, "track")?);
+                let request = match words.next() {
+                    Some("needs-audio") => {
+                        TrackRequest::NeedsAudio(Self::value(&mut words, "frames")?)
+                    }
+                    Some("seek") => {
+                        let beat = Self::value(&mut words, "beat")?;
+                        let units = beat * MusicalTime::UNITS_IN_BEAT;
+                        TrackRequest::Seek(MusicalTime::new_with_units(units))
+                    }
+                    Some("release-notes") => TrackRequest::ReleaseNotes,
+                    verb => {
+                        let (channel, message) = Self::midi(verb, &mut words)?;
+                        TrackRequest::Midi(channel, message)
+                    }
+                };
+                Self::finish(words, Self::Track(track_uid, request))
+            }
+            Some("entity") => {
+                let track_uid = TrackUid(Self::value(&mut words, "track")?);
+                let uid = Uid(Self::value(&mut words, "entity")?);
+                let request = match words.next() {
+                    Some("needs-audio") => {
+                        EntityRequest::NeedsAudio(Self::value(&mut words, "frames")?)
+                    }
+                    Some("control") => {
+                        let index = ControlIndex(Self::value(&mut words, "param")?);
+                        let value = ControlValue(Self::value(&mut words, "value")?);
+                        EntityRequest::Control(index, value)
+                    }
+                    Some("bypass") => EntityRequest::SetBypass(Self::value(&mut words, "bypass")?),
+                    verb => {
+                        let (channel, message) = Self::midi(verb, &mut words)?;
+                        EntityRequest::Midi(channel, message)
+                    }
+                };
+                Self::finish(words, Self::Entity(track_uid, uid, request))
+            }
+            Some(word) => Err(anyhow!("Expected \"track\" or \"entity\", not \"{word}\"")),
+            None => Err(anyhow!("Nothing to send")),
+        }
+    }
+
+    fn midi(
+        verb: Option<&str>,
+        words: &mut SplitWhitespace,
+    ) -> anyhow::Result<(MidiChannel, MidiMessage)> {
+        let message = match verb {
+            Some("note-on") => MidiMessage::NoteOn {
+                key: Self::value::<u8>(words, "key")?.into(),
+                vel: Self::value::<u8>(words, "velocity")?.into(),
+            },
+            Some("note-off") => MidiMessage::NoteOff {
+                key: Self::value::<u8>(words, "key")?.into(),
+                vel: 0.into(),
+            },
+            Some("cc") => MidiMessage::Controller {
+                controller: Self::value::<u8>(words, "controller")?.into(),
+                value: Self::value::<u8>(words, "value")?.into(),
+            },
+            Some(verb) => return Err(anyhow!("Unknown request \"{verb}\"")),
+            None => return Err(anyhow!("Missing a request")),
+        };
+        // Channels are numbered from 1, as in most MIDI gear.
+        let channel = match words.next() {
+            Some(word) => match word.parse::<u8>() {
+                Ok(channel @ 1..=16) => channel - 1,
+                _ => return Err(anyhow!("Expected a channel from 1 to 16, not \"{word}\"")),
+            },
+            None => 0,
+        };
+        Ok((MidiChannel(channel), message))
+    }
+
+    fn value<T: std::str::FromStr>(words: &mut SplitWhitespace, name: &str) -> anyhow::Result<T> {
+        let word = words.next().ok_or_else(|| anyhow!("Missing <{name}>"))?;
+        word.parse()
+            .map_err(|_| anyhow!("Couldn't make sense of <{name}> \"{word}\""))
+    }
+
+    fn finish(mut words: SplitWhitespace, injection: Self) -> anyhow::Result<Self> {
+        match words.next() {
+            Some(word) => Err(anyhow!("Unexpected \"{word}\"")),
+            None => Ok(injection),
+        }
+    }
+}
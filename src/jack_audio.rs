@@ -0,0 +1,203 @@
+//! Audio and MIDI through a JACK server. JACK needs its native library, so
+//! the backend is behind the `jack` feature; without it,
+//! [JackService::new_with] always fails.
+
+#[cfg(feature = "jack")]
+use crate::callback_audio::CallbackFeed;
+use crate::engine::EngineServiceInput;
+use crossbeam_channel::Sender;
+use derivative::Derivative;
+use ensnare::{prelude::*, types::CrossbeamChannel};
+use ensnare_services::prelude::*;
+#[cfg(feature = "jack")]
+use {
+    crossbeam_channel::Receiver,
+    jack::{
+        AsyncClient, AudioOut, Client, ClientOptions, Control, MidiIn, MidiOut, Port,
+        ProcessHandler, ProcessScope, RawMidi, TransportState,
+    },
+    midly::live::LiveEvent,
+};
+
+/// Runs the engine from JACK's process callback rather than from the audio
+/// queue that [CpalAudioService] keeps topped up. Each callback plays the
+/// frames that the engine generated for the one before, and asks the engine
+/// for the next ones, so the engine runs one period ahead of JACK.
+///
+/// It also follows JACK's transport: when another client starts, stops, or
+/// moves it, the engine does the same. MIDI arriving at the input port goes
+/// straight to the engine, and MIDI sent with [JackService::midi_sender]
+/// goes out the output port.
+///
+/// Give the engine [JackService::audio_sender] with
+/// [EngineServiceInput::SetAudioSender].
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct JackService {
+    audio: CrossbeamChannel<CpalAudioServiceInput>,
+    midi: CrossbeamChannel<(MidiChannel, MidiMessage)>,
+
+    // reason = "We need to keep a reference to the client or else it'll be deactivated"
+    #[cfg(feature = "jack")]
+    #[allow(dead_code)]
+    #[derivative(Debug = "ignore")]
+    client: AsyncClient<(), JackProcess>,
+}
+impl JackService {
+    /// The name that other JACK clients see.
+    pub const CLIENT_NAME: &'static str = "spike-actor-system";
+
+    /// Whether this build can use JACK at all.
+    pub fn is_available() -> bool {
+        cfg!(feature = "jack")
+    }
+
+    /// Connects to the running JACK server, registers the ports, and starts
+    /// driving the engine through the given sender. Doesn't start a server
+    /// if there isn't one.
+    #[cfg(feature = "jack")]
+    pub fn new_with(engine_sender: Sender<EngineServiceInput>) -> anyhow::Result<Self> {
+        let (client, _status) = Client::new(Self::CLIENT_NAME, ClientOptions::NO_START_SERVER)?;
+        let audio: CrossbeamChannel<CpalAudioServiceInput> = Default::default();
+        let midi: CrossbeamChannel<(MidiChannel, MidiMessage)> = Default::default();
+
+        let buffer_size = client.buffer_size() as usize;
+        let sample_rate = SampleRate(client.sample_rate());
+        let _ = engine_sender.try_send(EngineServiceInput::Configure(
+            sample_rate,
+            2,
+            Some(buffer_size),
+        ));
+
+        let process = JackProcess {
+            left: client.register_port("out_left", AudioOut::default())?,
+            right: client.register_port("out_right", AudioOut::default())?,
+            midi_in: client.register_port("midi_in", MidiIn::default())?,
+            midi_out: client.register_port("midi_out", MidiOut::default())?,
+            feed: CallbackFeed::new_with(engine_sender, audio.receiver.clone(), buffer_size),
+            midi_receiver: midi.receiver.clone(),
+            midi_buffer: Vec::with_capacity(3),
+            is_rolling: false,
+            expected_frame: 0,
+        };
+        let client = client.activate_async((), process)?;
+
+        Ok(Self {
+            audio,
+            midi,
+            client,
+        })
+    }
+    #[cfg(not(feature = "jack"))]
+    pub fn new_with(_engine_sender: Sender<EngineServiceInput>) -> anyhow::Result<Self> {
+        Err(anyhow::anyhow!(
+            "This build doesn't include JACK. Rebuild with the `jack` feature."
+        ))
+    }
+
+    /// Where the engine should send its frames.
+    pub fn audio_sender(&self) -> &Sender<CpalAudioServiceInput> {
+        &self.audio.sender
+    }
+
+    /// Where to send MIDI for the output port.
+    pub fn midi_sender(&self) -> &Sender<(MidiChannel, MidiMessage)> {
+        &self.midi.sender
+    }
+}
+
+/// The state that JACK's process callback works with.
+#[cfg(feature = "jack")]
+struct JackProcess {
+    left: Port<AudioOut>,
+    right: Port<AudioOut>,
+    midi_in: Port<MidiIn>,
+    midi_out: Port<MidiOut>,
+    feed: CallbackFeed,
+    midi_receiver: Receiver<(MidiChannel, MidiMessage)>,
+    midi_buffer: Vec<u8>,
+    /// Whether JACK's transport was rolling last period.
+    is_rolling: bool,
+    /// Where JACK's transport should be this period if nobody moved it.
+    expected_frame: usize,
+}
+#[cfg(feature = "jack")]
+impl ProcessHandler for JackProcess {
+    fn process(&mut self, client: &Client, ps: &ProcessScope) -> Control {
+        let frame_count = ps.n_frames() as usize;
+        self.follow_transport(client, frame_count);
+        self.receive_midi(ps);
+        self.send_midi(ps);
+
+        let Some(frames) = self.feed.next_period(frame_count) else {
+            return Control::Quit;
+        };
+        let left = self.left.as_mut_slice(ps);
+        let right = self.right.as_mut_slice(ps);
+        for ((left, right), frame) in left.iter_mut().zip(right.iter_mut()).zip(frames) {
+            (*left, *right) = frame;
+        }
+        Control::Continue
+    }
+}
+#[cfg(feature = "jack")]
+impl JackProcess {
+    /// Plays, stops, or seeks the engine to match JACK's transport. A jump
+    /// anywhere other than where the last period left off is a seek.
+    fn follow_transport(&mut self, client: &Client, frame_count: usize) {
+        let Ok(status) = client.transport().query() else {
+            return;
+        };
+        let frame = status.pos.frame() as usize;
+        if frame != self.expected_frame {
+            let _ = self
+                .feed
+                .engine_sender()
+                .try_send(EngineServiceInput::SeekToFrame(frame));
+        }
+        let is_rolling = matches!(status.state, TransportState::Rolling);
+        if is_rolling != self.is_rolling {
+            self.is_rolling = is_rolling;
+            let input = if is_rolling {
+                EngineServiceInput::Play
+            } else {
+                EngineServiceInput::Stop
+            };
+            let _ = self.feed.engine_sender().try_send(input);
+        }
+        self.expected_frame = if is_rolling {
+            frame + frame_count
+        } else {
+            frame
+        };
+    }
+
+    fn receive_midi(&mut self, ps: &ProcessScope) {
+        for raw in self.midi_in.iter(ps) {
+            if let Ok(LiveEvent::Midi { channel, message }) = LiveEvent::parse(raw.bytes) {
+                let channel = MidiChannel(channel.as_int());
+                let _ = self
+                    .feed
+                    .engine_sender()
+                    .try_send(EngineServiceInput::Midi(channel, message));
+            }
+        }
+    }
+
+    fn send_midi(&mut self, ps: &ProcessScope) {
+        let mut writer = self.midi_out.writer(ps);
+        while let Ok((channel, message)) = self.midi_receiver.try_recv() {
+            self.midi_buffer.clear();
+            let event = LiveEvent::Midi {
+                channel: channel.0.into(),
+                message,
+            };
+            if event.write(&mut self.midi_buffer).is_ok() {
+                let _ = writer.write(&RawMidi {
+                    time: 0,
+                    bytes: &self.midi_buffer,
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use eframe::egui::{Context, DragValue, Event, Key, Slider};
+use ensnare::prelude::*;
+use std::collections::HashMap;
+
+/// The keys that play notes, in semitone order starting at C. The layout
+/// mimics a piano: the home row is the white keys, and the row above it is the
+/// black keys.
+const NOTE_KEYS: [Key; 17] = [
+    Key::A,
+    Key::W,
+    Key::S,
+    Key::E,
+    Key::D,
+    Key::F,
+    Key::T,
+    Key::G,
+    Key::Y,
+    Key::H,
+    Key::U,
+    Key::J,
+    Key::K,
+    Key::O,
+    Key::L,
+    Key::P,
+    Key::Semicolon,
+];
+
+/// Turns the computer keyboard into a MIDI keyboard. Z and X shift the octave,
+/// and C and V change the velocity.
+#[derive(Debug)]
+pub struct QwertyKeyboard {
+    is_enabled: bool,
+    channel: MidiChannel,
+    /// The octave of the A key, where octave 4 starts at middle C.
+    octave: u8,
+    velocity: u8,
+    /// The note each held key started, so that releasing it stops the same
+    /// note even if the octave changed in between.
+    held_notes: HashMap<Key, u8>,
+}
+impl Default for QwertyKeyboard {
+    fn default() -> Self {
+        Self {
+            is_enabled: false,
+            channel: MidiChannel::default(),
+            octave: 4,
+            velocity: 100,
+            held_notes: Default::default(),
+        }
+    }
+}
+impl QwertyKeyboard {
+    const MAX_OCTAVE: u8 = 8;
+    const VELOCITY_STEP: u8 = 16;
+
+    /// Returns the MIDI messages for this frame's key events. Does nothing
+    /// while disabled, or while a widget such as a text field has keyboard
+    /// focus.
+    pub fn handle_input(&mut self, ctx: &Context) -> Vec<(MidiChannel, MidiMessage)> {
+        if !self.is_enabled || ctx.wants_keyboard_input() {
+            return Vec::default();
+        }
+        let events = ctx.input(|i| i.events.clone());
+        let mut messages = Vec::default();
+        for event in events {
+            let Event::Key {
+                key,
+                pressed,
+                repeat,
+                modifiers,
+                ..
+            } = event
+            else {
+                continue;
+            };
+            // Leave shortcuts such as Ctrl+Z to the rest of the app.
+            if repeat || (pressed && modifiers.command) {
+                continue;
+            }
+            if pressed {
+                match key {
+                    Key::Z => self.octave = self.octave.saturating_sub(1),
+                    Key::X => self.octave = (self.octave + 1).min(Self::MAX_OCTAVE),
+                    Key::C => {
+                        self.velocity = self.velocity.saturating_sub(Self::VELOCITY_STEP).max(1)
+                    }
+                    Key::V => self.velocity = (self.velocity + Self::VELOCITY_STEP).min(127),
+                    _ => {
+                        if let Some(note) = self.note_for_key(key) {
+                            self.held_notes.insert(key, note);
+                            messages.push((
+                                self.channel,
+                                MidiMessage::NoteOn {
+                                    key: note.into(),
+                                    vel: self.velocity.into(),
+                                },
+                            ));
+                        }
+                    }
+                }
+            } else if let Some(note) = self.held_notes.remove(&key) {
+                messages.push((
+                    self.channel,
+                    MidiMessage::NoteOff {
+                        key: note.into(),
+                        vel: 0.into(),
+                    },
+                ));
+            }
+        }
+        messages
+    }
+
+    fn note_for_key(&self, key: Key) -> Option<u8> {
+        let offset = NOTE_KEYS.iter().position(|k| *k == key)?;
+        let note = (self.octave as usize + 1) * 12 + offset;
+        (note <= 127).then_some(note as u8)
+    }
+}
+impl Displays for QwertyKeyboard {
+    fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
+        let response = ui.checkbox(&mut self.is_enabled, "Computer keyboard");
+        if self.is_enabled {
+            ui.add(
+                DragValue::new(&mut self.octave)
+                    .clamp_range(0..=Self::MAX_OCTAVE)
+                    .prefix("Octave: "),
+            );
+            ui.add(Slider::new(&mut self.velocity, 1..=127).text("Velocity"));
+            let mut channel = self.channel.0 + 1;
+            if ui
+                .add(DragValue::new(&mut channel).clamp_range(1..=16).prefix("Channel: "))
+                .changed()
+            {
+                self.channel = MidiChannel(channel - 1);
+            }
+        }
+        response
+    }
+}
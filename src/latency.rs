@@ -0,0 +1,95 @@
+//! Keeps tracks in time with each other when some of their entities delay
+//! the audio, e.g., by looking ahead. Each track reports how late its output
+//! is, and the [Engine](crate::engine::Engine) delays the others to match.
+
+use ensnare::prelude::*;
+use std::collections::{HashMap, VecDeque};
+
+/// Delays audio by a whole number of frames.
+#[derive(Debug, Default)]
+pub struct DelayLine {
+    frames: VecDeque<StereoSample>,
+}
+impl DelayLine {
+    pub fn delay(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Lengthening the delay leaves a gap of silence in the output, and
+    /// shortening it skips whatever was about to come out.
+    pub fn set_delay(&mut self, delay: usize) {
+        let len = self.frames.len();
+        if delay > len {
+            for _ in len..delay {
+                self.frames.push_front(StereoSample::SILENCE);
+            }
+        } else {
+            self.frames.drain(..len - delay);
+        }
+    }
+
+    pub fn process(&mut self, frames: &mut [StereoSample]) {
+        if self.frames.is_empty() {
+            return;
+        }
+        for frame in frames.iter_mut() {
+            self.frames.push_back(*frame);
+            if let Some(delayed) = self.frames.pop_front() {
+                *frame = delayed;
+            }
+        }
+    }
+}
+
+/// How many frames to delay each of the tracks' outputs so that everything
+/// reaches the master track at the same time. See [compensation_delays()].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CompensationDelays {
+    /// Each track's main output.
+    pub outputs: HashMap<TrackUid, usize>,
+    /// Each track's sends, keyed by the track and then the bus.
+    pub sends: HashMap<(TrackUid, TrackUid), usize>,
+}
+
+/// `latencies` has every track's own latency, `outputs` maps each track that
+/// goes to a bus to that bus, and `sends` has a (track, bus) pair for each
+/// send. Everything arriving at a bus, whether routed or sent, is first lined
+/// up with the bus's slowest input, and then each path to the master track is
+/// lined up with the slowest one. So a track that sends to a slow aux track
+/// has its dry signal delayed to match the wet one.
+pub fn compensation_delays(
+    latencies: &HashMap<TrackUid, usize>,
+    outputs: &HashMap<TrackUid, TrackUid>,
+    sends: &[(TrackUid, TrackUid)],
+) -> CompensationDelays {
+    let latency = |uid: &TrackUid| latencies.get(uid).copied().unwrap_or_default();
+    let mut slowest_inputs: HashMap<TrackUid, usize> = HashMap::default();
+    for (uid, bus_uid) in outputs.iter().chain(sends.iter().map(|(u, b)| (u, b))) {
+        let slowest = slowest_inputs.entry(*bus_uid).or_default();
+        *slowest = (*slowest).max(latency(uid));
+    }
+    let paths: HashMap<TrackUid, usize> = latencies
+        .keys()
+        .filter(|uid| !outputs.contains_key(uid))
+        .map(|uid| {
+            let slowest_input = slowest_inputs.get(uid).copied().unwrap_or_default();
+            (*uid, slowest_input + latency(uid))
+        })
+        .collect();
+    let slowest_path = paths.values().copied().max().unwrap_or_default();
+    let outputs = latencies
+        .keys()
+        .map(|uid| {
+            let delay = match outputs.get(uid) {
+                Some(bus_uid) => slowest_inputs[bus_uid] - latency(uid),
+                None => slowest_path - paths[uid],
+            };
+            (*uid, delay)
+        })
+        .collect();
+    let sends = sends
+        .iter()
+        .map(|&(uid, bus_uid)| ((uid, bus_uid), slowest_inputs[&bus_uid] - latency(&uid)))
+        .collect();
+    CompensationDelays { outputs, sends }
+}
@@ -0,0 +1,94 @@
+//! The actor system behind the app: an [Engine](engine::Engine) that owns
+//! tracks, each of which runs its entities on their own threads. For projects
+//! with many entities, [Executor::Pool](executor::Executor::Pool) runs them
+//! on a fixed set of worker threads instead.
+//!
+//! For tests and offline rendering, create the engine with
+//! [Executor::Synchronous](executor::Executor::Synchronous) instead. Then
+//! nothing runs on other threads, and
+//! [Engine::render](engine::Engine::render) produces the same frames every
+//! time.
+//!
+//! To embed it, create an [EngineService](engine::EngineService), send it
+//! [EngineServiceInput](engine::EngineServiceInput)s, and hold on to the
+//! engine that arrives in [EngineServiceEvent::Reset](engine::EngineServiceEvent::Reset).
+//! Tracks and entities are addressed by uid, and new entities come from the
+//! [EntityRegistry](registry::EntityRegistry) by string key.
+//!
+//! Our own UI code, and the eframe dependency, are behind the `gui` feature,
+//! which is on by default. Without it, `cargo build --lib
+//! --no-default-features` builds a headless engine whose entities fall back
+//! to the empty default [Displays::ui](ensnare::prelude::Displays::ui).
+
+use std::sync::atomic::Ordering;
+
+pub mod actions;
+#[cfg(feature = "gui")]
+pub mod arrangement;
+pub mod audio_input;
+pub mod batch;
+pub mod browser;
+pub mod buffer_pool;
+pub mod callback_audio;
+pub mod channels;
+pub mod clip;
+pub mod command;
+pub mod compare;
+pub mod declick;
+pub mod engine;
+pub mod entity;
+pub mod executor;
+pub mod groove;
+pub mod inject;
+pub mod jack_audio;
+pub mod latency;
+pub mod limiter;
+pub mod limits;
+pub mod link;
+pub mod logging;
+pub mod meter;
+pub mod metronome;
+pub mod metrics;
+pub mod midi_clock;
+pub mod midi_file;
+pub mod midi_input;
+pub mod mixer;
+pub mod notes;
+pub mod notification;
+pub mod performance;
+pub mod plugin;
+pub mod preset;
+pub mod project;
+pub mod punch;
+pub mod recording;
+pub mod registry;
+pub mod remote;
+pub mod resampler;
+pub mod scene;
+pub mod script;
+pub mod snapshot;
+pub mod spectrum;
+pub mod stress;
+pub mod subscription;
+pub mod tempo;
+pub mod trace;
+pub mod track;
+pub mod traits;
+pub mod transfer;
+pub mod wav_writer;
+
+mod always;
+mod arp;
+mod busy;
+mod drone;
+mod drums;
+mod eq;
+mod follower;
+mod lookahead;
+#[cfg(feature = "gui")]
+mod piano;
+mod poly_synth;
+mod quietener;
+mod signal;
+
+pub(crate) const ATOMIC_ORDERING: Ordering = Ordering::Relaxed;
@@ -0,0 +1,46 @@
+use crate::ATOMIC_ORDERING;
+use ensnare::prelude::*;
+use std::sync::{atomic::AtomicBool, Arc};
+
+/// A brick-wall peak limiter. Gain drops instantly to keep every sample within
+/// the ceiling, then recovers smoothly.
+#[derive(Debug)]
+pub struct Limiter {
+    ceiling: f64,
+    /// The fraction of the distance back to unity gain recovered per frame.
+    release: f64,
+    gain: f64,
+
+    /// Set whenever an incoming sample exceeds +/-1.0. Only the UI clears it.
+    is_clipping: Arc<AtomicBool>,
+}
+impl Limiter {
+    pub fn new_with(is_clipping: Arc<AtomicBool>) -> Self {
+        Self {
+            ceiling: 1.0,
+            release: 0.0005,
+            gain: 1.0,
+            is_clipping,
+        }
+    }
+
+    pub fn process(&mut self, frames: &mut [StereoSample]) {
+        for frame in frames.iter_mut() {
+            let peak = frame.0 .0.abs().max(frame.1 .0.abs());
+            if peak > 1.0 {
+                self.is_clipping.store(true, ATOMIC_ORDERING);
+            }
+            let target = if peak * self.gain > self.ceiling {
+                self.ceiling / peak
+            } else {
+                1.0
+            };
+            if target < self.gain {
+                self.gain = target;
+            } else {
+                self.gain += (1.0 - self.gain) * self.release;
+            }
+            *frame = StereoSample(Sample(frame.0 .0 * self.gain), Sample(frame.1 .0 * self.gain));
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Limits on how big a project can grow. With [Executor::Threaded], every
+//! track and entity has a thread of its own, so without them a flurry of
+//! "Add" clicks could use up the threads that the OS allows us.
+
+use crate::{executor::Executor, ATOMIC_ORDERING};
+use std::{
+    error::Error,
+    fmt::Display,
+    sync::{atomic::AtomicUsize, OnceLock},
+};
+
+/// Why a track or entity wasn't added. [report_error](crate::notification::report_error)
+/// shows it as a warning, since nothing went wrong.
+#[derive(Debug, Clone)]
+pub struct LimitReached(pub String);
+impl Display for LimitReached {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+impl Error for LimitReached {}
+
+/// The limits for the whole process, and how many actor threads are running.
+#[derive(Debug)]
+pub struct ActorLimits {
+    max_tracks: AtomicUsize,
+    max_entities_per_track: AtomicUsize,
+    max_threads: AtomicUsize,
+    threads: AtomicUsize,
+}
+impl ActorLimits {
+    pub const DEFAULT_MAX_TRACKS: usize = 64;
+    pub const DEFAULT_MAX_ENTITIES_PER_TRACK: usize = 32;
+    pub const DEFAULT_MAX_THREADS: usize = 1024;
+
+    pub fn global() -> &'static Self {
+        static LIMITS: OnceLock<ActorLimits> = OnceLock::new();
+        LIMITS.get_or_init(|| Self {
+            max_tracks: AtomicUsize::new(Self::DEFAULT_MAX_TRACKS),
+            max_entities_per_track: AtomicUsize::new(Self::DEFAULT_MAX_ENTITIES_PER_TRACK),
+            max_threads: AtomicUsize::new(Self::DEFAULT_MAX_THREADS),
+            threads: Default::default(),
+        })
+    }
+
+    /// Not counting the master track.
+    pub fn max_tracks(&self) -> usize {
+        self.max_tracks.load(ATOMIC_ORDERING)
+    }
+
+    pub fn set_max_tracks(&self, max_tracks: usize) {
+        self.max_tracks.store(max_tracks, ATOMIC_ORDERING);
+    }
+
+    pub fn max_entities_per_track(&self) -> usize {
+        self.max_entities_per_track.load(ATOMIC_ORDERING)
+    }
+
+    pub fn set_max_entities_per_track(&self, max_entities: usize) {
+        self.max_entities_per_track
+            .store(max_entities, ATOMIC_ORDERING);
+    }
+
+    /// Applies only to [Executor::Threaded]. The other executors have a
+    /// fixed number of threads.
+    pub fn max_threads(&self) -> usize {
+        self.max_threads.load(ATOMIC_ORDERING)
+    }
+
+    pub fn set_max_threads(&self, max_threads: usize) {
+        self.max_threads.store(max_threads, ATOMIC_ORDERING);
+    }
+
+    /// How many actors have a thread of their own right now, across all
+    /// engines.
+    pub fn thread_count(&self) -> usize {
+        self.threads.load(ATOMIC_ORDERING)
+    }
+
+    pub(crate) fn thread_started(&self) {
+        self.threads.fetch_add(1, ATOMIC_ORDERING);
+    }
+
+    pub(crate) fn thread_ended(&self) {
+        self.threads.fetch_sub(1, ATOMIC_ORDERING);
+    }
+
+    /// Whether a project that has the given number of tracks can have
+    /// another.
+    pub(crate) fn check_new_track(
+        &self,
+        track_count: usize,
+        executor: &Executor,
+    ) -> anyhow::Result<()> {
+        let max_tracks = self.max_tracks();
+        if track_count >= max_tracks {
+            let message = format!("A project can have at most {max_tracks} tracks");
+            return Err(LimitReached(message).into());
+        }
+        self.check_new_threads(1, executor)
+    }
+
+    /// Whether a track that has the given number of entities can have that
+    /// many more.
+    pub(crate) fn check_new_entities(
+        &self,
+        entity_count: usize,
+        adding: usize,
+        executor: &Executor,
+    ) -> anyhow::Result<()> {
+        self.check_entity_count(entity_count + adding)?;
+        self.check_new_threads(adding, executor)
+    }
+
+    /// Whether a track can have the given number of entities. For entities
+    /// that already have their threads, e.g., ones moving between tracks.
+    pub(crate) fn check_entity_count(&self, entity_count: usize) -> anyhow::Result<()> {
+        let max_entities = self.max_entities_per_track();
+        if entity_count > max_entities {
+            let message = format!("A track can have at most {max_entities} entities");
+            return Err(LimitReached(message).into());
+        }
+        Ok(())
+    }
+
+    fn check_new_threads(&self, adding: usize, executor: &Executor) -> anyhow::Result<()> {
+        if !matches!(executor, Executor::Threaded) {
+            return Ok(());
+        }
+        let (count, max_threads) = (self.thread_count(), self.max_threads());
+        if count + adding > max_threads {
+            let message =
+                format!("{count} tracks and entities are running, and at most {max_threads} can");
+            return Err(LimitReached(message).into());
+        }
+        Ok(())
+    }
+}
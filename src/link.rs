@@ -0,0 +1,104 @@
+use derivative::Derivative;
+use ensnare::prelude::*;
+#[cfg(feature = "link")]
+use rusty_link::{AblLink, SessionState};
+
+/// Syncs tempo and beat phase with other Ableton Link apps on the LAN. Link
+/// needs a native library, so it's behind the `link` feature; without it, this
+/// is a session that can never be enabled.
+#[derive(Derivative, Default)]
+#[derivative(Debug)]
+pub struct LinkSession {
+    /// Created the first time Link is enabled, so that we don't join the
+    /// network until asked.
+    #[cfg(feature = "link")]
+    #[derivative(Debug = "ignore")]
+    link: Option<AblLink>,
+}
+impl LinkSession {
+    /// Whether this build can use Link at all.
+    pub fn is_available() -> bool {
+        cfg!(feature = "link")
+    }
+
+    #[cfg(feature = "link")]
+    pub fn is_enabled(&self) -> bool {
+        self.link.as_ref().is_some_and(|link| link.is_enabled())
+    }
+    #[cfg(not(feature = "link"))]
+    pub fn is_enabled(&self) -> bool {
+        false
+    }
+
+    /// Joins or leaves the session. A newly created session starts at the
+    /// given tempo, which peers will override if there are any.
+    #[cfg(feature = "link")]
+    pub fn set_enabled(&mut self, is_enabled: bool, tempo: Tempo) {
+        self.link
+            .get_or_insert_with(|| AblLink::new(tempo.0))
+            .enable(is_enabled);
+    }
+    #[cfg(not(feature = "link"))]
+    pub fn set_enabled(&mut self, _is_enabled: bool, _tempo: Tempo) {}
+
+    #[cfg(feature = "link")]
+    pub fn peer_count(&self) -> usize {
+        match &self.link {
+            Some(link) => link.num_peers() as usize,
+            None => 0,
+        }
+    }
+    #[cfg(not(feature = "link"))]
+    pub fn peer_count(&self) -> usize {
+        0
+    }
+
+    /// The session tempo, or None if Link is off.
+    #[cfg(feature = "link")]
+    pub fn tempo(&self) -> Option<Tempo> {
+        let link = self.link.as_ref().filter(|link| link.is_enabled())?;
+        let mut state = SessionState::new();
+        link.capture_app_session_state(&mut state);
+        Some(Tempo(state.tempo()))
+    }
+    #[cfg(not(feature = "link"))]
+    pub fn tempo(&self) -> Option<Tempo> {
+        None
+    }
+
+    /// Proposes a new tempo to the session. Does nothing if Link is off.
+    #[cfg(feature = "link")]
+    pub fn set_tempo(&mut self, tempo: Tempo) {
+        let Some(link) = self.link.as_ref().filter(|link| link.is_enabled()) else {
+            return;
+        };
+        let mut state = SessionState::new();
+        link.capture_app_session_state(&mut state);
+        if state.tempo() != tempo.0 {
+            state.set_tempo(tempo.0, link.clock_micros());
+            link.commit_app_session_state(&state);
+        }
+    }
+    #[cfg(not(feature = "link"))]
+    pub fn set_tempo(&mut self, _tempo: Tempo) {}
+
+    /// Whether the session crosses a `quantum`-beat boundary (usually a bar)
+    /// within the next `duration_micros`. Always true if Link is off, so that
+    /// callers waiting on the grid don't wait forever.
+    #[cfg(feature = "link")]
+    pub fn crosses_boundary(&self, duration_micros: i64, quantum: f64) -> bool {
+        let Some(link) = self.link.as_ref().filter(|link| link.is_enabled()) else {
+            return true;
+        };
+        let mut state = SessionState::new();
+        link.capture_app_session_state(&mut state);
+        let now = link.clock_micros();
+        let phase_now = state.phase_at_time(now, quantum);
+        let phase_later = state.phase_at_time(now + duration_micros, quantum);
+        phase_later < phase_now
+    }
+    #[cfg(not(feature = "link"))]
+    pub fn crosses_boundary(&self, _duration_micros: i64, _quantum: f64) -> bool {
+        true
+    }
+}
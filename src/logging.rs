@@ -0,0 +1,248 @@
+//! Logging through the `log` crate, with a level for each [Subsystem] and a
+//! ring buffer of recent records for the UI. Records that pass their
+//! subsystem's level also go to `env_logger`, so `RUST_LOG` can still narrow
+//! what reaches stderr.
+
+use crate::ATOMIC_ORDERING;
+use derivative::Derivative;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    sync::{atomic::AtomicUsize, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+#[cfg(feature = "gui")]
+use {
+    eframe::egui::{Color32, ComboBox, Grid, RichText, ScrollArea},
+    ensnare::prelude::*,
+};
+
+/// The part of the app that a record came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Subsystem {
+    Engine,
+    Tracks,
+    Entities,
+    /// Capture files, stems, and MIDI export.
+    Writers,
+    Audio,
+    Midi,
+    /// Everything else in this app, e.g., the UI and remote control.
+    App,
+    /// Other crates.
+    Libraries,
+}
+impl Subsystem {
+    pub const ALL: [Subsystem; 8] = [
+        Self::Engine,
+        Self::Tracks,
+        Self::Entities,
+        Self::Writers,
+        Self::Audio,
+        Self::Midi,
+        Self::App,
+        Self::Libraries,
+    ];
+
+    /// Which subsystem a record with the given target belongs to. Unless the
+    /// caller says otherwise, the target is the module path.
+    pub fn from_target(target: &str) -> Self {
+        let Some(path) = target.strip_prefix("spike_actor_system") else {
+            return Self::Libraries;
+        };
+        let module = path.trim_start_matches("::").split("::").next();
+        match module.unwrap_or_default() {
+            "engine" | "command" | "executor" | "limits" | "subscription" | "traits" => {
+                Self::Engine
+            }
+            "track" | "mixer" => Self::Tracks,
+            "entity" | "registry" | "drums" | "plugin" | "preset" => Self::Entities,
+            "wav_writer" | "recording" | "midi_file" => Self::Writers,
+            "audio_device" | "callback_audio" | "jack_audio" => Self::Audio,
+            "midi_clock" | "midi_input" | "midi_ports" => Self::Midi,
+            _ => Self::App,
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+impl Display for Subsystem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Subsystem::Engine => "Engine",
+            Subsystem::Tracks => "Tracks",
+            Subsystem::Entities => "Entities",
+            Subsystem::Writers => "Writers",
+            Subsystem::Audio => "Audio",
+            Subsystem::Midi => "MIDI",
+            Subsystem::App => "App",
+            Subsystem::Libraries => "Libraries",
+        })
+    }
+}
+
+/// One logged message.
+#[derive(Debug, Clone)]
+pub struct LogRecord {
+    /// How long after logging started.
+    pub at: Duration,
+    pub level: Level,
+    pub subsystem: Subsystem,
+    pub message: String,
+}
+
+/// The `log` crate's logger for the whole process. It keeps the most recent
+/// [ProjectLogger::CAPACITY] records that passed their subsystem's level.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct ProjectLogger {
+    /// A [LevelFilter] for each subsystem, by [Subsystem::index].
+    levels: [AtomicUsize; Subsystem::ALL.len()],
+    started_at: Instant,
+    records: Mutex<VecDeque<LogRecord>>,
+    #[derivative(Debug = "ignore")]
+    stderr: env_logger::Logger,
+}
+impl ProjectLogger {
+    pub const CAPACITY: usize = 1000;
+
+    /// The logger that [ProjectLogger::install] installs.
+    pub fn global() -> &'static Self {
+        static LOGGER: OnceLock<ProjectLogger> = OnceLock::new();
+        LOGGER.get_or_init(|| {
+            let levels = Subsystem::ALL.map(|subsystem| {
+                let level = match subsystem {
+                    Subsystem::Libraries => LevelFilter::Warn,
+                    _ => LevelFilter::Info,
+                };
+                AtomicUsize::new(level as usize)
+            });
+            let env = env_logger::Env::default().default_filter_or("trace");
+            Self {
+                levels,
+                started_at: Instant::now(),
+                records: Default::default(),
+                stderr: env_logger::Builder::from_env(env).build(),
+            }
+        })
+    }
+
+    /// Makes [ProjectLogger::global] the `log` crate's logger. Call it once,
+    /// before anything logs.
+    pub fn install() -> anyhow::Result<()> {
+        let logger = Self::global();
+        log::set_logger(logger)?;
+        log::set_max_level(logger.max_level());
+        Ok(())
+    }
+
+    pub fn level(&self, subsystem: Subsystem) -> LevelFilter {
+        let level = self.levels[subsystem.index()].load(ATOMIC_ORDERING);
+        LevelFilter::iter().nth(level).unwrap_or(LevelFilter::Trace)
+    }
+
+    pub fn set_level(&self, subsystem: Subsystem, level: LevelFilter) {
+        self.levels[subsystem.index()].store(level as usize, ATOMIC_ORDERING);
+        log::set_max_level(self.max_level());
+    }
+
+    /// The records in the buffer, oldest first.
+    pub fn records(&self) -> Vec<LogRecord> {
+        self.records.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.records.lock().unwrap().clear();
+    }
+
+    /// The most detailed level of any subsystem, which lets the `log` macros
+    /// skip anything more detailed without calling us.
+    fn max_level(&self) -> LevelFilter {
+        let levels = Subsystem::ALL.map(|subsystem| self.level(subsystem));
+        levels.into_iter().max().unwrap_or(LevelFilter::Off)
+    }
+}
+impl Log for ProjectLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level(Subsystem::from_target(metadata.target()))
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        self.stderr.log(record);
+        let record = LogRecord {
+            at: self.started_at.elapsed(),
+            level: record.level(),
+            subsystem: Subsystem::from_target(record.target()),
+            message: record.args().to_string(),
+        };
+        let mut records = self.records.lock().unwrap();
+        if records.len() == Self::CAPACITY {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    fn flush(&self) {
+        self.stderr.flush();
+    }
+}
+
+/// Shows each subsystem's level, and the buffered records.
+#[cfg(feature = "gui")]
+#[derive(Debug, Default)]
+pub struct LogViewer;
+#[cfg(feature = "gui")]
+impl Displays for LogViewer {
+    fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
+        let logger = ProjectLogger::global();
+        Grid::new(ui.next_auto_id()).num_columns(2).show(ui, |ui| {
+            for subsystem in Subsystem::ALL {
+                ui.label(subsystem.to_string());
+                let mut level = logger.level(subsystem);
+                ComboBox::new(ui.next_auto_id(), "")
+                    .selected_text(level.to_string())
+                    .show_ui(ui, |ui| {
+                        for choice in LevelFilter::iter() {
+                            ui.selectable_value(&mut level, choice, choice.to_string());
+                        }
+                    });
+                if level != logger.level(subsystem) {
+                    logger.set_level(subsystem, level);
+                }
+                ui.end_row();
+            }
+        });
+        let response = ui.button("Clear");
+        if response.clicked() {
+            logger.clear();
+        }
+        ScrollArea::vertical()
+            .id_source("log")
+            .max_height(240.0)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                for record in logger.records() {
+                    let color = match record.level {
+                        Level::Error => Color32::RED,
+                        Level::Warn => Color32::YELLOW,
+                        _ => ui.visuals().text_color(),
+                    };
+                    let text = format!(
+                        "{:8.3} {:5} {}: {}",
+                        record.at.as_secs_f64(),
+                        record.level,
+                        record.subsystem,
+                        record.message
+                    );
+                    ui.label(RichText::new(text).monospace().color(color));
+                }
+            });
+        response
+    }
+}
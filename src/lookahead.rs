@@ -0,0 +1,173 @@
+use crate::traits::ReportsLatency;
+#[cfg(feature = "gui")]
+use eframe::egui::Slider;
+use ensnare::prelude::*;
+use ensnare_proc_macros::{Control, IsEntity, Metadata};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+const MAX_LOOKAHEAD_SECONDS: f64 = 0.01;
+const MIN_RELEASE_SECONDS: f64 = 0.001;
+const MAX_RELEASE_SECONDS: f64 = 1.0;
+
+/// A peak limiter that sees peaks coming. It holds the audio back by the
+/// lookahead time, and turns the gain down before a peak arrives rather than
+/// as it arrives, so even the first frame of a transient stays under the
+/// ceiling. The held-back audio makes the track late, so it reports the
+/// lookahead as its latency.
+#[derive(Debug, Control, IsEntity, Metadata, Serialize, Deserialize)]
+#[entity(Controls, GeneratesStereoSample)]
+pub struct LookaheadLimiter {
+    uid: Uid,
+
+    /// How far ahead to look, mapped linearly onto 0-10ms.
+    #[control]
+    lookahead: Normal,
+
+    /// The highest level that gets through.
+    #[control]
+    ceiling: Normal,
+
+    /// How quickly the gain recovers, mapped logarithmically onto 1ms-1s.
+    #[control]
+    release: Normal,
+
+    #[serde(skip)]
+    sample_rate: SampleRate,
+
+    /// The frames that are held back, and each one's peak.
+    #[serde(skip)]
+    window: VecDeque<(StereoSample, f64)>,
+
+    #[serde(skip)]
+    gain: f64,
+}
+impl Default for LookaheadLimiter {
+    fn default() -> Self {
+        let mut r = Self {
+            uid: Default::default(),
+            lookahead: Normal::from(0.5),
+            ceiling: Normal::from(0.9),
+            release: Normal::from(0.5),
+            sample_rate: Default::default(),
+            window: Default::default(),
+            gain: 1.0,
+        };
+        r.fit_window();
+        r
+    }
+}
+impl TransformsAudio for LookaheadLimiter {
+    fn transform(&mut self, samples: &mut [StereoSample]) {
+        let ceiling = self.ceiling.0;
+        let release = 1.0 - self.coefficient(self.release);
+        for sample in samples.iter_mut() {
+            let peak = sample.0 .0.abs().max(sample.1 .0.abs());
+            self.window.push_back((*sample, peak));
+            let loudest = self
+                .window
+                .iter()
+                .map(|(_, peak)| *peak)
+                .fold(0.0, f64::max);
+            let target = if loudest > ceiling {
+                ceiling / loudest
+            } else {
+                1.0
+            };
+            if target < self.gain {
+                self.gain = target;
+            } else {
+                self.gain += (target - self.gain) * release;
+            }
+            if let Some((delayed, _)) = self.window.pop_front() {
+                *sample = StereoSample(
+                    Sample(delayed.0 .0 * self.gain),
+                    Sample(delayed.1 .0 * self.gain),
+                );
+            }
+        }
+    }
+}
+impl Serializable for LookaheadLimiter {
+    fn after_deser(&mut self) {
+        self.gain = 1.0;
+        self.fit_window();
+    }
+}
+impl HandlesMidi for LookaheadLimiter {}
+impl Configurable for LookaheadLimiter {
+    fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate;
+        self.fit_window();
+    }
+}
+impl ReportsLatency for LookaheadLimiter {
+    fn latency(&self) -> usize {
+        self.lookahead_frames()
+    }
+}
+#[cfg(feature = "gui")]
+impl Displays for LookaheadLimiter {
+    fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
+        let response = ui.label(format!("Gain: {:.4}", self.gain));
+        let mut lookahead = self.lookahead.0;
+        let slider = Slider::new(&mut lookahead, Normal::range()).text("Lookahead");
+        if ui.add(slider).changed() {
+            self.set_lookahead(Normal::from(lookahead));
+        }
+        ui.label(format!("{} frames", self.lookahead_frames()));
+        let mut ceiling = self.ceiling.0;
+        let slider = Slider::new(&mut ceiling, Normal::range()).text("Ceiling");
+        if ui.add(slider).changed() {
+            self.set_ceiling(Normal::from(ceiling));
+        }
+        let mut release = self.release.0;
+        let slider = Slider::new(&mut release, Normal::range()).text("Release");
+        if ui.add(slider).changed() {
+            self.set_release(Normal::from(release));
+        }
+        response
+    }
+}
+#[cfg(not(feature = "gui"))]
+impl Displays for LookaheadLimiter {}
+impl LookaheadLimiter {
+    fn lookahead_frames(&self) -> usize {
+        (self.lookahead.0 * MAX_LOOKAHEAD_SECONDS * self.sample_rate.0 as f64).round() as usize
+    }
+
+    /// Pads or trims the held-back frames to the lookahead time. Padding
+    /// inserts a gap of silence, and trimming skips ahead.
+    fn fit_window(&mut self) {
+        let frames = self.lookahead_frames();
+        while self.window.len() < frames {
+            self.window.push_front((StereoSample::SILENCE, 0.0));
+        }
+        let excess = self.window.len() - frames;
+        self.window.drain(..excess);
+    }
+
+    /// The one-pole smoothing coefficient for the given time control.
+    fn coefficient(&self, time: Normal) -> f64 {
+        let seconds =
+            MIN_RELEASE_SECONDS * (MAX_RELEASE_SECONDS / MIN_RELEASE_SECONDS).powf(time.0);
+        (-1.0 / (seconds * self.sample_rate.0 as f64)).exp()
+    }
+
+    fn set_lookahead(&mut self, lookahead: Normal) {
+        self.lookahead = lookahead;
+        self.fit_window();
+    }
+
+    fn set_ceiling(&mut self, ceiling: Normal) {
+        self.ceiling = ceiling;
+    }
+
+    fn set_release(&mut self, release: Normal) {
+        self.release = release;
+    }
+}
@@ -1,39 +1,62 @@
 use anyhow::anyhow;
 use crossbeam_channel::{Receiver, Select, Sender};
-use eframe::egui::{CentralPanel, ComboBox, Id, SidePanel};
-use engine::{Engine, EngineService, EngineServiceEvent, EngineServiceInput};
+use eframe::{
+    egui::{
+        CentralPanel, CollapsingHeader, ComboBox, DragValue, Id, ScrollArea, SidePanel,
+        TopBottomPanel,
+    },
+    epaint::Color32,
+};
+use audio_device::{AudioDevicePicker, AudioDeviceSelection};
+use console::{MessageConsole, ScriptConsole};
+use keyboard::QwertyKeyboard;
+use midi_ports::{MidiPortEvent, MidiPortMonitor};
+use settings::{Settings, SettingsService};
 use ensnare::{
     prelude::*,
     traits::ProvidesService,
     types::{CrossbeamChannel, MidiPortDescriptor},
 };
 use ensnare_services::prelude::*;
+use log::{debug, error, info, warn};
+use spike_actor_system::{
+    arrangement::ArrangementView,
+    audio_input::AudioInputService,
+    browser::EntityBrowser,
+    callback_audio::{LowLatencyAudioService, LowLatencyBackend},
+    engine::{Engine, EngineService, EngineServiceEvent, EngineServiceInput, StallDiagnostics},
+    executor::Executor,
+    jack_audio::JackService,
+    logging::{LogViewer, ProjectLogger},
+    meter::Meter,
+    notification::{report_error, Notification, Notifications, Severity, Toasts},
+    remote::RemoteControlService,
+    snapshot::EngineSnapshot,
+    stress::{run_stress_test, StressConfig},
+    trace::TraceViewer,
+};
 use std::{
-    sync::{atomic::Ordering, Arc, Mutex},
+    collections::HashMap,
+    sync::{Arc, Mutex},
     time::Duration,
 };
 
-mod actions;
-mod always;
-mod arp;
-mod busy;
-mod drone;
-mod engine;
-mod entity;
-mod mixer;
-mod quietener;
-mod subscription;
-mod track;
-mod traits;
-mod wav_writer;
-
-pub(crate) const ATOMIC_ORDERING: Ordering = Ordering::Relaxed;
+mod audio_device;
+mod console;
+mod keyboard;
+mod midi_ports;
+mod settings;
 
 #[derive(Debug)]
 enum AppServiceInput {
     Quit,
     MidiInputPortSelected(MidiPortDescriptor),
     MidiOutputPortSelected(MidiPortDescriptor),
+    /// MIDI that originated in the app, e.g., from the computer keyboard.
+    Midi(MidiChannel, MidiMessage),
+    AudioDeviceSelected(AudioDeviceSelection),
+    /// A request for the engine service, e.g., from the transport bar.
+    Engine(EngineServiceInput),
 }
 
 #[derive(Debug)]
@@ -42,6 +65,11 @@ enum AppServiceEvent {
     Reset(Arc<Mutex<Engine>>),
     MidiInputsRefreshed(Vec<MidiPortDescriptor>),
     MidiOutputsRefreshed(Vec<MidiPortDescriptor>),
+    /// The engine stopped producing audio.
+    EngineStalled(StallDiagnostics),
+    EngineSnapshot(EngineSnapshot),
+    /// Something the user should hear about, from anywhere in the process.
+    Notification(Notification),
 }
 
 /// Manages all the services that the app uses.
@@ -56,10 +84,20 @@ struct AppServiceManager {
 
     // reason = "We need to keep a reference to the service or else it'll be dropped"
     #[allow(dead_code)]
-    midi_service: MidiService,
+    midi_service: MidiPortMonitor,
     // reason = "We need to keep a reference to the service or else it'll be dropped"
     #[allow(dead_code)]
     engine_service: EngineService,
+    /// Drives the engine instead of the audio service, if [Self::JACK_VAR]
+    /// is set.
+    jack_service: Option<JackService>,
+    /// Drives the engine instead of the audio service, if
+    /// [Self::LOW_LATENCY_VAR] or [Settings::low_latency_period] is set.
+    low_latency_service: Option<LowLatencyAudioService>,
+    /// Listens for remote clients, if [Self::REMOTE_ADDRESS_VAR] is set.
+    remote_service: Option<RemoteControlService>,
+
+    settings_service: SettingsService,
 }
 impl ProvidesService<AppServiceInput, AppServiceEvent> for AppServiceManager {
     fn receiver(&self) -> &Receiver<AppServiceEvent> {
@@ -71,25 +109,177 @@ impl ProvidesService<AppServiceInput, AppServiceEvent> for AppServiceManager {
     }
 }
 impl AppServiceManager {
-    pub fn new() -> Self {
+    /// Set ACTOR_POOL_THREADS to run the actors on that many worker threads
+    /// instead of one thread each.
+    const POOL_THREADS_VAR: &'static str = "ACTOR_POOL_THREADS";
+    /// Set ACTOR_REMOTE_ADDRESS, e.g., to 127.0.0.1:9001, to accept remote
+    /// control over WebSocket on that address.
+    const REMOTE_ADDRESS_VAR: &'static str = "ACTOR_REMOTE_ADDRESS";
+    /// Set ACTOR_JACK to run the engine from a JACK server instead of the
+    /// default audio device.
+    const JACK_VAR: &'static str = "ACTOR_JACK";
+    /// Set ACTOR_LOW_LATENCY to a buffer size in frames, e.g., 128, to open
+    /// the default output with that buffer, whatever the settings say.
+    const LOW_LATENCY_VAR: &'static str = "ACTOR_LOW_LATENCY";
+
+    /// Starts the services, picking up where the given settings left off.
+    pub fn new_with(settings: &Settings) -> Self {
         let audio_service = CpalAudioService::default();
+        let engine_service = EngineService::new_with(Self::executor());
+        if let Some(path) = settings.capture_path.as_ref() {
+            engine_service.send_input(EngineServiceInput::SetCapturePath(path.clone()));
+        }
+        if let Some(directory) = settings.capture_directory.as_ref() {
+            let input = EngineServiceInput::SetCaptureDirectory(directory.clone());
+            engine_service.send_input(input);
+        }
+        let jack_service = Self::jack_service(engine_service.sender());
+        let low_latency_service = if jack_service.is_none() {
+            Self::low_latency_service(settings, engine_service.sender())
+        } else {
+            None
+        };
+        let remote_service = Self::remote_service(engine_service.sender());
         let r = Self {
             audio_service,
-            midi_service: MidiService::default(),
-            engine_service: EngineService::default(),
+            midi_service: MidiPortMonitor::new_with(
+                settings.midi_input.clone(),
+                settings.midi_output.clone(),
+            ),
+            engine_service,
+            jack_service,
+            low_latency_service,
+            remote_service,
+            settings_service: SettingsService::new(),
             inputs: Default::default(),
             events: Default::default(),
         };
-        r.start_thread();
+        r.start_thread(settings.audio_input.clone());
         r
     }
 
-    fn start_thread(&self) {
+    fn save_settings(&self, settings: Settings) {
+        self.settings_service.save(settings);
+    }
+
+    /// Waits for the settings to be written.
+    fn join_settings(&mut self) {
+        self.settings_service.quit_and_join();
+    }
+
+    fn executor() -> Executor {
+        let Ok(value) = std::env::var(Self::POOL_THREADS_VAR) else {
+            return Executor::Threaded;
+        };
+        match value.parse() {
+            Ok(worker_count) => Executor::new_pool(worker_count),
+            Err(e) => {
+                warn!("Ignoring {}={value}: {e}", Self::POOL_THREADS_VAR);
+                Executor::Threaded
+            }
+        }
+    }
+
+    fn jack_service(engine_sender: &Sender<EngineServiceInput>) -> Option<JackService> {
+        std::env::var_os(Self::JACK_VAR)?;
+        match JackService::new_with(engine_sender.clone()) {
+            Ok(service) => Some(service),
+            Err(e) => {
+                report_error("While connecting to JACK", &e);
+                None
+            }
+        }
+    }
+
+    fn low_latency_service(
+        settings: &Settings,
+        engine_sender: &Sender<EngineServiceInput>,
+    ) -> Option<LowLatencyAudioService> {
+        let period = match std::env::var(Self::LOW_LATENCY_VAR) {
+            Ok(value) => match value.parse() {
+                Ok(period) => period,
+                Err(e) => {
+                    warn!("Ignoring {}={value}: {e}", Self::LOW_LATENCY_VAR);
+                    settings.low_latency_period?
+                }
+            },
+            Err(_) => settings.low_latency_period?,
+        };
+        let backend = settings
+            .low_latency_backend
+            .unwrap_or_else(LowLatencyBackend::best_available);
+        match LowLatencyAudioService::new_with(backend, period, engine_sender.clone()) {
+            Ok(service) => {
+                let period = service.period();
+                info!("Playing through {backend:?} with {period} frames");
+                Some(service)
+            }
+            Err(e) => {
+                report_error("While opening the low-latency output", &e);
+                None
+            }
+        }
+    }
+
+    fn remote_service(engine_sender: &Sender<EngineServiceInput>) -> Option<RemoteControlService> {
+        let address = std::env::var(Self::REMOTE_ADDRESS_VAR).ok()?;
+        match RemoteControlService::new_with(&address, engine_sender.clone()) {
+            Ok(service) => {
+                info!("Accepting remote control on ws://{}", service.address());
+                Some(service)
+            }
+            Err(e) => {
+                report_error("While starting remote control", &e);
+                None
+            }
+        }
+    }
+
+    /// What's playing the engine's output, for the settings panel.
+    fn output_description(&self) -> String {
+        if self.jack_service.is_some() {
+            "JACK".to_string()
+        } else if let Some(service) = self.low_latency_service.as_ref() {
+            format!("{} with {} frames", service.backend(), service.period())
+        } else {
+            "The selected device, through a queue".to_string()
+        }
+    }
+
+    /// Waits up to the timeout for the engine service to finish shutting down
+    /// after [AppServiceInput::Quit].
+    fn join_engine(&mut self, timeout: Duration) -> anyhow::Result<()> {
+        self.engine_service.join(timeout)
+    }
+
+    /// Opens the audio input for recording. A missing default input is
+    /// common, so only a device that the user picked is worth a
+    /// notification.
+    fn audio_input(
+        name: Option<&str>,
+        sample_rate: SampleRate,
+        engine_sender: &Sender<EngineServiceInput>,
+    ) -> Option<AudioInputService> {
+        match AudioInputService::new_with(name, sample_rate, engine_sender.clone()) {
+            Ok(service) => Some(service),
+            Err(e) if name.is_some() => {
+                report_error("While opening the audio input", &e);
+                None
+            }
+            Err(e) => {
+                warn!("Not capturing audio input: {e:?}");
+                None
+            }
+        }
+    }
+
+    fn start_thread(&self, mut audio_input_name: Option<String>) {
         let midi_receiver = self.midi_service.receiver().clone();
         let midi_sender = self.midi_service.sender().clone();
 
         let engine_receiver = self.engine_service.receiver().clone();
         let engine_sender = self.engine_service.sender().clone();
+        let remote_service = self.remote_service.clone();
 
         // This one is backwards (receiver of input, sender of event) because it
         // is the set of channels that the app uses to talk with the service
@@ -101,17 +291,37 @@ impl AppServiceManager {
         let audio_receiver = self.audio_service.receiver().clone();
         let audio_sender = self.audio_service.sender().clone();
 
+        let notification_receiver = Notifications::global().receiver().clone();
+
+        let engine_audio_sender = match (&self.jack_service, &self.low_latency_service) {
+            (Some(jack_service), _) => jack_service.audio_sender(),
+            (None, Some(low_latency_service)) => low_latency_service.audio_sender(),
+            (None, None) => self.audio_service.sender(),
+        }
+        .clone();
+        // Another backend drives the engine instead of the audio service.
+        let is_audio_service_bypassed = !engine_audio_sender.same_channel(&audio_sender);
         let _ = engine_sender.try_send(EngineServiceInput::SetAudioSender(
-            self.audio_service.sender().clone(),
+            engine_audio_sender.clone(),
         ));
+        let jack_midi_sender = self
+            .jack_service
+            .as_ref()
+            .map(|jack_service| jack_service.midi_sender().clone());
 
         std::thread::spawn(move || {
+            // Opened once the engine reports its sample rate, and again
+            // whenever that or the chosen device changes.
+            let mut audio_input = None;
+            let mut audio_input_rate = None;
+
             let mut sel = Select::new();
 
             let audio_index = sel.recv(&audio_receiver);
             let service_manager_index = sel.recv(&service_manager_receiver);
             let midi_index = sel.recv(&midi_receiver);
             let engine_index = sel.recv(&engine_receiver);
+            let notification_index = sel.recv(&notification_receiver);
 
             loop {
                 let operation = sel.select();
@@ -122,8 +332,12 @@ impl AppServiceManager {
                         {
                             match input {
                                 AppServiceInput::Quit => {
-                                    println!("ServiceInput::Quit");
+                                    debug!("ServiceInput::Quit");
                                     let _ = audio_sender.try_send(CpalAudioServiceInput::Quit);
+                                    if is_audio_service_bypassed {
+                                        let _ = engine_audio_sender
+                                            .try_send(CpalAudioServiceInput::Quit);
+                                    }
                                     let _ = midi_sender.try_send(MidiServiceInput::Quit);
                                     let _ = engine_sender.try_send(EngineServiceInput::Quit);
                                     break;
@@ -136,28 +350,74 @@ impl AppServiceManager {
                                     let _ = midi_sender
                                         .try_send(MidiServiceInput::SelectMidiOutput(port));
                                 }
+                                AppServiceInput::Engine(input) => {
+                                    let _ = engine_sender.try_send(input);
+                                }
+                                AppServiceInput::Midi(channel, message) => {
+                                    let _ = engine_sender
+                                        .try_send(EngineServiceInput::Midi(channel, message));
+                                }
+                                AppServiceInput::AudioDeviceSelected(
+                                    AudioDeviceSelection::Input(name),
+                                ) => {
+                                    // The next snapshot reopens it.
+                                    audio_input = None;
+                                    audio_input_rate = None;
+                                    audio_input_name = Some(name);
+                                }
+                                AppServiceInput::AudioDeviceSelected(selection) => {
+                                    // TODO: CpalAudioService always opens the
+                                    // default devices. Forward this once it
+                                    // can switch; its Reset event will then
+                                    // reconfigure the engine.
+                                    warn!("Can't switch audio devices yet: {selection:?}");
+                                }
                             }
                         }
                     }
                     index if index == audio_index => {
                         if let Ok(event) = Self::recv_operation(operation, &audio_receiver) {
+                            if is_audio_service_bypassed {
+                                continue;
+                            }
                             match event {
                                 CpalAudioServiceEvent::Reset(new_sample_rate, new_channels) => {
                                     let _ = engine_sender.try_send(EngineServiceInput::Configure(
                                         SampleRate(new_sample_rate),
                                         new_channels,
+                                        None,
                                     ));
                                 }
                                 CpalAudioServiceEvent::FramesNeeded(count) => {
                                     let _ = engine_sender
                                         .try_send(EngineServiceInput::AudioQueueNeedsAudio(count));
                                 }
-                                CpalAudioServiceEvent::Underrun => eprintln!("FYI underrun"),
+                                CpalAudioServiceEvent::Underrun => {
+                                    let _ = engine_sender
+                                        .try_send(EngineServiceInput::AudioUnderrun);
+                                }
                             }
                         }
                     }
                     index if index == midi_index => {
                         if let Ok(event) = Self::recv_operation(operation, &midi_receiver) {
+                            let event = match event {
+                                MidiPortEvent::Service(event) => event,
+                                MidiPortEvent::Connected(name) => {
+                                    Notifications::global().post(
+                                        Severity::Info,
+                                        format!("MIDI port connected: {name}"),
+                                    );
+                                    continue;
+                                }
+                                MidiPortEvent::Disconnected(name) => {
+                                    Notifications::global().post(
+                                        Severity::Warning,
+                                        format!("MIDI port disconnected: {name}"),
+                                    );
+                                    continue;
+                                }
+                            };
                             match event {
                                 MidiServiceEvent::Midi(channel, message) => {
                                     let _ = engine_sender
@@ -182,19 +442,64 @@ impl AppServiceManager {
                     }
                     index if index == engine_index => {
                         if let Ok(event) = Self::recv_operation(operation, &engine_receiver) {
+                            if let Some(remote_service) = remote_service.as_ref() {
+                                remote_service.forward(&event);
+                            }
                             match event {
                                 EngineServiceEvent::Reset(new_o) => {
                                     let _ = service_manager_sender
                                         .try_send(AppServiceEvent::Reset(new_o));
                                 }
                                 EngineServiceEvent::Midi(channel, message) => {
+                                    if let Some(jack_midi_sender) = jack_midi_sender.as_ref() {
+                                        let _ = jack_midi_sender.try_send((channel, message));
+                                    }
                                     let _ = midi_sender
                                         .try_send(MidiServiceInput::Midi(channel, message));
                                 }
+                                EngineServiceEvent::MidiRealtime(_message) => {
+                                    // TODO: MidiService sends only channel
+                                    // messages. Forward these once it can
+                                    // send system realtime messages too.
+                                }
+                                EngineServiceEvent::Stalled(diagnostics) => {
+                                    let _ = service_manager_sender
+                                        .try_send(AppServiceEvent::EngineStalled(diagnostics));
+                                }
+                                EngineServiceEvent::Snapshot(snapshot) => {
+                                    if audio_input_rate != Some(snapshot.sample_rate) {
+                                        audio_input_rate = Some(snapshot.sample_rate);
+                                        // Let go of the device before
+                                        // opening it again.
+                                        drop(audio_input.take());
+                                        audio_input = Self::audio_input(
+                                            audio_input_name.as_deref(),
+                                            snapshot.sample_rate,
+                                            &engine_sender,
+                                        );
+                                    }
+                                    let _ = service_manager_sender
+                                        .try_send(AppServiceEvent::EngineSnapshot(snapshot));
+                                }
+                                // The UI doesn't wait on these, and failures
+                                // already show up as notifications. Remote
+                                // clients heard about them above.
+                                EngineServiceEvent::Done(..)
+                                | EngineServiceEvent::Failed(..)
+                                | EngineServiceEvent::TrackCreated(..)
+                                | EngineServiceEvent::EntityAdded(..) => {}
                             }
                         }
                     }
-                    _ => panic!("ServiceManager: Unexpected select index"),
+                    index if index == notification_index => {
+                        if let Ok(notification) =
+                            Self::recv_operation(operation, &notification_receiver)
+                        {
+                            let _ = service_manager_sender
+                                .try_send(AppServiceEvent::Notification(notification));
+                        }
+                    }
+                    index => error!("ServiceManager: unexpected select index {index}"),
                 }
             }
         });
@@ -209,16 +514,65 @@ struct ActorSystemApp {
     midi_input_selected: usize,
     midi_output_ports: Vec<MidiPortDescriptor>,
     midi_output_selected: usize,
+    audio_devices: AudioDevicePicker,
+    keyboard: QwertyKeyboard,
+    console: ScriptConsole,
+    message_console: MessageConsole,
+    log_viewer: LogViewer,
+    trace_viewer: TraceViewer,
+    arrangement: ArrangementView,
+    browser: EntityBrowser,
+    /// The most recent stall, until the user dismisses it.
+    stall: Option<StallDiagnostics>,
+    /// The latest copy of the engine state, which everything but the
+    /// engine settings draws from.
+    snapshot: Option<EngineSnapshot>,
+    /// The track meters, fed from the snapshots so that they can decay
+    /// between them.
+    meters: HashMap<TrackUid, Meter>,
+    toasts: Toasts,
+    settings: Settings,
+    /// What was last sent to be saved, to tell when [Self::settings] changed.
+    saved_settings: Settings,
 }
 impl eframe::App for ActorSystemApp {
     fn update(&mut self, ctx: &eframe::egui::Context, _frame: &mut eframe::Frame) {
         while let Ok(event) = self.service_manager.receiver().try_recv() {
             match event {
-                AppServiceEvent::Reset(new_o) => self.engine = Some(new_o),
-                AppServiceEvent::MidiInputsRefreshed(ports) => self.midi_input_ports = ports,
-                AppServiceEvent::MidiOutputsRefreshed(ports) => self.midi_output_ports = ports,
+                AppServiceEvent::Reset(new_o) => {
+                    self.console.set_engine(Arc::clone(&new_o));
+                    self.message_console.set_engine(Arc::clone(&new_o));
+                    self.engine = Some(new_o);
+                }
+                AppServiceEvent::MidiInputsRefreshed(ports) => {
+                    self.midi_input_selected =
+                        Self::index_of(&ports, self.settings.midi_input.as_deref());
+                    self.midi_input_ports = ports;
+                }
+                AppServiceEvent::MidiOutputsRefreshed(ports) => {
+                    self.midi_output_selected =
+                        Self::index_of(&ports, self.settings.midi_output.as_deref());
+                    self.midi_output_ports = ports;
+                }
+                AppServiceEvent::EngineStalled(diagnostics) => self.stall = Some(diagnostics),
+                AppServiceEvent::EngineSnapshot(snapshot) => {
+                    for (uid, level) in snapshot.levels.iter() {
+                        self.meters.entry(*uid).or_default().update(*level);
+                    }
+                    self.snapshot = Some(snapshot);
+                }
+                AppServiceEvent::Notification(notification) => self.toasts.push(notification),
             }
         }
+        // The transport bar draws from the latest snapshot, so it doesn't
+        // hold up the engine.
+        TopBottomPanel::top(Id::new("transport-bar")).show(ctx, |ui| {
+            if let Some(snapshot) = self.snapshot.as_ref() {
+                for input in snapshot.show(ui) {
+                    self.service_manager.send_input(AppServiceInput::Engine(input));
+                }
+            }
+        });
         SidePanel::right(Id::new("right-panel")).show(ctx, |ui| {
             ui.heading("MIDI");
             if !self.midi_input_ports.is_empty()
@@ -231,10 +585,10 @@ impl eframe::App for ActorSystemApp {
                     )
                     .changed()
             {
+                let port = self.midi_input_ports[self.midi_input_selected].clone();
+                self.settings.midi_input = Some(port.to_string());
                 self.service_manager
-                    .send_input(AppServiceInput::MidiInputPortSelected(
-                        self.midi_input_ports[self.midi_input_selected].clone(),
-                    ));
+                    .send_input(AppServiceInput::MidiInputPortSelected(port));
             }
 
             if !self.midi_output_ports.is_empty()
@@ -247,19 +601,143 @@ impl eframe::App for ActorSystemApp {
                     )
                     .changed()
             {
+                let port = self.midi_output_ports[self.midi_output_selected].clone();
+                self.settings.midi_output = Some(port.to_string());
+                self.service_manager
+                    .send_input(AppServiceInput::MidiOutputPortSelected(port));
+            }
+
+            ui.heading("Audio");
+            if let Some(selection) = self.audio_devices.show(ui) {
+                match &selection {
+                    AudioDeviceSelection::Output(name) => {
+                        self.settings.audio_output = Some(name.clone())
+                    }
+                    AudioDeviceSelection::Input(name) => {
+                        self.settings.audio_input = Some(name.clone())
+                    }
+                }
                 self.service_manager
-                    .send_input(AppServiceInput::MidiOutputPortSelected(
-                        self.midi_output_ports[self.midi_output_selected].clone(),
-                    ))
+                    .send_input(AppServiceInput::AudioDeviceSelected(selection));
             }
+            CollapsingHeader::new("Low-latency output").show(ui, |ui| {
+                self.ui_low_latency(ui);
+            });
+            ui.separator();
+
+            self.keyboard.ui(ui);
+            ui.separator();
+            self.console.ui(ui);
+            ui.separator();
+            self.message_console.ui(ui);
+            ui.separator();
+
+            ui.heading("Log");
+            self.log_viewer.ui(ui);
+            ui.separator();
+
+            ui.heading("Message trace");
+            self.trace_viewer.ui(ui);
+        });
+        for (channel, message) in self.keyboard.handle_input(ctx) {
+            self.service_manager
+                .send_input(AppServiceInput::Midi(channel, message));
+        }
+        let dropped_paths: Vec<_> = ctx.input(|i| {
+            i.raw
+                .dropped_files
+                .iter()
+                .filter_map(|f| f.path.clone())
+                .collect()
+        });
+        if let Some(engine) = self.engine.as_ref() {
+            for path in dropped_paths {
+                let is_midi_file = path.extension().is_some_and(|e| {
+                    e.eq_ignore_ascii_case("mid") || e.eq_ignore_ascii_case("midi")
+                });
+                let is_wav_file = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("wav"));
+                let result = if is_midi_file {
+                    engine.lock().unwrap().import_midi_file(&path)
+                } else if is_wav_file {
+                    engine.lock().unwrap().import_audio_file(&path)
+                } else {
+                    continue;
+                };
+                if let Err(e) = result {
+                    report_error(&format!("While importing {path:?}"), &e);
+                }
+            }
+        }
+        TopBottomPanel::bottom(Id::new("arrangement"))
+            .resizable(true)
+            .show(ctx, |ui| {
+                let Some(snapshot) = self.snapshot.as_ref() else {
+                    return;
+                };
+                let seek = self.arrangement.show(ui, snapshot);
+                if let Some(time) = seek {
+                    self.service_manager
+                        .send_input(AppServiceInput::Engine(EngineServiceInput::Seek(time)));
+                }
+            });
+        SidePanel::left(Id::new("browser")).show(ctx, |ui| {
+            ui.heading("Browser");
+            let Some(snapshot) = self.snapshot.as_ref() else {
+                return;
+            };
+            let tracks: Vec<_> = snapshot
+                .tracks
+                .iter()
+                .map(|track| (track.uid, track.name.clone()))
+                .collect();
+            let picked = ScrollArea::vertical()
+                .show(ui, |ui| self.browser.show(ui, &snapshot.registry, &tracks))
+                .inner;
+            if let Some((track_uid, item)) = picked {
+                self.service_manager.send_input(AppServiceInput::Engine(
+                    EngineServiceInput::AddBrowserItem(track_uid, item),
+                ));
+            }
+            self.settings.favorite_entities = self.browser.favorites().cloned().collect();
         });
         CentralPanel::default().show(ctx, |ui| {
+            if let Some(stall) = self.stall.as_ref() {
+                ui.colored_label(
+                    Color32::YELLOW,
+                    format!("Engine stalled: no audio for {} ms", stall.waited.as_millis()),
+                );
+                for track in stall.tracks.iter() {
+                    ui.label(format!("{}: {}", track.name, track.state));
+                }
+                if ui.button("Dismiss").clicked() {
+                    self.stall = None;
+                }
+                ui.separator();
+            }
+            // Only these settings still need the engine itself, so it's
+            // locked only while they're open.
             if let Some(engine) = self.engine.as_ref() {
-                if let Ok(mut engine) = engine.lock() {
-                    engine.ui(ui);
+                CollapsingHeader::new("Engine settings").show(ui, |ui| {
+                    if let Ok(mut engine) = engine.lock() {
+                        engine.ui(ui);
+                    }
+                });
+            }
+            if let Some(snapshot) = self.snapshot.as_ref() {
+                for input in snapshot.show_tracks(ui, &mut self.meters) {
+                    self.service_manager.send_input(AppServiceInput::Engine(input));
                 }
             }
         });
+        self.toasts.show(ctx);
+
+        let size = ctx.screen_rect().size();
+        self.settings.window_size = Some((size.x, size.y));
+        if self.settings != self.saved_settings {
+            self.saved_settings = self.settings.clone();
+            self.service_manager.save_settings(self.settings.clone());
+        }
+
         ctx.request_repaint_after(Duration::from_millis(100));
     }
 
@@ -268,32 +746,157 @@ impl eframe::App for ActorSystemApp {
             .service_manager
             .sender()
             .try_send(AppServiceInput::Quit);
+        if let Err(e) = self.service_manager.join_engine(Self::SHUTDOWN_TIMEOUT) {
+            error!("While shutting down: {e:?}");
+        }
+        // The service has already done this unless it timed out, in which
+        // case this is one more chance for the actors to finish.
+        if let Some(engine) = self.engine.as_ref() {
+            if let Err(e) = engine.lock().unwrap().shutdown(Self::SHUTDOWN_TIMEOUT) {
+                error!("While shutting down the engine: {e:?}");
+            }
+        }
+        self.service_manager.join_settings();
     }
 }
 impl ActorSystemApp {
     pub const NAME: &'static str = "ActorSystemApp";
+    /// How long quitting waits for the WAV file to be finalized and the
+    /// actors to exit.
+    const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(3);
+    /// The buffer size that turning on the low-latency output starts with.
+    const DEFAULT_LOW_LATENCY_PERIOD: usize = 128;
 
-    pub fn new() -> Self {
+    pub fn new_with(settings: Settings) -> Self {
+        let mut audio_devices = AudioDevicePicker::new();
+        audio_devices.select(
+            settings.audio_output.as_deref(),
+            settings.audio_input.as_deref(),
+        );
         Self {
-            service_manager: AppServiceManager::new(),
+            service_manager: AppServiceManager::new_with(&settings),
             engine: Default::default(),
             midi_input_ports: Default::default(),
             midi_input_selected: Default::default(),
             midi_output_ports: Default::default(),
             midi_output_selected: Default::default(),
+            audio_devices,
+            keyboard: Default::default(),
+            console: Default::default(),
+            message_console: Default::default(),
+            log_viewer: Default::default(),
+            trace_viewer: Default::default(),
+            arrangement: Default::default(),
+            browser: EntityBrowser::new_with(settings.favorite_entities.iter().cloned()),
+            stall: Default::default(),
+            snapshot: Default::default(),
+            meters: Default::default(),
+            toasts: Default::default(),
+            saved_settings: settings.clone(),
+            settings,
         }
     }
+
+    /// Picks the buffer size and backend that the output opens with next
+    /// time. The services start with the app, so changes wait for a restart.
+    fn ui_low_latency(&mut self, ui: &mut eframe::egui::Ui) {
+        let description = self.service_manager.output_description();
+        ui.label(format!("Playing: {description}"));
+        let mut is_enabled = self.settings.low_latency_period.is_some();
+        if ui
+            .checkbox(&mut is_enabled, "Fixed buffer")
+            .on_hover_text("Let the device's callback drive the engine")
+            .changed()
+        {
+            self.settings.low_latency_period =
+                is_enabled.then_some(Self::DEFAULT_LOW_LATENCY_PERIOD);
+        }
+        if let Some(period) = self.settings.low_latency_period.as_mut() {
+            ui.add(
+                DragValue::new(period)
+                    .clamp_range(Engine::MIN_BLOCK_SIZE..=4096)
+                    .suffix(" frames"),
+            );
+            let current = self
+                .settings
+                .low_latency_backend
+                .unwrap_or_else(LowLatencyBackend::best_available);
+            let mut backend = current;
+            let choices = LowLatencyBackend::ALL
+                .into_iter()
+                .filter(|b| b.is_available());
+            ComboBox::new(ui.next_auto_id(), "Backend")
+                .selected_text(backend.to_string())
+                .show_ui(ui, |ui| {
+                    for choice in choices {
+                        ui.selectable_value(&mut backend, choice, choice.to_string());
+                    }
+                });
+            if backend != current {
+                self.settings.low_latency_backend = Some(backend);
+            }
+            if cfg!(windows) && backend == LowLatencyBackend::System {
+                ui.weak("WASAPI runs in shared mode, which adds about 10 ms");
+            }
+        }
+        ui.weak("Takes effect when the app restarts");
+    }
+
+    /// Where the port with the given name is in the list. If it's not there,
+    /// the first port.
+    fn index_of(ports: &[MidiPortDescriptor], name: Option<&str>) -> usize {
+        name.and_then(|name| ports.iter().position(|p| p.to_string() == name))
+            .unwrap_or_default()
+    }
+}
+
+/// `spike-actor-system stress [tracks] [entities per track] [blocks] [seed]`
+/// runs the stress test without the UI, and fails if it found problems.
+fn stress(args: &[String]) -> anyhow::Result<()> {
+    let mut config = StressConfig::default();
+    let sizes = [
+        &mut config.tracks,
+        &mut config.entities_per_track,
+        &mut config.blocks,
+    ];
+    for (size, arg) in sizes.into_iter().zip(args) {
+        *size = arg
+            .parse()
+            .map_err(|e| anyhow!("Couldn't read \"{arg}\": {e}"))?;
+    }
+    if let Some(arg) = args.get(3) {
+        config.seed = arg
+            .parse()
+            .map_err(|e| anyhow!("Couldn't read \"{arg}\": {e}"))?;
+    }
+    let report = run_stress_test(&config);
+    println!("{report}");
+    if report.is_ok() {
+        Ok(())
+    } else {
+        Err(anyhow!("The stress test found problems"))
+    }
 }
 
 fn main() -> anyhow::Result<()> {
     const APP_NAME: &str = ActorSystemApp::NAME;
 
-    env_logger::init();
+    if let Err(e) = ProjectLogger::install() {
+        eprintln!("While setting up logging: {e:?}");
+    }
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "stress") {
+        return stress(&args[1..]);
+    }
 
+    let settings = Settings::load();
+    settings.apply_limits();
+    let (width, height) = settings.window_size.unwrap_or((1280.0, 720.0));
     let options = eframe::NativeOptions {
         viewport: eframe::egui::ViewportBuilder::default()
             .with_title(APP_NAME)
-            .with_inner_size(eframe::epaint::vec2(1280.0, 720.0))
+            .with_inner_size(eframe::epaint::vec2(width, height))
             .to_owned(),
         vsync: true,
         centered: true,
@@ -303,7 +906,7 @@ fn main() -> anyhow::Result<()> {
     if let Err(e) = eframe::run_native(
         APP_NAME,
         options,
-        Box::new(|_cc| Box::new(ActorSystemApp::new())),
+        Box::new(|_cc| Box::new(ActorSystemApp::new_with(settings))),
     ) {
         return Err(anyhow!("eframe::run_native failed: {:?}", e));
     }
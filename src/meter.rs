@@ -0,0 +1,127 @@
+use ensnare::prelude::*;
+use std::time::Instant;
+#[cfg(feature = "gui")]
+use eframe::{
+    egui::Sense,
+    epaint::{vec2, Color32, Rect, Stroke},
+};
+
+/// The lowest level a meter shows.
+#[cfg(feature = "gui")]
+const FLOOR_DB: f64 = -60.0;
+
+/// How long it takes a displayed level to fall by half.
+const HALF_LIFE_SECONDS: f64 = 0.25;
+
+/// Peak and RMS levels of one buffer of audio, across both channels.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MeterSnapshot {
+    pub(crate) peak: f64,
+    pub(crate) rms: f64,
+}
+impl MeterSnapshot {
+    pub fn new_with_frames(frames: &[StereoSample]) -> Self {
+        if frames.is_empty() {
+            return Self::default();
+        }
+        let mut peak: f64 = 0.0;
+        let mut sum_of_squares = 0.0;
+        for frame in frames {
+            peak = peak.max(frame.0 .0.abs()).max(frame.1 .0.abs());
+            sum_of_squares += frame.0 .0 * frame.0 .0 + frame.1 .0 * frame.1 .0;
+        }
+        Self {
+            peak,
+            rms: (sum_of_squares / (frames.len() * 2) as f64).sqrt(),
+        }
+    }
+
+    pub fn peak(&self) -> f64 {
+        self.peak
+    }
+
+    pub fn rms(&self) -> f64 {
+        self.rms
+    }
+}
+
+/// A level meter for the UI. New snapshots push the levels up immediately,
+/// and they then decay smoothly.
+#[derive(Debug, Clone)]
+pub struct Meter {
+    level: MeterSnapshot,
+    last_update: Instant,
+}
+impl Default for Meter {
+    fn default() -> Self {
+        Self {
+            level: Default::default(),
+            last_update: Instant::now(),
+        }
+    }
+}
+impl Meter {
+    pub fn update(&mut self, snapshot: MeterSnapshot) {
+        let now = Instant::now();
+        let decayed = self.decayed(now);
+        self.level = MeterSnapshot {
+            peak: decayed.peak.max(snapshot.peak),
+            rms: decayed.rms.max(snapshot.rms),
+        };
+        self.last_update = now;
+    }
+
+    /// The levels as they stand now, after decay.
+    pub fn level(&self) -> MeterSnapshot {
+        self.decayed(Instant::now())
+    }
+
+    fn decayed(&self, now: Instant) -> MeterSnapshot {
+        let elapsed = now.duration_since(self.last_update).as_secs_f64();
+        let factor = 0.5f64.powf(elapsed / HALF_LIFE_SECONDS);
+        MeterSnapshot {
+            peak: self.level.peak * factor,
+            rms: self.level.rms * factor,
+        }
+    }
+
+    /// Maps a linear level onto 0.0..=1.0 on a dB scale.
+    #[cfg(feature = "gui")]
+    fn level_to_fraction(level: f64) -> f32 {
+        if level <= 0.0 {
+            return 0.0;
+        }
+        ((20.0 * level.log10() - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0) as f32
+    }
+}
+#[cfg(feature = "gui")]
+impl Displays for Meter {
+    fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
+        let width = ui.available_width().min(128.0);
+        let (response, painter) = ui.allocate_painter(vec2(width, 8.0), Sense::hover());
+        let rect = response.rect;
+        let level = self.decayed(Instant::now());
+
+        painter.rect_filled(rect, 0.0, Color32::BLACK);
+        let rms_width = rect.width() * Self::level_to_fraction(level.rms);
+        painter.rect_filled(
+            Rect::from_min_size(rect.min, vec2(rms_width, rect.height())),
+            0.0,
+            Color32::DARK_GREEN,
+        );
+        let peak_x = rect.left() + rect.width() * Self::level_to_fraction(level.peak);
+        painter.vline(
+            peak_x,
+            rect.y_range(),
+            Stroke::new(
+                1.0,
+                if level.peak > 1.0 {
+                    Color32::RED
+                } else {
+                    Color32::LIGHT_GREEN
+                },
+            ),
+        );
+        response
+    }
+}
@@ -0,0 +1,87 @@
+use crate::trace::ActorId;
+use ensnare::types::CrossbeamChannel;
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+/// How long one actor spent on one piece of work, such as generating a
+/// buffer.
+#[derive(Debug, Clone, Copy)]
+pub struct ActorTiming {
+    pub actor: ActorId,
+    pub busy: Duration,
+}
+
+/// Turns the time that actors spend working into CPU usage. Actors send
+/// [ActorTiming]s over its channel, and the engine collects them at the start
+/// of each generation cycle.
+#[derive(Debug)]
+pub struct CpuMetrics {
+    timings: CrossbeamChannel<ActorTiming>,
+    window: Mutex<MetricsWindow>,
+}
+impl CpuMetrics {
+    /// How often the usage figures are updated.
+    pub const WINDOW: Duration = Duration::from_secs(1);
+
+    /// The metrics that all actors in the process report to.
+    pub fn global() -> &'static Self {
+        static METRICS: OnceLock<CpuMetrics> = OnceLock::new();
+        METRICS.get_or_init(|| Self {
+            timings: Default::default(),
+            window: Mutex::new(MetricsWindow {
+                started_at: Instant::now(),
+                busy: Default::default(),
+                usage: Default::default(),
+            }),
+        })
+    }
+
+    pub(crate) fn record(&self, actor: ActorId, busy: Duration) {
+        let _ = self.timings.sender.try_send(ActorTiming { actor, busy });
+    }
+
+    /// Adds up the timings that have arrived, and updates the usage figures
+    /// if the current window is over.
+    pub(crate) fn collect(&self) {
+        let mut window = self.window.lock().unwrap();
+        while let Ok(timing) = self.timings.receiver.try_recv() {
+            *window.busy.entry(timing.actor).or_default() += timing.busy;
+        }
+        let elapsed = window.started_at.elapsed();
+        if elapsed >= Self::WINDOW {
+            let elapsed = elapsed.as_secs_f32();
+            window.usage = window
+                .busy
+                .drain()
+                .map(|(actor, busy)| (actor, busy.as_secs_f32() / elapsed * 100.0))
+                .collect();
+            window.started_at = Instant::now();
+        }
+    }
+
+    /// The percentage of one core that the actor used during the last
+    /// complete window, or None if it did no work then.
+    pub fn usage(&self, actor: ActorId) -> Option<f32> {
+        self.window.lock().unwrap().usage.get(&actor).copied()
+    }
+}
+
+#[derive(Debug)]
+struct MetricsWindow {
+    started_at: Instant,
+    /// Time spent so far in the current window.
+    busy: HashMap<ActorId, Duration>,
+    /// Percentages from the last complete window.
+    usage: HashMap<ActorId, f32>,
+}
+
+/// Runs the work, and reports how long it took to [CpuMetrics::global].
+pub(crate) fn time_work<R>(actor: ActorId, work: impl FnOnce() -> R) -> R {
+    let started_at = Instant::now();
+    let result = work();
+    CpuMetrics::global().record(actor, started_at.elapsed());
+    result
+}
@@ -0,0 +1,312 @@
+use crate::clip::read_wav;
+use derivative::Derivative;
+use ensnare::prelude::*;
+use std::{
+    f64::consts::TAU,
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Where the metronome's clicks go.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ClickOutput {
+    /// Into the master output, so that they're also in captures and renders.
+    #[default]
+    Master,
+    /// Only to the audio device, so that captures of the master output stay
+    /// clean.
+    Cue,
+}
+impl ClickOutput {
+    pub const ALL: [ClickOutput; 2] = [Self::Master, Self::Cue];
+}
+impl Display for ClickOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ClickOutput::Master => "Master",
+            ClickOutput::Cue => "Cue",
+        })
+    }
+}
+
+/// What each click sounds like.
+#[derive(Debug, Default, Clone)]
+pub enum ClickSound {
+    /// A short sine burst, higher on accented beats.
+    #[default]
+    Beep,
+    /// A short, woody knock, louder on accented beats.
+    Sidestick,
+    /// A sound loaded from a file, softer on unaccented beats.
+    Sample(ClickSample),
+}
+impl Display for ClickSound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClickSound::Beep => f.write_str("Beep"),
+            ClickSound::Sidestick => f.write_str("Sidestick"),
+            ClickSound::Sample(sample) => match sample.path.file_name() {
+                Some(name) => write!(f, "{}", name.to_string_lossy()),
+                None => f.write_str("Sample"),
+            },
+        }
+    }
+}
+impl ClickSound {
+    const BEEP_SECONDS: f64 = 0.03;
+    const SIDESTICK_SECONDS: f64 = 0.04;
+    const LEVEL: f64 = 0.5;
+    const UNACCENTED_LEVEL: f64 = 0.6;
+
+    /// The click's `elapsed`th frame, or None once it has finished.
+    fn frame(&self, elapsed: usize, is_accented: bool, sample_rate: f64) -> Option<StereoSample> {
+        let seconds = elapsed as f64 / sample_rate;
+        let value = match self {
+            ClickSound::Beep => {
+                if seconds >= Self::BEEP_SECONDS {
+                    return None;
+                }
+                let frequency = if is_accented { 1500.0 } else { 1000.0 };
+                let envelope = 1.0 - seconds / Self::BEEP_SECONDS;
+                (TAU * frequency * seconds).sin() * envelope * Self::LEVEL
+            }
+            ClickSound::Sidestick => {
+                if seconds >= Self::SIDESTICK_SECONDS {
+                    return None;
+                }
+                // Two inharmonic partials that die away quickly.
+                let envelope = (-seconds * 150.0).exp();
+                let body = (TAU * 1750.0 * seconds).sin() + 0.6 * (TAU * 2630.0 * seconds).sin();
+                let level = Self::level(is_accented);
+                body * envelope * level * Self::LEVEL / 1.6
+            }
+            ClickSound::Sample(sample) => {
+                let index = (seconds * sample.sample_rate.0 as f64) as usize;
+                let frame = sample.frames.get(index)?;
+                let level = Self::level(is_accented);
+                return Some(StereoSample(
+                    Sample(frame.0 .0 * level),
+                    Sample(frame.1 .0 * level),
+                ));
+            }
+        };
+        Some(StereoSample::from(value))
+    }
+
+    fn level(is_accented: bool) -> f64 {
+        if is_accented {
+            1.0
+        } else {
+            Self::UNACCENTED_LEVEL
+        }
+    }
+}
+
+/// A click sound loaded from a WAV file.
+#[derive(Derivative, Clone)]
+#[derivative(Debug)]
+pub struct ClickSample {
+    path: PathBuf,
+    sample_rate: SampleRate,
+    #[derivative(Debug = "ignore")]
+    frames: Arc<Vec<StereoSample>>,
+}
+impl ClickSample {
+    pub fn new_from_wav(path: &Path) -> anyhow::Result<Self> {
+        let (sample_rate, frames) = read_wav(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            sample_rate,
+            frames: Arc::new(frames),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Clicks on each beat, accenting the first beat of each bar. Besides
+/// clicking along with playback, it counts in before recording, while the
+/// [Engine](crate::engine::Engine) holds the transport.
+#[derive(Debug, Default)]
+pub struct Metronome {
+    /// Click along with playback, not only during a count-in.
+    is_enabled: bool,
+    output: ClickOutput,
+    sound: ClickSound,
+    count_in_bars: usize,
+    /// The count-in that's running.
+    count_in: Option<CountIn>,
+    /// Frames since the sounding click started, and whether it's accented.
+    click: Option<(usize, bool)>,
+    /// Click audio for the current generation cycle.
+    frames: Vec<StereoSample>,
+}
+#[derive(Debug)]
+struct CountIn {
+    /// Frames since the count-in started.
+    elapsed: usize,
+    beat_frames: usize,
+    beats_per_bar: usize,
+    beat_count: usize,
+}
+impl CountIn {
+    fn frame_count(&self) -> usize {
+        self.beat_frames * self.beat_count
+    }
+}
+impl Metronome {
+    /// The count-in lengths that the UI offers.
+    pub const COUNT_IN_BARS: [usize; 3] = [0, 1, 2];
+
+    pub fn is_enabled(&self) -> bool {
+        self.is_enabled
+    }
+
+    pub fn set_enabled(&mut self, is_enabled: bool) {
+        self.is_enabled = is_enabled;
+    }
+
+    pub fn output(&self) -> ClickOutput {
+        self.output
+    }
+
+    pub fn set_output(&mut self, output: ClickOutput) {
+        self.output = output;
+    }
+
+    pub fn sound(&self) -> &ClickSound {
+        &self.sound
+    }
+
+    pub fn set_sound(&mut self, sound: ClickSound) {
+        self.sound = sound;
+    }
+
+    pub fn count_in_bars(&self) -> usize {
+        self.count_in_bars
+    }
+
+    pub fn set_count_in_bars(&mut self, count_in_bars: usize) {
+        self.count_in_bars = count_in_bars;
+    }
+
+    pub fn is_counting_in(&self) -> bool {
+        self.count_in.is_some()
+    }
+
+    /// Starts counting in. Returns false if the count-in is off, in which
+    /// case the transport should start right away.
+    pub fn start_count_in(
+        &mut self,
+        sample_rate: SampleRate,
+        tempo: Tempo,
+        time_signature: TimeSignature,
+    ) -> bool {
+        if self.count_in_bars == 0 {
+            return false;
+        }
+        let beats_per_bar = (time_signature.top as usize).max(1);
+        self.count_in = Some(CountIn {
+            elapsed: 0,
+            beat_frames: ((sample_rate.0 as f64 * 60.0 / tempo.0) as usize).max(1),
+            beats_per_bar,
+            beat_count: beats_per_bar * self.count_in_bars,
+        });
+        true
+    }
+
+    pub fn cancel_count_in(&mut self) {
+        self.count_in = None;
+    }
+
+    /// Returns true, once, when the count-in has run its course. The
+    /// transport then starts at the next generation cycle, so it can be up
+    /// to a block late.
+    pub fn take_finished_count_in(&mut self) -> bool {
+        if self
+            .count_in
+            .as_ref()
+            .is_some_and(|count_in| count_in.elapsed >= count_in.frame_count())
+        {
+            self.count_in = None;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Works out the clicks for the next `count` frames. During a count-in,
+    /// they follow the count-in's own beats; otherwise they follow the slice
+    /// of song time that the transport covers in those frames, if it's
+    /// playing.
+    pub fn advance(
+        &mut self,
+        count: usize,
+        time_range: Option<&TimeRange>,
+        sample_rate: SampleRate,
+        beats_per_bar: usize,
+    ) {
+        let mut onsets = Vec::default();
+        if let Some(count_in) = self.count_in.as_mut() {
+            let end = (count_in.elapsed + count).min(count_in.frame_count());
+            let mut beat = count_in.elapsed.div_ceil(count_in.beat_frames);
+            while beat * count_in.beat_frames < end {
+                let offset = beat * count_in.beat_frames - count_in.elapsed;
+                onsets.push((offset, beat % count_in.beats_per_bar == 0));
+                beat += 1;
+            }
+            count_in.elapsed += count;
+        } else if let Some(time_range) = time_range.filter(|_| self.is_enabled) {
+            let start = time_range.0.start.total_units();
+            let end = time_range.0.end.total_units();
+            let mut beat = start.div_ceil(MusicalTime::UNITS_IN_BEAT);
+            while beat * MusicalTime::UNITS_IN_BEAT < end {
+                let offset = (beat * MusicalTime::UNITS_IN_BEAT - start) * count / (end - start);
+                onsets.push((offset, beat % beats_per_bar.max(1) == 0));
+                beat += 1;
+            }
+        }
+        self.render(count, &onsets, sample_rate);
+    }
+
+    /// Adds the current cycle's clicks to the given frames, if they go to
+    /// the master output.
+    pub fn mix_into(&mut self, frames: &mut [StereoSample]) {
+        if self.output != ClickOutput::Master {
+            return;
+        }
+        for (frame, click) in frames.iter_mut().zip(self.frames.drain(..)) {
+            *frame += click;
+        }
+    }
+
+    /// The current cycle's clicks, if they go to the cue output.
+    pub fn cue_frames(&self) -> &[StereoSample] {
+        match self.output {
+            ClickOutput::Master => &[],
+            ClickOutput::Cue => &self.frames,
+        }
+    }
+
+    /// Plays the click sound from each onset.
+    fn render(&mut self, count: usize, onsets: &[(usize, bool)], sample_rate: SampleRate) {
+        self.frames.clear();
+        let sample_rate = sample_rate.0.max(1) as f64;
+        let mut onsets = onsets.iter().peekable();
+        for i in 0..count {
+            while let Some(&(_, is_accented)) = onsets.next_if(|(offset, _)| *offset <= i) {
+                self.click = Some((0, is_accented));
+            }
+            let Some((elapsed, is_accented)) = self.click else {
+                self.frames.push(StereoSample::SILENCE);
+                continue;
+            };
+            let frame = self.sound.frame(elapsed, is_accented, sample_rate);
+            self.frames.push(frame.unwrap_or(StereoSample::SILENCE));
+            self.click = frame.map(|_| (elapsed + 1, is_accented));
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use ensnare::prelude::*;
+use midly::live::SystemRealtime;
+
+/// Generates MIDI beat clock and transport messages, so that external gear
+/// can follow the engine.
+#[derive(Debug, Default)]
+pub struct MidiClock {
+    is_enabled: bool,
+    /// Messages waiting to go out.
+    pending: Vec<SystemRealtime>,
+}
+impl MidiClock {
+    /// MIDI beat clock runs at 24 pulses per quarter note.
+    const PULSES_PER_BEAT: usize = 24;
+
+    pub fn is_enabled(&self) -> bool {
+        self.is_enabled
+    }
+
+    pub fn set_enabled(&mut self, is_enabled: bool) {
+        self.is_enabled = is_enabled;
+    }
+
+    /// The transport started. `from_beginning` distinguishes Start from
+    /// Continue.
+    pub fn start(&mut self, from_beginning: bool) {
+        if self.is_enabled {
+            self.pending.push(if from_beginning {
+                SystemRealtime::Start
+            } else {
+                SystemRealtime::Continue
+            });
+        }
+    }
+
+    /// The transport stopped.
+    pub fn stop(&mut self) {
+        if self.is_enabled {
+            self.pending.push(SystemRealtime::Stop);
+        }
+    }
+
+    /// Emits a clock pulse for each pulse position within the time range.
+    pub fn advance(&mut self, time_range: &TimeRange) {
+        if !self.is_enabled {
+            return;
+        }
+        let start = Self::first_pulse_at_or_after(time_range.0.start);
+        let end = Self::first_pulse_at_or_after(time_range.0.end);
+        for _ in start..end {
+            self.pending.push(SystemRealtime::TimingClock);
+        }
+    }
+
+    /// Removes and returns the messages that have accumulated.
+    pub fn take_messages(&mut self) -> Vec<SystemRealtime> {
+        std::mem::take(&mut self.pending)
+    }
+
+    fn first_pulse_at_or_after(time: MusicalTime) -> usize {
+        (time.total_units() * Self::PULSES_PER_BEAT).div_ceil(MusicalTime::UNITS_IN_BEAT)
+    }
+}
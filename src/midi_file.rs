@@ -0,0 +1,174 @@
+use crate::{clip::MidiClip, executor::join_on_drop};
+use anyhow::anyhow;
+use ensnare::{prelude::*, traits::ProvidesService, types::CrossbeamChannel};
+use midly::{
+    num::{u15, u28, u4},
+    Format, Header, MetaMessage, Smf, Timing, TrackEvent, TrackEventKind,
+};
+use std::{
+    path::{Path, PathBuf},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+/// The resolution of exported files.
+const EXPORT_TICKS_PER_BEAT: usize = 960;
+
+/// Reads a Standard MIDI File and returns one [MidiClip] per SMF track that
+/// contains channel messages. Tracks with only meta events (tempo maps, track
+/// names, etc.) are skipped.
+pub fn import_midi_file(path: &Path) -> anyhow::Result<Vec<MidiClip>> {
+    let data = std::fs::read(path)?;
+    let smf = Smf::parse(&data).map_err(|e| anyhow!("Couldn't parse {path:?}: {e}"))?;
+    let ticks_per_beat = match smf.header.timing {
+        Timing::Metrical(ticks_per_beat) => ticks_per_beat.as_int() as usize,
+        Timing::Timecode(..) => {
+            return Err(anyhow!("{path:?}: SMPTE timecode files aren't supported"));
+        }
+    };
+    if ticks_per_beat == 0 {
+        return Err(anyhow!("{path:?}: header has zero ticks per beat"));
+    }
+
+    let mut clips = Vec::default();
+    for track in smf.tracks.iter() {
+        let mut clip = MidiClip::default();
+        let mut ticks = 0usize;
+        for event in track.iter() {
+            ticks += event.delta.as_int() as usize;
+            if let TrackEventKind::Midi { channel, message } = event.kind {
+                clip.record(
+                    ticks_to_musical_time(ticks, ticks_per_beat),
+                    MidiChannel(channel.as_int()),
+                    message,
+                );
+            }
+        }
+        if !clip.is_empty() {
+            clips.push(clip);
+        }
+    }
+    Ok(clips)
+}
+
+/// Writes the given clip to a single-track Standard MIDI File.
+pub fn export_midi_file(path: &Path, clip: &MidiClip) -> anyhow::Result<()> {
+    let mut track = Vec::default();
+    let mut last_ticks = 0;
+    for event in clip.iter() {
+        let ticks = musical_time_to_ticks(event.time, EXPORT_TICKS_PER_BEAT);
+        track.push(TrackEvent {
+            delta: u28::new((ticks - last_ticks) as u32),
+            kind: TrackEventKind::Midi {
+                channel: u4::new(event.channel.0),
+                message: event.message,
+            },
+        });
+        last_ticks = ticks;
+    }
+    track.push(TrackEvent {
+        delta: u28::new(0),
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+    });
+
+    let smf = Smf {
+        header: Header::new(
+            Format::SingleTrack,
+            Timing::Metrical(u15::new(EXPORT_TICKS_PER_BEAT as u16)),
+        ),
+        tracks: vec![track],
+    };
+    smf.save(path).map_err(|e| anyhow!("Couldn't write {path:?}: {e}"))
+}
+
+fn ticks_to_musical_time(ticks: usize, ticks_per_beat: usize) -> MusicalTime {
+    MusicalTime::new_with_units(ticks * MusicalTime::UNITS_IN_BEAT / ticks_per_beat)
+}
+
+fn musical_time_to_ticks(time: MusicalTime, ticks_per_beat: usize) -> usize {
+    time.total_units() * ticks_per_beat / MusicalTime::UNITS_IN_BEAT
+}
+
+#[derive(Debug)]
+pub enum MidiFileWriterInput {
+    /// Record a MIDI message at the given position.
+    Midi(MusicalTime, MidiChannel, MidiMessage),
+    /// Write everything captured so far to the given file.
+    Export(PathBuf),
+    /// Forget everything captured so far.
+    Clear,
+    Quit,
+}
+
+#[derive(Debug)]
+pub enum MidiFileWriterEvent {
+    Err(anyhow::Error),
+}
+
+/// Captures MIDI traffic and writes it to Standard MIDI Files on its own
+/// thread, so that nobody on the audio path waits for the disk.
+#[derive(Debug)]
+pub struct MidiFileWriterService {
+    inputs: CrossbeamChannel<MidiFileWriterInput>,
+    events: CrossbeamChannel<MidiFileWriterEvent>,
+    thread: Option<JoinHandle<()>>,
+}
+impl Drop for MidiFileWriterService {
+    fn drop(&mut self) {
+        let _ = self.inputs.sender.try_send(MidiFileWriterInput::Quit);
+        let name = "The MIDI file writer";
+        join_on_drop(self.thread.take(), Self::DROP_TIMEOUT, name);
+    }
+}
+impl Default for MidiFileWriterService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl MidiFileWriterService {
+    /// How long dropping the service waits for an export to finish.
+    const DROP_TIMEOUT: Duration = Duration::from_secs(2);
+
+    pub fn new() -> Self {
+        let mut r = Self {
+            inputs: Default::default(),
+            events: Default::default(),
+            thread: Default::default(),
+        };
+
+        r.thread = Some(r.start_thread());
+        r
+    }
+
+    fn start_thread(&self) -> JoinHandle<()> {
+        let receiver = self.inputs.receiver.clone();
+        let sender = self.events.sender.clone();
+        let mut clip = MidiClip::default();
+
+        std::thread::spawn(move || {
+            while let Ok(input) = receiver.recv() {
+                match input {
+                    MidiFileWriterInput::Midi(time, channel, message) => {
+                        clip.record(time, channel, message);
+                    }
+                    MidiFileWriterInput::Export(path) => {
+                        if let Err(e) = export_midi_file(&path, &clip) {
+                            let _ = sender.try_send(MidiFileWriterEvent::Err(e));
+                        }
+                    }
+                    MidiFileWriterInput::Clear => clip.clear(),
+                    MidiFileWriterInput::Quit => break,
+                }
+            }
+        })
+    }
+}
+impl ProvidesService<MidiFileWriterInput, MidiFileWriterEvent> for MidiFileWriterService {
+    fn receiver(&self) -> &crossbeam_channel::Receiver<MidiFileWriterEvent> {
+        &self.events.receiver
+    }
+
+    fn sender(&self) -> &crossbeam_channel::Sender<MidiFileWriterInput> {
+        &self.inputs.sender
+    }
+}
@@ -0,0 +1,104 @@
+#[cfg(feature = "gui")]
+use eframe::egui::{DragValue, Slider, Ui};
+use ensnare::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Reshapes the notes arriving at a track before its entities hear them.
+/// The default leaves them alone.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MidiInputProcessor {
+    /// Semitones to move each note by. Notes moved out of MIDI's range are
+    /// dropped.
+    pub transpose: i8,
+    /// Above 1.0, notes come out softer than they were played; below 1.0,
+    /// louder. Only the extremes stay put.
+    pub velocity_curve: f64,
+    /// Play every note at this velocity (Some), however hard it was played.
+    pub fixed_velocity: Option<u8>,
+}
+impl Default for MidiInputProcessor {
+    fn default() -> Self {
+        Self {
+            transpose: 0,
+            velocity_curve: 1.0,
+            fixed_velocity: None,
+        }
+    }
+}
+impl MidiInputProcessor {
+    /// The message as the track's entities should hear it, or None if it
+    /// should be dropped.
+    pub fn process(&self, message: MidiMessage) -> Option<MidiMessage> {
+        Some(match message {
+            // A NoteOn with zero velocity is a NoteOff, so it stays zero.
+            MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => MidiMessage::NoteOn {
+                key: self.transposed(key.as_int())?.into(),
+                vel: self.velocity(vel.as_int()).into(),
+            },
+            MidiMessage::NoteOn { key, vel } => MidiMessage::NoteOn {
+                key: self.transposed(key.as_int())?.into(),
+                vel,
+            },
+            MidiMessage::NoteOff { key, vel } => MidiMessage::NoteOff {
+                key: self.transposed(key.as_int())?.into(),
+                vel,
+            },
+            MidiMessage::Aftertouch { key, vel } => MidiMessage::Aftertouch {
+                key: self.transposed(key.as_int())?.into(),
+                vel,
+            },
+            _ => message,
+        })
+    }
+
+    fn transposed(&self, key: u8) -> Option<u8> {
+        let key = key as i16 + self.transpose as i16;
+        (0..=127).contains(&key).then_some(key as u8)
+    }
+
+    fn velocity(&self, velocity: u8) -> u8 {
+        if let Some(velocity) = self.fixed_velocity {
+            return velocity.clamp(1, 127);
+        }
+        let curved = (velocity as f64 / 127.0).powf(self.velocity_curve);
+        ((curved * 127.0).round() as u8).max(1)
+    }
+}
+#[cfg(feature = "gui")]
+impl MidiInputProcessor {
+    /// Returns true if anything changed.
+    pub fn show(&mut self, ui: &mut Ui) -> bool {
+        let mut changed = ui
+            .add(
+                DragValue::new(&mut self.transpose)
+                    .clamp_range(-48..=48)
+                    .prefix("Transpose: "),
+            )
+            .changed();
+        changed |= ui
+            .add(
+                Slider::new(&mut self.velocity_curve, 0.25..=4.0)
+                    .logarithmic(true)
+                    .text("Velocity curve"),
+            )
+            .on_hover_text("Higher makes notes softer")
+            .changed();
+        ui.horizontal(|ui| {
+            let mut is_fixed = self.fixed_velocity.is_some();
+            if ui.checkbox(&mut is_fixed, "Fixed velocity").changed() {
+                self.fixed_velocity = is_fixed.then_some(100);
+                changed = true;
+            }
+            if let Some(velocity) = self.fixed_velocity.as_mut() {
+                changed |= ui
+                    .add(DragValue::new(velocity).clamp_range(1..=127))
+                    .changed();
+            }
+        });
+        if ui.button("Reset").clicked() {
+            *self = Self::default();
+            changed = true;
+        }
+        changed
+    }
+}
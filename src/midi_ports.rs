@@ -0,0 +1,166 @@
+use crossbeam_channel::{Receiver, Select, Sender};
+use ensnare::{
+    traits::ProvidesService,
+    types::{CrossbeamChannel, MidiPortDescriptor},
+};
+use ensnare_services::prelude::*;
+use log::error;
+use std::time::{Duration, Instant};
+
+/// What [MidiPortMonitor] reports.
+#[derive(Debug)]
+pub enum MidiPortEvent {
+    /// Passed along from the [MidiService].
+    Service(MidiServiceEvent),
+    /// A MIDI port with this name appeared.
+    Connected(String),
+    /// A MIDI port with this name went away.
+    Disconnected(String),
+}
+
+/// Wraps a [MidiService], and notices when MIDI devices come and go. When the
+/// system's ports change, it starts a fresh service, which reports the new
+/// port lists, and then selects the user's ports again by name.
+#[derive(Debug)]
+pub struct MidiPortMonitor {
+    inputs: CrossbeamChannel<MidiServiceInput>,
+    events: CrossbeamChannel<MidiPortEvent>,
+}
+impl ProvidesService<MidiServiceInput, MidiPortEvent> for MidiPortMonitor {
+    fn receiver(&self) -> &Receiver<MidiPortEvent> {
+        &self.events.receiver
+    }
+
+    fn sender(&self) -> &Sender<MidiServiceInput> {
+        &self.inputs.sender
+    }
+}
+impl MidiPortMonitor {
+    /// How often to look for added or removed ports.
+    const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+    /// Starts the service, and opens the ports with the given names once
+    /// they're listed.
+    pub fn new_with(input: Option<String>, output: Option<String>) -> Self {
+        let r = Self {
+            inputs: Default::default(),
+            events: Default::default(),
+        };
+        r.start_thread(input, output);
+        r
+    }
+
+    /// The names of the system's MIDI inputs and outputs.
+    fn port_names() -> Vec<String> {
+        let mut names = Vec::default();
+        if let Ok(midi_in) = midir::MidiInput::new("port-monitor") {
+            names.extend(midi_in.ports().iter().filter_map(|p| midi_in.port_name(p).ok()));
+        }
+        if let Ok(midi_out) = midir::MidiOutput::new("port-monitor") {
+            names.extend(midi_out.ports().iter().filter_map(|p| midi_out.port_name(p).ok()));
+        }
+        names
+    }
+
+    /// The port in the list with the given name.
+    fn find_port(ports: &[MidiPortDescriptor], name: &str) -> Option<MidiPortDescriptor> {
+        ports.iter().find(|p| p.to_string() == name).cloned()
+    }
+
+    fn start_thread(&self, input: Option<String>, output: Option<String>) {
+        let receiver = self.inputs.receiver.clone();
+        let sender = self.events.sender.clone();
+
+        std::thread::spawn(move || {
+            let mut service = MidiService::default();
+            let mut port_names = Self::port_names();
+            let mut polled_at = Instant::now();
+
+            // The ports the user picked, by name, so that a new service can
+            // reopen them once it has listed its ports.
+            let mut is_reselecting_input = input.is_some();
+            let mut is_reselecting_output = output.is_some();
+            let mut selected_input = input;
+            let mut selected_output = output;
+
+            loop {
+                if polled_at.elapsed() >= Self::POLL_INTERVAL {
+                    polled_at = Instant::now();
+                    let new_port_names = Self::port_names();
+                    if new_port_names != port_names {
+                        for name in new_port_names.iter().filter(|n| !port_names.contains(n)) {
+                            let _ = sender.try_send(MidiPortEvent::Connected(name.clone()));
+                        }
+                        for name in port_names.iter().filter(|n| !new_port_names.contains(n)) {
+                            let _ = sender.try_send(MidiPortEvent::Disconnected(name.clone()));
+                        }
+                        port_names = new_port_names;
+
+                        let _ = service.sender().try_send(MidiServiceInput::Quit);
+                        service = MidiService::default();
+                        is_reselecting_input = selected_input.is_some();
+                        is_reselecting_output = selected_output.is_some();
+                    }
+                }
+
+                // The service can change, so build the selection each time.
+                let service_receiver = service.receiver().clone();
+                let mut sel = Select::default();
+                let input_index = sel.recv(&receiver);
+                let service_index = sel.recv(&service_receiver);
+                let Ok(operation) = sel.select_timeout(Self::POLL_INTERVAL) else {
+                    continue;
+                };
+                match operation.index() {
+                    index if index == input_index => {
+                        let Ok(input) = Self::recv_operation(operation, &receiver) else {
+                            break;
+                        };
+                        match &input {
+                            MidiServiceInput::SelectMidiInput(port) => {
+                                selected_input = Some(port.to_string());
+                            }
+                            MidiServiceInput::SelectMidiOutput(port) => {
+                                selected_output = Some(port.to_string());
+                            }
+                            _ => {}
+                        }
+                        let is_quit = matches!(input, MidiServiceInput::Quit);
+                        let _ = service.sender().try_send(input);
+                        if is_quit {
+                            break;
+                        }
+                    }
+                    index if index == service_index => {
+                        let Ok(event) = Self::recv_operation(operation, &service_receiver) else {
+                            continue;
+                        };
+                        match &event {
+                            MidiServiceEvent::InputPorts(ports) if is_reselecting_input => {
+                                is_reselecting_input = false;
+                                let name = selected_input.as_deref().unwrap_or_default();
+                                if let Some(port) = Self::find_port(ports, name) {
+                                    let _ = service
+                                        .sender()
+                                        .try_send(MidiServiceInput::SelectMidiInput(port));
+                                }
+                            }
+                            MidiServiceEvent::OutputPorts(ports) if is_reselecting_output => {
+                                is_reselecting_output = false;
+                                let name = selected_output.as_deref().unwrap_or_default();
+                                if let Some(port) = Self::find_port(ports, name) {
+                                    let _ = service
+                                        .sender()
+                                        .try_send(MidiServiceInput::SelectMidiOutput(port));
+                                }
+                            }
+                            _ => {}
+                        }
+                        let _ = sender.try_send(MidiPortEvent::Service(event));
+                    }
+                    index => error!("MidiPortMonitor: unexpected select index {index}"),
+                }
+            }
+        });
+    }
+}
@@ -1,40 +1,251 @@
-use eframe::egui::{Color32, Frame, Slider, Stroke};
+use crate::{
+    channels::ChannelLayout,
+    command::Command,
+    meter::{Meter, MeterSnapshot},
+    track::TrackInfo,
+};
+use anyhow::anyhow;
+use crossbeam_channel::Sender;
 use ensnare::{
     orchestration::TrackUid,
-    traits::Displays,
     types::{Normal, StereoSample},
 };
-use std::collections::HashMap;
+use std::{collections::HashMap, f64::consts::FRAC_PI_2};
+#[cfg(feature = "gui")]
+use {
+    eframe::egui::{Color32, ComboBox, Frame, RichText, Slider, Stroke},
+    ensnare::traits::Displays,
+};
+
+/// Which side of the crossfader a track is on.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CrossfadeGroup {
+    A,
+    B,
+    /// The crossfader doesn't affect the track.
+    #[default]
+    Through,
+}
+
+impl CrossfadeGroup {
+    #[cfg(feature = "gui")]
+    const LABELS: [(CrossfadeGroup, &'static str); 3] =
+        [(Self::A, "A"), (Self::Through, "-"), (Self::B, "B")];
+}
+
+/// How the crossfader trades group A for group B as it moves.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CrossfadeCurve {
+    Linear,
+    /// Keeps the overall loudness steady through the middle.
+    #[default]
+    EqualPower,
+    /// Both groups play at full level until the crossfader nearly reaches
+    /// one end, for cutting and scratching.
+    Cut,
+}
+impl CrossfadeCurve {
+    pub const ALL: [CrossfadeCurve; 3] = [Self::Linear, Self::EqualPower, Self::Cut];
+
+    /// How far from the end [CrossfadeCurve::Cut] starts fading.
+    const CUT_WIDTH: f64 = 0.05;
+
+    /// The gain of a group when the crossfader is `distance` away from that
+    /// group's end, from 0.0 (all the way over to it) to 1.0 (at the other
+    /// end).
+    fn gain(&self, distance: f64) -> f64 {
+        match self {
+            CrossfadeCurve::Linear => 1.0 - distance,
+            CrossfadeCurve::EqualPower => (distance * FRAC_PI_2).cos(),
+            CrossfadeCurve::Cut => ((1.0 - distance) / Self::CUT_WIDTH).min(1.0),
+        }
+    }
+}
 
 #[derive(Debug, Default)]
 pub struct MixerParamSet {
     level: Normal,
     muted: bool,
     relative_level: f64,
+    /// Which of the [ChannelLayout]'s pairs the track goes to.
+    output_pair: usize,
+    crossfade_group: CrossfadeGroup,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct Mixer {
     track_uids: Vec<TrackUid>,
     track_param_sets: HashMap<TrackUid, MixerParamSet>,
+    meters: HashMap<TrackUid, Meter>,
+    infos: HashMap<TrackUid, TrackInfo>,
+    channel_layout: ChannelLayout,
+    /// 0.0 plays only group A, and 1.0 only group B.
+    crossfader: Normal,
+    crossfade_curve: CrossfadeCurve,
+    /// Where the UI sends level and mute changes, so that they can be undone.
+    commands: Sender<Command>,
 }
 impl Mixer {
+    pub(crate) fn new_with(commands: Sender<Command>) -> Self {
+        Self {
+            track_uids: Default::default(),
+            track_param_sets: Default::default(),
+            meters: Default::default(),
+            infos: Default::default(),
+            channel_layout: Default::default(),
+            crossfader: Normal::from(0.5),
+            crossfade_curve: Default::default(),
+            commands,
+        }
+    }
+
     pub(crate) fn add_track(&mut self, track_uid: TrackUid) {
         self.track_uids.push(track_uid);
         self.track_param_sets.insert(track_uid, Default::default());
         self.recalc_relative_levels();
     }
 
+    pub(crate) fn remove_track(&mut self, track_uid: TrackUid) {
+        self.track_uids.retain(|t| *t != track_uid);
+        self.track_param_sets.remove(&track_uid);
+        self.meters.remove(&track_uid);
+        self.recalc_relative_levels();
+    }
+
+    /// Gives the `to` track the same level, mute state, output pair, and
+    /// crossfade group as `from`.
+    pub(crate) fn copy_track_settings(&mut self, from: TrackUid, to: TrackUid) {
+        let Some(from) = self.track_param_sets.get(&from) else {
+            return;
+        };
+        let (level, muted, output_pair) = (from.level, from.muted, from.output_pair);
+        let crossfade_group = from.crossfade_group;
+        if let Some(to) = self.track_param_sets.get_mut(&to) {
+            to.level = level;
+            to.muted = muted;
+            to.output_pair = output_pair;
+            to.crossfade_group = crossfade_group;
+            self.recalc_relative_levels();
+        }
+    }
+
+    /// Sets the track's level, and returns the old one.
+    pub(crate) fn set_level(
+        &mut self,
+        track_uid: TrackUid,
+        level: Normal,
+    ) -> anyhow::Result<Normal> {
+        let param_set = self
+            .track_param_sets
+            .get_mut(&track_uid)
+            .ok_or_else(|| anyhow!("Track {track_uid} isn't in the mixer"))?;
+        let old_level = param_set.level;
+        param_set.level = level;
+        self.recalc_relative_levels();
+        Ok(old_level)
+    }
+
+    /// Mutes or unmutes the track, and returns whether it was muted.
+    pub(crate) fn set_muted(&mut self, track_uid: TrackUid, muted: bool) -> anyhow::Result<bool> {
+        let param_set = self
+            .track_param_sets
+            .get_mut(&track_uid)
+            .ok_or_else(|| anyhow!("Track {track_uid} isn't in the mixer"))?;
+        Ok(std::mem::replace(&mut param_set.muted, muted))
+    }
+
+    /// Sends the track to a different output pair, and returns the old one.
+    pub(crate) fn set_output_pair(
+        &mut self,
+        track_uid: TrackUid,
+        pair: usize,
+    ) -> anyhow::Result<usize> {
+        if pair >= self.channel_layout.pair_count() {
+            return Err(anyhow!("{} has no output pair {pair}", self.channel_layout));
+        }
+        let param_set = self
+            .track_param_sets
+            .get_mut(&track_uid)
+            .ok_or_else(|| anyhow!("Track {track_uid} isn't in the mixer"))?;
+        Ok(std::mem::replace(&mut param_set.output_pair, pair))
+    }
+
+    /// Puts the track on a side of the crossfader, and returns the old side.
+    pub(crate) fn set_crossfade_group(
+        &mut self,
+        track_uid: TrackUid,
+        group: CrossfadeGroup,
+    ) -> anyhow::Result<CrossfadeGroup> {
+        let param_set = self
+            .track_param_sets
+            .get_mut(&track_uid)
+            .ok_or_else(|| anyhow!("Track {track_uid} isn't in the mixer"))?;
+        Ok(std::mem::replace(&mut param_set.crossfade_group, group))
+    }
+
+    /// Each track's level and mute state, in the mixer's order.
+    pub(crate) fn levels(&self) -> Vec<(TrackUid, Normal, bool)> {
+        self.track_uids
+            .iter()
+            .filter_map(|uid| {
+                let param_set = self.track_param_sets.get(uid)?;
+                Some((*uid, param_set.level, param_set.muted))
+            })
+            .collect()
+    }
+
+    /// Moves the crossfader, and returns where it was.
+    pub(crate) fn set_crossfader(&mut self, position: Normal) -> Normal {
+        std::mem::replace(&mut self.crossfader, position)
+    }
+
+    /// Changes the crossfade curve, and returns the old one.
+    pub(crate) fn set_crossfade_curve(&mut self, curve: CrossfadeCurve) -> CrossfadeCurve {
+        std::mem::replace(&mut self.crossfade_curve, curve)
+    }
+
+    /// What the crossfader does to the given group's level.
+    fn crossfade_gain(&self, group: CrossfadeGroup) -> f64 {
+        match group {
+            CrossfadeGroup::A => self.crossfade_curve.gain(self.crossfader.0),
+            CrossfadeGroup::B => self.crossfade_curve.gain(1.0 - self.crossfader.0),
+            CrossfadeGroup::Through => 1.0,
+        }
+    }
+
+    /// Tracks keep their output pairs, even ones that the new layout doesn't
+    /// have. Those go to the main pair until the layout has them again.
+    pub(crate) fn set_channel_layout(&mut self, channel_layout: ChannelLayout) {
+        self.channel_layout = channel_layout;
+    }
+
+    pub(crate) fn update_meter(&mut self, track_uid: TrackUid, snapshot: MeterSnapshot) {
+        self.meters.entry(track_uid).or_default().update(snapshot);
+    }
+
+    pub(crate) fn update_info(&mut self, track_uid: TrackUid, info: TrackInfo) {
+        self.infos.insert(track_uid, info);
+    }
+
+    /// Adds the track's frames to its output pair: `main`, or one of
+    /// `other_pairs`, which are the pairs after the main one.
     pub(crate) fn mix(
         &self,
         track_uid: TrackUid,
         source: &[StereoSample],
-        dest: &mut [StereoSample],
+        main: &mut [StereoSample],
+        other_pairs: &mut [Vec<StereoSample>],
     ) {
         if let Some(param_set) = self.track_param_sets.get(&track_uid) {
             if !param_set.muted && param_set.level != Normal::minimum() {
+                let dest = match param_set.output_pair.checked_sub(1) {
+                    Some(index) if index < other_pairs.len() => &mut other_pairs[index][..],
+                    _ => main,
+                };
+                let crossfade_gain = self.crossfade_gain(param_set.crossfade_group);
+                let gain = param_set.relative_level * crossfade_gain;
                 for (src, dst) in source.iter().zip(dest.iter_mut()) {
-                    *dst += *src * param_set.relative_level;
+                    *dst += *src * gain;
                 }
             }
         }
@@ -56,35 +267,92 @@ impl Mixer {
         }
     }
 }
+#[cfg(feature = "gui")]
 impl Displays for Mixer {
     fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
         ui.horizontal_top(|ui| {
-            let mut needs_level_recalc = false;
             for track_uid in self.track_uids.iter() {
-                if let Some(param_set) = self.track_param_sets.get_mut(track_uid) {
+                if let Some(param_set) = self.track_param_sets.get(track_uid) {
                     Frame::default()
                         .stroke(Stroke::new(0.2, Color32::YELLOW))
                         .show(ui, |ui| {
                             ui.set_width(64.0);
                             ui.set_height(192.0);
                             ui.vertical_centered(|ui| {
+                                if let Some(info) = self.infos.get(track_uid) {
+                                    ui.label(RichText::new(&info.name).color(info.color32()));
+                                } else {
+                                    ui.label(format!("Track {track_uid}"));
+                                }
                                 let mut level_f64 = param_set.level.0;
                                 if ui
                                     .add(Slider::new(&mut level_f64, Normal::range()).vertical())
                                     .changed()
                                 {
-                                    param_set.level.set(level_f64);
-                                    needs_level_recalc = true;
+                                    let _ = self.commands.send(Command::SetMixerLevel(
+                                        *track_uid,
+                                        Normal::from(level_f64),
+                                    ));
                                 }
 
-                                ui.checkbox(&mut param_set.muted, "Mute");
+                                let mut muted = param_set.muted;
+                                if ui.checkbox(&mut muted, "Mute").changed() {
+                                    let _ = self
+                                        .commands
+                                        .send(Command::SetMixerMute(*track_uid, muted));
+                                }
+                                let pair_count = self.channel_layout.pair_count();
+                                let mut pair = param_set.output_pair;
+                                if pair_count > 1
+                                    && ComboBox::new(ui.next_auto_id(), "")
+                                        .width(56.0)
+                                        .show_index(ui, &mut pair, pair_count, |i| {
+                                            self.channel_layout.pair_name(i)
+                                        })
+                                        .changed()
+                                {
+                                    let _ = self
+                                        .commands
+                                        .send(Command::SetOutputPair(*track_uid, pair));
+                                }
+                                let mut group = param_set.crossfade_group;
+                                ui.horizontal(|ui| {
+                                    for (option, label) in CrossfadeGroup::LABELS {
+                                        ui.selectable_value(&mut group, option, label);
+                                    }
+                                });
+                                if group != param_set.crossfade_group {
+                                    let _ = self
+                                        .commands
+                                        .send(Command::SetCrossfadeGroup(*track_uid, group));
+                                }
+                                self.meters.entry(*track_uid).or_default().ui(ui);
                             });
                         });
                 }
             }
-            if needs_level_recalc {
-                self.recalc_relative_levels();
-            }
+            ui.vertical(|ui| {
+                let mut position = self.crossfader.0;
+                if ui
+                    .add(Slider::new(&mut position, Normal::range()).text("A/B"))
+                    .changed()
+                {
+                    let _ = self
+                        .commands
+                        .send(Command::SetCrossfader(Normal::from(position)));
+                }
+                let mut curve = self.crossfade_curve;
+                ComboBox::new(ui.next_auto_id(), "Curve")
+                    .selected_text(format!("{curve:?}"))
+                    .show_ui(ui, |ui| {
+                        for option in CrossfadeCurve::ALL {
+                            ui.selectable_value(&mut curve, option, format!("{option:?}"));
+                        }
+                    });
+                if curve != self.crossfade_curve {
+                    let _ = self.commands.send(Command::SetCrossfadeCurve(curve));
+                }
+            });
         })
         .response
     }
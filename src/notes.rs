@@ -0,0 +1,91 @@
+use ensnare::prelude::*;
+use std::collections::{BTreeSet, HashMap};
+
+/// The notes that are sounding, grouped by who sent their NoteOn. When the
+/// sender goes away before sending the NoteOff, or a receiver goes away
+/// before hearing it, this is what's needed to release the note instead of
+/// leaving it stuck.
+#[derive(Debug, Default)]
+pub struct ActiveNotes {
+    /// The key is the entity that played the note, or None for notes from
+    /// outside the track, including its MIDI clip. Each note is a channel and
+    /// a key.
+    notes: HashMap<Option<Uid>, BTreeSet<(u8, u8)>>,
+}
+impl ActiveNotes {
+    /// Updates the bookkeeping for a message that's on its way to the
+    /// track's entities.
+    pub fn note(&mut self, source: Option<Uid>, channel: MidiChannel, message: &MidiMessage) {
+        match *message {
+            // By convention, a NoteOn with zero velocity is a NoteOff.
+            MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => {
+                self.notes
+                    .entry(source)
+                    .or_default()
+                    .insert((channel.0, key.as_int()));
+            }
+            MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                if let Some(notes) = self.notes.get_mut(&source) {
+                    notes.remove(&(channel.0, key.as_int()));
+                }
+            }
+            // All Notes Off.
+            MidiMessage::Controller { controller, .. } if controller.as_int() == 123 => {
+                for notes in self.notes.values_mut() {
+                    notes.retain(|(c, _)| *c != channel.0);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Forgets the notes that the source played, and returns NoteOffs for
+    /// them.
+    pub fn release_from(&mut self, source: Option<Uid>) -> Vec<(MidiChannel, MidiMessage)> {
+        let notes = self.notes.remove(&source).unwrap_or_default();
+        notes.into_iter().map(Self::note_off).collect()
+    }
+
+    /// NoteOffs for the sounding notes whose sources pass the filter, along
+    /// with their sources. The notes stay in the bookkeeping, because other
+    /// receivers still hear them.
+    pub fn note_offs(
+        &self,
+        filter: impl Fn(Option<Uid>) -> bool,
+    ) -> Vec<(Option<Uid>, MidiChannel, MidiMessage)> {
+        self.notes
+            .iter()
+            .filter(|(source, _)| filter(**source))
+            .flat_map(|(&source, notes)| {
+                notes.iter().map(move |&note| {
+                    let (channel, message) = Self::note_off(note);
+                    (source, channel, message)
+                })
+            })
+            .collect()
+    }
+
+    /// The sources that have notes sounding.
+    pub fn sources(&self) -> Vec<Option<Uid>> {
+        self.notes
+            .iter()
+            .filter(|(_, notes)| !notes.is_empty())
+            .map(|(&source, _)| source)
+            .collect()
+    }
+
+    /// Whether no notes are sounding.
+    pub fn is_empty(&self) -> bool {
+        self.notes.values().all(|notes| notes.is_empty())
+    }
+
+    fn note_off((channel, key): (u8, u8)) -> (MidiChannel, MidiMessage) {
+        (
+            MidiChannel(channel),
+            MidiMessage::NoteOff {
+                key: key.into(),
+                vel: 0.into(),
+            },
+        )
+    }
+}
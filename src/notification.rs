@@ -0,0 +1,118 @@
+use crate::limits::LimitReached;
+use crossbeam_channel::Receiver;
+use ensnare::types::CrossbeamChannel;
+use log::{error, warn};
+use std::sync::OnceLock;
+#[cfg(feature = "gui")]
+use {
+    eframe::{
+        egui::{Align2, Area, Context, Frame, Id},
+        epaint::{vec2, Color32},
+    },
+    std::time::{Duration, Instant},
+};
+
+/// How much a [Notification] matters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// Something the user should hear about, such as a file that couldn't be
+/// opened.
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Carries [Notification]s from anywhere in the process to whoever shows
+/// them. The app's service manager forwards them to the UI; without a UI,
+/// nobody reads them, and [report_error] still prints them.
+#[derive(Debug)]
+pub struct Notifications {
+    channel: CrossbeamChannel<Notification>,
+}
+impl Notifications {
+    /// The channel that the whole process posts to.
+    pub fn global() -> &'static Self {
+        static NOTIFICATIONS: OnceLock<Notifications> = OnceLock::new();
+        NOTIFICATIONS.get_or_init(|| Self {
+            channel: Default::default(),
+        })
+    }
+
+    pub fn post(&self, severity: Severity, message: impl Into<String>) {
+        let _ = self.channel.sender.try_send(Notification {
+            severity,
+            message: message.into(),
+        });
+    }
+
+    pub fn receiver(&self) -> &Receiver<Notification> {
+        &self.channel.receiver
+    }
+}
+
+/// Logs the error, and tells the user about it. Reaching a [LimitReached]
+/// is only a warning.
+pub fn report_error(context: &str, e: &anyhow::Error) {
+    if let Some(limit) = e.downcast_ref::<LimitReached>() {
+        warn!("{context}: {limit}");
+        Notifications::global().post(Severity::Warning, limit.to_string());
+        return;
+    }
+    error!("{context}: {e:?}");
+    Notifications::global().post(Severity::Error, format!("{context}: {e}"));
+}
+
+/// Shows [Notification]s as a stack of toasts in the corner of the window.
+#[cfg(feature = "gui")]
+#[derive(Debug, Default)]
+pub struct Toasts {
+    toasts: Vec<(Notification, Instant)>,
+}
+#[cfg(feature = "gui")]
+impl Toasts {
+    /// How long info and warnings stay up. Errors stay until dismissed.
+    const LIFETIME: Duration = Duration::from_secs(8);
+
+    pub fn push(&mut self, notification: Notification) {
+        self.toasts.push((notification, Instant::now()));
+    }
+
+    pub fn show(&mut self, ctx: &Context) {
+        self.toasts.retain(|(notification, shown_at)| {
+            notification.severity == Severity::Error || shown_at.elapsed() < Self::LIFETIME
+        });
+        if self.toasts.is_empty() {
+            return;
+        }
+
+        let mut dismissed = None;
+        Area::new(Id::new("toasts"))
+            .anchor(Align2::RIGHT_BOTTOM, vec2(-8.0, -8.0))
+            .show(ctx, |ui| {
+                for (i, (notification, _)) in self.toasts.iter().enumerate() {
+                    Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            let color = match notification.severity {
+                                Severity::Info => ui.visuals().text_color(),
+                                Severity::Warning => Color32::YELLOW,
+                                Severity::Error => Color32::LIGHT_RED,
+                            };
+                            ui.colored_label(color, &notification.message);
+                            if ui.small_button("Dismiss").clicked() {
+                                dismissed = Some(i);
+                            }
+                        });
+                    });
+                }
+            });
+        if let Some(i) = dismissed {
+            self.toasts.remove(i);
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+#[cfg(feature = "gui")]
+use {
+    eframe::{
+        egui::{CollapsingHeader, Sense},
+        epaint::{pos2, vec2, Color32, Stroke},
+    },
+    ensnare::prelude::*,
+};
+
+/// How quickly the actor system is turning buffers around.
+#[derive(Debug, Default, Clone)]
+pub struct EnginePerformance {
+    /// From asking the master track for a block to getting it back.
+    pub round_trip: Duration,
+    /// The longest round trip since the block size last changed.
+    pub worst_round_trip: Duration,
+    /// Frames that the audio queue has asked for but not yet received.
+    pub frames_pending: usize,
+    /// How many times the audio device ran out of frames since the block size
+    /// last changed.
+    pub underruns: usize,
+    /// Samples of [EnginePerformance::frames_pending], oldest first, taken
+    /// every [EnginePerformance::HISTORY_INTERVAL].
+    pub queue_depth_history: VecDeque<usize>,
+    /// How busy each of [EngineService](crate::engine::EngineService)'s
+    /// channels was during the last second.
+    pub message_rates: MessageRates,
+    history_sampled_at: Option<Instant>,
+}
+impl EnginePerformance {
+    pub const HISTORY_INTERVAL: Duration = Duration::from_millis(50);
+    pub const HISTORY_LEN: usize = 200;
+
+    pub(crate) fn record(&mut self, round_trip: Duration, frames_pending: usize) {
+        self.round_trip = round_trip;
+        self.worst_round_trip = self.worst_round_trip.max(round_trip);
+        self.frames_pending = frames_pending;
+        let is_sample_due = match self.history_sampled_at {
+            Some(sampled_at) => sampled_at.elapsed() >= Self::HISTORY_INTERVAL,
+            None => true,
+        };
+        if is_sample_due {
+            self.history_sampled_at = Some(Instant::now());
+            if self.queue_depth_history.len() == Self::HISTORY_LEN {
+                self.queue_depth_history.pop_front();
+            }
+            self.queue_depth_history.push_back(frames_pending);
+        }
+    }
+}
+
+/// Messages per second on each of
+/// [EngineService](crate::engine::EngineService)'s channels.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MessageRates {
+    /// [EngineServiceInput](crate::engine::EngineServiceInput)s from the app.
+    pub inputs: f64,
+    /// Blocks from the master track.
+    pub audio: f64,
+    /// MIDI from the master track.
+    pub midi: f64,
+}
+
+/// Counts the messages that arrive on each channel, and turns the counts
+/// into [MessageRates] once a second.
+#[derive(Debug)]
+pub(crate) struct MessageCounter {
+    started_at: Instant,
+    pub(crate) inputs: usize,
+    pub(crate) audio: usize,
+    pub(crate) midi: usize,
+}
+impl Default for MessageCounter {
+    fn default() -> Self {
+        Self {
+            started_at: Instant::now(),
+            inputs: Default::default(),
+            audio: Default::default(),
+            midi: Default::default(),
+        }
+    }
+}
+impl MessageCounter {
+    const WINDOW: Duration = Duration::from_secs(1);
+
+    /// Returns the rates and starts counting again, if a second has passed.
+    pub(crate) fn take_rates(&mut self) -> Option<MessageRates> {
+        let elapsed = self.started_at.elapsed();
+        if elapsed < Self::WINDOW {
+            return None;
+        }
+        let seconds = elapsed.as_secs_f64();
+        let rates = MessageRates {
+            inputs: self.inputs as f64 / seconds,
+            audio: self.audio as f64 / seconds,
+            midi: self.midi as f64 / seconds,
+        };
+        *self = Self::default();
+        Some(rates)
+    }
+}
+
+#[cfg(feature = "gui")]
+impl EnginePerformance {
+    /// Shows everything in a collapsible panel. The sample rate converts
+    /// frame counts to milliseconds.
+    pub(crate) fn show(&self, ui: &mut eframe::egui::Ui, sample_rate: SampleRate) {
+        let sample_rate = sample_rate.0.max(1) as f64;
+        CollapsingHeader::new("Performance").show(ui, |ui| {
+            ui.label(format!(
+                "Round trip: {:.2} ms (worst {:.2} ms)",
+                self.round_trip.as_secs_f64() * 1000.0,
+                self.worst_round_trip.as_secs_f64() * 1000.0
+            ));
+            ui.label(format!(
+                "Pending: {} frames ({:.1} ms)",
+                self.frames_pending,
+                self.frames_pending as f64 * 1000.0 / sample_rate
+            ));
+            ui.label(format!("Underruns: {}", self.underruns));
+            ui.label(format!(
+                "Messages/s: {:.0} inputs, {:.0} audio, {:.0} MIDI",
+                self.message_rates.inputs, self.message_rates.audio, self.message_rates.midi
+            ));
+            self.ui_queue_depth(ui);
+        });
+    }
+
+    /// A line graph of the queue depth, scaled to its recent peak.
+    fn ui_queue_depth(&self, ui: &mut eframe::egui::Ui) {
+        let (response, painter) =
+            ui.allocate_painter(vec2(ui.available_width().min(400.0), 48.0), Sense::hover());
+        let rect = response.rect;
+        painter.rect_filled(rect, 0.0, Color32::BLACK);
+        let peak = self.queue_depth_history.iter().copied().max().unwrap_or_default();
+        if peak == 0 {
+            return;
+        }
+        let step = rect.width() / (Self::HISTORY_LEN - 1) as f32;
+        let points = self
+            .queue_depth_history
+            .iter()
+            .enumerate()
+            .map(|(i, &depth)| {
+                let fraction = depth as f32 / peak as f32;
+                pos2(rect.left() + i as f32 * step, rect.bottom() - fraction * rect.height())
+            })
+            .collect();
+        painter.line(points, Stroke::new(1.0, Color32::LIGHT_GREEN));
+        response.on_hover_text(format!("Queue depth (peak {peak} frames)"));
+    }
+}
@@ -0,0 +1,127 @@
+use eframe::{
+    egui::{Response, Sense, Ui},
+    epaint::{pos2, vec2, Color32, Pos2, Rect, Stroke},
+};
+use ensnare::prelude::*;
+
+/// Which of the twelve semitones in an octave are black keys.
+const IS_BLACK: [bool; 12] = [
+    false, true, false, true, false, false, true, false, true, false, true, false,
+];
+
+/// A clickable piano keyboard. Holding the mouse button down on a key plays
+/// it, and dragging across keys plays each in turn.
+#[derive(Debug)]
+pub struct Piano {
+    /// The note of the leftmost key. Should be a C.
+    first_note: u8,
+    octave_count: u8,
+    velocity: u8,
+    /// The note the pointer is holding down, if any.
+    held_note: Option<u8>,
+}
+impl Default for Piano {
+    fn default() -> Self {
+        Self {
+            first_note: 48,
+            octave_count: 2,
+            velocity: 100,
+            held_note: None,
+        }
+    }
+}
+impl Piano {
+    const WHITE_KEY_WIDTH: f32 = 12.0;
+    const HEIGHT: f32 = 40.0;
+
+    /// Draws the keyboard, and returns the MIDI messages that the user's
+    /// clicks produced.
+    pub fn show(&mut self, ui: &mut Ui) -> (Response, Vec<MidiMessage>) {
+        let white_key_count = self.octave_count as usize * 7;
+        let (response, painter) = ui.allocate_painter(
+            vec2(white_key_count as f32 * Self::WHITE_KEY_WIDTH, Self::HEIGHT),
+            Sense::click_and_drag(),
+        );
+        let rect = response.rect;
+
+        // White keys first, so the black keys are drawn on top of them.
+        for (is_black_pass, fill) in [(false, Color32::WHITE), (true, Color32::BLACK)] {
+            for note in self.notes() {
+                if IS_BLACK[note as usize % 12] != is_black_pass {
+                    continue;
+                }
+                let key_rect = self.key_rect(rect, note);
+                let fill = if self.held_note == Some(note) {
+                    Color32::LIGHT_BLUE
+                } else {
+                    fill
+                };
+                painter.rect(key_rect, 0.0, fill, Stroke::new(1.0, Color32::DARK_GRAY));
+            }
+        }
+
+        let pointer_note = if response.is_pointer_button_down_on() {
+            response
+                .interact_pointer_pos()
+                .and_then(|pos| self.note_at(rect, pos))
+        } else {
+            None
+        };
+        let mut messages = Vec::default();
+        if pointer_note != self.held_note {
+            if let Some(note) = self.held_note {
+                messages.push(MidiMessage::NoteOff {
+                    key: note.into(),
+                    vel: 0.into(),
+                });
+            }
+            if let Some(note) = pointer_note {
+                messages.push(MidiMessage::NoteOn {
+                    key: note.into(),
+                    vel: self.velocity.into(),
+                });
+            }
+            self.held_note = pointer_note;
+        }
+        (response, messages)
+    }
+
+    fn notes(&self) -> impl Iterator<Item = u8> {
+        self.first_note..self.first_note + self.octave_count * 12
+    }
+
+    fn key_rect(&self, rect: Rect, note: u8) -> Rect {
+        let semitone = (note - self.first_note) as usize;
+        let white_keys_before = (semitone / 12) * 7
+            + IS_BLACK[..semitone % 12]
+                .iter()
+                .filter(|is_black| !**is_black)
+                .count();
+        let left = rect.left() + white_keys_before as f32 * Self::WHITE_KEY_WIDTH;
+        if IS_BLACK[semitone % 12] {
+            let width = Self::WHITE_KEY_WIDTH * 0.6;
+            Rect::from_min_size(
+                pos2(left - width / 2.0, rect.top()),
+                vec2(width, rect.height() * 0.6),
+            )
+        } else {
+            Rect::from_min_size(
+                pos2(left, rect.top()),
+                vec2(Self::WHITE_KEY_WIDTH, rect.height()),
+            )
+        }
+    }
+
+    fn note_at(&self, rect: Rect, pos: Pos2) -> Option<u8> {
+        // Black keys overlap white ones, so they get first dibs.
+        let black = self
+            .notes()
+            .filter(|note| IS_BLACK[*note as usize % 12])
+            .find(|note| self.key_rect(rect, *note).contains(pos));
+        black.or_else(|| {
+            self.notes()
+                .filter(|note| !IS_BLACK[*note as usize % 12])
+                .find(|note| self.key_rect(rect, *note).contains(pos))
+        })
+    }
+}
@@ -0,0 +1,267 @@
+//! An adapter layer for externally hosted plugins (VST3, LV2, etc.).
+//!
+//! A plugin format is supported by implementing [PluginFormat] and
+//! [PluginInstance] on top of a host crate, and then adding the format to the
+//! [PluginHost]. [PluginEntity] wraps an instance so that it can be added to a
+//! track like any other entity.
+//!
+//! No format backends ship with this crate. An app registers its own with
+//! [EntityRegistry::plugin_host_mut](crate::registry::EntityRegistry::plugin_host_mut),
+//! and hands the registry to
+//! [Engine::new_with_registry](crate::engine::Engine::new_with_registry).
+//! Projects save each plugin's descriptor and state, and
+//! [PluginHost::restore()] brings it back when the project is opened.
+
+use crate::traits::ReportsLatency;
+#[cfg(feature = "gui")]
+use eframe::egui::Slider;
+use ensnare::prelude::*;
+use ensnare_proc_macros::{IsEntity, Metadata};
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+/// Identifies a plugin that a [PluginFormat] can instantiate.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PluginDescriptor {
+    /// The [PluginFormat::name()] of the format that provides the plugin.
+    pub format: String,
+    /// A format-specific identifier, such as a VST3 class ID or an LV2 URI.
+    pub id: String,
+    /// A human-readable name.
+    pub name: String,
+    /// Whether the plugin makes sound (true) or processes it (false).
+    pub is_instrument: bool,
+}
+
+/// A plugin format backed by a host library.
+pub trait PluginFormat: Debug + Send + Sync {
+    /// A short name like "VST3" or "LV2".
+    fn name(&self) -> &str;
+
+    /// Returns the plugins that are installed on this system.
+    fn scan(&self) -> Vec<PluginDescriptor>;
+
+    /// Creates a new instance of the given plugin.
+    fn instantiate(&self, descriptor: &PluginDescriptor) -> anyhow::Result<Box<dyn PluginInstance>>;
+}
+
+/// A running plugin. Parameter values are normalized to 0.0..=1.0.
+pub trait PluginInstance: Debug + Send + Sync {
+    fn parameter_count(&self) -> usize;
+    fn parameter_name(&self, index: usize) -> Option<String>;
+    fn parameter_value(&self, index: usize) -> f64;
+    fn set_parameter_value(&mut self, index: usize, value: f64);
+
+    fn set_sample_rate(&mut self, sample_rate: SampleRate);
+
+    fn handle_midi(&mut self, channel: MidiChannel, message: MidiMessage);
+
+    /// Processes the buffer in place. Instruments receive silence and add
+    /// their output to it.
+    fn process(&mut self, buffer: &mut [StereoSample]);
+
+    /// How many frames late [PluginInstance::process()]'s output is.
+    fn latency(&self) -> usize {
+        0
+    }
+
+    /// Returns the plugin's opaque state for saving with a project.
+    fn save_state(&self) -> Vec<u8>;
+
+    /// Restores state previously returned by [PluginInstance::save_state()].
+    fn load_state(&mut self, state: &[u8]) -> anyhow::Result<()>;
+}
+
+/// The set of plugin formats available to the app.
+#[derive(Debug, Default)]
+pub struct PluginHost {
+    formats: Vec<Box<dyn PluginFormat>>,
+    descriptors: Vec<PluginDescriptor>,
+}
+impl PluginHost {
+    /// Makes the format's plugins available, and scans for them.
+    pub fn add_format(&mut self, format: Box<dyn PluginFormat>) {
+        self.descriptors.extend(format.scan());
+        self.formats.push(format);
+    }
+
+    /// Every plugin found by the registered formats.
+    pub fn descriptors(&self) -> &[PluginDescriptor] {
+        &self.descriptors
+    }
+
+    pub fn instantiate(&self, descriptor: &PluginDescriptor) -> anyhow::Result<PluginEntity> {
+        let format = self
+            .formats
+            .iter()
+            .find(|f| f.name() == descriptor.format)
+            .ok_or_else(|| anyhow::anyhow!("No plugin format named {}", descriptor.format))?;
+        let instance = format.instantiate(descriptor)?;
+        Ok(PluginEntity::new_with(descriptor.clone(), instance))
+    }
+
+    /// Recreates the instance of an entity that was just deserialized or
+    /// made with [PluginEntity::new_saved()], and restores its saved state.
+    pub fn restore(&self, entity: &mut PluginEntity) -> anyhow::Result<()> {
+        let mut restored = self.instantiate(&entity.descriptor)?;
+        if let Some(instance) = restored.instance.as_mut() {
+            instance.load_state(&entity.state)?;
+        }
+        entity.instance = restored.instance;
+        Ok(())
+    }
+}
+
+/// An entity that forwards everything to a [PluginInstance]. Plugin
+/// parameters appear as the entity's controls.
+#[derive(Debug, IsEntity, Metadata, Serialize, Deserialize)]
+#[entity(Controls)]
+pub struct PluginEntity {
+    uid: Uid,
+    descriptor: PluginDescriptor,
+
+    /// The plugin's state as of the last save. [PluginHost::restore()] uses
+    /// this to bring back the instance after deserialization.
+    state: Vec<u8>,
+
+    #[serde(skip)]
+    instance: Option<Box<dyn PluginInstance>>,
+
+    #[serde(skip)]
+    sample_rate: SampleRate,
+}
+impl PluginEntity {
+    fn new_with(descriptor: PluginDescriptor, instance: Box<dyn PluginInstance>) -> Self {
+        Self {
+            uid: Default::default(),
+            descriptor,
+            state: Default::default(),
+            instance: Some(instance),
+            sample_rate: Default::default(),
+        }
+    }
+
+    /// An entity for a plugin saved with a project. It makes no sound until
+    /// [PluginHost::restore()] gives it an instance.
+    pub fn new_saved(descriptor: PluginDescriptor, state: Vec<u8>) -> Self {
+        Self {
+            uid: Default::default(),
+            descriptor,
+            state,
+            instance: None,
+            sample_rate: Default::default(),
+        }
+    }
+
+    pub fn descriptor(&self) -> &PluginDescriptor {
+        &self.descriptor
+    }
+
+    /// The instance's current state, or the saved state if there's no
+    /// instance.
+    pub fn save_state(&self) -> Vec<u8> {
+        self.instance
+            .as_ref()
+            .map_or_else(|| self.state.clone(), |instance| instance.save_state())
+    }
+}
+impl Controllable for PluginEntity {
+    fn control_index_count(&self) -> usize {
+        self.instance.as_ref().map_or(0, |instance| instance.parameter_count())
+    }
+
+    fn control_name_for_index(&self, index: ControlIndex) -> Option<String> {
+        self.instance.as_ref().and_then(|instance| instance.parameter_name(index.0))
+    }
+
+    fn control_index_for_name(&self, name: &str) -> Option<ControlIndex> {
+        (0..self.control_index_count())
+            .find(|&i| self.control_name_for_index(ControlIndex(i)).as_deref() == Some(name))
+            .map(ControlIndex)
+    }
+
+    fn control_set_param_by_index(&mut self, index: ControlIndex, value: ControlValue) {
+        if let Some(instance) = self.instance.as_mut() {
+            instance.set_parameter_value(index.0, value.0);
+        }
+    }
+
+    fn control_set_param_by_name(&mut self, name: &str, value: ControlValue) {
+        if let Some(index) = self.control_index_for_name(name) {
+            self.control_set_param_by_index(index, value);
+        }
+    }
+}
+impl Generates<StereoSample> for PluginEntity {
+    fn generate(&mut self, values: &mut [StereoSample]) -> bool {
+        if let Some(instance) = self.instance.as_mut() {
+            instance.process(values);
+            values.iter().any(|v| *v != StereoSample::SILENCE)
+        } else {
+            false
+        }
+    }
+}
+impl TransformsAudio for PluginEntity {
+    fn transform(&mut self, samples: &mut [StereoSample]) {
+        if let Some(instance) = self.instance.as_mut() {
+            instance.process(samples);
+        }
+    }
+}
+impl HandlesMidi for PluginEntity {
+    fn handle_midi_message(
+        &mut self,
+        channel: MidiChannel,
+        message: MidiMessage,
+        _midi_messages_fn: &mut MidiMessagesFn,
+    ) {
+        if let Some(instance) = self.instance.as_mut() {
+            instance.handle_midi(channel, message);
+        }
+    }
+}
+impl Configurable for PluginEntity {
+    fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate;
+        if let Some(instance) = self.instance.as_mut() {
+            instance.set_sample_rate(sample_rate);
+        }
+    }
+}
+impl ReportsLatency for PluginEntity {
+    fn latency(&self) -> usize {
+        self.instance.as_ref().map_or(0, |i| i.latency())
+    }
+}
+impl Serializable for PluginEntity {
+    fn before_ser(&mut self) {
+        if let Some(instance) = self.instance.as_ref() {
+            self.state = instance.save_state();
+        }
+    }
+}
+#[cfg(feature = "gui")]
+impl Displays for PluginEntity {
+    fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
+        let response = ui.label(format!("{} ({})", self.descriptor.name, self.descriptor.format));
+        if let Some(instance) = self.instance.as_mut() {
+            for index in 0..instance.parameter_count() {
+                let mut value = instance.parameter_value(index);
+                let name = instance.parameter_name(index).unwrap_or_default();
+                if ui.add(Slider::new(&mut value, 0.0..=1.0).text(name)).changed() {
+                    instance.set_parameter_value(index, value);
+                }
+            }
+        } else {
+            ui.label("(plugin not loaded)");
+        }
+        response
+    }
+}
+#[cfg(not(feature = "gui"))]
+impl Displays for PluginEntity {}
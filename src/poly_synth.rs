@@ -0,0 +1,417 @@
+#[cfg(feature = "gui")]
+use eframe::egui::Slider;
+use ensnare::prelude::*;
+use ensnare_proc_macros::{Control, IsEntity, Metadata};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+const MIN_CUTOFF: f64 = 20.0;
+const MAX_CUTOFF: f64 = 20000.0;
+const MIN_Q: f64 = 0.5;
+const MAX_Q: f64 = 10.0;
+const MIN_TIME_SECONDS: f64 = 0.001;
+const MAX_TIME_SECONDS: f64 = 5.0;
+const MAX_DETUNE_CENTS: f64 = 50.0;
+const MIN_VOICES: usize = 8;
+const MAX_VOICES: usize = 16;
+/// Where a releasing voice counts as silent.
+const SILENT_LEVEL: f64 = 0.0001;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum Stage {
+    #[default]
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// The values that every voice needs for each sample, worked out once per
+/// buffer.
+struct VoiceParams {
+    sample_rate: f64,
+    mix: f64,
+    detune_ratio: f64,
+    attack_step: f64,
+    decay_coefficient: f64,
+    sustain: f64,
+    release_coefficient: f64,
+    /// The coefficients of a topology-preserving-transform state-variable
+    /// lowpass filter.
+    filter: [f64; 3],
+}
+
+#[derive(Debug, Clone, Default)]
+struct Voice {
+    key: u8,
+    velocity: f64,
+    frequency: f64,
+    /// Where each oscillator is in its cycle, from 0.0 to 1.0.
+    phases: [f64; 2],
+    stage: Stage,
+    level: f64,
+    /// Which note this was, counting from the synth's first, so that the
+    /// oldest voice can be stolen.
+    note_number: usize,
+    /// The filter's two integrator states.
+    filter: [f64; 2],
+}
+impl Voice {
+    fn is_idle(&self) -> bool {
+        self.stage == Stage::Idle
+    }
+
+    fn start(&mut self, key: u8, velocity: u8, note_number: usize) {
+        self.key = key;
+        self.velocity = velocity as f64 / 127.0;
+        self.frequency = 440.0 * 2.0f64.powf((key as f64 - 69.0) / 12.0);
+        self.stage = Stage::Attack;
+        self.note_number = note_number;
+        // A stolen voice attacks from wherever it was, so that it doesn't
+        // click.
+        if self.level == 0.0 {
+            self.phases = Default::default();
+            self.filter = Default::default();
+        }
+    }
+
+    fn release(&mut self) {
+        if !self.is_idle() {
+            self.stage = Stage::Release;
+        }
+    }
+
+    fn next_sample(&mut self, params: &VoiceParams) -> f64 {
+        match self.stage {
+            Stage::Idle => return 0.0,
+            Stage::Attack => {
+                self.level += params.attack_step;
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                self.level =
+                    params.sustain + (self.level - params.sustain) * params.decay_coefficient;
+                if self.level - params.sustain < SILENT_LEVEL {
+                    self.stage = Stage::Sustain;
+                }
+            }
+            Stage::Sustain => self.level = params.sustain,
+            Stage::Release => {
+                self.level *= params.release_coefficient;
+                if self.level < SILENT_LEVEL {
+                    self.level = 0.0;
+                    self.stage = Stage::Idle;
+                    return 0.0;
+                }
+            }
+        }
+
+        let dt = [
+            self.frequency / params.sample_rate,
+            self.frequency * params.detune_ratio / params.sample_rate,
+        ];
+        let [saw_phase, square_phase] = self.phases;
+        let saw = 2.0 * saw_phase - 1.0 - poly_blep(saw_phase, dt[0]);
+        let square_edge = if square_phase < 0.5 { 1.0 } else { -1.0 };
+        let square = square_edge + poly_blep(square_phase, dt[1])
+            - poly_blep((square_phase + 0.5).fract(), dt[1]);
+        for (phase, dt) in self.phases.iter_mut().zip(dt) {
+            *phase = (*phase + dt).fract();
+        }
+        let x = saw * (1.0 - params.mix) + square * params.mix;
+
+        let [a1, a2, a3] = params.filter;
+        let [ic1, ic2] = self.filter;
+        let v3 = x - ic2;
+        let v1 = a1 * ic1 + a2 * v3;
+        let v2 = ic2 + a2 * ic1 + a3 * v3;
+        self.filter = [2.0 * v1 - ic1, 2.0 * v2 - ic2];
+        v2 * self.level * self.velocity
+    }
+}
+
+/// Smooths an oscillator's jump at the start of its cycle, which would
+/// otherwise alias.
+fn poly_blep(phase: f64, dt: f64) -> f64 {
+    if phase < dt {
+        let t = phase / dt;
+        2.0 * t - t * t - 1.0
+    } else if phase > 1.0 - dt {
+        let t = (phase - 1.0) / dt;
+        t * t + 2.0 * t + 1.0
+    } else {
+        0.0
+    }
+}
+
+/// A polyphonic subtractive synth: a sawtooth and a detunable square wave,
+/// through a resonant lowpass filter, shaped by an ADSR envelope. When
+/// every voice is busy, a new note steals the quietest releasing voice, or
+/// else the oldest one.
+///
+/// As with [ParametricEq](crate::eq::ParametricEq), every parameter is a
+/// [Normal], so that all of them work with control links. Times map
+/// logarithmically onto 1ms-5s, cutoff onto 20Hz-20kHz, and resonance onto
+/// a Q of 0.5-10.
+#[derive(Debug, Control, IsEntity, Metadata, Serialize, Deserialize)]
+#[entity(Controls, TransformsAudio)]
+pub struct PolySynth {
+    uid: Uid,
+
+    /// All sawtooth at 0.0, all square at 1.0.
+    #[control]
+    oscillator_mix: Normal,
+    /// How far above the sawtooth the square wave is tuned, up to 50 cents.
+    #[control]
+    detune: Normal,
+    #[control]
+    cutoff: Normal,
+    #[control]
+    resonance: Normal,
+    #[control]
+    attack: Normal,
+    #[control]
+    decay: Normal,
+    #[control]
+    sustain: Normal,
+    #[control]
+    release: Normal,
+    #[control]
+    level: Normal,
+    /// How many notes can sound at once, mapped onto 8-16.
+    #[control]
+    voice_count: Normal,
+
+    #[serde(skip)]
+    sample_rate: SampleRate,
+    #[serde(skip)]
+    voices: Vec<Voice>,
+    #[serde(skip)]
+    note_count: usize,
+}
+impl Default for PolySynth {
+    fn default() -> Self {
+        let mut r = Self {
+            uid: Default::default(),
+            oscillator_mix: Normal::from(0.0),
+            detune: Normal::from(0.2),
+            cutoff: Self::cutoff_to_normal(4000.0),
+            resonance: Self::q_to_normal(0.707),
+            attack: Self::time_to_normal(0.005),
+            decay: Self::time_to_normal(0.3),
+            sustain: Normal::from(0.7),
+            release: Self::time_to_normal(0.2),
+            level: Normal::from(0.5),
+            voice_count: Normal::from(0.0),
+            sample_rate: Default::default(),
+            voices: Default::default(),
+            note_count: 0,
+        };
+        r.fit_voices();
+        r
+    }
+}
+impl Serializable for PolySynth {
+    fn after_deser(&mut self) {
+        self.fit_voices();
+    }
+}
+impl HandlesMidi for PolySynth {
+    fn handle_midi_message(
+        &mut self,
+        _channel: MidiChannel,
+        message: MidiMessage,
+        _midi_messages_fn: &mut MidiMessagesFn,
+    ) {
+        match message {
+            MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => {
+                let index = self.free_voice();
+                self.note_count += 1;
+                self.voices[index].start(key.as_int(), vel.as_int(), self.note_count);
+            }
+            MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                for voice in self.voices.iter_mut().filter(|v| v.key == key.as_int()) {
+                    voice.release();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+impl Generates<StereoSample> for PolySynth {
+    fn generate(&mut self, values: &mut [StereoSample]) -> bool {
+        let params = self.voice_params();
+        let level = self.level.0 * 0.25;
+        for value in values.iter_mut() {
+            let sample: f64 = self.voices.iter_mut().map(|v| v.next_sample(&params)).sum();
+            *value = StereoSample::from(sample * level);
+        }
+        self.voices.iter().any(|v| !v.is_idle())
+    }
+}
+impl Configurable for PolySynth {
+    fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate;
+    }
+}
+#[cfg(feature = "gui")]
+impl Displays for PolySynth {
+    fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
+        let active = self.voices.iter().filter(|v| !v.is_idle()).count();
+        let response = ui.label(format!("Voices: {active} of {}", self.voices.len()));
+        Self::ui_normal(ui, &mut self.oscillator_mix, "Saw/Square");
+        Self::ui_normal(ui, &mut self.detune, "Detune");
+        Self::ui_normal(ui, &mut self.cutoff, "Cutoff");
+        Self::ui_normal(ui, &mut self.resonance, "Resonance");
+        Self::ui_normal(ui, &mut self.attack, "Attack");
+        Self::ui_normal(ui, &mut self.decay, "Decay");
+        Self::ui_normal(ui, &mut self.sustain, "Sustain");
+        Self::ui_normal(ui, &mut self.release, "Release");
+        Self::ui_normal(ui, &mut self.level, "Level");
+        let mut voice_count = self.voices.len();
+        if ui
+            .add(Slider::new(&mut voice_count, MIN_VOICES..=MAX_VOICES).text("Voices"))
+            .changed()
+        {
+            let fraction = (voice_count - MIN_VOICES) as f64 / (MAX_VOICES - MIN_VOICES) as f64;
+            self.set_voice_count(Normal::from(fraction));
+        }
+        response
+    }
+}
+#[cfg(not(feature = "gui"))]
+impl Displays for PolySynth {}
+impl PolySynth {
+    fn voice_params(&self) -> VoiceParams {
+        let sample_rate = self.sample_rate.0.max(1) as f64;
+        let samples = |value: Normal| Self::normal_to_time(value) * sample_rate;
+        let cutoff = Self::normal_to_cutoff(self.cutoff).min(sample_rate * 0.45);
+        let g = (PI * cutoff / sample_rate).tan();
+        let k = 1.0 / Self::normal_to_q(self.resonance);
+        let a1 = 1.0 / (1.0 + g * (g + k));
+        VoiceParams {
+            sample_rate,
+            mix: self.oscillator_mix.0,
+            detune_ratio: 2.0f64.powf(self.detune.0 * MAX_DETUNE_CENTS / 1200.0),
+            attack_step: 1.0 / samples(self.attack),
+            decay_coefficient: (-1.0 / samples(self.decay)).exp(),
+            sustain: self.sustain.0,
+            // Down to SILENT_LEVEL over the release time.
+            release_coefficient: (SILENT_LEVEL.ln() / samples(self.release)).exp(),
+            filter: [a1, g * a1, g * g * a1],
+        }
+    }
+
+    /// An idle voice, or else one to steal.
+    fn free_voice(&self) -> usize {
+        if let Some(index) = self.voices.iter().position(|v| v.is_idle()) {
+            return index;
+        }
+        let quietest_releasing = self
+            .voices
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| v.stage == Stage::Release)
+            .min_by(|(_, a), (_, b)| a.level.total_cmp(&b.level))
+            .map(|(index, _)| index);
+        quietest_releasing.unwrap_or_else(|| {
+            self.voices
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, v)| v.note_number)
+                .map(|(index, _)| index)
+                .unwrap_or_default()
+        })
+    }
+
+    fn fit_voices(&mut self) {
+        let count =
+            MIN_VOICES + (self.voice_count.0 * (MAX_VOICES - MIN_VOICES) as f64).round() as usize;
+        self.voices.resize(count, Voice::default());
+    }
+
+    fn time_to_normal(seconds: f64) -> Normal {
+        Normal::from((seconds / MIN_TIME_SECONDS).ln() / (MAX_TIME_SECONDS / MIN_TIME_SECONDS).ln())
+    }
+
+    fn normal_to_time(value: Normal) -> f64 {
+        MIN_TIME_SECONDS * (MAX_TIME_SECONDS / MIN_TIME_SECONDS).powf(value.0)
+    }
+
+    fn cutoff_to_normal(frequency: f64) -> Normal {
+        Normal::from((frequency / MIN_CUTOFF).ln() / (MAX_CUTOFF / MIN_CUTOFF).ln())
+    }
+
+    fn normal_to_cutoff(value: Normal) -> f64 {
+        MIN_CUTOFF * (MAX_CUTOFF / MIN_CUTOFF).powf(value.0)
+    }
+
+    fn q_to_normal(q: f64) -> Normal {
+        Normal::from((q / MIN_Q).ln() / (MAX_Q / MIN_Q).ln())
+    }
+
+    fn normal_to_q(value: Normal) -> f64 {
+        MIN_Q * (MAX_Q / MIN_Q).powf(value.0)
+    }
+
+    #[cfg(feature = "gui")]
+    fn ui_normal(ui: &mut eframe::egui::Ui, value: &mut Normal, label: &str) {
+        let mut v = value.0;
+        if ui
+            .add(Slider::new(&mut v, Normal::range()).text(label))
+            .changed()
+        {
+            value.set(v);
+        }
+    }
+
+    fn set_oscillator_mix(&mut self, value: Normal) {
+        self.oscillator_mix = value;
+    }
+
+    fn set_detune(&mut self, value: Normal) {
+        self.detune = value;
+    }
+
+    fn set_cutoff(&mut self, value: Normal) {
+        self.cutoff = value;
+    }
+
+    fn set_resonance(&mut self, value: Normal) {
+        self.resonance = value;
+    }
+
+    fn set_attack(&mut self, value: Normal) {
+        self.attack = value;
+    }
+
+    fn set_decay(&mut self, value: Normal) {
+        self.decay = value;
+    }
+
+    fn set_sustain(&mut self, value: Normal) {
+        self.sustain = value;
+    }
+
+    fn set_release(&mut self, value: Normal) {
+        self.release = value;
+    }
+
+    fn set_level(&mut self, value: Normal) {
+        self.level = value;
+    }
+
+    /// Fewer voices cut off the notes in the voices that go away.
+    fn set_voice_count(&mut self, value: Normal) {
+        self.voice_count = value;
+        self.fit_voices();
+    }
+}
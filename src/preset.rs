@@ -0,0 +1,93 @@
+use anyhow::anyhow;
+use derivative::Derivative;
+use std::{path::PathBuf, sync::Arc};
+
+/// Returns the entity's state as RON.
+pub type PresetSaveFn = Arc<dyn Fn() -> anyhow::Result<String> + Send + Sync>;
+/// Replaces the entity's state with the given RON.
+pub type PresetLoadFn = Arc<dyn Fn(&str) -> anyhow::Result<()> + Send + Sync>;
+
+/// Named snapshots of one entity's settings. A preset is the entity
+/// serialized as RON, in a directory shared by every entity of the same
+/// kind, so a preset saved from one synth can be loaded into any other.
+#[derive(Derivative, Clone)]
+#[derivative(Debug)]
+pub struct EntityPresets {
+    /// The [EntityRegistry](crate::registry::EntityRegistry) key of the
+    /// entity's kind.
+    key: String,
+    #[derivative(Debug = "ignore")]
+    save_fn: PresetSaveFn,
+    #[derivative(Debug = "ignore")]
+    load_fn: PresetLoadFn,
+}
+impl EntityPresets {
+    const APP_NAME: &'static str = "spike-actor-system";
+    const EXTENSION: &'static str = "ron";
+
+    pub(crate) fn new_with(key: &str, save_fn: PresetSaveFn, load_fn: PresetLoadFn) -> Self {
+        Self {
+            key: key.to_string(),
+            save_fn,
+            load_fn,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Where presets for this kind of entity live, next to the app's
+    /// settings file.
+    pub fn directory(&self) -> anyhow::Result<PathBuf> {
+        let path = confy::get_configuration_file_path(Self::APP_NAME, "presets")?;
+        Ok(path.with_extension("").join(&self.key))
+    }
+
+    /// The saved presets' names, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let Ok(entries) = self.directory().and_then(|d| Ok(std::fs::read_dir(d)?)) else {
+            return Vec::default();
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|e| e == Self::EXTENSION))
+            .filter_map(|path| Some(path.file_stem()?.to_string_lossy().to_string()))
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Writes the entity's current settings to the named preset, replacing
+    /// it if it exists.
+    pub fn save(&self, name: &str) -> anyhow::Result<()> {
+        let path = self.path(name)?;
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        Ok(std::fs::write(path, self.to_ron()?)?)
+    }
+
+    /// Gives the entity the named preset's settings.
+    pub fn load(&self, name: &str) -> anyhow::Result<()> {
+        self.apply_ron(&std::fs::read_to_string(self.path(name)?)?)
+    }
+
+    /// The entity's current settings, as a preset file would hold them.
+    pub fn to_ron(&self) -> anyhow::Result<String> {
+        (self.save_fn)()
+    }
+
+    /// Gives the entity the settings in a preset file's contents.
+    pub fn apply_ron(&self, ron: &str) -> anyhow::Result<()> {
+        (self.load_fn)(ron)
+    }
+
+    fn path(&self, name: &str) -> anyhow::Result<PathBuf> {
+        let name = name.trim();
+        if name.is_empty() || name.contains(['/', '\\']) || name.starts_with('.') {
+            return Err(anyhow!("\"{name}\" isn't a usable preset name"));
+        }
+        Ok(self.directory()?.join(format!("{name}.{}", Self::EXTENSION)))
+    }
+}
@@ -0,0 +1,75 @@
+//! Saving a project to a file and opening it again. A project file holds the
+//! tracks in order, each with its name, color, and entities. Entities are
+//! saved the way their presets are, as RON, so anything a preset remembers
+//! comes back with the project. Plugins are saved as their descriptors and
+//! the state that the plugin itself provides. MIDI-learned mappings refer to
+//! entities by the uids they had when saved, which opening maps to new ones.
+
+use crate::{
+    plugin::PluginDescriptor,
+    track::{MidiMapping, TrackInfo},
+};
+use ensnare::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Everything that [Engine::to_project](crate::engine::Engine::to_project)
+/// saves.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Project {
+    /// Not including the master track.
+    pub tracks: Vec<TrackProject>,
+}
+impl Project {
+    pub const EXTENSION: &'static str = "ron";
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let ron = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        Ok(std::fs::write(path, ron)?)
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Ok(ron::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+/// One track of a [Project].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrackProject {
+    pub info: TrackInfo,
+    /// In processing order.
+    pub entities: Vec<EntityProject>,
+    #[serde(default)]
+    pub midi_mappings: Vec<MidiMapping>,
+}
+
+/// One entity of a [TrackProject].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum EntityProject {
+    /// An entity from the [EntityRegistry](crate::registry::EntityRegistry),
+    /// with its state as a preset file would hold it.
+    Registered {
+        #[serde(default)]
+        uid: Uid,
+        key: String,
+        state: String,
+    },
+    /// A plugin, with the opaque state that
+    /// [PluginInstance::save_state](crate::plugin::PluginInstance::save_state)
+    /// returned.
+    Plugin {
+        #[serde(default)]
+        uid: Uid,
+        descriptor: PluginDescriptor,
+        state: Vec<u8>,
+    },
+}
+impl EntityProject {
+    /// The entity's uid when it was saved.
+    pub fn uid(&self) -> Uid {
+        match self {
+            EntityProject::Registered { uid, .. } | EntityProject::Plugin { uid, .. } => *uid,
+        }
+    }
+}
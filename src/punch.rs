@@ -0,0 +1,115 @@
+use ensnare::prelude::*;
+#[cfg(feature = "gui")]
+use eframe::{
+    egui::{Response, Sense, Ui},
+    epaint::{pos2, vec2, Color32, Rect, Stroke},
+};
+
+/// The part of the song where recording happens on its own. While punch is
+/// enabled and the transport plays, the engine starts recording when the
+/// playhead enters the region and stops when it leaves, without stopping
+/// playback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PunchRegion {
+    pub is_enabled: bool,
+    /// Punch in here.
+    pub start: MusicalTime,
+    /// Punch out here.
+    pub end: MusicalTime,
+}
+impl Default for PunchRegion {
+    fn default() -> Self {
+        Self {
+            is_enabled: false,
+            start: Self::beat(4),
+            end: Self::beat(8),
+        }
+    }
+}
+impl PunchRegion {
+    /// How many beats the timeline shows.
+    #[cfg(feature = "gui")]
+    const TIMELINE_BEATS: usize = 32;
+
+    pub fn new_with(start: MusicalTime, end: MusicalTime) -> Self {
+        Self {
+            is_enabled: true,
+            start,
+            end,
+        }
+    }
+
+    /// The start of the given beat, counting from zero.
+    pub fn beat(beat: usize) -> MusicalTime {
+        MusicalTime::new_with_units(beat * MusicalTime::UNITS_IN_BEAT)
+    }
+
+    /// Whether recording should be on at this time. The start is inside the
+    /// region and the end isn't.
+    pub fn contains(&self, time: MusicalTime) -> bool {
+        let units = time.total_units();
+        self.start.total_units() <= units && units < self.end.total_units()
+    }
+}
+#[cfg(feature = "gui")]
+impl PunchRegion {
+    /// Draws a beat ruler with the region and the playhead on it. Dragging
+    /// moves whichever edge of the region is closer, one beat at a time.
+    pub fn show(&mut self, ui: &mut Ui, playhead: Option<MusicalTime>) -> Response {
+        ui.checkbox(&mut self.is_enabled, "Punch");
+        let (response, painter) =
+            ui.allocate_painter(vec2(ui.available_width(), 24.0), Sense::click_and_drag());
+        let rect = response.rect;
+        let beat_width = rect.width() / Self::TIMELINE_BEATS as f32;
+        let x_of = |time: MusicalTime| {
+            let beats = time.total_units() as f32 / MusicalTime::UNITS_IN_BEAT as f32;
+            rect.left() + beats.min(Self::TIMELINE_BEATS as f32) * beat_width
+        };
+
+        painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+        let region_color = if self.is_enabled {
+            Color32::from_rgba_unmultiplied(200, 40, 40, 96)
+        } else {
+            Color32::from_gray(80)
+        };
+        painter.rect_filled(
+            Rect::from_x_y_ranges(x_of(self.start)..=x_of(self.end), rect.y_range()),
+            0.0,
+            region_color,
+        );
+        for beat in 0..=Self::TIMELINE_BEATS {
+            let x = rect.left() + beat as f32 * beat_width;
+            // Taller lines mark the bars, assuming 4/4.
+            let top = if beat % 4 == 0 {
+                rect.top()
+            } else {
+                rect.center().y
+            };
+            painter.line_segment(
+                [pos2(x, top), pos2(x, rect.bottom())],
+                Stroke::new(1.0, Color32::GRAY),
+            );
+        }
+        if let Some(playhead) = playhead {
+            let x = x_of(playhead);
+            painter.line_segment(
+                [pos2(x, rect.top()), pos2(x, rect.bottom())],
+                Stroke::new(2.0, Color32::YELLOW),
+            );
+        }
+
+        if let Some(pointer) = response.interact_pointer_pos() {
+            let beat = ((pointer.x - rect.left()) / beat_width).round().max(0.0) as usize;
+            let beat = beat.min(Self::TIMELINE_BEATS);
+            let start_beat = self.start.total_beats();
+            let end_beat = self.end.total_beats();
+            // The region always spans at least one beat.
+            if beat.abs_diff(start_beat) <= beat.abs_diff(end_beat) {
+                self.start = Self::beat(beat.min(end_beat.saturating_sub(1)));
+            } else {
+                self.end = Self::beat(beat.max(start_beat + 1));
+            }
+        }
+        response.on_hover_text("Drag to move the punch-in and punch-out points")
+    }
+}
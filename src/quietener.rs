@@ -1,3 +1,4 @@
+#[cfg(feature = "gui")]
 use eframe::egui::DragValue;
 use ensnare::prelude::*;
 use ensnare_proc_macros::{Control, IsEntity, Metadata};
@@ -27,6 +28,7 @@ impl TransformsAudio for Quietener {
 impl Serializable for Quietener {}
 impl HandlesMidi for Quietener {}
 impl Configurable for Quietener {}
+#[cfg(feature = "gui")]
 impl Displays for Quietener {
     fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
         let mut v = self.quiet_factor.0;
@@ -43,6 +45,8 @@ impl Displays for Quietener {
         response
     }
 }
+#[cfg(not(feature = "gui"))]
+impl Displays for Quietener {}
 impl Quietener {
     fn set_quiet_factor(&mut self, quiet_factor: Normal) {
         self.quiet_factor = quiet_factor;
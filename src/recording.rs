@@ -0,0 +1,225 @@
+use crate::{
+    notification::report_error,
+    track::{TrackActor, TrackRequest},
+    traits::ProvidesActorService,
+    wav_writer::{ExportFormat, WavWriterEvent, WavWriterInput, WavWriterService},
+};
+use anyhow::anyhow;
+use crossbeam_channel::{Receiver, Sender};
+use ensnare::{prelude::*, traits::ProvidesService};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Decides which outputs get captured to WAV files: the master output, and
+/// each track whose capture is armed. Every destination has its own
+/// [WavWriterService], so arming or disarming one doesn't disturb the
+/// others.
+#[derive(Debug)]
+pub struct RecordingManager {
+    master: WavWriterService,
+    is_master_armed: bool,
+    /// Where to capture the master output. Without one, the name comes from
+    /// the format.
+    capture_path: Option<PathBuf>,
+    /// Where capture files go when they don't have a path of their own.
+    capture_directory: PathBuf,
+    export_format: ExportFormat,
+    /// The sample rate and channel count of the master output, once the
+    /// audio device is configured. Nothing is captured before then.
+    configuration: Option<(SampleRate, u8)>,
+    tracks: HashMap<TrackUid, WavWriterService>,
+}
+impl Default for RecordingManager {
+    fn default() -> Self {
+        Self {
+            master: WavWriterService::new(),
+            is_master_armed: true,
+            capture_path: Default::default(),
+            capture_directory: Self::default_capture_directory(),
+            export_format: Default::default(),
+            configuration: Default::default(),
+            tracks: Default::default(),
+        }
+    }
+}
+impl RecordingManager {
+    /// Where [EngineService](crate::engine::EngineService) sends the master
+    /// output. The writer drops frames while master capture is disarmed.
+    pub(crate) fn master_sender(&self) -> Sender<WavWriterInput> {
+        self.master.sender().clone()
+    }
+
+    pub(crate) fn master_events(&self) -> Receiver<WavWriterEvent> {
+        self.master.receiver().clone()
+    }
+
+    pub fn is_master_armed(&self) -> bool {
+        self.is_master_armed
+    }
+
+    /// Arming starts a new master capture file, and disarming finishes the
+    /// current one.
+    pub fn set_master_armed(&mut self, is_armed: bool) {
+        if is_armed == self.is_master_armed {
+            return;
+        }
+        self.is_master_armed = is_armed;
+        if is_armed {
+            self.restart_master();
+        } else {
+            self.master.send_input(WavWriterInput::Finish);
+        }
+    }
+
+    /// The master output's format changed. Starts a new capture file.
+    pub fn configure(&mut self, sample_rate: SampleRate, channel_count: u8) {
+        self.configuration = Some((sample_rate, channel_count));
+        self.restart_master();
+    }
+
+    /// The master output gained or lost channels. Starts a new capture file.
+    pub fn set_channel_count(&mut self, channel_count: u8) {
+        if let Some((sample_rate, _)) = self.configuration {
+            self.configure(sample_rate, channel_count);
+        }
+    }
+
+    /// Takes effect with the next capture file.
+    pub fn set_capture_path(&mut self, path: PathBuf) {
+        self.capture_path = Some(path);
+    }
+
+    /// The platform's audio directory, or else the home directory.
+    pub fn default_capture_directory() -> PathBuf {
+        dirs::audio_dir()
+            .or_else(dirs::home_dir)
+            .unwrap_or_default()
+    }
+
+    pub fn capture_directory(&self) -> &Path {
+        &self.capture_directory
+    }
+
+    /// Takes effect with the next capture file.
+    pub fn set_capture_directory(&mut self, directory: PathBuf) {
+        self.capture_directory = directory;
+    }
+
+    pub fn export_format(&self) -> ExportFormat {
+        self.export_format
+    }
+
+    /// Starts a new master capture file in the given format.
+    pub fn set_export_format(&mut self, export_format: ExportFormat) {
+        self.export_format = export_format;
+        self.master
+            .send_input(WavWriterInput::SetFormat(export_format));
+        self.restart_master();
+    }
+
+    fn restart_master(&self) {
+        let Some((sample_rate, channel_count)) = self.configuration else {
+            return;
+        };
+        if !self.is_master_armed {
+            return;
+        }
+        let path = self.capture_path.clone().unwrap_or_else(|| {
+            let file_name = format!("out-{}-{}.wav", sample_rate.0, channel_count);
+            self.capture_directory.join(file_name)
+        });
+        self.master
+            .send_input(WavWriterInput::Reset(path, sample_rate, channel_count));
+    }
+
+    pub fn is_track_armed(&self, track_uid: TrackUid) -> bool {
+        self.tracks.contains_key(&track_uid)
+    }
+
+    /// Arming a track starts writing its output to its own file, and
+    /// disarming it finishes the file.
+    pub fn set_track_armed(&mut self, track_uid: TrackUid, track: &TrackActor, is_armed: bool) {
+        if is_armed == self.is_track_armed(track_uid) {
+            return;
+        }
+        if !is_armed {
+            self.disarm_track(track_uid, Some(track));
+            return;
+        }
+        let Some((sample_rate, _)) = self.configuration else {
+            report_error(
+                "While arming track capture",
+                &anyhow!("The audio device isn't configured yet"),
+            );
+            return;
+        };
+        let writer = WavWriterService::new();
+        let file_name = format!("capture-{}-{}.wav", track_uid, sample_rate.0);
+        writer.send_input(WavWriterInput::AddStem(
+            track_uid,
+            self.capture_directory.join(file_name),
+            sample_rate,
+        ));
+        track.send_request(TrackRequest::SubscribeFrames(
+            writer.track_action_sender().clone(),
+        ));
+        self.tracks.insert(track_uid, writer);
+    }
+
+    /// Finishes the track's capture file. Pass the track if it's still
+    /// around, so that it stops sending frames.
+    pub fn disarm_track(&mut self, track_uid: TrackUid, track: Option<&TrackActor>) {
+        let Some(writer) = self.tracks.remove(&track_uid) else {
+            return;
+        };
+        if let Some(track) = track {
+            track.send_request(TrackRequest::UnsubscribeFrames(
+                writer.track_action_sender().clone(),
+            ));
+        }
+        writer.send_input(WavWriterInput::Quit);
+    }
+
+    /// Finishes every track's capture file.
+    pub(crate) fn disarm_tracks(&mut self, tracks: &HashMap<TrackUid, TrackActor>) {
+        let track_uids: Vec<TrackUid> = self.tracks.keys().copied().collect();
+        for track_uid in track_uids {
+            self.disarm_track(track_uid, tracks.get(&track_uid));
+        }
+    }
+
+    /// Reports errors from the track writers. The master writer's errors
+    /// go to whoever has [RecordingManager::master_events].
+    pub fn report_errors(&self) {
+        for (track_uid, writer) in self.tracks.iter() {
+            while let Ok(WavWriterEvent::Err(e)) = writer.receiver().try_recv() {
+                report_error(&format!("While capturing track {track_uid}"), &e);
+            }
+        }
+    }
+
+    /// Finalizes every capture file, waiting up to the timeout.
+    pub fn quit_and_join(
+        &mut self,
+        tracks: &HashMap<TrackUid, TrackActor>,
+        timeout: Duration,
+    ) -> anyhow::Result<()> {
+        let deadline = Instant::now() + timeout;
+        let mut result = self.master.quit_and_join(timeout);
+        for (track_uid, mut writer) in self.tracks.drain() {
+            if let Some(track) = tracks.get(&track_uid) {
+                track.send_request(TrackRequest::UnsubscribeFrames(
+                    writer.track_action_sender().clone(),
+                ));
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if let Err(e) = writer.quit_and_join(remaining) {
+                result = Err(e);
+            }
+        }
+        result
+    }
+}
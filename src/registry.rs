@@ -0,0 +1,251 @@
+use crate::{
+    always::AlwaysSame,
+    arp::Arpeggiator,
+    busy::BusyWaiter,
+    drone::DroneController,
+    drums::DrumMachine,
+    entity::{EntityRoles, WorkSchedule},
+    eq::ParametricEq,
+    follower::EnvelopeFollower,
+    lookahead::LookaheadLimiter,
+    plugin::PluginHost,
+    poly_synth::PolySynth,
+    preset::{EntityPresets, PresetLoadFn, PresetSaveFn},
+    quietener::Quietener,
+    signal::SignalGenerator,
+    traits::ReportsLatency,
+};
+use anyhow::anyhow;
+use derivative::Derivative;
+use ensnare::prelude::*;
+use ensnare_toys::{ToyInstrument, ToySynth};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+type EntityFactoryFn = Box<dyn Fn() -> NewEntity + Send + Sync>;
+
+/// Makes an independent copy of the entity it was created with, in its
+/// current state.
+pub type EntityDuplicateFn = Arc<dyn Fn() -> anyhow::Result<NewEntity> + Send + Sync>;
+
+/// How many frames late the entity's output is. See [ReportsLatency].
+pub type EntityLatencyFn = Arc<dyn Fn() -> usize + Send + Sync>;
+
+/// An entity fresh from the registry. The caller is responsible for
+/// assigning its [Uid].
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct NewEntity {
+    pub entity: Arc<Mutex<dyn Entity>>,
+    pub roles: EntityRoles,
+    #[derivative(Debug = "ignore")]
+    pub duplicate_fn: EntityDuplicateFn,
+    pub presets: EntityPresets,
+    /// Present if the entity was registered with
+    /// [EntityRegistry::register_latent].
+    #[derivative(Debug = "ignore")]
+    pub latency_fn: Option<EntityLatencyFn>,
+}
+
+/// One kind of entity that the registry knows how to make.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct EntityRegistryEntry {
+    /// The stable string that identifies this kind of entity, e.g., in project
+    /// files and scripts.
+    pub key: String,
+    /// What to call it in the UI.
+    pub name: String,
+    /// How it participates in a track's audio pipeline.
+    pub roles: EntityRoles,
+    #[derivative(Debug = "ignore")]
+    factory_fn: EntityFactoryFn,
+}
+
+/// Creates entities by string key. Everything that needs to instantiate an
+/// entity without naming its type -- the track UI, project loading, scripting
+/// -- goes through here.
+#[derive(Debug, Default)]
+pub struct EntityRegistry {
+    entries: Vec<EntityRegistryEntry>,
+    key_to_index: HashMap<String, usize>,
+    plugin_host: PluginHost,
+}
+impl EntityRegistry {
+    /// Returns a registry containing every entity built into the app.
+    pub fn new_with_builtins() -> Self {
+        let mut r = Self::default();
+        r.register::<ToySynth>("toy-synth", "Synth", EntityRoles::MIDI_INSTRUMENT);
+        r.register::<ToyInstrument>("toy-instrument", "ToyInstrument", EntityRoles::INSTRUMENT);
+        r.register::<PolySynth>("poly-synth", "Poly Synth", EntityRoles::MIDI_INSTRUMENT);
+        r.register::<DrumMachine>("drum-machine", "Drum Machine", EntityRoles::INSTRUMENT);
+        r.register::<SignalGenerator>(
+            "signal-generator",
+            "Signal Generator",
+            EntityRoles::INSTRUMENT,
+        );
+        r.register::<BusyWaiter>("busy-waiter", "Busy Waiter", EntityRoles::INSTRUMENT);
+        r.register_with("always-1.0", "1.0", EntityRoles::INSTRUMENT, || AlwaysSame::new_with(1.0));
+        r.register_with("always-0.5", "0.5", EntityRoles::INSTRUMENT, || AlwaysSame::new_with(0.5));
+        r.register_with("always--1.0", "-1.0", EntityRoles::INSTRUMENT, || {
+            AlwaysSame::new_with(-1.0)
+        });
+        r.register::<Arpeggiator>("arpeggiator", "Arpeggiator", EntityRoles::CONTROLLER);
+        // Effects that don't follow the song position have no use for Work.
+        let pure_effect = EntityRoles::EFFECT.with_work(WorkSchedule::Never);
+        r.register::<Quietener>("quietener", "Quietener", pure_effect);
+        // The drone updates its oscillator in generate(), so it needs
+        // NeedsAudio even though its output is silent.
+        r.register::<DroneController>("drone", "Drone", EntityRoles::INSTRUMENT);
+        r.register::<ParametricEq>("parametric-eq", "EQ", pure_effect);
+        // Its envelope doesn't need to reach its links every block.
+        let control_rate = WorkSchedule::ControlRate(WorkSchedule::CONTROL_RATE_BLOCKS);
+        r.register::<EnvelopeFollower>(
+            "envelope-follower",
+            "Envelope Follower",
+            EntityRoles::EFFECT.with_work(control_rate),
+        );
+        r.register_latent::<LookaheadLimiter>(
+            "lookahead-limiter",
+            "Lookahead Limiter",
+            pure_effect,
+        );
+        r
+    }
+
+    /// Registers an entity type that's constructed with its [Default] impl.
+    pub fn register<E: Entity + Default + Serialize + DeserializeOwned + 'static>(
+        &mut self,
+        key: &str,
+        name: &str,
+        roles: EntityRoles,
+    ) {
+        self.register_with(key, name, roles, E::default);
+    }
+
+    /// Registers an entity constructed by the given function. Registering a
+    /// key a second time replaces the earlier entry.
+    pub fn register_with<E: Entity + Serialize + DeserializeOwned + 'static>(
+        &mut self,
+        key: &str,
+        name: &str,
+        roles: EntityRoles,
+        f: impl Fn() -> E + Send + Sync + 'static,
+    ) {
+        self.insert(key, name, roles, f, None);
+    }
+
+    /// Like [EntityRegistry::register], for an entity whose output lags its
+    /// input.
+    pub fn register_latent<
+        E: Entity + Default + ReportsLatency + Serialize + DeserializeOwned + 'static,
+    >(
+        &mut self,
+        key: &str,
+        name: &str,
+        roles: EntityRoles,
+    ) {
+        self.insert(key, name, roles, E::default, Some(E::latency));
+    }
+
+    fn insert<E: Entity + Serialize + DeserializeOwned + 'static>(
+        &mut self,
+        key: &str,
+        name: &str,
+        roles: EntityRoles,
+        f: impl Fn() -> E + Send + Sync + 'static,
+        latency: Option<fn(&E) -> usize>,
+    ) {
+        let factory_key = key.to_string();
+        let entry = EntityRegistryEntry {
+            key: key.to_string(),
+            name: name.to_string(),
+            roles,
+            factory_fn: Box::new(move || Self::wrap(f(), &factory_key, roles, latency)),
+        };
+        if let Some(&index) = self.key_to_index.get(key) {
+            self.entries[index] = entry;
+        } else {
+            self.key_to_index.insert(key.to_string(), self.entries.len());
+            self.entries.push(entry);
+        }
+    }
+
+    /// Wraps the entity for an actor, along with functions that copy it and
+    /// save and load its presets by round-tripping it through serde.
+    fn wrap<E: Entity + Serialize + DeserializeOwned + 'static>(
+        entity: E,
+        key: &str,
+        roles: EntityRoles,
+        latency: Option<fn(&E) -> usize>,
+    ) -> NewEntity {
+        let entity = Arc::new(Mutex::new(entity));
+        let source = Arc::clone(&entity);
+        let duplicate_key = key.to_string();
+        let duplicate_fn: EntityDuplicateFn = Arc::new(move || {
+            let json = serde_json::to_string(&*source.lock().unwrap())?;
+            let mut copy: E = serde_json::from_str(&json)?;
+            copy.after_deser();
+            Ok(Self::wrap(copy, &duplicate_key, roles, latency))
+        });
+        let source = Arc::clone(&entity);
+        let save_fn: PresetSaveFn = Arc::new(move || {
+            let config = ron::ser::PrettyConfig::default();
+            Ok(ron::ser::to_string_pretty(&*source.lock().unwrap(), config)?)
+        });
+        let target = Arc::clone(&entity);
+        let load_fn: PresetLoadFn = Arc::new(move |ron| {
+            let mut preset: E = ron::from_str(ron)?;
+            preset.after_deser();
+            let mut target = target.lock().unwrap();
+            // The preset came from some other entity, but this one keeps its
+            // identity.
+            preset.set_uid(target.uid());
+            *target = preset;
+            Ok(())
+        });
+        NewEntity {
+            latency_fn: latency.map(|f| latency_fn(&entity, f)),
+            entity,
+            roles,
+            duplicate_fn,
+            presets: EntityPresets::new_with(key, save_fn, load_fn),
+        }
+    }
+
+    /// All registered entities, in registration order.
+    pub fn entries(&self) -> &[EntityRegistryEntry] {
+        &self.entries
+    }
+
+    /// Creates a new entity of the given kind.
+    pub fn new_entity(&self, key: &str) -> anyhow::Result<NewEntity> {
+        self.key_to_index
+            .get(key)
+            .map(|&index| (self.entries[index].factory_fn)())
+            .ok_or_else(|| anyhow!("No entity registered with key {key}"))
+    }
+
+    pub fn plugin_host(&self) -> &PluginHost {
+        &self.plugin_host
+    }
+
+    /// For adding plugin formats before the registry goes to
+    /// [Engine::new_with_registry](crate::engine::Engine::new_with_registry).
+    pub fn plugin_host_mut(&mut self) -> &mut PluginHost {
+        &mut self.plugin_host
+    }
+}
+
+/// Asks the entity for its latency with `f`, for callers that don't know its
+/// type.
+pub(crate) fn latency_fn<E: Send + 'static>(
+    entity: &Arc<Mutex<E>>,
+    f: fn(&E) -> usize,
+) -> EntityLatencyFn {
+    let entity = Arc::clone(entity);
+    Arc::new(move || f(&entity.lock().unwrap()))
+}
@@ -0,0 +1,305 @@
+//! Remote control over WebSocket, so that a mixer surface in a browser or on
+//! another machine can drive the engine. Clients send [RemoteRequest]s as
+//! JSON text messages, and hear back [RemoteUpdate]s: the answers to
+//! requests, plus transport and meter updates about 30 times a second.
+
+use crate::{
+    command::Command,
+    engine::{EngineServiceEvent, EngineServiceInput},
+    subscription::Subscription,
+};
+use crossbeam_channel::{Receiver, Sender, TryRecvError};
+use ensnare::prelude::*;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+    io::ErrorKind,
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tungstenite::Message;
+
+/// A request from a remote client. Tracks and entities are addressed by
+/// their uids' numbers, and positions are in beats.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RemoteRequest {
+    Play,
+    Stop,
+    Seek {
+        beats: f64,
+    },
+    Undo,
+    Redo,
+    CreateTrack,
+    DeleteTrack {
+        track: usize,
+    },
+    SetTrackName {
+        track: usize,
+        name: String,
+    },
+    AddEntity {
+        track: usize,
+        key: String,
+    },
+    RemoveEntity {
+        track: usize,
+        entity: usize,
+    },
+    SetParam {
+        track: usize,
+        entity: usize,
+        param: usize,
+        value: f64,
+    },
+    SetMixerLevel {
+        track: usize,
+        level: f64,
+    },
+    SetMixerMute {
+        track: usize,
+        muted: bool,
+    },
+}
+impl From<RemoteRequest> for EngineServiceInput {
+    fn from(request: RemoteRequest) -> Self {
+        match request {
+            RemoteRequest::Play => Self::Play,
+            RemoteRequest::Stop => Self::Stop,
+            RemoteRequest::Seek { beats } => Self::Seek(MusicalTime::new_with_units(
+                (beats.max(0.0) * MusicalTime::UNITS_IN_BEAT as f64) as usize,
+            )),
+            RemoteRequest::Undo => Self::Undo,
+            RemoteRequest::Redo => Self::Redo,
+            RemoteRequest::CreateTrack => Self::CreateTrack,
+            RemoteRequest::DeleteTrack { track } => Self::DeleteTrack(TrackUid(track)),
+            RemoteRequest::SetTrackName { track, name } => {
+                Self::SetTrackName(TrackUid(track), name)
+            }
+            RemoteRequest::AddEntity { track, key } => Self::AddEntity(TrackUid(track), key),
+            RemoteRequest::RemoveEntity { track, entity } => {
+                Self::RemoveEntity(TrackUid(track), Uid(entity))
+            }
+            RemoteRequest::SetParam {
+                track,
+                entity,
+                param,
+                value,
+            } => Self::SetParam(
+                TrackUid(track),
+                Uid(entity),
+                ControlIndex(param),
+                ControlValue(value),
+            ),
+            RemoteRequest::SetMixerLevel { track, level } => {
+                Self::Execute(Command::SetMixerLevel(TrackUid(track), Normal::from(level)))
+            }
+            RemoteRequest::SetMixerMute { track, muted } => {
+                Self::Execute(Command::SetMixerMute(TrackUid(track), muted))
+            }
+        }
+    }
+}
+
+/// One track's meter levels in a [RemoteUpdate::Meters].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrackLevel {
+    pub track: usize,
+    pub peak: f64,
+    pub rms: f64,
+}
+
+/// What remote clients hear about. The answers to requests go to every
+/// client, so a client that needs to match them up should send one request
+/// at a time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RemoteUpdate {
+    /// The request with this name succeeded, e.g., "Play" or "Execute".
+    Done {
+        request: String,
+    },
+    Failed {
+        request: String,
+        error: String,
+    },
+    TrackCreated {
+        track: usize,
+    },
+    EntityAdded {
+        track: usize,
+        entity: usize,
+    },
+    Transport {
+        is_performing: bool,
+        is_recording: bool,
+        beats: f64,
+        tempo: f64,
+        can_undo: bool,
+        can_redo: bool,
+    },
+    /// The master track is track 0.
+    Meters {
+        levels: Vec<TrackLevel>,
+    },
+}
+impl RemoteUpdate {
+    /// What clients should hear about the given event, if anything.
+    pub fn from_event(event: &EngineServiceEvent) -> Vec<Self> {
+        match event {
+            EngineServiceEvent::Done(name) => vec![Self::Done {
+                request: name.to_string(),
+            }],
+            EngineServiceEvent::Failed(name, error) => vec![Self::Failed {
+                request: name.to_string(),
+                error: error.clone(),
+            }],
+            EngineServiceEvent::TrackCreated(track_uid) => {
+                vec![Self::TrackCreated { track: track_uid.0 }]
+            }
+            EngineServiceEvent::EntityAdded(track_uid, uid) => vec![Self::EntityAdded {
+                track: track_uid.0,
+                entity: uid.0,
+            }],
+            EngineServiceEvent::Snapshot(snapshot) => {
+                let beats =
+                    snapshot.position.total_units() as f64 / MusicalTime::UNITS_IN_BEAT as f64;
+                let levels = snapshot
+                    .levels
+                    .iter()
+                    .map(|(uid, level)| TrackLevel {
+                        track: uid.0,
+                        peak: level.peak(),
+                        rms: level.rms(),
+                    })
+                    .collect();
+                vec![
+                    Self::Transport {
+                        is_performing: snapshot.is_performing,
+                        is_recording: snapshot.is_recording,
+                        beats,
+                        tempo: snapshot.tempo.0,
+                        can_undo: snapshot.can_undo,
+                        can_redo: snapshot.can_redo,
+                    },
+                    Self::Meters { levels },
+                ]
+            }
+            _ => Vec::default(),
+        }
+    }
+}
+
+/// Listens for WebSocket clients and passes their requests to the
+/// [EngineService](crate::engine::EngineService). It doesn't hear from the
+/// engine service itself, because the app already owns that receiver, so
+/// the app hands it each event with [RemoteControlService::forward]. Each
+/// client runs on its own thread, and the listener runs until the process
+/// exits.
+#[derive(Debug, Clone)]
+pub struct RemoteControlService {
+    address: SocketAddr,
+    clients: Arc<Mutex<Subscription<String>>>,
+}
+impl RemoteControlService {
+    /// How long a client's thread waits for a request before checking for
+    /// updates to send.
+    const POLL_INTERVAL: Duration = Duration::from_millis(10);
+    /// How many updates can wait for a client before it's dropped as too
+    /// slow.
+    const CLIENT_BACKLOG: usize = 256;
+
+    /// Starts listening on the given address, e.g., "127.0.0.1:9001". Port
+    /// 0 picks a free port; see [RemoteControlService::address].
+    pub fn new_with(
+        address: &str,
+        engine_sender: Sender<EngineServiceInput>,
+    ) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let r = Self {
+            address: listener.local_addr()?,
+            clients: Default::default(),
+        };
+        let clients = Arc::clone(&r.clients);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        warn!("Remote control: while accepting a client: {e:?}");
+                        continue;
+                    }
+                };
+                let (sender, receiver) = crossbeam_channel::bounded(Self::CLIENT_BACKLOG);
+                clients.lock().unwrap().subscribe(&sender);
+                let engine_sender = engine_sender.clone();
+                std::thread::spawn(move || {
+                    if let Err(e) = Self::serve(stream, engine_sender, receiver) {
+                        warn!("Remote control: client disconnected: {e:?}");
+                    }
+                });
+            }
+        });
+        Ok(r)
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Sends whatever clients should hear about the event to all of them.
+    pub fn forward(&self, event: &EngineServiceEvent) {
+        for update in RemoteUpdate::from_event(event) {
+            match serde_json::to_string(&update) {
+                Ok(text) => self.clients.lock().unwrap().broadcast_mut(text),
+                Err(e) => warn!("Remote control: while encoding an update: {e:?}"),
+            }
+        }
+    }
+
+    fn serve(
+        stream: TcpStream,
+        engine_sender: Sender<EngineServiceInput>,
+        updates: Receiver<String>,
+    ) -> anyhow::Result<()> {
+        let mut socket = tungstenite::accept(stream)?;
+        // Only after the handshake, which would fail on a timeout.
+        socket
+            .get_ref()
+            .set_read_timeout(Some(Self::POLL_INTERVAL))?;
+        loop {
+            loop {
+                match updates.try_recv() {
+                    Ok(text) => socket.send(Message::Text(text))?,
+                    Err(TryRecvError::Empty) => break,
+                    // The client fell too far behind and was dropped.
+                    Err(TryRecvError::Disconnected) => {
+                        socket.close(None)?;
+                        return Ok(());
+                    }
+                }
+            }
+            match socket.read() {
+                Ok(Message::Text(text)) => match serde_json::from_str::<RemoteRequest>(&text) {
+                    Ok(request) => {
+                        let _ = engine_sender.try_send(request.into());
+                    }
+                    Err(e) => {
+                        let update = RemoteUpdate::Failed {
+                            request: text,
+                            error: e.to_string(),
+                        };
+                        socket.send(Message::Text(serde_json::to_string(&update)?))?;
+                    }
+                },
+                Ok(Message::Close(..)) | Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(e))
+                    if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use ensnare::prelude::*;
+use std::{f64::consts::PI, fmt::Display};
+
+/// How carefully [resample] converts. It runs once per imported file, so
+/// the slow, clean option is the default.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ResampleQuality {
+    /// Straight lines between neighboring frames. Fast, but dulls the highs
+    /// and lets aliasing through.
+    Linear,
+    /// Catmull-Rom curves through four neighboring frames.
+    Cubic,
+    /// A windowed-sinc filter, which also keeps frequencies above the new
+    /// Nyquist limit out when converting down.
+    #[default]
+    Sinc,
+}
+impl Display for ResampleQuality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ResampleQuality::Linear => "Linear",
+            ResampleQuality::Cubic => "Cubic",
+            ResampleQuality::Sinc => "Sinc",
+        })
+    }
+}
+impl ResampleQuality {
+    pub const ALL: [ResampleQuality; 3] = [Self::Linear, Self::Cubic, Self::Sinc];
+}
+
+/// Zero crossings on each side of the sinc filter's center, at full
+/// bandwidth. Converting down widens the filter by the same ratio.
+const SINC_HALF_WIDTH: f64 = 16.0;
+
+/// Converts audio from one sample rate to another. The result lasts as long
+/// as the original.
+pub fn resample(
+    frames: &[StereoSample],
+    from: SampleRate,
+    to: SampleRate,
+    quality: ResampleQuality,
+) -> Vec<StereoSample> {
+    if from.0 == to.0 || frames.is_empty() || from.0 == 0 || to.0 == 0 {
+        return frames.to_vec();
+    }
+    let step = from.0 as f64 / to.0 as f64;
+    let len = (frames.len() as f64 / step).round() as usize;
+    // Below 1.0, the filter cuts off at the new, lower Nyquist limit.
+    let cutoff = (1.0 / step).min(1.0);
+    (0..len)
+        .map(|n| {
+            let position = n as f64 * step;
+            match quality {
+                ResampleQuality::Linear => linear_at(frames, position),
+                ResampleQuality::Cubic => cubic_at(frames, position),
+                ResampleQuality::Sinc => sinc_at(frames, position, cutoff),
+            }
+        })
+        .collect()
+}
+
+/// The frame at the index, or silence past either end.
+fn frame_at(frames: &[StereoSample], index: isize) -> StereoSample {
+    usize::try_from(index)
+        .ok()
+        .and_then(|i| frames.get(i))
+        .copied()
+        .unwrap_or(StereoSample::SILENCE)
+}
+
+fn linear_at(frames: &[StereoSample], position: f64) -> StereoSample {
+    let index = position.floor() as isize;
+    let t = position - index as f64;
+    let mut frame = frame_at(frames, index) * (1.0 - t);
+    frame += frame_at(frames, index + 1) * t;
+    frame
+}
+
+fn cubic_at(frames: &[StereoSample], position: f64) -> StereoSample {
+    let index = position.floor() as isize;
+    let t = position - index as f64;
+    let (t2, t3) = (t * t, t * t * t);
+    let weights = [
+        (-t3 + 2.0 * t2 - t) / 2.0,
+        (3.0 * t3 - 5.0 * t2 + 2.0) / 2.0,
+        (-3.0 * t3 + 4.0 * t2 + t) / 2.0,
+        (t3 - t2) / 2.0,
+    ];
+    let mut frame = StereoSample::SILENCE;
+    for (offset, weight) in (-1..=2).zip(weights) {
+        frame += frame_at(frames, index + offset) * weight;
+    }
+    frame
+}
+
+fn sinc_at(frames: &[StereoSample], position: f64, cutoff: f64) -> StereoSample {
+    let half_width = SINC_HALF_WIDTH / cutoff;
+    let first = (position - half_width).ceil() as isize;
+    let last = (position + half_width).floor() as isize;
+    let mut frame = StereoSample::SILENCE;
+    for index in first..=last {
+        let x = position - index as f64;
+        let weight = cutoff * sinc(cutoff * x) * blackman(x / half_width);
+        frame += frame_at(frames, index) * weight;
+    }
+    frame
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// The Blackman window, for `x` from -1.0 to 1.0.
+fn blackman(x: f64) -> f64 {
+    0.42 + 0.5 * (PI * x).cos() + 0.08 * (2.0 * PI * x).cos()
+}
@@ -0,0 +1,158 @@
+//! Scenes: numbered snapshots of the master mixer's levels and mutes, plus
+//! whichever entity parameters were picked for them. The
+//! [Engine](crate::engine::Engine) captures and recalls them, either at once
+//! or with a morph that moves every value over a few seconds.
+
+use ensnare::prelude::*;
+use std::collections::BTreeMap;
+
+/// An entity parameter that scenes capture.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SceneParam {
+    pub track_uid: TrackUid,
+    pub uid: Uid,
+    pub index: ControlIndex,
+}
+
+/// The values that a scene recalls.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Scene {
+    /// Each track's level and mute state in the master mixer.
+    pub mixer: Vec<(TrackUid, Normal, bool)>,
+    /// [Controllable] can't report a parameter, so this has only the
+    /// parameters whose values were known when the scene was captured.
+    pub params: Vec<(SceneParam, ControlValue)>,
+}
+impl Scene {
+    /// The values `progress` of the way from `self` to `to`, which is from
+    /// 0.0 to 1.0. Tracks that `to` unmutes unmute right away, and tracks
+    /// that it mutes don't mute until the end, so that nothing drops out in
+    /// the middle. Values that `self` doesn't have jump straight to `to`'s.
+    pub fn morph_to(&self, to: &Scene, progress: f64) -> Scene {
+        let lerp = |from: f64, to: f64| from + (to - from) * progress;
+        let mixer = to
+            .mixer
+            .iter()
+            .map(|&(track_uid, level, muted)| {
+                let from = self.mixer.iter().find(|(uid, ..)| *uid == track_uid);
+                match from {
+                    Some(&(_, from_level, from_muted)) => {
+                        let level = Normal::from(lerp(from_level.0, level.0));
+                        let muted = muted && (from_muted || progress >= 1.0);
+                        (track_uid, level, muted)
+                    }
+                    None => (track_uid, level, muted),
+                }
+            })
+            .collect();
+        let params = to
+            .params
+            .iter()
+            .map(|&(param, value)| {
+                let from = self.params.iter().find(|(from, _)| *from == param);
+                match from {
+                    Some(&(_, from)) => (param, ControlValue(lerp(from.0, value.0))),
+                    None => (param, value),
+                }
+            })
+            .collect();
+        Scene { mixer, params }
+    }
+}
+
+/// A recall that's moving from one scene to another.
+#[derive(Debug)]
+struct SceneMorph {
+    from: Scene,
+    to: Scene,
+    elapsed: usize,
+    duration: usize,
+}
+
+/// The numbered scenes, which parameters go into new ones, and the morph
+/// that's under way, if any.
+#[derive(Debug, Default)]
+pub struct SceneStore {
+    scenes: BTreeMap<u8, Scene>,
+    params: Vec<SceneParam>,
+    /// How long a recall from a MIDI program change takes.
+    morph_seconds: f64,
+    morph: Option<SceneMorph>,
+}
+impl SceneStore {
+    pub const MAX_MORPH_SECONDS: f64 = 30.0;
+
+    pub fn scene(&self, number: u8) -> Option<&Scene> {
+        self.scenes.get(&number)
+    }
+
+    /// The numbers of the scenes that have been captured, in order.
+    pub fn numbers(&self) -> impl Iterator<Item = u8> + '_ {
+        self.scenes.keys().copied()
+    }
+
+    pub fn insert(&mut self, number: u8, scene: Scene) {
+        self.scenes.insert(number, scene);
+    }
+
+    pub fn remove(&mut self, number: u8) -> Option<Scene> {
+        self.scenes.remove(&number)
+    }
+
+    /// The entity parameters that new scenes capture.
+    pub fn params(&self) -> &[SceneParam] {
+        &self.params
+    }
+
+    pub fn is_param_selected(&self, param: SceneParam) -> bool {
+        self.params.contains(&param)
+    }
+
+    pub fn select_param(&mut self, param: SceneParam, is_selected: bool) {
+        self.params.retain(|p| *p != param);
+        if is_selected {
+            self.params.push(param);
+        }
+    }
+
+    pub fn morph_seconds(&self) -> f64 {
+        self.morph_seconds
+    }
+
+    pub fn set_morph_seconds(&mut self, seconds: f64) {
+        self.morph_seconds = seconds.clamp(0.0, Self::MAX_MORPH_SECONDS);
+    }
+
+    pub fn is_morphing(&self) -> bool {
+        self.morph.is_some()
+    }
+
+    /// Starts moving from `from` to `to` over `frames` frames. A morph
+    /// that's already under way is replaced, so `from` should be where it
+    /// had got to.
+    pub fn start_morph(&mut self, from: Scene, to: Scene, frames: usize) {
+        self.morph = Some(SceneMorph {
+            from,
+            to,
+            elapsed: 0,
+            duration: frames.max(1),
+        });
+    }
+
+    pub fn cancel_morph(&mut self) {
+        self.morph = None;
+    }
+
+    /// Moves the morph along by `count` frames, and returns the values to
+    /// apply, if there's a morph under way.
+    pub fn advance_morph(&mut self, count: usize) -> Option<Scene> {
+        let morph = self.morph.as_mut()?;
+        morph.elapsed = (morph.elapsed + count).min(morph.duration);
+        let progress = morph.elapsed as f64 / morph.duration as f64;
+        let scene = morph.from.morph_to(&morph.to, progress);
+        if morph.elapsed == morph.duration {
+            self.morph = None;
+        }
+        Some(scene)
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{engine::Engine, track::TrackActor};
+use anyhow::anyhow;
+use derivative::Derivative;
+use ensnare::prelude::*;
+use rhai::{Dynamic, EvalAltResult, INT};
+use std::sync::{Arc, Mutex};
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// Runs [Rhai](https://rhai.rs) scripts against an [Engine]. Scripts can call:
+///
+/// - `create_track()`, which returns the new track's uid
+/// - `add_entity(track, key)`, which returns the new entity's uid
+/// - `remove_entity(track, entity)`
+/// - `link(track, source, target, param)`
+/// - `set_param(track, entity, param, value)`
+/// - `set_tempo(bpm)`, `play()`, and `stop()`
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct ScriptHost {
+    #[derivative(Debug = "ignore")]
+    rhai: rhai::Engine,
+    /// Whatever the script has printed since the last run.
+    output: Arc<Mutex<Vec<String>>>,
+}
+impl ScriptHost {
+    pub fn new_with(engine: Arc<Mutex<Engine>>) -> Self {
+        let mut rhai = rhai::Engine::new();
+        let output: Arc<Mutex<Vec<String>>> = Default::default();
+
+        let o = Arc::clone(&output);
+        rhai.on_print(move |s| o.lock().unwrap().push(s.to_string()));
+
+        let e = Arc::clone(&engine);
+        rhai.register_fn("create_track", move || -> ScriptResult<INT> {
+            let uid = e.lock().unwrap().create_track().map_err(to_script_error)?;
+            Ok(uid.0 as INT)
+        });
+        let e = Arc::clone(&engine);
+        rhai.register_fn(
+            "add_entity",
+            move |track: INT, key: &str| -> ScriptResult<INT> {
+                let track_uid = TrackUid(track as usize);
+                let mut engine = e.lock().unwrap();
+                let uid = engine
+                    .add_entity_to_track(track_uid, key)
+                    .map_err(to_script_error)?;
+                Ok(uid.0 as INT)
+            },
+        );
+        let e = Arc::clone(&engine);
+        rhai.register_fn(
+            "remove_entity",
+            move |track: INT, entity: INT| -> ScriptResult<()> {
+                let track_uid = TrackUid(track as usize);
+                let mut engine = e.lock().unwrap();
+                engine
+                    .remove_entity(track_uid, Uid(entity as usize))
+                    .map_err(to_script_error)
+            },
+        );
+        let e = Arc::clone(&engine);
+        rhai.register_fn(
+            "link",
+            move |track: INT, source: INT, target: INT, param: INT| -> ScriptResult<()> {
+                let mut engine = e.lock().unwrap();
+                engine
+                    .link_control(
+                        TrackUid(track as usize),
+                        Uid(source as usize),
+                        Uid(target as usize),
+                        ControlIndex(param as usize),
+                    )
+                    .map_err(to_script_error)
+            },
+        );
+        let e = Arc::clone(&engine);
+        rhai.register_fn(
+            "set_param",
+            move |track: INT, entity: INT, param: INT, value: f64| -> ScriptResult<()> {
+                with_track(&e, track, |track| {
+                    track.set_param(
+                        Uid(entity as usize),
+                        ControlIndex(param as usize),
+                        ControlValue(value),
+                    )
+                })
+            },
+        );
+        let e = Arc::clone(&engine);
+        rhai.register_fn("set_tempo", move |bpm: f64| {
+            e.lock().unwrap().update_tempo(Tempo(bpm));
+        });
+        let e = Arc::clone(&engine);
+        rhai.register_fn("play", move || e.lock().unwrap().play());
+        let e = Arc::clone(&engine);
+        rhai.register_fn("stop", move || e.lock().unwrap().stop());
+
+        Self { rhai, output }
+    }
+
+    /// Runs the script, and returns what it printed, followed by its value if
+    /// it has one.
+    pub fn run(&self, source: &str) -> anyhow::Result<Vec<String>> {
+        let result = self.rhai.eval::<Dynamic>(source);
+        let mut output = std::mem::take(&mut *self.output.lock().unwrap());
+        let value = result.map_err(|e| anyhow!("{e}"))?;
+        if !value.is_unit() {
+            output.push(value.to_string());
+        }
+        Ok(output)
+    }
+}
+
+fn to_script_error(e: anyhow::Error) -> Box<EvalAltResult> {
+    e.to_string().into()
+}
+
+/// Calls `f` with the given track. The engine is locked for the duration.
+fn with_track<T>(
+    engine: &Arc<Mutex<Engine>>,
+    track: INT,
+    f: impl FnOnce(&TrackActor) -> anyhow::Result<T>,
+) -> ScriptResult<T> {
+    let engine = engine.lock().unwrap();
+    let track_uid = TrackUid(track as usize);
+    let track = engine
+        .track(track_uid)
+        .ok_or_else(|| to_script_error(anyhow!("Couldn't find track {track_uid}")))?;
+    f(track).map_err(to_script_error)
+}
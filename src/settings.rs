@@ -0,0 +1,134 @@
+use ensnare::types::CrossbeamChannel;
+use serde::{Deserialize, Serialize};
+use spike_actor_system::{
+    callback_audio::LowLatencyBackend, limits::ActorLimits, notification::report_error,
+};
+use std::{path::PathBuf, thread::JoinHandle};
+
+/// What the app remembers between runs. Missing entries keep the app's
+/// defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Port names, as the MIDI combo boxes show them.
+    pub midi_input: Option<String>,
+    pub midi_output: Option<String>,
+    /// Device names, as the audio device pickers show them.
+    pub audio_output: Option<String>,
+    pub audio_input: Option<String>,
+    /// The output's buffer size in frames. With it, the device's callback
+    /// drives the engine, rather than the engine keeping a queue topped up.
+    /// The ACTOR_LOW_LATENCY environment variable overrides it.
+    pub low_latency_period: Option<usize>,
+    /// Which API opens the low-latency output. Without it, the app picks
+    /// [LowLatencyBackend::best_available].
+    pub low_latency_backend: Option<LowLatencyBackend>,
+    /// The window's inner size, in points.
+    pub window_size: Option<(f32, f32)>,
+    /// Where to capture the master output. Without it, the engine picks a
+    /// file name from the sample rate.
+    pub capture_path: Option<PathBuf>,
+    /// Where per-track captures and exports go. Without it, the engine uses
+    /// the platform's audio directory.
+    pub capture_directory: Option<PathBuf>,
+    /// What's starred in the entity browser.
+    pub favorite_entities: Vec<String>,
+    /// Overrides for the [ActorLimits] defaults.
+    pub max_tracks: Option<usize>,
+    pub max_entities_per_track: Option<usize>,
+    pub max_actor_threads: Option<usize>,
+}
+impl Settings {
+    const APP_NAME: &'static str = "spike-actor-system";
+    const CONFIG_NAME: &'static str = "settings";
+
+    /// Reads the settings file, or returns the defaults if there isn't one.
+    pub fn load() -> Self {
+        match confy::load(Self::APP_NAME, Self::CONFIG_NAME) {
+            Ok(settings) => settings,
+            Err(e) => {
+                report_error("While loading settings", &e.into());
+                Self::default()
+            }
+        }
+    }
+
+    /// Hands the limits that the file sets over to [ActorLimits].
+    pub fn apply_limits(&self) {
+        let limits = ActorLimits::global();
+        if let Some(max_tracks) = self.max_tracks {
+            limits.set_max_tracks(max_tracks);
+        }
+        if let Some(max_entities) = self.max_entities_per_track {
+            limits.set_max_entities_per_track(max_entities);
+        }
+        if let Some(max_threads) = self.max_actor_threads {
+            limits.set_max_threads(max_threads);
+        }
+    }
+
+    fn store(&self) -> anyhow::Result<()> {
+        Ok(confy::store(Self::APP_NAME, Self::CONFIG_NAME, self)?)
+    }
+}
+
+#[derive(Debug)]
+pub enum SettingsInput {
+    /// Write these settings to the file.
+    Save(Settings),
+    /// Finish writing and exit.
+    Quit,
+}
+
+/// Writes [Settings] to disk on its own thread, so that the UI doesn't wait
+/// for the file system.
+#[derive(Debug)]
+pub struct SettingsService {
+    inputs: CrossbeamChannel<SettingsInput>,
+    thread: Option<JoinHandle<()>>,
+}
+impl SettingsService {
+    pub fn new() -> Self {
+        let mut r = Self {
+            inputs: Default::default(),
+            thread: Default::default(),
+        };
+        r.thread = Some(r.start_thread());
+        r
+    }
+
+    pub fn save(&self, settings: Settings) {
+        let _ = self.inputs.sender.try_send(SettingsInput::Save(settings));
+    }
+
+    /// Waits for pending saves to be written.
+    pub fn quit_and_join(&mut self) {
+        let _ = self.inputs.sender.try_send(SettingsInput::Quit);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+
+    fn start_thread(&self) -> JoinHandle<()> {
+        let receiver = self.inputs.receiver.clone();
+        std::thread::spawn(move || {
+            let mut is_quitting = false;
+            while !is_quitting {
+                let Ok(SettingsInput::Save(mut settings)) = receiver.recv() else {
+                    break;
+                };
+                // Only the latest of a burst of saves matters.
+                loop {
+                    match receiver.try_recv() {
+                        Ok(SettingsInput::Save(newer)) => settings = newer,
+                        Ok(SettingsInput::Quit) => is_quitting = true,
+                        Err(_) => break,
+                    }
+                }
+                if let Err(e) = settings.store() {
+                    report_error("While saving settings", &e);
+                }
+            }
+        })
+    }
+}
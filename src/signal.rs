@@ -0,0 +1,300 @@
+#[cfg(feature = "gui")]
+use eframe::egui::{ComboBox, DragValue};
+use ensnare::prelude::*;
+use ensnare_proc_macros::{IsEntity, Metadata};
+use serde::{Deserialize, Serialize};
+use std::{f64::consts::TAU, fmt::Display};
+
+const MIN_FREQUENCY: f64 = 1.0;
+const MAX_FREQUENCY: f64 = 20000.0;
+const MIN_LEVEL_DB: f64 = -120.0;
+
+/// What [SignalGenerator] produces.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SignalKind {
+    #[default]
+    Sine,
+    WhiteNoise,
+    /// Noise with equal energy per octave.
+    PinkNoise,
+    /// A sine that rises exponentially from the frequency to the sweep's end
+    /// frequency, once per period.
+    Sweep,
+    /// A single full-scale frame, once per period.
+    Impulse,
+}
+impl Display for SignalKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SignalKind::Sine => "Sine",
+            SignalKind::WhiteNoise => "White noise",
+            SignalKind::PinkNoise => "Pink noise",
+            SignalKind::Sweep => "Swept sine",
+            SignalKind::Impulse => "Impulse",
+        })
+    }
+}
+impl SignalKind {
+    pub const ALL: [SignalKind; 5] = [
+        Self::Sine,
+        Self::WhiteNoise,
+        Self::PinkNoise,
+        Self::Sweep,
+        Self::Impulse,
+    ];
+}
+
+/// Produces test signals for measuring whatever comes after it. The
+/// frequency and level are kept in Hz and dBFS, so that typed values are
+/// exact. As controls, the frequency maps logarithmically onto 1Hz-20kHz
+/// and the level linearly onto -120-0 dBFS.
+///
+/// The noise comes from a fixed seed, so renders are repeatable.
+#[derive(Debug, IsEntity, Metadata, Serialize, Deserialize)]
+#[entity(Controls, TransformsAudio)]
+#[serde(default)]
+pub struct SignalGenerator {
+    uid: Uid,
+    kind: SignalKind,
+    /// The sine's frequency, and where a sweep starts.
+    frequency: f64,
+    sweep_end_frequency: f64,
+    /// How long a sweep takes, and how far apart impulses are.
+    period_seconds: f64,
+    level_db: f64,
+
+    #[serde(skip)]
+    sample_rate: SampleRate,
+    /// Cycles completed, between 0.0 and 1.0.
+    #[serde(skip)]
+    phase: f64,
+    /// Frames into the current period.
+    #[serde(skip)]
+    position: usize,
+    #[serde(skip)]
+    noise_state: u64,
+    /// The pink noise filter's state.
+    #[serde(skip)]
+    pink: [f64; 7],
+}
+impl Default for SignalGenerator {
+    fn default() -> Self {
+        Self {
+            uid: Default::default(),
+            kind: Default::default(),
+            frequency: 1000.0,
+            sweep_end_frequency: MAX_FREQUENCY,
+            period_seconds: 1.0,
+            level_db: -12.0,
+            sample_rate: Default::default(),
+            phase: 0.0,
+            position: 0,
+            noise_state: Self::NOISE_SEED,
+            pink: Default::default(),
+        }
+    }
+}
+impl Serializable for SignalGenerator {}
+impl HandlesMidi for SignalGenerator {}
+impl Generates<StereoSample> for SignalGenerator {
+    fn generate(&mut self, values: &mut [StereoSample]) -> bool {
+        let gain = Self::db_to_gain(self.level_db);
+        for value in values.iter_mut() {
+            *value = StereoSample::from(self.next_sample() * gain);
+        }
+        true
+    }
+}
+impl Configurable for SignalGenerator {
+    fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate;
+    }
+}
+impl Controllable for SignalGenerator {
+    fn control_index_count(&self) -> usize {
+        Self::CONTROL_NAMES.len()
+    }
+
+    fn control_name_for_index(&self, index: ControlIndex) -> Option<String> {
+        Self::CONTROL_NAMES
+            .get(index.0)
+            .map(|name| name.to_string())
+    }
+
+    fn control_index_for_name(&self, name: &str) -> Option<ControlIndex> {
+        Self::CONTROL_NAMES
+            .iter()
+            .position(|n| *n == name)
+            .map(ControlIndex)
+    }
+
+    fn control_set_param_by_index(&mut self, index: ControlIndex, value: ControlValue) {
+        let value = value.0.clamp(0.0, 1.0);
+        match index.0 {
+            0 => {
+                let count = SignalKind::ALL.len();
+                self.set_kind(SignalKind::ALL[((value * count as f64) as usize).min(count - 1)]);
+            }
+            1 => self.frequency = MIN_FREQUENCY * (MAX_FREQUENCY / MIN_FREQUENCY).powf(value),
+            2 => self.level_db = MIN_LEVEL_DB * (1.0 - value),
+            _ => {}
+        }
+    }
+
+    fn control_set_param_by_name(&mut self, name: &str, value: ControlValue) {
+        if let Some(index) = self.control_index_for_name(name) {
+            self.control_set_param_by_index(index, value);
+        }
+    }
+}
+#[cfg(feature = "gui")]
+impl Displays for SignalGenerator {
+    fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
+        let mut kind = self.kind;
+        let response = ComboBox::from_label("Signal")
+            .selected_text(kind.to_string())
+            .show_ui(ui, |ui| {
+                for k in SignalKind::ALL {
+                    ui.selectable_value(&mut kind, k, k.to_string());
+                }
+            })
+            .response;
+        if kind != self.kind {
+            self.set_kind(kind);
+        }
+        let frequency_range = MIN_FREQUENCY..=MAX_FREQUENCY;
+        if matches!(self.kind, SignalKind::Sine | SignalKind::Sweep) {
+            ui.add(
+                DragValue::new(&mut self.frequency)
+                    .clamp_range(frequency_range.clone())
+                    .max_decimals(3)
+                    .prefix("Frequency: ")
+                    .suffix(" Hz"),
+            );
+        }
+        if self.kind == SignalKind::Sweep {
+            ui.add(
+                DragValue::new(&mut self.sweep_end_frequency)
+                    .clamp_range(frequency_range)
+                    .max_decimals(3)
+                    .prefix("To: ")
+                    .suffix(" Hz"),
+            );
+        }
+        if matches!(self.kind, SignalKind::Sweep | SignalKind::Impulse) {
+            ui.add(
+                DragValue::new(&mut self.period_seconds)
+                    .clamp_range(0.01..=60.0)
+                    .speed(0.01)
+                    .prefix("Every: ")
+                    .suffix(" s"),
+            );
+        }
+        ui.add(
+            DragValue::new(&mut self.level_db)
+                .clamp_range(MIN_LEVEL_DB..=0.0)
+                .speed(0.1)
+                .max_decimals(2)
+                .prefix("Level: ")
+                .suffix(" dBFS"),
+        );
+        if ui.button("Restart").clicked() {
+            self.restart();
+        }
+        response
+    }
+}
+#[cfg(not(feature = "gui"))]
+impl Displays for SignalGenerator {}
+impl SignalGenerator {
+    const CONTROL_NAMES: [&'static str; 3] = ["kind", "frequency", "level"];
+    const NOISE_SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+    fn set_kind(&mut self, kind: SignalKind) {
+        if kind != self.kind {
+            self.kind = kind;
+            self.restart();
+        }
+    }
+
+    /// Starts the signal over, as if the entity were new.
+    fn restart(&mut self) {
+        self.phase = 0.0;
+        self.position = 0;
+        self.noise_state = Self::NOISE_SEED;
+        self.pink = Default::default();
+    }
+
+    fn db_to_gain(db: f64) -> f64 {
+        if db <= MIN_LEVEL_DB {
+            0.0
+        } else {
+            10.0f64.powf(db / 20.0)
+        }
+    }
+
+    fn next_sample(&mut self) -> f64 {
+        let sample_rate = self.sample_rate.0.max(1) as f64;
+        let period_frames = ((self.period_seconds * sample_rate) as usize).max(1);
+        let sample = match self.kind {
+            SignalKind::Sine => {
+                let sample = (self.phase * TAU).sin();
+                self.advance_phase(self.frequency / sample_rate);
+                sample
+            }
+            SignalKind::WhiteNoise => self.white_noise(),
+            SignalKind::PinkNoise => self.pink_noise(),
+            SignalKind::Sweep => {
+                if self.position == 0 {
+                    self.phase = 0.0;
+                }
+                let sample = (self.phase * TAU).sin();
+                let progress = self.position as f64 / period_frames as f64;
+                let ratio = self.sweep_end_frequency / self.frequency;
+                self.advance_phase(self.frequency * ratio.powf(progress) / sample_rate);
+                sample
+            }
+            SignalKind::Impulse => {
+                if self.position == 0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        };
+        self.position = (self.position + 1) % period_frames;
+        sample
+    }
+
+    fn advance_phase(&mut self, cycles: f64) {
+        self.phase = (self.phase + cycles).fract();
+    }
+
+    /// Uniform between -1.0 and 1.0, from a xorshift generator.
+    fn white_noise(&mut self) -> f64 {
+        self.noise_state ^= self.noise_state << 13;
+        self.noise_state ^= self.noise_state >> 7;
+        self.noise_state ^= self.noise_state << 17;
+        (self.noise_state >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+    }
+
+    /// White noise through Paul Kellet's pinking filter, scaled to stay
+    /// roughly within -1.0 to 1.0.
+    fn pink_noise(&mut self) -> f64 {
+        let white = self.white_noise();
+        let b = &mut self.pink;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.1538520;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+        pink * 0.11
+    }
+}
@@ -0,0 +1,410 @@
+use crate::{
+    channels::ChannelLayout, clip::ClipSpan, meter::MeterSnapshot, performance::EnginePerformance,
+    registry::EntityRegistry, track::TrackEditor, wav_writer::ExportFormat,
+};
+use derivative::Derivative;
+use ensnare::prelude::*;
+use std::{collections::HashMap, sync::Arc};
+#[cfg(feature = "gui")]
+use {
+    crate::{
+        command::Command,
+        engine::{ControlTarget, Engine, EngineServiceInput, SelectionAction},
+        meter::Meter,
+        project::Project,
+        wav_writer::{BitDepth, ExportContainer},
+    },
+    eframe::{
+        egui::{
+            Button, Checkbox, ComboBox, DragValue, Key, KeyboardShortcut, Modifiers, Sense, Slider,
+        },
+        epaint::{vec2, Color32},
+    },
+    std::time::Instant,
+};
+
+/// A copy of the engine state that the UI draws from.
+/// [EngineService](crate::engine::EngineService) publishes one about 30
+/// times a second, so that the UI can draw without waiting for the engine's
+/// lock.
+#[derive(Derivative, Clone)]
+#[derivative(Debug)]
+pub struct EngineSnapshot {
+    pub can_undo: bool,
+    pub can_redo: bool,
+    pub is_performing: bool,
+    pub is_recording: bool,
+    pub is_clipping: bool,
+    pub block_size: usize,
+    pub channel_layout: ChannelLayout,
+    pub export_format: ExportFormat,
+    /// Whether the master output is being captured to a file.
+    pub is_master_capture_armed: bool,
+    pub sample_rate: SampleRate,
+    pub performance: EnginePerformance,
+    /// Where the next block of frames starts.
+    pub position: MusicalTime,
+    pub tempo: Tempo,
+    /// Each track's current meter levels. The master track's uid is
+    /// `TrackUid::default()`.
+    pub levels: Vec<(TrackUid, MeterSnapshot)>,
+    /// All tracks except the master track, in display order.
+    pub tracks: Vec<TrackSnapshot>,
+    pub master_track: TrackEditor,
+    /// Whether there are copied entities to paste.
+    pub can_paste: bool,
+    /// What the browser offers.
+    #[derivative(Debug = "ignore")]
+    pub registry: Arc<EntityRegistry>,
+    /// The parameters that entities' control signals can drive.
+    #[cfg(feature = "gui")]
+    #[derivative(Debug = "ignore")]
+    pub control_targets: Arc<Vec<(ControlTarget, String)>>,
+}
+
+/// A track as the track list and arrangement show it.
+#[derive(Debug, Clone)]
+pub struct TrackSnapshot {
+    pub uid: TrackUid,
+    pub name: String,
+    /// sRGB, if the track has been given a color.
+    pub color: Option<[u8; 3]>,
+    pub clip_spans: Vec<ClipSpan>,
+    pub is_bus: bool,
+    /// The bus the track routes to, or None for the master track.
+    pub output: Option<TrackUid>,
+    /// The track's send levels, by bus.
+    pub sends: HashMap<TrackUid, Normal>,
+    /// Whether the track's output is being written to its own file.
+    pub is_capturing: bool,
+    pub editor: TrackEditor,
+}
+
+#[cfg(feature = "gui")]
+impl EngineSnapshot {
+    fn project_dialog() -> rfd::FileDialog {
+        rfd::FileDialog::new().add_filter("Project", &[Project::EXTENSION])
+    }
+
+    /// Draws the transport bar and engine settings. Rather than changing the
+    /// engine, returns the requests for
+    /// [EngineService](crate::engine::EngineService) that the user made.
+    pub fn show(&self, ui: &mut eframe::egui::Ui) -> Vec<EngineServiceInput> {
+        let mut inputs = Vec::default();
+
+        // Check the longer shortcut first, because Ctrl+Z would match both.
+        let redo_shortcut = KeyboardShortcut::new(Modifiers::COMMAND | Modifiers::SHIFT, Key::Z);
+        let undo_shortcut = KeyboardShortcut::new(Modifiers::COMMAND, Key::Z);
+        let mut wants_redo = ui.input_mut(|i| i.consume_shortcut(&redo_shortcut));
+        let mut wants_undo = ui.input_mut(|i| i.consume_shortcut(&undo_shortcut));
+
+        ui.horizontal_wrapped(|ui| {
+            if ui
+                .button("New project")
+                .on_hover_text("Close this project and start an empty one")
+                .clicked()
+            {
+                inputs.push(EngineServiceInput::NewProject);
+            }
+            if ui.button("Open project…").clicked() {
+                if let Some(path) = Self::project_dialog().pick_file() {
+                    inputs.push(EngineServiceInput::OpenProject(path));
+                }
+            }
+            if ui.button("Save project…").clicked() {
+                if let Some(path) = Self::project_dialog().save_file() {
+                    inputs.push(EngineServiceInput::SaveProject(path));
+                }
+            }
+            if ui.add_enabled(self.can_undo, Button::new("Undo")).clicked() {
+                wants_undo = true;
+            }
+            if ui.add_enabled(self.can_redo, Button::new("Redo")).clicked() {
+                wants_redo = true;
+            }
+            if ui.selectable_label(self.is_performing, "Play").clicked() {
+                inputs.push(EngineServiceInput::Play);
+            }
+            if ui.button("Stop").clicked() {
+                inputs.push(EngineServiceInput::Stop);
+            }
+            let mut bpm = self.tempo.0;
+            let tempo = DragValue::new(&mut bpm)
+                .clamp_range(20.0..=300.0)
+                .speed(0.1)
+                .suffix(" BPM");
+            if ui.add(tempo).changed() {
+                inputs.push(EngineServiceInput::SetTempo(Tempo(bpm)));
+            }
+            if ui
+                .button("Tap")
+                .on_hover_text("Tap along to set the tempo")
+                .clicked()
+            {
+                inputs.push(EngineServiceInput::TapTempo(Instant::now()));
+            }
+            if self.ui_clip_indicator(ui) {
+                inputs.push(EngineServiceInput::ResetClipping);
+            }
+            if ui.selectable_label(self.is_recording, "Record").clicked() {
+                inputs.push(EngineServiceInput::SetRecording(!self.is_recording));
+            }
+            let is_armed = self.is_master_capture_armed;
+            if ui
+                .selectable_label(is_armed, "Capture master")
+                .on_hover_text("Write the master output to a file")
+                .clicked()
+            {
+                inputs.push(EngineServiceInput::SetMasterCapture(!is_armed));
+            }
+            if is_armed {
+                ui.colored_label(Color32::RED, "●");
+            }
+            let mut block_size = self.block_size;
+            ComboBox::new(ui.next_auto_id(), "Block size")
+                .selected_text(block_size.to_string())
+                .show_ui(ui, |ui| {
+                    for size in Engine::BLOCK_SIZES {
+                        ui.selectable_value(&mut block_size, size, size.to_string());
+                    }
+                });
+            if block_size != self.block_size {
+                inputs.push(EngineServiceInput::SetBlockSize(block_size));
+            }
+            let sample_rate = self.sample_rate.0.max(1) as f64;
+            ui.label(format!("{:.1} ms", self.block_size as f64 * 1000.0 / sample_rate));
+            let mut channel_layout = self.channel_layout;
+            ComboBox::new(ui.next_auto_id(), "Channels")
+                .selected_text(channel_layout.to_string())
+                .show_ui(ui, |ui| {
+                    for layout in ChannelLayout::ALL {
+                        ui.selectable_value(&mut channel_layout, layout, layout.to_string());
+                    }
+                });
+            if channel_layout != self.channel_layout {
+                inputs.push(EngineServiceInput::SetChannelLayout(channel_layout));
+            }
+        });
+        if let Some(export_format) = self.ui_export_format(ui) {
+            inputs.push(EngineServiceInput::SetExportFormat(export_format));
+        }
+        self.performance.show(ui, self.sample_rate);
+
+        if wants_undo {
+            inputs.push(EngineServiceInput::Undo);
+        }
+        if wants_redo {
+            inputs.push(EngineServiceInput::Redo);
+        }
+        inputs
+    }
+
+    /// A dialog for the capture file's format. Returns the new format if the
+    /// user changed it.
+    fn ui_export_format(&self, ui: &mut eframe::egui::Ui) -> Option<ExportFormat> {
+        let mut format = self.export_format;
+        ui.menu_button(format!("Export {}…", format.container), |ui| {
+            ComboBox::new(ui.next_auto_id(), "Format")
+                .selected_text(format.container.to_string())
+                .show_ui(ui, |ui| {
+                    for &container in ExportContainer::ALL {
+                        let text = container.to_string();
+                        ui.selectable_value(&mut format.container, container, text);
+                    }
+                });
+            let has_bit_depth = format.container.has_bit_depth();
+            ui.add_enabled_ui(has_bit_depth, |ui| {
+                ComboBox::new(ui.next_auto_id(), "Bit depth")
+                    .selected_text(format.bit_depth.to_string())
+                    .show_ui(ui, |ui| {
+                        for bit_depth in BitDepth::ALL {
+                            let text = bit_depth.to_string();
+                            ui.selectable_value(&mut format.bit_depth, bit_depth, text);
+                        }
+                    });
+            });
+            // Floats keep every bit of the mix, so there's nothing to dither.
+            // FLAC has no floats, though.
+            let is_integer = has_bit_depth
+                && (format.bit_depth != BitDepth::Float32
+                    || format.container == ExportContainer::Flac);
+            ui.add_enabled(is_integer, Checkbox::new(&mut format.is_dithered, "Dither"));
+            let rate_text = |rate: Option<usize>| {
+                rate.map_or_else(|| "Device rate".to_string(), |rate| format!("{rate} Hz"))
+            };
+            ComboBox::new(ui.next_auto_id(), "Sample rate")
+                .selected_text(rate_text(format.sample_rate))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut format.sample_rate, None, rate_text(None));
+                    for rate in ExportFormat::SAMPLE_RATES {
+                        let rate = Some(rate);
+                        ui.selectable_value(&mut format.sample_rate, rate, rate_text(rate));
+                    }
+                });
+        });
+        (format != self.export_format).then_some(format)
+    }
+
+    /// A clip LED that stays lit until clicked. Returns true if clicked.
+    fn ui_clip_indicator(&self, ui: &mut eframe::egui::Ui) -> bool {
+        let (response, painter) = ui.allocate_painter(vec2(12.0, 12.0), Sense::click());
+        painter.circle_filled(
+            response.rect.center(),
+            5.0,
+            if self.is_clipping {
+                Color32::RED
+            } else {
+                Color32::DARK_GRAY
+            },
+        );
+        response.on_hover_text("Clip (click to reset)").clicked()
+    }
+
+    /// Draws the track list, master track last. Like [EngineSnapshot::show],
+    /// returns what the user asked the engine to do. Each track's own editor
+    /// locks just that track.
+    pub fn show_tracks(
+        &self,
+        ui: &mut eframe::egui::Ui,
+        meters: &mut HashMap<TrackUid, Meter>,
+    ) -> Vec<EngineServiceInput> {
+        let mut inputs = Vec::default();
+        ui.horizontal(|ui| {
+            if ui.button("Add track").clicked() {
+                inputs.push(EngineServiceInput::Execute(Command::AddTrack));
+            }
+            if ui.button("Add bus").clicked() {
+                inputs.push(EngineServiceInput::CreateBusTrack);
+            }
+        });
+        let mut track_names = vec![(TrackUid::default(), "Master".to_string())];
+        track_names.extend(self.tracks.iter().map(|t| (t.uid, t.name.clone())));
+        let buses: Vec<&TrackSnapshot> = self.tracks.iter().filter(|t| t.is_bus).collect();
+
+        for track in self.tracks.iter() {
+            let track_uid = track.uid;
+            meters.entry(track_uid).or_default().ui(ui);
+            track.editor.ui(ui, &self.control_targets);
+            let has_selection = !track.editor.selection().is_empty();
+            if let Some(action) = self.ui_selection(ui, track_uid, has_selection, &track_names) {
+                inputs.push(EngineServiceInput::EditSelection(track_uid, action));
+            }
+
+            ui.horizontal(|ui| {
+                if ui.button(format!("Delete Track {}", track_uid)).clicked() {
+                    inputs.push(EngineServiceInput::DeleteTrack(track_uid));
+                }
+                if ui.button("Duplicate").clicked() {
+                    inputs.push(EngineServiceInput::DuplicateTrack(track_uid));
+                }
+                if ui
+                    .selectable_label(track.is_capturing, "Capture")
+                    .on_hover_text("Write this track's output to its own file")
+                    .clicked()
+                {
+                    let is_armed = !track.is_capturing;
+                    inputs.push(EngineServiceInput::SetTrackCapture(track_uid, is_armed));
+                }
+                if track.is_capturing {
+                    ui.colored_label(Color32::RED, "●");
+                }
+                if !track.is_bus {
+                    let output_name = buses
+                        .iter()
+                        .find(|bus| Some(bus.uid) == track.output)
+                        .map_or("Master", |bus| bus.name.as_str());
+                    ComboBox::new(ui.next_auto_id(), "Output")
+                        .selected_text(output_name)
+                        .show_ui(ui, |ui| {
+                            if ui
+                                .selectable_label(track.output.is_none(), "Master")
+                                .clicked()
+                            {
+                                inputs.push(EngineServiceInput::RouteTrack(track_uid, None));
+                            }
+                            for bus in buses.iter() {
+                                let is_selected = track.output == Some(bus.uid);
+                                if ui.selectable_label(is_selected, &bus.name).clicked() {
+                                    let output = Some(bus.uid);
+                                    inputs.push(EngineServiceInput::RouteTrack(track_uid, output));
+                                }
+                            }
+                        });
+                }
+            });
+            if !track.is_bus {
+                for bus in buses.iter().filter(|bus| Some(bus.uid) != track.output) {
+                    let mut level = track
+                        .sends
+                        .get(&bus.uid)
+                        .copied()
+                        .unwrap_or(Normal::minimum())
+                        .0;
+                    if ui
+                        .add(
+                            Slider::new(&mut level, Normal::range())
+                                .text(format!("Send to {}", bus.name)),
+                        )
+                        .changed()
+                    {
+                        let level = Normal::from(level);
+                        inputs.push(EngineServiceInput::SetSend(track_uid, bus.uid, level));
+                    }
+                }
+            }
+        }
+        ui.separator();
+        let master_uid = TrackUid::default();
+        meters.entry(master_uid).or_default().ui(ui);
+        self.master_track.ui(ui, &self.control_targets);
+        let has_selection = !self.master_track.selection().is_empty();
+        if let Some(action) = self.ui_selection(ui, master_uid, has_selection, &track_names) {
+            inputs.push(EngineServiceInput::EditSelection(master_uid, action));
+        }
+        inputs
+    }
+
+    /// The buttons for bulk operations on the track's selected entities.
+    /// `tracks` are where the selection can move to, with their names.
+    fn ui_selection(
+        &self,
+        ui: &mut eframe::egui::Ui,
+        track_uid: TrackUid,
+        has_selection: bool,
+        tracks: &[(TrackUid, String)],
+    ) -> Option<SelectionAction> {
+        let mut action = None;
+        ui.horizontal(|ui| {
+            ui.add_enabled_ui(has_selection, |ui| {
+                if ui.button("Remove selected").clicked() {
+                    action = Some(SelectionAction::Remove);
+                }
+                if ui.button("Bypass selected").clicked() {
+                    action = Some(SelectionAction::Bypass(true));
+                }
+                if ui.button("Enable selected").clicked() {
+                    action = Some(SelectionAction::Bypass(false));
+                }
+                if ui.button("Copy selected").clicked() {
+                    action = Some(SelectionAction::Copy);
+                }
+                ui.menu_button("Move selected to…", |ui| {
+                    for (uid, name) in tracks.iter().filter(|(uid, _)| *uid != track_uid) {
+                        if ui.button(name).clicked() {
+                            action = Some(SelectionAction::MoveTo(*uid));
+                            ui.close_menu();
+                        }
+                    }
+                });
+            });
+            if ui
+                .add_enabled(self.can_paste, Button::new("Paste"))
+                .on_hover_text("Add copies of the copied entities")
+                .clicked()
+            {
+                action = Some(SelectionAction::Paste);
+            }
+        });
+        action
+    }
+}
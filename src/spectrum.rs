@@ -0,0 +1,150 @@
+use crate::ATOMIC_ORDERING;
+use crossbeam_queue::ArrayQueue;
+use derivative::Derivative;
+use ensnare::prelude::*;
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use std::sync::{atomic::AtomicBool, Arc};
+#[cfg(feature = "gui")]
+use eframe::{
+    egui::Sense,
+    epaint::{pos2, vec2, Color32, Stroke},
+};
+
+/// The number of samples in each FFT.
+const FFT_SIZE: usize = 2048;
+
+/// The lowest level drawn.
+#[cfg(feature = "gui")]
+const FLOOR_DB: f32 = -90.0;
+
+/// The audio-path end of a [SpectrumAnalyzer]. Cheap to clone, and never
+/// blocks.
+#[derive(Debug, Clone)]
+pub struct SpectrumFeed {
+    queue: Arc<ArrayQueue<f32>>,
+    is_enabled: Arc<AtomicBool>,
+}
+impl SpectrumFeed {
+    /// Hands a buffer to the analyzer, if it's enabled. If the UI hasn't
+    /// kept up, the oldest samples are discarded.
+    pub fn push(&self, frames: &[StereoSample]) {
+        if !self.is_enabled.load(ATOMIC_ORDERING) {
+            return;
+        }
+        for frame in frames {
+            let _ = self.queue.force_push(((frame.0 .0 + frame.1 .0) / 2.0) as f32);
+        }
+    }
+}
+
+/// Shows the frequency content of the audio fed to it through its
+/// [SpectrumFeed]. The FFT runs on the UI thread, and only while the analyzer
+/// is enabled.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct SpectrumAnalyzer {
+    feed: SpectrumFeed,
+    sample_rate: SampleRate,
+
+    /// The most recent FFT_SIZE samples, oldest first.
+    window: Vec<f32>,
+
+    #[derivative(Debug = "ignore")]
+    fft: Arc<dyn Fft<f32>>,
+}
+impl Default for SpectrumAnalyzer {
+    fn default() -> Self {
+        Self {
+            feed: SpectrumFeed {
+                queue: Arc::new(ArrayQueue::new(FFT_SIZE * 4)),
+                is_enabled: Default::default(),
+            },
+            sample_rate: Default::default(),
+            window: vec![0.0; FFT_SIZE],
+            fft: FftPlanner::new().plan_fft_forward(FFT_SIZE),
+        }
+    }
+}
+impl SpectrumAnalyzer {
+    pub fn feed(&self) -> SpectrumFeed {
+        self.feed.clone()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.feed.is_enabled.load(ATOMIC_ORDERING)
+    }
+
+    pub fn set_enabled(&mut self, is_enabled: bool) {
+        self.feed.is_enabled.store(is_enabled, ATOMIC_ORDERING);
+        if !is_enabled {
+            while self.feed.queue.pop().is_some() {}
+        }
+    }
+
+    pub fn update_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate;
+    }
+
+    /// Moves whatever the feed has collected into the analysis window.
+    pub fn drain_feed(&mut self) {
+        let mut incoming = Vec::default();
+        while let Some(sample) = self.feed.queue.pop() {
+            incoming.push(sample);
+        }
+        if incoming.len() >= FFT_SIZE {
+            self.window.copy_from_slice(&incoming[incoming.len() - FFT_SIZE..]);
+        } else {
+            self.window.rotate_left(incoming.len());
+            self.window[FFT_SIZE - incoming.len()..].copy_from_slice(&incoming);
+        }
+    }
+
+    /// Returns the magnitude in dB of each bin up to Nyquist.
+    pub fn magnitudes(&self) -> Vec<f32> {
+        // Hann window to keep leakage from smearing everything together.
+        let mut buffer: Vec<Complex<f32>> = self
+            .window
+            .iter()
+            .enumerate()
+            .map(|(i, &s)| {
+                let w =
+                    0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / FFT_SIZE as f32).cos();
+                Complex::new(s * w, 0.0)
+            })
+            .collect();
+        self.fft.process(&mut buffer);
+        let scale = 2.0 / FFT_SIZE as f32;
+        buffer[..FFT_SIZE / 2]
+            .iter()
+            .map(|c| 20.0 * (c.norm() * scale).max(1e-9).log10())
+            .collect()
+    }
+}
+#[cfg(feature = "gui")]
+impl Displays for SpectrumAnalyzer {
+    fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
+        self.drain_feed();
+        let magnitudes = self.magnitudes();
+
+        let (response, painter) =
+            ui.allocate_painter(vec2(ui.available_width().min(512.0), 128.0), Sense::hover());
+        let rect = response.rect;
+        painter.rect_filled(rect, 0.0, Color32::BLACK);
+
+        // Log-frequency axis from 20Hz to Nyquist.
+        let nyquist = (self.sample_rate.0 / 2) as f32;
+        let bin_width = nyquist / (FFT_SIZE / 2) as f32;
+        let low = 20.0f32.ln();
+        let high = nyquist.ln();
+        let points = (0..=rect.width() as usize)
+            .map(|x| {
+                let frequency = (low + (high - low) * x as f32 / rect.width()).exp();
+                let bin = ((frequency / bin_width) as usize).min(magnitudes.len() - 1);
+                let fraction = ((magnitudes[bin] - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0);
+                pos2(rect.left() + x as f32, rect.bottom() - fraction * rect.height())
+            })
+            .collect();
+        painter.line(points, Stroke::new(1.0, Color32::LIGHT_BLUE));
+        response
+    }
+}
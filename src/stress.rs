@@ -0,0 +1,277 @@
+//! A stress test for the actor system. It builds a project of many tracks and
+//! entities, sends random MIDI and parameter changes before every block, and
+//! checks after each block that every track's state machine is back to idle,
+//! that messages aren't piling up, and that the whole block arrived.
+
+use crate::{
+    engine::{ControlTarget, Engine},
+    executor::{Executor, SyncExecutor},
+    track::{TrackActor, TrackRequest},
+    traits::ProvidesActorService,
+};
+use anyhow::anyhow;
+use ensnare::prelude::*;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use std::{
+    any::Any,
+    fmt::Display,
+    panic::{catch_unwind, AssertUnwindSafe},
+    time::{Duration, Instant},
+};
+
+/// How big a project [run_stress_test] builds, and how hard it pushes it.
+#[derive(Debug, Clone)]
+pub struct StressConfig {
+    pub tracks: usize,
+    pub entities_per_track: usize,
+    pub blocks: usize,
+    /// How many random MIDI messages and parameter changes go out before
+    /// each block.
+    pub events_per_block: usize,
+    /// The most messages that may still be waiting, across all queues, once
+    /// a block is done.
+    pub max_queue_depth: usize,
+    /// The same seed builds the same project and sends the same events.
+    pub seed: u64,
+}
+impl Default for StressConfig {
+    fn default() -> Self {
+        Self {
+            tracks: 8,
+            entities_per_track: 4,
+            blocks: 1000,
+            events_per_block: 64,
+            max_queue_depth: 256,
+            seed: 0,
+        }
+    }
+}
+
+/// What a stress test did, and what it found.
+#[derive(Debug, Default, Clone)]
+pub struct StressReport {
+    pub blocks: usize,
+    pub frames: usize,
+    pub midi_messages: usize,
+    pub param_changes: usize,
+    /// The most messages that were waiting at the end of a block.
+    pub max_queue_depth: usize,
+    pub elapsed: Duration,
+    /// What went wrong, in the order it was found. The test stops after the
+    /// first block with a problem.
+    pub violations: Vec<String>,
+}
+impl StressReport {
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+impl Display for StressReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} blocks ({} frames) in {:.2} s",
+            self.blocks,
+            self.frames,
+            self.elapsed.as_secs_f64()
+        )?;
+        writeln!(
+            f,
+            "{} MIDI messages, {} parameter changes",
+            self.midi_messages, self.param_changes
+        )?;
+        writeln!(f, "Deepest queues after a block: {}", self.max_queue_depth)?;
+        if self.is_ok() {
+            writeln!(f, "No problems found")
+        } else {
+            for violation in self.violations.iter() {
+                writeln!(f, "Problem: {violation}")?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Builds a project as described by the config, and runs it on the caller's
+/// thread. Panics in the actors are reported as problems rather than passed
+/// on.
+pub fn run_stress_test(config: &StressConfig) -> StressReport {
+    let started_at = Instant::now();
+    let mut report = StressReport::default();
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        StressTest::new_with(config)?.run(&mut report)
+    }));
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => report.violations.push(format!("{e:#}")),
+        Err(payload) => {
+            let message = panic_message(&*payload);
+            report.violations.push(format!("Panicked: {message}"));
+        }
+    }
+    report.elapsed = started_at.elapsed();
+    report
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "(no message)".to_string()
+    }
+}
+
+struct StressTest {
+    config: StressConfig,
+    engine: Engine,
+    executor: SyncExecutor,
+    rng: StdRng,
+    track_uids: Vec<TrackUid>,
+    /// Every entity parameter in the project.
+    params: Vec<(TrackUid, Uid, ControlIndex)>,
+}
+impl StressTest {
+    /// The busy waiter is slow on purpose.
+    const SKIPPED_KEYS: [&'static str; 1] = ["busy-waiter"];
+
+    fn new_with(config: &StressConfig) -> anyhow::Result<Self> {
+        let executor = SyncExecutor::default();
+        let mut engine = Engine::new_with(Executor::Synchronous(executor.clone()));
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        let keys: Vec<String> = engine
+            .registry()
+            .entries()
+            .iter()
+            .map(|entry| entry.key.clone())
+            .filter(|key| !Self::SKIPPED_KEYS.contains(&key.as_str()))
+            .collect();
+        let mut track_uids = Vec::default();
+        for _ in 0..config.tracks {
+            let track_uid = engine.create_track()?;
+            executor.run_until_idle();
+            let track = engine
+                .track(track_uid)
+                .ok_or_else(|| anyhow!("Track {track_uid} disappeared"))?;
+            for _ in 0..config.entities_per_track {
+                let key = keys
+                    .choose(&mut rng)
+                    .ok_or_else(|| anyhow!("No entities"))?;
+                track.add_entity_by_key(key)?;
+            }
+            track_uids.push(track_uid);
+        }
+        executor.run_until_idle();
+        let params = engine
+            .control_targets()
+            .into_iter()
+            .filter_map(|(target, _)| match target {
+                ControlTarget::Entity(track_uid, uid, index) => Some((track_uid, uid, index)),
+                _ => None,
+            })
+            .collect();
+        Ok(Self {
+            config: config.clone(),
+            engine,
+            executor,
+            rng,
+            track_uids,
+            params,
+        })
+    }
+
+    fn run(&mut self, report: &mut StressReport) -> anyhow::Result<()> {
+        let block_size = self.engine.block_size();
+        for block in 0..self.config.blocks {
+            for _ in 0..self.config.events_per_block {
+                self.send_random_event(report)?;
+            }
+            self.executor.run_until_idle();
+            let frames = self.engine.render(block_size)?;
+            report.blocks += 1;
+            report.frames += frames.len();
+            if frames.len() != block_size {
+                let count = frames.len();
+                let violation = format!("Block {block}: {count} of {block_size} frames arrived");
+                report.violations.push(violation);
+            }
+            let is_finite = |f: &StereoSample| f.0 .0.is_finite() && f.1 .0.is_finite();
+            if !frames.iter().all(is_finite) {
+                let violation = format!("Block {block}: the output isn't a number");
+                report.violations.push(violation);
+            }
+            self.check_tracks(block, report);
+            if !report.is_ok() {
+                break;
+            }
+        }
+        for track_uid in self.track_uids.iter() {
+            if let Some(track) = self.engine.track(*track_uid) {
+                track.send_request(TrackRequest::ReleaseNotes);
+            }
+        }
+        self.executor.run_until_idle();
+        Ok(())
+    }
+
+    /// Sends a note to a random track, or changes a random parameter.
+    fn send_random_event(&mut self, report: &mut StressReport) -> anyhow::Result<()> {
+        let is_param = !self.params.is_empty() && self.rng.gen_bool(0.5);
+        if is_param {
+            let (track_uid, uid, index) = self.params[self.rng.gen_range(0..self.params.len())];
+            let value = ControlValue(self.rng.gen());
+            self.track(track_uid)?.set_param(uid, index, value)?;
+            report.param_changes += 1;
+        } else if let Some(&track_uid) = self.track_uids.choose(&mut self.rng) {
+            let key: u8 = self.rng.gen_range(24..108);
+            let message = if self.rng.gen_bool(0.5) {
+                let vel: u8 = self.rng.gen_range(1..128);
+                MidiMessage::NoteOn {
+                    key: key.into(),
+                    vel: vel.into(),
+                }
+            } else {
+                MidiMessage::NoteOff {
+                    key: key.into(),
+                    vel: 0.into(),
+                }
+            };
+            let request = TrackRequest::Midi(MidiChannel::default(), message);
+            self.track(track_uid)?.send_request(request);
+            report.midi_messages += 1;
+        }
+        Ok(())
+    }
+
+    /// Checks that every track finished the block, and that no queues are
+    /// filling up.
+    fn check_tracks(&self, block: usize, report: &mut StressReport) {
+        let master = (TrackUid::default(), self.engine.master_track());
+        let tracks = self
+            .track_uids
+            .iter()
+            .filter_map(|&uid| Some((uid, self.engine.track(uid)?)))
+            .chain(std::iter::once(master));
+        let mut queue_depth = 0;
+        for (uid, track) in tracks {
+            if !track.is_idle() {
+                let state = track.describe_state();
+                let violation = format!("Block {block}: track {uid} is stuck {state}");
+                report.violations.push(violation);
+            }
+            queue_depth += track.queue_depth();
+        }
+        report.max_queue_depth = report.max_queue_depth.max(queue_depth);
+        if queue_depth > self.config.max_queue_depth {
+            let violation = format!("Block {block}: {queue_depth} messages are still waiting");
+            report.violations.push(violation);
+        }
+    }
+
+    fn track(&self, uid: TrackUid) -> anyhow::Result<&TrackActor> {
+        self.engine
+            .track(uid)
+            .ok_or_else(|| anyhow!("No track {uid}"))
+    }
+}
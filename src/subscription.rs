@@ -1,4 +1,5 @@
 use crossbeam_channel::Sender;
+use log::warn;
 
 #[derive(Debug)]
 pub struct Subscription<A: Clone> {
@@ -25,15 +26,25 @@ impl<A: Clone> Subscription<A> {
         for sender in self.subscribers.iter() {
             let r = sender.try_send(action.clone());
             if let Err(e) = r {
-                eprintln!("Subscription: while broadcasting: {e:?}");
+                warn!("Subscription: while broadcasting: {e:?}");
             }
         }
     }
 
     /// Broadcasts to all subscribers, removing any that fail to send
-    /// successfully.
+    /// successfully. The last subscriber gets the action itself rather than
+    /// a clone, so that pooled buffers make it back to the pool.
     pub fn broadcast_mut(&mut self, action: A) {
-        self.subscribers
-            .retain(|sender| sender.try_send(action.clone()).is_ok());
+        let mut remaining = self.subscribers.len();
+        let mut action = Some(action);
+        self.subscribers.retain(|sender| {
+            remaining -= 1;
+            let action = if remaining == 0 {
+                action.take()
+            } else {
+                action.clone()
+            };
+            action.is_some_and(|action| sender.try_send(action).is_ok())
+        });
     }
 }
@@ -0,0 +1,160 @@
+//! Tempo changes during playback: a [TempoMap] of points in musical time,
+//! with linear ramps between them, and [TapTempo] for setting the tempo by
+//! tapping along.
+
+use ensnare::prelude::*;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// The tempo at a point in the song.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TempoPoint {
+    pub time: MusicalTime,
+    pub tempo: Tempo,
+}
+
+/// Tempo automation. The tempo ramps linearly, over musical time, from each
+/// point to the next. It holds the first point's tempo before it and the
+/// last point's after it.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct TempoMap {
+    /// In order of time, and never two at the same time.
+    points: Vec<TempoPoint>,
+}
+impl TempoMap {
+    /// Below this change in BPM per beat, a ramp is treated as flat, which
+    /// avoids dividing by almost zero.
+    const FLAT_SLOPE: f64 = 1e-9;
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn points(&self) -> &[TempoPoint] {
+        &self.points
+    }
+
+    /// Adds the point, replacing one that's at the same time.
+    pub fn insert(&mut self, point: TempoPoint) {
+        match self.points.iter().position(|p| p.time >= point.time) {
+            Some(index) if self.points[index].time == point.time => self.points[index] = point,
+            Some(index) => self.points.insert(index, point),
+            None => self.points.push(point),
+        }
+    }
+
+    pub fn remove(&mut self, time: MusicalTime) -> Option<TempoPoint> {
+        let index = self.points.iter().position(|p| p.time == time)?;
+        Some(self.points.remove(index))
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    /// The tempo at the given time, or None if there are no points.
+    pub fn tempo_at(&self, time: MusicalTime) -> Option<Tempo> {
+        self.bpm_at(Self::beats(time)).map(Tempo)
+    }
+
+    /// Where the playhead gets to after `frames` frames from `start`, and
+    /// the tempo there. None if there are no points.
+    pub fn advance(
+        &self,
+        start: MusicalTime,
+        frames: usize,
+        sample_rate: SampleRate,
+    ) -> Option<(MusicalTime, Tempo)> {
+        let mut beats = Self::beats(start);
+        let mut bpm = self.bpm_at(beats)?;
+        let mut seconds = frames as f64 / sample_rate.0.max(1) as f64;
+        // The tempo at `b` beats is bpm + slope * (b - beats), and the
+        // playhead moves at bpm / 60 beats a second. So the tempo grows
+        // exponentially with time within a ramp.
+        loop {
+            let next = self.points.iter().find(|p| Self::beats(p.time) > beats);
+            let Some(next) = next else {
+                beats += seconds * bpm / 60.0;
+                break;
+            };
+            let (next_beats, next_bpm) = (Self::beats(next.time), next.tempo.0);
+            let slope = (next_bpm - bpm) / (next_beats - beats);
+            let is_flat = slope.abs() < Self::FLAT_SLOPE;
+            let seconds_to_next = if is_flat {
+                (next_beats - beats) * 60.0 / bpm
+            } else {
+                60.0 / slope * (next_bpm / bpm).ln()
+            };
+            if seconds < seconds_to_next {
+                if is_flat {
+                    beats += seconds * bpm / 60.0;
+                } else {
+                    let end_bpm = bpm * (slope * seconds / 60.0).exp();
+                    beats += (end_bpm - bpm) / slope;
+                    bpm = end_bpm;
+                }
+                break;
+            }
+            seconds -= seconds_to_next;
+            beats = next_beats;
+            bpm = next_bpm;
+        }
+        let units = (beats * MusicalTime::UNITS_IN_BEAT as f64).round() as usize;
+        Some((MusicalTime::new_with_units(units), Tempo(bpm)))
+    }
+
+    fn beats(time: MusicalTime) -> f64 {
+        time.total_units() as f64 / MusicalTime::UNITS_IN_BEAT as f64
+    }
+
+    fn bpm_at(&self, beats: f64) -> Option<f64> {
+        let first = self.points.first()?;
+        let after = self.points.iter().position(|p| Self::beats(p.time) > beats);
+        Some(match after {
+            Some(0) => first.tempo.0,
+            Some(index) => {
+                let (from, to) = (self.points[index - 1], self.points[index]);
+                let (from_beats, to_beats) = (Self::beats(from.time), Self::beats(to.time));
+                let progress = (beats - from_beats) / (to_beats - from_beats);
+                from.tempo.0 + (to.tempo.0 - from.tempo.0) * progress
+            }
+            None => self.points[self.points.len() - 1].tempo.0,
+        })
+    }
+}
+
+/// Works out a tempo from the intervals between taps. A pause longer than
+/// [TapTempo::RESET_AFTER] starts over.
+#[derive(Debug, Default)]
+pub struct TapTempo {
+    taps: VecDeque<Instant>,
+}
+impl TapTempo {
+    pub const RESET_AFTER: Duration = Duration::from_secs(2);
+    /// How many of the latest taps are averaged.
+    const TAP_COUNT: usize = 5;
+
+    /// Notes a tap, and returns the tempo once there have been at least two.
+    pub fn tap(&mut self, at: Instant) -> Option<Tempo> {
+        if self
+            .taps
+            .back()
+            .is_some_and(|last| at.saturating_duration_since(*last) > Self::RESET_AFTER)
+        {
+            self.taps.clear();
+        }
+        self.taps.push_back(at);
+        if self.taps.len() > Self::TAP_COUNT {
+            self.taps.pop_front();
+        }
+        if self.taps.len() < 2 {
+            return None;
+        }
+        let (first, last) = (self.taps.front()?, self.taps.back()?);
+        let elapsed = last.saturating_duration_since(*first).as_secs_f64();
+        let seconds = elapsed / (self.taps.len() - 1) as f64;
+        (seconds > 0.0).then(|| Tempo(60.0 / seconds))
+    }
+}
@@ -0,0 +1,236 @@
+use crate::ATOMIC_ORDERING;
+use ensnare::prelude::*;
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize},
+        Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+#[cfg(feature = "gui")]
+use {
+    eframe::{
+        egui::{Align2, Grid, ScrollArea, Sense, Slider},
+        epaint::{pos2, vec2, Color32, Stroke},
+    },
+    std::collections::HashMap,
+};
+
+/// Who sent or received a traced message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ActorId {
+    /// Requests don't say who sent them.
+    Unknown,
+    Engine,
+    Track(TrackUid),
+    Entity(Uid),
+}
+impl Display for ActorId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ActorId::Unknown => write!(f, "?"),
+            ActorId::Engine => write!(f, "Engine"),
+            ActorId::Track(uid) => write!(f, "Track {uid}"),
+            ActorId::Entity(uid) => write!(f, "Entity {uid}"),
+        }
+    }
+}
+
+/// One message, recorded when its destination handled it.
+#[derive(Debug, Clone)]
+pub struct TraceEvent {
+    /// How long after tracing started.
+    pub at: Duration,
+    /// The generation cycle that was underway.
+    pub cycle: usize,
+    pub source: ActorId,
+    pub destination: ActorId,
+    /// The request or action variant, e.g., "NeedsAudio".
+    pub variant: &'static str,
+}
+
+/// Records every request and action that actors handle. It's off by default,
+/// and costs one atomic load per message while off. While it's on, it keeps
+/// the most recent [MessageTrace::CAPACITY] messages.
+#[derive(Debug)]
+pub struct MessageTrace {
+    is_enabled: AtomicBool,
+    cycle: AtomicUsize,
+    started_at: Instant,
+    events: Mutex<VecDeque<TraceEvent>>,
+}
+impl MessageTrace {
+    pub const CAPACITY: usize = 16384;
+
+    /// The trace that all actors in the process record to.
+    pub fn global() -> &'static Self {
+        static TRACE: OnceLock<MessageTrace> = OnceLock::new();
+        TRACE.get_or_init(|| Self {
+            is_enabled: Default::default(),
+            cycle: Default::default(),
+            started_at: Instant::now(),
+            events: Default::default(),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.is_enabled.load(ATOMIC_ORDERING)
+    }
+
+    pub fn set_enabled(&self, is_enabled: bool) {
+        self.is_enabled.store(is_enabled, ATOMIC_ORDERING);
+    }
+
+    /// How long since tracing started, in the same terms as [TraceEvent::at].
+    pub fn elapsed(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    /// The engine has started generating another block.
+    pub(crate) fn next_cycle(&self) {
+        self.cycle.fetch_add(1, ATOMIC_ORDERING);
+    }
+
+    pub(crate) fn record(&self, source: ActorId, destination: ActorId, variant: &'static str) {
+        if !self.is_enabled() {
+            return;
+        }
+        let event = TraceEvent {
+            at: self.started_at.elapsed(),
+            cycle: self.cycle.load(ATOMIC_ORDERING),
+            source,
+            destination,
+            variant,
+        };
+        let mut events = self.events.lock().unwrap();
+        if events.len() == Self::CAPACITY {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+
+    /// A copy of what has been recorded so far, oldest first.
+    pub fn events(&self) -> Vec<TraceEvent> {
+        self.events.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.events.lock().unwrap().clear();
+    }
+}
+
+/// Records a message in the [MessageTrace::global] trace, if it's on.
+pub(crate) fn trace_message(source: ActorId, destination: ActorId, variant: &'static str) {
+    MessageTrace::global().record(source, destination, variant);
+}
+
+/// Shows the messages of one generation cycle on a timeline, with a lane for
+/// each actor that received something.
+#[cfg(feature = "gui")]
+#[derive(Debug, Default)]
+pub struct TraceViewer {
+    /// None follows the most recent complete cycle.
+    cycle: Option<usize>,
+}
+#[cfg(feature = "gui")]
+impl Displays for TraceViewer {
+    fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
+        let trace = MessageTrace::global();
+        let mut is_enabled = trace.is_enabled();
+        let response = ui.checkbox(&mut is_enabled, "Trace messages");
+        if response.changed() {
+            trace.set_enabled(is_enabled);
+        }
+        if ui.button("Clear").clicked() {
+            trace.clear();
+            self.cycle = None;
+        }
+
+        let events = trace.events();
+        let (Some(first), Some(last)) = (events.first(), events.last()) else {
+            return response;
+        };
+        // The last cycle is probably still being recorded.
+        let first_cycle = first.cycle;
+        let last_cycle = last.cycle.saturating_sub(1).max(first_cycle);
+        let mut cycle = self.cycle.unwrap_or(last_cycle).clamp(first_cycle, last_cycle);
+        if ui
+            .add(Slider::new(&mut cycle, first_cycle..=last_cycle).text("Cycle"))
+            .changed()
+        {
+            self.cycle = Some(cycle);
+        }
+        if self.cycle.is_some() && ui.button("Follow").clicked() {
+            self.cycle = None;
+        }
+
+        let events: Vec<&TraceEvent> = events.iter().filter(|e| e.cycle == cycle).collect();
+        let (Some(start), Some(end)) = (events.first().map(|e| e.at), events.last().map(|e| e.at))
+        else {
+            return response;
+        };
+        let span = (end - start).as_secs_f32().max(f32::EPSILON);
+
+        let mut lanes: HashMap<ActorId, usize> = HashMap::default();
+        let mut lane_names = Vec::default();
+        for event in events.iter() {
+            lanes.entry(event.destination).or_insert_with(|| {
+                lane_names.push(event.destination.to_string());
+                lane_names.len() - 1
+            });
+        }
+
+        const LANE_HEIGHT: f32 = 16.0;
+        const LABEL_WIDTH: f32 = 80.0;
+        let (timeline, painter) = ui.allocate_painter(
+            vec2(ui.available_width(), LANE_HEIGHT * lane_names.len() as f32),
+            Sense::hover(),
+        );
+        let rect = timeline.rect;
+        let lane_y = |lane: usize| rect.top() + (lane as f32 + 0.5) * LANE_HEIGHT;
+        let time_x = |at: Duration| {
+            rect.left()
+                + LABEL_WIDTH
+                + (at - start).as_secs_f32() / span * (rect.width() - LABEL_WIDTH - 4.0)
+        };
+        for (lane, name) in lane_names.iter().enumerate() {
+            painter.text(
+                pos2(rect.left(), lane_y(lane)),
+                Align2::LEFT_CENTER,
+                name,
+                Default::default(),
+                ui.visuals().text_color(),
+            );
+        }
+        for event in events.iter() {
+            let x = time_x(event.at);
+            let to = pos2(x, lane_y(lanes[&event.destination]));
+            if let Some(&source_lane) = lanes.get(&event.source) {
+                painter.line_segment(
+                    [pos2(x, lane_y(source_lane)), to],
+                    Stroke::new(0.5, Color32::GRAY),
+                );
+            }
+            painter.circle_filled(to, 2.5, Color32::LIGHT_BLUE);
+        }
+
+        ScrollArea::vertical()
+            .id_source("trace-events")
+            .max_height(160.0)
+            .show(ui, |ui| {
+                Grid::new("trace-events-grid").striped(true).show(ui, |ui| {
+                    for event in events.iter() {
+                        ui.monospace(format!("+{}us", (event.at - start).as_micros()));
+                        ui.label(event.source.to_string());
+                        ui.label(event.destination.to_string());
+                        ui.label(event.variant);
+                        ui.end_row();
+                    }
+                });
+            });
+
+        response
+    }
+}
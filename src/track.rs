@@ -1,24 +1,57 @@
 use ensnare_v1::prelude::*;
 use crate::{
-    actions::{AudioAction, ControlAction, MidiAction},
-    always::AlwaysSame,
-    arp::Arpeggiator,
-    busy::BusyWaiter,
-    drone::DroneController,
-    entity::{EntityActor, EntityRequest},
-    mixer::Mixer,
-    quietener::Quietener,
+    actions::{AudioAction, ControlAction, MidiAction, TrackAction},
+    batch::AudioBatch,
+    buffer_pool::BufferPool,
+    channels::ChannelLayout,
+    command::Command,
+    engine::Engine,
+    executor::{join_on_drop, ActorLoop, ActorStep, Executor},
+    groove::Groove,
+    latency::DelayLine,
+    limits::ActorLimits,
+    metrics::time_work,
+    midi_input::MidiInputProcessor,
+    trace::{trace_message, ActorId},
+    clip::{AudioClip, ClipEdits, ClipSpan, MidiClip},
+    entity::{EntityActor, EntityRequest, EntityRoles, EntitySettings},
+    meter::MeterSnapshot,
+    mixer::{CrossfadeCurve, CrossfadeGroup, Mixer},
+    notes::ActiveNotes,
+    plugin::{PluginDescriptor, PluginEntity},
+    preset::EntityPresets,
+    project::{EntityProject, TrackProject},
+    registry::{latency_fn, EntityDuplicateFn, EntityRegistry, NewEntity},
     subscription::Subscription,
-    traits::ProvidesActorService,
+    traits::{ProvidesActorService, ReportsLatency},
+    transfer::TransferFunction,
+    wav_writer::{WavWriterInput, WavWriterService},
 };
 use anyhow::anyhow;
 use crossbeam_channel::{Receiver, Select, Sender};
-use eframe::egui::{ComboBox, Frame, Margin};
-use ensnare::{prelude::*, types::CrossbeamChannel};
-use ensnare_toys::{ToyInstrument, ToySynth};
+use derivative::Derivative;
+use ensnare::{prelude::*, traits::ProvidesService, types::CrossbeamChannel};
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
+    path::PathBuf,
     sync::{Arc, Mutex},
+    thread::JoinHandle,
+    time::Duration,
+};
+#[cfg(feature = "gui")]
+use {
+    crate::{
+        browser::BrowserItem,
+        clip::{Humanize, Quantize},
+        engine::{ControlRoute, ControlTarget},
+        entity::ui_midi_channel,
+        groove::{GrooveGrid, GrooveTemplate},
+        metrics::CpuMetrics,
+        notification::report_error,
+    },
+    eframe::egui::{Button, Color32, ComboBox, DragValue, Frame, Margin, RichText, Slider},
 };
 
 #[derive(Debug, Clone)]
@@ -29,23 +62,171 @@ pub enum TrackRequest {
     UnsubscribeAudio(Sender<AudioAction>),
     /// Add a subscriber to our audio actions.
     SubscribeMidi(Sender<MidiAction>),
-    /// Remove a subscriber from our audio actions.
+    /// Remove a subscriber from our MIDI actions. It gets NoteOffs for
+    /// the notes it's still hearing from us.
     UnsubscribeMidi(Sender<MidiAction>),
+    /// Send NoteOffs for every sounding note, to the track's entities and
+    /// MIDI subscribers, e.g., because the track is being removed.
+    ReleaseNotes,
+    /// Add a subscriber to our track actions.
+    SubscribeTrackActions(Sender<TrackAction>),
+    /// Remove a subscriber from our track actions.
+    UnsubscribeTrackActions(Sender<TrackAction>),
+    /// Add a subscriber to copies of our output, as [TrackAction::Frames].
+    SubscribeFrames(Sender<TrackAction>),
+    /// Remove a subscriber from copies of our output.
+    UnsubscribeFrames(Sender<TrackAction>),
     /// The track should handle an incoming MIDI message.
     Midi(MidiChannel, MidiMessage),
+    /// Accept incoming MIDI only on the given channel (Some), or on any
+    /// channel (None, or omni).
+    SetMidiChannelFilter(Option<MidiChannel>),
+    /// Transpose incoming notes and change their velocities.
+    SetMidiInput(MidiInputProcessor),
+    /// Ask all generators for audio with one shared [AudioBatch] (true), or
+    /// with a separate request and reply for each (false).
+    SetBatchGenerators(bool),
+    /// Map the next MIDI CC message the track gets to the given entity
+    /// parameter.
+    MidiLearn(Uid, ControlIndex),
     /// The track should perform work for the given slice of time.
     Work(TimeRange),
+    /// The transport jumped to the given position.
+    Seek(MusicalTime),
     /// The track should generate a buffer of audio frames.
     NeedsAudio(usize),
+    /// Delay the track's output by the given number of frames, so that it
+    /// lines up with slower tracks.
+    SetLatencyCompensation(usize),
+    /// Delay the track's send to the given bus by the given number of
+    /// frames, so that it lines up with the bus's slower inputs.
+    SetSendLatencyCompensation(TrackUid, usize),
     /// This track should consume the given track's output. All tracks,
     /// including the master track, accept sends. An aux track is one whose
     /// audio sources are only sends.
     AddSend(TrackUid, Sender<TrackRequest>),
     /// This track should stop consuming the given track's output.
     RemoveSend(TrackUid),
+    /// In addition to its main output, this track should send a copy of its
+    /// output, scaled by the given level, to the given track. That track
+    /// should also have been told to [TrackRequest::AddSend] this one.
+    SubscribeSend(TrackUid, Sender<AudioAction>, Normal),
+    /// Stop sending a copy of this track's output to the given track.
+    UnsubscribeSend(TrackUid),
+    /// Change the level of the send to the given track.
+    SetSendLevel(TrackUid, Normal),
+    /// Give the second send the same mixer settings as the first.
+    CopyMixerSettings(TrackUid, TrackUid),
+    /// Move the given entity to the given position in the track's processing
+    /// order.
+    MoveEntity(Uid, usize),
+    /// Put the given effect into a parallel group (Some), or back into the
+    /// serial chain (None). Adjacent effects in the same group process the
+    /// same input concurrently, and their outputs are averaged.
+    SetEffectGroup(Uid, Option<usize>),
+    /// Set how much of the given effect's output replaces its input. 1.0 is
+    /// fully wet, 0.0 fully dry.
+    SetEffectMix(Uid, Normal),
+    /// Put the given entity into the track's MIDI chain (true), or take it
+    /// out (false). The chain's MIDI effects run in processing order, each
+    /// hearing only the previous one's output.
+    SetMidiEffect(Uid, bool),
+    /// Arm (true) or disarm (false) this track for recording.
+    Arm(bool),
+    /// If armed, the track should begin capturing incoming audio and MIDI.
+    StartRecording,
+    /// The track should stop capturing incoming audio and MIDI.
+    StopRecording,
+    /// Choose whether a new MIDI recording replaces or adds to the existing
+    /// clip.
+    SetRecordMode(RecordMode),
+    /// Change the swing and groove of the track's sequencers.
+    SetGroove(Groove),
+    /// Replace the track's MIDI clip, e.g., with one imported from a file.
+    SetMidiClip(MidiClip),
+    /// Change the quantizing and humanizing that the track applies to its
+    /// MIDI clip during playback.
+    SetClipEdits(ClipEdits),
+    /// Add an audio clip to the track.
+    AddAudioClip(AudioClip),
+    /// The engine's sample rate, tempo, or block size changed. The block size
+    /// is the most frames that a single [TrackRequest::NeedsAudio] will ask
+    /// for.
+    Configure(SampleRate, Tempo, usize),
+    /// Audio arrived from the audio interface's input. Armed tracks that are
+    /// recording append it to their current take.
+    AudioInput(Vec<StereoSample>),
+    /// Render the track from the start of the song up to the given time, and
+    /// play back the result instead of running the track's entities. A freeze
+    /// renders at the track's constant tempo, so buses and tracks in a
+    /// tempo-mapped song refuse it.
+    Freeze(MusicalTime),
+    /// Discard the frozen audio and go back to running the track's entities.
+    Unfreeze,
+    /// Whether the engine follows a tempo map during playback.
+    SetIsTempoMapped(bool),
+    /// Rename the track.
+    SetName(String),
+    /// Change the track's color, as sRGB.
+    SetColor([u8; 3]),
+    /// If the track has a recorded take, write it to the given file.
+    WriteRecording(PathBuf, SampleRate),
     /// The [TrackActor] should exit.
     Quit,
 }
+impl TrackRequest {
+    /// The variant's name, for [MessageTrace](crate::trace::MessageTrace).
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            TrackRequest::SubscribeAudio(..) => "SubscribeAudio",
+            TrackRequest::UnsubscribeAudio(..) => "UnsubscribeAudio",
+            TrackRequest::SubscribeMidi(..) => "SubscribeMidi",
+            TrackRequest::UnsubscribeMidi(..) => "UnsubscribeMidi",
+            TrackRequest::ReleaseNotes => "ReleaseNotes",
+            TrackRequest::SubscribeTrackActions(..) => "SubscribeTrackActions",
+            TrackRequest::UnsubscribeTrackActions(..) => "UnsubscribeTrackActions",
+            TrackRequest::SubscribeFrames(..) => "SubscribeFrames",
+            TrackRequest::UnsubscribeFrames(..) => "UnsubscribeFrames",
+            TrackRequest::Midi(..) => "Midi",
+            TrackRequest::SetMidiChannelFilter(..) => "SetMidiChannelFilter",
+            TrackRequest::SetMidiInput(..) => "SetMidiInput",
+            TrackRequest::SetBatchGenerators(..) => "SetBatchGenerators",
+            TrackRequest::MidiLearn(..) => "MidiLearn",
+            TrackRequest::Work(..) => "Work",
+            TrackRequest::Seek(..) => "Seek",
+            TrackRequest::NeedsAudio(..) => "NeedsAudio",
+            TrackRequest::SetLatencyCompensation(..) => "SetLatencyCompensation",
+            TrackRequest::SetSendLatencyCompensation(..) => "SetSendLatencyCompensation",
+            TrackRequest::AddSend(..) => "AddSend",
+            TrackRequest::RemoveSend(..) => "RemoveSend",
+            TrackRequest::SubscribeSend(..) => "SubscribeSend",
+            TrackRequest::UnsubscribeSend(..) => "UnsubscribeSend",
+            TrackRequest::SetSendLevel(..) => "SetSendLevel",
+            TrackRequest::CopyMixerSettings(..) => "CopyMixerSettings",
+            TrackRequest::MoveEntity(..) => "MoveEntity",
+            TrackRequest::SetEffectGroup(..) => "SetEffectGroup",
+            TrackRequest::SetEffectMix(..) => "SetEffectMix",
+            TrackRequest::SetMidiEffect(..) => "SetMidiEffect",
+            TrackRequest::Arm(..) => "Arm",
+            TrackRequest::StartRecording => "StartRecording",
+            TrackRequest::StopRecording => "StopRecording",
+            TrackRequest::SetRecordMode(..) => "SetRecordMode",
+            TrackRequest::SetGroove(..) => "SetGroove",
+            TrackRequest::SetMidiClip(..) => "SetMidiClip",
+            TrackRequest::SetClipEdits(..) => "SetClipEdits",
+            TrackRequest::AddAudioClip(..) => "AddAudioClip",
+            TrackRequest::Configure(..) => "Configure",
+            TrackRequest::AudioInput(..) => "AudioInput",
+            TrackRequest::Freeze(..) => "Freeze",
+            TrackRequest::Unfreeze => "Unfreeze",
+            TrackRequest::SetIsTempoMapped(..) => "SetIsTempoMapped",
+            TrackRequest::SetName(..) => "SetName",
+            TrackRequest::SetColor(..) => "SetColor",
+            TrackRequest::WriteRecording(..) => "WriteRecording",
+            TrackRequest::Quit => "Quit",
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct TrackActor {
@@ -58,23 +239,65 @@ pub struct TrackActor {
     /// Receives MIDI actions.
     midi_actions: CrossbeamChannel<MidiAction>,
 
+    /// Receives track actions from the tracks that send to this one.
+    track_actions: CrossbeamChannel<TrackAction>,
+
     inner: Arc<Mutex<Track>>,
-}
-impl Displays for TrackActor {
-    fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
-        self.inner.lock().unwrap().ui(ui)
-    }
+
+    /// The track's thread, if the executor gave it one.
+    thread: Option<JoinHandle<()>>,
 }
 impl ProvidesActorService<TrackRequest, AudioAction> for TrackActor {
     fn sender(&self) -> &Sender<TrackRequest> {
         &self.requests.sender
     }
 }
+impl Drop for TrackActor {
+    fn drop(&mut self) {
+        self.send_request(TrackRequest::Quit);
+        join_on_drop(self.thread.take(), Self::DROP_TIMEOUT, "A track");
+    }
+}
+
+/// Draws a track's editor from the UI thread. It locks only the track, so
+/// drawing doesn't hold up the [Engine]. Edits that the engine has to know
+/// about go to it as [Command]s.
+#[derive(Debug, Clone)]
+pub struct TrackEditor {
+    inner: Arc<Mutex<Track>>,
+}
+#[cfg(feature = "gui")]
+impl TrackEditor {
+    /// Draws the track. `control_targets` are the parameters anywhere in the
+    /// project that the track offers as targets for its entities' control
+    /// signals.
+    pub fn ui(
+        &self,
+        ui: &mut eframe::egui::Ui,
+        control_targets: &Arc<Vec<(ControlTarget, String)>>,
+    ) -> eframe::egui::Response {
+        let mut track = self.inner.lock().unwrap();
+        track.control_targets = Arc::clone(control_targets);
+        track.ui(ui)
+    }
+
+    /// The entities picked for bulk operations.
+    pub fn selection(&self) -> Vec<Uid> {
+        self.inner.lock().unwrap().selection()
+    }
+}
 impl TrackActor {
+    /// How long dropping the actor waits for its thread to exit. Its
+    /// entities get their own time when the track drops them.
+    const DROP_TIMEOUT: Duration = Duration::from_secs(1);
+
     pub(crate) fn new_with(
         track_uid: TrackUid,
         is_master_track: bool,
         uid_factory: &Arc<EntityUidFactory>,
+        registry: &Arc<EntityRegistry>,
+        commands: &Sender<Command>,
+        executor: &Executor,
     ) -> Self {
         // These three channel pairs are for actions we want to receive from
         // downstream (entities and child tracks).
@@ -99,122 +322,499 @@ impl TrackActor {
             is_master_track,
             action_subscription_senders,
             uid_factory,
+            registry,
+            commands,
+            executor,
         );
         let mut r = Self {
             requests: Default::default(),
             audio_actions: audio_action_channel_pair,
             midi_actions: midi_action_channel_pair,
+            track_actions: Default::default(),
             inner: Arc::new(Mutex::new(track)),
+            thread: Default::default(),
         };
 
-        r.start_thread(audio_receiver, midi_receiver, control_receiver);
+        r.start_loop(
+            executor,
+            track_uid,
+            is_master_track,
+            audio_receiver,
+            midi_receiver,
+            control_receiver,
+        );
 
         r
     }
 
-    fn start_thread(
+    fn start_loop(
         &mut self,
+        executor: &Executor,
+        track_uid: TrackUid,
+        is_master_track: bool,
         audio_receiver: Receiver<AudioAction>,
         midi_receiver: Receiver<MidiAction>,
         control_receiver: Receiver<ControlAction>,
     ) {
-        let input_receiver = self.requests.receiver.clone();
-        let track = Arc::clone(&self.inner);
-
-        std::thread::spawn(move || {
-            let mut sel = Select::default();
-
-            let input_index = sel.recv(&input_receiver);
-            let audio_index = sel.recv(&audio_receiver);
-            let midi_index = sel.recv(&midi_receiver);
-            let control_index = sel.recv(&control_receiver);
-
-            loop {
-                let operation = sel.select();
-                match operation.index() {
-                    index if index == input_index => {
-                        if let Ok(request) = Self::recv_operation(operation, &input_receiver) {
-                            match request {
-                                TrackRequest::Midi(channel, message) => {
-                                    if let Ok(mut track) = track.lock() {
-                                        track
-                                            .entity_request_subscription
-                                            .broadcast_mut(EntityRequest::Midi(channel, message));
-                                    }
-                                }
-                                TrackRequest::NeedsAudio(count) => {
-                                    track.lock().unwrap().handle_needs_audio(count);
-                                }
-                                TrackRequest::Quit => {
-                                    if let Ok(mut track) = track.lock() {
-                                        track
-                                            .entity_request_subscription
-                                            .broadcast_mut(EntityRequest::Quit);
-                                    }
-                                    break;
-                                }
-                                TrackRequest::Work(time_range) => {
-                                    if let Ok(mut track) = track.lock() {
-                                        track
-                                            .entity_request_subscription
-                                            .broadcast_mut(EntityRequest::Work(time_range.clone()));
-                                    }
-                                }
-                                TrackRequest::AddSend(uid, sender) => {
-                                    if let Ok(mut track) = track.lock() {
-                                        track.send_tracks.insert(uid, sender);
-                                        if let Some(mixer) = track.mixer.as_mut() {
-                                            mixer.add_track(uid);
-                                        }
-                                    }
-                                }
-                                TrackRequest::RemoveSend(uid) => {
-                                    if let Ok(mut track) = track.lock() {
-                                        track.send_tracks.remove(&uid);
-                                    }
-                                }
-                                TrackRequest::SubscribeAudio(sender) => {
-                                    track.lock().unwrap().audio_subscription.subscribe(&sender);
-                                }
-                                TrackRequest::UnsubscribeAudio(sender) => {
-                                    track
-                                        .lock()
-                                        .unwrap()
-                                        .audio_subscription
-                                        .unsubscribe(&sender);
-                                }
-                                TrackRequest::SubscribeMidi(sender) => {
-                                    track.lock().unwrap().midi_subscription.subscribe(&sender)
-                                }
-                                TrackRequest::UnsubscribeMidi(sender) => {
-                                    track.lock().unwrap().midi_subscription.unsubscribe(&sender);
-                                }
-                            }
-                        }
-                    }
-                    index if index == audio_index => {
-                        if let Ok(action) = Self::recv_operation(operation, &audio_receiver) {
-                            track.lock().unwrap().handle_audio_action(action);
-                        }
-                    }
-                    index if index == midi_index => {
-                        if let Ok(action) = Self::recv_operation(operation, &midi_receiver) {
-                            track.lock().unwrap().handle_midi_action(action)
-                        }
-                    }
-                    index if index == control_index => {
-                        if let Ok(_action) = Self::recv_operation(operation, &control_receiver) {
-                            panic!("For now, Tracks shouldn't receive Control messages")
-                        }
-                    }
-                    _ => {
-                        panic!("Unexpected select index")
-                    }
-                }
-            }
+        self.thread = executor.start(TrackLoop {
+            uid: track_uid,
+            is_master_track,
+            track: Arc::clone(&self.inner),
+            requests: self.requests.receiver.clone(),
+            audio_actions: audio_receiver,
+            midi_actions: midi_receiver,
+            control_actions: control_receiver,
+            track_actions: self.track_actions.receiver.clone(),
         });
     }
 
+    /// Hands over the threads of this track and its entities, for joining
+    /// once the track has been told to [TrackRequest::Quit].
+    pub(crate) fn take_threads(&mut self) -> Vec<JoinHandle<()>> {
+        let mut threads: Vec<_> = self.thread.take().into_iter().collect();
+        let mut track = self.inner.lock().unwrap();
+        threads.extend(track.actors.values_mut().filter_map(|a| a.take_thread()));
+        threads
+    }
+
+    /// Makes this track a copy of the other one: the same entities in the
+    /// same state and order, with the same links, clips, and settings.
+    pub(crate) fn duplicate_from(&self, other: &TrackActor) -> anyhow::Result<()> {
+        let other = other.inner.lock().unwrap();
+        self.inner.lock().unwrap().duplicate_from(&other)
+    }
+
+    /// The track's name, color, and entities, for saving with the project.
+    pub fn to_project(&self) -> anyhow::Result<TrackProject> {
+        self.inner.lock().unwrap().to_project()
+    }
+
+    /// Takes the saved track's name and color, and adds its entities after
+    /// any that this track already has.
+    pub fn add_project(&self, project: &TrackProject) -> anyhow::Result<()> {
+        self.inner.lock().unwrap().add_project(project)
+    }
+
+    /// Creates an entity of the kind registered under the given key, and adds
+    /// it to this track.
+    pub fn add_entity_by_key(&self, key: &str) -> anyhow::Result<Uid> {
+        self.inner.lock().unwrap().add_entity_by_key(key)
+    }
+
+    /// Instantiates the plugin, adds it to this track, and returns its uid.
+    pub fn add_plugin(&self, descriptor: &PluginDescriptor) -> anyhow::Result<Uid> {
+        self.inner.lock().unwrap().add_plugin(descriptor)
+    }
+
+    /// Drives the target entity's parameter with the source entity's control
+    /// signal.
+    pub fn link(
+        &self,
+        source_uid: Uid,
+        target_uid: Uid,
+        index: ControlIndex,
+    ) -> anyhow::Result<()> {
+        self.inner.lock().unwrap().link(source_uid, target_uid, index)
+    }
+
+    /// Stops the source entity from driving the target's parameter.
+    pub fn unlink(&self, source_uid: Uid, target_uid: Uid, index: ControlIndex) {
+        self.inner.lock().unwrap().unlink(source_uid, target_uid, index);
+    }
+
+    /// Every parameter of this track's entities, with its name for the UI.
+    pub fn controllables(&self) -> Vec<(Uid, ControlIndex, String)> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .controllables
+            .iter()
+            .skip(1)
+            .map(|c| (c.uid, c.param, c.name.clone()))
+            .collect()
+    }
+
+    /// Where an entity on another track sends control signals that should
+    /// drive one of this track's entities.
+    pub(crate) fn entity_control_sender(&self, uid: Uid) -> anyhow::Result<Sender<ControlAction>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner.actor(uid)?.control_sender().clone())
+    }
+
+    /// Where an entity sends control signals that should drive this track's
+    /// mixer.
+    pub(crate) fn control_sender(&self) -> Sender<ControlAction> {
+        let inner = self.inner.lock().unwrap();
+        inner.actor_subscription_senders.control.clone()
+    }
+
+    pub(crate) fn send_entity_request(
+        &self,
+        uid: Uid,
+        request: EntityRequest,
+    ) -> anyhow::Result<()> {
+        self.inner.lock().unwrap().actor(uid)?.send(request);
+        Ok(())
+    }
+
+    /// Drives the level of one of the tracks in this track's mixer with the
+    /// entity's control signal. The entity should also be told to
+    /// [EntityRequest::ControlSubscribe] this track's
+    /// [TrackActor::control_sender].
+    pub(crate) fn link_mixer_level(&self, source_uid: Uid, track_uid: TrackUid) {
+        let mut inner = self.inner.lock().unwrap();
+        let links = inner.mixer_level_links.entry(source_uid).or_default();
+        links.push(track_uid);
+    }
+
+    /// Undoes [TrackActor::link_mixer_level].
+    pub(crate) fn unlink_mixer_level(&self, source_uid: Uid, track_uid: TrackUid) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(links) = inner.mixer_level_links.get_mut(&source_uid) {
+            if let Some(position) = links.iter().position(|t| *t == track_uid) {
+                links.remove(position);
+            }
+        }
+    }
+
+    /// Drives the crossfader in this track's mixer with the entity's control
+    /// signal, the same way as [TrackActor::link_mixer_level].
+    pub(crate) fn link_crossfader(&self, source_uid: Uid) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.crossfader_links.insert(source_uid);
+    }
+
+    /// Undoes [TrackActor::link_crossfader].
+    pub(crate) fn unlink_crossfader(&self, source_uid: Uid) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.crossfader_links.remove(&source_uid);
+    }
+
+    /// A handle for drawing this track without going through the [Engine].
+    pub fn editor(&self) -> TrackEditor {
+        TrackEditor {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+
+    /// Removes the entity from this track, but keeps it around so that it can
+    /// be put back.
+    pub fn detach_entity(&self, uid: Uid) -> anyhow::Result<DetachedEntity> {
+        self.inner.lock().unwrap().detach_entity(uid)
+    }
+
+    /// The selected entities, in processing order.
+    pub fn selection(&self) -> Vec<Uid> {
+        self.inner.lock().unwrap().selection()
+    }
+
+    pub fn set_selected(&self, uid: Uid, is_selected: bool) {
+        self.inner.lock().unwrap().set_selected(uid, is_selected);
+    }
+
+    /// Bypasses (true) or re-enables (false) those of the given entities
+    /// that are effects.
+    pub fn set_bypass(&self, uids: &[Uid], is_bypassed: bool) {
+        let mut inner = self.inner.lock().unwrap();
+        for uid in uids {
+            if let Some(actor) = inner.actors.get_mut(uid) {
+                actor.set_bypass(is_bypassed);
+            }
+        }
+    }
+
+    /// Snapshots the given entities and the links among them.
+    pub fn copy_entities(&self, uids: &[Uid]) -> anyhow::Result<EntityClipboard> {
+        self.inner.lock().unwrap().copy_entities(uids)
+    }
+
+    /// Adds copies of the clipboard's entities, and returns their uids.
+    pub fn paste_entities(&self, clipboard: &EntityClipboard) -> anyhow::Result<Vec<Uid>> {
+        self.inner.lock().unwrap().paste_entities(clipboard)
+    }
+
+    /// Moves the given entities to the other track. See
+    /// [Track::move_entities_to].
+    pub(crate) fn move_entities_to(
+        &self,
+        other: &TrackActor,
+        uids: &[Uid],
+    ) -> anyhow::Result<Vec<(Uid, ControlLink)>> {
+        if Arc::ptr_eq(&self.inner, &other.inner) {
+            return Err(anyhow!("The entities are already on this track"));
+        }
+        let mut other = other.inner.lock().unwrap();
+        let mut inner = self.inner.lock().unwrap();
+        inner.move_entities_to(&mut other, uids)
+    }
+
+    /// Puts a removed entity back.
+    pub fn attach_entity(&self, detached: DetachedEntity) -> anyhow::Result<Uid> {
+        self.inner.lock().unwrap().attach_entity(detached)
+    }
+
+    /// Sets the level of one of the tracks in this track's mixer, and returns
+    /// the old level. Only the master track has a mixer.
+    pub fn set_mixer_level(&self, track_uid: TrackUid, level: Normal) -> anyhow::Result<Normal> {
+        self.inner.lock().unwrap().set_mixer_level(track_uid, level)
+    }
+
+    /// Mutes or unmutes one of the tracks in this track's mixer, and returns
+    /// whether it was muted.
+    pub fn set_mixer_mute(&self, track_uid: TrackUid, muted: bool) -> anyhow::Result<bool> {
+        self.inner.lock().unwrap().set_mixer_mute(track_uid, muted)
+    }
+
+    /// Each track's level and mute state in this track's mixer. Empty if
+    /// the track has no mixer.
+    pub fn mixer_levels(&self) -> Vec<(TrackUid, Normal, bool)> {
+        match self.inner.lock().unwrap().mixer.as_ref() {
+            Some(mixer) => mixer.levels(),
+            None => Vec::default(),
+        }
+    }
+
+    /// Sends one of the tracks in this track's mixer to a different output
+    /// pair, and returns the old one.
+    pub fn set_output_pair(&self, track_uid: TrackUid, pair: usize) -> anyhow::Result<usize> {
+        self.inner.lock().unwrap().set_output_pair(track_uid, pair)
+    }
+
+    /// Puts one of the tracks in this track's mixer on a side of the
+    /// crossfader, and returns the old side.
+    pub fn set_crossfade_group(
+        &self,
+        track_uid: TrackUid,
+        group: CrossfadeGroup,
+    ) -> anyhow::Result<CrossfadeGroup> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.set_crossfade_group(track_uid, group)
+    }
+
+    /// Moves the crossfader in this track's mixer, and returns where it was.
+    pub fn set_crossfader(&self, position: Normal) -> anyhow::Result<Normal> {
+        self.inner.lock().unwrap().set_crossfader(position)
+    }
+
+    /// Changes the crossfade curve of this track's mixer, and returns the old
+    /// one.
+    pub fn set_crossfade_curve(&self, curve: CrossfadeCurve) -> anyhow::Result<CrossfadeCurve> {
+        self.inner.lock().unwrap().set_crossfade_curve(curve)
+    }
+
+    /// Sets how many output pairs this track's mixer feeds. Only the master
+    /// track has a mixer; other tracks ignore this.
+    pub fn set_channel_layout(&self, channel_layout: ChannelLayout) {
+        self.inner.lock().unwrap().set_channel_layout(channel_layout);
+    }
+
+    /// Sets a parameter of one of this track's entities.
+    pub fn set_param(
+        &self,
+        uid: Uid,
+        index: ControlIndex,
+        value: ControlValue,
+    ) -> anyhow::Result<()> {
+        self.inner.lock().unwrap().set_param(uid, index, value)
+    }
+
+    pub fn midi_clip(&self) -> MidiClip {
+        self.inner.lock().unwrap().midi_clip.clone()
+    }
+
+    /// Replaces the track's MIDI clip, and returns the old one.
+    pub fn set_midi_clip(&self, midi_clip: MidiClip) -> MidiClip {
+        self.inner.lock().unwrap().set_midi_clip(midi_clip)
+    }
+
+    /// Changes the quantizing and humanizing that the track applies to its
+    /// MIDI clip during playback, and returns the old settings.
+    pub fn set_clip_edits(&self, clip_edits: ClipEdits) -> ClipEdits {
+        self.inner.lock().unwrap().set_clip_edits(clip_edits)
+    }
+
+    /// The last value that one of this track's entities' parameters was set
+    /// to, if it was set from here. See
+    /// [ParameterCompare](crate::compare::ParameterCompare).
+    pub fn param(&self, uid: Uid, index: ControlIndex) -> Option<ControlValue> {
+        let inner = self.inner.lock().unwrap();
+        inner.actors.get(&uid)?.param(index)
+    }
+
+    /// Whether the track has stopped asking the entity for audio. See
+    /// [EntityRoles::can_suspend](crate::entity::EntityRoles::can_suspend).
+    pub fn is_suspended(&self, uid: Uid) -> bool {
+        let inner = self.inner.lock().unwrap();
+        inner.actors.get(&uid).is_some_and(|a| a.is_suspended())
+    }
+
+    /// Gives each of the entity's parameters a random value within the
+    /// range set with [TrackActor::set_random_range].
+    pub fn randomize_params(&self, uid: Uid) -> anyhow::Result<()> {
+        self.inner.lock().unwrap().actor_mut(uid)?.randomize_params();
+        Ok(())
+    }
+
+    /// Where the entity's parameter can land when randomized. Both ends are
+    /// normalized.
+    pub fn set_random_range(
+        &self,
+        uid: Uid,
+        index: ControlIndex,
+        range: (f64, f64),
+    ) -> anyhow::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.actor_mut(uid)?.compare_mut().set_range(index, range);
+        Ok(())
+    }
+
+    /// Switches the entity between its A and B parameter snapshots.
+    pub fn toggle_compare(&self, uid: Uid) -> anyhow::Result<()> {
+        self.inner.lock().unwrap().actor_mut(uid)?.toggle_compare();
+        Ok(())
+    }
+
+    /// How the link from `source_uid` to the parameter of `target_uid`
+    /// reshapes its signal.
+    pub fn control_transfer(
+        &self,
+        source_uid: Uid,
+        target_uid: Uid,
+        index: ControlIndex,
+    ) -> TransferFunction {
+        let inner = self.inner.lock().unwrap();
+        inner.control_transfer(source_uid, target_uid, index)
+    }
+
+    /// Reshapes the signal of the link from `source_uid` to the parameter of
+    /// `target_uid`, and returns the old shape. It takes effect whenever the
+    /// link exists, including if it's made later.
+    pub fn set_control_transfer(
+        &self,
+        source_uid: Uid,
+        target_uid: Uid,
+        index: ControlIndex,
+        transfer: TransferFunction,
+    ) -> TransferFunction {
+        let mut inner = self.inner.lock().unwrap();
+        inner.set_control_transfer(source_uid, target_uid, index, transfer)
+    }
+
+    /// Moves the entity to a new position in the serial effects chain, and
+    /// returns its old position.
+    pub fn move_entity(&self, uid: Uid, index: usize) -> anyhow::Result<usize> {
+        self.inner
+            .lock()
+            .unwrap()
+            .move_entity(uid, index)
+            .ok_or_else(|| anyhow!("Couldn't find entity {uid}"))
+    }
+
+    /// Puts the effect in a parallel group (None for serial), and returns
+    /// its old group.
+    pub fn set_effect_group(&self, uid: Uid, group: Option<usize>) -> Option<usize> {
+        self.inner.lock().unwrap().set_effect_group(uid, group)
+    }
+
+    /// Sets how much of the effect's output replaces its input, and returns
+    /// the old mix.
+    pub fn set_effect_mix(&self, uid: Uid, mix: Normal) -> Normal {
+        self.inner.lock().unwrap().set_effect_mix(uid, mix)
+    }
+
+    /// Adds the entity to the track's MIDI-effect chain (true) or takes it
+    /// out (false), and returns whether it was in it.
+    pub fn set_midi_effect(&self, uid: Uid, is_midi_effect: bool) -> bool {
+        self.inner
+            .lock()
+            .unwrap()
+            .set_midi_effect(uid, is_midi_effect)
+    }
+
+    /// Arms the track for MIDI recording (true) or disarms it (false), and
+    /// returns whether it was armed.
+    pub fn set_armed(&self, is_armed: bool) -> bool {
+        std::mem::replace(&mut self.inner.lock().unwrap().is_armed, is_armed)
+    }
+
+    /// See [TrackRequest::SetBatchGenerators]. Returns the old setting.
+    pub fn set_batching_generators(&self, is_batching: bool) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        std::mem::replace(&mut inner.is_batching_generators, is_batching)
+    }
+
+    /// The entity whose MIDI most recently came back around to it, and how
+    /// many looping messages the track has dropped.
+    pub fn midi_loop(&self) -> Option<(Uid, usize)> {
+        self.inner.lock().unwrap().midi_loop
+    }
+
+    /// How many frames late the track's output is, because of the entities
+    /// on it. This doesn't count [TrackRequest::SetLatencyCompensation].
+    pub fn latency(&self) -> usize {
+        self.inner.lock().unwrap().latency()
+    }
+
+    /// How many frames the track delays its output to line up with slower
+    /// tracks.
+    pub fn latency_compensation(&self) -> usize {
+        self.inner.lock().unwrap().latency_compensation.delay()
+    }
+
+    /// Saves and loads presets for one of this track's entities. Entities
+    /// that didn't come from the registry don't have presets.
+    pub fn entity_presets(&self, uid: Uid) -> Option<EntityPresets> {
+        let inner = self.inner.lock().unwrap();
+        inner.actors.get(&uid)?.presets().cloned()
+    }
+
+    /// Where the track's clips are.
+    pub fn clip_spans(&self) -> Vec<ClipSpan> {
+        self.inner.lock().unwrap().clip_spans()
+    }
+
+    /// Where the track's state machine is, for diagnosing stalls. Doesn't
+    /// wait if the track is busy.
+    pub fn describe_state(&self) -> String {
+        let Ok(track) = self.inner.try_lock() else {
+            return "busy".to_string();
+        };
+        match &track.state {
+            TrackState::Idle => "idle".to_string(),
+            TrackState::AwaitingSources(count) => format!("waiting for {count} sources"),
+            TrackState::AwaitingEffect {
+                remaining_stages,
+                outstanding,
+                ..
+            } => format!(
+                "waiting for {outstanding} effects, with {} stages to go",
+                remaining_stages.len()
+            ),
+        }
+    }
+
+    /// Whether the track is between generation cycles, rather than waiting
+    /// for its sources or effects.
+    pub fn is_idle(&self) -> bool {
+        matches!(self.inner.lock().unwrap().state, TrackState::Idle)
+    }
+
+    /// Whether the track plays back the render from its last freeze.
+    pub fn is_frozen(&self) -> bool {
+        self.inner.lock().unwrap().frozen_clip.is_some()
+    }
+
+    /// How many requests and actions are waiting for the track and its
+    /// entities.
+    pub fn queue_depth(&self) -> usize {
+        let inner = self.inner.lock().unwrap();
+        let entities: usize = inner.actors.values().map(|a| a.queue_depth()).sum();
+        self.requests.receiver.len()
+            + self.audio_actions.receiver.len()
+            + self.midi_actions.receiver.len()
+            + self.track_actions.receiver.len()
+            + entities
+    }
+
     pub(crate) fn audio_sender(&self) -> &Sender<AudioAction> {
         &self.audio_actions.sender
     }
@@ -222,67 +822,662 @@ impl TrackActor {
     pub(crate) fn midi_sender(&self) -> &Sender<MidiAction> {
         &self.midi_actions.sender
     }
-}
-
-#[derive(Debug)]
-struct ControllableItem {
-    name: String,
-    uid: Uid,
-    param: ControlIndex,
-}
-
-#[derive(Debug)]
-struct ActionSubscriptionSenders {
-    audio: Sender<AudioAction>,
-    midi: Sender<MidiAction>,
-    control: Sender<ControlAction>,
-}
 
-#[derive(Default, Debug)]
-enum TrackState {
-    #[default]
-    Idle,
-    AwaitingSources(usize),
-    AwaitingEffect(VecDeque<Uid>),
+    pub(crate) fn track_action_sender(&self) -> &Sender<TrackAction> {
+        &self.track_actions.sender
+    }
 }
 
-#[derive(Debug)]
-struct Track {
+/// The state that a [TrackActor]'s message loop owns.
+struct TrackLoop {
     uid: TrackUid,
     is_master_track: bool,
-    uid_factory: Arc<EntityUidFactory>,
-    ordered_actor_uids: Vec<Uid>,
-    actors: HashMap<Uid, EntityActor>,
-    send_tracks: HashMap<TrackUid, Sender<TrackRequest>>,
-
-    entity_request_subscription: Subscription<EntityRequest>,
+    track: Arc<Mutex<Track>>,
+    requests: Receiver<TrackRequest>,
+    audio_actions: Receiver<AudioAction>,
+    midi_actions: Receiver<MidiAction>,
+    control_actions: Receiver<ControlAction>,
+    track_actions: Receiver<TrackAction>,
+}
+impl ActorLoop for TrackLoop {
+    fn run(mut self) {
+        let input_receiver = self.requests.clone();
+        let audio_receiver = self.audio_actions.clone();
+        let midi_receiver = self.midi_actions.clone();
+        let control_receiver = self.control_actions.clone();
+        let track_action_receiver = self.track_actions.clone();
 
-    controllables: Vec<ControllableItem>,
-    control_links: HashMap<Uid, Vec<ControlLink>>,
+        let mut sel = Select::default();
 
-    mixer: Option<Mixer>,
+        let input_index = sel.recv(&input_receiver);
+        let audio_index = sel.recv(&audio_receiver);
+        let midi_index = sel.recv(&midi_receiver);
+        let control_index = sel.recv(&control_receiver);
+        let track_action_index = sel.recv(&track_action_receiver);
 
-    actor_subscription_senders: ActionSubscriptionSenders,
+        loop {
+            let operation = sel.select();
+            match operation.index() {
+                index if index == input_index => {
+                    if let Ok(request) = TrackActor::recv_operation(operation, &input_receiver) {
+                        if self.handle_request(request) == ActorStep::Quit {
+                            break;
+                        }
+                    }
+                }
+                index if index == audio_index => {
+                    if let Ok(action) = TrackActor::recv_operation(operation, &audio_receiver) {
+                        self.handle_audio_action(action);
+                    }
+                }
+                index if index == midi_index => {
+                    if let Ok(action) = TrackActor::recv_operation(operation, &midi_receiver) {
+                        self.handle_midi_action(action)
+                    }
+                }
+                index if index == control_index => {
+                    if let Ok(action) = TrackActor::recv_operation(operation, &control_receiver) {
+                        self.handle_control_action(action);
+                    }
+                }
+                index if index == track_action_index => {
+                    if let Ok(action) =
+                        TrackActor::recv_operation(operation, &track_action_receiver)
+                    {
+                        self.handle_track_action(action);
+                    }
+                }
+                index => error!("Track {}: unexpected select index {index}", self.uid),
+            }
+        }
+    }
 
-    state: TrackState,
-    buffer: GenerationBuffer<StereoSample>,
-    audio_subscription: Subscription<AudioAction>,
-    midi_subscription: Subscription<MidiAction>,
+    fn step(&mut self) -> ActorStep {
+        if let Ok(request) = self.requests.try_recv() {
+            return self.handle_request(request);
+        }
+        if let Ok(action) = self.audio_actions.try_recv() {
+            self.handle_audio_action(action);
+        } else if let Ok(action) = self.midi_actions.try_recv() {
+            self.handle_midi_action(action);
+        } else if let Ok(action) = self.control_actions.try_recv() {
+            self.handle_control_action(action);
+        } else if let Ok(action) = self.track_actions.try_recv() {
+            self.handle_track_action(action);
+        } else {
+            return ActorStep::Idle;
+        }
+        ActorStep::Busy
+    }
 }
-impl Track {
-    fn new_with(
-        uid: TrackUid,
+impl TrackLoop {
+    fn handle_request(&mut self, request: TrackRequest) -> ActorStep {
+        trace_message(ActorId::Unknown, ActorId::Track(self.uid), request.name());
+        let track = &self.track;
+        match request {
+            TrackRequest::Midi(channel, message) => {
+                track.lock().unwrap().handle_midi(channel, message);
+            }
+            TrackRequest::SetMidiChannelFilter(channel) => {
+                track.lock().unwrap().midi_channel_filter = channel;
+            }
+            TrackRequest::SetMidiInput(midi_input) => {
+                track.lock().unwrap().set_midi_input(midi_input);
+            }
+            TrackRequest::SetLatencyCompensation(delay) => {
+                track.lock().unwrap().latency_compensation.set_delay(delay);
+            }
+            TrackRequest::SetSendLatencyCompensation(uid, delay) => {
+                if let Some(destination) = track.lock().unwrap().send_destinations.get_mut(&uid) {
+                    destination.delay.set_delay(delay);
+                }
+            }
+            TrackRequest::SetBatchGenerators(is_batching) => {
+                track.lock().unwrap().is_batching_generators = is_batching;
+            }
+            TrackRequest::MidiLearn(uid, param) => {
+                track.lock().unwrap().midi_learn_target = Some((uid, param));
+            }
+            TrackRequest::NeedsAudio(count) => {
+                let mut track = track.lock().unwrap();
+                time_work(ActorId::Track(self.uid), || track.handle_needs_audio(count));
+            }
+            TrackRequest::Quit => {
+                if let Ok(mut track) = track.lock() {
+                    track
+                        .entity_request_subscription
+                        .broadcast_mut(EntityRequest::Quit);
+                }
+                debug!("Track {} quit", self.uid);
+                return ActorStep::Quit;
+            }
+            TrackRequest::Work(time_range) => {
+                if let Ok(mut track) = track.lock() {
+                    time_work(ActorId::Track(self.uid), || track.handle_work(time_range));
+                }
+            }
+            TrackRequest::Seek(time) => {
+                track.lock().unwrap().time_range = TimeRange(time..time);
+            }
+            TrackRequest::AddSend(uid, sender) => {
+                if let Ok(mut track) = track.lock() {
+                    track.send_tracks.insert(uid, sender);
+                    if let Some(mixer) = track.mixer.as_mut() {
+                        mixer.add_track(uid);
+                    }
+                }
+            }
+            TrackRequest::RemoveSend(uid) => {
+                if let Ok(mut track) = track.lock() {
+                    track.send_tracks.remove(&uid);
+                    if let Some(mixer) = track.mixer.as_mut() {
+                        mixer.remove_track(uid);
+                    }
+                }
+            }
+            TrackRequest::SubscribeSend(uid, sender, level) => {
+                track
+                    .lock()
+                    .unwrap()
+                    .send_destinations
+                    .insert(uid, SendDestination::new_with(sender, level));
+            }
+            TrackRequest::UnsubscribeSend(uid) => {
+                track.lock().unwrap().send_destinations.remove(&uid);
+            }
+            TrackRequest::SetSendLevel(uid, level) => {
+                if let Some(destination) = track.lock().unwrap().send_destinations.get_mut(&uid) {
+                    destination.level = level;
+                }
+            }
+            TrackRequest::CopyMixerSettings(from, to) => {
+                if let Some(mixer) = track.lock().unwrap().mixer.as_mut() {
+                    mixer.copy_track_settings(from, to);
+                }
+            }
+            TrackRequest::MoveEntity(uid, index) => {
+                track.lock().unwrap().move_entity(uid, index);
+            }
+            TrackRequest::SetEffectGroup(uid, group) => {
+                track.lock().unwrap().set_effect_group(uid, group);
+            }
+            TrackRequest::SetEffectMix(uid, mix) => {
+                track.lock().unwrap().set_effect_mix(uid, mix);
+            }
+            TrackRequest::SetMidiEffect(uid, is_midi_effect) => {
+                track.lock().unwrap().set_midi_effect(uid, is_midi_effect);
+            }
+            TrackRequest::Arm(is_armed) => {
+                track.lock().unwrap().is_armed = is_armed;
+            }
+            TrackRequest::StartRecording => {
+                track.lock().unwrap().start_recording();
+            }
+            TrackRequest::StopRecording => {
+                track.lock().unwrap().is_recording = false;
+            }
+            TrackRequest::SetRecordMode(record_mode) => {
+                track.lock().unwrap().record_mode = record_mode;
+            }
+            TrackRequest::SetGroove(groove) => {
+                track.lock().unwrap().groove = groove;
+            }
+            TrackRequest::SetMidiClip(midi_clip) => {
+                track.lock().unwrap().set_midi_clip(midi_clip);
+            }
+            TrackRequest::SetClipEdits(clip_edits) => {
+                track.lock().unwrap().set_clip_edits(clip_edits);
+            }
+            TrackRequest::AddAudioClip(audio_clip) => {
+                track.lock().unwrap().audio_clips.push(audio_clip);
+            }
+            TrackRequest::Configure(sample_rate, tempo, block_size) => {
+                if let Ok(mut track) = track.lock() {
+                    track.sample_rate = sample_rate;
+                    track.set_tempo(tempo);
+                    track.block_size = block_size;
+                }
+            }
+            TrackRequest::Freeze(end) => {
+                track.lock().unwrap().freeze(end);
+            }
+            TrackRequest::SetIsTempoMapped(is_tempo_mapped) => {
+                track.lock().unwrap().is_tempo_mapped = is_tempo_mapped;
+            }
+            TrackRequest::Unfreeze => {
+                track.lock().unwrap().frozen_clip = None;
+            }
+            TrackRequest::SetName(name) => {
+                track.lock().unwrap().set_name(name);
+            }
+            TrackRequest::SetColor(color) => {
+                track.lock().unwrap().set_color(color);
+            }
+            TrackRequest::AudioInput(frames) => {
+                track.lock().unwrap().handle_audio_input(&frames);
+            }
+            TrackRequest::WriteRecording(path, sample_rate) => {
+                track.lock().unwrap().write_recording(path, sample_rate);
+            }
+            TrackRequest::SubscribeAudio(sender) => {
+                track.lock().unwrap().audio_subscription.subscribe(&sender);
+            }
+            TrackRequest::UnsubscribeAudio(sender) => {
+                track
+                    .lock()
+                    .unwrap()
+                    .audio_subscription
+                    .unsubscribe(&sender);
+            }
+            TrackRequest::SubscribeMidi(sender) => {
+                track.lock().unwrap().midi_subscription.subscribe(&sender)
+            }
+            TrackRequest::UnsubscribeMidi(sender) => {
+                track.lock().unwrap().unsubscribe_midi(&sender);
+            }
+            TrackRequest::ReleaseNotes => {
+                track.lock().unwrap().release_notes();
+            }
+            TrackRequest::SubscribeTrackActions(sender) => {
+                if let Ok(mut track) = track.lock() {
+                    let info = TrackAction::Info(track.uid, track.info.clone());
+                    let _ = sender.try_send(info);
+                    track.track_action_subscription.subscribe(&sender);
+                }
+            }
+            TrackRequest::UnsubscribeTrackActions(sender) => {
+                track
+                    .lock()
+                    .unwrap()
+                    .track_action_subscription
+                    .unsubscribe(&sender);
+            }
+            TrackRequest::SubscribeFrames(sender) => {
+                track.lock().unwrap().frames_subscription.subscribe(&sender);
+            }
+            TrackRequest::UnsubscribeFrames(sender) => {
+                track
+                    .lock()
+                    .unwrap()
+                    .frames_subscription
+                    .unsubscribe(&sender);
+            }
+        }
+        ActorStep::Busy
+    }
+
+    /// Tracks hear from their entities, except for the master track, which
+    /// hears from other tracks.
+    fn handle_audio_action(&mut self, action: AudioAction) {
+        let source = if self.is_master_track {
+            ActorId::Track(TrackUid(action.source_uid.0))
+        } else {
+            ActorId::Entity(action.source_uid)
+        };
+        trace_message(source, ActorId::Track(self.uid), "Audio");
+        let mut track = self.track.lock().unwrap();
+        time_work(ActorId::Track(self.uid), || track.handle_audio_action(action));
+    }
+
+    fn handle_midi_action(&mut self, action: MidiAction) {
+        let source = ActorId::Entity(action.source_uid);
+        trace_message(source, ActorId::Track(self.uid), "Midi");
+        self.track.lock().unwrap().handle_midi_action(action);
+    }
+
+    fn handle_control_action(&mut self, action: ControlAction) {
+        let source = ActorId::Entity(action.source_uid);
+        trace_message(source, ActorId::Track(self.uid), "Control");
+        self.track.lock().unwrap().handle_control_action(action);
+    }
+
+    fn handle_track_action(&mut self, action: TrackAction) {
+        let source = match &action {
+            TrackAction::Meter(uid, _)
+            | TrackAction::Frames(uid, _)
+            | TrackAction::Info(uid, _)
+            | TrackAction::Latency(uid, _)
+            | TrackAction::Fault(uid, _) => ActorId::Track(*uid),
+        };
+        trace_message(source, ActorId::Track(self.uid), action.name());
+        let mut track = self.track.lock().unwrap();
+        time_work(ActorId::Track(self.uid), || track.handle_track_action(action));
+    }
+}
+
+#[derive(Debug)]
+struct ControllableItem {
+    name: String,
+    uid: Uid,
+    param: ControlIndex,
+}
+
+#[derive(Debug)]
+struct ActionSubscriptionSenders {
+    audio: Sender<AudioAction>,
+    midi: Sender<MidiAction>,
+    control: Sender<ControlAction>,
+}
+
+/// Routes a hardware controller to an entity parameter. Created by MIDI
+/// learn.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MidiMapping {
+    pub channel: u8,
+    pub controller: u8,
+    pub uid: Uid,
+    pub param: ControlIndex,
+}
+
+/// An entity that was removed from its track but kept alive, along with
+/// what it takes to put it back. Dropping it ends the entity's thread.
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct DetachedEntity {
+    actor: Option<EntityActor>,
+    #[derivative(Debug = "ignore")]
+    duplicate_fn: Option<EntityDuplicateFn>,
+    /// Where it was in the track's processing order.
+    position: usize,
+    effect_group: Option<usize>,
+    effect_mix: Option<Normal>,
+    is_midi_effect: bool,
+    /// The parameters it was driving.
+    links_from: Vec<ControlLink>,
+    /// The entities that were driving its parameters, and which ones.
+    links_to: Vec<(Uid, ControlIndex)>,
+    midi_mappings: Vec<MidiMapping>,
+}
+
+/// One entity in an [EntityClipboard].
+#[derive(Derivative)]
+#[derivative(Debug)]
+struct CopiedEntity {
+    /// Its uid on the track it was copied from, which the clipboard's links
+    /// refer to.
+    uid: Uid,
+    /// Copies the snapshot that was taken when the entity was copied.
+    #[derivative(Debug = "ignore")]
+    duplicate_fn: EntityDuplicateFn,
+    settings: EntitySettings,
+    effect_group: Option<usize>,
+    effect_mix: Option<Normal>,
+    is_midi_effect: bool,
+}
+
+/// Entities copied from a track, with their parameter values and the control
+/// links among them, ready to paste into any track any number of times.
+#[derive(Debug, Default)]
+pub struct EntityClipboard {
+    /// In the order that the source track processed them.
+    entities: Vec<CopiedEntity>,
+    control_links: Vec<(Uid, ControlLink)>,
+    control_transfers: Vec<(Uid, ControlLink, TransferFunction)>,
+}
+impl EntityClipboard {
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+}
+
+/// What the user calls a track, and how it's shown.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrackInfo {
+    pub name: String,
+    /// sRGB.
+    pub color: [u8; 3],
+}
+#[cfg(feature = "gui")]
+impl TrackInfo {
+    pub fn color32(&self) -> Color32 {
+        Color32::from_rgb(self.color[0], self.color[1], self.color[2])
+    }
+}
+
+/// How a new MIDI recording treats the track's existing clip.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RecordMode {
+    /// Discard the existing clip when recording starts.
+    #[default]
+    Replace,
+    /// Add newly recorded events to the existing clip.
+    Overdub,
+}
+
+/// Another track that gets a scaled copy of this track's output.
+#[derive(Debug)]
+struct SendDestination {
+    sender: Sender<AudioAction>,
+    level: Normal,
+    /// Holds the send back until the bus's slower inputs catch up.
+    delay: DelayLine,
+}
+impl SendDestination {
+    fn new_with(sender: Sender<AudioAction>, level: Normal) -> Self {
+        Self {
+            sender,
+            level,
+            delay: Default::default(),
+        }
+    }
+}
+
+/// An offline render in progress. See [TrackRequest::Freeze].
+#[derive(Debug)]
+struct FreezeProgress {
+    /// The track's output so far.
+    frames: Vec<StereoSample>,
+    /// Where to stop rendering.
+    end: MusicalTime,
+    /// Whether the buffer in flight belongs to the render, rather than to a
+    /// request that was already underway when the freeze began.
+    is_started: bool,
+    /// Set while [Track::render_next_freeze_block] is looping, so that a
+    /// block that finishes right away doesn't recurse back into it.
+    is_driving: bool,
+    /// Whether the block in flight has finished.
+    is_block_done: bool,
+}
+
+#[derive(Default, Debug)]
+enum TrackState {
+    #[default]
+    Idle,
+    AwaitingSources(usize),
+    /// Waiting for the current stage of the effects chain. A stage is either
+    /// a single serial effect or a parallel group.
+    AwaitingEffect {
+        /// Stages that haven't started yet.
+        remaining_stages: VecDeque<Vec<Uid>>,
+        /// How many effects in the current stage haven't reported back.
+        outstanding: usize,
+        /// How many effects are in the current stage.
+        stage_size: usize,
+    },
+}
+
+#[derive(Debug)]
+struct Track {
+    uid: TrackUid,
+    is_master_track: bool,
+    info: TrackInfo,
+    uid_factory: Arc<EntityUidFactory>,
+    registry: Arc<EntityRegistry>,
+    ordered_actor_uids: Vec<Uid>,
+    actors: HashMap<Uid, EntityActor>,
+    /// The entities picked for bulk operations.
+    selected_uids: HashSet<Uid>,
+    /// Copies entities that came from the registry. Entities added any other
+    /// way (e.g., plugins) don't have one, and can't be duplicated.
+    duplicate_fns: HashMap<Uid, EntityDuplicateFn>,
+    send_tracks: HashMap<TrackUid, Sender<TrackRequest>>,
+    /// Tracks that get a copy of our output in addition to our main output.
+    send_destinations: HashMap<TrackUid, SendDestination>,
+    /// Whether we've produced our output since the last [TrackRequest::Work].
+    /// A track with sends is asked for audio by each of its destinations,
+    /// but it renders only once and delivers to all of them at the same time.
+    has_rendered: bool,
+    /// Audio that our sources delivered before we asked for it, because
+    /// another of their destinations asked first.
+    early_audio_actions: Vec<AudioAction>,
+    /// Whether generators share one [AudioBatch] per cycle.
+    is_batching_generators: bool,
+    /// Reused from cycle to cycle while the number of generators stays the
+    /// same.
+    generator_batch: Option<Arc<AudioBatch>>,
+
+    entity_request_subscription: Subscription<EntityRequest>,
+
+    controllables: Vec<ControllableItem>,
+    control_links: HashMap<Uid, Vec<ControlLink>>,
+    /// How each link from the given source reshapes its signal, for links
+    /// that do. Kept after unlinking, so that undo brings the curve back
+    /// along with the link.
+    control_transfers: Vec<(Uid, ControlLink, TransferFunction)>,
+    /// The tracks whose mixer levels each entity drives, from anywhere in the
+    /// project. Only the master track has any.
+    mixer_level_links: HashMap<Uid, Vec<TrackUid>>,
+    /// The entities whose control signals drive the crossfader.
+    crossfader_links: HashSet<Uid>,
+    /// What the "Controls" picker offers, as collected by the
+    /// [Engine](crate::engine::Engine) across every track.
+    #[cfg(feature = "gui")]
+    control_targets: Arc<Vec<(ControlTarget, String)>>,
+
+    mixer: Option<Mixer>,
+
+    actor_subscription_senders: ActionSubscriptionSenders,
+    /// Where the UI sends changes that can be undone.
+    commands: Sender<Command>,
+    /// Where our entities run.
+    executor: Executor,
+
+    state: TrackState,
+    buffer: GenerationBuffer<StereoSample>,
+    /// Accumulates the outputs of the effects in the current stage.
+    stage_buffer: Vec<StereoSample>,
+    /// The master track's output pairs after the main one, which is
+    /// [Track::buffer]. Empty for stereo, and for other tracks.
+    other_pairs: Vec<Vec<StereoSample>>,
+    /// Effects that belong to a parallel group, keyed by entity.
+    effect_groups: HashMap<Uid, usize>,
+    /// Wet/dry mix per effect. Effects without an entry are fully wet.
+    effect_mixes: HashMap<Uid, Normal>,
+    /// The entities in the MIDI chain. The chain's order is the processing
+    /// order.
+    midi_effects: HashSet<Uid>,
+    /// The entity whose MIDI most recently went around in a loop, and how
+    /// many looping messages have been dropped since the warning was last
+    /// dismissed.
+    midi_loop: Option<(Uid, usize)>,
+    audio_subscription: Subscription<AudioAction>,
+    midi_subscription: Subscription<MidiAction>,
+    /// The notes that our entities are hearing, so that none are left
+    /// hanging when an entity or a subscriber goes away.
+    active_notes: ActiveNotes,
+    track_action_subscription: Subscription<TrackAction>,
+    /// Kept apart from [Track::track_action_subscription] so that only
+    /// those who want every buffer (e.g., stem writers) get them.
+    frames_subscription: Subscription<TrackAction>,
+
+    /// Which channel incoming MIDI has to be on to reach our entities. None
+    /// means any channel.
+    midi_channel_filter: Option<MidiChannel>,
+    /// What happens to incoming notes that pass the filter.
+    midi_input: MidiInputProcessor,
+    /// Incoming CC messages that adjust entity parameters.
+    midi_mappings: Vec<MidiMapping>,
+    /// The parameter that the next incoming CC message gets mapped to.
+    midi_learn_target: Option<(Uid, ControlIndex)>,
+
+    /// Whether incoming audio should be captured when recording starts.
+    is_armed: bool,
+    /// Whether we're currently capturing incoming audio.
+    is_recording: bool,
+    /// The audio captured during the most recent recording.
+    recorded_frames: Vec<StereoSample>,
+    /// Whether MIDI recording replaces or overdubs [Track::midi_clip].
+    record_mode: RecordMode,
+    /// Bends the time that the track's controllers, such as sequencers and
+    /// arpeggiators, work on.
+    groove: Groove,
+    /// Recorded MIDI, replayed to this track's entities during playback.
+    midi_clip: MidiClip,
+    /// Edits that playback applies without changing [Track::midi_clip].
+    clip_edits: ClipEdits,
+    /// [Track::midi_clip] with [Track::clip_edits] applied, if there are
+    /// any.
+    playback_clip: Option<MidiClip>,
+    /// The settings that the "Clip…" menu's buttons use.
+    #[cfg(feature = "gui")]
+    quantize: Quantize,
+    #[cfg(feature = "gui")]
+    humanize: Humanize,
+    /// The time slice of the most recent [TrackRequest::Work].
+    time_range: TimeRange,
+    /// Audio regions streamed into the track's buffer during playback.
+    audio_clips: Vec<AudioClip>,
+
+    /// Set while the track is rendering itself for a freeze.
+    freeze_progress: Option<FreezeProgress>,
+    /// The rendered output of a frozen track. While this is set, the
+    /// track's entities, sends, and clips sit idle.
+    frozen_clip: Option<AudioClip>,
+    /// Whether the engine follows a tempo map, which a freeze can't.
+    is_tempo_mapped: bool,
+
+    sample_rate: SampleRate,
+    tempo: Tempo,
+    /// The engine's block size, which freezing also renders in.
+    block_size: usize,
+
+    /// The latency that we last reported with [TrackAction::Latency].
+    reported_latency: usize,
+    /// Holds our output back until slower tracks' output catches up.
+    latency_compensation: DelayLine,
+}
+impl Track {
+    /// How far the Freeze button renders past the end of the track's clips,
+    /// so that releases and effect tails make it into the frozen audio.
+    #[cfg(feature = "gui")]
+    const FREEZE_TAIL_BEATS: usize = 4;
+
+    fn new_with(
+        uid: TrackUid,
         is_master_track: bool,
         actor_subscription_senders: ActionSubscriptionSenders,
         uid_factory: &Arc<EntityUidFactory>,
+        registry: &Arc<EntityRegistry>,
+        commands: &Sender<Command>,
+        executor: &Executor,
     ) -> Self {
         Self {
             uid,
             is_master_track,
+            info: TrackInfo {
+                name: if is_master_track {
+                    "Master Track".to_string()
+                } else {
+                    format!("Track {uid}")
+                },
+                color: [160, 160, 160],
+            },
             uid_factory: Arc::clone(uid_factory),
+            registry: Arc::clone(registry),
             ordered_actor_uids: Default::default(),
             actors: Default::default(),
+            selected_uids: Default::default(),
+            duplicate_fns: Default::default(),
             send_tracks: Default::default(),
+            send_destinations: Default::default(),
+            has_rendered: Default::default(),
+            early_audio_actions: Default::default(),
+            is_batching_generators: Default::default(),
+            generator_batch: Default::default(),
             entity_request_subscription: Default::default(),
             controllables: vec![ControllableItem {
                 name: "None".to_string(),
@@ -290,73 +1485,1045 @@ impl Track {
                 param: ControlIndex(0),
             }],
             control_links: Default::default(),
+            control_transfers: Default::default(),
+            mixer_level_links: Default::default(),
+            crossfader_links: Default::default(),
+            #[cfg(feature = "gui")]
+            control_targets: Default::default(),
             mixer: if is_master_track {
-                Some(Mixer::default())
+                Some(Mixer::new_with(commands.clone()))
             } else {
                 None
             },
             actor_subscription_senders,
+            commands: commands.clone(),
+            executor: executor.clone(),
+
+            state: Default::default(),
+            buffer: Default::default(),
+            stage_buffer: Default::default(),
+            other_pairs: Default::default(),
+            effect_groups: Default::default(),
+            midi_effects: Default::default(),
+            midi_loop: Default::default(),
+            effect_mixes: Default::default(),
+            audio_subscription: Default::default(),
+            midi_subscription: Default::default(),
+            active_notes: Default::default(),
+            track_action_subscription: Default::default(),
+            frames_subscription: Default::default(),
+
+            midi_channel_filter: Default::default(),
+            midi_input: Default::default(),
+            midi_mappings: Default::default(),
+            midi_learn_target: Default::default(),
+            is_armed: Default::default(),
+            is_recording: Default::default(),
+            recorded_frames: Default::default(),
+            record_mode: Default::default(),
+            groove: Default::default(),
+            midi_clip: Default::default(),
+            clip_edits: Default::default(),
+            playback_clip: Default::default(),
+            #[cfg(feature = "gui")]
+            quantize: Default::default(),
+            #[cfg(feature = "gui")]
+            humanize: Default::default(),
+            time_range: Default::default(),
+            audio_clips: Default::default(),
+            freeze_progress: Default::default(),
+            frozen_clip: Default::default(),
+            is_tempo_mapped: Default::default(),
+            sample_rate: Default::default(),
+            tempo: Default::default(),
+            block_size: Engine::DEFAULT_BLOCK_SIZE,
+            reported_latency: Default::default(),
+            latency_compensation: Default::default(),
+        }
+    }
+
+    /// Passes a tempo change on to the entities, so that tempo-synced ones
+    /// keep up.
+    fn set_tempo(&mut self, tempo: Tempo) {
+        if tempo.0 != self.tempo.0 {
+            self.tempo = tempo;
+            self.entity_request_subscription
+                .broadcast_mut(EntityRequest::UpdateTempo(tempo));
+        }
+    }
+
+    fn set_name(&mut self, name: String) {
+        self.info.name = name;
+        self.broadcast_info();
+    }
+
+    fn set_color(&mut self, color: [u8; 3]) {
+        self.info.color = color;
+        self.broadcast_info();
+    }
+
+    fn broadcast_info(&mut self) {
+        self.track_action_subscription
+            .broadcast_mut(TrackAction::Info(self.uid, self.info.clone()));
+    }
+
+    fn start_recording(&mut self) {
+        if self.is_armed {
+            self.recorded_frames.clear();
+            if self.record_mode == RecordMode::Replace {
+                self.midi_clip.clear();
+                self.update_playback_clip();
+            }
+            self.is_recording = true;
+        }
+    }
+
+    fn handle_work(&mut self, time_range: TimeRange) {
+        self.has_rendered = false;
+
+        // A freeze drives the entities on its own timeline, and a frozen
+        // track doesn't need them at all.
+        if self.freeze_progress.is_some() {
+            return;
+        }
+        if self.frozen_clip.is_some() {
+            self.time_range = time_range;
+            return;
+        }
+        self.work(time_range);
+    }
+
+    fn work(&mut self, time_range: TimeRange) {
+        // Only controllers get the grooved time. Instruments and effects that
+        // follow the song position stay on the grid.
+        let is_grooved = !self.groove.is_straight();
+        let grooved = is_grooved.then(|| self.groove.straight_range(&time_range));
+        for uid in self.ordered_actor_uids.iter() {
+            let Some(actor) = self.actors.get_mut(uid) else {
+                continue;
+            };
+            let roles = actor.roles();
+            let is_controller = !roles.generates_audio && !roles.transforms_audio;
+            let time_range = match grooved.as_ref() {
+                Some(grooved) if is_controller => grooved,
+                _ => &time_range,
+            };
+            if let Some(request) = actor.schedule_work(time_range) {
+                actor.send(request);
+            }
+        }
+
+        // Play back anything we recorded earlier. Events recorded during this
+        // pass land behind the current time, so they'll be heard on the next
+        // pass rather than doubled immediately.
+        let clip = self.playback_clip.as_ref().unwrap_or(&self.midi_clip);
+        let chain = (!self.midi_effects.is_empty()).then(|| self.midi_destinations(None));
+        for event in clip.events_in(&time_range) {
+            self.active_notes.note(None, event.channel, &event.message);
+            let request = EntityRequest::Midi(event.channel, event.message);
+            match chain.as_ref() {
+                Some(chain) => self.send_midi(chain, request),
+                None => self.entity_request_subscription.broadcast_mut(request),
+            }
+        }
+        self.time_range = time_range;
+    }
+
+    fn handle_midi(&mut self, channel: MidiChannel, message: MidiMessage) {
+        // Mappings carry their own channel, so they come before the filter.
+        if let MidiMessage::Controller { controller, value } = message {
+            self.handle_controller(channel, controller.as_int(), value.as_int());
+        }
+        if self.midi_channel_filter.is_some_and(|c| c != channel) {
+            return;
+        }
+        let Some(message) = self.midi_input.process(message) else {
+            return;
+        };
+        self.record_midi(channel, message);
+        self.active_notes.note(None, channel, &message);
+        self.send_input_midi(EntityRequest::Midi(channel, message));
+    }
+
+    /// Sends MIDI from the track's input or clip to the entities. With a
+    /// MIDI-effect chain, it enters the chain rather than reaching everyone.
+    fn send_input_midi(&mut self, request: EntityRequest) {
+        if self.midi_effects.is_empty() {
+            self.entity_request_subscription.broadcast_mut(request);
+        } else {
+            self.send_midi(&self.midi_destinations(None), request);
+        }
+    }
+
+    fn handle_controller(&mut self, channel: MidiChannel, controller: u8, value: u8) {
+        if let Some((uid, param)) = self.midi_learn_target.take() {
+            self.midi_mappings
+                .retain(|m| !(m.uid == uid && m.param == param));
+            self.midi_mappings.push(MidiMapping {
+                channel: channel.0,
+                controller,
+                uid,
+                param,
+            });
+        }
+        let value = ControlValue(value as f64 / 127.0);
+        for mapping in self
+            .midi_mappings
+            .iter()
+            .filter(|m| m.channel == channel.0 && m.controller == controller)
+        {
+            if let Some(actor) = self.actors.get(&mapping.uid) {
+                actor.send(EntityRequest::Control(mapping.param, value));
+            }
+        }
+    }
+
+    fn record_midi(&mut self, channel: MidiChannel, message: MidiMessage) {
+        // A transport that isn't moving produces empty time ranges, and there's
+        // no meaningful position to stamp the event with.
+        if self.is_recording && !self.time_range.0.is_empty() {
+            self.midi_clip.record(self.time_range.0.start, channel, message);
+            self.update_playback_clip();
+        }
+    }
+
+    /// Replaces the MIDI clip, and returns the old one.
+    fn set_midi_clip(&mut self, midi_clip: MidiClip) -> MidiClip {
+        let old = std::mem::replace(&mut self.midi_clip, midi_clip);
+        self.update_playback_clip();
+        old
+    }
+
+    /// Changes the playback edits, and returns the old ones.
+    fn set_clip_edits(&mut self, clip_edits: ClipEdits) -> ClipEdits {
+        let old = std::mem::replace(&mut self.clip_edits, clip_edits);
+        self.update_playback_clip();
+        old
+    }
+
+    fn update_playback_clip(&mut self) {
+        self.playback_clip =
+            (!self.clip_edits.is_empty()).then(|| self.clip_edits.apply(&self.midi_clip));
+    }
+
+    fn handle_audio_input(&mut self, frames: &[StereoSample]) {
+        if self.is_recording {
+            self.recorded_frames.extend_from_slice(frames);
+        }
+    }
+
+    fn write_recording(&self, path: PathBuf, sample_rate: SampleRate) {
+        if self.recorded_frames.is_empty() {
+            return;
+        }
+
+        // The writer thread finalizes the file when it gets Quit, so we can
+        // hand it the whole take and forget about it.
+        let writer_service = WavWriterService::new();
+        writer_service.send_input(WavWriterInput::Reset(path, sample_rate, 2));
+        writer_service.send_input(WavWriterInput::Frames(
+            self.recorded_frames.clone(),
+            Default::default(),
+        ));
+        writer_service.send_input(WavWriterInput::Quit);
+    }
+
+    fn freeze(&mut self, end: MusicalTime) {
+        if self.freeze_progress.is_some() {
+            return;
+        }
+        if let Err(e) = self.check_can_freeze() {
+            report_error("While freezing", &e);
+            return;
+        }
+        self.frozen_clip = None;
+        self.freeze_progress = Some(FreezeProgress {
+            frames: Default::default(),
+            end,
+            is_started: false,
+            is_driving: false,
+            is_block_done: false,
+        });
+
+        // If a buffer is already in flight, the render starts once it's done.
+        if matches!(self.state, TrackState::Idle) {
+            self.render_next_freeze_block();
+        }
+    }
+
+    /// A bus's sources render only when the bus pulls them, which it doesn't
+    /// do while freezing. And the render runs at the track's current tempo,
+    /// so it would drift from a tempo map.
+    fn check_can_freeze(&self) -> anyhow::Result<()> {
+        if !self.send_tracks.is_empty() {
+            return Err(anyhow!("A track with sources routed to it can't be frozen"));
+        }
+        if self.is_tempo_mapped {
+            return Err(anyhow!("Tracks can't be frozen while a tempo map is set"));
+        }
+        Ok(())
+    }
+
+    /// Renders freeze blocks until one has to wait for the entities, or the
+    /// freeze is done. A track without sources or effects finishes every
+    /// block on the spot, so this loops rather than recursing per block.
+    fn render_next_freeze_block(&mut self) {
+        let Some(progress) = self.freeze_progress.as_mut() else {
+            return;
+        };
+        if progress.is_driving {
+            progress.is_block_done = true;
+            return;
+        }
+        progress.is_driving = true;
+        loop {
+            let Some(progress) = self.freeze_progress.as_mut() else {
+                return;
+            };
+            let start_frame = progress.frames.len();
+            let start = self.frame_to_time(start_frame);
+            if start >= progress.end {
+                let frames = std::mem::take(&mut progress.frames);
+                self.freeze_progress = None;
+                self.frozen_clip = Some(AudioClip::new_with(
+                    MusicalTime::START,
+                    self.sample_rate,
+                    frames,
+                ));
+                return;
+            }
+            progress.is_started = true;
+            progress.is_block_done = false;
+            let end = self.frame_to_time(start_frame + self.block_size);
+            self.work(TimeRange(start..end));
+            self.render(self.block_size);
+            match self.freeze_progress.as_mut() {
+                Some(progress) if progress.is_block_done => continue,
+                Some(progress) => {
+                    // The rest of the freeze resumes when the block's audio
+                    // arrives.
+                    progress.is_driving = false;
+                    return;
+                }
+                None => return,
+            }
+        }
+    }
+
+    /// The song position of the given frame, counting from the start.
+    fn frame_to_time(&self, frame: usize) -> MusicalTime {
+        let beats = frame as f64 / self.sample_rate.0 as f64 * self.tempo.0 / 60.0;
+        MusicalTime::new_with_units((beats * MusicalTime::UNITS_IN_BEAT as f64) as usize)
+    }
+
+    fn clip_spans(&self) -> Vec<ClipSpan> {
+        let midi_span = match (self.midi_clip.iter().next(), self.midi_clip.iter().last()) {
+            (Some(first), Some(last)) => Some(ClipSpan {
+                start: first.time,
+                end: last.time,
+                is_audio: false,
+            }),
+            _ => None,
+        };
+        let audio_spans = self.audio_clips.iter().map(|clip| ClipSpan {
+            start: clip.start(),
+            end: clip.end(self.tempo),
+            is_audio: true,
+        });
+        midi_span.into_iter().chain(audio_spans).collect()
+    }
+
+    /// Where the track's clips end, plus some room for tails.
+    #[cfg(feature = "gui")]
+    fn content_end(&self) -> MusicalTime {
+        let midi_end = self
+            .midi_clip
+            .iter()
+            .last()
+            .map(|e| e.time)
+            .unwrap_or(MusicalTime::START);
+        let end = self
+            .audio_clips
+            .iter()
+            .map(|c| c.end(self.tempo))
+            .fold(midi_end, |a, b| a.max(b));
+        MusicalTime::new_with_units(
+            end.total_units() + Self::FREEZE_TAIL_BEATS * MusicalTime::UNITS_IN_BEAT,
+        )
+    }
+
+    fn add_plugin(&mut self, descriptor: &PluginDescriptor) -> anyhow::Result<Uid> {
+        self.check_new_entities(1)?;
+        let entity = self.registry.plugin_host().instantiate(descriptor)?;
+        Ok(self.add_plugin_entity(entity))
+    }
+
+    fn add_plugin_entity(&mut self, mut entity: PluginEntity) -> Uid {
+        let uid = self.uid_factory.mint_next();
+        entity.set_uid(uid);
+        let roles = if entity.descriptor().is_instrument {
+            EntityRoles::INSTRUMENT
+        } else {
+            EntityRoles::EFFECT
+        };
+        let entity = Arc::new(Mutex::new(entity));
+        let latency_fn = latency_fn(&entity, ReportsLatency::latency);
+        let mut actor = EntityActor::new_with_wrapped(uid, entity.clone(), roles, &self.executor);
+        actor.set_latency_fn(latency_fn);
+        actor.set_plugin(entity);
+        self.add_actor(actor);
+        uid
+    }
+
+    /// Creates an entity of the kind registered under the given key, and adds
+    /// it to this track.
+    fn add_entity_by_key(&mut self, key: &str) -> anyhow::Result<Uid> {
+        self.check_new_entities(1)?;
+        let new_entity = self.registry.new_entity(key)?;
+        Ok(self.add_new_entity(new_entity))
+    }
+
+    /// Whether the track has room for that many more entities. See
+    /// [ActorLimits].
+    fn check_new_entities(&self, adding: usize) -> anyhow::Result<()> {
+        ActorLimits::global().check_new_entities(self.actors.len(), adding, &self.executor)
+    }
+
+    fn add_new_entity(&mut self, new_entity: NewEntity) -> Uid {
+        let uid = self.uid_factory.mint_next();
+        new_entity.entity.lock().unwrap().set_uid(uid);
+        let mut actor = EntityActor::new_with_wrapped(
+            uid,
+            new_entity.entity,
+            new_entity.roles,
+            &self.executor,
+        );
+        actor.set_presets(new_entity.presets);
+        if let Some(latency_fn) = new_entity.latency_fn {
+            actor.set_latency_fn(latency_fn);
+        }
+        self.add_actor(actor);
+        self.duplicate_fns.insert(uid, new_entity.duplicate_fn);
+        uid
+    }
+
+    fn to_project(&self) -> anyhow::Result<TrackProject> {
+        let mut entities = Vec::default();
+        for uid in self.ordered_actor_uids.iter() {
+            let Some(actor) = self.actors.get(uid) else {
+                continue;
+            };
+            if let Some(presets) = actor.presets() {
+                entities.push(EntityProject::Registered {
+                    uid: *uid,
+                    key: presets.key().to_string(),
+                    state: presets.to_ron()?,
+                });
+            } else if let Some(plugin) = actor.plugin() {
+                let plugin = plugin.lock().unwrap();
+                entities.push(EntityProject::Plugin {
+                    uid: *uid,
+                    descriptor: plugin.descriptor().clone(),
+                    state: plugin.save_state(),
+                });
+            }
+        }
+        // Mappings to entities that weren't saved would dangle.
+        let midi_mappings = self
+            .midi_mappings
+            .iter()
+            .filter(|m| entities.iter().any(|e| e.uid() == m.uid))
+            .copied()
+            .collect();
+        Ok(TrackProject {
+            info: self.info.clone(),
+            entities,
+            midi_mappings,
+        })
+    }
+
+    fn add_project(&mut self, project: &TrackProject) -> anyhow::Result<()> {
+        self.check_new_entities(project.entities.len())?;
+        self.info = project.info.clone();
+        self.broadcast_info();
+        let mut uid_map = HashMap::new();
+        for entity in project.entities.iter() {
+            let new_uid = match entity {
+                EntityProject::Registered { key, state, .. } => {
+                    let uid = self.add_entity_by_key(key)?;
+                    if let Some(presets) = self.actors.get(&uid).and_then(|a| a.presets()) {
+                        presets.apply_ron(state)?;
+                    }
+                    uid
+                }
+                EntityProject::Plugin {
+                    descriptor, state, ..
+                } => {
+                    let mut entity = PluginEntity::new_saved(descriptor.clone(), state.clone());
+                    // A plugin that isn't installed stays as a silent
+                    // placeholder, so its state survives the next save.
+                    if let Err(e) = self.registry.plugin_host().restore(&mut entity) {
+                        report_error(&format!("While restoring {}", descriptor.name), &e);
+                    }
+                    self.add_plugin_entity(entity)
+                }
+            };
+            uid_map.insert(entity.uid(), new_uid);
+        }
+        for mapping in project.midi_mappings.iter() {
+            if let Some(&uid) = uid_map.get(&mapping.uid) {
+                self.midi_mappings.push(MidiMapping { uid, ..*mapping });
+            }
+        }
+        Ok(())
+    }
+
+    fn duplicate_from(&mut self, other: &Track) -> anyhow::Result<()> {
+        self.check_new_entities(other.ordered_actor_uids.len())?;
+        let mut uid_map = HashMap::new();
+        for uid in other.ordered_actor_uids.iter() {
+            let Some(duplicate_fn) = other.duplicate_fns.get(uid) else {
+                warn!("Skipping entity {uid}, which can't be duplicated");
+                continue;
+            };
+            let new_uid = self.add_new_entity(duplicate_fn()?);
+            if let (Some(actor), Some(other_actor)) =
+                (self.actors.get_mut(&new_uid), other.actors.get(uid))
+            {
+                actor.copy_settings_from(other_actor);
+            }
+            uid_map.insert(*uid, new_uid);
+        }
+        for (uid, new_uid) in uid_map.iter() {
+            if let Some(&group) = other.effect_groups.get(uid) {
+                self.effect_groups.insert(*new_uid, group);
+            }
+            if let Some(&mix) = other.effect_mixes.get(uid) {
+                self.effect_mixes.insert(*new_uid, mix);
+            }
+            if other.midi_effects.contains(uid) {
+                self.midi_effects.insert(*new_uid);
+            }
+        }
+        for (source_uid, links) in other.control_links.iter() {
+            for link in links {
+                if let (Some(&source_uid), Some(&target_uid)) =
+                    (uid_map.get(source_uid), uid_map.get(&link.uid))
+                {
+                    self.link(source_uid, target_uid, link.param)?;
+                }
+            }
+        }
+        for (source_uid, link, transfer) in other.control_transfers.iter() {
+            if let (Some(&source_uid), Some(&target_uid)) =
+                (uid_map.get(source_uid), uid_map.get(&link.uid))
+            {
+                self.set_control_transfer(source_uid, target_uid, link.param, *transfer);
+            }
+        }
+        self.set_name(format!("{} copy", other.info.name));
+        self.set_color(other.info.color);
+        self.record_mode = other.record_mode;
+        self.groove = other.groove;
+        self.midi_channel_filter = other.midi_channel_filter;
+        self.midi_input = other.midi_input;
+        self.is_batching_generators = other.is_batching_generators;
+        for mapping in other.midi_mappings.iter() {
+            if let Some(&uid) = uid_map.get(&mapping.uid) {
+                self.midi_mappings.push(MidiMapping { uid, ..*mapping });
+            }
+        }
+        self.midi_clip = other.midi_clip.clone();
+        self.clip_edits = other.clip_edits;
+        self.update_playback_clip();
+        self.audio_clips = other.audio_clips.clone();
+        Ok(())
+    }
+
+    fn selection(&self) -> Vec<Uid> {
+        self.ordered_actor_uids
+            .iter()
+            .filter(|uid| self.selected_uids.contains(uid))
+            .copied()
+            .collect()
+    }
+
+    /// Those of the given entities that are on this track, in processing
+    /// order.
+    fn selection_of(&self, uids: &[Uid]) -> Vec<Uid> {
+        self.ordered_actor_uids
+            .iter()
+            .filter(|uid| uids.contains(uid))
+            .copied()
+            .collect()
+    }
+
+    fn set_selected(&mut self, uid: Uid, is_selected: bool) {
+        if is_selected && self.actors.contains_key(&uid) {
+            self.selected_uids.insert(uid);
+        } else {
+            self.selected_uids.remove(&uid);
+        }
+    }
+
+    /// Snapshots the given entities, in processing order, with their
+    /// settings and the links among them. Entities that can't be duplicated
+    /// are skipped.
+    fn copy_entities(&self, uids: &[Uid]) -> anyhow::Result<EntityClipboard> {
+        let mut clipboard = EntityClipboard::default();
+        for uid in self.selection_of(uids).iter() {
+            let (Some(duplicate_fn), Some(actor)) =
+                (self.duplicate_fns.get(uid), self.actors.get(uid))
+            else {
+                warn!("Skipping entity {uid}, which can't be copied");
+                continue;
+            };
+            clipboard.entities.push(CopiedEntity {
+                uid: *uid,
+                duplicate_fn: duplicate_fn()?.duplicate_fn,
+                settings: actor.settings(),
+                effect_group: self.effect_groups.get(uid).copied(),
+                effect_mix: self.effect_mixes.get(uid).copied(),
+                is_midi_effect: self.midi_effects.contains(uid),
+            });
+        }
+        let copied: HashSet<Uid> = clipboard.entities.iter().map(|e| e.uid).collect();
+        for (source_uid, links) in self.control_links.iter() {
+            for link in links {
+                if copied.contains(source_uid) && copied.contains(&link.uid) {
+                    clipboard.control_links.push((*source_uid, *link));
+                }
+            }
+        }
+        clipboard.control_transfers = self
+            .control_transfers
+            .iter()
+            .filter(|(source_uid, link, _)| {
+                copied.contains(source_uid) && copied.contains(&link.uid)
+            })
+            .copied()
+            .collect();
+        Ok(clipboard)
+    }
+
+    /// Adds copies of the clipboard's entities after our own, with their
+    /// settings and the links among them.
+    fn paste_entities(&mut self, clipboard: &EntityClipboard) -> anyhow::Result<Vec<Uid>> {
+        self.check_new_entities(clipboard.entities.len())?;
+        let mut uid_map = HashMap::new();
+        for copied in clipboard.entities.iter() {
+            let uid = self.add_new_entity((copied.duplicate_fn)()?);
+            if let Some(actor) = self.actors.get_mut(&uid) {
+                actor.apply_settings(&copied.settings);
+            }
+            if let Some(group) = copied.effect_group {
+                self.effect_groups.insert(uid, group);
+            }
+            if let Some(mix) = copied.effect_mix {
+                self.effect_mixes.insert(uid, mix);
+            }
+            self.set_midi_effect(uid, copied.is_midi_effect);
+            uid_map.insert(copied.uid, uid);
+        }
+        for (source_uid, link) in clipboard.control_links.iter() {
+            self.link(uid_map[source_uid], uid_map[&link.uid], link.param)?;
+        }
+        for (source_uid, link, transfer) in clipboard.control_transfers.iter() {
+            let (source_uid, target_uid) = (uid_map[source_uid], uid_map[&link.uid]);
+            self.set_control_transfer(source_uid, target_uid, link.param, *transfer);
+        }
+        let uids = clipboard.entities.iter().map(|copied| uid_map[&copied.uid]);
+        Ok(uids.collect())
+    }
+
+    fn add_actor(&mut self, actor: EntityActor) {
+        let uid = actor.uid();
+        actor.send_request(EntityRequest::ActionSubscribe(
+            self.actor_subscription_senders.audio.clone(),
+        ));
+        actor.send_request(EntityRequest::MidiSubscribe(
+            self.actor_subscription_senders.midi.clone(),
+        ));
+
+        // Note: do not automatically subscribe Control, because that's
+        // point-to-point
+
+        if let Ok(entity) = actor.entity.lock() {
+            for i in 0..entity.control_index_count() {
+                self.controllables.push(ControllableItem {
+                    name: format!(
+                        "{}: {}",
+                        entity.name(),
+                        entity.control_name_for_index(i.into()).unwrap()
+                    ),
+                    uid: entity.uid(),
+                    param: i.into(),
+                })
+            }
+        }
+
+        actor.send_request(EntityRequest::UpdateTempo(self.tempo));
+        self.entity_request_subscription.subscribe(actor.sender());
+        self.ordered_actor_uids.push(uid);
+        self.actors.insert(uid, actor);
+    }
+
+    fn remove_actor(&mut self, uid: Uid) -> Option<EntityActor> {
+        // Where the entity's NoteOffs go, worked out while it's still in the
+        // MIDI chain.
+        let destinations = self.midi_destinations(Some(uid));
+        if let Some(actor) = self.actors.get(&uid) {
+            // Undo can bring the entity back, so it shouldn't be left holding
+            // notes whose NoteOffs it will miss.
+            for (_, channel, message) in self.active_notes.note_offs(|s| s != Some(uid)) {
+                actor.send(EntityRequest::Midi(channel, message));
+            }
+            self.entity_request_subscription.unsubscribe(actor.sender());
+            actor.send_request(EntityRequest::ActionUnsubscribe(
+                self.actor_subscription_senders.audio.clone(),
+            ));
+            actor.send_request(EntityRequest::MidiUnsubscribe(
+                self.actor_subscription_senders.midi.clone(),
+            ));
+            actor.send_request(EntityRequest::ControlUnsubscribe(
+                self.actor_subscription_senders.control.clone(),
+            ));
+        }
+        self.duplicate_fns.remove(&uid);
+        self.midi_mappings.retain(|m| m.uid != uid);
+        self.ordered_actor_uids.retain(|u| *u != uid);
+        self.effect_groups.remove(&uid);
+        self.effect_mixes.remove(&uid);
+        self.midi_effects.remove(&uid);
+        self.selected_uids.remove(&uid);
+        self.controllables.retain(|c| c.uid != uid);
+        let actor = self.actors.remove(&uid);
+
+        // Nobody else will end the notes that the entity was playing.
+        for (channel, message) in self.active_notes.release_from(Some(uid)) {
+            let action = MidiAction {
+                source_uid: uid,
+                channel,
+                message,
+                hops: 0,
+            };
+            self.midi_subscription.broadcast_mut(action);
+            self.send_midi(&destinations, EntityRequest::Midi(channel, message));
+        }
+        actor
+    }
+
+    fn unsubscribe_midi(&mut self, sender: &Sender<MidiAction>) {
+        for (source, channel, message) in self.active_notes.note_offs(|s| s.is_some()) {
+            let _ = sender.try_send(MidiAction {
+                source_uid: source.unwrap_or_default(),
+                channel,
+                message,
+                hops: 0,
+            });
+        }
+        self.midi_subscription.unsubscribe(sender);
+    }
+
+    fn set_midi_input(&mut self, midi_input: MidiInputProcessor) {
+        // Held notes' NoteOffs would be transposed differently from their
+        // NoteOns, so end them now.
+        if midi_input.transpose != self.midi_input.transpose {
+            for (channel, message) in self.active_notes.release_from(None) {
+                self.send_input_midi(EntityRequest::Midi(channel, message));
+            }
+        }
+        self.midi_input = midi_input;
+    }
+
+    fn release_notes(&mut self) {
+        for source in self.active_notes.sources() {
+            for (channel, message) in self.active_notes.release_from(source) {
+                match source {
+                    Some(source_uid) => self.handle_midi_action(MidiAction {
+                        source_uid,
+                        channel,
+                        message,
+                        hops: 0,
+                    }),
+                    None => self.send_input_midi(EntityRequest::Midi(channel, message)),
+                }
+            }
+        }
+    }
+
+    /// Removes the entity and its links, but keeps it running so that
+    /// [Track::attach_entity()] can put it back.
+    fn detach_entity(&mut self, uid: Uid) -> anyhow::Result<DetachedEntity> {
+        let position = self
+            .ordered_actor_uids
+            .iter()
+            .position(|u| *u == uid)
+            .ok_or_else(|| anyhow!("Couldn't find entity {uid}"))?;
+        let links_from = self.control_links.remove(&uid).unwrap_or_default();
+        for link in links_from.iter() {
+            self.unlink(uid, link.uid, link.param);
+        }
+        let mut links_to = Vec::default();
+        let source_uids: Vec<Uid> = self.control_links.keys().copied().collect();
+        for source_uid in source_uids {
+            let links = self.control_links[&source_uid].clone();
+            for link in links.into_iter().filter(|link| link.uid == uid) {
+                self.unlink(source_uid, link.uid, link.param);
+                links_to.push((source_uid, link.param));
+            }
+        }
+        let effect_group = self.effect_groups.get(&uid).copied();
+        let effect_mix = self.effect_mixes.get(&uid).copied();
+        let is_midi_effect = self.midi_effects.contains(&uid);
+        let midi_mappings = self
+            .midi_mappings
+            .iter()
+            .filter(|m| m.uid == uid)
+            .copied()
+            .collect();
+        let duplicate_fn = self.duplicate_fns.get(&uid).cloned();
+        Ok(DetachedEntity {
+            actor: self.remove_actor(uid),
+            duplicate_fn,
+            position,
+            effect_group,
+            effect_mix,
+            is_midi_effect,
+            links_from,
+            links_to,
+            midi_mappings,
+        })
+    }
+
+    /// Puts a detached entity back where it was, with its links and settings.
+    fn attach_entity(&mut self, mut detached: DetachedEntity) -> anyhow::Result<Uid> {
+        let uid = self.attach_actor(&mut detached)?;
+        self.restore_links(uid, &detached);
+        Ok(uid)
+    }
+
+    /// Does everything for [Track::attach_entity] except restoring links.
+    fn attach_actor(&mut self, detached: &mut DetachedEntity) -> anyhow::Result<Uid> {
+        let actor = detached
+            .actor
+            .take()
+            .ok_or_else(|| anyhow!("Entity was already attached"))?;
+        let uid = actor.uid();
+        self.add_actor(actor);
+        self.move_entity(uid, detached.position);
+        if let Some(duplicate_fn) = detached.duplicate_fn.take() {
+            self.duplicate_fns.insert(uid, duplicate_fn);
+        }
+        if let Some(group) = detached.effect_group {
+            self.effect_groups.insert(uid, group);
+        }
+        if let Some(mix) = detached.effect_mix {
+            self.effect_mixes.insert(uid, mix);
+        }
+        self.set_midi_effect(uid, detached.is_midi_effect);
+        self.midi_mappings.append(&mut detached.midi_mappings);
+        Ok(uid)
+    }
+
+    fn restore_links(&mut self, uid: Uid, detached: &DetachedEntity) {
+        // An entity on the other end might itself have been removed since.
+        for link in detached.links_from.iter() {
+            if let Err(e) = self.link(uid, link.uid, link.param) {
+                warn!("While restoring a link from {uid}: {e:?}");
+            }
+        }
+        for &(source_uid, param) in detached.links_to.iter() {
+            if let Err(e) = self.link(source_uid, uid, param) {
+                warn!("While restoring a link to {uid}: {e:?}");
+            }
+        }
+    }
 
-            state: Default::default(),
-            buffer: Default::default(),
-            audio_subscription: Default::default(),
-            midi_subscription: Default::default(),
+    /// Hands the given entities over to the other track, after its own
+    /// entities, along with the links among them. The entities keep running
+    /// throughout. Returns the links between them and the entities that stay
+    /// behind, which the move breaks.
+    fn move_entities_to(
+        &mut self,
+        other: &mut Track,
+        uids: &[Uid],
+    ) -> anyhow::Result<Vec<(Uid, ControlLink)>> {
+        let uids = self.selection_of(uids);
+        ActorLimits::global().check_entity_count(other.actors.len() + uids.len())?;
+        let mut moving = Vec::default();
+        let mut broken = Vec::default();
+        for &uid in uids.iter() {
+            let mut detached = self.detach_entity(uid)?;
+            let (links_from, lost): (Vec<_>, Vec<_>) = detached
+                .links_from
+                .drain(..)
+                .partition(|link| uids.contains(&link.uid));
+            detached.links_from = links_from;
+            broken.extend(lost.into_iter().map(|link| (uid, link)));
+            let (links_to, lost): (Vec<_>, Vec<_>) = detached
+                .links_to
+                .drain(..)
+                .partition(|(source_uid, _)| uids.contains(source_uid));
+            detached.links_to = links_to;
+            broken.extend(
+                lost.into_iter()
+                    .map(|(source_uid, param)| (source_uid, ControlLink { uid, param })),
+            );
+            detached.position = usize::MAX;
+            moving.push(detached);
+        }
+        // Every entity has to be there before any of the links can be.
+        let mut moved_uids = Vec::default();
+        for detached in moving.iter_mut() {
+            moved_uids.push(other.attach_actor(detached)?);
+        }
+        for (&uid, detached) in moved_uids.iter().zip(moving.iter()) {
+            other.restore_links(uid, detached);
+        }
+        for &(source_uid, link, transfer) in self.control_transfers.iter() {
+            if uids.contains(&source_uid) && uids.contains(&link.uid) {
+                other.set_control_transfer(source_uid, link.uid, link.param, transfer);
+            }
         }
+        Ok(broken)
     }
 
-    fn add_entity(&mut self, mut entity: impl Entity + 'static) {
-        entity.set_uid(self.uid_factory.mint_next());
-        let actor = EntityActor::new_with(entity);
-        self.add_actor(actor);
+    /// Moves the mixer levels and the crossfader that the source entity
+    /// drives. This bypasses the undo history, as automation would.
+    fn handle_control_action(&mut self, action: ControlAction) {
+        let Some(mixer) = self.mixer.as_mut() else {
+            return;
+        };
+        if let Some(track_uids) = self.mixer_level_links.get(&action.source_uid) {
+            for &track_uid in track_uids {
+                let _ = mixer.set_level(track_uid, Normal::from(action.value.0));
+            }
+        }
+        if self.crossfader_links.contains(&action.source_uid) {
+            mixer.set_crossfader(Normal::from(action.value.0));
+        }
     }
 
-    fn add_actor(&mut self, actor: EntityActor) {
-        let uid = actor.uid();
-        actor.send_request(EntityRequest::ActionSubscribe(
-            self.actor_subscription_senders.audio.clone(),
-        ));
-        actor.send_request(EntityRequest::MidiSubscribe(
-            self.actor_subscription_senders.midi.clone(),
-        ));
+    fn set_mixer_level(&mut self, track_uid: TrackUid, level: Normal) -> anyhow::Result<Normal> {
+        self.mixer
+            .as_mut()
+            .ok_or_else(|| anyhow!("Track {} has no mixer", self.uid))?
+            .set_level(track_uid, level)
+    }
 
-        // Note: do not automatically subscribe Control, because that's
-        // point-to-point
+    fn set_mixer_mute(&mut self, track_uid: TrackUid, muted: bool) -> anyhow::Result<bool> {
+        self.mixer
+            .as_mut()
+            .ok_or_else(|| anyhow!("Track {} has no mixer", self.uid))?
+            .set_muted(track_uid, muted)
+    }
 
-        if let Ok(entity) = actor.entity.lock() {
-            for i in 0..entity.control_index_count() {
-                self.controllables.push(ControllableItem {
-                    name: format!(
-                        "{}: {}",
-                        entity.name(),
-                        entity.control_name_for_index(i.into()).unwrap()
-                    ),
-                    uid: entity.uid(),
-                    param: i.into(),
-                })
-            }
+    fn set_output_pair(&mut self, track_uid: TrackUid, pair: usize) -> anyhow::Result<usize> {
+        self.mixer
+            .as_mut()
+            .ok_or_else(|| anyhow!("Track {} has no mixer", self.uid))?
+            .set_output_pair(track_uid, pair)
+    }
+
+    fn set_crossfade_group(
+        &mut self,
+        track_uid: TrackUid,
+        group: CrossfadeGroup,
+    ) -> anyhow::Result<CrossfadeGroup> {
+        self.mixer
+            .as_mut()
+            .ok_or_else(|| anyhow!("Track {} has no mixer", self.uid))?
+            .set_crossfade_group(track_uid, group)
+    }
+
+    fn set_crossfader(&mut self, position: Normal) -> anyhow::Result<Normal> {
+        Ok(self
+            .mixer
+            .as_mut()
+            .ok_or_else(|| anyhow!("Track {} has no mixer", self.uid))?
+            .set_crossfader(position))
+    }
+
+    fn set_crossfade_curve(&mut self, curve: CrossfadeCurve) -> anyhow::Result<CrossfadeCurve> {
+        Ok(self
+            .mixer
+            .as_mut()
+            .ok_or_else(|| anyhow!("Track {} has no mixer", self.uid))?
+            .set_crossfade_curve(curve))
+    }
+
+    fn set_channel_layout(&mut self, channel_layout: ChannelLayout) {
+        if let Some(mixer) = self.mixer.as_mut() {
+            mixer.set_channel_layout(channel_layout);
+            self.other_pairs
+                .resize_with(channel_layout.pair_count() - 1, Default::default);
         }
+    }
 
-        self.entity_request_subscription.subscribe(actor.sender());
-        self.ordered_actor_uids.push(uid);
-        self.actors.insert(uid, actor);
+    /// Moves the entity to a new position in the serial effects chain. Indexes
+    /// past the end move it to the end. Returns where it was, if it's here.
+    fn move_entity(&mut self, uid: Uid, index: usize) -> Option<usize> {
+        let current_index = self.ordered_actor_uids.iter().position(|u| *u == uid)?;
+        self.ordered_actor_uids.remove(current_index);
+        let index = index.min(self.ordered_actor_uids.len());
+        self.ordered_actor_uids.insert(index, uid);
+        Some(current_index)
     }
 
-    fn remove_actor(&mut self, uid: Uid) {
-        if let Some(actor) = self.actors.get(&uid) {
-            self.entity_request_subscription.unsubscribe(actor.sender());
-            actor.send_request(EntityRequest::ActionUnsubscribe(
-                self.actor_subscription_senders.audio.clone(),
-            ));
-            actor.send_request(EntityRequest::MidiUnsubscribe(
-                self.actor_subscription_senders.midi.clone(),
-            ));
-            actor.send_request(EntityRequest::ControlUnsubscribe(
-                self.actor_subscription_senders.control.clone(),
-            ));
+    fn set_midi_effect(&mut self, uid: Uid, is_midi_effect: bool) -> bool {
+        if is_midi_effect {
+            !self.midi_effects.insert(uid)
+        } else {
+            self.midi_effects.remove(&uid)
         }
-        self.actors.remove(&uid);
-        self.ordered_actor_uids.retain(|u| *u != uid);
-        self.controllables.retain(|c| c.uid != uid);
+    }
+
+    /// The entities that hear MIDI from the given one, or from the track's
+    /// input (None). MIDI from the input, or from an entity outside the
+    /// chain, enters the first MIDI effect. Each MIDI effect's output goes
+    /// to the next, and the last one's to every entity outside the chain.
+    /// Without a chain, each entity hears everything but its own output.
+    fn midi_destinations(&self, source_uid: Option<Uid>) -> Vec<Uid> {
+        let chain: Vec<Uid> = self
+            .ordered_actor_uids
+            .iter()
+            .filter(|uid| self.midi_effects.contains(uid))
+            .copied()
+            .collect();
+        let next = match source_uid.and_then(|uid| chain.iter().position(|u| *u == uid)) {
+            Some(position) => chain.get(position + 1),
+            None => chain.first(),
+        };
+        if let Some(&next) = next {
+            return vec![next];
+        }
+        self.ordered_actor_uids
+            .iter()
+            .filter(|uid| Some(**uid) != source_uid && !self.midi_effects.contains(uid))
+            .copied()
+            .collect()
+    }
+
+    fn send_midi(&self, uids: &[Uid], request: EntityRequest) {
+        for actor in uids.iter().filter_map(|uid| self.actors.get(uid)) {
+            actor.send(request.clone());
+        }
+    }
+
+    fn set_effect_group(&mut self, uid: Uid, group: Option<usize>) -> Option<usize> {
+        if let Some(group) = group {
+            self.effect_groups.insert(uid, group)
+        } else {
+            self.effect_groups.remove(&uid)
+        }
+    }
+
+    /// Effects without a mix are fully wet.
+    fn set_effect_mix(&mut self, uid: Uid, mix: Normal) -> Normal {
+        self.effect_mixes
+            .insert(uid, mix)
+            .unwrap_or(Normal::maximum())
     }
 
     fn link(
@@ -371,6 +2538,11 @@ impl Track {
                     target.control_sender().clone(),
                 ));
                 target.send_request(EntityRequest::ControlLinkAdd(source_uid, index));
+                let transfer = self.control_transfer(source_uid, target_uid, index);
+                if !transfer.is_identity() {
+                    let request = EntityRequest::SetControlTransfer(source_uid, index, transfer);
+                    target.send_request(request);
+                }
                 self.control_links
                     .entry(source_uid)
                     .or_default()
@@ -384,6 +2556,65 @@ impl Track {
         Err(anyhow!("Couldn't find both {source_uid} and {target_uid}"))
     }
 
+    fn set_param(
+        &mut self,
+        uid: Uid,
+        index: ControlIndex,
+        value: ControlValue,
+    ) -> anyhow::Result<()> {
+        self.actor_mut(uid)?.set_param(index, value);
+        Ok(())
+    }
+
+    fn actor(&self, uid: Uid) -> anyhow::Result<&EntityActor> {
+        self.actors
+            .get(&uid)
+            .ok_or_else(|| anyhow!("Couldn't find entity {uid}"))
+    }
+
+    fn actor_mut(&mut self, uid: Uid) -> anyhow::Result<&mut EntityActor> {
+        self.actors
+            .get_mut(&uid)
+            .ok_or_else(|| anyhow!("Couldn't find entity {uid}"))
+    }
+
+    fn control_transfer(
+        &self,
+        source_uid: Uid,
+        target_uid: Uid,
+        index: ControlIndex,
+    ) -> TransferFunction {
+        self.control_transfers
+            .iter()
+            .find(|(s, link, _)| *s == source_uid && link.uid == target_uid && link.param == index)
+            .map(|(_, _, transfer)| *transfer)
+            .unwrap_or_default()
+    }
+
+    fn set_control_transfer(
+        &mut self,
+        source_uid: Uid,
+        target_uid: Uid,
+        index: ControlIndex,
+        transfer: TransferFunction,
+    ) -> TransferFunction {
+        let old = self.control_transfer(source_uid, target_uid, index);
+        self.control_transfers.retain(|(s, link, _)| {
+            *s != source_uid || link.uid != target_uid || link.param != index
+        });
+        if !transfer.is_identity() {
+            let link = ControlLink {
+                uid: target_uid,
+                param: index,
+            };
+            self.control_transfers.push((source_uid, link, transfer));
+        }
+        if let Some(target) = self.actors.get(&target_uid) {
+            target.send_request(EntityRequest::SetControlTransfer(source_uid, index, transfer));
+        }
+        old
+    }
+
     fn unlink(&mut self, source_uid: Uid, target_uid: Uid, index: ControlIndex) {
         if let Some(source) = self.actors.get(&source_uid) {
             if let Some(target) = self.actors.get(&target_uid) {
@@ -392,179 +2623,630 @@ impl Track {
                 ));
                 target.send_request(EntityRequest::ControlLinkRemove(source_uid, index));
                 if let Some(links) = self.control_links.get_mut(&source_uid) {
-                    links.retain(|link| link.uid != target_uid && link.param != index);
+                    links.retain(|link| link.uid != target_uid || link.param != index);
                 }
             }
         }
     }
 
     fn handle_audio_action(&mut self, action: AudioAction) {
-        let track_uid = TrackUid::default(); // HACK!
+        if matches!(self.state, TrackState::Idle) {
+            self.early_audio_actions.push(action);
+            return;
+        }
         if self.mixer.is_some() {
+            let track_uid = TrackUid(action.source_uid.0);
             self.handle_incoming_track_frames(track_uid, action.frames);
         } else {
-            self.handle_incoming_frames(action.frames);
+            self.handle_incoming_frames(action.source_uid, action.frames);
         }
     }
 
     fn handle_midi_action(&mut self, action: MidiAction) {
-        self.midi_subscription.broadcast_mut(action.clone());
-        // TODO: opportunity to use direct channels?
-        for actor in self
-            .actors
-            .values()
-            .filter(|&a| a.uid() != action.source_uid)
-        {
-            actor.send(EntityRequest::Midi(action.channel, action.message));
+        // A message can't pass through more entities than the track has
+        // without visiting one of them twice.
+        if action.hops >= self.actors.len() {
+            let dropped = self.midi_loop.map_or(0, |(_, dropped)| dropped);
+            self.midi_loop = Some((action.source_uid, dropped + 1));
+            return;
         }
+        self.active_notes
+            .note(Some(action.source_uid), action.channel, &action.message);
+        self.midi_subscription.broadcast_mut(action.clone());
+        let destinations = self.midi_destinations(Some(action.source_uid));
+        self.send_midi(&destinations, EntityRequest::RelayedMidi(action));
     }
 
-    fn handle_incoming_frames(&mut self, frames: Vec<StereoSample>) {
-        assert!(frames.len() <= 64);
+    fn handle_incoming_frames(&mut self, source_uid: Uid, frames: Vec<StereoSample>) {
         match &self.state {
-            TrackState::Idle => panic!("We got frames when we weren't expecting any"),
+            TrackState::Idle => {
+                self.fault(format!("Frames from {source_uid} arrived between cycles"));
+            }
             TrackState::AwaitingSources(_) => {
                 // We got some audio from someone. Mix it into the track buffer.
                 self.buffer.merge(&frames);
                 self.advance_state_awaiting_sources();
             }
-            TrackState::AwaitingEffect(_) => {
-                // An effect completed processing. Copy its results over the
-                // current buffer.
-                self.buffer.buffer_mut().copy_from_slice(&frames);
-                self.advance_state_awaiting_effect();
+            TrackState::AwaitingEffect {
+                outstanding,
+                stage_size,
+                ..
+            } => {
+                // An effect completed processing. Blend its results with its
+                // input (which is still in the track buffer) and add them to
+                // the stage. Once everyone in the stage is done, replace the
+                // current buffer with their average.
+                let (outstanding, stage_size) = (*outstanding, *stage_size);
+                let wet = self
+                    .effect_mixes
+                    .get(&source_uid)
+                    .copied()
+                    .unwrap_or(Normal::maximum())
+                    .0;
+                for ((dst, wet_sample), dry_sample) in self
+                    .stage_buffer
+                    .iter_mut()
+                    .zip(frames.iter())
+                    .zip(self.buffer.buffer().iter())
+                {
+                    *dst += *wet_sample * wet + *dry_sample * (1.0 - wet);
+                }
+                if outstanding == 1 {
+                    let scale = 1.0 / stage_size as f64;
+                    for (dst, src) in self
+                        .buffer
+                        .buffer_mut()
+                        .iter_mut()
+                        .zip(self.stage_buffer.iter())
+                    {
+                        *dst = *src * scale;
+                    }
+                    self.advance_state_awaiting_effect();
+                } else if let TrackState::AwaitingEffect { outstanding, .. } = &mut self.state {
+                    *outstanding -= 1;
+                }
             }
         }
+        BufferPool::global().recycle(frames);
     }
 
     fn handle_incoming_track_frames(&mut self, track_uid: TrackUid, frames: Vec<StereoSample>) {
-        assert!(frames.len() <= 64);
-        assert!(matches!(self.state, TrackState::AwaitingSources(..)));
-        assert!(self.is_master_track);
+        if !matches!(self.state, TrackState::AwaitingSources(..)) {
+            self.fault(format!("Frames from track {track_uid} arrived after the mix"));
+            BufferPool::global().recycle(frames);
+            return;
+        }
 
         if let Some(mixer) = self.mixer.as_ref() {
-            mixer.mix(track_uid, &frames, self.buffer.buffer_mut());
+            mixer.mix(
+                track_uid,
+                &frames,
+                self.buffer.buffer_mut(),
+                &mut self.other_pairs,
+            );
         }
+        BufferPool::global().recycle(frames);
         self.advance_state_awaiting_sources();
     }
 
     fn advance_state_awaiting_sources(&mut self) {
         match &self.state {
-            TrackState::Idle => panic!("invalid starting state"),
+            TrackState::Idle => self.fault("A source finished between cycles".to_string()),
             TrackState::AwaitingSources(count) => {
                 // We got a frame. See if we've gotten all the ones we expect.
                 if *count == 1 {
                     // We have. Now it's time to let the effects process what we
                     // have.
-                    self.state =
-                        TrackState::AwaitingEffect(VecDeque::from(self.ordered_actor_uids.clone()));
+                    self.state = self.new_awaiting_effect_state();
                     self.advance_state_awaiting_effect();
                 } else {
                     self.state = TrackState::AwaitingSources(count - 1);
                 }
             }
-            TrackState::AwaitingEffect(_) => {
-                panic!("invalid state")
+            TrackState::AwaitingEffect { .. } => {
+                self.fault("A source finished while the effects were running".to_string())
             }
         }
     }
 
     fn advance_state_awaiting_effect(&mut self) {
-        if let TrackState::AwaitingEffect(uids) = &mut self.state {
-            if let Some(uid) = uids.pop_front() {
-                if let Some(actor) = self.actors.get(&uid) {
+        if let TrackState::AwaitingEffect {
+            remaining_stages,
+            outstanding,
+            stage_size,
+        } = &mut self.state
+        {
+            // Count only the effects that are still here, e.g., not just
+            // removed, since the others will never answer. A stage with none
+            // left is skipped.
+            while let Some(stage) = remaining_stages.pop_front() {
+                let actors: Vec<&EntityActor> = stage
+                    .iter()
+                    .filter_map(|uid| self.actors.get(uid))
+                    .collect();
+                if actors.is_empty() {
+                    continue;
+                }
+                *outstanding = actors.len();
+                *stage_size = actors.len();
+                self.stage_buffer.clear();
+                self.stage_buffer.resize(self.buffer.buffer().len(), StereoSample::SILENCE);
+                for actor in actors {
                     actor.send_request(EntityRequest::NeedsTransformation(
-                        self.buffer.buffer().to_vec(),
+                        BufferPool::global().take_copy(self.buffer.buffer()),
                     ));
                 }
-            } else {
-                // We're out of effects. Send what we have!
-                self.issue_outgoing_frames_action();
+                return;
             }
+            // We're out of effects. Send what we have!
+            self.issue_outgoing_frames_action();
         } else {
-            panic!("wrong state: {:?}", self.state);
+            self.fault(format!("Effects can't run while {:?}", self.state));
+        }
+    }
+
+    /// Handles a message that the state machine didn't expect. Rather than
+    /// take the actor down, the track logs it, starts over from idle, and
+    /// tells its subscribers with [TrackAction::Fault].
+    fn fault(&mut self, fault: String) {
+        error!("Track {}: {fault}; starting over from idle", self.uid);
+        self.state = TrackState::Idle;
+        self.track_action_subscription
+            .broadcast_mut(TrackAction::Fault(self.uid, fault));
+    }
+
+    fn handle_track_action(&mut self, action: TrackAction) {
+        match action {
+            TrackAction::Meter(track_uid, snapshot) => {
+                if let Some(mixer) = self.mixer.as_mut() {
+                    mixer.update_meter(track_uid, snapshot);
+                }
+            }
+            TrackAction::Frames(..) | TrackAction::Latency(..) | TrackAction::Fault(..) => {}
+            TrackAction::Info(track_uid, info) => {
+                if let Some(mixer) = self.mixer.as_mut() {
+                    mixer.update_info(track_uid, info);
+                }
+            }
+        }
+    }
+
+    /// The transformers in this track, in processing order, grouped into
+    /// stages. Adjacent effects in the same parallel group share a stage.
+    fn effect_stages(&self) -> VecDeque<Vec<Uid>> {
+        let mut stages: VecDeque<Vec<Uid>> = Default::default();
+        let mut last_group = None;
+        for &uid in self
+            .ordered_actor_uids
+            .iter()
+            .filter(|uid| self.actors.get(uid).is_some_and(|a| a.roles().transforms_audio))
+        {
+            let group = self.effect_groups.get(&uid).copied();
+            match stages.back_mut() {
+                Some(stage) if group.is_some() && group == last_group => stage.push(uid),
+                _ => stages.push_back(vec![uid]),
+            }
+            last_group = group;
+        }
+        stages
+    }
+
+    /// How many frames late our output is: the slowest instrument's
+    /// latency, plus the slowest effect's in each stage of the chain.
+    fn latency(&self) -> usize {
+        let instruments = self
+            .actors
+            .values()
+            .filter(|actor| actor.roles().generates_audio)
+            .map(|actor| actor.latency())
+            .max()
+            .unwrap_or_default();
+        let effects: usize = self
+            .effect_stages()
+            .iter()
+            .map(|stage| {
+                stage
+                    .iter()
+                    .filter_map(|uid| self.actors.get(uid))
+                    .map(|actor| actor.latency())
+                    .max()
+                    .unwrap_or_default()
+            })
+            .sum();
+        instruments + effects
+    }
+
+    fn new_awaiting_effect_state(&self) -> TrackState {
+        TrackState::AwaitingEffect {
+            remaining_stages: self.effect_stages(),
+            outstanding: 0,
+            stage_size: 0,
         }
     }
 
     fn issue_outgoing_frames_action(&mut self) {
         self.state = TrackState::Idle;
+        if let Some(progress) = self.freeze_progress.as_mut() {
+            if progress.is_started {
+                progress.frames.extend_from_slice(self.buffer.buffer());
+                self.render_next_freeze_block();
+                return;
+            }
+        }
+        let latency = self.latency();
+        if latency != self.reported_latency {
+            self.reported_latency = latency;
+            self.track_action_subscription
+                .broadcast_mut(TrackAction::Latency(self.uid, latency));
+        }
+        self.track_action_subscription.broadcast_mut(TrackAction::Meter(
+            self.uid,
+            MeterSnapshot::new_with_frames(self.buffer.buffer()),
+        ));
+        let pool = BufferPool::global();
+        self.frames_subscription
+            .broadcast_mut(TrackAction::Frames(self.uid, pool.take_copy(self.buffer.buffer())));
+        self.deliver(pool.take_copy(self.buffer.buffer()));
+        if self.freeze_progress.is_some() {
+            self.render_next_freeze_block();
+        }
+    }
+
+    /// Sends the frames to our main output and to each send destination.
+    /// Tracks put their [TrackUid] in [AudioAction::source_uid] so that the
+    /// master track's mixer can tell them apart.
+    fn deliver(&mut self, mut frames: Vec<StereoSample>) {
+        let source_uid = Uid(self.uid.0);
+        for destination in self.send_destinations.values_mut() {
+            let mut scaled = BufferPool::global().take(frames.len());
+            for (dst, src) in scaled.iter_mut().zip(frames.iter()) {
+                *dst = *src * destination.level.0;
+            }
+            destination.delay.process(&mut scaled);
+            let _ = destination.sender.try_send(AudioAction {
+                source_uid,
+                frames: scaled,
+                other_pairs: Default::default(),
+            });
+        }
+        self.latency_compensation.process(&mut frames);
+        let pool = BufferPool::global();
+        let other_pairs = self.other_pairs.iter().map(|p| pool.take_copy(p)).collect();
         self.audio_subscription.broadcast_mut(AudioAction {
-            source_uid: Uid::default(), // HACK
-            frames: self.buffer.buffer().into(),
+            source_uid,
+            frames,
+            other_pairs,
         });
     }
 
     fn handle_needs_audio(&mut self, count: usize) {
-        assert!(
-            matches!(self.state, TrackState::Idle),
-            "{}: expected a clean slate",
-            self.uid
-        );
+        if self.has_rendered {
+            return;
+        }
+        self.has_rendered = true;
+
+        // The entities are busy with the freeze, so everyone else hears
+        // silence until it's done.
+        if self.freeze_progress.is_some() {
+            self.deliver(BufferPool::global().take(count));
+            return;
+        }
+        if let Some(frozen_clip) = self.frozen_clip.as_ref() {
+            self.buffer.resize(count);
+            self.buffer.clear();
+            frozen_clip.mix_into(
+                self.time_range.0.start,
+                self.tempo,
+                self.sample_rate,
+                self.buffer.buffer_mut(),
+            );
+            self.issue_outgoing_frames_action();
+            return;
+        }
+        self.render(count);
+    }
+
+    /// Returns a batch with a slot for each generator, ready for a new cycle.
+    /// The previous cycle's batch is reused if it fits and its generators are
+    /// done with it.
+    fn start_generator_batch(&mut self, slot_count: usize, frame_count: usize) -> Arc<AudioBatch> {
+        let batch = match self.generator_batch.take() {
+            Some(batch) if batch.slot_count() == slot_count && Arc::strong_count(&batch) == 1 => {
+                batch
+            }
+            _ => Arc::new(AudioBatch::new_with(
+                slot_count,
+                self.actor_subscription_senders.audio.clone(),
+            )),
+        };
+        batch.start(frame_count);
+        self.generator_batch = Some(Arc::clone(&batch));
+        batch
+    }
+
+    fn render(&mut self, count: usize) {
+        if !matches!(self.state, TrackState::Idle) {
+            self.fault(format!("Audio was requested while {:?}", self.state));
+        }
         self.buffer.resize(count);
         self.buffer.clear();
+        for pair in self.other_pairs.iter_mut() {
+            pair.clear();
+            pair.resize(count, StereoSample::SILENCE);
+        }
+
+        // Audio clips go straight into the buffer, ahead of the other sources.
+        for audio_clip in self.audio_clips.iter() {
+            audio_clip.mix_into(
+                self.time_range.0.start,
+                self.tempo,
+                self.sample_rate,
+                self.buffer.buffer_mut(),
+            );
+        }
 
         // if we have source tracks, start them. Same for instruments.
-        let new_sources_count = self.send_tracks.len() + self.actors.len();
+        // Suspended instruments are skipped, and the buffer's silence stands
+        // in for them, unless the track is holding notes that might be
+        // theirs. The flag can change under us, so decide just once.
+        let is_holding_notes = !self.active_notes.is_empty();
+        let generator_uids: Vec<Uid> = self
+            .actors
+            .iter()
+            .filter(|(_, a)| a.roles().generates_audio && (is_holding_notes || !a.is_suspended()))
+            .map(|(&uid, _)| uid)
+            .collect();
+        let generator_count = generator_uids.len();
+        let batch = if self.is_batching_generators && generator_count > 0 {
+            Some(self.start_generator_batch(generator_count, count))
+        } else {
+            None
+        };
+        let generators = generator_uids.iter().filter_map(|uid| self.actors.get(uid));
+        // A batch replies only once, no matter how many generators it has.
+        let generator_source_count = if batch.is_some() { 1 } else { generator_count };
+        let new_sources_count = self.send_tracks.len() + generator_source_count;
         self.state = TrackState::AwaitingSources(new_sources_count);
         for source in self.send_tracks.values() {
             let _ = source.try_send(TrackRequest::NeedsAudio(count));
         }
-        for actor in self.actors.values() {
-            actor.send(EntityRequest::NeedsAudio(count));
+        if let Some(batch) = batch {
+            for (index, actor) in generators.enumerate() {
+                actor.send(EntityRequest::NeedsAudioBatch(Arc::clone(&batch), index));
+            }
+        } else {
+            for actor in generators {
+                actor.send(EntityRequest::NeedsAudio(count));
+            }
+        }
+        for action in std::mem::take(&mut self.early_audio_actions) {
+            self.handle_audio_action(action);
         }
 
-        // Did we have any sources in the first place?
+        // Did we have any sources in the first place? If not, the effects
+        // still get a crack at the (silent, or clip-only) buffer.
         if new_sources_count == 0 {
-            self.issue_outgoing_frames_action();
+            self.state = self.new_awaiting_effect_state();
+            self.advance_state_awaiting_effect();
         } else {
             // Nothing to do now but wait for incoming Frames from our sources
         }
     }
 }
 
+#[cfg(feature = "gui")]
+impl Track {
+    /// Quantize and humanize settings. The buttons change the clip, and the
+    /// checkboxes apply the settings during playback instead.
+    fn ui_clip(&mut self, ui: &mut eframe::egui::Ui) {
+        let mut clip_edits = self.clip_edits;
+        let quantize = &mut self.quantize;
+        let mut is_changed = false;
+        let grid_name = Quantize::DIVISIONS
+            .iter()
+            .find(|(division, _)| Quantize::new_with(*division, quantize.strength) == *quantize)
+            .map_or("", |(_, name)| *name);
+        ComboBox::new(ui.next_auto_id(), "Grid")
+            .selected_text(grid_name)
+            .show_ui(ui, |ui| {
+                for (division, name) in Quantize::DIVISIONS {
+                    let grid = Quantize::new_with(division, quantize.strength);
+                    is_changed |= ui.selectable_value(quantize, grid, name).changed();
+                }
+            });
+        let mut strength = quantize.strength.0;
+        if ui
+            .add(Slider::new(&mut strength, 0.0..=1.0).text("Strength"))
+            .changed()
+        {
+            quantize.strength = Normal::from(strength);
+            is_changed = true;
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Quantize").clicked() {
+                let _ = self
+                    .commands
+                    .send(Command::QuantizeMidiClip(self.uid, *quantize));
+            }
+            let mut is_on = clip_edits.quantize.is_some();
+            is_changed |= ui.checkbox(&mut is_on, "On playback").changed();
+            if is_changed {
+                clip_edits.quantize = is_on.then_some(*quantize);
+            }
+        });
+        ui.separator();
+
+        let humanize = &mut self.humanize;
+        let mut is_changed = false;
+        let max_timing = MusicalTime::UNITS_IN_BEAT / 8;
+        let timing = Slider::new(&mut humanize.timing_units, 0..=max_timing);
+        is_changed |= ui
+            .add(timing.text("Timing"))
+            .on_hover_text("The most that a note moves either way, in units of a beat")
+            .changed();
+        let velocity = Slider::new(&mut humanize.velocity, 0..=32);
+        is_changed |= ui.add(velocity.text("Velocity")).changed();
+        ui.horizontal(|ui| {
+            if ui.button("Humanize").clicked() {
+                humanize.seed = rand::random();
+                let _ = self
+                    .commands
+                    .send(Command::HumanizeMidiClip(self.uid, *humanize));
+            }
+            let mut is_on = clip_edits.humanize.is_some();
+            is_changed |= ui.checkbox(&mut is_on, "On playback").changed();
+            if is_changed {
+                clip_edits.humanize = is_on.then_some(*humanize);
+            }
+        });
+        if clip_edits != self.clip_edits {
+            let _ = self
+                .commands
+                .send(Command::SetClipEdits(self.uid, clip_edits));
+        }
+    }
+
+    fn ui_groove(&mut self, ui: &mut eframe::egui::Ui) {
+        let groove = &mut self.groove;
+        let swing = Slider::new(&mut groove.swing, Groove::MIN_SWING..=Groove::MAX_SWING);
+        ui.add(swing.text("Swing %"))
+            .on_hover_text("How much of each pair of steps the first one takes");
+        ComboBox::new(ui.next_auto_id(), "Grid")
+            .selected_text(groove.grid.to_string())
+            .show_ui(ui, |ui| {
+                for grid in GrooveGrid::ALL {
+                    ui.selectable_value(&mut groove.grid, grid, grid.to_string());
+                }
+            });
+        ComboBox::new(ui.next_auto_id(), "Template")
+            .selected_text(groove.template.to_string())
+            .show_ui(ui, |ui| {
+                for template in GrooveTemplate::ALL {
+                    ui.selectable_value(&mut groove.template, template, template.to_string());
+                }
+            });
+    }
+}
+#[cfg(feature = "gui")]
 impl Displays for Track {
     fn ui(&mut self, ui: &mut eframe::egui::Ui) -> eframe::egui::Response {
-        let response = if self.is_master_track {
-            ui.heading("Master Track")
-        } else {
-            ui.heading(format!("Track {}", self.uid))
-        };
+        let response = ui.heading(RichText::new(&self.info.name).color(self.info.color32()));
+        if let Some(usage) = CpuMetrics::global().usage(ActorId::Track(self.uid)) {
+            ui.label(format!("CPU: {usage:.1}%"));
+        }
+        if let Some((uid, dropped)) = self.midi_loop {
+            ui.horizontal(|ui| {
+                ui.colored_label(
+                    Color32::YELLOW,
+                    format!("MIDI from entity {uid} loops back to it ({dropped} dropped)"),
+                );
+                if ui.button("Dismiss").clicked() {
+                    self.midi_loop = None;
+                }
+            });
+        }
         ui.horizontal_wrapped(|ui| {
             if !self.is_master_track {
-                if ui.button("Add Synth").clicked() {
-                    self.add_entity(ToySynth::default());
+                let mut name = self.info.name.clone();
+                if ui.text_edit_singleline(&mut name).changed() {
+                    self.set_name(name);
                 }
-                if ui.button("Add ToyInstrument").clicked() {
-                    self.add_entity(ToyInstrument::default());
+                let mut color = self.info.color;
+                if ui.color_edit_button_srgb(&mut color).changed() {
+                    self.set_color(color);
                 }
-                if ui.button("Add Busy Waiter").clicked() {
-                    self.add_entity(BusyWaiter::default());
+                ui_midi_channel(ui, "MIDI in", &mut self.midi_channel_filter);
+                let mut midi_input = self.midi_input;
+                ui.menu_button("Input…", |ui| {
+                    if midi_input.show(ui) {
+                        self.set_midi_input(midi_input);
+                    }
+                });
+                ui.end_row();
+
+                let mut is_armed = self.is_armed;
+                if ui.checkbox(&mut is_armed, "Arm").changed() {
+                    let _ = self.commands.send(Command::ArmTrack(self.uid, is_armed));
                 }
-                if ui.button("Add 1.0").clicked() {
-                    self.add_entity(AlwaysSame::new_with(1.0));
+                let mut is_batching = self.is_batching_generators;
+                if ui
+                    .checkbox(&mut is_batching, "Batch")
+                    .on_hover_text("Ask all instruments for audio with a single shared buffer")
+                    .changed()
+                {
+                    let _ = self
+                        .commands
+                        .send(Command::SetBatchGenerators(self.uid, is_batching));
                 }
-                if ui.button("Add 0.5").clicked() {
-                    self.add_entity(AlwaysSame::new_with(0.5));
+                let mut is_overdub = self.record_mode == RecordMode::Overdub;
+                if ui.checkbox(&mut is_overdub, "Overdub").changed() {
+                    self.record_mode = if is_overdub {
+                        RecordMode::Overdub
+                    } else {
+                        RecordMode::Replace
+                    };
                 }
-                if ui.button("Add -1.0").clicked() {
-                    self.add_entity(AlwaysSame::new_with(-1.0));
+                ui.menu_button("Groove…", |ui| self.ui_groove(ui));
+                if self.is_recording {
+                    ui.label(format!("Recording ({} frames)", self.recorded_frames.len()));
                 }
-                if ui.button("Add Arpeggiator").clicked() {
-                    self.add_entity(Arpeggiator::default());
+                if !self.midi_clip.is_empty() {
+                    ui.label(format!("Clip: {} events", self.midi_clip.len()));
+                    ui.menu_button("Clip…", |ui| self.ui_clip(ui));
                 }
-                if ui.button("Add Quietener").clicked() {
-                    self.add_entity(Quietener::default());
+                if !self.audio_clips.is_empty() {
+                    ui.label(format!("Audio clips: {}", self.audio_clips.len()));
                 }
-                if ui.button("Add Drone").clicked() {
-                    self.add_entity(DroneController::default());
+                if self.freeze_progress.is_some() {
+                    ui.label("Freezing...");
+                } else if self.frozen_clip.is_some() {
+                    if ui.button("Unfreeze").clicked() {
+                        self.frozen_clip = None;
+                    }
+                } else if ui
+                    .add_enabled(self.check_can_freeze().is_ok(), Button::new("Freeze"))
+                    .on_hover_text("Render this track and play back the result")
+                    .on_disabled_hover_text("Buses and tempo-mapped songs can't be frozen")
+                    .clicked()
+                {
+                    self.freeze(self.content_end());
                 }
                 ui.end_row();
             }
-
+        });
+        // The browser's entries can be dropped anywhere on this.
+        let (_, item) = ui.dnd_drop_zone::<BrowserItem>(Frame::default(), |ui| {
+            ui.weak("Drop entities from the browser here");
+        });
+        if let Some(item) = item {
+            match item.as_ref() {
+                BrowserItem::Entity(key) => {
+                    let _ = self
+                        .commands
+                        .send(Command::AddEntity(self.uid, key.clone()));
+                }
+                BrowserItem::Plugin(descriptor) => {
+                    let _ = self
+                        .commands
+                        .send(Command::AddPlugin(self.uid, descriptor.clone()));
+                }
+            }
+        }
+        ui.horizontal_wrapped(|ui| {
             let mut actor_uid_to_remove = None;
+            let mut selection_to_set = None;
+            let mut actor_to_move = None;
+            let mut effect_group_to_set = None;
+            let mut effect_mix_to_set = None;
+            let mut midi_effect_to_set = None;
             let mut link_to_add = None;
+            let mut route_to_add = None;
             let mut link_to_remove = None;
-            for &uid in self.ordered_actor_uids.iter() {
+            let mut transfer_to_set = None;
+            let mut midi_learn_target = None;
+            let mut midi_mapping_to_remove = None;
+            let actor_count = self.ordered_actor_uids.len();
+            for (position, &uid) in self.ordered_actor_uids.iter().enumerate() {
                 if let Some(actor) = self.actors.get_mut(&uid) {
                     ui.vertical(|ui| {
                         Frame::default()
@@ -577,79 +3259,237 @@ impl Displays for Track {
                             .show(ui, |ui| {
                                 actor.ui(ui);
                                 ui.label("");
-                                if ui.button("Remove").clicked() {
-                                    actor_uid_to_remove = Some(uid);
+                                ui.horizontal(|ui| {
+                                    let mut is_selected = self.selected_uids.contains(&uid);
+                                    if ui
+                                        .checkbox(&mut is_selected, "")
+                                        .on_hover_text("Select for bulk operations")
+                                        .changed()
+                                    {
+                                        selection_to_set = Some((uid, is_selected));
+                                    }
+                                    if ui.button("Remove").clicked() {
+                                        actor_uid_to_remove = Some(uid);
+                                    }
+                                    if ui
+                                        .add_enabled(position > 0, Button::new("<"))
+                                        .on_hover_text("Process earlier")
+                                        .clicked()
+                                    {
+                                        actor_to_move = Some((uid, position - 1));
+                                    }
+                                    if ui
+                                        .add_enabled(position + 1 < actor_count, Button::new(">"))
+                                        .on_hover_text("Process later")
+                                        .clicked()
+                                    {
+                                        actor_to_move = Some((uid, position + 1));
+                                    }
+                                });
+                                if actor.roles().transforms_audio {
+                                    let mut group =
+                                        self.effect_groups.get(&uid).copied().unwrap_or_default();
+                                    if ui
+                                        .add(
+                                            DragValue::new(&mut group)
+                                                .clamp_range(0..=8)
+                                                .prefix("Parallel group: "),
+                                        )
+                                        .on_hover_text("0 = serial")
+                                        .changed()
+                                    {
+                                        let group = (group != 0).then_some(group);
+                                        effect_group_to_set = Some((uid, group));
+                                    }
+                                    let mut wet = self
+                                        .effect_mixes
+                                        .get(&uid)
+                                        .map_or(Normal::maximum().0, |mix| mix.0);
+                                    if ui
+                                        .add(Slider::new(&mut wet, Normal::range()).text("Wet"))
+                                        .changed()
+                                    {
+                                        effect_mix_to_set = Some((uid, Normal::from(wet)));
+                                    }
+                                }
+                                let mut is_midi_effect = self.midi_effects.contains(&uid);
+                                if ui
+                                    .checkbox(&mut is_midi_effect, "MIDI effect")
+                                    .on_hover_text(
+                                        "Chain it with the track's other MIDI effects, in order",
+                                    )
+                                    .changed()
+                                {
+                                    midi_effect_to_set = Some((uid, is_midi_effect));
                                 }
 
-                                if !self.controllables.is_empty() {
+                                if !self.control_targets.is_empty() {
+                                    let targets = Arc::clone(&self.control_targets);
                                     let mut selected_index = 0;
                                     if ComboBox::new(ui.next_auto_id(), "Controls")
                                         .show_index(
                                             ui,
                                             &mut selected_index,
-                                            self.controllables.len(),
-                                            |i| self.controllables[i].name.clone(),
+                                            targets.len() + 1,
+                                            |i| {
+                                                if i == 0 {
+                                                    "None".to_string()
+                                                } else {
+                                                    targets[i - 1].1.clone()
+                                                }
+                                            },
                                         )
                                         .changed()
                                         && selected_index != 0
                                     {
-                                        let link = &self.controllables[selected_index];
-                                        link_to_add = Some((
-                                            uid,
-                                            ControlLink {
-                                                uid: link.uid,
-                                                param: link.param,
-                                            },
-                                        ));
+                                        let target = targets[selected_index - 1].0;
+                                        match target {
+                                            // Links within the track stay the
+                                            // track's business.
+                                            ControlTarget::Entity(track_uid, target_uid, param)
+                                                if track_uid == self.uid =>
+                                            {
+                                                link_to_add = Some((
+                                                    uid,
+                                                    ControlLink {
+                                                        uid: target_uid,
+                                                        param,
+                                                    },
+                                                ));
+                                            }
+                                            _ => {
+                                                route_to_add = Some(ControlRoute {
+                                                    source_track: self.uid,
+                                                    source: uid,
+                                                    target,
+                                                });
+                                            }
+                                        }
                                     };
                                 }
+                                let params: Vec<&ControllableItem> =
+                                    self.controllables.iter().filter(|c| c.uid == uid).collect();
+                                let param_name = |param: ControlIndex| {
+                                    params
+                                        .iter()
+                                        .find(|c| c.param == param)
+                                        .map(|c| c.name.clone())
+                                        .unwrap_or_default()
+                                };
+                                if let Some((_, param)) =
+                                    self.midi_learn_target.filter(|(u, _)| *u == uid)
+                                {
+                                    ui.label(format!(
+                                        "Move a MIDI control for {}",
+                                        param_name(param)
+                                    ));
+                                } else if !params.is_empty() {
+                                    let mut selected_index = 0;
+                                    if ComboBox::new(ui.next_auto_id(), "MIDI learn")
+                                        .show_index(ui, &mut selected_index, params.len() + 1, |i| {
+                                            if i == 0 {
+                                                "None".to_string()
+                                            } else {
+                                                params[i - 1].name.clone()
+                                            }
+                                        })
+                                        .changed()
+                                        && selected_index != 0
+                                    {
+                                        let param = params[selected_index - 1].param;
+                                        midi_learn_target = Some((uid, param));
+                                    }
+                                }
+                                for mapping in self.midi_mappings.iter().filter(|m| m.uid == uid) {
+                                    if ui
+                                        .button(format!(
+                                            "CC {} (ch {}): {}",
+                                            mapping.controller,
+                                            mapping.channel + 1,
+                                            param_name(mapping.param)
+                                        ))
+                                        .on_hover_text("Click to remove")
+                                        .clicked()
+                                    {
+                                        midi_mapping_to_remove = Some(*mapping);
+                                    }
+                                }
+
                                 if let Some(links) = self.control_links.get(&uid) {
                                     ui.label("This controls");
                                     for link in links {
-                                        if ui
-                                            .button(format!(
-                                                "Uid #{}, Param #{}",
-                                                link.uid, link.param
-                                            ))
-                                            .clicked()
-                                        {
-                                            link_to_remove = Some((uid, *link));
-                                        }
+                                        ui.horizontal(|ui| {
+                                            if ui
+                                                .button(format!(
+                                                    "Uid #{}, Param #{}",
+                                                    link.uid, link.param
+                                                ))
+                                                .clicked()
+                                            {
+                                                link_to_remove = Some((uid, *link));
+                                            }
+                                            let mut transfer =
+                                                self.control_transfer(uid, link.uid, link.param);
+                                            ui.menu_button("Curve…", |ui| {
+                                                if transfer.show(ui) {
+                                                    transfer_to_set = Some((uid, *link, transfer));
+                                                }
+                                            });
+                                        });
                                     }
                                 }
                             });
                     });
                 }
             }
-            if let Some(actor_uid_to_remove) = actor_uid_to_remove {
-                if let Some(links) = self.control_links.get(&actor_uid_to_remove) {
-                    let links = links.clone();
-                    for link in links {
-                        self.unlink(actor_uid_to_remove, link.uid, link.param);
-                    }
-                }
-
-                let keys: Vec<Uid> = self.control_links.keys().map(|k| *k).collect();
-                for source_uid in keys {
-                    if let Some(links) = self.control_links.get(&source_uid) {
-                        let links = links.clone();
-                        for link in links {
-                            if link.uid == actor_uid_to_remove {
-                                self.unlink(source_uid, link.uid, link.param);
-                            }
-                        }
-                    }
-                }
-                if let Some(actor) = self.actors.get(&actor_uid_to_remove) {
-                    actor.send_request(EntityRequest::Quit);
-                }
-                self.remove_actor(actor_uid_to_remove);
+            if midi_learn_target.is_some() {
+                self.midi_learn_target = midi_learn_target;
+            }
+            if let Some(mapping) = midi_mapping_to_remove {
+                self.midi_mappings.retain(|m| *m != mapping);
+            }
+            if let Some((uid, index)) = actor_to_move {
+                let _ = self
+                    .commands
+                    .send(Command::MoveEntity(self.uid, uid, index));
+            }
+            if let Some((uid, group)) = effect_group_to_set {
+                let _ = self
+                    .commands
+                    .send(Command::SetEffectGroup(self.uid, uid, group));
+            }
+            if let Some((uid, mix)) = effect_mix_to_set {
+                let _ = self
+                    .commands
+                    .send(Command::SetEffectMix(self.uid, uid, mix));
+            }
+            if let Some((uid, is_midi_effect)) = midi_effect_to_set {
+                let _ = self
+                    .commands
+                    .send(Command::SetMidiEffect(self.uid, uid, is_midi_effect));
+            }
+            if let Some(uid) = actor_uid_to_remove {
+                let _ = self.commands.send(Command::RemoveEntity(self.uid, uid));
+            }
+            if let Some((uid, is_selected)) = selection_to_set {
+                self.set_selected(uid, is_selected);
             }
             if let Some((source_uid, control_link)) = link_to_add {
-                let _ = self.link(source_uid, control_link.uid, control_link.param);
+                let _ = self
+                    .commands
+                    .send(Command::Link(self.uid, source_uid, control_link));
+            }
+            if let Some((source_uid, link, transfer)) = transfer_to_set {
+                let command = Command::SetControlTransfer(self.uid, source_uid, link, transfer);
+                let _ = self.commands.send(command);
+            }
+            if let Some(route) = route_to_add {
+                let _ = self.commands.send(Command::RouteControl(route));
             }
             if let Some((source_uid, control_link)) = link_to_remove {
-                self.unlink(source_uid, control_link.uid, control_link.param);
+                let _ = self
+                    .commands
+                    .send(Command::Unlink(self.uid, source_uid, control_link));
             }
             if let Some(mixer) = self.mixer.as_mut() {
                 mixer.ui(ui);
@@ -1,4 +1,5 @@
 use crossbeam_channel::{Receiver, Sender};
+use log::warn;
 
 pub trait ProvidesActorService<R, A> {
     /// Send side of channel for service requests.
@@ -14,7 +15,7 @@ pub trait ProvidesActorService<R, A> {
     ) -> Result<T, crossbeam_channel::RecvError> {
         let input_result = oper.recv(r);
         if let Err(e) = input_result {
-            eprintln!(
+            warn!(
                 "ProvidesActorService: While attempting to receive from {:?}: {}",
                 *r, e
             );
@@ -22,3 +23,12 @@ pub trait ProvidesActorService<R, A> {
         input_result
     }
 }
+
+/// An entity whose output lags its input, e.g., because it looks ahead.
+/// Register it with
+/// [EntityRegistry::register_latent](crate::registry::EntityRegistry::register_latent)
+/// so that tracks can compensate.
+pub trait ReportsLatency {
+    /// How many frames late the entity's output is.
+    fn latency(&self) -> usize;
+}
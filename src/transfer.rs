@@ -0,0 +1,125 @@
+#[cfg(feature = "gui")]
+use eframe::{
+    egui::{Checkbox, DragValue, Sense, Slider, Ui},
+    epaint::{pos2, vec2, Color32, Stroke},
+};
+use ensnare::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Reshapes a control signal on its way from a link's source to the target
+/// parameter. The default passes values through unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TransferFunction {
+    /// Bends the input: above 1.0, the output stays low longer; below 1.0,
+    /// it rises sooner.
+    pub exponent: f64,
+    /// Flip the curve, so that rising input lowers the parameter.
+    pub is_inverted: bool,
+    pub scale: f64,
+    pub offset: f64,
+    /// The lowest value that reaches the parameter.
+    pub min: f64,
+    /// The highest value that reaches the parameter.
+    pub max: f64,
+}
+impl Default for TransferFunction {
+    fn default() -> Self {
+        Self {
+            exponent: 1.0,
+            is_inverted: false,
+            scale: 1.0,
+            offset: 0.0,
+            min: 0.0,
+            max: 1.0,
+        }
+    }
+}
+impl TransferFunction {
+    /// Curves, then inverts, then scales and offsets, then limits.
+    pub fn apply(&self, value: ControlValue) -> ControlValue {
+        let mut value = value.0.clamp(0.0, 1.0).powf(self.exponent);
+        if self.is_inverted {
+            value = 1.0 - value;
+        }
+        // Not clamp(), which panics if the editor left min above max.
+        ControlValue(
+            (value * self.scale + self.offset)
+                .max(self.min)
+                .min(self.max),
+        )
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+}
+#[cfg(feature = "gui")]
+impl TransferFunction {
+    /// Edits the function, with a graph of input against output. Returns
+    /// true if anything changed.
+    pub fn show(&mut self, ui: &mut Ui) -> bool {
+        let mut changed = ui
+            .add(
+                Slider::new(&mut self.exponent, 0.1..=10.0)
+                    .logarithmic(true)
+                    .text("Curve"),
+            )
+            .changed();
+        changed |= ui
+            .add(Checkbox::new(&mut self.is_inverted, "Invert"))
+            .changed();
+        ui.horizontal(|ui| {
+            changed |= ui
+                .add(
+                    DragValue::new(&mut self.scale)
+                        .speed(0.01)
+                        .prefix("Scale: "),
+                )
+                .changed();
+            changed |= ui
+                .add(
+                    DragValue::new(&mut self.offset)
+                        .speed(0.01)
+                        .prefix("Offset: "),
+                )
+                .changed();
+        });
+        ui.horizontal(|ui| {
+            changed |= ui
+                .add(
+                    DragValue::new(&mut self.min)
+                        .clamp_range(0.0..=self.max)
+                        .speed(0.01),
+                )
+                .changed();
+            ui.label("to");
+            changed |= ui
+                .add(
+                    DragValue::new(&mut self.max)
+                        .clamp_range(self.min..=1.0)
+                        .speed(0.01),
+                )
+                .changed();
+        });
+        if ui.button("Reset").clicked() {
+            *self = Self::default();
+            changed = true;
+        }
+
+        let (response, painter) = ui.allocate_painter(vec2(96.0, 96.0), Sense::hover());
+        let rect = response.rect;
+        painter.rect_filled(rect, 2.0, ui.visuals().extreme_bg_color);
+        let points = (0..=32)
+            .map(|i| {
+                let input = i as f64 / 32.0;
+                let output = self.apply(ControlValue(input)).0;
+                pos2(
+                    rect.left() + input as f32 * rect.width(),
+                    rect.bottom() - output as f32 * rect.height(),
+                )
+            })
+            .collect();
+        painter.line(points, Stroke::new(1.5, Color32::LIGHT_BLUE));
+        changed
+    }
+}
@@ -1,13 +1,141 @@
 use ensnare_v1::prelude::*;
+use crate::{
+    actions::TrackAction,
+    buffer_pool::BufferPool,
+    channels::interleave,
+    executor::{join_on_drop, join_until},
+    resampler::{resample, ResampleQuality},
+};
 use anyhow::anyhow;
+use crossbeam_channel::{Select, Sender};
 use ensnare::{prelude::*, traits::ProvidesService, types::CrossbeamChannel};
 use ensnare_services::prelude::*;
-use std::path::PathBuf;
+use log::{error, info};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs::File,
+    io::BufWriter,
+    path::PathBuf,
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// How each sample is stored in the file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BitDepth {
+    Int16,
+    Int24,
+    #[default]
+    Float32,
+}
+impl Display for BitDepth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            BitDepth::Int16 => "16-bit",
+            BitDepth::Int24 => "24-bit",
+            BitDepth::Float32 => "32-bit float",
+        })
+    }
+}
+impl BitDepth {
+    pub const ALL: [BitDepth; 3] = [Self::Int16, Self::Int24, Self::Float32];
+
+    fn bits(&self) -> u16 {
+        match self {
+            BitDepth::Int16 => 16,
+            BitDepth::Int24 => 24,
+            BitDepth::Float32 => 32,
+        }
+    }
+}
+
+/// The kind of file a capture goes to. The encoded ones are encoded on the
+/// writer's thread, so they cost the audio thread nothing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ExportContainer {
+    #[default]
+    Wav,
+    /// Lossless. Integer samples only, so 32-bit float is stored as 24-bit.
+    Flac,
+    /// Lossy, and ignores the bit depth.
+    #[cfg(feature = "vorbis")]
+    OggVorbis,
+}
+impl Display for ExportContainer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ExportContainer::Wav => "WAV",
+            ExportContainer::Flac => "FLAC",
+            #[cfg(feature = "vorbis")]
+            ExportContainer::OggVorbis => "Ogg Vorbis",
+        })
+    }
+}
+impl ExportContainer {
+    pub const ALL: &'static [ExportContainer] = &[
+        Self::Wav,
+        Self::Flac,
+        #[cfg(feature = "vorbis")]
+        Self::OggVorbis,
+    ];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportContainer::Wav => "wav",
+            ExportContainer::Flac => "flac",
+            #[cfg(feature = "vorbis")]
+            ExportContainer::OggVorbis => "ogg",
+        }
+    }
+
+    /// Whether the file stores samples at [ExportFormat::bit_depth].
+    pub fn has_bit_depth(&self) -> bool {
+        match self {
+            ExportContainer::Wav | ExportContainer::Flac => true,
+            #[cfg(feature = "vorbis")]
+            ExportContainer::OggVorbis => false,
+        }
+    }
+}
+
+/// The format of the capture file, which can differ from what the engine
+/// produces.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ExportFormat {
+    pub container: ExportContainer,
+    pub bit_depth: BitDepth,
+    /// Add TPDF dither before rounding to an integer bit depth.
+    pub is_dithered: bool,
+    /// In Hz. Without one, the file has the engine's rate.
+    pub sample_rate: Option<usize>,
+}
+impl ExportFormat {
+    /// The rates the UI offers besides the engine's own.
+    pub const SAMPLE_RATES: [usize; 4] = [44100, 48000, 88200, 96000];
+}
 
 #[derive(Debug)]
 pub enum WavWriterInput {
+    /// Use this format for files started by later
+    /// [WavWriterInput::Reset]s.
+    SetFormat(ExportFormat),
+    /// Start a new file with the given path, the sample rate of the frames
+    /// that will arrive, and channel count. The path's extension is replaced
+    /// with the [ExportContainer]'s.
     Reset(PathBuf, SampleRate, u8),
-    Frames(Vec<StereoSample>),
+    /// Finalize the file that [WavWriterInput::Reset] started. Frames are
+    /// dropped until the next one.
+    Finish,
+    /// The main output pair, then any others, as in
+    /// [AudioAction](crate::actions::AudioAction). The file gets as many of
+    /// their channels as [WavWriterInput::Reset] asked for.
+    Frames(Vec<StereoSample>, Vec<Vec<StereoSample>>),
+    /// Start writing the given track's [TrackAction::Frames] to a file of
+    /// its own.
+    AddStem(TrackUid, PathBuf, SampleRate),
+    /// Finish the given track's file.
+    RemoveStem(TrackUid),
     Quit,
 }
 
@@ -20,79 +148,215 @@ pub enum WavWriterEvent {
 pub struct WavWriterService {
     inputs: CrossbeamChannel<WavWriterInput>,
     events: CrossbeamChannel<WavWriterEvent>,
+
+    /// Receives the output of the tracks we're writing stems for.
+    track_actions: CrossbeamChannel<TrackAction>,
+
+    thread: Option<JoinHandle<()>>,
 }
 impl Default for WavWriterService {
     fn default() -> Self {
         Self::new()
     }
 }
+impl Drop for WavWriterService {
+    fn drop(&mut self) {
+        let _ = self.inputs.sender.try_send(WavWriterInput::Quit);
+        join_on_drop(self.thread.take(), Self::DROP_TIMEOUT, "The WAV writer");
+    }
+}
 impl WavWriterService {
+    /// How long dropping the service waits for it to finalize its files.
+    const DROP_TIMEOUT: Duration = Duration::from_secs(2);
+
     pub fn new() -> Self {
-        let r = Self {
+        let mut r = Self {
             inputs: Default::default(),
             events: Default::default(),
+            track_actions: Default::default(),
+            thread: Default::default(),
         };
 
-        r.start_thread();
+        r.thread = Some(r.start_thread());
         r
     }
 
-    fn start_thread(&self) {
+    /// Tells the writer to finalize its files, and waits up to the timeout
+    /// for it to finish.
+    pub fn quit_and_join(&mut self, timeout: Duration) -> anyhow::Result<()> {
+        self.send_input(WavWriterInput::Quit);
+        if join_until(self.thread.take(), Instant::now() + timeout) > 0 {
+            return Err(anyhow!("The WAV writer didn't finish within {timeout:?}"));
+        }
+        Ok(())
+    }
+
+    fn create_writer(
+        path_buf: &PathBuf,
+        sample_rate: SampleRate,
+        channel_count: u8,
+        bit_depth: BitDepth,
+    ) -> anyhow::Result<hound::WavWriter<BufWriter<File>>> {
+        hound::WavWriter::create(
+            path_buf.as_os_str(),
+            hound::WavSpec {
+                channels: channel_count as u16,
+                sample_rate: sample_rate.0 as u32,
+                bits_per_sample: bit_depth.bits(),
+                sample_format: match bit_depth {
+                    BitDepth::Float32 => hound::SampleFormat::Float,
+                    _ => hound::SampleFormat::Int,
+                },
+            },
+        )
+        .map_err(|e| anyhow!("Error while creating file: {:?}", e))
+    }
+
+    /// The index of the first frame that isn't silent in any pair.
+    fn first_sound(frames: &[StereoSample], other_pairs: &[Vec<StereoSample>]) -> usize {
+        let is_sound = |i: usize| {
+            frames[i] != StereoSample::SILENCE
+                || other_pairs
+                    .iter()
+                    .any(|p| p.get(i).is_some_and(|f| *f != StereoSample::SILENCE))
+        };
+        (0..frames.len()).find(|&i| is_sound(i)).unwrap_or(frames.len())
+    }
+
+    fn start_thread(&self) -> JoinHandle<()> {
         let receiver = self.inputs.receiver.clone();
+        let track_action_receiver = self.track_actions.receiver.clone();
         let sender = self.events.sender.clone();
-        let mut writer = None;
+        let mut format = ExportFormat::default();
+        let mut writer: Option<CaptureFile> = None;
+        let mut stem_writers = HashMap::default();
 
         // Nice touch: don't write to the file until our first non-silent sample.
         let mut has_lead_in_ended = false;
 
         std::thread::spawn(move || {
-            while let Ok(input) = receiver.recv() {
-                match input {
-                    WavWriterInput::Reset(path_buf, new_sample_rate, new_channel_count) => {
-                        has_lead_in_ended = false;
-                        match hound::WavWriter::create(
-                            path_buf.as_os_str(),
-                            hound::WavSpec {
-                                channels: new_channel_count as u16,
-                                sample_rate: new_sample_rate.0 as u32,
-                                bits_per_sample: 32,
-                                sample_format: hound::SampleFormat::Float,
-                            },
-                        ) {
-                            Ok(ww) => {
-                                writer = Some(ww);
+            let mut sel = Select::default();
+            let input_index = sel.recv(&receiver);
+            let track_action_index = sel.recv(&track_action_receiver);
+
+            loop {
+                let operation = sel.select();
+                match operation.index() {
+                    index if index == input_index => {
+                        let Ok(input) = Self::recv_operation(operation, &receiver) else {
+                            break;
+                        };
+                        match input {
+                            WavWriterInput::SetFormat(new_format) => format = new_format,
+                            WavWriterInput::Reset(path_buf, new_sample_rate, new_channel_count) => {
+                                has_lead_in_ended = false;
+                                if let Some(writer) = writer.take() {
+                                    if let Err(e) = writer.finalize() {
+                                        let _ = sender.try_send(WavWriterEvent::Err(e));
+                                    }
+                                }
+                                match CaptureFile::new_with(
+                                    &path_buf,
+                                    new_sample_rate,
+                                    new_channel_count,
+                                    format,
+                                ) {
+                                    Ok(capture_file) => {
+                                        info!("Capturing to {}", path_buf.display());
+                                        writer = Some(capture_file);
+                                    }
+                                    Err(e) => {
+                                        let _ = sender.try_send(WavWriterEvent::Err(e));
+                                    }
+                                }
                             }
-                            Err(e) => {
-                                writer = None;
-                                let _ = sender.try_send(WavWriterEvent::Err(anyhow!(
-                                    "Error while creating file: {:?}",
-                                    e
-                                )));
+                            WavWriterInput::Finish => {
+                                if let Some(writer) = writer.take() {
+                                    if let Err(e) = writer.finalize() {
+                                        let _ = sender.try_send(WavWriterEvent::Err(e));
+                                    }
+                                    info!("Finished capturing");
+                                }
                             }
-                        }
-                    }
-                    WavWriterInput::Frames(frames) => {
-                        if let Some(writer) = writer.as_mut() {
-                            frames.iter().for_each(|&f| {
-                                if !has_lead_in_ended && f != StereoSample::SILENCE {
-                                    has_lead_in_ended = true;
+                            WavWriterInput::Frames(frames, other_pairs) => {
+                                if let Some(writer) = writer.as_mut() {
+                                    let start = if has_lead_in_ended {
+                                        0
+                                    } else {
+                                        Self::first_sound(&frames, &other_pairs)
+                                    };
+                                    has_lead_in_ended = start < frames.len();
+                                    let pairs: Vec<_> = other_pairs
+                                        .iter()
+                                        .map(|p| p.get(start..).unwrap_or_default())
+                                        .collect();
+                                    writer.write(&frames[start..], &pairs);
                                 }
-                                if has_lead_in_ended {
-                                    let _ = writer.write_sample(f.0 .0 as f32);
-                                    let _ = writer.write_sample(f.1 .0 as f32);
+                                let pool = BufferPool::global();
+                                pool.recycle(frames);
+                                other_pairs.into_iter().for_each(|p| pool.recycle(p));
+                            }
+                            WavWriterInput::AddStem(track_uid, path_buf, sample_rate) => {
+                                match Self::create_writer(
+                                    &path_buf,
+                                    sample_rate,
+                                    2,
+                                    BitDepth::Float32,
+                                ) {
+                                    Ok(ww) => {
+                                        let path = path_buf.display();
+                                        info!("Writing track {track_uid}'s stem to {path}");
+                                        stem_writers.insert(track_uid, ww);
+                                    }
+                                    Err(e) => {
+                                        let _ = sender.try_send(WavWriterEvent::Err(e));
+                                    }
                                 }
-                            })
+                            }
+                            WavWriterInput::RemoveStem(track_uid) => {
+                                if let Some(stem_writer) = stem_writers.remove(&track_uid) {
+                                    let _ = stem_writer.finalize();
+                                }
+                            }
+                            WavWriterInput::Quit => {
+                                if let Some(writer) = writer {
+                                    if let Err(e) = writer.finalize() {
+                                        let _ = sender.try_send(WavWriterEvent::Err(e));
+                                    }
+                                }
+                                for (_, stem_writer) in stem_writers.drain() {
+                                    let _ = stem_writer.finalize();
+                                }
+                                break;
+                            }
                         }
                     }
-                    WavWriterInput::Quit => {
-                        if let Some(writer) = writer {
-                            let _ = writer.finalize();
+                    index if index == track_action_index => {
+                        // Stems skip the lead-in trimming so that they all
+                        // line up when imported together.
+                        if let Ok(TrackAction::Frames(track_uid, frames)) =
+                            Self::recv_operation(operation, &track_action_receiver)
+                        {
+                            if let Some(stem_writer) = stem_writers.get_mut(&track_uid) {
+                                for f in frames.iter() {
+                                    let _ = stem_writer.write_sample(f.0 .0 as f32);
+                                    let _ = stem_writer.write_sample(f.1 .0 as f32);
+                                }
+                            }
+                            BufferPool::global().recycle(frames);
                         }
-                        break;
                     }
+                    index => error!("WavWriterService: unexpected select index {index}"),
                 }
             }
-        });
+        })
+    }
+
+    /// Subscribe this to a track with
+    /// [TrackRequest::SubscribeFrames](crate::track::TrackRequest::SubscribeFrames)
+    /// after adding a stem for it.
+    pub(crate) fn track_action_sender(&self) -> &Sender<TrackAction> {
+        &self.track_actions.sender
     }
 }
 impl ProvidesService<WavWriterInput, WavWriterEvent> for WavWriterService {
@@ -104,3 +368,278 @@ impl ProvidesService<WavWriterInput, WavWriterEvent> for WavWriterService {
         &self.inputs.sender
     }
 }
+
+/// The main output file, and what it takes to write it in its
+/// [ExportFormat].
+struct CaptureFile {
+    output: CaptureOutput,
+    channel_count: usize,
+    encoder: SampleEncoder,
+    /// When the file's sample rate differs from the engine's, the capture
+    /// waits here until the file is finished, because the resampler needs
+    /// to see the frames on both sides of each one.
+    conversion: Option<Conversion>,
+}
+struct Conversion {
+    from: SampleRate,
+    to: SampleRate,
+    /// The main pair, then the others.
+    pairs: Vec<Vec<StereoSample>>,
+}
+impl CaptureFile {
+    fn new_with(
+        path_buf: &PathBuf,
+        sample_rate: SampleRate,
+        channel_count: u8,
+        format: ExportFormat,
+    ) -> anyhow::Result<Self> {
+        let file_sample_rate = format.sample_rate.map_or(sample_rate, SampleRate);
+        let output = CaptureOutput::new_with(path_buf, file_sample_rate, channel_count, format)?;
+        let conversion = (file_sample_rate.0 != sample_rate.0).then(|| Conversion {
+            from: sample_rate,
+            to: file_sample_rate,
+            pairs: vec![Vec::default(); (channel_count as usize).div_ceil(2)],
+        });
+        Ok(Self {
+            output,
+            channel_count: channel_count as usize,
+            encoder: SampleEncoder::new_with(format),
+            conversion,
+        })
+    }
+
+    /// `pairs` holds the pairs after the main one.
+    fn write(&mut self, main: &[StereoSample], pairs: &[&[StereoSample]]) {
+        if let Some(conversion) = self.conversion.as_mut() {
+            for (i, buffer) in conversion.pairs.iter_mut().enumerate() {
+                match i.checked_sub(1).and_then(|i| pairs.get(i)) {
+                    None if i == 0 => buffer.extend_from_slice(main),
+                    Some(pair) if pair.len() == main.len() => buffer.extend_from_slice(pair),
+                    _ => buffer.resize(buffer.len() + main.len(), StereoSample::SILENCE),
+                }
+            }
+            return;
+        }
+        let samples = interleave(main, pairs, self.channel_count);
+        let _ = self.output.write(&mut self.encoder, samples);
+    }
+
+    fn finalize(mut self) -> anyhow::Result<()> {
+        if let Some(conversion) = self.conversion.take() {
+            let converted: Vec<_> = conversion
+                .pairs
+                .iter()
+                .map(|p| resample(p, conversion.from, conversion.to, ResampleQuality::Sinc))
+                .collect();
+            if let Some((main, others)) = converted.split_first() {
+                let others: Vec<_> = others.iter().map(|p| p.as_slice()).collect();
+                let samples = interleave(main, &others, self.channel_count);
+                self.output.write(&mut self.encoder, samples)?;
+            }
+        }
+        self.output.finalize()
+    }
+}
+
+/// Where a [CaptureFile]'s samples end up.
+enum CaptureOutput {
+    Wav(hound::WavWriter<BufWriter<File>>),
+    /// FLAC is encoded all at once when the file is finished, so the
+    /// samples wait here until then.
+    Flac {
+        path_buf: PathBuf,
+        sample_rate: SampleRate,
+        channel_count: usize,
+        bits: usize,
+        samples: Vec<i32>,
+    },
+    #[cfg(feature = "vorbis")]
+    OggVorbis {
+        encoder: vorbis_rs::VorbisEncoder<File>,
+        channel_count: usize,
+    },
+}
+impl CaptureOutput {
+    fn new_with(
+        path_buf: &PathBuf,
+        sample_rate: SampleRate,
+        channel_count: u8,
+        format: ExportFormat,
+    ) -> anyhow::Result<Self> {
+        let path_buf = path_buf.with_extension(format.container.extension());
+        Ok(match format.container {
+            ExportContainer::Wav => Self::Wav(WavWriterService::create_writer(
+                &path_buf,
+                sample_rate,
+                channel_count,
+                format.bit_depth,
+            )?),
+            ExportContainer::Flac => {
+                // Fail now rather than after the whole take is recorded.
+                File::create(&path_buf)?;
+                Self::Flac {
+                    path_buf,
+                    sample_rate,
+                    channel_count: channel_count as usize,
+                    bits: match format.bit_depth {
+                        BitDepth::Int16 => 16,
+                        BitDepth::Int24 | BitDepth::Float32 => 24,
+                    },
+                    samples: Vec::default(),
+                }
+            }
+            #[cfg(feature = "vorbis")]
+            ExportContainer::OggVorbis => {
+                let sample_rate = std::num::NonZeroU32::new(sample_rate.0 as u32)
+                    .ok_or_else(|| anyhow!("The sample rate is zero"))?;
+                let channel_count = std::num::NonZeroU8::new(channel_count)
+                    .ok_or_else(|| anyhow!("The channel count is zero"))?;
+                let file = File::create(&path_buf)?;
+                let encoder =
+                    vorbis_rs::VorbisEncoderBuilder::new(sample_rate, channel_count, file)?
+                        .build()?;
+                Self::OggVorbis {
+                    encoder,
+                    channel_count: channel_count.get() as usize,
+                }
+            }
+        })
+    }
+
+    /// Takes interleaved samples.
+    fn write(
+        &mut self,
+        encoder: &mut SampleEncoder,
+        samples: impl Iterator<Item = Sample>,
+    ) -> anyhow::Result<()> {
+        match self {
+            CaptureOutput::Wav(writer) => {
+                for sample in samples {
+                    encoder.write(writer, sample)?;
+                }
+            }
+            CaptureOutput::Flac {
+                bits,
+                samples: buffer,
+                ..
+            } => {
+                let bits = *bits as u32;
+                buffer.extend(samples.map(|sample| encoder.quantize(sample.0, bits)));
+            }
+            #[cfg(feature = "vorbis")]
+            CaptureOutput::OggVorbis {
+                encoder,
+                channel_count,
+            } => {
+                // The encoder wants a block per channel.
+                let mut planar = vec![Vec::default(); *channel_count];
+                for (i, sample) in samples.enumerate() {
+                    planar[i % *channel_count].push(sample.0 as f32);
+                }
+                encoder.encode_audio_block(&planar)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finalize(self) -> anyhow::Result<()> {
+        match self {
+            CaptureOutput::Wav(writer) => Ok(writer.finalize()?),
+            CaptureOutput::Flac {
+                path_buf,
+                sample_rate,
+                channel_count,
+                bits,
+                samples,
+            } => {
+                use flacenc::{component::BitRepr, error::Verify};
+
+                let config = flacenc::config::Encoder::default()
+                    .into_verified()
+                    .map_err(|(_, e)| anyhow!("Invalid FLAC encoder settings: {e:?}"))?;
+                let source = flacenc::source::MemSource::from_samples(
+                    &samples,
+                    channel_count,
+                    bits,
+                    sample_rate.0,
+                );
+                let stream =
+                    flacenc::encode_with_fixed_block_size(&config, source, config.block_size)
+                        .map_err(|e| anyhow!("Error while encoding FLAC: {e:?}"))?;
+                let mut sink = flacenc::bitsink::ByteSink::new();
+                stream
+                    .write(&mut sink)
+                    .map_err(|e| anyhow!("Error while encoding FLAC: {e:?}"))?;
+                Ok(std::fs::write(path_buf, sink.as_slice())?)
+            }
+            #[cfg(feature = "vorbis")]
+            CaptureOutput::OggVorbis { encoder, .. } => {
+                encoder.finish()?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Turns samples into the file's bit depth.
+struct SampleEncoder {
+    bit_depth: BitDepth,
+    /// Present if dithering.
+    dither: Option<TpdfDither>,
+}
+impl SampleEncoder {
+    fn new_with(format: ExportFormat) -> Self {
+        Self {
+            bit_depth: format.bit_depth,
+            dither: format.is_dithered.then(TpdfDither::default),
+        }
+    }
+
+    fn write(
+        &mut self,
+        writer: &mut hound::WavWriter<BufWriter<File>>,
+        sample: Sample,
+    ) -> hound::Result<()> {
+        match self.bit_depth {
+            BitDepth::Int16 => writer.write_sample(self.quantize(sample.0, 16) as i16),
+            BitDepth::Int24 => writer.write_sample(self.quantize(sample.0, 24)),
+            BitDepth::Float32 => writer.write_sample(sample.0 as f32),
+        }
+    }
+
+    fn quantize(&mut self, value: f64, bits: u32) -> i32 {
+        let scale = (1i64 << (bits - 1)) as f64;
+        let mut value = value * scale;
+        if let Some(dither) = self.dither.as_mut() {
+            value += dither.next_lsb();
+        }
+        value.round().clamp(-scale, scale - 1.0) as i32
+    }
+}
+
+/// Triangular noise one least-significant bit wide on each side, which
+/// turns rounding distortion into a steady, quiet hiss.
+struct TpdfDither {
+    /// Xorshift state. Any nonzero value works.
+    state: u64,
+}
+impl Default for TpdfDither {
+    fn default() -> Self {
+        Self {
+            state: 0x2545_f491_4f6c_dd1d,
+        }
+    }
+}
+impl TpdfDither {
+    fn next_lsb(&mut self) -> f64 {
+        self.next_unit() - self.next_unit()
+    }
+
+    /// A uniform random number from 0.0 to 1.0.
+    fn next_unit(&mut self) -> f64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
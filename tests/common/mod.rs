@@ -0,0 +1,126 @@
+//! Helpers for building an [Engine] without a UI or an audio device, and
+//! rendering its output on the test's thread.
+#![allow(dead_code)]
+
+use ensnare::prelude::*;
+use spike_actor_system::{
+    command::Command,
+    engine::Engine,
+    executor::{Executor, SyncExecutor},
+    registry::EntityRegistry,
+    track::TrackRequest,
+    traits::ProvidesActorService,
+};
+use std::sync::Arc;
+
+/// An [Engine] whose actors run only when the test asks them to.
+pub struct TestEngine {
+    pub engine: Engine,
+    executor: SyncExecutor,
+}
+impl Default for TestEngine {
+    fn default() -> Self {
+        let executor = SyncExecutor::default();
+        Self {
+            engine: Engine::new_with(Executor::Synchronous(executor.clone())),
+            executor,
+        }
+    }
+}
+impl TestEngine {
+    /// An engine whose actors handle their messages in an order that depends
+    /// on the seed. See [SyncExecutor::new_shuffled].
+    pub fn shuffled(seed: u64) -> Self {
+        let executor = SyncExecutor::new_shuffled(seed);
+        Self {
+            engine: Engine::new_with(Executor::Synchronous(executor.clone())),
+            executor,
+        }
+    }
+
+    /// An engine with the entities and plugin formats of the given registry.
+    pub fn with_registry(registry: Arc<EntityRegistry>) -> Self {
+        let executor = SyncExecutor::default();
+        Self {
+            engine: Engine::new_with_registry(Executor::Synchronous(executor.clone()), registry),
+            executor,
+        }
+    }
+
+    pub fn block_size(mut self, block_size: usize) -> Self {
+        self.engine.set_block_size(block_size);
+        self
+    }
+
+    /// Adds a track at full level in the master mixer, and returns a builder
+    /// for its contents.
+    pub fn track(&mut self) -> TestTrack {
+        let uid = self.engine.create_track().unwrap();
+        self.settle();
+        self.engine
+            .execute(Command::SetMixerLevel(uid, Normal::maximum()))
+            .unwrap();
+        TestTrack { engine: self, uid }
+    }
+
+    /// Lets every actor handle whatever has been sent to it.
+    pub fn settle(&self) {
+        self.executor.run_until_idle();
+    }
+
+    /// Runs the given number of generation cycles, and returns everything
+    /// the master track produced.
+    pub fn render_blocks(&mut self, count: usize) -> Vec<StereoSample> {
+        self.settle();
+        let frames = self
+            .engine
+            .render(count * self.engine.block_size())
+            .unwrap();
+        assert_eq!(frames.len(), count * self.engine.block_size());
+        frames
+    }
+
+    pub fn send(&self, track_uid: TrackUid, request: TrackRequest) {
+        self.engine.track(track_uid).unwrap().send_request(request);
+    }
+}
+
+/// Adds entities to one track of a [TestEngine].
+pub struct TestTrack<'a> {
+    engine: &'a mut TestEngine,
+    pub uid: TrackUid,
+}
+impl TestTrack<'_> {
+    /// Adds an entity by its registry key, and returns its uid.
+    pub fn entity(&mut self, key: &str) -> Uid {
+        let track = self.engine.engine.track(self.uid).unwrap();
+        track.add_entity_by_key(key).unwrap()
+    }
+
+    /// Adds a quietener, which scales its input by the given factor.
+    pub fn quietener(&mut self, factor: f64) -> Uid {
+        let uid = self.entity("quietener");
+        let track = self.engine.engine.track(self.uid).unwrap();
+        track
+            .set_param(uid, ControlIndex(0), ControlValue(factor))
+            .unwrap();
+        uid
+    }
+
+    pub fn send(&self, request: TrackRequest) {
+        self.engine.send(self.uid, request);
+    }
+}
+
+/// Asserts that every frame has the given value in both channels, give or
+/// take rounding.
+pub fn assert_all_frames(frames: &[StereoSample], expected: f64) {
+    for (i, frame) in frames.iter().enumerate() {
+        for sample in [frame.0, frame.1] {
+            assert!(
+                (sample.0 - expected).abs() < 1e-9,
+                "frame {i} is {frame:?}, but should be {expected}"
+            );
+        }
+    }
+}
@@ -0,0 +1,1669 @@
+//! End-to-end tests of the actor pipeline: entities to tracks to the master
+//! track's mixer to the engine.
+
+mod common;
+
+use common::{assert_all_frames, TestEngine};
+use crossbeam_channel::{unbounded, TryRecvError};
+use ensnare::{prelude::*, traits::ProvidesService, util::MidiUtils};
+use spike_actor_system::{
+    actions::TrackAction,
+    browser::{BrowserCategory, BrowserItem, EntityBrowser},
+    channels::ChannelLayout,
+    clip::{ClipEdits, Humanize, MidiClip, Quantize},
+    command::Command,
+    engine::{
+        ControlRoute, ControlTarget, Engine, EngineService, EngineServiceEvent, EngineServiceInput,
+    },
+    entity::{EntityActor, WorkSchedule},
+    executor::Executor,
+    groove::{Groove, GrooveGrid},
+    inject::Injection,
+    limits::{ActorLimits, LimitReached},
+    metronome::{ClickOutput, ClickSound},
+    midi_input::MidiInputProcessor,
+    mixer::{CrossfadeCurve, CrossfadeGroup},
+    plugin::{PluginDescriptor, PluginFormat, PluginInstance},
+    project::{EntityProject, Project},
+    punch::PunchRegion,
+    registry::EntityRegistry,
+    remote::{RemoteControlService, RemoteUpdate},
+    stress::{run_stress_test, StressConfig},
+    tempo::TempoPoint,
+    track::TrackRequest,
+    transfer::TransferFunction,
+    wav_writer::{ExportContainer, ExportFormat, WavWriterInput, WavWriterService},
+};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+#[test]
+fn empty_project_is_silent() {
+    let mut e = TestEngine::default();
+    assert_all_frames(&e.render_blocks(4), 0.0);
+}
+
+#[test]
+fn instrument_reaches_master() {
+    let mut e = TestEngine::default();
+    e.track().entity("always-1.0");
+    assert_all_frames(&e.render_blocks(4), 1.0);
+}
+
+#[test]
+fn instruments_on_one_track_add_up() {
+    let mut e = TestEngine::default();
+    let mut track = e.track();
+    track.entity("always-1.0");
+    track.entity("always-0.5");
+    assert_all_frames(&e.render_blocks(2), 1.5);
+}
+
+#[test]
+fn batched_instruments_add_up_the_same_way() {
+    let mut e = TestEngine::default();
+    let mut track = e.track();
+    track.entity("always-1.0");
+    track.entity("always-0.5");
+    track.quietener(0.5);
+    track.send(TrackRequest::SetBatchGenerators(true));
+    assert_all_frames(&e.render_blocks(3), 0.75);
+}
+
+#[test]
+fn master_mixes_tracks_by_relative_level() {
+    let mut e = TestEngine::default();
+    e.track().entity("always-1.0");
+    e.track().entity("always-0.5");
+    assert_all_frames(&e.render_blocks(2), 0.75);
+}
+
+#[test]
+fn master_mixer_levels_and_mutes_apply_to_the_right_track() {
+    let mut e = TestEngine::default();
+    let loud = {
+        let mut track = e.track();
+        track.entity("always-1.0");
+        track.uid
+    };
+    let quiet = {
+        let mut track = e.track();
+        track.entity("always-0.5");
+        track.uid
+    };
+
+    // The loud track gets three quarters of the mix.
+    e.engine
+        .execute(Command::SetMixerLevel(quiet, Normal::from(1.0 / 3.0)))
+        .unwrap();
+    assert_all_frames(&e.render_blocks(2), 1.0 * 0.75 + 0.5 * 0.25);
+
+    // Muting doesn't give the other tracks a bigger share.
+    e.engine.execute(Command::SetMixerMute(loud, true)).unwrap();
+    assert_all_frames(&e.render_blocks(2), 0.5 * 0.25);
+
+    e.engine.undo().unwrap();
+    assert_all_frames(&e.render_blocks(2), 1.0 * 0.75 + 0.5 * 0.25);
+}
+
+#[test]
+fn effects_process_the_track_buffer_in_series() {
+    let mut e = TestEngine::default();
+    let mut track = e.track();
+    track.entity("always-1.0");
+    track.quietener(0.5);
+    track.quietener(0.5);
+    assert_all_frames(&e.render_blocks(2), 0.25);
+}
+
+#[test]
+fn effect_order_decides_parallel_groups() {
+    let mut e = TestEngine::default();
+    let mut track = e.track();
+    track.entity("always-1.0");
+    let first = track.quietener(0.5);
+    let second = track.quietener(0.5);
+    let third = track.quietener(0.5);
+    track.send(TrackRequest::SetEffectGroup(second, Some(1)));
+    track.send(TrackRequest::SetEffectGroup(third, Some(1)));
+    let track_uid = track.uid;
+
+    // The first halves the signal, and then the other two each halve that,
+    // side by side, and their outputs are averaged.
+    assert_all_frames(&e.render_blocks(2), 0.25);
+
+    // Moving the ungrouped effect between the grouped ones splits the group,
+    // so now all three run in series.
+    e.send(track_uid, TrackRequest::MoveEntity(first, 2));
+    assert_all_frames(&e.render_blocks(2), 0.125);
+}
+
+#[test]
+fn effect_mix_blends_wet_and_dry() {
+    let mut e = TestEngine::default();
+    let mut track = e.track();
+    track.entity("always-1.0");
+    let quietener = track.quietener(0.0);
+    track.send(TrackRequest::SetEffectMix(quietener, Normal::from(0.25)));
+    assert_all_frames(&e.render_blocks(2), 0.75);
+}
+
+#[test]
+fn effect_edits_undo() {
+    let mut e = TestEngine::default();
+    let mut track = e.track();
+    track.entity("always-1.0");
+    let first = track.quietener(0.5);
+    let second = track.quietener(0.5);
+    let third = track.quietener(0.5);
+    let track_uid = track.uid;
+    e.engine
+        .execute(Command::SetEffectGroup(track_uid, second, Some(1)))
+        .unwrap();
+    e.engine
+        .execute(Command::SetEffectGroup(track_uid, third, Some(1)))
+        .unwrap();
+    assert_all_frames(&e.render_blocks(2), 0.25);
+
+    e.engine
+        .execute(Command::MoveEntity(track_uid, first, 2))
+        .unwrap();
+    assert_all_frames(&e.render_blocks(2), 0.125);
+    e.engine.undo().unwrap();
+    assert_all_frames(&e.render_blocks(2), 0.25);
+
+    // Dragging the wet slider undoes as one step.
+    e.engine
+        .execute(Command::SetEffectMix(track_uid, first, Normal::from(0.5)))
+        .unwrap();
+    e.engine
+        .execute(Command::SetEffectMix(track_uid, first, Normal::minimum()))
+        .unwrap();
+    assert_all_frames(&e.render_blocks(2), 0.5);
+    e.engine.undo().unwrap();
+    assert_all_frames(&e.render_blocks(2), 0.25);
+
+    // Taking the third out of the group leaves all three in series.
+    e.engine.undo().unwrap();
+    assert_all_frames(&e.render_blocks(2), 0.125);
+}
+
+#[test]
+fn block_size_sets_how_much_each_cycle_renders() {
+    let mut e = TestEngine::default().block_size(256);
+    e.track().entity("always-0.5");
+    let frames = e.render_blocks(3);
+    assert_eq!(frames.len(), 3 * 256);
+    assert_all_frames(&frames, 0.5);
+}
+
+#[test]
+fn rendering_is_repeatable() {
+    fn render() -> Vec<StereoSample> {
+        let mut e = TestEngine::default();
+        let mut track = e.track();
+        track.entity("arpeggiator");
+        track.entity("toy-synth");
+        track.quietener(0.5);
+        e.track().entity("drone");
+        e.engine.play();
+        e.render_blocks(200)
+    }
+    assert_eq!(render(), render());
+}
+
+#[test]
+fn shutdown_joins_every_actor_thread() {
+    let mut engine = Engine::new_with(Executor::Threaded);
+    let track_uid = engine.create_track().unwrap();
+    let track = engine.track(track_uid).unwrap();
+    track.add_entity_by_key("always-1.0").unwrap();
+    track.add_entity_by_key("quietener").unwrap();
+    assert!(engine.shutdown(Duration::from_secs(5)).is_ok());
+    assert!(engine.shutdown(Duration::from_secs(5)).is_ok());
+}
+
+#[test]
+fn tracks_on_other_output_pairs_leave_the_main_pair() {
+    let mut e = TestEngine::default();
+    let mut rear = e.track();
+    rear.entity("always-1.0");
+    let uid = rear.uid;
+    e.track().entity("always-0.5");
+    e.engine.set_channel_layout(ChannelLayout::Quad);
+    e.engine.execute(Command::SetOutputPair(uid, 1)).unwrap();
+    assert_all_frames(&e.render_blocks(2), 0.25);
+}
+
+#[test]
+fn punch_region_records_while_playback_continues() {
+    let mut e = TestEngine::default();
+    let punch = PunchRegion::new_with(PunchRegion::beat(1), PunchRegion::beat(2));
+    e.engine.set_punch(punch);
+    let frames_per_beat = e.engine.sample_rate().0 as f64 * 60.0 / e.engine.tempo().0;
+    let half_beat_of_blocks = (frames_per_beat / 2.0) as usize / e.engine.block_size();
+    e.engine.play();
+    e.render_blocks(half_beat_of_blocks);
+    assert!(!e.engine.is_recording());
+    e.render_blocks(2 * half_beat_of_blocks);
+    assert!(e.engine.is_recording());
+    e.render_blocks(2 * half_beat_of_blocks);
+    assert!(!e.engine.is_recording());
+    assert!(e.engine.is_performing());
+}
+
+#[test]
+fn count_in_clicks_before_the_transport_rolls() {
+    let mut e = TestEngine::default();
+    e.engine.metronome_mut().set_count_in_bars(1);
+    let frames_per_beat = e.engine.sample_rate().0 as f64 * 60.0 / e.engine.tempo().0;
+    let bar_of_blocks = (frames_per_beat * 4.0) as usize / e.engine.block_size();
+    e.engine.start_recording();
+    e.engine.play();
+    let frames = e.render_blocks(bar_of_blocks / 2);
+    assert!(frames.iter().any(|frame| frame.0 .0 != 0.0));
+    assert!(!e.engine.is_performing());
+    e.render_blocks(bar_of_blocks);
+    assert!(e.engine.is_performing());
+}
+
+#[test]
+fn seeking_moves_the_playhead() {
+    let mut e = TestEngine::default();
+    let bar = PunchRegion::beat(4);
+    e.engine.seek(bar);
+    assert_eq!(e.engine.position(), bar);
+    e.engine.play();
+    e.render_blocks(1);
+    assert!(e.engine.position() > bar);
+}
+
+#[test]
+fn captures_can_be_encoded_as_flac() {
+    let path = std::env::temp_dir().join("spike-actor-system-capture.wav");
+    let mut writer = WavWriterService::new();
+    writer.send_input(WavWriterInput::SetFormat(ExportFormat {
+        container: ExportContainer::Flac,
+        ..Default::default()
+    }));
+    writer.send_input(WavWriterInput::Reset(path.clone(), SampleRate(44100), 2));
+    writer.send_input(WavWriterInput::Frames(
+        vec![StereoSample::from(0.5); 4096],
+        Vec::default(),
+    ));
+    writer.quit_and_join(Duration::from_secs(5)).unwrap();
+    let bytes = std::fs::read(path.with_extension("flac")).unwrap();
+    assert!(bytes.starts_with(b"fLaC"));
+}
+
+#[test]
+fn presets_carry_settings_between_entities_of_a_kind() {
+    let mut e = TestEngine::default();
+    let mut track = e.track();
+    let loud = track.entity("always-1.0");
+    let quiet = track.entity("always-0.5");
+    let track_uid = track.uid;
+    let track = e.engine.track(track_uid).unwrap();
+    let preset = track.entity_presets(loud).unwrap().to_ron().unwrap();
+    track.entity_presets(quiet).unwrap().apply_ron(&preset).unwrap();
+    assert_all_frames(&e.render_blocks(2), 2.0);
+}
+
+#[test]
+fn compare_switches_between_randomized_snapshots() {
+    let mut e = TestEngine::default();
+    let mut track = e.track();
+    track.entity("always-1.0");
+    let quietener = track.quietener(1.0);
+    let track_uid = track.uid;
+    let track = e.engine.track(track_uid).unwrap();
+    let index = ControlIndex(0);
+    track.set_random_range(quietener, index, (0.5, 0.5)).unwrap();
+    track.randomize_params(quietener).unwrap();
+    track.toggle_compare(quietener).unwrap();
+    track.set_random_range(quietener, index, (0.25, 0.25)).unwrap();
+    track.randomize_params(quietener).unwrap();
+    assert_all_frames(&e.render_blocks(1), 0.25);
+    let track = e.engine.track(track_uid).unwrap();
+    track.toggle_compare(quietener).unwrap();
+    assert_all_frames(&e.render_blocks(1), 0.5);
+}
+
+#[test]
+fn control_routes_cross_tracks() {
+    let mut e = TestEngine::default();
+    let mut controller_track = e.track();
+    let drone = controller_track.entity("drone");
+    let source_track = controller_track.uid;
+    let mut target_track = e.track();
+    target_track.entity("always-1.0");
+    let quietener = target_track.quietener(1.0);
+    let target = ControlTarget::Entity(target_track.uid, quietener, ControlIndex(0));
+    e.engine
+        .execute(Command::RouteControl(ControlRoute {
+            source_track,
+            source: drone,
+            target,
+        }))
+        .unwrap();
+    e.engine.play();
+    let frames = e.render_blocks(4);
+    assert!(frames.iter().any(|frame| (frame.0 .0 - 0.5).abs() > 1e-6));
+    e.engine.undo().unwrap();
+    assert!(e.engine.control_routes().is_empty());
+}
+
+#[test]
+fn link_transfer_functions_reshape_control_signals() {
+    let mut e = TestEngine::default();
+    let mut track = e.track();
+    track.entity("always-1.0");
+    let quietener = track.quietener(1.0);
+    let drone = track.entity("drone");
+    let track_uid = track.uid;
+    // Pinned to one value, whatever the drone sends.
+    let transfer = TransferFunction {
+        min: 0.25,
+        max: 0.25,
+        ..Default::default()
+    };
+    let track = e.engine.track(track_uid).unwrap();
+    track.set_control_transfer(drone, quietener, ControlIndex(0), transfer);
+    let link = ControlLink {
+        uid: quietener,
+        param: ControlIndex(0),
+    };
+    e.engine
+        .execute(Command::Link(track_uid, drone, link))
+        .unwrap();
+    e.engine.play();
+    e.render_blocks(2);
+    assert_all_frames(&e.render_blocks(1), 0.25);
+}
+
+#[test]
+fn arpeggiator_climbs_through_held_notes_and_octaves() {
+    let mut e = TestEngine::default().block_size(512);
+    let mut track = e.track();
+    let arpeggiator = track.entity("arpeggiator");
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    track.send(TrackRequest::SubscribeMidi(sender));
+    for key in [64, 60] {
+        let message = MidiUtils::new_note_on(key, 100);
+        track.send(TrackRequest::Midi(MidiChannel::default(), message));
+    }
+    let track_uid = track.uid;
+    // The second of four octave settings.
+    let octaves = ControlValue(1.0 / 3.0);
+    let track = e.engine.track(track_uid).unwrap();
+    track
+        .set_param(arpeggiator, ControlIndex(1), octaves)
+        .unwrap();
+    e.engine.play();
+    e.render_blocks(128);
+    let keys: Vec<u8> = receiver
+        .try_iter()
+        .filter_map(|action| match action.message {
+            MidiMessage::NoteOn { key, .. } => Some(key.as_int()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(keys[..5], [60, 64, 72, 76, 60]);
+}
+
+#[test]
+fn midi_chain_feeds_each_effect_only_the_one_before() {
+    let mut e = TestEngine::default().block_size(512);
+    let mut track = e.track();
+    let first = track.entity("arpeggiator");
+    let second = track.entity("arpeggiator");
+    for uid in [first, second] {
+        track.send(TrackRequest::SetMidiEffect(uid, true));
+    }
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    track.send(TrackRequest::SubscribeMidi(sender));
+    let message = MidiUtils::new_note_on(60, 100);
+    track.send(TrackRequest::Midi(MidiChannel::default(), message));
+    let track_uid = track.uid;
+    let track = e.engine.track(track_uid).unwrap();
+    // Both span two octaves. The second latches, so that it keeps playing
+    // between the first one's notes.
+    for uid in [first, second] {
+        track
+            .set_param(uid, ControlIndex(1), ControlValue(1.0 / 3.0))
+            .unwrap();
+    }
+    track
+        .set_param(second, ControlIndex(5), ControlValue(1.0))
+        .unwrap();
+    e.engine.play();
+    e.render_blocks(128);
+    let note_ons: Vec<(Uid, u8)> = receiver
+        .try_iter()
+        .filter_map(|action| match action.message {
+            MidiMessage::NoteOn { key, .. } => Some((action.source_uid, key.as_int())),
+            _ => None,
+        })
+        .collect();
+    // Without the chain, the second's 84 would reach the first.
+    assert!(note_ons
+        .iter()
+        .filter(|(uid, _)| *uid == first)
+        .all(|(_, key)| [60, 72].contains(key)));
+    assert!(note_ons.iter().any(|(uid, _)| *uid == second));
+}
+
+#[test]
+fn clips_play_into_the_midi_chain() {
+    let mut e = TestEngine::default().block_size(512);
+    let mut track = e.track();
+    let chained = track.entity("arpeggiator");
+    let outside = track.entity("arpeggiator");
+    track.send(TrackRequest::SetMidiEffect(chained, true));
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    track.send(TrackRequest::SubscribeMidi(sender));
+    let track_uid = track.uid;
+
+    // A note too short for the chain to play as it happens.
+    let channel = MidiChannel::default();
+    let mut clip = MidiClip::default();
+    let units = MusicalTime::new_with_units;
+    clip.record(units(1), channel, MidiUtils::new_note_on(60, 100));
+    clip.record(units(2), channel, MidiUtils::new_note_off(60, 0));
+    e.engine
+        .execute(Command::SetMidiClip(track_uid, clip))
+        .unwrap();
+
+    // The chain latches the note and plays it down two octaves, starting at
+    // 72. The other arpeggiator latches whatever it hears.
+    let track = e.engine.track(track_uid).unwrap();
+    for (uid, index, value) in [
+        (chained, 0, 1.0 / 3.0),
+        (chained, 1, 1.0 / 3.0),
+        (chained, 5, 1.0),
+        (outside, 5, 1.0),
+    ] {
+        track
+            .set_param(uid, ControlIndex(index), ControlValue(value))
+            .unwrap();
+    }
+    e.engine.play();
+    e.render_blocks(128);
+    let first_note_on = receiver.try_iter().find_map(|action| match action.message {
+        MidiMessage::NoteOn { key, .. } if action.source_uid == outside => Some(key.as_int()),
+        _ => None,
+    });
+    // Hearing the clip directly, it would have started with 60.
+    assert_eq!(first_note_on, Some(72));
+}
+
+#[test]
+fn midi_input_processor_reshapes_incoming_notes() {
+    let mut e = TestEngine::default().block_size(512);
+    let mut track = e.track();
+    track.entity("arpeggiator");
+    let (sender, receiver) = crossbeam_channel::unbounded();
+    track.send(TrackRequest::SubscribeMidi(sender));
+    track.send(TrackRequest::SetMidiInput(MidiInputProcessor {
+        transpose: 7,
+        fixed_velocity: Some(90),
+        ..Default::default()
+    }));
+    let message = MidiUtils::new_note_on(60, 30);
+    track.send(TrackRequest::Midi(MidiChannel::default(), message));
+    e.engine.play();
+    e.render_blocks(16);
+    let first_note_on = receiver.try_iter().find_map(|action| match action.message {
+        MidiMessage::NoteOn { key, vel } => Some((key.as_int(), vel.as_int())),
+        _ => None,
+    });
+    assert_eq!(first_note_on, Some((67, 90)));
+}
+
+#[test]
+fn poly_synth_steals_voices_and_falls_silent_after_release() {
+    let mut e = TestEngine::default().block_size(512);
+    let mut track = e.track();
+    track.entity("poly-synth");
+    // More notes than the synth has voices.
+    for key in 40..60 {
+        let message = MidiUtils::new_note_on(key, 100);
+        track.send(TrackRequest::Midi(MidiChannel::default(), message));
+    }
+    let track_uid = track.uid;
+    let frames = e.render_blocks(4);
+    assert!(frames.iter().any(|frame| frame.0 .0.abs() > 0.001));
+    for key in 40..60 {
+        let message = MidiUtils::new_note_off(key, 0);
+        e.send(
+            track_uid,
+            TrackRequest::Midi(MidiChannel::default(), message),
+        );
+    }
+    e.render_blocks(32);
+    assert_all_frames(&e.render_blocks(1), 0.0);
+}
+
+#[test]
+fn drum_machine_plays_the_pad_mapped_to_each_note() {
+    let path = std::env::temp_dir().join("spike-actor-system-drum.wav");
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: 44100,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+    for _ in 0..4096 {
+        writer.write_sample(0.5f32).unwrap();
+    }
+    writer.finalize().unwrap();
+
+    let mut e = TestEngine::default();
+    let mut track = e.track();
+    let drums = track.entity("drum-machine");
+    let track_uid = track.uid;
+    let track = e.engine.track(track_uid).unwrap();
+    let presets = track.entity_presets(drums).unwrap();
+    // Load the sample into the first pad, which plays the kick's note.
+    let ron = presets.to_ron().unwrap().replacen(
+        "path: None",
+        &format!("path: Some({:?})", path.display().to_string()),
+        1,
+    );
+    presets.apply_ron(&ron).unwrap();
+    assert_all_frames(&e.render_blocks(1), 0.0);
+
+    // A note without a pad does nothing.
+    let message = MidiUtils::new_note_on(60, 127);
+    e.send(
+        track_uid,
+        TrackRequest::Midi(MidiChannel::default(), message),
+    );
+    assert_all_frames(&e.render_blocks(1), 0.0);
+
+    let message = MidiUtils::new_note_on(36, 127);
+    e.send(
+        track_uid,
+        TrackRequest::Midi(MidiChannel::default(), message),
+    );
+    assert_all_frames(&e.render_blocks(1), 0.5);
+}
+
+#[test]
+fn signal_generator_fires_full_scale_impulses() {
+    let mut e = TestEngine::default().block_size(512);
+    let mut track = e.track();
+    let generator = track.entity("signal-generator");
+    let track_uid = track.uid;
+    let track = e.engine.track(track_uid).unwrap();
+    // The last kind is the impulse, and the top of the level is 0 dBFS.
+    for index in [0, 2] {
+        track
+            .set_param(generator, ControlIndex(index), ControlValue(1.0))
+            .unwrap();
+    }
+    let frames = e.render_blocks(1);
+    assert_all_frames(&frames[..1], 1.0);
+    assert_all_frames(&frames[1..], 0.0);
+}
+
+#[test]
+fn tracks_line_up_behind_a_lookahead_limiter() {
+    let mut e = TestEngine::default().block_size(512);
+    let mut generators = Vec::default();
+    for has_limiter in [false, true] {
+        let mut track = e.track();
+        let generator = track.entity("signal-generator");
+        let limiter = has_limiter.then(|| track.entity("lookahead-limiter"));
+        generators.push((track.uid, generator, limiter));
+    }
+    for (track_uid, generator, limiter) in generators {
+        let track = e.engine.track(track_uid).unwrap();
+        // Full-scale impulses, once a second.
+        for index in [0, 2] {
+            track
+                .set_param(generator, ControlIndex(index), ControlValue(1.0))
+                .unwrap();
+        }
+        // With the ceiling all the way up, the limiter only delays.
+        if let Some(limiter) = limiter {
+            track
+                .set_param(limiter, ControlIndex(1), ControlValue(1.0))
+                .unwrap();
+        }
+    }
+
+    // The limiter looks 5ms ahead. By the second impulse, the other track
+    // has been delayed to match.
+    let frames = e.render_blocks(90);
+    let lookahead = 221;
+    let second = 44100 + lookahead;
+    assert_all_frames(&frames[44100..second], 0.0);
+    assert_all_frames(&frames[second..second + 1], 1.0);
+    assert_all_frames(&frames[second + 1..], 0.0);
+}
+
+#[test]
+fn dry_signal_waits_for_a_slow_aux_track() {
+    let mut e = TestEngine::default().block_size(512);
+    let mut track = e.track();
+    let generator = track.entity("signal-generator");
+    let track_uid = track.uid;
+    let aux_uid = e.engine.create_bus_track().unwrap();
+    e.settle();
+    e.engine
+        .execute(Command::SetMixerLevel(aux_uid, Normal::maximum()))
+        .unwrap();
+    let aux = e.engine.track(aux_uid).unwrap();
+    let limiter = aux.add_entity_by_key("lookahead-limiter").unwrap();
+    aux.set_param(limiter, ControlIndex(1), ControlValue(1.0))
+        .unwrap();
+    let track = e.engine.track(track_uid).unwrap();
+    for index in [0, 2] {
+        track
+            .set_param(generator, ControlIndex(index), ControlValue(1.0))
+            .unwrap();
+    }
+    e.engine
+        .set_send(track_uid, aux_uid, Normal::maximum())
+        .unwrap();
+
+    // By the second impulse, the dry signal has been delayed to meet the
+    // wet one coming out of the limiter.
+    let frames = e.render_blocks(90);
+    let second = 44100 + 221;
+    assert_all_frames(&frames[44100..second], 0.0);
+    assert_all_frames(&frames[second..second + 1], 1.0);
+    assert_all_frames(&frames[second + 1..], 0.0);
+    let delays = e.engine.latency_compensation();
+    assert_eq!(delays.outputs.get(&track_uid), Some(&221));
+    assert_eq!(delays.sends.get(&(track_uid, aux_uid)), Some(&0));
+}
+
+#[test]
+fn play_and_stop_ramp_the_master_output() {
+    let mut e = TestEngine::default();
+    e.track().entity("always-1.0");
+    // The ramps take 2ms.
+    let ramp = e.engine.sample_rate().0 * 2 / 1000 + 1;
+    e.engine.play();
+    let frames = e.render_blocks(4);
+    assert!(frames[0].0 .0 < 0.05);
+    assert!(frames[..ramp].windows(2).all(|w| w[1].0 .0 > w[0].0 .0));
+    assert_all_frames(&frames[ramp..], 1.0);
+
+    // The transport keeps rolling until the output has faded out.
+    e.engine.stop();
+    let frames = e.render_blocks(2);
+    assert!(frames[..ramp].windows(2).all(|w| w[1].0 .0 < w[0].0 .0));
+    assert_all_frames(&frames[ramp..], 0.0);
+    assert!(e.engine.is_performing());
+    e.render_blocks(1);
+    assert!(!e.engine.is_performing());
+}
+
+#[test]
+fn sessions_can_be_built_without_the_ui() {
+    let mut e = TestEngine::default();
+    let track_uid = e.track().uid;
+    e.engine
+        .add_entity_to_track(track_uid, "always-1.0")
+        .unwrap();
+    let quietener = e
+        .engine
+        .add_entity_to_track(track_uid, "quietener")
+        .unwrap();
+    let drone = e.engine.add_entity_to_track(track_uid, "drone").unwrap();
+    e.engine
+        .link_control(track_uid, drone, quietener, ControlIndex(0))
+        .unwrap();
+    e.engine.play();
+    let frames = e.render_blocks(4);
+    assert!(frames.iter().any(|frame| (frame.0 .0 - 1.0).abs() > 1e-6));
+
+    e.engine.remove_entity(track_uid, quietener).unwrap();
+    e.render_blocks(1);
+    assert_all_frames(&e.render_blocks(1), 1.0);
+    // Undoing puts the same entity back.
+    e.engine.undo().unwrap();
+    assert!(e.engine.remove_entity(track_uid, quietener).is_ok());
+}
+
+/// The next event that answers an input, skipping the service's own reports.
+fn next_answer(service: &EngineService) -> EngineServiceEvent {
+    loop {
+        let timeout = Duration::from_secs(5);
+        match service.receiver().recv_timeout(timeout).unwrap() {
+            EngineServiceEvent::Reset(..)
+            | EngineServiceEvent::Snapshot(..)
+            | EngineServiceEvent::Midi(..)
+            | EngineServiceEvent::MidiRealtime(..)
+            | EngineServiceEvent::Stalled(..) => {}
+            event => return event,
+        }
+    }
+}
+
+#[test]
+fn engine_service_can_be_driven_entirely_over_channels() {
+    let mut service = EngineService::new_with(Executor::Threaded);
+    let send = |input| service.sender().send(input).unwrap();
+    send(EngineServiceInput::CreateTrack);
+    let EngineServiceEvent::TrackCreated(track_uid) = next_answer(&service) else {
+        panic!("CreateTrack wasn't answered with TrackCreated");
+    };
+    send(EngineServiceInput::AddEntity(track_uid, "quietener".into()));
+    let EngineServiceEvent::EntityAdded(_, uid) = next_answer(&service) else {
+        panic!("AddEntity wasn't answered with EntityAdded");
+    };
+    let inputs = [
+        EngineServiceInput::SetParam(track_uid, uid, ControlIndex(0), ControlValue(0.5)),
+        EngineServiceInput::Execute(Command::SetMixerLevel(track_uid, Normal::from(0.5))),
+        EngineServiceInput::SetTrackName(track_uid, "Drums".into()),
+        EngineServiceInput::Play,
+        EngineServiceInput::Seek(MusicalTime::START),
+        EngineServiceInput::Stop,
+        EngineServiceInput::RemoveEntity(track_uid, uid),
+        EngineServiceInput::DeleteTrack(track_uid),
+    ];
+    for input in inputs {
+        let name = input.name();
+        send(input);
+        assert!(matches!(next_answer(&service), EngineServiceEvent::Done(n) if n == name));
+    }
+    let key = "no-such-entity".to_string();
+    send(EngineServiceInput::AddEntity(track_uid, key));
+    assert!(matches!(
+        next_answer(&service),
+        EngineServiceEvent::Failed("AddEntity", _)
+    ));
+    send(EngineServiceInput::Quit);
+    assert!(service.join(Duration::from_secs(5)).is_ok());
+}
+
+#[test]
+fn new_project_replaces_the_engine() {
+    let mut service = EngineService::new_with(Executor::Threaded);
+    let EngineServiceEvent::Reset(old_engine) = service.receiver().recv().unwrap() else {
+        panic!("The service didn't start with Reset");
+    };
+    let send = |input| service.sender().send(input).unwrap();
+    send(EngineServiceInput::CreateTrack);
+    let answer = next_answer(&service);
+    assert!(matches!(answer, EngineServiceEvent::TrackCreated(..)));
+
+    send(EngineServiceInput::NewProject);
+    let timeout = Duration::from_secs(5);
+    let new_engine = loop {
+        let event = service.receiver().recv_timeout(timeout).unwrap();
+        if let EngineServiceEvent::Reset(engine) = event {
+            break engine;
+        }
+    };
+    let answer = next_answer(&service);
+    assert!(matches!(answer, EngineServiceEvent::Done("NewProject")));
+    assert!(!Arc::ptr_eq(&old_engine, &new_engine));
+    assert!(new_engine.lock().unwrap().track_uids().is_empty());
+
+    // The new project works like the old one did.
+    send(EngineServiceInput::CreateTrack);
+    let answer = next_answer(&service);
+    assert!(matches!(answer, EngineServiceEvent::TrackCreated(..)));
+    assert_eq!(new_engine.lock().unwrap().track_uids().len(), 1);
+    send(EngineServiceInput::Quit);
+    assert!(service.join(timeout).is_ok());
+}
+
+#[test]
+fn remote_clients_drive_the_engine_over_websocket() {
+    let mut service = EngineService::new_with(Executor::Threaded);
+    let remote = RemoteControlService::new_with("127.0.0.1:0", service.sender().clone()).unwrap();
+    let url = format!("ws://{}", remote.address());
+    let (mut socket, _) = tungstenite::connect(url).unwrap();
+
+    // Stands in for the app, which hands the remote service its events.
+    let events = service.receiver().clone();
+    let forwarder = std::thread::spawn(move || {
+        while let Ok(event) = events.recv_timeout(Duration::from_secs(5)) {
+            remote.forward(&event);
+        }
+    });
+
+    let mut next_answer = |request: &str| {
+        socket.send(request.into()).unwrap();
+        loop {
+            let text = socket.read().unwrap().into_text().unwrap();
+            match serde_json::from_str(&text).unwrap() {
+                RemoteUpdate::Transport { .. } | RemoteUpdate::Meters { .. } => {}
+                update => return update,
+            }
+        }
+    };
+    let RemoteUpdate::TrackCreated { track } = next_answer(r#"{"type": "create_track"}"#) else {
+        panic!("create_track wasn't answered with track_created");
+    };
+    let request = format!(r#"{{"type": "set_mixer_mute", "track": {track}, "muted": true}}"#);
+    assert!(matches!(
+        next_answer(&request),
+        RemoteUpdate::Done { request } if request == "Execute"
+    ));
+    assert!(matches!(
+        next_answer(r#"{"type": "no_such_request"}"#),
+        RemoteUpdate::Failed { .. }
+    ));
+
+    service.sender().send(EngineServiceInput::Quit).unwrap();
+    assert!(service.join(Duration::from_secs(5)).is_ok());
+    drop(service);
+    forwarder.join().unwrap();
+}
+
+#[test]
+fn blocks_fit_in_a_fixed_device_buffer() {
+    let mut e = TestEngine::default();
+    // 192 frames take three blocks of 64, rather than one and a half of 128.
+    let cases = [(128, 128), (192, 64), (100, 64), (16, 32), (4096, 512)];
+    for (period, block_size) in cases {
+        e.engine.fit_block_size(period);
+        assert_eq!(e.engine.block_size(), block_size, "period {period}");
+    }
+}
+
+#[test]
+fn crossfader_trades_group_a_for_group_b() {
+    let mut e = TestEngine::default();
+    let a = {
+        let mut track = e.track();
+        track.entity("always-1.0");
+        track.uid
+    };
+    let b = {
+        let mut track = e.track();
+        track.entity("always-0.5");
+        track.uid
+    };
+    e.engine
+        .execute(Command::SetCrossfadeGroup(a, CrossfadeGroup::A))
+        .unwrap();
+    e.engine
+        .execute(Command::SetCrossfadeGroup(b, CrossfadeGroup::B))
+        .unwrap();
+    e.engine
+        .execute(Command::SetCrossfadeCurve(CrossfadeCurve::Linear))
+        .unwrap();
+
+    // Each track has half of the mix, and the crossfader starts in the middle.
+    assert_all_frames(&e.render_blocks(2), (1.0 + 0.5) * 0.5 * 0.5);
+
+    e.engine
+        .execute(Command::SetCrossfader(Normal::minimum()))
+        .unwrap();
+    assert_all_frames(&e.render_blocks(2), 1.0 * 0.5);
+    e.engine
+        .execute(Command::SetCrossfader(Normal::maximum()))
+        .unwrap();
+    assert_all_frames(&e.render_blocks(2), 0.5 * 0.5);
+
+    // Moving the crossfader twice undoes as one step.
+    e.engine.undo().unwrap();
+    assert_all_frames(&e.render_blocks(2), (1.0 + 0.5) * 0.5 * 0.5);
+}
+
+#[test]
+fn scenes_recall_the_mixer_at_once_or_with_a_morph() {
+    let mut e = TestEngine::default();
+    let a = {
+        let mut track = e.track();
+        track.entity("always-1.0");
+        track.uid
+    };
+    let b = {
+        let mut track = e.track();
+        track.entity("always-0.5");
+        track.uid
+    };
+    e.engine.capture_scene(0);
+    e.engine
+        .execute(Command::SetMixerLevel(a, Normal::minimum()))
+        .unwrap();
+    e.engine.execute(Command::SetMixerMute(b, true)).unwrap();
+    e.engine.capture_scene(1);
+    assert_all_frames(&e.render_blocks(2), 0.0);
+
+    e.engine.recall_scene(0, 0.0).unwrap();
+    assert_all_frames(&e.render_blocks(2), (1.0 + 0.5) * 0.5);
+    e.engine.recall_scene(1, 0.0).unwrap();
+    assert_all_frames(&e.render_blocks(2), 0.0);
+    assert!(e.engine.recall_scene(2, 0.0).is_err());
+
+    // Track A's level rises over four blocks, and track B unmutes right away.
+    let block_size = e.engine.block_size();
+    let morph_seconds = (4 * block_size) as f64 / e.engine.sample_rate().0 as f64;
+    e.engine.recall_scene(0, morph_seconds).unwrap();
+    let frames = e.render_blocks(4);
+    let levels: Vec<f64> = frames.iter().step_by(block_size).map(|f| f.0 .0).collect();
+    // After the first block, A is at 0.25 and B at full level.
+    assert!((levels[0] - (0.25 + 0.5) / 1.25).abs() < 1e-9);
+    assert!(levels.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_all_frames(&frames[3 * block_size..], (1.0 + 0.5) * 0.5);
+    assert!(!e.engine.scenes().is_morphing());
+}
+
+#[test]
+fn tempo_follows_taps_and_the_tempo_map() {
+    let mut e = TestEngine::default();
+    let start = Instant::now();
+    e.engine.tap_tempo(start);
+    e.engine.tap_tempo(start + Duration::from_millis(500));
+    assert!((e.engine.tempo().0 - 120.0).abs() < 1e-6);
+    e.engine.tap_tempo(start + Duration::from_millis(1000));
+    e.engine.tap_tempo(start + Duration::from_millis(1400));
+    assert!((e.engine.tempo().0 - 60.0 / (1.4 / 3.0)).abs() < 1e-6);
+
+    // 120 BPM at the start, ramping up to 240 BPM at beat 4. Doubling the
+    // tempo that way takes 2 ln 2 seconds.
+    let beat = |beat: usize| MusicalTime::new_with_units(beat * MusicalTime::UNITS_IN_BEAT);
+    for (time, bpm) in [(beat(0), 120.0), (beat(4), 240.0)] {
+        let point = TempoPoint {
+            time,
+            tempo: Tempo(bpm),
+        };
+        e.engine.tempo_map_mut().insert(point);
+    }
+    e.engine.play();
+    let sample_rate = e.engine.sample_rate().0 as f64;
+    let beats = |e: &TestEngine| {
+        e.engine.position().total_units() as f64 / MusicalTime::UNITS_IN_BEAT as f64
+    };
+    e.engine
+        .render((2.0 * 2.0_f64.ln() * sample_rate).round() as usize)
+        .unwrap();
+    assert!((beats(&e) - 4.0).abs() < 0.01, "at beat {}", beats(&e));
+    assert!((e.engine.tempo().0 - 240.0).abs() < 0.1);
+
+    // Past the last point, the tempo holds.
+    e.engine.render(sample_rate as usize).unwrap();
+    assert!((beats(&e) - 8.0).abs() < 0.01, "at beat {}", beats(&e));
+    assert!((e.engine.tempo().0 - 240.0).abs() < 0.1);
+
+    // Seeking picks up the tempo at the new position.
+    e.engine.seek(beat(2));
+    assert!((e.engine.tempo().0 - 180.0).abs() < 1e-6);
+}
+
+#[test]
+fn swing_delays_every_other_step_of_a_sequencer() {
+    fn note_on_blocks(groove: Groove) -> Vec<usize> {
+        let mut e = TestEngine::default();
+        let mut track = e.track();
+        track.entity("arpeggiator");
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        track.send(TrackRequest::SubscribeMidi(sender));
+        track.send(TrackRequest::SetGroove(groove));
+        let message = MidiUtils::new_note_on(60, 100);
+        track.send(TrackRequest::Midi(MidiChannel::default(), message));
+        e.engine.play();
+        let mut blocks = Vec::default();
+        for block in 0..400 {
+            e.render_blocks(1);
+            let note_ons = receiver
+                .try_iter()
+                .filter(|action| matches!(action.message, MidiMessage::NoteOn { .. }));
+            blocks.extend(note_ons.map(|_| block));
+        }
+        blocks
+    }
+    let intervals = |blocks: Vec<usize>| -> Vec<usize> {
+        blocks.windows(2).map(|pair| pair[1] - pair[0]).collect()
+    };
+
+    // The arpeggiator plays sixteenths.
+    let straight = intervals(note_on_blocks(Groove::default()));
+    assert!(straight.len() >= 3);
+    assert!(straight.iter().all(|i| i.abs_diff(straight[0]) <= 1));
+
+    let swung = intervals(note_on_blocks(Groove {
+        swing: Groove::MAX_SWING,
+        grid: GrooveGrid::Sixteenth,
+        ..Default::default()
+    }));
+    assert!(swung.len() >= 3);
+    assert!(swung[0] > swung[1] * 2, "{swung:?}");
+    assert!(swung[2] > swung[1] * 2, "{swung:?}");
+    assert!((swung[0] + swung[1]).abs_diff(straight[0] * 2) <= 1);
+}
+
+#[test]
+fn quantize_and_humanize_keep_note_lengths_and_undo() {
+    let mut e = TestEngine::default();
+    let track_uid = e.track().uid;
+    let beat = MusicalTime::UNITS_IN_BEAT;
+    let units = |units| MusicalTime::new_with_units(units);
+    let mut clip = MidiClip::default();
+    let channel = MidiChannel::default();
+    let note_on = MidiUtils::new_note_on(60, 100);
+    clip.record(units(beat + beat / 16), channel, note_on);
+    clip.record(units(beat * 2), channel, MidiUtils::new_note_off(60, 0));
+    e.engine
+        .execute(Command::SetMidiClip(track_uid, clip))
+        .unwrap();
+    let span = |e: &TestEngine| {
+        let spans = e.engine.track(track_uid).unwrap().clip_spans();
+        (spans[0].start.total_units(), spans[0].end.total_units())
+    };
+    let recorded = (beat + beat / 16, beat * 2);
+    assert_eq!(span(&e), recorded);
+
+    // Halfway to the nearest sixteenth, with the note-off following along.
+    let quantize = Quantize::new_with(4, Normal::from(0.5));
+    e.engine
+        .execute(Command::QuantizeMidiClip(track_uid, quantize))
+        .unwrap();
+    assert_eq!(span(&e), (beat + beat / 32, beat * 2 - beat / 32));
+    e.engine.undo().unwrap();
+    assert_eq!(span(&e), recorded);
+
+    let humanize = Humanize {
+        seed: 1,
+        ..Default::default()
+    };
+    e.engine
+        .execute(Command::HumanizeMidiClip(track_uid, humanize))
+        .unwrap();
+    let (start, end) = span(&e);
+    assert_eq!(end - start, recorded.1 - recorded.0);
+    assert!(start.abs_diff(recorded.0) <= humanize.timing_units);
+    e.engine.undo().unwrap();
+
+    // Playback edits leave the clip itself alone.
+    let clip_edits = ClipEdits {
+        quantize: Some(Quantize::default()),
+        humanize: None,
+    };
+    e.engine
+        .execute(Command::SetClipEdits(track_uid, clip_edits))
+        .unwrap();
+    assert_eq!(span(&e), recorded);
+}
+
+#[test]
+fn cue_clicks_stay_out_of_the_master_output() {
+    let mut e = TestEngine::default();
+    let metronome = e.engine.metronome_mut();
+    metronome.set_enabled(true);
+    metronome.set_sound(ClickSound::Sidestick);
+    e.engine.play();
+    let frames = e.render_blocks(1);
+    assert!(frames.iter().any(|frame| frame.0 .0 != 0.0));
+
+    e.engine.seek(MusicalTime::START);
+    e.engine.metronome_mut().set_output(ClickOutput::Cue);
+    assert_all_frames(&e.render_blocks(1), 0.0);
+    let cue = e.engine.metronome().cue_frames();
+    assert!(cue.iter().any(|frame| frame.0 .0 != 0.0));
+}
+
+#[test]
+fn browser_finds_entities_and_adds_them_to_tracks() {
+    let mut e = TestEngine::default();
+    let track_uid = e.track().uid;
+    let mut browser = EntityBrowser::default();
+    browser.set_query("ARP");
+    let entries = browser.entries(e.engine.registry());
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].category, BrowserCategory::Controller);
+
+    browser.set_query("1.0");
+    let item = BrowserItem::Entity("always-1.0".to_string());
+    let entries = browser.entries(e.engine.registry());
+    assert!(entries.iter().any(|entry| entry.item == item));
+    browser.set_favorite(&item, true);
+    assert!(browser.is_favorite(&item));
+
+    e.engine.add_browser_item(track_uid, &item).unwrap();
+    assert_all_frames(&e.render_blocks(1), 1.0);
+}
+
+#[test]
+fn selected_entities_copy_bypass_and_remove_together() {
+    let mut e = TestEngine::default();
+    let mut source = e.track();
+    let tone = source.entity("always-1.0");
+    let quietener = source.quietener(0.5);
+    let source_uid = source.uid;
+    let target_uid = e.track().uid;
+    assert_all_frames(&e.render_blocks(1), 0.5);
+
+    let track = e.engine.track(source_uid).unwrap();
+    track.set_selected(tone, true);
+    track.set_selected(quietener, true);
+    assert_eq!(track.selection(), vec![tone, quietener]);
+    e.engine.copy_selected_entities(source_uid).unwrap();
+    e.engine.paste_entities(target_uid).unwrap();
+    assert_all_frames(&e.render_blocks(1), 1.0);
+
+    // Only the quietener is an effect, so only it is bypassed.
+    e.engine.bypass_selected_entities(source_uid, true).unwrap();
+    assert_all_frames(&e.render_blocks(1), 1.5);
+
+    e.engine.remove_selected_entities(source_uid).unwrap();
+    assert_all_frames(&e.render_blocks(1), 0.5);
+    e.engine.undo().unwrap();
+    assert_all_frames(&e.render_blocks(1), 1.5);
+    e.engine.undo().unwrap();
+    assert_all_frames(&e.render_blocks(1), 1.0);
+}
+
+#[test]
+fn moving_an_entity_keeps_it_running_on_the_other_track() {
+    let mut e = TestEngine::default();
+    let mut source = e.track();
+    source.entity("always-1.0");
+    let quietener = source.quietener(0.5);
+    let source_uid = source.uid;
+    let mut target = e.track();
+    target.entity("always-0.5");
+    let target_uid = target.uid;
+    assert_all_frames(&e.render_blocks(1), 1.0);
+
+    let command = Command::MoveEntities(source_uid, target_uid, vec![quietener]);
+    e.engine.execute(command).unwrap();
+    assert_all_frames(&e.render_blocks(1), 1.25);
+    e.engine.undo().unwrap();
+    assert_all_frames(&e.render_blocks(1), 1.0);
+    e.engine.redo().unwrap();
+    assert_all_frames(&e.render_blocks(1), 1.25);
+
+    // It's the same entity, now answering to its new track.
+    let target = e.engine.track(target_uid).unwrap();
+    let (index, value) = (ControlIndex(0), ControlValue(1.0));
+    target.set_param(quietener, index, value).unwrap();
+    assert_all_frames(&e.render_blocks(1), 1.5);
+}
+
+#[test]
+fn injected_requests_reach_their_entity() {
+    let mut e = TestEngine::default();
+    let mut track = e.track();
+    track.entity("always-1.0");
+    let quietener = track.quietener(0.5);
+    let track_uid = track.uid;
+    assert_all_frames(&e.render_blocks(1), 0.5);
+
+    let line = format!("entity {} {} control 0 0.25", track_uid.0, quietener.0);
+    e.engine.inject(Injection::parse(&line).unwrap()).unwrap();
+    assert_all_frames(&e.render_blocks(1), 0.25);
+
+    assert!(Injection::parse("entity 1").is_err());
+    assert!(Injection::parse("track 1 needs-audio 64 extra").is_err());
+    assert!(Injection::parse("track 1 note-on 60 100 17").is_err());
+}
+
+#[test]
+fn stress_test_finds_no_problems() {
+    let config = StressConfig {
+        tracks: 4,
+        entities_per_track: 3,
+        blocks: 50,
+        ..Default::default()
+    };
+    let report = run_stress_test(&config);
+    assert!(report.is_ok(), "{report}");
+    assert_eq!(report.blocks, 50);
+    assert!(report.midi_messages > 0 && report.param_changes > 0);
+}
+
+#[test]
+fn threaded_engines_render_too() {
+    let mut engine = Engine::new_with(Executor::Threaded);
+    let track_uid = engine.create_track().unwrap();
+    let track = engine.track(track_uid).unwrap();
+    track.add_entity_by_key("always-0.5").unwrap();
+    engine
+        .execute(Command::SetMixerLevel(track_uid, Normal::maximum()))
+        .unwrap();
+    engine.render(engine.block_size()).unwrap();
+    let count = 4 * engine.block_size();
+    assert_all_frames(&engine.render(count).unwrap(), 0.5);
+    assert!(engine.shutdown(Duration::from_secs(5)).is_ok());
+}
+
+#[test]
+fn tracks_recover_from_requests_they_did_not_expect() {
+    let mut e = TestEngine::default();
+    let mut track = e.track();
+    track.entity("always-1.0");
+    let track_uid = track.uid;
+    let (sender, receiver) = unbounded();
+    track.send(TrackRequest::SubscribeTrackActions(sender));
+
+    // The second request arrives while the track is still waiting for its
+    // generator to answer the first.
+    let count = e.engine.block_size();
+    e.send(track_uid, TrackRequest::NeedsAudio(count));
+    e.send(track_uid, TrackRequest::NeedsAudio(count));
+    e.settle();
+    let faults = receiver
+        .try_iter()
+        .filter(|action| matches!(action, TrackAction::Fault(..)))
+        .count();
+    assert_eq!(faults, 1);
+
+    // The track is still alive, and still in step with the master track.
+    assert_all_frames(&e.render_blocks(2), 1.0);
+}
+
+#[test]
+fn dropping_an_engine_ends_its_actor_threads() {
+    let mut engine = Engine::new_with(Executor::Threaded);
+    let (sender, receiver) = unbounded();
+    for _ in 0..4 {
+        let track_uid = engine.create_track().unwrap();
+        let track = engine.track(track_uid).unwrap();
+        track.add_entity_by_key("always-0.5").unwrap();
+        track.add_entity_by_key("quietener").unwrap();
+        track.send_request(TrackRequest::SubscribeTrackActions(sender.clone()));
+    }
+    drop(sender);
+    engine.render(engine.block_size()).unwrap();
+    drop(engine);
+
+    // The tracks are gone along with their threads, so nobody is left to
+    // send anything.
+    while receiver.try_recv().is_ok() {}
+    let result = receiver.try_recv();
+    assert!(matches!(result, Err(TryRecvError::Disconnected)));
+}
+
+#[test]
+fn projects_stop_growing_at_their_limits() {
+    let mut e = TestEngine::default();
+    let limits = ActorLimits::global();
+    let track_uid = e.track().uid;
+    for _ in 1..limits.max_tracks() {
+        e.engine.create_track().unwrap();
+    }
+    let error = e.engine.create_track().unwrap_err();
+    assert!(error.downcast_ref::<LimitReached>().is_some());
+    assert_eq!(e.engine.track_uids().len(), limits.max_tracks());
+
+    let track = e.engine.track(track_uid).unwrap();
+    for _ in 0..limits.max_entities_per_track() {
+        track.add_entity_by_key("quietener").unwrap();
+    }
+    let error = track.add_entity_by_key("quietener").unwrap_err();
+    assert!(error.downcast_ref::<LimitReached>().is_some());
+
+    // What's there still plays.
+    assert_all_frames(&e.render_blocks(1), 0.0);
+}
+
+#[test]
+fn control_rate_entities_work_every_few_blocks() {
+    let mut e = TestEngine::default();
+    let mut track = e.track();
+    track.entity("always-1.0");
+    let follower = track.entity("envelope-follower");
+    let quietener = track.quietener(1.0);
+    let track_uid = track.uid;
+    let transfer = TransferFunction {
+        min: 0.25,
+        max: 0.25,
+        ..Default::default()
+    };
+    let track = e.engine.track(track_uid).unwrap();
+    track.set_control_transfer(follower, quietener, ControlIndex(0), transfer);
+    let link = ControlLink {
+        uid: quietener,
+        param: ControlIndex(0),
+    };
+    e.engine
+        .execute(Command::Link(track_uid, follower, link))
+        .unwrap();
+    e.engine.play();
+
+    // The follower hears the audio from the first block, but it doesn't
+    // report until its first Work, which covers the first few blocks.
+    assert_all_frames(&e.render_blocks(2), 1.0);
+    e.render_blocks(WorkSchedule::CONTROL_RATE_BLOCKS);
+    assert_all_frames(&e.render_blocks(1), 0.25);
+}
+
+#[test]
+fn silent_instruments_suspend_until_a_note_wakes_them() {
+    let mut e = TestEngine::default();
+    let mut track = e.track();
+    let synth = track.entity("toy-synth");
+    let track_uid = track.uid;
+    e.engine.play();
+
+    let frames = e.render_blocks(EntityActor::SUSPEND_AFTER_BLOCKS);
+    assert_all_frames(&frames, 0.0);
+    e.settle();
+    assert!(e.engine.track(track_uid).unwrap().is_suspended(synth));
+    assert_all_frames(&e.render_blocks(2), 0.0);
+
+    let message = MidiUtils::new_note_on(60, 100);
+    e.send(
+        track_uid,
+        TrackRequest::Midi(MidiChannel::default(), message),
+    );
+    let frames = e.render_blocks(1);
+    assert!(frames.iter().any(|frame| frame.0 .0.abs() > 0.001));
+    assert!(!e.engine.track(track_uid).unwrap().is_suspended(synth));
+}
+
+#[test]
+fn a_burst_of_parameter_changes_lands_in_order() {
+    let mut e = TestEngine::default();
+    let mut track = e.track();
+    track.entity("always-1.0");
+    let quietener = track.quietener(1.0);
+    let track_uid = track.uid;
+    assert_all_frames(&e.render_blocks(1), 1.0);
+
+    // Like dragging a slider from 1.0 down to 0.25.
+    let track = e.engine.track(track_uid).unwrap();
+    for step in 0..=300 {
+        let value = ControlValue(1.0 - step as f64 / 400.0);
+        track.set_param(quietener, ControlIndex(0), value).unwrap();
+    }
+    assert_all_frames(&e.render_blocks(1), 0.25);
+    assert_eq!(e.engine.track(track_uid).unwrap().queue_depth(), 0);
+}
+
+#[test]
+fn freezing_a_track_without_sources_loops_instead_of_recursing() {
+    let mut e = TestEngine::default().block_size(Engine::MIN_BLOCK_SIZE);
+    let track_uid = e.track().uid;
+    // Tens of thousands of blocks, each of which finishes on the spot.
+    let end = MusicalTime::new_with_units(128 * MusicalTime::UNITS_IN_BEAT);
+    e.send(track_uid, TrackRequest::Freeze(end));
+    e.settle();
+    let track = e.engine.track(track_uid).unwrap();
+    assert!(track.is_frozen());
+    assert!(track.is_idle());
+}
+
+#[test]
+fn buses_and_tempo_mapped_tracks_refuse_to_freeze() {
+    let mut e = TestEngine::default();
+    let track_uid = e.track().uid;
+    let bus_uid = e.engine.create_bus_track().unwrap();
+    e.engine.route_track(track_uid, Some(bus_uid)).unwrap();
+    e.settle();
+    let end = MusicalTime::new_with_units(MusicalTime::UNITS_IN_BEAT);
+    e.send(bus_uid, TrackRequest::Freeze(end));
+    e.settle();
+    assert!(!e.engine.track(bus_uid).unwrap().is_frozen());
+
+    let point = TempoPoint {
+        time: MusicalTime::START,
+        tempo: Tempo(90.0),
+    };
+    e.engine.tempo_map_mut().insert(point);
+    e.render_blocks(1);
+    e.send(track_uid, TrackRequest::Freeze(end));
+    e.settle();
+    assert!(!e.engine.track(track_uid).unwrap().is_frozen());
+}
+
+#[test]
+fn instruments_added_during_playback_answer_the_next_block() {
+    for seed in 0..8 {
+        let mut e = TestEngine::shuffled(seed);
+        let track_uid = e.track().uid;
+        e.engine.play();
+        assert_all_frames(&e.render_blocks(1), 0.0);
+
+        // No settling, so the new entity's subscription races the block.
+        e.engine
+            .track(track_uid)
+            .unwrap()
+            .add_entity_by_key("always-1.0")
+            .unwrap();
+        let block_size = e.engine.block_size();
+        let frames = e.engine.render(block_size).unwrap();
+        assert_all_frames(&frames, 1.0);
+    }
+}
+
+#[test]
+fn projects_round_trip_through_a_file() {
+    let mut e = TestEngine::default();
+    let mut track = e.track();
+    track.entity("always-1.0");
+    track.quietener(0.25);
+    track.send(TrackRequest::SetName("Lead".to_string()));
+    track.send(TrackRequest::SetColor([12, 34, 56]));
+    e.settle();
+
+    let project = e.engine.to_project().unwrap();
+    let file_name = format!("project-{}.{}", std::process::id(), Project::EXTENSION);
+    let path = std::env::temp_dir().join(file_name);
+    project.save(&path).unwrap();
+    let loaded = Project::load(&path);
+    let _ = std::fs::remove_file(&path);
+    let loaded = loaded.unwrap();
+    assert_eq!(loaded, project);
+    assert_eq!(loaded.tracks[0].entities.len(), 2);
+
+    let mut opened = TestEngine::default();
+    opened.engine.add_project(&loaded).unwrap();
+    opened.settle();
+    let track_uid = opened.engine.track_uids()[0];
+    opened
+        .engine
+        .execute(Command::SetMixerLevel(track_uid, Normal::maximum()))
+        .unwrap();
+    let reopened = opened.engine.to_project().unwrap();
+    assert_eq!(reopened.tracks[0].info.name, "Lead");
+    assert_eq!(reopened.tracks[0].info.color, [12, 34, 56]);
+    assert_all_frames(&opened.render_blocks(1), 0.25);
+}
+
+#[test]
+fn projects_keep_midi_learned_mappings() {
+    let mut e = TestEngine::default();
+    let mut track = e.track();
+    track.entity("always-1.0");
+    let quietener = track.quietener(1.0);
+    let channel = MidiChannel::default();
+    let cc = |value: u8| MidiMessage::Controller {
+        controller: 7.into(),
+        value: value.into(),
+    };
+    track.send(TrackRequest::MidiLearn(quietener, ControlIndex(0)));
+    track.send(TrackRequest::Midi(channel, cc(127)));
+    e.settle();
+
+    let project = e.engine.to_project().unwrap();
+    let file_name = format!("mappings-{}.{}", std::process::id(), Project::EXTENSION);
+    let path = std::env::temp_dir().join(file_name);
+    project.save(&path).unwrap();
+    let loaded = Project::load(&path);
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(loaded.unwrap().tracks[0].midi_mappings.len(), 1);
+
+    let mut opened = TestEngine::default();
+    opened.engine.add_project(&project).unwrap();
+    opened.settle();
+    let track_uid = opened.engine.track_uids()[0];
+    opened
+        .engine
+        .execute(Command::SetMixerLevel(track_uid, Normal::maximum()))
+        .unwrap();
+    assert_all_frames(&opened.render_blocks(1), 1.0);
+    opened.send(track_uid, TrackRequest::Midi(channel, cc(0)));
+    assert_all_frames(&opened.render_blocks(1), 0.0);
+}
+
+/// An instrument plugin that plays a constant level, which is both its only
+/// parameter and its whole state.
+#[derive(Debug)]
+struct LevelPlugin(f64);
+impl PluginInstance for LevelPlugin {
+    fn parameter_count(&self) -> usize {
+        1
+    }
+
+    fn parameter_name(&self, index: usize) -> Option<String> {
+        (index == 0).then(|| "level".to_string())
+    }
+
+    fn parameter_value(&self, _index: usize) -> f64 {
+        self.0
+    }
+
+    fn set_parameter_value(&mut self, _index: usize, value: f64) {
+        self.0 = value;
+    }
+
+    fn set_sample_rate(&mut self, _sample_rate: SampleRate) {}
+
+    fn handle_midi(&mut self, _channel: MidiChannel, _message: MidiMessage) {}
+
+    fn process(&mut self, buffer: &mut [StereoSample]) {
+        buffer.fill(StereoSample::from(self.0));
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        self.0.to_le_bytes().to_vec()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> anyhow::Result<()> {
+        self.0 = f64::from_le_bytes(state.try_into()?);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct LevelFormat;
+impl PluginFormat for LevelFormat {
+    fn name(&self) -> &str {
+        "Test"
+    }
+
+    fn scan(&self) -> Vec<PluginDescriptor> {
+        vec![PluginDescriptor {
+            format: self.name().to_string(),
+            id: "level".to_string(),
+            name: "Level".to_string(),
+            is_instrument: true,
+        }]
+    }
+
+    fn instantiate(
+        &self,
+        _descriptor: &PluginDescriptor,
+    ) -> anyhow::Result<Box<dyn PluginInstance>> {
+        Ok(Box::new(LevelPlugin(1.0)))
+    }
+}
+
+#[test]
+fn projects_restore_plugins_with_their_state() {
+    let mut registry = EntityRegistry::new_with_builtins();
+    registry.plugin_host_mut().add_format(Box::new(LevelFormat));
+    let registry = Arc::new(registry);
+    let descriptor = registry.plugin_host().descriptors()[0].clone();
+
+    let mut e = TestEngine::with_registry(Arc::clone(&registry));
+    let track_uid = e.track().uid;
+    let track = e.engine.track(track_uid).unwrap();
+    let uid = track.add_plugin(&descriptor).unwrap();
+    track
+        .set_param(uid, ControlIndex(0), ControlValue(0.25))
+        .unwrap();
+    assert_all_frames(&e.render_blocks(1), 0.25);
+
+    let project = e.engine.to_project().unwrap();
+    let expected = EntityProject::Plugin {
+        uid,
+        descriptor,
+        state: 0.25f64.to_le_bytes().to_vec(),
+    };
+    assert_eq!(project.tracks[0].entities, vec![expected]);
+
+    let mut opened = TestEngine::with_registry(registry);
+    opened.engine.add_project(&project).unwrap();
+    opened.settle();
+    let track_uid = opened.engine.track_uids()[0];
+    opened
+        .engine
+        .execute(Command::SetMixerLevel(track_uid, Normal::maximum()))
+        .unwrap();
+    assert_all_frames(&opened.render_blocks(1), 0.25);
+}
+
+#[test]
+fn adding_a_plugin_from_the_browser_undoes() {
+    let mut registry = EntityRegistry::new_with_builtins();
+    registry.plugin_host_mut().add_format(Box::new(LevelFormat));
+    let registry = Arc::new(registry);
+    let descriptor = registry.plugin_host().descriptors()[0].clone();
+
+    let mut e = TestEngine::with_registry(registry);
+    let track_uid = e.track().uid;
+    e.engine
+        .add_browser_item(track_uid, &BrowserItem::Plugin(descriptor))
+        .unwrap();
+    assert_all_frames(&e.render_blocks(1), 1.0);
+
+    e.engine.undo().unwrap();
+    assert_all_frames(&e.render_blocks(1), 0.0);
+
+    e.engine.redo().unwrap();
+    assert_all_frames(&e.render_blocks(1), 1.0);
+}
+
+/// Stands in for [LevelFormat] on a system where its plugin isn't installed.
+#[derive(Debug)]
+struct MissingFormat;
+impl PluginFormat for MissingFormat {
+    fn name(&self) -> &str {
+        LevelFormat.name()
+    }
+
+    fn scan(&self) -> Vec<PluginDescriptor> {
+        Vec::new()
+    }
+
+    fn instantiate(
+        &self,
+        descriptor: &PluginDescriptor,
+    ) -> anyhow::Result<Box<dyn PluginInstance>> {
+        Err(anyhow::anyhow!("{} isn't installed", descriptor.name))
+    }
+}
+
+#[test]
+fn projects_keep_plugins_that_are_not_installed() {
+    let mut registry = EntityRegistry::new_with_builtins();
+    registry.plugin_host_mut().add_format(Box::new(LevelFormat));
+    let registry = Arc::new(registry);
+    let descriptor = registry.plugin_host().descriptors()[0].clone();
+
+    let mut e = TestEngine::with_registry(registry);
+    let track_uid = e.track().uid;
+    let track = e.engine.track(track_uid).unwrap();
+    let uid = track.add_plugin(&descriptor).unwrap();
+    track
+        .set_param(uid, ControlIndex(0), ControlValue(0.25))
+        .unwrap();
+    e.settle();
+    let project = e.engine.to_project().unwrap();
+
+    let mut registry = EntityRegistry::new_with_builtins();
+    registry
+        .plugin_host_mut()
+        .add_format(Box::new(MissingFormat));
+    let mut opened = TestEngine::with_registry(Arc::new(registry));
+    opened.engine.add_project(&project).unwrap();
+    opened.settle();
+    assert_all_frames(&opened.render_blocks(1), 0.0);
+    assert_eq!(opened.engine.to_project().unwrap(), project);
+}
@@ -0,0 +1,167 @@
+//! Property tests of the pieces that the actor pipeline is built from, which
+//! should behave the same no matter what order things happen in.
+
+mod common;
+
+use common::{assert_all_frames, TestEngine};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use proptest::prelude::*;
+use spike_actor_system::{subscription::Subscription, track::TrackRequest};
+
+const CHANNEL_COUNT: usize = 4;
+
+#[derive(Debug, Clone)]
+enum SubscriptionOp {
+    Subscribe(usize),
+    Unsubscribe(usize),
+    Broadcast(u8),
+    BroadcastMut(u8),
+    /// Drops the receiving end of the channel.
+    Disconnect(usize),
+}
+
+fn subscription_op() -> impl Strategy<Value = SubscriptionOp> {
+    prop_oneof![
+        (0..CHANNEL_COUNT).prop_map(SubscriptionOp::Subscribe),
+        (0..CHANNEL_COUNT).prop_map(SubscriptionOp::Unsubscribe),
+        any::<u8>().prop_map(SubscriptionOp::Broadcast),
+        any::<u8>().prop_map(SubscriptionOp::BroadcastMut),
+        (0..CHANNEL_COUNT).prop_map(SubscriptionOp::Disconnect),
+    ]
+}
+
+/// An effect that scales its input by the factor, in the given parallel
+/// group, if any.
+#[derive(Debug, Clone)]
+struct EffectLayout {
+    factor: f64,
+    group: Option<usize>,
+}
+
+/// The generators and effects on one track.
+#[derive(Debug, Clone)]
+struct TrackLayout {
+    /// The registry key of each generator, and the value it always produces.
+    generators: Vec<(&'static str, f64)>,
+    effects: Vec<EffectLayout>,
+    is_batching_generators: bool,
+}
+impl TrackLayout {
+    /// What the track should produce. Adjacent effects in the same group
+    /// process the same input, and their outputs are averaged.
+    fn expected(&self) -> f64 {
+        let mut value: f64 = self.generators.iter().map(|(_, value)| value).sum();
+        let mut effects = self.effects.iter().peekable();
+        while let Some(effect) = effects.next() {
+            let mut factors = vec![effect.factor];
+            if effect.group.is_some() {
+                while let Some(next) = effects.next_if(|next| next.group == effect.group) {
+                    factors.push(next.factor);
+                }
+            }
+            value *= factors.iter().sum::<f64>() / factors.len() as f64;
+        }
+        value
+    }
+}
+
+fn track_layout() -> impl Strategy<Value = TrackLayout> {
+    let generator = prop::sample::select(vec![("always-0.5", 0.5), ("always-1.0", 1.0)]);
+    let factor = prop::sample::select(vec![0.25, 0.5, 1.0]);
+    let effect = (factor, prop::option::of(0..2usize))
+        .prop_map(|(factor, group)| EffectLayout { factor, group });
+    let generators = prop::collection::vec(generator, 0..4);
+    let effects = prop::collection::vec(effect, 0..4);
+    (generators, effects, any::<bool>()).prop_map(|(generators, effects, is_batching)| {
+        TrackLayout {
+            generators,
+            effects,
+            is_batching_generators: is_batching,
+        }
+    })
+}
+
+proptest! {
+    #[test]
+    fn subscribers_get_each_broadcast_once_per_subscription(
+        ops in prop::collection::vec(subscription_op(), 0..64)
+    ) {
+        let (senders, receivers): (Vec<Sender<u8>>, Vec<Receiver<u8>>) =
+            (0..CHANNEL_COUNT).map(|_| unbounded()).unzip();
+        let mut receivers: Vec<_> = receivers.into_iter().map(Some).collect();
+        let mut subscription = Subscription::default();
+        // Which channels the subscription should have, in order. A channel
+        // that subscribes twice is there twice.
+        let mut subscribed: Vec<usize> = Vec::default();
+        let mut expected = vec![Vec::default(); CHANNEL_COUNT];
+        for op in ops {
+            match op {
+                SubscriptionOp::Subscribe(i) => {
+                    subscription.subscribe(&senders[i]);
+                    subscribed.push(i);
+                }
+                SubscriptionOp::Unsubscribe(i) => {
+                    subscription.unsubscribe(&senders[i]);
+                    subscribed.retain(|&s| s != i);
+                }
+                SubscriptionOp::Broadcast(value) => {
+                    subscription.broadcast(value);
+                    for &i in subscribed.iter() {
+                        expected[i].push(value);
+                    }
+                }
+                SubscriptionOp::BroadcastMut(value) => {
+                    subscription.broadcast_mut(value);
+                    for &i in subscribed.iter() {
+                        expected[i].push(value);
+                    }
+                    // It forgets the channels that can't receive anymore.
+                    subscribed.retain(|&i| receivers[i].is_some());
+                }
+                SubscriptionOp::Disconnect(i) => {
+                    receivers[i] = None;
+                }
+            }
+        }
+        for (receiver, expected) in receivers.iter().zip(expected) {
+            if let Some(receiver) = receiver {
+                let received: Vec<u8> = receiver.try_iter().collect();
+                prop_assert_eq!(received, expected);
+            }
+        }
+    }
+}
+
+proptest! {
+    // Each case builds and renders a whole project.
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn tracks_produce_the_same_output_in_any_message_order(
+        layouts in prop::collection::vec(track_layout(), 1..4),
+        seed in any::<u64>(),
+        block_size in prop::sample::select(vec![1, 64, 100]),
+        blocks in 1..4usize,
+    ) {
+        let mut e = TestEngine::shuffled(seed).block_size(block_size);
+        for layout in layouts.iter() {
+            let mut track = e.track();
+            for (key, _) in layout.generators.iter() {
+                track.entity(key);
+            }
+            for effect in layout.effects.iter() {
+                let uid = track.quietener(effect.factor);
+                track.send(TrackRequest::SetEffectGroup(uid, effect.group));
+            }
+            track.send(TrackRequest::SetBatchGenerators(layout.is_batching_generators));
+        }
+        let expected = layouts.iter().map(TrackLayout::expected).sum();
+        assert_all_frames(&e.render_blocks(blocks), expected);
+
+        // Every state machine made it back to the start.
+        prop_assert!(e.engine.master_track().is_idle());
+        for &uid in e.engine.track_uids() {
+            prop_assert!(e.engine.track(uid).unwrap().is_idle());
+        }
+    }
+}